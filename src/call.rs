@@ -0,0 +1,222 @@
+use anyhow::{anyhow, Context, Result};
+use colored::Colorize;
+use serde_json::Value;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::gateway::{Launched, ServerSpec};
+
+/// The server `mcpz call` and `mcpz tools` talk to: a `[servers]` entry of the config file by name,
+/// or anything `mcpz gateway` accepts (a package, `builtin:...` or an http(s) URL)
+pub fn resolve(target: &str, config: &Config) -> Result<ServerSpec> {
+    if config.servers.contains_key(target) {
+        let servers = config.servers()?;
+        if let Some(server) = servers.into_iter().find(|server| server.spec.name == target) {
+            return Ok(server.spec);
+        }
+    }
+    ServerSpec::parse(target)
+}
+
+/// Start `spec`, call `tool` once with `arguments` (a JSON object) and print the result:
+/// its text content, or the whole result with `json`. Fails if the tool reports an error.
+pub fn call(
+    spec: &ServerSpec,
+    tool: &str,
+    arguments: &str,
+    json: bool,
+    opts: &crate::RunOptions,
+    timeout: Duration,
+) -> Result<()> {
+    let arguments: Value = serde_json::from_str(arguments).context("--args is not valid JSON")?;
+    if !arguments.is_object() {
+        return Err(anyhow!("--args must be a JSON object, e.g. '{{\"path\": \"/tmp\"}}'"));
+    }
+
+    let Launched { server, .. } = spec.launch(opts, timeout).with_context(|| format!("{} failed to start", spec.name))?;
+    let tools: Vec<String> = server.tools().into_iter().map(|tool| tool.name).collect();
+    if !tools.iter().any(|name| name == tool) {
+        return Err(anyhow!(
+            "{} has no tool '{}' (tools: {})",
+            spec.name,
+            tool,
+            if tools.is_empty() { "none".to_string() } else { tools.join(", ") }
+        ));
+    }
+
+    let result = server.call_tool(tool, &arguments).with_context(|| format!("Calling {} failed", tool))?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&result)?);
+    } else {
+        println!("{}", content_text(&result));
+    }
+    if result.get("isError").and_then(Value::as_bool) == Some(true) {
+        return Err(anyhow!("{} reported an error", tool));
+    }
+    Ok(())
+}
+
+/// Start `spec` and print its tools: each with a signature and description, or all of
+/// them (with full input schemas) as JSON
+pub fn list_tools(spec: &ServerSpec, json: bool, opts: &crate::RunOptions, timeout: Duration) -> Result<()> {
+    let Launched { server, .. } = spec.launch(opts, timeout).with_context(|| format!("{} failed to start", spec.name))?;
+    let tools = server.tools();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&tools)?);
+        return Ok(());
+    }
+
+    println!(
+        "{}",
+        format!(
+            "{} v{}: {} {}",
+            server.name(),
+            server.version(),
+            tools.len(),
+            if tools.len() == 1 { "tool" } else { "tools" }
+        )
+        .green()
+        .bold()
+    );
+    for tool in &tools {
+        println!();
+        println!("  {}({})", tool.name.cyan(), signature(&tool.input_schema));
+        for line in tool.description.lines().filter(|line| !line.trim().is_empty()) {
+            println!("    {}", line.trim());
+        }
+    }
+    Ok(())
+}
+
+/// A tool's parameters from its input schema, required ones first, e.g. `path: string, depth?: integer`
+pub fn signature(schema: &Value) -> String {
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|names| names.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    let Some(properties) = schema.get("properties").and_then(Value::as_object) else {
+        return String::new();
+    };
+    let mut parameters: Vec<(bool, String)> = properties
+        .iter()
+        .map(|(name, property)| {
+            let optional = !required.contains(&name.as_str());
+            (optional, format!("{}{}: {}", name, if optional { "?" } else { "" }, type_name(property)))
+        })
+        .collect();
+    parameters.sort_by_key(|(optional, _)| *optional);
+    parameters.into_iter().map(|(_, parameter)| parameter).collect::<Vec<_>>().join(", ")
+}
+
+/// A property's JSON schema type, compactly: `string`, `"a"|"b"`, `integer[]`, `string|null`
+fn type_name(property: &Value) -> String {
+    if let Some(values) = property.get("enum").and_then(Value::as_array) {
+        return values.iter().map(Value::to_string).collect::<Vec<_>>().join("|");
+    }
+    match property.get("type") {
+        Some(Value::String(kind)) if kind == "array" => {
+            let items = property.get("items").map(type_name).unwrap_or_else(|| "any".to_string());
+            if items.contains('|') {
+                format!("({})[]", items)
+            } else {
+                format!("{}[]", items)
+            }
+        }
+        Some(Value::String(kind)) => kind.clone(),
+        Some(Value::Array(kinds)) => kinds.iter().filter_map(Value::as_str).collect::<Vec<_>>().join("|"),
+        _ => "any".to_string(),
+    }
+}
+
+/// A tool result as text: its text content, with placeholders for images and other
+/// content, or the structured content (or whole result) as JSON if it has no content
+pub fn content_text(result: &Value) -> String {
+    let Some(content) = result.get("content").and_then(Value::as_array).filter(|content| !content.is_empty()) else {
+        let value = result.get("structuredContent").unwrap_or(result);
+        return serde_json::to_string_pretty(value).unwrap_or_default();
+    };
+    content
+        .iter()
+        .map(|item| {
+            let kind = item.get("type").and_then(Value::as_str).unwrap_or("unknown");
+            match (kind, item.get("text").and_then(Value::as_str)) {
+                ("text", Some(text)) => text.to_string(),
+                ("resource", _) => {
+                    let resource = item.get("resource");
+                    let field = |key| resource.and_then(|r| r.get(key)).and_then(Value::as_str);
+                    field("text")
+                        .map(str::to_string)
+                        .unwrap_or_else(|| format!("[resource {}]", field("uri").unwrap_or("")))
+                }
+                _ => match item.get("mimeType").and_then(Value::as_str) {
+                    Some(mime) => format!("[{} content, {}]", kind, mime),
+                    None => format!("[{} content]", kind),
+                },
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gateway::Upstream;
+    use serde_json::json;
+
+    #[test]
+    fn test_content_text() {
+        let result = json!({"content": [
+            {"type": "text", "text": "first"},
+            {"type": "image", "data": "...", "mimeType": "image/png"},
+            {"type": "resource", "resource": {"uri": "file:///a", "text": "body"}},
+            {"type": "resource", "resource": {"uri": "file:///b", "blob": "..."}}
+        ]});
+        assert_eq!(content_text(&result), "first\n[image content, image/png]\nbody\n[resource file:///b]");
+        assert_eq!(content_text(&json!({"content": [], "structuredContent": {"a": 1}})), "{\n  \"a\": 1\n}");
+    }
+
+    #[test]
+    fn test_signature() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "query": {"type": "string"},
+                "limit": {"type": "integer"},
+                "sort": {"enum": ["asc", "desc"]},
+                "tags": {"type": "array", "items": {"type": ["string", "null"]}},
+                "extra": {}
+            },
+            "required": ["query"]
+        });
+        assert_eq!(
+            signature(&schema),
+            r#"query: string, extra?: any, limit?: integer, sort?: "asc"|"desc", tags?: (string|null)[]"#
+        );
+        assert_eq!(signature(&json!({"type": "object"})), "");
+    }
+
+    #[test]
+    fn test_resolve_prefers_config_servers() {
+        let config = Config::parse("[servers.sh]\nbuiltin = \"shell\"\nargs = [\"--allow\", \"ls*\"]").unwrap();
+        let spec = resolve("sh", &config).unwrap();
+        assert_eq!(spec.upstream, Upstream::Builtin(["shell", "--allow", "ls*"].map(String::from).to_vec()));
+        assert_eq!(resolve("mcp-server-time", &config).unwrap().upstream, Upstream::package("mcp-server-time"));
+        assert_eq!(resolve("builtin:shell", &Config::default()).unwrap().name, "shell");
+    }
+
+    #[test]
+    fn test_call_builtin() {
+        let spec = ServerSpec::parse("builtin:shell").unwrap();
+        let opts = crate::RunOptions::default();
+        let timeout = Duration::from_secs(5);
+        assert!(call(&spec, "execute_command", r#"{"command": "true"}"#, true, &opts, timeout).is_ok());
+        assert!(call(&spec, "execute_command", r#"{"command": "exit 3"}"#, false, &opts, timeout).is_ok());
+        let err = call(&spec, "nope", "{}", false, &opts, timeout).unwrap_err().to_string();
+        assert!(err.contains("shell has no tool 'nope' (tools: execute_command)"));
+        assert!(call(&spec, "execute_command", "[1]", false, &opts, timeout).is_err());
+        assert!(call(&spec, "execute_command", "{", false, &opts, timeout).is_err());
+        assert!(list_tools(&spec, false, &opts, timeout).is_ok());
+    }
+}
@@ -0,0 +1,410 @@
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Protocol version mcpz speaks as a client (matches the built-in servers)
+pub const PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// Number of stderr lines kept for error messages
+const STDERR_TAIL: usize = 20;
+
+/// How long `notify` may take to deliver a notification
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Answers a request the server makes while working on one of ours (such as
+/// `sampling/createMessage`), with the JSON-RPC `result` or `error` object
+pub type Answerer<'a> = &'a mut dyn FnMut(&str, &Value) -> std::result::Result<Value, Value>;
+
+/// Called with each message a server sends other than a response to our request
+pub type MessageHandler = Arc<dyn Fn(Value) + Send + Sync>;
+
+/// The answer to server requests mcpz has no answer for
+pub fn refuse(method: &str, _params: &Value) -> std::result::Result<Value, Value> {
+    Err(json!({"code": -32601, "message": format!("Method not found: {}", method)}))
+}
+
+/// The message answering request `id` with `answer`
+pub fn reply(id: &Value, answer: std::result::Result<Value, Value>) -> Value {
+    match answer {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(error) => json!({"jsonrpc": "2.0", "id": id, "error": error}),
+    }
+}
+
+/// A JSON-RPC connection to an MCP server: a spawned package (`StdioClient`) or a
+/// remote Streamable HTTP endpoint (`http::client::HttpClient`)
+pub trait McpClient {
+    /// Send a message as given; if it's a request, wait for and return the whole response
+    /// with the same id (notifications and responses return None once sent)
+    fn exchange(&mut self, message: &Value, timeout: Duration) -> Result<Option<Value>>;
+
+    /// `exchange`, answering the requests the server makes before it responds with `answer`
+    fn exchange_answering(&mut self, message: &Value, timeout: Duration, answer: Answerer) -> Result<Option<Value>> {
+        let _ = answer;
+        self.exchange(message, timeout)
+    }
+
+    /// Id for the next request sent with `call`
+    fn next_id(&mut self) -> u64;
+
+    /// Pass the notifications the server sends to `handler` as they arrive, even between
+    /// requests (connections that can't are left as they are)
+    fn listen(&mut self, handler: MessageHandler) -> Result<()> {
+        let _ = handler;
+        Ok(())
+    }
+
+    /// Stop the server, so that requests to it fail instead of waiting (a no-op for connections
+    /// mcpz didn't start)
+    fn stop(&mut self) {}
+
+    /// Send a notification (no response expected)
+    fn notify(&mut self, method: &str, params: Value) -> Result<()> {
+        self.exchange(&json!({"jsonrpc": "2.0", "method": method, "params": params}), NOTIFY_TIMEOUT)
+            .map(|_| ())
+    }
+
+    /// Send a request and wait for its response, returning the `result`
+    fn request(&mut self, method: &str, params: Value, timeout: Duration) -> Result<Value> {
+        self.call(method, params, timeout)?.map_err(|error| {
+            anyhow!(
+                "{} failed: {}",
+                method,
+                error.get("message").and_then(|m| m.as_str()).unwrap_or("unknown error")
+            )
+        })
+    }
+
+    /// Send a request and wait for its response, returning the `result` or the JSON-RPC `error` object
+    fn call(&mut self, method: &str, params: Value, timeout: Duration) -> Result<std::result::Result<Value, Value>> {
+        self.call_answering(method, params, timeout, &mut refuse)
+    }
+
+    /// `call`, answering the requests the server makes before it responds with `answer`
+    fn call_answering(
+        &mut self,
+        method: &str,
+        params: Value,
+        timeout: Duration,
+        answer: Answerer,
+    ) -> Result<std::result::Result<Value, Value>> {
+        let id = self.next_id();
+        let request = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params});
+        let response = self
+            .exchange_answering(&request, timeout, answer)?
+            .ok_or_else(|| anyhow!("No response to {}", method))?;
+        if let Some(error) = response.get("error") {
+            return Ok(Err(error.clone()));
+        }
+        Ok(Ok(response.get("result").cloned().unwrap_or(Value::Null)))
+    }
+
+    /// Perform the `initialize` handshake, returning the server's result
+    fn initialize(&mut self, timeout: Duration) -> Result<Value> {
+        self.initialize_with(json!({}), timeout)
+    }
+
+    /// Perform the `initialize` handshake declaring `capabilities`, returning the server's result
+    fn initialize_with(&mut self, capabilities: Value, timeout: Duration) -> Result<Value> {
+        let result = self.request(
+            "initialize",
+            json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": capabilities,
+                "clientInfo": {"name": "mcpz", "version": env!("CARGO_PKG_VERSION")}
+            }),
+            timeout,
+        )?;
+        self.notify("notifications/initialized", json!({}))?;
+        Ok(result)
+    }
+}
+
+/// A JSON-RPC client talking to an MCP server over the child's stdin/stdout
+pub struct StdioClient {
+    child: Child,
+    stdin: ChildStdin,
+    messages: Receiver<Value>,
+    stderr: Arc<Mutex<VecDeque<String>>>,
+    stderr_thread: std::thread::JoinHandle<()>,
+    /// Takes notifications instead of `messages`, once set with `listen`
+    listener: Arc<Mutex<Option<MessageHandler>>>,
+    next_id: u64,
+}
+
+impl StdioClient {
+    /// Spawn the server with piped stdio
+    pub fn spawn(mut cmd: Command) -> Result<Self> {
+        cmd.stdin(Stdio::piped());
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+
+        let program = cmd.get_program().to_string_lossy().to_string();
+        let mut child = cmd
+            .spawn()
+            .with_context(|| format!("Failed to spawn {}", program))?;
+        let stdin = child.stdin.take().ok_or_else(|| anyhow!("Child stdin unavailable"))?;
+        let stdout = child.stdout.take().ok_or_else(|| anyhow!("Child stdout unavailable"))?;
+        let stderr_pipe = child.stderr.take().ok_or_else(|| anyhow!("Child stderr unavailable"))?;
+
+        // Lines that aren't JSON (stray logging) are skipped
+        let (tx, messages) = mpsc::channel();
+        let listener: Arc<Mutex<Option<MessageHandler>>> = Arc::default();
+        let listening = listener.clone();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                let Ok(message) = serde_json::from_str::<Value>(&line) else {
+                    continue;
+                };
+                if message.get("id").is_none() {
+                    let handler = listening.lock().unwrap().clone();
+                    if let Some(handler) = handler {
+                        handler(message);
+                        continue;
+                    }
+                }
+                if tx.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let stderr = Arc::new(Mutex::new(VecDeque::new()));
+        let stderr_tail = stderr.clone();
+        let stderr_thread = std::thread::spawn(move || {
+            for line in BufReader::new(stderr_pipe).lines().map_while(Result::ok) {
+                let mut tail = stderr_tail.lock().unwrap();
+                if tail.len() == STDERR_TAIL {
+                    tail.pop_front();
+                }
+                tail.push_back(line);
+            }
+        });
+
+        Ok(Self {
+            child,
+            stdin,
+            messages,
+            stderr,
+            stderr_thread,
+            listener,
+            next_id: 1,
+        })
+    }
+
+    fn send(&mut self, message: &Value) -> Result<()> {
+        writeln!(self.stdin, "{}", message).context("Failed to write to server stdin")?;
+        self.stdin.flush().context("Failed to flush server stdin")
+    }
+
+    /// Error for a server that exited before answering, with its exit status and stderr
+    fn exited_error(&mut self, method: &str) -> anyhow::Error {
+        let status = self
+            .child
+            .wait()
+            .map(|s| s.to_string())
+            .unwrap_or_else(|_| "unknown status".to_string());
+        // Give the stderr reader a moment to drain the dying process's last words
+        let drain_deadline = Instant::now() + Duration::from_millis(500);
+        while !self.stderr_thread.is_finished() && Instant::now() < drain_deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        anyhow!(
+            "Server exited ({}) before responding to {}{}",
+            status,
+            method,
+            self.stderr_summary()
+        )
+    }
+
+    /// Last stderr lines, formatted for appending to an error message
+    fn stderr_summary(&self) -> String {
+        let tail = self.stderr.lock().unwrap();
+        if tail.is_empty() {
+            String::new()
+        } else {
+            let lines: Vec<&str> = tail.iter().map(|s| s.as_str()).collect();
+            format!("\nServer stderr:\n  {}", lines.join("\n  "))
+        }
+    }
+}
+
+impl McpClient for StdioClient {
+    /// Send a message as given; if it's a request, wait for and return the whole response
+    /// with the same id (notifications and responses return None once sent). Requests the
+    /// server makes meanwhile are refused.
+    fn exchange(&mut self, message: &Value, timeout: Duration) -> Result<Option<Value>> {
+        self.exchange_answering(message, timeout, &mut refuse)
+    }
+
+    fn exchange_answering(&mut self, message: &Value, timeout: Duration, answer: Answerer) -> Result<Option<Value>> {
+        let method = message.get("method").and_then(|m| m.as_str()).unwrap_or("message").to_string();
+        if let Err(err) = self.send(message) {
+            // A closed pipe usually means the server already died; report that instead
+            return match self.child.try_wait() {
+                Ok(Some(_)) => Err(self.exited_error(&method)),
+                _ => Err(err),
+            };
+        }
+        let Some(id) = message.get("id").filter(|_| message.get("method").is_some()) else {
+            return Ok(None);
+        };
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let response = match self.messages.recv_timeout(remaining) {
+                Ok(response) => response,
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(anyhow!(
+                        "No response to {} within {}s{}",
+                        method,
+                        timeout.as_secs(),
+                        self.stderr_summary()
+                    ))
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err(self.exited_error(&method)),
+            };
+
+            if response.get("id") == Some(id) && response.get("method").is_none() {
+                return Ok(Some(response));
+            }
+            // Answer requests the server makes meanwhile; notifications are skipped
+            let request = response.get("method").and_then(|m| m.as_str()).zip(response.get("id"));
+            if let Some((method, request_id)) = request {
+                let params = response.get("params").cloned().unwrap_or(Value::Null);
+                let answer = answer(method, &params);
+                self.send(&reply(request_id, answer))?;
+            }
+        }
+    }
+
+    fn next_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn stop(&mut self) {
+        let _ = self.child.kill();
+    }
+
+    fn listen(&mut self, handler: MessageHandler) -> Result<()> {
+        *self.listener.lock().unwrap() = Some(handler);
+        Ok(())
+    }
+}
+
+impl Drop for StdioClient {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// What a successful health check found
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthReport {
+    pub server_name: String,
+    pub server_version: String,
+    pub tool_count: usize,
+}
+
+/// Spawn a throwaway copy of a server and check it completes `initialize` and `tools/list`
+pub fn health_check(cmd: Command, timeout: Duration) -> Result<HealthReport> {
+    let mut client = StdioClient::spawn(cmd)?;
+    let deadline = Instant::now() + timeout;
+
+    let init = client.initialize(timeout)?;
+    let server_info = init.get("serverInfo");
+    let field = |key: &str| {
+        server_info
+            .and_then(|info| info.get(key))
+            .and_then(|v| v.as_str())
+            .unwrap_or("unknown")
+            .to_string()
+    };
+
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    let tools = client.request("tools/list", json!({}), remaining.max(Duration::from_secs(1)))?;
+    let tool_count = tools
+        .get("tools")
+        .and_then(|t| t.as_array())
+        .map(|t| t.len())
+        .ok_or_else(|| anyhow!("tools/list response has no tools array"))?;
+
+    Ok(HealthReport {
+        server_name: field("name"),
+        server_version: field("version"),
+        tool_count,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A shell script that answers initialize and tools/list like a minimal MCP server
+    fn fake_server(script: &str) -> Command {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", script]);
+        cmd
+    }
+
+    const FAKE_SERVER: &str = r#"
+        while read -r line; do
+            case "$line" in
+                *'"initialize"'*) echo 'not json, just logging'
+                    echo '{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":"2024-11-05","serverInfo":{"name":"fake","version":"0.1.0"},"capabilities":{}}}' ;;
+                *'"tools/list"'*) echo '{"jsonrpc":"2.0","method":"notifications/message","params":{}}'
+                    echo '{"jsonrpc":"2.0","id":2,"result":{"tools":[{"name":"a"},{"name":"b"}]}}' ;;
+            esac
+        done
+    "#;
+
+    #[test]
+    fn test_health_check_success() {
+        let report = health_check(fake_server(FAKE_SERVER), Duration::from_secs(5)).unwrap();
+        assert_eq!(report.server_name, "fake");
+        assert_eq!(report.server_version, "0.1.0");
+        assert_eq!(report.tool_count, 2);
+    }
+
+    #[test]
+    fn test_health_check_server_exits() {
+        let err = health_check(
+            fake_server("echo 'npm ERR! 404 Not Found' >&2; exit 1"),
+            Duration::from_secs(5),
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("exited"));
+        assert!(err.contains("404 Not Found"));
+    }
+
+    #[test]
+    fn test_health_check_timeout() {
+        let err = health_check(fake_server("sleep 10"), Duration::from_millis(200))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("No response to initialize"));
+    }
+
+    #[test]
+    fn test_request_error_response() {
+        let mut client = StdioClient::spawn(fake_server(
+            r#"read -r line; echo '{"jsonrpc":"2.0","id":1,"error":{"code":-32601,"message":"Method not found"}}'; sleep 1"#,
+        ))
+        .unwrap();
+        let err = client
+            .request("bogus", json!({}), Duration::from_secs(5))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Method not found"));
+    }
+}
@@ -0,0 +1,466 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::gateway::{self, ServerSpec, Upstream};
+use crate::servers::affinity::Affinity;
+use crate::servers::breaker::BreakerSettings;
+use crate::servers::middleware::MiddlewareConfig;
+use crate::servers::prompts::PromptTemplate;
+use crate::servers::router::ToolRules;
+use crate::RestartPolicy;
+
+/// Default location of the fleet config: `~/.config/mcpz/config.toml`
+pub fn default_path() -> Result<PathBuf> {
+    Ok(dirs::config_dir()
+        .ok_or_else(|| anyhow!("Could not determine config directory"))?
+        .join("mcpz")
+        .join("config.toml"))
+}
+
+/// Named servers (and gateway settings) shared by `mcpz gateway` and `mcpz daemon`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub gateway: GatewaySettings,
+    #[serde(default)]
+    pub servers: BTreeMap<String, ServerConfig>,
+    /// `[profile.NAME]` sections, selected with `--profile NAME`
+    #[serde(default, rename = "profile")]
+    pub profiles: BTreeMap<String, Profile>,
+    /// `[prompts.NAME]` templates the gateway offers
+    #[serde(default)]
+    pub prompts: BTreeMap<String, PromptTemplate>,
+}
+
+/// `[profile.NAME]`: a variant of the fleet for one kind of client
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Names from `[servers]` to serve (all of them if unset)
+    pub include: Option<Vec<String>>,
+    /// Servers added by the profile, replacing any `[servers]` entry of the same name
+    #[serde(default)]
+    pub servers: BTreeMap<String, ServerConfig>,
+    /// Environment (e.g. credentials) for every package; a server's own `env` wins
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Extra directories every filesystem built-in may access
+    #[serde(default)]
+    pub directories: Vec<String>,
+    /// Overrides for `[gateway]`
+    #[serde(default)]
+    pub gateway: GatewaySettings,
+}
+
+/// `[gateway]`: defaults for `mcpz gateway`; command-line flags win
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GatewaySettings {
+    pub transport: Option<Transport>,
+    pub port: Option<u16>,
+    pub host: Option<String>,
+    /// Startup timeout per server, in seconds
+    pub timeout: Option<u64>,
+    /// Calls per minute of each tool in each session, for servers without their own `rate_limit`
+    pub rate_limit: Option<u32>,
+    /// Also serve each server on its own at `/mcp/NAME` (HTTP only)
+    pub paths: Option<bool>,
+    /// Bytes of text in a tool result, for servers without their own `max_result_size`
+    pub max_result_size: Option<usize>,
+    /// Seconds between keepalive pings of idle package servers
+    pub ping_interval: Option<u64>,
+    /// Refuse malformed JSON-RPC messages and tool arguments that don't match the tool's schema
+    pub strict: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Stdio,
+    Http,
+}
+
+/// `[servers.NAME]`: exactly one of `package`, `builtin` or `url`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Package, alias or local path, resolved as `mcpz run` would
+    pub package: Option<String>,
+    /// Built-in server type (shell, filesystem, sql); `args` are its `mcpz server` options
+    pub builtin: Option<String>,
+    /// Streamable HTTP endpoint of a remote server
+    pub url: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Environment for a package
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Restart policy under the daemon (no, on-failure or on-failure:MAX)
+    pub restart: Option<String>,
+    /// Port under the daemon
+    pub port: Option<u16>,
+    /// Tools the gateway hides (`tools.allow` / `tools.deny` patterns)
+    #[serde(default)]
+    pub tools: ToolRules,
+    /// Built-in middleware around the server's tool calls in the gateway
+    #[serde(default, deserialize_with = "MiddlewareConfig::deserialize_list")]
+    pub middleware: Vec<MiddlewareConfig>,
+    /// Calls allowed per minute of each of the server's tools in each session
+    pub rate_limit: Option<u32>,
+    /// Copies of the server the gateway starts and spreads tool calls over
+    pub replicas: Option<usize>,
+    /// When the gateway stops calling a server that keeps failing (default: 5 failures, 30s)
+    pub circuit_breaker: Option<BreakerSettings>,
+    /// Seconds the gateway reuses each listed tool's results for in a session
+    #[serde(default)]
+    pub cache: BTreeMap<String, u64>,
+    /// Bytes of text the gateway lets a tool result carry before truncating it
+    pub max_result_size: Option<usize>,
+    /// Keep each session on one replica (`session`), or give it a copy of its own (`dedicated`)
+    pub affinity: Option<Affinity>,
+}
+
+/// A server from the config, with the settings only the daemon uses
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedServer {
+    pub spec: ServerSpec,
+    pub restart: Option<RestartPolicy>,
+    pub port: Option<u16>,
+}
+
+impl Config {
+    /// Load `path`, or the default config if none is given. An explicit path must
+    /// exist; a missing default config is the same as an empty one.
+    pub fn load(path: Option<&Path>) -> Result<(PathBuf, Self)> {
+        let (path, explicit) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => (default_path()?, false),
+        };
+        if !explicit && !path.exists() {
+            return Ok((path, Self::default()));
+        }
+        let content = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let config = Self::parse(&content).with_context(|| format!("Invalid config {}", path.display()))?;
+        Ok((path, config))
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        Ok(toml::from_str(content)?)
+    }
+
+    /// Apply `[profile.NAME]`: narrow and extend the servers, hand them the profile's
+    /// environment and directories, and override the gateway settings
+    pub fn select_profile(mut self, name: &str) -> Result<Self> {
+        let Some(profile) = self.profiles.remove(name) else {
+            let defined: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            return Err(anyhow!(
+                "No profile named '{}' (defined: {})",
+                name,
+                if defined.is_empty() { "none".to_string() } else { defined.join(", ") }
+            ));
+        };
+
+        if let Some(include) = &profile.include {
+            if let Some(unknown) = include.iter().find(|server| !self.servers.contains_key(*server)) {
+                return Err(anyhow!("Profile '{}' includes unknown server '{}'", name, unknown));
+            }
+            self.servers.retain(|server, _| include.contains(server));
+        }
+        self.servers.extend(profile.servers);
+        for server in self.servers.values_mut() {
+            if server.package.is_some() {
+                for (key, value) in &profile.env {
+                    server.env.entry(key.clone()).or_insert_with(|| value.clone());
+                }
+            }
+            if server.builtin.as_deref() == Some("filesystem") {
+                for dir in &profile.directories {
+                    server.args.extend(["--dir".to_string(), dir.clone()]);
+                }
+            }
+        }
+
+        let gateway = profile.gateway;
+        self.gateway = GatewaySettings {
+            transport: gateway.transport.or(self.gateway.transport),
+            port: gateway.port.or(self.gateway.port),
+            host: gateway.host.or(self.gateway.host),
+            timeout: gateway.timeout.or(self.gateway.timeout),
+            rate_limit: gateway.rate_limit.or(self.gateway.rate_limit),
+            paths: gateway.paths.or(self.gateway.paths),
+            max_result_size: gateway.max_result_size.or(self.gateway.max_result_size),
+            ping_interval: gateway.ping_interval.or(self.gateway.ping_interval),
+            strict: gateway.strict.or(self.gateway.strict),
+        };
+        Ok(self)
+    }
+
+    /// The `[servers]` table as gateway specs, in name order
+    pub fn servers(&self) -> Result<Vec<NamedServer>> {
+        self.servers
+            .iter()
+            .map(|(name, server)| server.resolve(name).with_context(|| format!("In [servers.{}]", name)))
+            .collect()
+    }
+
+    /// The `[prompts]` table, with every template checked
+    pub fn prompts(&self) -> Result<BTreeMap<String, PromptTemplate>> {
+        for (name, prompt) in &self.prompts {
+            prompt.check().with_context(|| format!("In [prompts.{}]", name))?;
+        }
+        Ok(self.prompts.clone())
+    }
+}
+
+impl ServerConfig {
+    fn resolve(&self, name: &str) -> Result<NamedServer> {
+        gateway::check_name(name)?;
+        let upstream = match (&self.package, &self.builtin, &self.url) {
+            (Some(package), None, None) => Upstream::Package {
+                package: package.clone(),
+                args: self.args.clone(),
+                env: self.env.iter().map(|(key, value)| format!("{}={}", key, value)).collect(),
+            },
+            (None, Some(builtin), None) => {
+                if !gateway::BUILTIN_TYPES.contains(&builtin.as_str()) {
+                    return Err(anyhow!(
+                        "Unknown builtin '{}' (expected {})",
+                        builtin,
+                        gateway::BUILTIN_TYPES.join(", ")
+                    ));
+                }
+                if !self.env.is_empty() {
+                    return Err(anyhow!("env is only supported for packages"));
+                }
+                Upstream::Builtin(std::iter::once(builtin.clone()).chain(self.args.iter().cloned()).collect())
+            }
+            (None, None, Some(url)) => {
+                if !self.args.is_empty() || !self.env.is_empty() {
+                    return Err(anyhow!("args and env can't be used with url"));
+                }
+                Upstream::Remote(url.clone())
+            }
+            _ => return Err(anyhow!("Set exactly one of package, builtin or url")),
+        };
+        self.tools.check()?;
+        for middleware in &self.middleware {
+            middleware.build(name)?;
+        }
+        if self.replicas == Some(0) {
+            return Err(anyhow!("replicas must be at least 1"));
+        }
+        if self.affinity == Some(Affinity::Dedicated) && self.replicas.unwrap_or(1) > 1 {
+            return Err(anyhow!("replicas can't be used with affinity = \"dedicated\""));
+        }
+        let restart = self
+            .restart
+            .as_deref()
+            .map(str::parse::<RestartPolicy>)
+            .transpose()
+            .map_err(|e| anyhow!(e))?;
+
+        Ok(NamedServer {
+            spec: ServerSpec {
+                name: name.to_string(),
+                upstream,
+                tools: self.tools.clone(),
+                middleware: self.middleware.clone(),
+                rate_limit: self.rate_limit,
+                replicas: self.replicas.unwrap_or(1),
+                circuit_breaker: self.circuit_breaker.unwrap_or_default(),
+                cache: self.cache.clone(),
+                max_result_size: self.max_result_size,
+                affinity: self.affinity,
+            },
+            restart,
+            port: self.port,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = r#"
+[gateway]
+transport = "http"
+port = 4000
+rate_limit = 60
+paths = true
+max_result_size = 100000
+ping_interval = 30
+strict = true
+
+[servers.shell]
+builtin = "shell"
+args = ["--allow", "ls*,git*"]
+
+[servers.github]
+package = "@modelcontextprotocol/server-github"
+env = { GITHUB_TOKEN = "ghp_x" }
+restart = "on-failure:3"
+port = 3100
+rate_limit = 10
+replicas = 3
+circuit_breaker = { failures = 3 }
+cache = { list_tables = 60 }
+max_result_size = 20000
+affinity = "session"
+tools.deny = ["delete_*", "merge_pull_request"]
+tools.rename = { search_issues = "find_issues" }
+tools.override.create_issue = { description = "File a bug in the team tracker", input_schema = { type = "object" } }
+
+[servers.search]
+url = "https://search.example.com/mcp"
+middleware = ["log", { type = "redact", patterns = ["sess-*"] }]
+
+[prompts.summarize]
+description = "Summarize a directory"
+arguments = [{ name = "path", required = true }]
+template = "Summarize the files in {{path}}."
+"#;
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::parse(EXAMPLE).unwrap();
+        assert_eq!(config.gateway.transport, Some(Transport::Http));
+        assert_eq!(config.gateway.port, Some(4000));
+        assert!(config.gateway.host.is_none());
+        assert_eq!(config.gateway.rate_limit, Some(60));
+        assert_eq!(config.gateway.paths, Some(true));
+        assert_eq!(config.gateway.max_result_size, Some(100000));
+        assert_eq!(config.gateway.ping_interval, Some(30));
+        assert_eq!(config.gateway.strict, Some(true));
+
+        let servers = config.servers().unwrap();
+        let names: Vec<&str> = servers.iter().map(|s| s.spec.name.as_str()).collect();
+        assert_eq!(names, ["github", "search", "shell"]);
+        assert_eq!(
+            servers[0].spec.upstream,
+            Upstream::Package {
+                package: "@modelcontextprotocol/server-github".to_string(),
+                args: vec![],
+                env: vec!["GITHUB_TOKEN=ghp_x".to_string()],
+            }
+        );
+        assert_eq!(servers[0].restart, Some(RestartPolicy::OnFailure { max: Some(3) }));
+        assert_eq!(servers[0].spec.tools.deny, ["delete_*", "merge_pull_request"]);
+        assert!(servers[0].spec.tools.allow.is_empty());
+        assert_eq!(servers[0].spec.tools.rename["search_issues"], "find_issues");
+        let create = &servers[0].spec.tools.overrides["create_issue"];
+        assert_eq!(create.description.as_deref(), Some("File a bug in the team tracker"));
+        assert_eq!(create.input_schema, Some(serde_json::json!({"type": "object"})));
+        assert_eq!(servers[1].spec.tools, ToolRules::default());
+        assert_eq!(
+            servers[1].spec.middleware,
+            [MiddlewareConfig::Log, MiddlewareConfig::Redact { patterns: vec!["sess-*".to_string()] }]
+        );
+        assert!(servers[0].spec.middleware.is_empty());
+        assert_eq!(servers[0].port, Some(3100));
+        assert_eq!(servers[0].spec.rate_limit, Some(10));
+        assert_eq!(servers[0].spec.replicas, 3);
+        assert_eq!(servers[1].spec.replicas, 1);
+        assert_eq!(servers[0].spec.circuit_breaker, BreakerSettings { failures: 3, cooldown: 30 });
+        assert_eq!(servers[1].spec.circuit_breaker, BreakerSettings::default());
+        assert_eq!(servers[0].spec.cache, BTreeMap::from([("list_tables".to_string(), 60)]));
+        assert!(servers[1].spec.cache.is_empty());
+        assert_eq!(servers[0].spec.max_result_size, Some(20000));
+        assert!(servers[1].spec.max_result_size.is_none());
+        assert_eq!(servers[0].spec.affinity, Some(Affinity::Session));
+        assert!(servers[1].spec.affinity.is_none());
+        assert!(servers[1].spec.rate_limit.is_none());
+        assert_eq!(servers[1].spec.upstream, Upstream::Remote("https://search.example.com/mcp".to_string()));
+        assert_eq!(
+            servers[2].spec.upstream,
+            Upstream::Builtin(vec!["shell".to_string(), "--allow".to_string(), "ls*,git*".to_string()])
+        );
+        assert!(servers[2].restart.is_none());
+
+        let prompts = config.prompts().unwrap();
+        assert_eq!(prompts["summarize"].arguments[0].name, "path");
+        assert!(prompts["summarize"].arguments[0].required);
+    }
+
+    #[test]
+    fn test_config_rejects_invalid_servers() {
+        let error = |toml: &str| format!("{:#}", Config::parse(toml).and_then(|c| c.servers()).unwrap_err());
+        assert!(error("[servers.a]\npackage = \"x\"\nurl = \"http://h/mcp\"").contains("exactly one"));
+        assert!(error("[servers.a]\nargs = [\"x\"]").contains("exactly one"));
+        assert!(error("[servers.a]\nbuiltin = \"ftp\"").contains("Unknown builtin 'ftp'"));
+        assert!(error("[servers.a]\nbuiltin = \"shell\"\nenv = { A = \"1\" }").contains("only supported for packages"));
+        assert!(error("[servers.a]\nurl = \"http://h/mcp\"\nargs = [\"x\"]").contains("can't be used with url"));
+        assert!(error("[servers.a]\nbuiltin = \"shell\"\nreplicas = 0").contains("at least 1"));
+        assert!(error("[servers.a]\nbuiltin = \"shell\"\nreplicas = 2\naffinity = \"dedicated\"").contains("can't be used"));
+        assert!(error("[servers.a]\npackage = \"x\"\nrestart = \"always\"").contains("invalid restart policy"));
+        assert!(error("[servers.\"my server\"]\npackage = \"x\"").contains("Invalid server name"));
+        assert!(error("[servers.a]\npackage = \"x\"\nimage = \"y\"").contains("unknown field"));
+        assert!(error("[servers.a]\npackage = \"x\"\ntools.hide = [\"y\"]").contains("unknown field"));
+    }
+
+    #[test]
+    fn test_select_profile() {
+        let config = Config::parse(&format!(
+            "{}{}",
+            EXAMPLE,
+            r#"
+[servers.files]
+builtin = "filesystem"
+args = ["--dir", "/srv/shared"]
+
+[profile.work]
+include = ["github", "files"]
+env = { GITHUB_TOKEN = "ghp_work", GITHUB_HOST = "github.corp" }
+directories = ["/home/me/work"]
+gateway = { port = 4100 }
+
+[profile.work.servers.jira]
+package = "mcp-jira"
+"#
+        ))
+        .unwrap();
+
+        let work = config.select_profile("work").unwrap();
+        assert_eq!(work.gateway.port, Some(4100));
+        assert_eq!(work.gateway.transport, Some(Transport::Http));
+        let servers = work.servers().unwrap();
+        let names: Vec<&str> = servers.iter().map(|s| s.spec.name.as_str()).collect();
+        assert_eq!(names, ["files", "github", "jira"]);
+        assert_eq!(
+            servers[0].spec.upstream,
+            Upstream::Builtin(["filesystem", "--dir", "/srv/shared", "--dir", "/home/me/work"].map(String::from).to_vec())
+        );
+        // The server's own token wins over the profile's
+        let Upstream::Package { env, .. } = &servers[1].spec.upstream else { panic!("expected a package") };
+        assert_eq!(env, &["GITHUB_HOST=github.corp", "GITHUB_TOKEN=ghp_x"]);
+        let Upstream::Package { env, .. } = &servers[2].spec.upstream else { panic!("expected a package") };
+        assert_eq!(env, &["GITHUB_HOST=github.corp", "GITHUB_TOKEN=ghp_work"]);
+    }
+
+    #[test]
+    fn test_select_profile_errors() {
+        let error = |toml: &str, profile: &str| Config::parse(toml).unwrap().select_profile(profile).unwrap_err().to_string();
+        assert!(error(EXAMPLE, "work").contains("No profile named 'work' (defined: none)"));
+        assert!(error("[profile.home]
+[profile.work]", "play").contains("(defined: home, work)"));
+        assert!(error("[profile.work]
+include = [\"nope\"]", "work").contains("unknown server 'nope'"));
+    }
+
+    #[test]
+    fn test_load_default_and_explicit() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing.toml");
+        assert!(Config::load(Some(&missing)).is_err());
+
+        let path = dir.path().join("config.toml");
+        fs::write(&path, EXAMPLE).unwrap();
+        let (loaded, config) = Config::load(Some(&path)).unwrap();
+        assert_eq!(loaded, path);
+        assert_eq!(config.servers.len(), 3);
+    }
+}
@@ -0,0 +1,69 @@
+use anyhow::{Context, Result};
+use colored::Colorize;
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::client::McpClient;
+use crate::http::client::HttpClient;
+use crate::servers::common::JsonRpcResponse;
+
+/// JSON-RPC error code for requests the remote server couldn't be reached for
+const UPSTREAM_ERROR_CODE: i32 = -32603;
+
+/// `mcpz connect`: serve the remote Streamable HTTP server at `url` on stdin/stdout, for
+/// clients that only speak stdio. Messages are relayed as they are (the client's own ids
+/// and `initialize` go through); whatever the server sends besides responses, on a
+/// response's event stream or the session's GET stream, is written to stdout too.
+pub fn connect(url: &str, token: Option<String>, timeout: Duration, verbose: bool) -> Result<()> {
+    let mut client = HttpClient::new(url)?.with_token(token).on_message(Arc::new(write_message));
+    eprintln!("{}", format!("[mcpz] Bridging stdio to {}", url).cyan());
+
+    let mut subscribed = false;
+    for line in std::io::stdin().lock().lines() {
+        let line = line.context("Failed to read stdin")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let message: Value = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(e) => {
+                write_message(json!(JsonRpcResponse::parse_error(format!("Parse error: {}", e))));
+                continue;
+            }
+        };
+        let method = message.get("method").and_then(Value::as_str).unwrap_or("response").to_string();
+        if verbose {
+            eprintln!("[mcpz] -> {}", method);
+        }
+
+        match client.exchange(&message, timeout) {
+            Ok(Some(response)) => {
+                write_message(response);
+                // The session exists once initialize is answered; listen for what the server sends on its own
+                if method == "initialize" && !subscribed {
+                    client.subscribe()?;
+                    subscribed = true;
+                }
+            }
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("{}", format!("[mcpz] {} failed: {:#}", method, e).red());
+                if let Some(id) = message.get("id").filter(|_| message.get("method").is_some()) {
+                    write_message(json!(JsonRpcResponse::error(Some(id.clone()), UPSTREAM_ERROR_CODE, format!("{:#}", e))));
+                }
+            }
+        }
+    }
+
+    client.close();
+    Ok(())
+}
+
+/// Write one message to stdout as a line (whole lines, so the event stream thread can't interleave)
+fn write_message(message: Value) {
+    let mut stdout = std::io::stdout().lock();
+    let _ = writeln!(stdout, "{}", message);
+    let _ = stdout.flush();
+}
@@ -0,0 +1,407 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::PackageType;
+
+/// Credentials to attach to a registry request
+#[derive(Debug, Clone, PartialEq)]
+pub enum Auth {
+    Bearer(String),
+    Basic { login: String, password: String },
+}
+
+/// The parts of `.npmrc` that matter for resolving private packages
+#[derive(Debug, Default)]
+pub struct Npmrc {
+    /// `@scope:registry=` entries, keyed by scope (with the @)
+    scope_registries: HashMap<String, String>,
+    /// `//host/path/:_authToken=` entries, keyed by the `//host/path/` prefix
+    auth_tokens: Vec<(String, String)>,
+}
+
+impl Npmrc {
+    /// Parse `.npmrc` content, expanding `${VAR}` references from `lookup`
+    pub fn parse(content: &str, lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let mut npmrc = Self::default();
+
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let (key, value) = (key.trim(), expand_vars(value.trim(), &lookup));
+
+            if let Some(scope) = key.strip_suffix(":registry").filter(|s| s.starts_with('@')) {
+                npmrc
+                    .scope_registries
+                    .insert(scope.to_string(), value.trim_end_matches('/').to_string());
+            } else if let Some(prefix) = key.strip_suffix(":_authToken").filter(|s| s.starts_with("//")) {
+                npmrc.auth_tokens.push((prefix.to_string(), value));
+            }
+        }
+
+        npmrc
+    }
+
+    /// Load `~/.npmrc`, then `./.npmrc` (project entries take precedence)
+    pub fn load() -> Self {
+        let lookup = |key: &str| std::env::var(key).ok();
+        let mut npmrc = Self::default();
+        let paths = [dirs::home_dir().map(|h| h.join(".npmrc")), Some(PathBuf::from(".npmrc"))];
+        for path in paths.into_iter().flatten() {
+            if let Ok(content) = fs::read_to_string(&path) {
+                let parsed = Self::parse(&content, lookup);
+                npmrc.scope_registries.extend(parsed.scope_registries);
+                npmrc.auth_tokens.splice(0..0, parsed.auth_tokens);
+            }
+        }
+        npmrc
+    }
+
+    /// Registry configured for a scope such as `@mycorp`
+    pub fn registry_for_scope(&self, scope: &str) -> Option<&str> {
+        self.scope_registries.get(scope).map(|s| s.as_str())
+    }
+
+    /// All registries configured for scopes
+    pub fn scope_registries(&self) -> impl Iterator<Item = &str> {
+        self.scope_registries.values().map(|s| s.as_str())
+    }
+
+    /// Token whose `//host/path/` prefix is the longest match for `url`
+    pub fn token_for(&self, url: &str) -> Option<&str> {
+        let target = nerf_dart(url);
+        self.auth_tokens
+            .iter()
+            .filter(|(prefix, _)| target.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, token)| token.as_str())
+    }
+}
+
+/// Logins from a `.netrc` file
+#[derive(Debug, Default)]
+pub struct Netrc {
+    machines: HashMap<String, (String, String)>,
+    default: Option<(String, String)>,
+}
+
+impl Netrc {
+    pub fn parse(content: &str) -> Self {
+        let mut netrc = Self::default();
+        let mut tokens = content.split_whitespace();
+        // Host being defined; None means the `default` entry
+        let mut current: Option<Option<String>> = None;
+        let (mut login, mut password) = (String::new(), String::new());
+
+        let mut finish = |current: Option<Option<String>>, login: &mut String, password: &mut String| {
+            if let Some(host) = current {
+                let entry = (std::mem::take(login), std::mem::take(password));
+                match host {
+                    Some(host) => {
+                        netrc.machines.insert(host, entry);
+                    }
+                    None => netrc.default = Some(entry),
+                }
+            }
+        };
+
+        while let Some(token) = tokens.next() {
+            match token {
+                "machine" => {
+                    finish(current.take(), &mut login, &mut password);
+                    current = tokens.next().map(|h| Some(h.to_string()));
+                }
+                "default" => {
+                    finish(current.take(), &mut login, &mut password);
+                    current = Some(None);
+                }
+                "login" => login = tokens.next().unwrap_or_default().to_string(),
+                "password" => password = tokens.next().unwrap_or_default().to_string(),
+                _ => {}
+            }
+        }
+        finish(current, &mut login, &mut password);
+
+        netrc
+    }
+
+    /// Load `$NETRC` or `~/.netrc`
+    pub fn load() -> Self {
+        let path = std::env::var_os("NETRC")
+            .map(PathBuf::from)
+            .or_else(|| dirs::home_dir().map(|h| h.join(".netrc")));
+        path.and_then(|p| fs::read_to_string(p).ok())
+            .map(|content| Self::parse(&content))
+            .unwrap_or_default()
+    }
+
+    pub fn login_for(&self, host: &str) -> Option<(&str, &str)> {
+        self.machines
+            .get(host)
+            .or(self.default.as_ref())
+            .map(|(login, password)| (login.as_str(), password.as_str()))
+    }
+}
+
+/// Registry credentials: an explicit `--registry-token`, `.npmrc` tokens and `.netrc` logins
+#[derive(Debug, Default)]
+pub struct Credentials {
+    token: Option<String>,
+    /// Hosts the explicit token may be sent to
+    token_hosts: Vec<String>,
+    npmrc: Npmrc,
+    netrc: Netrc,
+}
+
+impl Credentials {
+    /// `token_registries` are the private registries the explicit token belongs to
+    pub fn new(token: Option<String>, token_registries: &[&str], npmrc: Npmrc, netrc: Netrc) -> Self {
+        let mut token_hosts: Vec<String> = token_registries
+            .iter()
+            .copied()
+            .chain(npmrc.scope_registries())
+            .map(|url| host_of(url).to_string())
+            .collect();
+        token_hosts.sort();
+        token_hosts.dedup();
+
+        Self {
+            token: token.filter(|t| !t.is_empty()),
+            token_hosts,
+            npmrc,
+            netrc,
+        }
+    }
+
+    pub fn npmrc(&self) -> &Npmrc {
+        &self.npmrc
+    }
+
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    /// Credentials for a request to `url`, if any are configured for its host
+    pub fn authorization(&self, url: &str) -> Option<Auth> {
+        let host = host_of(url);
+        if let Some(token) = &self.token {
+            if self.token_hosts.iter().any(|h| h == host) {
+                return Some(Auth::Bearer(token.clone()));
+            }
+        }
+        if let Some(token) = self.npmrc.token_for(url) {
+            return Some(Auth::Bearer(token.to_string()));
+        }
+        let hostname = host.split(':').next().unwrap_or(host);
+        self.netrc.login_for(hostname).map(|(login, password)| Auth::Basic {
+            login: login.to_string(),
+            password: password.to_string(),
+        })
+    }
+}
+
+static CREDENTIALS: OnceLock<Credentials> = OnceLock::new();
+
+/// Load credentials for this process; call once before any registry access
+pub fn init(token: Option<String>, token_registries: &[&str]) {
+    let _ = CREDENTIALS.set(Credentials::new(token, token_registries, Npmrc::load(), Netrc::load()));
+}
+
+/// Credentials for this process (files only, if `init` was never called)
+pub fn credentials() -> &'static Credentials {
+    CREDENTIALS.get_or_init(|| Credentials::new(None, &[], Npmrc::load(), Netrc::load()))
+}
+
+/// Extra environment handed to a runner so it can authenticate with the explicit token.
+/// Holds a temporary `.npmrc` that is deleted when dropped.
+#[derive(Default)]
+pub struct RunnerAuth {
+    pub env: Vec<(String, String)>,
+    npmrc: Option<tempfile::NamedTempFile>,
+}
+
+impl RunnerAuth {
+    /// `registry` is where the runner will fetch the package from (npm registry or PyPI index)
+    pub fn prepare(pkg_type: PackageType, registry: &str) -> Result<Self> {
+        let mut auth = Self::default();
+        let Some(token) = credentials().token() else {
+            return Ok(auth);
+        };
+
+        match pkg_type {
+            PackageType::Npm | PackageType::Deno => {
+                // npm can't take _authToken from the environment, so layer it over the user's .npmrc
+                let existing = dirs::home_dir()
+                    .and_then(|h| fs::read_to_string(h.join(".npmrc")).ok())
+                    .unwrap_or_default();
+                let content = format!("{}\n{}:_authToken={}\n", existing, nerf_dart(registry), token);
+
+                let npmrc = write_private(&content)?;
+                auth.env.push(("NPM_CONFIG_USERCONFIG".to_string(), npmrc.path().to_string_lossy().to_string()));
+                auth.env.push(("NPM_CONFIG_TOKEN".to_string(), token.to_string()));
+                auth.npmrc = Some(npmrc);
+            }
+            PackageType::Python => {
+                if let Some((scheme, rest)) = registry.split_once("://") {
+                    auth.env.push((
+                        "UV_INDEX_URL".to_string(),
+                        format!("{}://__token__:{}@{}", scheme, token, rest),
+                    ));
+                }
+            }
+            PackageType::Cargo | PackageType::Docker => {}
+        }
+
+        Ok(auth)
+    }
+}
+
+/// Write a new temp file readable only by the current user, removed when dropped. It gets a
+/// random name and is created exclusively, so no file or symlink planted in the temp
+/// directory beforehand can receive the content.
+fn write_private(content: &str) -> Result<tempfile::NamedTempFile> {
+    let mut file = tempfile::Builder::new()
+        .prefix("mcpz-npmrc-")
+        .tempfile()
+        .context("Failed to create temporary npmrc")?;
+    std::io::Write::write_all(&mut file, content.as_bytes()).context("Failed to write npmrc")?;
+    Ok(file)
+}
+
+/// Host (with port) of a URL
+fn host_of(url: &str) -> &str {
+    let rest = url.split_once("://").map(|(_, r)| r).unwrap_or(url);
+    rest.split('/').next().unwrap_or(rest)
+}
+
+/// npm's "nerf dart" form of a URL: `//host/path/` without the scheme
+fn nerf_dart(url: &str) -> String {
+    let rest = url.split_once("://").map(|(_, r)| r).unwrap_or(url);
+    if rest.ends_with('/') {
+        format!("//{}", rest)
+    } else {
+        format!("//{}/", rest)
+    }
+}
+
+/// Expand `${VAR}` references the way npm does
+fn expand_vars(value: &str, lookup: &impl Fn(&str) -> Option<String>) -> String {
+    let mut result = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        result.push_str(&rest[..start]);
+        result.push_str(&lookup(&rest[start + 2..start + end]).unwrap_or_default());
+        rest = &rest[start + end + 1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_npmrc_parse_scopes_and_tokens() {
+        let npmrc = Npmrc::parse(
+            "# corp registry\n@mycorp:registry=https://npm.corp.example/api/npm/\n//npm.corp.example/api/npm/:_authToken=${NPM_TOKEN}\nregistry=https://registry.npmjs.org/\n",
+            |key| (key == "NPM_TOKEN").then(|| "s3cret".to_string()),
+        );
+
+        assert_eq!(npmrc.registry_for_scope("@mycorp"), Some("https://npm.corp.example/api/npm"));
+        assert_eq!(npmrc.registry_for_scope("@other"), None);
+        assert_eq!(
+            npmrc.token_for("https://npm.corp.example/api/npm/@mycorp/internal-mcp/latest"),
+            Some("s3cret")
+        );
+        assert_eq!(npmrc.token_for("https://registry.npmjs.org/left-pad"), None);
+    }
+
+    #[test]
+    fn test_npmrc_longest_prefix_wins() {
+        let npmrc = Npmrc::parse(
+            "//corp.example/:_authToken=broad\n//corp.example/npm/:_authToken=narrow\n",
+            |_| None,
+        );
+        assert_eq!(npmrc.token_for("https://corp.example/npm/pkg"), Some("narrow"));
+        assert_eq!(npmrc.token_for("https://corp.example/pypi/pkg"), Some("broad"));
+    }
+
+    #[test]
+    fn test_netrc_parse() {
+        let netrc = Netrc::parse(
+            "machine pypi.corp.example\n  login alice\n  password hunter2\ndefault login anon password none\n",
+        );
+        assert_eq!(netrc.login_for("pypi.corp.example"), Some(("alice", "hunter2")));
+        assert_eq!(netrc.login_for("elsewhere"), Some(("anon", "none")));
+    }
+
+    #[test]
+    fn test_authorization_sources() {
+        let npmrc = Npmrc::parse(
+            "@mycorp:registry=https://npm.corp.example\n//npm.corp.example/:_authToken=from-npmrc\n",
+            |_| None,
+        );
+        let netrc = Netrc::parse("machine pypi.corp.example login alice password hunter2");
+        let creds = Credentials::new(None, &[], npmrc, netrc);
+
+        assert_eq!(
+            creds.authorization("https://npm.corp.example/@mycorp/x/latest"),
+            Some(Auth::Bearer("from-npmrc".to_string()))
+        );
+        assert_eq!(
+            creds.authorization("https://pypi.corp.example/pypi/x/json"),
+            Some(Auth::Basic { login: "alice".to_string(), password: "hunter2".to_string() })
+        );
+        assert_eq!(creds.authorization("https://crates.io/api/v1/crates?q=x"), None);
+    }
+
+    #[test]
+    fn test_explicit_token_only_sent_to_private_registries() {
+        let npmrc = Npmrc::parse("@mycorp:registry=https://npm.corp.example\n", |_| None);
+        let creds = Credentials::new(
+            Some("cli-token".to_string()),
+            &["https://pypi.corp.example/simple"],
+            npmrc,
+            Netrc::default(),
+        );
+
+        let bearer = Some(Auth::Bearer("cli-token".to_string()));
+        assert_eq!(creds.authorization("https://npm.corp.example/@mycorp/x/latest"), bearer);
+        assert_eq!(creds.authorization("https://pypi.corp.example/pypi/x/json"), bearer);
+        assert_eq!(creds.authorization("https://api.npmjs.org/downloads/point/last-month/x"), None);
+    }
+
+    #[test]
+    fn test_nerf_dart() {
+        assert_eq!(nerf_dart("https://npm.corp.example/api/npm"), "//npm.corp.example/api/npm/");
+        assert_eq!(nerf_dart("https://registry.npmjs.org/"), "//registry.npmjs.org/");
+        assert_eq!(host_of("https://npm.corp.example:8443/api"), "npm.corp.example:8443");
+    }
+
+    #[test]
+    fn test_write_private_uses_fresh_private_files() {
+        let first = write_private("//registry/:_authToken=secret\n").unwrap();
+        let second = write_private("").unwrap();
+        assert_ne!(first.path(), second.path());
+        assert_eq!(fs::read_to_string(first.path()).unwrap(), "//registry/:_authToken=secret\n");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(first.path()).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        let path = first.path().to_path_buf();
+        drop(first);
+        assert!(!path.exists());
+    }
+}
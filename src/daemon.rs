@@ -0,0 +1,760 @@
+use anyhow::{anyhow, Context, Result};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::client::McpClient;
+use crate::gateway::{ServerSpec, Upstream};
+use crate::http::client::HttpClient;
+use crate::{signals, RestartPolicy};
+
+/// How often supervisor threads check on their process
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How often a running server's endpoint is checked, and how long it has to answer
+const HEALTH_INTERVAL: Duration = Duration::from_secs(30);
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Failed checks in a row after which a server that is still running gets restarted
+const HEALTH_FAILURES: u32 = 3;
+
+/// How long a control client has to send its request and read the answer, so one that
+/// never does can't hold up the others
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A package the daemon keeps running, served over HTTP on its own port
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceSpec {
+    pub name: String,
+    /// The package, or `builtin:TYPE` for a built-in server (as shown by `mcpz ps`)
+    pub package: String,
+    pub port: u16,
+    /// `mcpz server` arguments for a built-in server
+    pub builtin: Option<Vec<String>>,
+    /// Arguments and KEY=VALUE environment for a package
+    pub args: Vec<String>,
+    pub env: Vec<String>,
+    /// Overrides the daemon's --restart policy for this server
+    pub restart: Option<RestartPolicy>,
+    /// Calls allowed per minute of each tool in each session
+    pub rate_limit: Option<u32>,
+}
+
+impl ServiceSpec {
+    /// Parse `NAME=PACKAGE`, or a bare `PACKAGE` named after the package
+    pub fn parse(spec: &str, port: u16) -> Result<Self> {
+        let (name, package) = match spec.split_once('=') {
+            Some((name, package)) => (name.to_string(), package.to_string()),
+            None => (crate::tool_prefix(spec), spec.to_string()),
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(anyhow!(
+                "Invalid server name '{}': use letters, digits, '-' and '_'",
+                name
+            ));
+        }
+        if package.is_empty() {
+            return Err(anyhow!("No package given for server '{}'", name));
+        }
+        Ok(Self {
+            name,
+            package,
+            port,
+            builtin: None,
+            args: vec![],
+            env: vec![],
+            restart: None,
+            rate_limit: None,
+        })
+    }
+
+    /// A server from the config file; remote servers can't be supervised
+    pub fn from_server(server: &ServerSpec, port: u16, restart: Option<RestartPolicy>) -> Result<Self> {
+        let mut spec = Self {
+            name: server.name.clone(),
+            package: String::new(),
+            port,
+            builtin: None,
+            args: vec![],
+            env: vec![],
+            restart,
+            rate_limit: server.rate_limit,
+        };
+        match &server.upstream {
+            Upstream::Builtin(args) => {
+                spec.package = format!("builtin:{}", args.first().map(String::as_str).unwrap_or_default());
+                spec.builtin = Some(args.clone());
+            }
+            Upstream::Package { package, args, env } => {
+                spec.package = package.clone();
+                spec.args = args.clone();
+                spec.env = env.clone();
+            }
+            Upstream::Remote(url) => {
+                return Err(anyhow!("{} is a remote server ({}); the daemon only runs packages and built-ins", server.name, url))
+            }
+        }
+        Ok(spec)
+    }
+
+    fn endpoint(&self) -> String {
+        format!("http://127.0.0.1:{}/mcp", self.port)
+    }
+
+    /// `mcpz run --http` for this package, so each server gets the proxy's handshake and
+    /// sessions (`mcpz server --http` for a built-in)
+    fn command(&self) -> Result<Command> {
+        let mut cmd = Command::new(std::env::current_exe().context("Could not locate the mcpz binary")?);
+        let port = self.port.to_string();
+        let rate_limit = self.rate_limit.map(|limit| ["--rate-limit".to_string(), limit.to_string()]);
+        if let Some(args) = &self.builtin {
+            cmd.arg("server").args(args).args(["--http", "--quiet", "--port", &port]);
+            cmd.args(rate_limit.iter().flatten());
+            return Ok(cmd);
+        }
+        cmd.args(["run", "--first", "--http", "--quiet", "--port", &port]);
+        cmd.args(rate_limit.iter().flatten());
+        for env in &self.env {
+            cmd.args(["-e", env]);
+        }
+        cmd.arg(&self.package);
+        if !self.args.is_empty() {
+            cmd.arg("--").args(&self.args);
+        }
+        Ok(cmd)
+    }
+}
+
+/// Lifecycle state of a supervised server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceState {
+    Running,
+    /// Waiting out the backoff before the next restart
+    Restarting,
+    /// Exited cleanly or stopped with `mcpz stop`
+    Stopped,
+    /// Crashed and out of restarts (or couldn't be spawned)
+    Failed,
+}
+
+struct Service {
+    spec: ServiceSpec,
+    child: Option<Child>,
+    state: ServiceState,
+    restarts: u32,
+    started: Option<Instant>,
+    stop_requested: bool,
+    /// When a process asked to stop is killed if it's still running
+    kill_at: Option<Instant>,
+}
+
+impl Service {
+    /// Ask the process to stop with SIGTERM, which `mcpz run` passes on to the package, and
+    /// have the supervisor kill it if it is still running `STOP_GRACE` later
+    fn terminate(&mut self) {
+        if let Some(child) = self.child.as_mut() {
+            signals::terminate(child);
+            self.kill_at.get_or_insert(Instant::now() + signals::STOP_GRACE);
+        }
+    }
+}
+
+type Services = Arc<Mutex<BTreeMap<String, Service>>>;
+
+/// A request sent to the daemon's control socket (one JSON line)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "lowercase")]
+pub enum Request {
+    Ps,
+    Stop { name: String },
+    Logs { name: String, lines: usize },
+}
+
+/// The daemon's answer to a `Request` (one JSON line)
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Response {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub services: Vec<ServiceStatus>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lines: Vec<String>,
+}
+
+impl Response {
+    fn error(message: String) -> Self {
+        Self {
+            error: Some(message),
+            ..Default::default()
+        }
+    }
+}
+
+/// One row of `mcpz ps`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServiceStatus {
+    pub name: String,
+    pub package: String,
+    pub endpoint: String,
+    pub state: ServiceState,
+    pub pid: Option<u32>,
+    pub restarts: u32,
+    pub uptime_secs: Option<u64>,
+}
+
+fn daemon_dir() -> Result<PathBuf> {
+    Ok(dirs::cache_dir()
+        .ok_or_else(|| anyhow!("Could not determine cache directory"))?
+        .join("mcpz/daemon"))
+}
+
+/// Control socket shared by `mcpz daemon` and `mcpz ps/stop/logs`
+fn socket_path() -> Result<PathBuf> {
+    Ok(daemon_dir()?.join("mcpz.sock"))
+}
+
+fn log_path(name: &str) -> Result<PathBuf> {
+    Ok(daemon_dir()?.join("logs").join(format!("{}.log", name)))
+}
+
+/// Bind the control socket so only this user can connect, whatever the umask: its directory
+/// is made 0700 before binding, so nobody else can reach the socket in between, and the
+/// socket itself 0600
+fn bind_private(socket: &Path) -> Result<UnixListener> {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = socket.parent().unwrap_or(Path::new("."));
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+        .with_context(|| format!("Failed to restrict {}", dir.display()))?;
+    let listener = UnixListener::bind(socket)
+        .with_context(|| format!("Failed to bind control socket {}", socket.display()))?;
+    fs::set_permissions(socket, fs::Permissions::from_mode(0o600))
+        .with_context(|| format!("Failed to restrict control socket {}", socket.display()))?;
+    Ok(listener)
+}
+
+/// Run the daemon in the foreground: start every service, supervise them and answer control requests
+pub fn run(specs: Vec<ServiceSpec>, restart: RestartPolicy) -> Result<()> {
+    let mut seen = std::collections::HashSet::new();
+    for spec in &specs {
+        if !seen.insert(&spec.name) {
+            return Err(anyhow!("Server name '{}' is used twice; name them with NAME=PACKAGE", spec.name));
+        }
+    }
+
+    let socket = socket_path()?;
+    fs::create_dir_all(socket.parent().unwrap_or(Path::new(".")).join("logs"))
+        .context("Failed to create daemon directory")?;
+    if UnixStream::connect(&socket).is_ok() {
+        return Err(anyhow!("mcpz daemon is already running ({})", socket.display()));
+    }
+    // A socket nobody answers on is left over from a daemon that was killed
+    let _ = fs::remove_file(&socket);
+    let listener = bind_private(&socket)?;
+
+    let services: Services = Arc::new(Mutex::new(BTreeMap::new()));
+    for spec in specs {
+        println!(
+            "{}",
+            format!("[mcpz] Starting {} ({}) on {}", spec.name, spec.package, spec.endpoint()).cyan()
+        );
+        let name = spec.name.clone();
+        let spec_restart = spec.restart;
+        services.lock().unwrap().insert(
+            name.clone(),
+            Service {
+                spec,
+                child: None,
+                state: ServiceState::Running,
+                restarts: 0,
+                started: None,
+                stop_requested: false,
+                kill_at: None,
+            },
+        );
+        let services = services.clone();
+        let restart = spec_restart.unwrap_or(restart);
+        std::thread::spawn(move || supervise(&services, &name, restart));
+    }
+    println!(
+        "{}",
+        format!("[mcpz] Daemon ready; control socket {}", socket.display()).green()
+    );
+
+    serve(listener, &services);
+    Ok(())
+}
+
+/// Answer control requests, each connection on its own thread
+fn serve(listener: UnixListener, services: &Services) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let services = services.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = serve_client(stream, &services) {
+                        eprintln!("[mcpz] Control request failed: {}", e);
+                    }
+                });
+            }
+            Err(e) => eprintln!("[mcpz] Control socket error: {}", e),
+        }
+    }
+}
+
+/// Keep one service running, restarting it after failures as the policy allows. A server
+/// whose endpoint stops answering is stopped and restarted the same way.
+fn supervise(services: &Services, name: &str, restart: RestartPolicy) {
+    let Ok(log_path) = log_path(name) else {
+        return;
+    };
+    let mut consecutive = 0;
+
+    loop {
+        {
+            let mut all = services.lock().unwrap();
+            let Some(service) = all.get_mut(name) else {
+                return;
+            };
+            if service.stop_requested {
+                service.state = ServiceState::Stopped;
+                return;
+            }
+            match spawn_service(&service.spec, &log_path) {
+                Ok(child) => {
+                    service.child = Some(child);
+                    service.state = ServiceState::Running;
+                    service.started = Some(Instant::now());
+                }
+                Err(e) => {
+                    append_log(&log_path, &format!("[mcpz] Failed to start {}: {}", name, e));
+                    service.state = ServiceState::Failed;
+                    return;
+                }
+            }
+        }
+
+        let mut next_check = Instant::now() + HEALTH_INTERVAL;
+        let mut failures = 0;
+        let status = loop {
+            std::thread::sleep(POLL_INTERVAL);
+            let endpoint = {
+                let mut all = services.lock().unwrap();
+                let Some(service) = all.get_mut(name) else {
+                    return;
+                };
+                let kill_due = service.kill_at.is_some_and(|at| Instant::now() >= at);
+                let Some(child) = service.child.as_mut() else {
+                    return;
+                };
+                match child.try_wait() {
+                    Ok(Some(status)) => break Some(status),
+                    Ok(None) => {}
+                    Err(_) => break None,
+                }
+                if kill_due {
+                    signals::kill(child);
+                }
+                if service.kill_at.is_some() || Instant::now() < next_check {
+                    continue;
+                }
+                service.spec.endpoint()
+            };
+
+            // Checked without holding the lock, so control requests aren't kept waiting
+            if responds(&endpoint) {
+                failures = 0;
+            } else {
+                failures += 1;
+                append_log(
+                    &log_path,
+                    &format!("[mcpz] {} isn't answering at {} ({}/{})", name, endpoint, failures, HEALTH_FAILURES),
+                );
+                if failures >= HEALTH_FAILURES {
+                    if let Some(service) = services.lock().unwrap().get_mut(name) {
+                        service.terminate();
+                    }
+                }
+            }
+            next_check = Instant::now() + HEALTH_INTERVAL;
+        };
+
+        let delay = {
+            let mut all = services.lock().unwrap();
+            let Some(service) = all.get_mut(name) else {
+                return;
+            };
+            service.child = None;
+            service.kill_at = None;
+            let ran_for = service.started.take().map(|s| s.elapsed()).unwrap_or_default();
+
+            if service.stop_requested || status.is_some_and(|s| s.success()) {
+                service.state = ServiceState::Stopped;
+                return;
+            }
+            let status = status.map(|s| s.to_string()).unwrap_or_else(|| "unknown status".to_string());
+            if !restart.allows(service.restarts) {
+                append_log(&log_path, &format!("[mcpz] {} exited ({}); not restarting", name, status));
+                service.state = ServiceState::Failed;
+                return;
+            }
+
+            if ran_for >= RestartPolicy::STABLE_RUN {
+                consecutive = 0;
+            }
+            consecutive += 1;
+            service.restarts += 1;
+            service.state = ServiceState::Restarting;
+            let delay = RestartPolicy::backoff(consecutive);
+            append_log(
+                &log_path,
+                &format!(
+                    "[mcpz] {} exited ({}); restarting in {}s (restart {})",
+                    name,
+                    status,
+                    delay.as_secs(),
+                    service.restarts
+                ),
+            );
+            delay
+        };
+
+        // Sleep in small steps so `mcpz stop` doesn't wait out the backoff
+        let wake = Instant::now() + delay;
+        while Instant::now() < wake {
+            std::thread::sleep(POLL_INTERVAL);
+            let mut all = services.lock().unwrap();
+            if let Some(service) = all.get_mut(name).filter(|s| s.stop_requested) {
+                service.state = ServiceState::Stopped;
+                return;
+            }
+        }
+    }
+}
+
+fn spawn_service(spec: &ServiceSpec, log_path: &Path) -> Result<Child> {
+    use std::os::unix::process::CommandExt;
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path)
+        .with_context(|| format!("Failed to open {}", log_path.display()))?;
+    let mut cmd = spec.command()?;
+    // Its own process group, so stopping it reaches the package `mcpz run` started as well
+    cmd.process_group(0);
+    cmd.stdin(Stdio::null());
+    cmd.stdout(log.try_clone()?);
+    cmd.stderr(log);
+    cmd.spawn().with_context(|| format!("Failed to spawn {}", spec.package))
+}
+
+fn append_log(path: &Path, line: &str) {
+    if let Ok(mut log) = OpenOptions::new().create(true).append(true).open(path) {
+        let _ = writeln!(log, "{}", line);
+    }
+}
+
+/// Whether the server at `endpoint` completes an MCP handshake in time
+fn responds(endpoint: &str) -> bool {
+    let Ok(mut client) = HttpClient::new(endpoint) else {
+        return false;
+    };
+    let answered = client.initialize(HEALTH_TIMEOUT).is_ok();
+    client.close();
+    answered
+}
+
+fn serve_client(stream: UnixStream, services: &Services) -> Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let response = match serde_json::from_str::<Request>(&line) {
+        Ok(request) => handle_request(services, request),
+        Err(e) => Response::error(format!("Invalid request: {}", e)),
+    };
+    let mut stream = stream;
+    writeln!(stream, "{}", serde_json::to_string(&response)?)?;
+    Ok(())
+}
+
+fn handle_request(services: &Services, request: Request) -> Response {
+    let mut all = services.lock().unwrap();
+    match request {
+        Request::Ps => Response {
+            services: all.values_mut().map(status).collect(),
+            ..Default::default()
+        },
+        Request::Stop { name } => {
+            let Some(service) = all.get_mut(&name) else {
+                return Response::error(format!("No server named '{}'", name));
+            };
+            service.stop_requested = true;
+            service.terminate();
+            Response {
+                services: vec![status(service)],
+                ..Default::default()
+            }
+        }
+        Request::Logs { name, lines } => {
+            if !all.contains_key(&name) {
+                return Response::error(format!("No server named '{}'", name));
+            }
+            drop(all);
+            match log_path(&name).and_then(|path| tail(&path, lines)) {
+                Ok(lines) => Response {
+                    lines,
+                    ..Default::default()
+                },
+                Err(e) => Response::error(e.to_string()),
+            }
+        }
+    }
+}
+
+fn status(service: &mut Service) -> ServiceStatus {
+    ServiceStatus {
+        name: service.spec.name.clone(),
+        package: service.spec.package.clone(),
+        endpoint: service.spec.endpoint(),
+        state: service.state,
+        pid: service.child.as_ref().map(|c| c.id()),
+        restarts: service.restarts,
+        uptime_secs: service.started.map(|s| s.elapsed().as_secs()),
+    }
+}
+
+/// Last `lines` lines of a log file (empty if nothing was logged yet)
+fn tail(path: &Path, lines: usize) -> Result<Vec<String>> {
+    let Ok(file) = File::open(path) else {
+        return Ok(vec![]);
+    };
+    let all: Vec<String> = BufReader::new(file).lines().map_while(Result::ok).collect();
+    Ok(all[all.len().saturating_sub(lines)..].to_vec())
+}
+
+/// Send a request to the running daemon
+pub fn send(request: &Request) -> Result<Response> {
+    let socket = socket_path()?;
+    let mut stream = UnixStream::connect(&socket)
+        .map_err(|_| anyhow!("mcpz daemon isn't running (no control socket at {})", socket.display()))?;
+    writeln!(stream, "{}", serde_json::to_string(request)?)?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let response: Response = serde_json::from_str(&line).context("Invalid response from daemon")?;
+    match response.error {
+        Some(error) => Err(anyhow!(error)),
+        None => Ok(response),
+    }
+}
+
+/// Format seconds as a short uptime (e.g. 45s, 12m, 3h, 2d)
+pub fn format_uptime(secs: u64) -> String {
+    match secs {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m", s / 60),
+        s if s < 86400 => format!("{}h", s / 3600),
+        s => format!("{}d", s / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn services_with(spec: ServiceSpec) -> Services {
+        let mut all = BTreeMap::new();
+        all.insert(
+            spec.name.clone(),
+            Service {
+                spec,
+                child: None,
+                state: ServiceState::Restarting,
+                restarts: 2,
+                started: None,
+                stop_requested: false,
+                kill_at: None,
+            },
+        );
+        Arc::new(Mutex::new(all))
+    }
+
+    #[test]
+    fn test_service_spec_parse() {
+        let spec = ServiceSpec::parse("time=mcp-server-time@1.0", 3001).unwrap();
+        assert_eq!(spec.name, "time");
+        assert_eq!(spec.package, "mcp-server-time@1.0");
+        assert_eq!(spec.endpoint(), "http://127.0.0.1:3001/mcp");
+
+        let spec = ServiceSpec::parse("@modelcontextprotocol/server-filesystem", 3000).unwrap();
+        assert_eq!(spec.name, "server-filesystem");
+
+        assert!(ServiceSpec::parse("my server=pkg", 3000).is_err());
+        assert!(ServiceSpec::parse("name=", 3000).is_err());
+    }
+
+    #[test]
+    fn test_service_command_for_config_servers() {
+        let args = |cmd: &Command| cmd.get_args().map(|a| a.to_string_lossy().into_owned()).collect::<Vec<_>>();
+
+        let server = ServerSpec {
+            name: "gh".to_string(),
+            upstream: Upstream::Package {
+                package: "server-github".to_string(),
+                args: vec!["--read-only".to_string()],
+                env: vec!["GITHUB_TOKEN=x".to_string()],
+            },
+            tools: Default::default(),
+            middleware: vec![],
+            rate_limit: None,
+            replicas: 1,
+            circuit_breaker: Default::default(),
+            cache: Default::default(),
+            max_result_size: None,
+            affinity: None,
+        };
+        let spec = ServiceSpec::from_server(&server, 3005, Some(RestartPolicy::Never)).unwrap();
+        assert_eq!(spec.restart, Some(RestartPolicy::Never));
+        assert_eq!(
+            args(&spec.command().unwrap()),
+            ["run", "--first", "--http", "--quiet", "--port", "3005", "-e", "GITHUB_TOKEN=x", "server-github", "--", "--read-only"]
+        );
+
+        let server = ServerSpec {
+            name: "sh".to_string(),
+            upstream: Upstream::Builtin(vec!["shell".to_string(), "--allow".to_string(), "ls*".to_string()]),
+            tools: Default::default(),
+            middleware: vec![],
+            rate_limit: Some(20),
+            replicas: 1,
+            circuit_breaker: Default::default(),
+            cache: Default::default(),
+            max_result_size: None,
+            affinity: None,
+        };
+        let spec = ServiceSpec::from_server(&server, 3006, None).unwrap();
+        assert_eq!(spec.package, "builtin:shell");
+        assert_eq!(
+            args(&spec.command().unwrap()),
+            ["server", "shell", "--allow", "ls*", "--http", "--quiet", "--port", "3006", "--rate-limit", "20"]
+        );
+
+        let server = ServerSpec {
+            name: "remote".to_string(),
+            upstream: Upstream::Remote("https://example.com/mcp".to_string()),
+            tools: Default::default(),
+            middleware: vec![],
+            rate_limit: None,
+            replicas: 1,
+            circuit_breaker: Default::default(),
+            cache: Default::default(),
+            max_result_size: None,
+            affinity: None,
+        };
+        assert!(ServiceSpec::from_server(&server, 3007, None).is_err());
+    }
+
+    #[test]
+    fn test_request_wire_format() {
+        let json = serde_json::to_string(&Request::Logs { name: "time".to_string(), lines: 10 }).unwrap();
+        assert_eq!(json, r#"{"command":"logs","name":"time","lines":10}"#);
+        assert_eq!(serde_json::from_str::<Request>(r#"{"command":"ps"}"#).unwrap(), Request::Ps);
+    }
+
+    #[test]
+    fn test_handle_request_ps_and_stop() {
+        let services = services_with(ServiceSpec::parse("time=mcp-server-time", 3000).unwrap());
+
+        let ps = handle_request(&services, Request::Ps);
+        assert_eq!(ps.services.len(), 1);
+        assert_eq!(ps.services[0].state, ServiceState::Restarting);
+        assert_eq!(ps.services[0].restarts, 2);
+        assert!(ps.services[0].pid.is_none());
+
+        let stopped = handle_request(&services, Request::Stop { name: "time".to_string() });
+        assert!(stopped.error.is_none());
+        assert!(services.lock().unwrap()["time"].stop_requested);
+
+        let missing = handle_request(&services, Request::Stop { name: "fetch".to_string() });
+        assert!(missing.error.unwrap().contains("No server named 'fetch'"));
+    }
+
+    #[test]
+    fn test_stop_terminates_before_killing() {
+        use std::os::unix::process::ExitStatusExt;
+        let services = services_with(ServiceSpec::parse("time=mcp-server-time", 3000).unwrap());
+        let child = Command::new("sleep").arg("30").spawn().unwrap();
+        services.lock().unwrap().get_mut("time").unwrap().child = Some(child);
+
+        handle_request(&services, Request::Stop { name: "time".to_string() });
+        let mut all = services.lock().unwrap();
+        let service = all.get_mut("time").unwrap();
+        assert!(service.kill_at.is_some_and(|at| at > Instant::now()));
+        let status = service.child.as_mut().unwrap().wait().unwrap();
+        assert_eq!(status.signal(), Some(libc::SIGTERM));
+    }
+
+    #[test]
+    fn test_silent_client_doesnt_block_others() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("mcpz.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let services = services_with(ServiceSpec::parse("time=mcp-server-time", 3000).unwrap());
+        std::thread::spawn(move || serve(listener, &services));
+
+        let _silent = UnixStream::connect(&socket).unwrap();
+        let mut stream = UnixStream::connect(&socket).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        writeln!(stream, "{}", serde_json::to_string(&Request::Ps).unwrap()).unwrap();
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line).unwrap();
+        let response: Response = serde_json::from_str(&line).unwrap();
+        assert_eq!(response.services[0].name, "time");
+    }
+
+    #[test]
+    fn test_responds_fails_without_server() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        assert!(!responds(&format!("http://127.0.0.1:{}/mcp", port)));
+    }
+
+    #[test]
+    fn test_bind_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let daemon = dir.path().join("daemon");
+        fs::create_dir(&daemon).unwrap();
+        fs::set_permissions(&daemon, fs::Permissions::from_mode(0o777)).unwrap();
+
+        let _listener = bind_private(&daemon.join("mcpz.sock")).unwrap();
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&daemon), 0o700);
+        assert_eq!(mode(&daemon.join("mcpz.sock")), 0o600);
+    }
+
+    #[test]
+    fn test_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("svc.log");
+        fs::write(&path, "one\ntwo\nthree\n").unwrap();
+        assert_eq!(tail(&path, 2).unwrap(), vec!["two", "three"]);
+        assert_eq!(tail(&path, 10).unwrap().len(), 3);
+        assert!(tail(&dir.path().join("missing.log"), 5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_format_uptime() {
+        assert_eq!(format_uptime(42), "42s");
+        assert_eq!(format_uptime(600), "10m");
+        assert_eq!(format_uptime(7200), "2h");
+        assert_eq!(format_uptime(172800), "2d");
+    }
+}
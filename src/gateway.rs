@@ -0,0 +1,657 @@
+use anyhow::{anyhow, Context, Result};
+use clap::Parser;
+use colored::Colorize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::credentials::RunnerAuth;
+use crate::http::client::HttpClient;
+use crate::servers::affinity::{Affinity, Launcher, PerSession};
+use crate::servers::breaker::{BreakerSettings, CircuitBreaker};
+use crate::servers::cache::Cached;
+use crate::servers::common::McpServer;
+use crate::servers::filesystem::{FilesystemServer, FilesystemServerConfig};
+use crate::servers::middleware::{MiddlewareConfig, Pipeline};
+use crate::servers::proxy::ProxyServer;
+use crate::servers::ratelimit::RateLimited;
+use crate::servers::replicas::Replicas;
+use crate::servers::router::{Route, RouterServer, ToolRules};
+use crate::servers::shell::{ShellServer, ShellServerConfig};
+use crate::servers::sql::{connect_database, AccessMode, SqlServer, SqlServerConfig};
+use crate::ServerType;
+
+/// Scheme for built-in servers in a gateway server spec
+const BUILTIN_SCHEME: &str = "builtin:";
+
+/// How often `Gateway::watch` checks the config file for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Built-in server types, as accepted by `mcpz server`
+pub const BUILTIN_TYPES: [&str; 3] = ["shell", "filesystem", "sql"];
+
+/// Where a gateway server comes from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Upstream {
+    /// A built-in server: `mcpz server` arguments, starting with its type (e.g. `["shell", "--allow", "ls*"]`)
+    Builtin(Vec<String>),
+    /// A Streamable HTTP endpoint (`http(s)://host/mcp`)
+    Remote(String),
+    /// A package, alias or local path, resolved as `mcpz run` would, with its
+    /// arguments and KEY=VALUE environment
+    Package {
+        package: String,
+        args: Vec<String>,
+        env: Vec<String>,
+    },
+}
+
+impl Upstream {
+    pub fn package(package: &str) -> Self {
+        Upstream::Package {
+            package: package.to_string(),
+            args: vec![],
+            env: vec![],
+        }
+    }
+}
+
+/// One server mounted in the gateway; its tools are exposed as `NAME.tool`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerSpec {
+    pub name: String,
+    pub upstream: Upstream,
+    /// How the gateway presents this server's tools (hidden, renamed, overridden)
+    pub tools: ToolRules,
+    /// Middleware wrapped around this server's tool calls, outermost first
+    pub middleware: Vec<MiddlewareConfig>,
+    /// Calls allowed per minute of each tool in each session (unlimited if None)
+    pub rate_limit: Option<u32>,
+    /// Copies of the server to start, taking tool calls in turn
+    pub replicas: usize,
+    /// When to stop sending calls to a server that keeps failing
+    pub circuit_breaker: BreakerSettings,
+    /// Seconds results of each listed tool are reused for repeated calls in a session
+    pub cache: BTreeMap<String, u64>,
+    /// Bytes of text a tool result may carry before it's truncated (unlimited if None)
+    pub max_result_size: Option<usize>,
+    /// Which process each session's calls go to (any of them if None)
+    pub affinity: Option<Affinity>,
+}
+
+impl ServerSpec {
+    /// Parse `[NAME=]TARGET`, where TARGET is `builtin:shell`, `builtin:filesystem[:DIR,...]`,
+    /// `builtin:sql:URL`, an `http(s)://` URL, or a package
+    pub fn parse(spec: &str) -> Result<Self> {
+        // URLs and connection strings may contain '=', but only after a ':' or '/'
+        let (name, target) = match spec.split_once('=') {
+            Some((name, target)) if !name.contains([':', '/']) => (Some(name.to_string()), target),
+            _ => (None, spec),
+        };
+        if target.is_empty() {
+            return Err(anyhow!("No server given for '{}'", spec));
+        }
+
+        let upstream = if let Some(builtin) = target.strip_prefix(BUILTIN_SCHEME) {
+            Upstream::Builtin(parse_builtin(builtin)?)
+        } else if target.starts_with("http://") || target.starts_with("https://") {
+            Upstream::Remote(target.to_string())
+        } else {
+            Upstream::package(target)
+        };
+        let name = match name {
+            Some(name) => name,
+            None => default_name(&upstream)?,
+        };
+        check_name(&name)?;
+        Ok(Self {
+            name,
+            upstream,
+            tools: ToolRules::default(),
+            middleware: vec![],
+            rate_limit: None,
+            replicas: 1,
+            circuit_breaker: BreakerSettings::default(),
+            cache: BTreeMap::new(),
+            max_result_size: None,
+            affinity: None,
+        })
+    }
+
+    /// Start (or connect to) the server and complete its MCP handshake, without the
+    /// gateway's middleware and rate limit
+    pub fn launch(&self, opts: &crate::RunOptions, timeout: Duration) -> Result<Launched> {
+        eprintln!("{}", format!("[mcpz] Starting {}...", self.name).cyan());
+        let mut auth = None;
+        let server: Box<dyn McpServer + Send + Sync> = match &self.upstream {
+            Upstream::Builtin(args) => start_builtin(args)?,
+            Upstream::Remote(url) => {
+                Box::new(ProxyServer::connect(Box::new(HttpClient::new(url)?), timeout, true, false)?)
+            }
+            Upstream::Package { package, args, env } => {
+                let mut opts = opts.clone();
+                opts.env.extend(env.iter().cloned());
+                let prepared = crate::prepare_package(package, args, &opts)?;
+                let server = ProxyServer::start(prepared.cmd, timeout, true, false)?;
+                if let Some(interval) = opts.ping_interval {
+                    server.keepalive(interval);
+                }
+                auth = Some(prepared._auth);
+                Box::new(server)
+            }
+        };
+        Ok(Launched { server, _auth: auth })
+    }
+
+    /// Launch the server (each replica, if several) wrapped in its circuit breaker,
+    /// middleware, cache and rate limit, ready to mount
+    fn start(&self, opts: &crate::RunOptions, timeout: Duration) -> Result<Mounted> {
+        let mut servers = vec![];
+        let mut auth = vec![];
+        for _ in 0..self.replicas.max(1) {
+            let Launched { server, _auth } = self.launch(opts, timeout)?;
+            servers.push(server);
+            auth.extend(_auth);
+        }
+        let mut server = match (servers.len(), self.affinity) {
+            (1, Some(Affinity::Dedicated)) => {
+                let (spec, opts) = (self.clone(), opts.clone());
+                let launch: Launcher = Box::new(move || {
+                    let Launched { server, _auth } = spec.launch(&opts, timeout)?;
+                    Ok((server, Box::new(_auth)))
+                });
+                Box::new(PerSession::new(&self.name, servers.remove(0), launch))
+            }
+            (1, _) => servers.remove(0),
+            (_, Some(Affinity::Session)) => Box::new(Replicas::new(&self.name, servers).pinned()),
+            _ => Box::new(Replicas::new(&self.name, servers)),
+        };
+        if self.circuit_breaker.enabled() {
+            server = Box::new(CircuitBreaker::new(&self.name, server, self.circuit_breaker));
+        }
+        if !self.middleware.is_empty() {
+            let middleware = self.middleware.iter().map(|config| config.build(&self.name));
+            let middleware = middleware.collect::<Result<_>>()?;
+            server = Box::new(Pipeline::new(server, middleware));
+        }
+        if !self.cache.is_empty() {
+            server = Box::new(Cached::new(server, &self.cache));
+        }
+        if let Some(limit) = self.rate_limit {
+            server = Box::new(RateLimited::new(server, limit));
+        }
+        Ok(Mounted {
+            spec: self.clone(),
+            route: Arc::new(Route {
+                prefix: self.name.clone(),
+                server,
+                tools: self.tools.clone(),
+                max_result_size: self.max_result_size,
+            }),
+            _auth: auth,
+        })
+    }
+}
+
+/// Server names become tool prefixes, so they are restricted to letters, digits, '-' and '_'
+pub fn check_name(name: &str) -> Result<()> {
+    if is_valid_name(name) {
+        Ok(())
+    } else {
+        Err(anyhow!("Invalid server name '{}': use letters, digits, '-' and '_'", name))
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// `mcpz server` arguments for the short built-in forms
+fn parse_builtin(spec: &str) -> Result<Vec<String>> {
+    let (kind, value) = match spec.split_once(':') {
+        Some((kind, value)) => (kind, Some(value)),
+        None => (spec, None),
+    };
+    let mut args = vec![kind.to_string()];
+    match (kind, value) {
+        ("shell", None) => {}
+        ("filesystem", dirs) => {
+            for dir in dirs.into_iter().flat_map(|dirs| dirs.split(',')).filter(|dir| !dir.is_empty()) {
+                args.extend(["--dir".to_string(), dir.to_string()]);
+            }
+        }
+        ("sql", Some(connection)) if !connection.is_empty() => {
+            args.extend(["--connection".to_string(), connection.to_string(), "--readonly".to_string()]);
+        }
+        ("sql", _) => return Err(anyhow!("builtin:sql needs a connection string, e.g. builtin:sql:sqlite:///path/to/db")),
+        _ => {
+            return Err(anyhow!(
+                "Unknown built-in server '{}': use builtin:shell, builtin:filesystem[:DIR,...] or builtin:sql:URL",
+                spec
+            ))
+        }
+    }
+    Ok(args)
+}
+
+/// `mcpz server` arguments, parsed the same way as on the command line
+#[derive(Parser)]
+#[command(name = "builtin", no_binary_name = true)]
+struct BuiltinCommand {
+    #[command(subcommand)]
+    server: ServerType,
+}
+
+/// Build a built-in server in-process from its `mcpz server` arguments
+fn start_builtin(args: &[String]) -> Result<Box<dyn McpServer + Send + Sync>> {
+    let command = BuiltinCommand::try_parse_from(args)
+        .map_err(|e| anyhow!("Invalid built-in server arguments '{}':\n{}", args.join(" "), e))?;
+    let transport_only = |http: bool, record: &Option<PathBuf>| {
+        if http || record.is_some() {
+            Err(anyhow!("--http and --record can't be used for a server inside the gateway"))
+        } else {
+            Ok(())
+        }
+    };
+
+    Ok(match command.server {
+        ServerType::Shell { working_dir, timeout, shell, allow, deny, no_stderr, verbose, http, record, .. } => {
+            transport_only(http, &record)?;
+            Box::new(ShellServer::new(ShellServerConfig::new(
+                working_dir,
+                timeout,
+                shell,
+                allow,
+                deny,
+                no_stderr,
+                verbose,
+            )))
+        }
+        ServerType::Filesystem {
+            allowed_directories,
+            scratch_dir,
+            allow_delete,
+            allow_chmod,
+            no_gitignore,
+            follow_symlinks,
+            exclude,
+            verbose,
+            http,
+            record,
+            ..
+        } => {
+            transport_only(http, &record)?;
+            let dirs = if allowed_directories.is_empty() {
+                vec![std::env::current_dir()?]
+            } else {
+                allowed_directories
+            };
+            let config = FilesystemServerConfig::new(dirs, verbose)?
+                .with_scratch_root(scratch_dir)?
+                .with_allow_delete(allow_delete)
+                .with_allow_chmod(allow_chmod)
+                .with_respect_gitignore(!no_gitignore)
+                .with_follow_symlinks(follow_symlinks)
+                .with_exclude(exclude)?;
+            Box::new(FilesystemServer::new(config))
+        }
+        ServerType::Sql { connection, readonly, timeout, verbose, http, record, .. } => {
+            transport_only(http, &record)?;
+            let access_mode = if readonly { AccessMode::ReadOnly } else { AccessMode::FullAccess };
+            let config = SqlServerConfig::new(connection, access_mode, timeout, verbose)?;
+            let runtime = tokio::runtime::Runtime::new()?;
+            let pool = runtime
+                .block_on(connect_database(&config.connection_string, config.db_type, config.timeout))
+                .context("Failed to connect to database")?;
+            Box::new(SqlServer::new(config, pool, runtime))
+        }
+    })
+}
+
+/// Name for a server given without `NAME=`
+fn default_name(upstream: &Upstream) -> Result<String> {
+    Ok(match upstream {
+        Upstream::Builtin(args) => args.first().cloned().unwrap_or_default(),
+        Upstream::Remote(url) => {
+            let url = reqwest::Url::parse(url).with_context(|| format!("Invalid server URL: {}", url))?;
+            url.host_str()
+                .unwrap_or("remote")
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+                .collect()
+        }
+        Upstream::Package { package, .. } => crate::tool_prefix(package),
+    })
+}
+
+/// Reads the servers the gateway should be serving (from the config file, on reload)
+pub type LoadSpecs = Box<dyn Fn() -> Result<Vec<ServerSpec>> + Send>;
+
+/// A running server, with credentials its package needs while it runs
+pub struct Launched {
+    pub server: Box<dyn McpServer + Send + Sync>,
+    _auth: Option<RunnerAuth>,
+}
+
+/// A running server, with the spec it was started from and credentials its package (or
+/// each replica) needs
+struct Mounted {
+    spec: ServerSpec,
+    route: Arc<Route>,
+    _auth: Vec<RunnerAuth>,
+}
+
+/// The gateway's merged server, plus what it needs to start more servers on reload
+pub struct Gateway {
+    pub router: Arc<RouterServer>,
+    mounted: Vec<Mounted>,
+    /// Routers serving one server each (`/mcp/NAME` with `--paths`), by server name
+    endpoints: HashMap<String, Arc<RouterServer>>,
+    opts: crate::RunOptions,
+    timeout: Duration,
+}
+
+fn check_unique(specs: &[ServerSpec]) -> Result<()> {
+    let mut names = HashSet::new();
+    if let Some(spec) = specs.iter().find(|spec| !names.insert(spec.name.as_str())) {
+        return Err(anyhow!("Server name '{}' is used more than once; name them with NAME=SERVER", spec.name));
+    }
+    Ok(())
+}
+
+/// Start every server and mount them in one router, namespacing tools by server name
+pub fn start(specs: &[ServerSpec], opts: &crate::RunOptions, timeout: Duration, verbose: bool) -> Result<Gateway> {
+    check_unique(specs)?;
+    let mut mounted = vec![];
+    for spec in specs {
+        mounted.push(spec.start(opts, timeout).with_context(|| format!("{} failed to start", spec.name))?);
+    }
+    let routes = mounted.iter().map(|server| server.route.clone()).collect();
+    let router = Arc::new(RouterServer::new(routes, true, verbose)?.with_package_names(crate::cached_package_names));
+    Ok(Gateway {
+        router,
+        mounted,
+        endpoints: HashMap::new(),
+        opts: opts.clone(),
+        timeout,
+    })
+}
+
+impl Gateway {
+    /// A router per mounted server, with its tools unprefixed, for serving each server on
+    /// its own as well. Reloads keep them up to date.
+    pub fn endpoints(&mut self) -> Result<Vec<(String, Arc<RouterServer>)>> {
+        use crate::servers::common::McpServer;
+        let mut endpoints = vec![];
+        for server in &self.mounted {
+            let router = RouterServer::new(vec![server.route.clone()], false, self.router.verbose())?;
+            let router = Arc::new(router.with_package_names(crate::cached_package_names));
+            self.endpoints.insert(server.spec.name.clone(), router.clone());
+            endpoints.push((server.spec.name.clone(), router));
+        }
+        Ok(endpoints)
+    }
+
+    /// Bring the mounted servers in line with `specs`: start new ones, restart changed
+    /// ones and stop removed ones, leaving unchanged servers (and their sessions) alone.
+    /// A server that fails to start is skipped, or kept at its old settings if it was
+    /// changed. Clients are told the tool list changed; returns whether anything did.
+    pub fn reload(&mut self, specs: &[ServerSpec]) -> Result<bool> {
+        check_unique(specs)?;
+        let mut previous: HashMap<String, Mounted> =
+            self.mounted.drain(..).map(|server| (server.spec.name.clone(), server)).collect();
+        let mut changed = false;
+        for spec in specs {
+            match previous.remove(&spec.name) {
+                Some(server) if server.spec == *spec => self.mounted.push(server),
+                old => match spec.start(&self.opts, self.timeout) {
+                    Ok(server) => {
+                        self.mounted.push(server);
+                        changed = true;
+                    }
+                    Err(e) => {
+                        eprintln!("{}", format!("[mcpz] {} failed to start: {:#}", spec.name, e).red());
+                        self.mounted.extend(old);
+                    }
+                },
+            }
+        }
+        for name in previous.keys() {
+            eprintln!("{}", format!("[mcpz] Stopping {}", name).cyan());
+            changed = true;
+        }
+        if changed {
+            self.router.replace(self.mounted.iter().map(|server| server.route.clone()).collect())?;
+            self.update_endpoints()?;
+        }
+        Ok(changed)
+    }
+
+    /// Point each server's own endpoint at its current route; removed servers' endpoints
+    /// are left without tools. Endpoints can't be added while serving, so new servers are
+    /// only on the merged one.
+    fn update_endpoints(&self) -> Result<()> {
+        if self.endpoints.is_empty() {
+            return Ok(());
+        }
+        for (name, router) in &self.endpoints {
+            let route = self.mounted.iter().find(|server| server.spec.name == *name).map(|server| server.route.clone());
+            if router.routes().first().map(Arc::as_ptr) != route.as_ref().map(Arc::as_ptr) {
+                router.replace(route.into_iter().collect())?;
+            }
+        }
+        for server in self.mounted.iter().filter(|server| !self.endpoints.contains_key(&server.spec.name)) {
+            eprintln!(
+                "{}",
+                format!("[mcpz] {} is served at /mcp only; restart the gateway to give it its own path", server.spec.name)
+                    .yellow()
+            );
+        }
+        Ok(())
+    }
+
+    /// Reload whenever `path` is modified, with the servers `load` reads from it. Errors
+    /// (a bad edit, say) are reported and the running servers kept.
+    pub fn watch(mut self, path: PathBuf, load: LoadSpecs) {
+        std::thread::spawn(move || {
+            let modified = |path: &Path| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+            let mut last = modified(&path);
+            loop {
+                std::thread::sleep(WATCH_INTERVAL);
+                let current = modified(&path);
+                if current == last {
+                    continue;
+                }
+                last = current;
+                match load().and_then(|specs| self.reload(&specs)) {
+                    Ok(true) => eprintln!(
+                        "{}",
+                        format!("[mcpz] Reloaded {}: {} servers", path.display(), self.mounted.len()).green()
+                    ),
+                    Ok(false) => {}
+                    Err(e) => eprintln!("{}", format!("[mcpz] Not reloading {}: {:#}", path.display(), e).red()),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(spec: &str) -> ServerSpec {
+        ServerSpec::parse(spec).unwrap()
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_builtins() {
+        assert_eq!(parse("builtin:shell").upstream, Upstream::Builtin(args(&["shell"])));
+        assert_eq!(parse("builtin:shell").name, "shell");
+        assert_eq!(
+            parse("docs=builtin:filesystem:/srv/docs,/tmp"),
+            ServerSpec {
+                name: "docs".to_string(),
+                upstream: Upstream::Builtin(args(&["filesystem", "--dir", "/srv/docs", "--dir", "/tmp"])),
+                tools: ToolRules::default(),
+                middleware: vec![],
+                rate_limit: None,
+                replicas: 1,
+                circuit_breaker: BreakerSettings::default(),
+                cache: BTreeMap::new(),
+                max_result_size: None,
+                affinity: None,
+            }
+        );
+        assert_eq!(parse("builtin:filesystem").upstream, Upstream::Builtin(args(&["filesystem"])));
+        assert_eq!(
+            parse("builtin:sql:postgres://u:p@db/app?sslmode=require").upstream,
+            Upstream::Builtin(args(&["sql", "--connection", "postgres://u:p@db/app?sslmode=require", "--readonly"]))
+        );
+        assert!(ServerSpec::parse("builtin:sql").is_err());
+        assert!(ServerSpec::parse("builtin:shell:zsh").is_err());
+        assert!(ServerSpec::parse("builtin:ftp").is_err());
+    }
+
+    #[test]
+    fn test_start_builtin_uses_server_arguments() {
+        assert!(start_builtin(&args(&["shell", "--allow", "ls*", "-t", "5"])).is_ok());
+        let err = start_builtin(&args(&["shell", "--http"])).err().unwrap().to_string();
+        assert!(err.contains("--http and --record"));
+        let err = start_builtin(&args(&["shell", "--bogus"])).err().unwrap().to_string();
+        assert!(err.contains("Invalid built-in server arguments 'shell --bogus'"));
+        assert!(start_builtin(&args(&["sql", "--connection", "sqlite::memory:"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_remote_and_packages() {
+        assert_eq!(
+            parse("https://mcp.example.com/mcp?team=a"),
+            ServerSpec {
+                name: "mcp_example_com".to_string(),
+                upstream: Upstream::Remote("https://mcp.example.com/mcp?team=a".to_string()),
+                tools: ToolRules::default(),
+                middleware: vec![],
+                rate_limit: None,
+                replicas: 1,
+                circuit_breaker: BreakerSettings::default(),
+                cache: BTreeMap::new(),
+                max_result_size: None,
+                affinity: None,
+            }
+        );
+        assert_eq!(parse("api=http://localhost:3000/mcp").name, "api");
+        assert_eq!(
+            parse("@modelcontextprotocol/server-memory"),
+            ServerSpec {
+                name: "server-memory".to_string(),
+                upstream: Upstream::package("@modelcontextprotocol/server-memory"),
+                tools: ToolRules::default(),
+                middleware: vec![],
+                rate_limit: None,
+                replicas: 1,
+                circuit_breaker: BreakerSettings::default(),
+                cache: BTreeMap::new(),
+                max_result_size: None,
+                affinity: None,
+            }
+        );
+        assert!(ServerSpec::parse("my server=mcp-server-time").is_err());
+        assert!(ServerSpec::parse("time=").is_err());
+    }
+
+    #[test]
+    fn test_start_rejects_duplicate_names() {
+        let specs = vec![parse("builtin:shell"), parse("shell=builtin:filesystem")];
+        let err = start(&specs, &crate::RunOptions::default(), Duration::from_secs(5), false)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains("'shell' is used more than once"));
+    }
+
+    #[test]
+    fn test_start_builtins() {
+        let dir = tempfile::tempdir().unwrap();
+        let specs = vec![
+            parse("builtin:shell"),
+            parse(&format!("builtin:filesystem:{}", dir.path().display())),
+        ];
+        let gateway = start(&specs, &crate::RunOptions::default(), Duration::from_secs(5), false).unwrap();
+        let names: Vec<String> = gateway.router.tools().into_iter().map(|tool| tool.name).collect();
+        assert!(names.iter().any(|name| name.starts_with("shell.")));
+        assert!(names.iter().any(|name| name.starts_with("filesystem.")));
+    }
+
+    #[test]
+    fn test_reload_adds_restarts_and_removes_servers() {
+        let dir = tempfile::tempdir().unwrap();
+        let fs = parse(&format!("builtin:filesystem:{}", dir.path().display()));
+        let mut gateway = start(&[parse("builtin:shell")], &crate::RunOptions::default(), Duration::from_secs(5), false).unwrap();
+        let mut notifications = gateway.router.notifier().unwrap().subscribe();
+        let shell = gateway.router.routes()[0].clone();
+
+        // Unchanged servers keep running
+        assert!(!gateway.reload(&[parse("builtin:shell")]).unwrap());
+        assert!(notifications.try_recv().is_err());
+
+        assert!(gateway.reload(&[parse("builtin:shell"), fs.clone()]).unwrap());
+        assert!(Arc::ptr_eq(&gateway.router.routes()[0], &shell));
+        assert_eq!(gateway.router.routes()[1].prefix, "filesystem");
+        assert_eq!(notifications.try_recv().unwrap().message["method"], "notifications/tools/list_changed");
+
+        // A changed server that fails to start keeps its old settings; removed servers go
+        let broken = ServerSpec {
+            name: "filesystem".to_string(),
+            upstream: Upstream::Builtin(args(&["filesystem", "--bogus"])),
+            tools: ToolRules::default(),
+            middleware: vec![],
+            rate_limit: None,
+            replicas: 1,
+            circuit_breaker: BreakerSettings::default(),
+            cache: BTreeMap::new(),
+            max_result_size: None,
+            affinity: None,
+        };
+        assert!(gateway.reload(&[broken]).unwrap());
+        let routes = gateway.router.routes();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0].prefix, "filesystem");
+        assert!(gateway.mounted[0].spec == fs);
+        assert!(gateway.router.tools().iter().all(|tool| tool.name.starts_with("filesystem.")));
+
+        assert!(gateway.reload(&[parse("builtin:shell"), parse("shell=builtin:shell")]).is_err());
+    }
+
+    #[test]
+    fn test_endpoints_follow_reloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let fs = parse(&format!("builtin:filesystem:{}", dir.path().display()));
+        let opts = crate::RunOptions::default();
+        let mut gateway = start(&[parse("builtin:shell"), fs.clone()], &opts, Duration::from_secs(5), false).unwrap();
+        let endpoints = gateway.endpoints().unwrap();
+        let names: Vec<&str> = endpoints.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["shell", "filesystem"]);
+        let (_, shell) = &endpoints[0];
+        assert_eq!(shell.tools().into_iter().map(|tool| tool.name).collect::<Vec<_>>(), ["execute_command"]);
+
+        // A restarted server's endpoint serves the new instance; a removed one's has no tools
+        let sh = ServerSpec {
+            tools: ToolRules {
+                deny: vec!["*".to_string()],
+                ..Default::default()
+            },
+            ..parse("builtin:shell")
+        };
+        assert!(gateway.reload(&[sh]).unwrap());
+        assert!(Arc::ptr_eq(&shell.routes()[0], &gateway.router.routes()[0]));
+        assert!(shell.tools().is_empty());
+        assert!(endpoints[1].1.routes().is_empty());
+    }
+}
@@ -0,0 +1,104 @@
+use anyhow::{anyhow, Context, Result};
+use axum::http::{header, HeaderMap};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Bearer tokens accepted on `/mcp` (`--auth-token`, `--auth-tokens-file`)
+#[derive(Clone)]
+pub struct AuthTokens {
+    tokens: Vec<String>,
+}
+
+impl AuthTokens {
+    /// Combine `--auth-token` with the tokens in `file` (one per line; blank lines and
+    /// `#` comments are skipped). None if neither is given.
+    pub fn load(token: Option<String>, file: Option<&Path>) -> Result<Option<Self>> {
+        let mut tokens: Vec<String> = token.into_iter().collect();
+        if let Some(file) = file {
+            let content = fs::read_to_string(file).with_context(|| format!("Failed to read {}", file.display()))?;
+            let before = tokens.len();
+            tokens.extend(
+                content
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(str::to_string),
+            );
+            if tokens.len() == before {
+                return Err(anyhow!("No tokens in {}", file.display()));
+            }
+        }
+        if tokens.iter().any(|token| token.is_empty() || token.contains(char::is_whitespace)) {
+            return Err(anyhow!("Auth tokens can't be empty or contain whitespace"));
+        }
+        Ok((!tokens.is_empty()).then_some(Self { tokens }))
+    }
+
+    /// How many tokens are accepted
+    pub fn count(&self) -> usize {
+        self.tokens.len()
+    }
+
+    /// Whether the request carries `Authorization: Bearer <one of the tokens>`
+    pub fn authorizes(&self, headers: &HeaderMap) -> bool {
+        let Some(given) = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+        else {
+            return false;
+        };
+        // Check every token so the time taken doesn't tell which one nearly matched
+        self.tokens
+            .iter()
+            .fold(false, |found, token| constant_time_eq(token.as_bytes(), given.trim().as_bytes()) | found)
+    }
+}
+
+impl fmt::Debug for AuthTokens {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AuthTokens({} hidden)", self.tokens.len())
+    }
+}
+
+/// Compare without stopping at the first differing byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn bearer(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn test_authorizes_bearer_tokens() {
+        let auth = AuthTokens::load(Some("s3cret".to_string()), None).unwrap().unwrap();
+        assert!(auth.authorizes(&bearer("Bearer s3cret")));
+        assert!(!auth.authorizes(&bearer("Bearer s3cre")));
+        assert!(!auth.authorizes(&bearer("Basic s3cret")));
+        assert!(!auth.authorizes(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn test_load_tokens_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tokens");
+        fs::write(&path, "# laptop\nabc\n\n  def  \n").unwrap();
+        let auth = AuthTokens::load(Some("xyz".to_string()), Some(&path)).unwrap().unwrap();
+        assert_eq!(auth.count(), 3);
+        assert!(auth.authorizes(&bearer("Bearer def")));
+
+        assert!(AuthTokens::load(None, None).unwrap().is_none());
+        fs::write(&path, "# nobody\n").unwrap();
+        assert!(AuthTokens::load(None, Some(&path)).is_err());
+        assert!(AuthTokens::load(Some("two words".to_string()), None).is_err());
+    }
+}
@@ -0,0 +1,387 @@
+use anyhow::{anyhow, Context, Result};
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::time::Duration;
+
+use crate::client::{reply, Answerer, McpClient, MessageHandler};
+
+use super::handlers::MCP_SESSION_ID_HEADER;
+
+/// A JSON-RPC client for a remote MCP server speaking the Streamable HTTP transport.
+/// Responses may come back as plain JSON or as an SSE stream; the session id handed
+/// out on `initialize` is sent with every later request.
+pub struct HttpClient {
+    url: String,
+    client: reqwest::Client,
+    session_id: Option<String>,
+    /// Sent as `Authorization: Bearer <token>`
+    token: Option<String>,
+    /// Receives what the server sends besides responses (None drops them)
+    on_message: Option<MessageHandler>,
+    /// Drives requests for the blocking `McpClient` interface (None only while dropping)
+    runtime: Option<tokio::runtime::Runtime>,
+    next_id: u64,
+}
+
+impl HttpClient {
+    pub fn new(url: &str) -> Result<Self> {
+        let parsed = reqwest::Url::parse(url).with_context(|| format!("Invalid server URL: {}", url))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(anyhow!("Server URL must start with http:// or https://: {}", url));
+        }
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("Failed to start async runtime")?;
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("mcpz/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .context("Failed to build HTTP client")?;
+
+        Ok(Self {
+            url: url.to_string(),
+            client,
+            session_id: None,
+            token: None,
+            on_message: None,
+            runtime: Some(runtime),
+            next_id: 1,
+        })
+    }
+
+    /// Authenticate every request with a bearer token
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        self
+    }
+
+    /// Pass notifications and server requests to `handler`: those interleaved with a
+    /// response's event stream, and those on the `subscribe` stream
+    pub fn on_message(mut self, handler: MessageHandler) -> Self {
+        self.on_message = Some(handler);
+        self
+    }
+
+    /// Open the session's GET event stream on a background thread, passing every message
+    /// to the `on_message` handler until the stream ends. Servers that don't offer one
+    /// (HTTP 405) are fine; messages then only arrive alongside responses.
+    pub fn subscribe(&self) -> Result<()> {
+        let (Some(handler), Some(session_id)) = (self.on_message.clone(), self.session_id.clone()) else {
+            return Ok(());
+        };
+        let mut request = self
+            .client
+            .get(&self.url)
+            .header(ACCEPT, "text/event-stream")
+            .header(MCP_SESSION_ID_HEADER, session_id);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let url = self.url.clone();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("Failed to start async runtime")?;
+        std::thread::spawn(move || {
+            runtime.block_on(async move {
+                let mut response = match request.send().await {
+                    Ok(response) if response.status().is_success() => response,
+                    Ok(response) if response.status() == StatusCode::METHOD_NOT_ALLOWED => return,
+                    Ok(response) => return eprintln!("[mcpz] {} refused the event stream: HTTP {}", url, response.status()),
+                    Err(e) => return eprintln!("[mcpz] Failed to open the event stream of {}: {}", url, e),
+                };
+                let mut buffer = String::new();
+                while let Ok(Some(chunk)) = response.chunk().await {
+                    buffer.push_str(&String::from_utf8_lossy(&chunk));
+                    while let Some(data) = take_sse_event(&mut buffer) {
+                        if let Ok(message) = serde_json::from_str(&data) {
+                            handler(message);
+                        }
+                    }
+                }
+            })
+        });
+        Ok(())
+    }
+
+    /// End the session on the server (DELETE), if there is one
+    pub fn close(&mut self) {
+        let (Some(runtime), Some(session_id)) = (&self.runtime, self.session_id.take()) else {
+            return;
+        };
+        let mut request = self.client.delete(&self.url).header(MCP_SESSION_ID_HEADER, session_id);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        // Best effort: the server expires abandoned sessions anyway
+        let _ = runtime.block_on(async { tokio::time::timeout(Duration::from_secs(5), request.send()).await });
+    }
+
+    /// Send `message`; if it's a request, read the response to it. Requests the server makes
+    /// on the response's event stream go to `answer` if given, and the answers are posted
+    /// back; without one they go to the `on_message` handler like notifications.
+    async fn post(
+        &self,
+        message: &Value,
+        method: &str,
+        mut answer: Option<Answerer<'_>>,
+    ) -> Result<(Option<String>, Option<Value>)> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(ACCEPT, "application/json, text/event-stream")
+            .body(message.to_string());
+        if let Some(session_id) = &self.session_id {
+            request = request.header(MCP_SESSION_ID_HEADER, session_id);
+        }
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        let mut response = request
+            .send()
+            .await
+            .with_context(|| format!("Failed to send {} to {}", method, self.url))?;
+        let status = response.status();
+        if status == StatusCode::UNAUTHORIZED {
+            return Err(match self.token {
+                Some(_) => anyhow!("{} rejected the bearer token", self.url),
+                None => anyhow!("{} requires a bearer token", self.url),
+            });
+        }
+        if status == StatusCode::NOT_FOUND && self.session_id.is_some() {
+            return Err(anyhow!("{} no longer knows this session (it may have restarted)", self.url));
+        }
+        if !status.is_success() {
+            return Err(anyhow!("{} answered {} with HTTP {}", self.url, method, status));
+        }
+        let session_id = response
+            .headers()
+            .get(MCP_SESSION_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+
+        let Some(id) = message.get("id").filter(|_| message.get("method").is_some()) else {
+            return Ok((session_id, None));
+        };
+        let is_sse = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("text/event-stream"));
+
+        if !is_sse {
+            let body = response.text().await.context("Failed to read response")?;
+            let message = serde_json::from_str(&body)
+                .with_context(|| format!("{} answered {} with invalid JSON", self.url, method))?;
+            return Ok((session_id, Some(message)));
+        }
+
+        // Read events until the response to our request arrives
+        let mut buffer = String::new();
+        while let Some(chunk) = response.chunk().await.context("Failed to read event stream")? {
+            buffer.push_str(&String::from_utf8_lossy(&chunk));
+            while let Some(data) = take_sse_event(&mut buffer) {
+                let Ok(message) = serde_json::from_str::<Value>(&data) else {
+                    continue;
+                };
+                if message.get("id") == Some(id) && message.get("method").is_none() {
+                    return Ok((session_id, Some(message)));
+                }
+                let request = message.get("method").and_then(Value::as_str).zip(message.get("id"));
+                if let (Some(answer), Some((request_method, request_id))) = (answer.as_mut(), request) {
+                    let params = message.get("params").cloned().unwrap_or(Value::Null);
+                    let reply = reply(request_id, answer(request_method, &params));
+                    Box::pin(self.post(&reply, "response", None)).await?;
+                    continue;
+                }
+                if let Some(handler) = &self.on_message {
+                    handler(message);
+                }
+            }
+        }
+        Err(anyhow!("{} closed the event stream before responding to {}", self.url, method))
+    }
+
+    /// Send a message over `post` within `timeout`, keeping the session id it hands out
+    fn send(&mut self, message: &Value, timeout: Duration, answer: Option<Answerer>) -> Result<Option<Value>> {
+        let method = message.get("method").and_then(|m| m.as_str()).unwrap_or("message").to_string();
+        let runtime = self.runtime.as_ref().ok_or_else(|| anyhow!("HTTP client is shut down"))?;
+        let (session_id, response) = runtime
+            .block_on(async { tokio::time::timeout(timeout, self.post(message, &method, answer)).await })
+            .map_err(|_| anyhow!("No response to {} within {}s", method, timeout.as_secs()))??;
+        if session_id.is_some() {
+            self.session_id = session_id;
+        }
+        Ok(response)
+    }
+}
+
+/// Remove the first complete event from an SSE buffer, returning its `data` lines
+/// joined by newlines (events without data are skipped)
+fn take_sse_event(buffer: &mut String) -> Option<String> {
+    loop {
+        if buffer.contains('\r') {
+            *buffer = buffer.replace("\r\n", "\n");
+        }
+        let end = buffer.find("\n\n")?;
+        let event: String = buffer.drain(..end + 2).collect();
+        let data: Vec<&str> = event
+            .lines()
+            .filter_map(|line| line.strip_prefix("data:"))
+            .map(|data| data.strip_prefix(' ').unwrap_or(data))
+            .collect();
+        if !data.is_empty() {
+            return Some(data.join("\n"));
+        }
+    }
+}
+
+impl McpClient for HttpClient {
+    fn exchange(&mut self, message: &Value, timeout: Duration) -> Result<Option<Value>> {
+        self.send(message, timeout, None)
+    }
+
+    fn exchange_answering(&mut self, message: &Value, timeout: Duration, answer: Answerer) -> Result<Option<Value>> {
+        self.send(message, timeout, Some(answer))
+    }
+
+    fn next_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    /// Also opens the session's GET event stream, for notifications sent between requests
+    fn listen(&mut self, handler: MessageHandler) -> Result<()> {
+        self.on_message = Some(handler);
+        self.subscribe()
+    }
+}
+
+impl Drop for HttpClient {
+    fn drop(&mut self) {
+        // Dropping a runtime blocks, which panics inside the HTTP server's runtime
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::handlers::AppState;
+    use crate::http::session::SessionManager;
+    use crate::servers::common::{McpServer, McpTool};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    struct Echo;
+
+    impl McpServer for Echo {
+        fn name(&self) -> &str {
+            "echo"
+        }
+
+        fn version(&self) -> &str {
+            "1.0.0"
+        }
+
+        fn tools(&self) -> Vec<McpTool> {
+            vec![McpTool {
+                name: "echo".to_string(),
+                description: String::new(),
+                input_schema: json!({"type": "object"}),
+                output_schema: None,
+            }]
+        }
+
+        fn call_tool(&self, _name: &str, arguments: &Value) -> Result<Value> {
+            Ok(arguments.clone())
+        }
+
+        fn verbose(&self) -> bool {
+            false
+        }
+    }
+
+    /// Serve `Echo` on a free local port from a background thread, returning its URL
+    fn serve_echo() -> String {
+        serve_echo_with_auth(None)
+    }
+
+    fn serve_echo_with_auth(auth: Option<crate::http::AuthTokens>) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+        listener.set_nonblocking(true).unwrap();
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            runtime.block_on(async {
+                let state = Arc::new(AppState::new(
+                    Echo,
+                    Arc::new(SessionManager::new(Duration::from_secs(60))),
+                    vec![],
+                    false,
+                )
+                .with_auth(auth));
+                let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+                axum::serve(listener, crate::http::server::routes(state)).await.unwrap();
+            });
+        });
+        url
+    }
+
+    #[test]
+    fn test_take_sse_event() {
+        let mut buffer = "event: message\r\ndata: {\"id\":1}\r\n\r\n: keepalive\n\ndata: a\ndata: b\n\ndata: partial".to_string();
+        assert_eq!(take_sse_event(&mut buffer), Some("{\"id\":1}".to_string()));
+        assert_eq!(take_sse_event(&mut buffer), Some("a\nb".to_string()));
+        assert_eq!(take_sse_event(&mut buffer), None);
+        assert_eq!(buffer, "data: partial");
+    }
+
+    #[test]
+    fn test_http_client_talks_to_mcpz_server() {
+        let mut client = HttpClient::new(&serve_echo()).unwrap();
+        let init = client.initialize(Duration::from_secs(5)).unwrap();
+        assert_eq!(init["serverInfo"]["name"], "echo");
+        assert!(client.session_id.is_some());
+
+        let result = client
+            .request(
+                "tools/call",
+                json!({"name": "echo", "arguments": {"text": "hi"}}),
+                Duration::from_secs(5),
+            )
+            .unwrap();
+        assert_eq!(result, json!({"text": "hi"}));
+        let error = client.call("bogus", json!({}), Duration::from_secs(5)).unwrap().unwrap_err();
+        assert_eq!(error["code"], -32601);
+    }
+
+    #[test]
+    fn test_http_client_bearer_token() {
+        let auth = crate::http::AuthTokens::load(Some("s3cret".to_string()), None).unwrap();
+        let url = serve_echo_with_auth(auth);
+        let timeout = Duration::from_secs(5);
+
+        let error = HttpClient::new(&url).unwrap().initialize(timeout).unwrap_err();
+        assert!(error.to_string().contains("requires a bearer token"));
+        let mut client = HttpClient::new(&url).unwrap().with_token(Some("wrong".to_string()));
+        assert!(client.initialize(timeout).unwrap_err().to_string().contains("rejected the bearer token"));
+
+        let mut client = HttpClient::new(&url).unwrap().with_token(Some("s3cret".to_string()));
+        assert_eq!(client.initialize(timeout).unwrap()["serverInfo"]["name"], "echo");
+        client.close();
+        assert!(client.session_id.is_none());
+    }
+
+    #[test]
+    fn test_http_client_rejects_bad_urls() {
+        assert!(HttpClient::new("not a url").is_err());
+        assert!(HttpClient::new("ftp://example.com/mcp").is_err());
+    }
+}
@@ -3,24 +3,35 @@ use axum::{
     http::{header, HeaderMap, StatusCode},
     response::{IntoResponse, Response, Sse},
 };
-use futures::stream;
+use futures::{stream, StreamExt};
 use serde::Serialize;
 use std::convert::Infallible;
 use std::sync::Arc;
 use std::time::Duration;
+use tokio::task::JoinError;
 
-use crate::servers::common::{JsonRpcRequest, McpServer};
+use crate::servers::common::{client_response, JsonRpcRequest, McpServer, RequestContext, PROTOCOL_VERSIONS};
+use crate::servers::ratelimit::RateLimiter;
+use crate::servers::strict::{check_message, refusal};
 
+use super::auth::AuthTokens;
 use super::session::{SessionError, SessionManager};
 
 /// Custom header name for MCP session ID
 pub const MCP_SESSION_ID_HEADER: &str = "mcp-session-id";
 
+/// Header clients on protocol version 2025-06-18 and later name the session's version in
+pub const MCP_PROTOCOL_VERSION_HEADER: &str = "mcp-protocol-version";
+
 /// Application state shared across handlers
 pub struct AppState<S: McpServer + Send + Sync + 'static> {
     pub mcp_server: Arc<S>,
     pub sessions: Arc<SessionManager>,
     pub allowed_origins: Vec<String>,
+    /// Bearer tokens every request must carry (None: no authentication)
+    pub auth: Option<AuthTokens>,
+    /// Limit on tool calls per tool and session (`--rate-limit`)
+    pub rate_limiter: Option<RateLimiter>,
     pub verbose: bool,
 }
 
@@ -35,10 +46,46 @@ impl<S: McpServer + Send + Sync + 'static> AppState<S> {
             mcp_server: Arc::new(mcp_server),
             sessions,
             allowed_origins,
+            auth: None,
+            rate_limiter: None,
             verbose,
         }
     }
 
+    /// Require `Authorization: Bearer` with one of `auth`'s tokens
+    pub fn with_auth(mut self, auth: Option<AuthTokens>) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// Allow at most `limit` calls per minute of each tool in each session
+    pub fn with_rate_limit(mut self, limit: Option<u32>) -> Self {
+        self.rate_limiter = limit.map(RateLimiter::new);
+        self
+    }
+
+    /// Run `f` with the server on the blocking pool. Servers block (child processes, their
+    /// own runtimes), and would otherwise hold up the workers serving other requests.
+    pub async fn blocking<T, F>(&self, f: F) -> Result<T, JoinError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&S) -> T + Send + 'static,
+    {
+        let server = self.mcp_server.clone();
+        tokio::task::spawn_blocking(move || f(&server)).await
+    }
+
+    /// Release what the server and the rate limiter keep for an ended session
+    pub async fn end_session(&self, session_id: &str) {
+        let id = session_id.to_string();
+        if let Err(e) = self.blocking(move |server| server.on_session_end(Some(&id))).await {
+            self.log(&format!("Ending session {} failed: {}", session_id, e));
+        }
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.end_session(Some(session_id));
+        }
+    }
+
     fn log(&self, message: &str) {
         if self.verbose {
             eprintln!("[mcpz] {}", message);
@@ -82,6 +129,17 @@ fn validate_origin(headers: &HeaderMap, allowed_origins: &[String]) -> Result<()
     Err(StatusCode::FORBIDDEN)
 }
 
+/// The 401 response for a request without a valid bearer token, if the server requires one
+fn unauthorized<S: McpServer + Send + Sync + 'static>(state: &AppState<S>, headers: &HeaderMap) -> Option<Response> {
+    match &state.auth {
+        Some(auth) if !auth.authorizes(headers) => {
+            state.log("Missing or invalid bearer token");
+            Some((StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Bearer")]).into_response())
+        }
+        _ => None,
+    }
+}
+
 /// Extract session ID from headers
 fn get_session_id(headers: &HeaderMap) -> Option<String> {
     headers
@@ -96,11 +154,40 @@ pub async fn handle_post<S: McpServer + Send + Sync + 'static>(
     headers: HeaderMap,
     body: String,
 ) -> Result<Response, StatusCode> {
-    // 1. Validate Origin header
+    // 1. Validate Origin header and bearer token
     validate_origin(&headers, &state.allowed_origins)?;
+    if let Some(response) = unauthorized(&state, &headers) {
+        return Ok(response);
+    }
 
     state.log(&format!("POST /mcp: {}", body));
 
+    // Malformed messages are refused outright in strict mode, requests with a JSON-RPC error
+    let message: Option<serde_json::Value> = serde_json::from_str(&body).ok();
+    if let Some(message) = message.as_ref().filter(|_| state.mcp_server.strict()) {
+        if let Err(reason) = check_message(message) {
+            state.log(&format!("Refusing invalid message: {}", reason));
+            return Ok(match refusal(message, reason).and_then(|answer| serde_json::to_string(&answer).ok()) {
+                Some(answer) => {
+                    (StatusCode::BAD_REQUEST, [(header::CONTENT_TYPE, "application/json")], answer).into_response()
+                }
+                None => StatusCode::BAD_REQUEST.into_response(),
+            });
+        }
+    }
+
+    // A client's answer to a request the server sent on the session's event stream
+    if let Some((id, answer)) = message.as_ref().and_then(client_response) {
+        let session_id = get_session_id(&headers).ok_or(StatusCode::BAD_REQUEST)?;
+        state.sessions.validate_session(&session_id).await.map_err(|_| StatusCode::NOT_FOUND)?;
+        let ctx = RequestContext::with_session(&session_id);
+        state
+            .blocking(move |server| server.handle_client_response(&id, answer, &ctx))
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        return Ok((StatusCode::ACCEPTED, [(MCP_SESSION_ID_HEADER, session_id)]).into_response());
+    }
+
     // 2. Parse JSON-RPC request
     let request: JsonRpcRequest = serde_json::from_str(&body).map_err(|e| {
         state.log(&format!("Parse error: {}", e));
@@ -136,9 +223,38 @@ pub async fn handle_post<S: McpServer + Send + Sync + 'static>(
             Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
         }
     };
+    if let Some(version) = headers.get(MCP_PROTOCOL_VERSION_HEADER) {
+        if !PROTOCOL_VERSIONS.iter().any(|supported| version.as_bytes() == supported.as_bytes()) {
+            state.log(&format!("Unsupported protocol version header: {:?}", version));
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
 
-    // 4. Dispatch to MCP server
-    let response = match state.mcp_server.handle_request(request) {
+    // 4. Dispatch to MCP server, unless the rate limit refuses the tool call
+    let limited = match (&state.rate_limiter, request.method.as_str()) {
+        (Some(limiter), "tools/call") => {
+            let tool = request.params.get("name").and_then(|v| v.as_str()).unwrap_or_default();
+            limiter.check(Some(&session_id), tool).err()
+        }
+        _ => None,
+    };
+    let response = if let Some(limited) = limited {
+        state.log(&limited.to_string());
+        Some(limited.to_response(request.id))
+    } else {
+        let ctx = RequestContext {
+            protocol_version: state.sessions.protocol_version(&session_id).await,
+            ..RequestContext::with_session(&session_id)
+        };
+        state
+            .blocking(move |server| server.handle_request_with_context(request, &ctx))
+            .await
+            .map_err(|e| {
+                state.log(&format!("Request handler failed: {}", e));
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+    };
+    let response = match response {
         Some(resp) => resp,
         None => {
             // Notification - no response needed
@@ -154,9 +270,10 @@ pub async fn handle_post<S: McpServer + Send + Sync + 'static>(
     // 5. Mark session as initialized after successful initialize
     if response.result.is_some() {
         if let Some(result) = &response.result {
-            if result.get("protocolVersion").is_some() {
+            if let Some(version) = result.get("protocolVersion") {
                 // This is an initialize response
-                state.sessions.mark_initialized(&session_id).await.ok();
+                let version = version.as_str().map(str::to_string);
+                state.sessions.mark_initialized(&session_id, version).await.ok();
                 state.log(&format!("Session {} initialized", session_id));
             }
         }
@@ -186,8 +303,11 @@ pub async fn handle_get<S: McpServer + Send + Sync + 'static>(
     State(state): State<Arc<AppState<S>>>,
     headers: HeaderMap,
 ) -> Result<Response, StatusCode> {
-    // Validate Origin
+    // Validate Origin and bearer token
     validate_origin(&headers, &state.allowed_origins)?;
+    if let Some(response) = unauthorized(&state, &headers) {
+        return Ok(response);
+    }
 
     // Validate session
     let session_id = get_session_id(&headers).ok_or(StatusCode::BAD_REQUEST)?;
@@ -204,9 +324,25 @@ pub async fn handle_get<S: McpServer + Send + Sync + 'static>(
 
     state.log(&format!("GET /mcp: SSE stream opened for session {}", session_id));
 
-    // Return empty SSE stream (we don't have server-initiated messages yet)
-    // The stream stays open but doesn't send anything
-    let stream = stream::pending::<Result<axum::response::sse::Event, Infallible>>();
+    // Server-initiated notifications (e.g. tools/list_changed) and requests for this session,
+    // if the server sends any; otherwise the stream stays open but doesn't send anything
+    let stream = match state.mcp_server.notifier() {
+        Some(notifier) => stream::unfold((notifier.subscribe(), session_id), |(mut notifications, session_id)| async move {
+            loop {
+                match notifications.recv().await {
+                    Ok(outgoing) if outgoing.to.as_ref().is_some_and(|to| *to != session_id) => continue,
+                    Ok(outgoing) => {
+                        let event = axum::response::sse::Event::default().data(outgoing.message.to_string());
+                        return Some((Ok::<_, Infallible>(event), (notifications, session_id)));
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+        .boxed(),
+        None => stream::pending().boxed(),
+    };
 
     Ok(Sse::new(stream)
         .keep_alive(
@@ -221,32 +357,40 @@ pub async fn handle_get<S: McpServer + Send + Sync + 'static>(
 pub async fn handle_delete<S: McpServer + Send + Sync + 'static>(
     State(state): State<Arc<AppState<S>>>,
     headers: HeaderMap,
-) -> StatusCode {
-    // Validate Origin
+) -> Response {
+    // Validate Origin and bearer token
     if validate_origin(&headers, &state.allowed_origins).is_err() {
-        return StatusCode::FORBIDDEN;
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    if let Some(response) = unauthorized(&state, &headers) {
+        return response;
     }
 
     // Get session ID
     let session_id = match get_session_id(&headers) {
         Some(id) => id,
-        None => return StatusCode::BAD_REQUEST,
+        None => return StatusCode::BAD_REQUEST.into_response(),
     };
 
     // Delete session
     if state.sessions.delete_session(&session_id).await {
+        state.end_session(&session_id).await;
         state.log(&format!("DELETE /mcp: Session {} terminated", session_id));
-        StatusCode::OK
+        StatusCode::OK.into_response()
     } else {
         state.log(&format!("DELETE /mcp: Session {} not found", session_id));
-        StatusCode::NOT_FOUND
+        StatusCode::NOT_FOUND.into_response()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::servers::common::McpTool;
+    use anyhow::Result;
     use axum::http::HeaderValue;
+    use serde_json::{json, Value};
+    use std::time::Instant;
 
     #[test]
     fn test_validate_origin_no_header() {
@@ -325,4 +469,62 @@ mod tests {
             Some("test-session-123".to_string())
         );
     }
+
+    /// Takes its time over every tool call and session end
+    struct Slow;
+
+    impl McpServer for Slow {
+        fn name(&self) -> &str {
+            "slow"
+        }
+
+        fn version(&self) -> &str {
+            "1.0.0"
+        }
+
+        fn tools(&self) -> Vec<McpTool> {
+            vec![]
+        }
+
+        fn call_tool(&self, _name: &str, _arguments: &Value) -> Result<Value> {
+            std::thread::sleep(Duration::from_millis(300));
+            Ok(json!("done"))
+        }
+
+        fn on_session_end(&self, _session_id: Option<&str>) {
+            std::thread::sleep(Duration::from_millis(300));
+        }
+
+        fn verbose(&self) -> bool {
+            false
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_servers_leave_the_runtime_free() {
+        let sessions = Arc::new(SessionManager::new(Duration::from_secs(60)));
+        let state = Arc::new(AppState::new(Slow, sessions, vec![], false));
+        let post = |session: Option<&str>, id: u64, method: &str| {
+            let mut headers = HeaderMap::new();
+            if let Some(session) = session {
+                headers.insert(MCP_SESSION_ID_HEADER, session.parse().unwrap());
+            }
+            let body = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": {"name": "wait"}});
+            handle_post(State(state.clone()), headers, body.to_string())
+        };
+        let init = post(None, 1, "initialize").await.unwrap();
+        let session = init.headers()[MCP_SESSION_ID_HEADER].to_str().unwrap().to_string();
+
+        // The test runtime has a single thread: the calls and the session's end only overlap
+        // if none of them holds it
+        let started = Instant::now();
+        let (a, b, _) = tokio::join!(
+            post(Some(&session), 2, "tools/call"),
+            post(Some(&session), 3, "tools/call"),
+            state.end_session(&session)
+        );
+        assert_eq!(a.unwrap().status(), StatusCode::OK);
+        assert_eq!(b.unwrap().status(), StatusCode::OK);
+        assert!(started.elapsed() < Duration::from_millis(800), "took {:?}", started.elapsed());
+    }
 }
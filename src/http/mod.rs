@@ -1,6 +1,9 @@
+pub mod auth;
+pub mod client;
 pub mod handlers;
 pub mod server;
 pub mod session;
 pub mod tls;
 
-pub use server::{run_http_server, HttpServerConfig};
+pub use auth::AuthTokens;
+pub use server::{run_http_server, run_http_server_with_paths, HttpServerConfig};
@@ -10,6 +10,7 @@ use std::time::Duration;
 
 use crate::servers::common::McpServer;
 
+use super::auth::AuthTokens;
 use super::handlers::{handle_delete, handle_get, handle_post, AppState};
 use super::session::SessionManager;
 use super::tls::TlsConfig;
@@ -22,8 +23,14 @@ pub struct HttpServerConfig {
     pub cert_path: Option<PathBuf>,
     pub key_path: Option<PathBuf>,
     pub allowed_origins: Vec<String>,
+    /// Bearer tokens required on every request (None: no authentication)
+    pub auth: Option<AuthTokens>,
     pub session_ttl: Duration,
+    /// Calls allowed per minute of each tool in each session (unlimited if None)
+    pub rate_limit: Option<u32>,
     pub verbose: bool,
+    /// Suppress the startup banner (security warnings are still printed)
+    pub quiet: bool,
 }
 
 impl HttpServerConfig {
@@ -47,58 +54,219 @@ impl HttpServerConfig {
             cert_path,
             key_path,
             allowed_origins,
+            auth: None,
             session_ttl: Duration::from_secs(3600), // 1 hour default
+            rate_limit: None,
             verbose,
+            quiet: false,
         }
     }
+
+    /// Suppress the startup banner
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Require `Authorization: Bearer` with one of these tokens
+    pub fn with_auth(mut self, auth: Option<AuthTokens>) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// Limit calls per minute of each tool in each session
+    pub fn with_rate_limit(mut self, rate_limit: Option<u32>) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+}
+
+/// TLS status shown in the startup banner
+enum TlsStatus {
+    Disabled,
+    SelfSigned { fingerprint: Option<String> },
+    Certificate(PathBuf),
+}
+
+/// Startup summary printed once the HTTP server is configured
+struct StartupBanner {
+    server_name: String,
+    server_version: String,
+    tool_count: usize,
+    endpoint: String,
+    /// Servers also served on their own, as `/mcp/NAME (N tools)`
+    paths: Vec<String>,
+    tls: TlsStatus,
+    allowed_origins: Vec<String>,
+    /// Number of accepted bearer tokens (0: no authentication)
+    auth_tokens: usize,
+    session_ttl: Duration,
+    rate_limit: Option<u32>,
+    warnings: Vec<String>,
+}
+
+impl StartupBanner {
+    fn new<S: McpServer>(server: &S, paths: &[(String, S)], config: &HttpServerConfig, tls: TlsStatus) -> Self {
+        let scheme = if config.tls_enabled { "https" } else { "http" };
+        Self {
+            server_name: server.name().to_string(),
+            server_version: server.version().to_string(),
+            tool_count: server.tools().len(),
+            endpoint: format!("{}://{}/mcp", scheme, SocketAddr::new(config.host, config.port)),
+            paths: paths
+                .iter()
+                .map(|(name, server)| {
+                    let count = server.tools().len();
+                    format!("/mcp/{} ({} {})", name, count, if count == 1 { "tool" } else { "tools" })
+                })
+                .collect(),
+            tls,
+            allowed_origins: config.allowed_origins.clone(),
+            auth_tokens: config.auth.as_ref().map_or(0, AuthTokens::count),
+            session_ttl: config.session_ttl,
+            rate_limit: config.rate_limit,
+            warnings: security_warnings(config),
+        }
+    }
+
+    /// Render the banner as `[mcpz]`-prefixed lines
+    fn render(&self) -> String {
+        let tls = match &self.tls {
+            TlsStatus::Disabled => "disabled".to_string(),
+            TlsStatus::SelfSigned { fingerprint: Some(fp) } => {
+                format!("self-signed certificate\n[mcpz]   Fingerprint: SHA256:{}", fp)
+            }
+            TlsStatus::SelfSigned { fingerprint: None } => "self-signed certificate".to_string(),
+            TlsStatus::Certificate(path) => format!("certificate {}", path.display()),
+        };
+        let origins = if self.allowed_origins.is_empty() {
+            "localhost only".to_string()
+        } else {
+            format!("localhost, {}", self.allowed_origins.join(", "))
+        };
+        let tools = if self.tool_count == 1 { "tool" } else { "tools" };
+        let auth = match self.auth_tokens {
+            0 => "none".to_string(),
+            1 => "bearer token".to_string(),
+            n => format!("bearer token ({} accepted)", n),
+        };
+        let rate_limit = match self.rate_limit {
+            Some(limit) => format!("{} calls/min per tool and session", limit),
+            None => "none".to_string(),
+        };
+
+        let mut lines = vec![
+            "[mcpz] MCP server ready".to_string(),
+            format!(
+                "[mcpz]   Server:      {} v{} ({} {})",
+                self.server_name, self.server_version, self.tool_count, tools
+            ),
+            format!("[mcpz]   Endpoint:    {}", self.endpoint),
+        ];
+        if !self.paths.is_empty() {
+            lines.push(format!("[mcpz]   Servers:     {}", self.paths.join(", ")));
+        }
+        lines.extend([
+            format!("[mcpz]   TLS:         {}", tls),
+            format!("[mcpz]   Auth:        {}", auth),
+            format!("[mcpz]   Origins:     {}", origins),
+            format!("[mcpz]   Session TTL: {}s", self.session_ttl.as_secs()),
+            format!("[mcpz]   Rate limit:  {}", rate_limit),
+        ]);
+        lines.extend(self.warnings.iter().cloned());
+        lines.join("\n")
+    }
 }
 
 /// Run an MCP server over HTTP transport
 pub async fn run_http_server<S: McpServer + Send + Sync + 'static>(
     mcp_server: S,
     config: HttpServerConfig,
+) -> Result<()> {
+    run_http_server_with_paths(mcp_server, vec![], config).await
+}
+
+/// Run an MCP server at `/mcp`, and each of `paths` at `/mcp/NAME`, on one port. Every
+/// endpoint has its own sessions; authentication, origins and rate limits are shared settings.
+pub async fn run_http_server_with_paths<S: McpServer + Send + Sync + 'static>(
+    mcp_server: S,
+    paths: Vec<(String, S)>,
+    config: HttpServerConfig,
 ) -> Result<()> {
     let addr = SocketAddr::new(config.host, config.port);
 
-    // Print security warnings
-    print_security_warnings(&config);
+    // Load TLS up front so the banner can report the certificate in use
+    let tls_config = if config.tls_enabled {
+        Some(TlsConfig::load_or_generate(
+            config.cert_path.as_deref(),
+            config.key_path.as_deref(),
+        )?)
+    } else {
+        None
+    };
+
+    let tls_status = match &tls_config {
+        None => TlsStatus::Disabled,
+        Some(tls) if tls.is_self_signed => TlsStatus::SelfSigned {
+            fingerprint: tls.fingerprint().ok(),
+        },
+        Some(_) => TlsStatus::Certificate(config.cert_path.clone().unwrap_or_default()),
+    };
+    let banner = StartupBanner::new(&mcp_server, &paths, &config, tls_status);
 
-    // Create session manager
-    let sessions = Arc::new(SessionManager::new(config.session_ttl));
+    let mut app = routes(serve(mcp_server, &config));
+    for (name, server) in paths {
+        app = app.merge(routes_at(&format!("/mcp/{}", name), serve(server, &config)));
+    }
 
-    // Start session cleanup task
-    sessions.clone().start_cleanup_task(Duration::from_secs(60));
-
-    // Create app state
-    let state = Arc::new(AppState::new(
-        mcp_server,
-        sessions,
-        config.allowed_origins.clone(),
-        config.verbose,
-    ));
-
-    // Build router
-    let app = Router::new()
-        .route("/mcp", post(handle_post::<S>))
-        .route("/mcp", get(handle_get::<S>))
-        .route("/mcp", delete(handle_delete::<S>))
-        .with_state(state);
-
-    if config.tls_enabled {
-        run_https_server(app, addr, &config).await
+    if config.quiet {
+        for warning in &banner.warnings {
+            eprintln!("{}", warning);
+        }
     } else {
-        run_http_server_plain(app, addr, &config).await
+        eprintln!("{}", banner.render());
+    }
+
+    match tls_config {
+        Some(tls) => run_https_server(app, addr, &tls).await,
+        None => run_http_server_plain(app, addr).await,
     }
 }
 
-/// Run plain HTTP server
-async fn run_http_server_plain(
-    app: Router,
-    addr: SocketAddr,
-    _config: &HttpServerConfig,
-) -> Result<()> {
-    eprintln!("[mcpz] Listening on http://{}/mcp", addr);
+/// State for one endpoint: the server with its own session pool, whose expired sessions
+/// are cleaned up in the background (letting the server release per-session resources)
+fn serve<S: McpServer + Send + Sync + 'static>(mcp_server: S, config: &HttpServerConfig) -> Arc<AppState<S>> {
+    let sessions = Arc::new(SessionManager::new(config.session_ttl));
+    let state = Arc::new(
+        AppState::new(mcp_server, sessions.clone(), config.allowed_origins.clone(), config.verbose)
+            .with_auth(config.auth.clone())
+            .with_rate_limit(config.rate_limit),
+    );
+
+    let cleanup_state = state.clone();
+    sessions.start_cleanup_task(Duration::from_secs(60), move |id| {
+        let (state, id) = (cleanup_state.clone(), id.to_string());
+        tokio::spawn(async move { state.end_session(&id).await });
+    });
+    state
+}
+
+/// The `/mcp` endpoint's routes
+pub fn routes<S: McpServer + Send + Sync + 'static>(state: Arc<AppState<S>>) -> Router {
+    routes_at("/mcp", state)
+}
 
+/// An MCP endpoint's routes at `path`
+fn routes_at<S: McpServer + Send + Sync + 'static>(path: &str, state: Arc<AppState<S>>) -> Router {
+    Router::new()
+        .route(path, post(handle_post::<S>))
+        .route(path, get(handle_get::<S>))
+        .route(path, delete(handle_delete::<S>))
+        .with_state(state)
+}
+
+/// Run plain HTTP server
+async fn run_http_server_plain(app: Router, addr: SocketAddr) -> Result<()> {
     let listener = tokio::net::TcpListener::bind(addr)
         .await
         .context("Failed to bind to address")?;
@@ -111,32 +279,7 @@ async fn run_http_server_plain(
 }
 
 /// Run HTTPS server with TLS
-async fn run_https_server(
-    app: Router,
-    addr: SocketAddr,
-    config: &HttpServerConfig,
-) -> Result<()> {
-    // Load or generate TLS config
-    let tls_config = TlsConfig::load_or_generate(
-        config.cert_path.as_deref(),
-        config.key_path.as_deref(),
-    )?;
-
-    // Print certificate info
-    if tls_config.is_self_signed {
-        eprintln!("[mcpz] Using self-signed certificate");
-        if let Ok(fingerprint) = tls_config.fingerprint() {
-            eprintln!("[mcpz] Fingerprint: SHA256:{}", fingerprint);
-        }
-    } else {
-        eprintln!(
-            "[mcpz] Using certificate: {:?}",
-            config.cert_path.as_ref().unwrap()
-        );
-    }
-
-    eprintln!("[mcpz] Listening on https://{}/mcp", addr);
-
+async fn run_https_server(app: Router, addr: SocketAddr, tls_config: &TlsConfig) -> Result<()> {
     // Build rustls config
     let rustls_config = tls_config.build_rustls_config()?;
 
@@ -152,19 +295,28 @@ async fn run_https_server(
     Ok(())
 }
 
-/// Print security warnings based on configuration
-fn print_security_warnings(config: &HttpServerConfig) {
-    let is_localhost = config.host.is_loopback();
+/// Security warnings based on configuration
+fn security_warnings(config: &HttpServerConfig) -> Vec<String> {
+    let mut warnings = Vec::new();
 
-    if !is_localhost {
-        eprintln!("WARNING: Binding to {} exposes this server to all network interfaces.", config.host);
-        eprintln!("         Ensure proper authentication and firewall rules are in place.");
+    if !config.host.is_loopback() {
+        warnings.push(format!(
+            "WARNING: Binding to {} exposes this server to all network interfaces.",
+            config.host
+        ));
+        if config.auth.is_some() {
+            warnings.push("         Ensure proper firewall rules are in place.".to_string());
+        } else {
+            warnings.push("         Anyone who can reach it can use it; consider --auth-token.".to_string());
+        }
 
         if !config.tls_enabled {
-            eprintln!("WARNING: Running without TLS on a public interface.");
-            eprintln!("         Consider using --tls for encrypted connections.");
+            warnings.push("WARNING: Running without TLS on a public interface.".to_string());
+            warnings.push("         Consider using --tls for encrypted connections.".to_string());
         }
     }
+
+    warnings
 }
 
 #[cfg(test)]
@@ -209,5 +361,86 @@ mod tests {
         assert!(config.tls_enabled);
         assert!(config.allowed_origins.is_empty());
         assert!(config.verbose);
+        assert!(!config.quiet);
+    }
+
+    struct TestServer;
+
+    impl McpServer for TestServer {
+        fn name(&self) -> &str {
+            "test-server"
+        }
+
+        fn version(&self) -> &str {
+            "1.0.0"
+        }
+
+        fn tools(&self) -> Vec<crate::servers::common::McpTool> {
+            vec![]
+        }
+
+        fn call_tool(&self, _name: &str, _arguments: &serde_json::Value) -> Result<serde_json::Value> {
+            Ok(serde_json::json!({}))
+        }
+
+        fn verbose(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn test_startup_banner_localhost() {
+        let config = HttpServerConfig::new(
+            3000,
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            false,
+            None,
+            None,
+            None,
+            false,
+        );
+        let banner = StartupBanner::new(&TestServer, &[], &config, TlsStatus::Disabled).render();
+
+        assert!(banner.contains("test-server v1.0.0 (0 tools)"));
+        assert!(banner.contains("Endpoint:    http://127.0.0.1:3000/mcp"));
+        assert!(banner.contains("TLS:         disabled"));
+        assert!(banner.contains("Origins:     localhost only"));
+        assert!(banner.contains("Rate limit:  none"));
+        assert!(banner.contains("Auth:        none"));
+        assert!(!banner.contains("WARNING"));
+        assert!(!banner.contains("Servers:"));
+    }
+
+    #[test]
+    fn test_startup_banner_public_tls() {
+        let config = HttpServerConfig::new(
+            8443,
+            IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
+            true,
+            None,
+            None,
+            Some("https://example.com".to_string()),
+            false,
+        )
+        .with_quiet(true)
+        .with_rate_limit(Some(30))
+        .with_auth(AuthTokens::load(Some("s3cret".to_string()), None).unwrap());
+        let tls = TlsStatus::SelfSigned { fingerprint: Some("AB:CD".to_string()) };
+        let paths = [("shell".to_string(), TestServer), ("fs".to_string(), TestServer)];
+        let banner = StartupBanner::new(&TestServer, &paths, &config, tls);
+        let rendered = banner.render();
+
+        assert!(config.quiet);
+        assert!(rendered.contains("https://0.0.0.0:8443/mcp"));
+        assert!(rendered.contains("Fingerprint: SHA256:AB:CD"));
+        assert!(rendered.contains("Servers:     /mcp/shell (0 tools), /mcp/fs (0 tools)"));
+        assert!(rendered.contains("Origins:     localhost, https://example.com"));
+        assert!(rendered.contains("Rate limit:  30 calls/min per tool and session"));
+        // Public interface warning, but no plaintext warning since TLS is on
+        assert_eq!(banner.warnings.len(), 2);
+        assert!(rendered.contains("exposes this server"));
+        assert!(rendered.contains("Auth:        bearer token\n"));
+        assert!(rendered.contains("firewall rules"));
+        assert!(!rendered.contains("s3cret"));
     }
 }
@@ -11,6 +11,8 @@ pub struct Session {
     pub created_at: Instant,
     pub last_activity: Instant,
     pub initialized: bool,
+    /// Protocol version agreed on in `initialize`
+    pub protocol_version: Option<String>,
 }
 
 impl Session {
@@ -21,6 +23,7 @@ impl Session {
             created_at: now,
             last_activity: now,
             initialized: false,
+            protocol_version: None,
         }
     }
 }
@@ -79,13 +82,14 @@ impl SessionManager {
         }
     }
 
-    /// Mark a session as initialized
-    pub async fn mark_initialized(&self, id: &str) -> Result<(), SessionError> {
+    /// Mark a session as initialized with the protocol version agreed on
+    pub async fn mark_initialized(&self, id: &str, protocol_version: Option<String>) -> Result<(), SessionError> {
         let mut sessions = self.sessions.write().await;
 
         match sessions.get_mut(id) {
             Some(session) => {
                 session.initialized = true;
+                session.protocol_version = protocol_version;
                 session.last_activity = Instant::now();
                 Ok(())
             }
@@ -103,6 +107,11 @@ impl SessionManager {
         }
     }
 
+    /// The protocol version the session agreed on, if it has been initialized
+    pub async fn protocol_version(&self, id: &str) -> Option<String> {
+        self.sessions.read().await.get(id).and_then(|session| session.protocol_version.clone())
+    }
+
     /// Update the last activity time for a session
     pub async fn touch_session(&self, id: &str) -> Result<(), SessionError> {
         let mut sessions = self.sessions.write().await;
@@ -124,12 +133,23 @@ impl SessionManager {
 
     /// Clean up expired sessions
     pub async fn cleanup_expired(&self) -> usize {
-        let mut sessions = self.sessions.write().await;
-        let before = sessions.len();
+        self.remove_expired().await.len()
+    }
 
-        sessions.retain(|_, session| session.last_activity.elapsed() <= self.ttl);
+    /// Remove expired sessions and return their IDs
+    pub async fn remove_expired(&self) -> Vec<String> {
+        let mut sessions = self.sessions.write().await;
+        let expired: Vec<String> = sessions
+            .values()
+            .filter(|session| session.last_activity.elapsed() > self.ttl)
+            .map(|session| session.id.clone())
+            .collect();
+
+        for id in &expired {
+            sessions.remove(id);
+        }
 
-        before - sessions.len()
+        expired
     }
 
     /// Get the number of active sessions
@@ -138,15 +158,22 @@ impl SessionManager {
         sessions.len()
     }
 
-    /// Start a background task to periodically clean up expired sessions
-    pub fn start_cleanup_task(self: Arc<Self>, interval: Duration) {
+    /// Start a background task to periodically clean up expired sessions,
+    /// invoking `on_expired` for each removed session ID
+    pub fn start_cleanup_task<F>(self: Arc<Self>, interval: Duration, on_expired: F)
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
         tokio::spawn(async move {
             let mut interval_timer = tokio::time::interval(interval);
             loop {
                 interval_timer.tick().await;
-                let cleaned = self.cleanup_expired().await;
-                if cleaned > 0 {
-                    eprintln!("[mcpz] Cleaned up {} expired sessions", cleaned);
+                let expired = self.remove_expired().await;
+                for id in &expired {
+                    on_expired(id);
+                }
+                if !expired.is_empty() {
+                    eprintln!("[mcpz] Cleaned up {} expired sessions", expired.len());
                 }
             }
         });
@@ -208,7 +235,8 @@ mod tests {
         assert!(!manager.is_initialized(&id).await.unwrap());
 
         // Mark as initialized
-        manager.mark_initialized(&id).await.unwrap();
+        manager.mark_initialized(&id, Some("2025-06-18".to_string())).await.unwrap();
+        assert_eq!(manager.protocol_version(&id).await.as_deref(), Some("2025-06-18"));
 
         // Should now be initialized
         assert!(manager.is_initialized(&id).await.unwrap());
@@ -264,4 +292,15 @@ mod tests {
         assert_eq!(cleaned, 2);
         assert_eq!(manager.session_count().await, 0);
     }
+
+    #[tokio::test]
+    async fn test_remove_expired_returns_ids() {
+        let manager = SessionManager::new(Duration::from_millis(10));
+        let id = manager.create_session().await;
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        assert_eq!(manager.remove_expired().await, vec![id]);
+        assert!(manager.remove_expired().await.is_empty());
+    }
 }
@@ -0,0 +1,296 @@
+use anyhow::{anyhow, Context, Result};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::call::signature;
+use crate::gateway::{Launched, ServerSpec};
+use crate::servers::common::{JsonRpcRequest, McpServer};
+
+/// Most pages `list` follows through `nextCursor`
+const MAX_PAGES: usize = 100;
+
+/// Everything a server declares about itself: `mcpz inspect --json` output, and the
+/// `--baseline` later runs are compared against
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub protocol_version: String,
+    pub server_info: Value,
+    pub capabilities: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+    #[serde(default)]
+    pub tools: Vec<Value>,
+    #[serde(default)]
+    pub resources: Vec<Value>,
+    #[serde(default)]
+    pub prompts: Vec<Value>,
+}
+
+impl Snapshot {
+    /// Ask a started server for its initialize result and every tool, resource and prompt
+    /// it advertises
+    pub fn take(server: &dyn McpServer) -> Result<Self> {
+        let init = request(server, "initialize", json!({}))?;
+        let capabilities = init.get("capabilities").cloned().unwrap_or_else(|| json!({}));
+        let list = |capability: &str, method: &str, key: &str| -> Result<Vec<Value>> {
+            if capabilities.get(capability).is_none() {
+                return Ok(vec![]);
+            }
+            list(server, method, key)
+        };
+        Ok(Self {
+            protocol_version: init
+                .get("protocolVersion")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            server_info: init.get("serverInfo").cloned().unwrap_or_else(|| json!({})),
+            instructions: init.get("instructions").and_then(Value::as_str).map(str::to_string),
+            tools: list("tools", "tools/list", "tools")?,
+            resources: list("resources", "resources/list", "resources")?,
+            prompts: list("prompts", "prompts/list", "prompts")?,
+            capabilities,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&content).with_context(|| format!("{} is not an mcpz inspect --json snapshot", path.display()))
+    }
+
+    /// Human-readable summary of the snapshot
+    pub fn render(&self) -> String {
+        let field = |key: &str| self.server_info.get(key).and_then(Value::as_str).unwrap_or("unknown");
+        let mut capabilities: Vec<&str> = self
+            .capabilities
+            .as_object()
+            .map(|capabilities| capabilities.keys().map(String::as_str).collect())
+            .unwrap_or_default();
+        capabilities.sort_unstable();
+
+        let mut lines = vec![
+            format!("{} v{}", field("name"), field("version")).green().bold().to_string(),
+            format!("  Protocol:     {}", self.protocol_version),
+            format!(
+                "  Capabilities: {}",
+                if capabilities.is_empty() { "none".to_string() } else { capabilities.join(", ") }
+            ),
+        ];
+        if let Some(instructions) = &self.instructions {
+            lines.push(format!("  Instructions: {}", instructions.lines().next().unwrap_or_default()));
+        }
+
+        lines.push(String::new());
+        lines.push(format!("Tools ({})", self.tools.len()).bold().to_string());
+        for tool in &self.tools {
+            let schema = tool.get("inputSchema").cloned().unwrap_or_default();
+            lines.push(format!("  {}({})", name(tool, "name").cyan(), signature(&schema)));
+            lines.extend(description(tool));
+        }
+        lines.push(String::new());
+        lines.push(format!("Resources ({})", self.resources.len()).bold().to_string());
+        for resource in &self.resources {
+            let mime = resource.get("mimeType").and_then(Value::as_str).map(|mime| format!(" ({})", mime));
+            lines.push(format!("  {} {}{}", name(resource, "uri").cyan(), name(resource, "name"), mime.unwrap_or_default()));
+            lines.extend(description(resource));
+        }
+        lines.push(String::new());
+        lines.push(format!("Prompts ({})", self.prompts.len()).bold().to_string());
+        for prompt in &self.prompts {
+            let arguments: Vec<String> = prompt
+                .get("arguments")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .map(|argument| {
+                    let required = argument.get("required").and_then(Value::as_bool) == Some(true);
+                    format!("{}{}", name(argument, "name"), if required { "" } else { "?" })
+                })
+                .collect();
+            lines.push(format!("  {}({})", name(prompt, "name").cyan(), arguments.join(", ")));
+            lines.extend(description(prompt));
+        }
+        lines.join("\n")
+    }
+
+    /// What changed since `baseline`, one line per difference: `+` added, `-` removed,
+    /// `~` changed (with the fields that differ)
+    pub fn diff(&self, baseline: &Snapshot) -> Vec<String> {
+        let mut changes = vec![];
+        let mut compare = |what: &str, old: &Value, new: &Value| {
+            if old != new {
+                changes.push(format!("~ {}: {} -> {}", what, old, new));
+            }
+        };
+        compare("protocol version", &json!(baseline.protocol_version), &json!(self.protocol_version));
+        compare("server", &baseline.server_info, &self.server_info);
+        compare("capabilities", &baseline.capabilities, &self.capabilities);
+        compare("instructions", &json!(baseline.instructions), &json!(self.instructions));
+        changes.extend(diff_items("tool", "name", &baseline.tools, &self.tools));
+        changes.extend(diff_items("resource", "uri", &baseline.resources, &self.resources));
+        changes.extend(diff_items("prompt", "name", &baseline.prompts, &self.prompts));
+        changes
+    }
+}
+
+/// `key` of a listed item (its name, or a resource's uri)
+fn name<'a>(item: &'a Value, key: &str) -> &'a str {
+    item.get(key).and_then(Value::as_str).unwrap_or("?")
+}
+
+/// An item's description, indented under it
+fn description(item: &Value) -> Vec<String> {
+    let text = item.get("description").and_then(Value::as_str).unwrap_or_default();
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| format!("    {}", line.trim()))
+        .collect()
+}
+
+/// Added, removed and changed items of one kind, matched by `key`
+fn diff_items(kind: &str, key: &str, old: &[Value], new: &[Value]) -> Vec<String> {
+    let find = |items: &'_ [Value], id: &str| items.iter().find(|item| name(item, key) == id).cloned();
+    let mut changes = vec![];
+    for item in new {
+        let id = name(item, key);
+        match find(old, id) {
+            None => changes.push(format!("+ {} {}", kind, id)),
+            Some(previous) if previous != *item => {
+                let mut fields: Vec<&str> = item
+                    .as_object()
+                    .into_iter()
+                    .chain(previous.as_object())
+                    .flat_map(|object| object.keys().map(String::as_str))
+                    .filter(|field| item.get(field) != previous.get(field))
+                    .collect();
+                fields.sort_unstable();
+                fields.dedup();
+                changes.push(format!("~ {} {}: {} changed", kind, id, fields.join(", ")));
+            }
+            Some(_) => {}
+        }
+    }
+    for item in old {
+        let id = name(item, key);
+        if find(new, id).is_none() {
+            changes.push(format!("- {} {}", kind, id));
+        }
+    }
+    changes
+}
+
+/// Send a request through the server's own JSON-RPC handling, returning its result
+fn request(server: &dyn McpServer, method: &str, params: Value) -> Result<Value> {
+    let request: JsonRpcRequest = serde_json::from_value(json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params}))?;
+    let response = server.handle_request(request).ok_or_else(|| anyhow!("No response to {}", method))?;
+    if let Some(error) = response.error {
+        return Err(anyhow!("{} failed: {}", method, error.message));
+    }
+    Ok(response.result.unwrap_or_default())
+}
+
+/// Every item of a paginated list (`tools/list` and friends), following `nextCursor`
+fn list(server: &dyn McpServer, method: &str, key: &str) -> Result<Vec<Value>> {
+    let mut items = vec![];
+    let mut params = json!({});
+    for _ in 0..MAX_PAGES {
+        let result = request(server, method, params)?;
+        items.extend(result.get(key).and_then(Value::as_array).cloned().unwrap_or_default());
+        match result.get("nextCursor").and_then(Value::as_str) {
+            Some(cursor) => params = json!({ "cursor": cursor }),
+            None => break,
+        }
+    }
+    Ok(items)
+}
+
+/// `mcpz inspect`: start the server, then print its snapshot (as JSON with `json`), or
+/// only what changed since `baseline`
+pub fn inspect(
+    spec: &ServerSpec,
+    json: bool,
+    baseline: Option<&Path>,
+    opts: &crate::RunOptions,
+    timeout: Duration,
+) -> Result<()> {
+    let baseline = baseline.map(Snapshot::load).transpose()?;
+    let Launched { server, .. } = spec.launch(opts, timeout).with_context(|| format!("{} failed to start", spec.name))?;
+    let snapshot = Snapshot::take(server.as_ref())?;
+
+    match baseline {
+        None if json => println!("{}", serde_json::to_string_pretty(&snapshot)?),
+        None => println!("{}", snapshot.render()),
+        Some(baseline) => {
+            let changes = snapshot.diff(&baseline);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&changes)?);
+            } else if changes.is_empty() {
+                println!("{}", "✓ No changes since the baseline".green());
+            } else {
+                for change in &changes {
+                    let line = match change.chars().next() {
+                        Some('+') => change.green(),
+                        Some('-') => change.red(),
+                        _ => change.yellow(),
+                    };
+                    println!("{}", line);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::servers::shell::{ShellServer, ShellServerConfig};
+
+    fn snapshot() -> Snapshot {
+        let server = ShellServer::new(ShellServerConfig::new(None, 30, "/bin/sh".to_string(), None, None, false, false));
+        Snapshot::take(&server).unwrap()
+    }
+
+    #[test]
+    fn test_snapshot_of_builtin() {
+        let snapshot = snapshot();
+        assert_eq!(snapshot.protocol_version, "2024-11-05");
+        assert_eq!(snapshot.server_info["name"], "mcpz-shell");
+        assert_eq!(snapshot.tools.len(), 1);
+        assert!(snapshot.resources.is_empty());
+        let rendered = snapshot.render();
+        assert!(rendered.contains("Capabilities: logging, tools"));
+        assert!(rendered.contains("Prompts (0)"));
+
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert!(json.contains("\"protocolVersion\""));
+        assert_eq!(serde_json::from_str::<Snapshot>(&json).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn test_diff_against_baseline() {
+        let baseline = snapshot();
+        assert!(snapshot().diff(&baseline).is_empty());
+
+        let mut current = baseline.clone();
+        current.server_info["version"] = json!("9.9.9");
+        current.tools[0]["description"] = json!("Run something");
+        current.tools.push(json!({"name": "kill", "inputSchema": {}}));
+        current.prompts.push(json!({"name": "review"}));
+        let mut old = baseline.clone();
+        old.resources.push(json!({"uri": "file:///notes", "name": "notes"}));
+
+        let changes = current.diff(&old);
+        assert_eq!(changes.len(), 5);
+        assert!(changes[0].starts_with("~ server: "));
+        assert_eq!(changes[1], "~ tool execute_command: description changed");
+        assert_eq!(changes[2], "+ tool kill");
+        assert_eq!(changes[3], "- resource file:///notes");
+        assert_eq!(changes[4], "+ prompt review");
+    }
+}
@@ -0,0 +1,227 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::command_exists;
+
+/// Check whether a package spec refers to a local path rather than a registry package
+pub fn is_local_path(spec: &str) -> bool {
+    spec == "."
+        || spec == ".."
+        || spec.starts_with("./")
+        || spec.starts_with("../")
+        || spec.starts_with('/')
+        || spec.starts_with("~/")
+}
+
+/// Expand a leading ~ to the home directory
+fn expand_home(spec: &str) -> PathBuf {
+    match spec.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir()
+            .map(|home| home.join(rest))
+            .unwrap_or_else(|| PathBuf::from(spec)),
+        None => PathBuf::from(spec),
+    }
+}
+
+/// Build the command line (program followed by its arguments) that runs a local server
+pub fn local_invocation(spec: &str) -> Result<Vec<String>> {
+    let path = expand_home(spec);
+    let path = fs::canonicalize(&path)
+        .with_context(|| format!("Local path not found: {}", path.display()))?;
+
+    if path.is_dir() {
+        directory_invocation(&path)
+    } else {
+        file_invocation(&path)
+    }
+}
+
+/// Pick a runner for a single script based on its extension
+fn file_invocation(path: &Path) -> Result<Vec<String>> {
+    let file = path.to_string_lossy().to_string();
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+
+    let invocation = match extension {
+        "py" if command_exists("uv") => vec!["uv", "run", &file],
+        "py" => vec!["python3", &file],
+        "js" | "mjs" | "cjs" => vec!["node", &file],
+        "ts" | "mts" => vec!["deno", "run", "-A", &file],
+        _ if is_executable(path) => vec![file.as_str()],
+        _ => {
+            return Err(anyhow!(
+                "Don't know how to run {} (expected .py, .js, .ts or an executable)",
+                path.display()
+            ))
+        }
+    };
+
+    Ok(invocation.into_iter().map(String::from).collect())
+}
+
+/// Pick a runner for a project directory based on its manifest
+fn directory_invocation(dir: &Path) -> Result<Vec<String>> {
+    let dir_str = dir.to_string_lossy().to_string();
+
+    let cargo_toml = dir.join("Cargo.toml");
+    if cargo_toml.exists() {
+        return Ok(vec![
+            "cargo".to_string(),
+            "run".to_string(),
+            "--quiet".to_string(),
+            "--manifest-path".to_string(),
+            cargo_toml.to_string_lossy().to_string(),
+            "--".to_string(),
+        ]);
+    }
+
+    let pyproject = dir.join("pyproject.toml");
+    if pyproject.exists() {
+        let content = fs::read_to_string(&pyproject).context("Failed to read pyproject.toml")?;
+        let manifest: toml::Value = toml::from_str(&content).context("Failed to parse pyproject.toml")?;
+        let script = manifest
+            .get("project")
+            .and_then(|p| p.get("scripts"))
+            .and_then(|s| s.as_table())
+            .and_then(|scripts| scripts.keys().next().cloned())
+            .ok_or_else(|| anyhow!("No [project.scripts] entry in {}", pyproject.display()))?;
+        return Ok(vec!["uvx".to_string(), "--from".to_string(), dir_str, script]);
+    }
+
+    let package_json = dir.join("package.json");
+    if package_json.exists() {
+        let content = fs::read_to_string(&package_json).context("Failed to read package.json")?;
+        let manifest: serde_json::Value =
+            serde_json::from_str(&content).context("Failed to parse package.json")?;
+
+        // "bin" is either a path or a map of command name -> path; fall back to "main"
+        let entry = match manifest.get("bin") {
+            Some(serde_json::Value::String(bin)) => Some(bin.clone()),
+            Some(serde_json::Value::Object(bins)) => {
+                bins.values().next().and_then(|v| v.as_str()).map(String::from)
+            }
+            _ => None,
+        }
+        .or_else(|| manifest.get("main").and_then(|m| m.as_str()).map(String::from))
+        .ok_or_else(|| anyhow!("No \"bin\" or \"main\" entry in {}", package_json.display()))?;
+
+        return Ok(vec![
+            "node".to_string(),
+            dir.join(entry).to_string_lossy().to_string(),
+        ]);
+    }
+
+    Err(anyhow!(
+        "No Cargo.toml, pyproject.toml or package.json found in {}",
+        dir.display()
+    ))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path)
+        .map(|m| m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_is_local_path() {
+        assert!(is_local_path("."));
+        assert!(is_local_path("./server.py"));
+        assert!(is_local_path("../server"));
+        assert!(is_local_path("/opt/server"));
+        assert!(is_local_path("~/server"));
+        assert!(!is_local_path("mcp-server-time"));
+        assert!(!is_local_path("@scope/pkg"));
+        assert!(!is_local_path("docker:foo"));
+    }
+
+    #[test]
+    fn test_file_invocation_by_extension() {
+        let dir = TempDir::new().unwrap();
+        let js = dir.path().join("server.js");
+        fs::write(&js, "").unwrap();
+        let ts = dir.path().join("server.ts");
+        fs::write(&ts, "").unwrap();
+
+        let invocation = local_invocation(js.to_str().unwrap()).unwrap();
+        assert_eq!(invocation[0], "node");
+        assert!(invocation[1].ends_with("server.js"));
+
+        let invocation = local_invocation(ts.to_str().unwrap()).unwrap();
+        assert_eq!(&invocation[..3], &["deno", "run", "-A"]);
+    }
+
+    #[test]
+    fn test_file_invocation_unknown_extension() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("server.txt");
+        fs::write(&file, "").unwrap();
+
+        let result = local_invocation(file.to_str().unwrap());
+        assert!(result.unwrap_err().to_string().contains("Don't know how to run"));
+    }
+
+    #[test]
+    fn test_directory_invocation_package_json() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{"name": "server", "bin": {"server": "dist/index.js"}}"#,
+        )
+        .unwrap();
+
+        let invocation = local_invocation(dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(invocation[0], "node");
+        assert!(invocation[1].ends_with("dist/index.js"));
+    }
+
+    #[test]
+    fn test_directory_invocation_pyproject() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("pyproject.toml"),
+            "[project]\nname = \"server\"\n\n[project.scripts]\nmcp-server = \"server:main\"\n",
+        )
+        .unwrap();
+
+        let invocation = local_invocation(dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(invocation[0], "uvx");
+        assert_eq!(invocation[1], "--from");
+        assert_eq!(invocation[3], "mcp-server");
+    }
+
+    #[test]
+    fn test_directory_invocation_cargo() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("Cargo.toml"), "[package]\nname = \"server\"\n").unwrap();
+
+        let invocation = local_invocation(dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(&invocation[..2], &["cargo", "run"]);
+        assert_eq!(invocation.last().unwrap(), "--");
+    }
+
+    #[test]
+    fn test_directory_without_manifest() {
+        let dir = TempDir::new().unwrap();
+        let result = local_invocation(dir.path().to_str().unwrap());
+        assert!(result.unwrap_err().to_string().contains("No Cargo.toml"));
+    }
+
+    #[test]
+    fn test_missing_path() {
+        let result = local_invocation("./definitely-not-here-12345");
+        assert!(result.unwrap_err().to_string().contains("Local path not found"));
+    }
+}
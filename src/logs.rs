@@ -0,0 +1,297 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::record::{Recorder, Sender};
+
+/// A log file is rotated once it would grow past this size
+pub const MAX_LOG_BYTES: u64 = 10 * 1024 * 1024;
+/// Rotated files kept next to the live one (`stderr.log.1` is the newest)
+pub const KEEP_ROTATED: usize = 5;
+
+/// Where `mcpz run --log-dir` writes a package's logs
+#[derive(Debug, Clone)]
+pub struct LogOptions {
+    /// Directory for this package's files (`stderr.log`, `traffic.log` and rotations)
+    pub dir: PathBuf,
+    /// Also record the JSON-RPC messages exchanged with the client
+    pub traffic: bool,
+}
+
+/// Default base directory: `~/.local/state/mcpz/logs`
+pub fn default_log_dir() -> Result<PathBuf> {
+    let state = dirs::state_dir()
+        .or_else(|| dirs::home_dir().map(|home| home.join(".local").join("state")))
+        .ok_or_else(|| anyhow!("Could not determine state directory"))?;
+    Ok(state.join("mcpz").join("logs"))
+}
+
+/// Directory name for a package's logs (`@scope/server-fs@1.0` -> `scope_server-fs`)
+pub fn package_dir_name(package: &str) -> String {
+    let name: String = crate::split_version(package)
+        .0
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '_' })
+        .collect();
+    let name = name.trim_matches(|c| c == '_' || c == '.');
+    if name.is_empty() { "package".to_string() } else { name.to_string() }
+}
+
+/// An append-only log file that is renamed to `<name>.1` (shifting older
+/// rotations up and dropping the oldest) when it reaches `max_bytes`
+pub struct RotatingLog {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+    keep: usize,
+}
+
+impl RotatingLog {
+    pub fn open(path: PathBuf, max_bytes: u64, keep: usize) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let file = append(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { path, file, size, max_bytes, keep })
+    }
+
+    /// Append one line, stamped with the local time and `tag`
+    pub fn write_line(&mut self, tag: &str, line: &[u8]) -> Result<()> {
+        let line = String::from_utf8_lossy(line);
+        let mut entry = chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f ").to_string();
+        if !tag.is_empty() {
+            entry.push_str(tag);
+            entry.push(' ');
+        }
+        entry.push_str(line.trim_end_matches(['\r', '\n']));
+        entry.push('\n');
+        if self.size > 0 && self.size + entry.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(entry.as_bytes())?;
+        self.size += entry.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        let rotated = |n: usize| PathBuf::from(format!("{}.{}", self.path.display(), n));
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(rotated(self.keep));
+            for n in (1..self.keep).rev() {
+                let _ = fs::rename(rotated(n), rotated(n + 1));
+            }
+            fs::rename(&self.path, rotated(1))?;
+        }
+        self.file = append(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn append(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))
+}
+
+/// Called with every line passing through a tapped stream
+type Observer = Box<dyn Fn(&[u8]) + Send>;
+
+/// Spawn a package with its streams tapped: stderr copied to `stderr.log` with
+/// `logs`, and stdin/stdout copied to `traffic.log` (with `logs.traffic`) and to
+/// `recorder`. Everything still reaches our own streams byte for byte. Returns
+/// the child's exit status.
+pub fn spawn_tapped(
+    mut cmd: Command,
+    name: &str,
+    logs: Option<&LogOptions>,
+    recorder: Option<Arc<Recorder>>,
+) -> Result<std::process::ExitStatus> {
+    let open = |dir: &Path, file: &str| -> Result<Arc<Mutex<RotatingLog>>> {
+        Ok(Arc::new(Mutex::new(RotatingLog::open(dir.join(file), MAX_LOG_BYTES, KEEP_ROTATED)?)))
+    };
+    let stderr_log = logs.map(|logs| open(&logs.dir, "stderr.log")).transpose()?;
+    let traffic_log = logs
+        .filter(|logs| logs.traffic)
+        .map(|logs| open(&logs.dir, "traffic.log"))
+        .transpose()?;
+    let tap_traffic = traffic_log.is_some() || recorder.is_some();
+
+    let stdio = |tapped: bool| if tapped { Stdio::piped() } else { Stdio::inherit() };
+    cmd.stderr(stdio(stderr_log.is_some()));
+    cmd.stdin(stdio(tap_traffic));
+    cmd.stdout(stdio(tap_traffic));
+    let mut child: Child = cmd.spawn().context(format!("Failed to spawn {}", name))?;
+
+    let mut pumps = vec![];
+    if let Some(log) = stderr_log {
+        let observe: Observer = Box::new(move |line| {
+            let _ = log.lock().unwrap().write_line("", line);
+        });
+        pumps.push(tee(child.stderr.take(), std::io::stderr(), observe));
+    }
+    let done = Arc::new(AtomicBool::new(false));
+    if tap_traffic {
+        let observer = |tag: &'static str, from: Sender| -> Observer {
+            let log = traffic_log.clone();
+            let recorder = recorder.clone();
+            Box::new(move |line| {
+                if let Some(log) = &log {
+                    let _ = log.lock().unwrap().write_line(tag, line);
+                }
+                if let Some(recorder) = &recorder {
+                    recorder.record(from, line);
+                }
+            })
+        };
+        pumps.push(tee(child.stdout.take(), std::io::stdout(), observer("<-", Sender::Server)));
+        if let Some(stdin) = child.stdin.take() {
+            // Not joined: it only notices the child is gone on its next poll
+            forward_stdin(stdin, observer("->", Sender::Client), done.clone());
+        }
+    }
+
+    let status = crate::signals::wait(&mut child, name, crate::signals::STOP_GRACE)
+        .context(format!("Failed to wait for {}", name))?;
+    done.store(true, Ordering::SeqCst);
+    for pump in pumps {
+        let _ = pump.join();
+    }
+    Ok(status)
+}
+
+/// Copy `source` to `sink` unchanged, showing each line to `observe`
+fn tee<R, W>(source: Option<R>, mut sink: W, observe: Observer) -> JoinHandle<()>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    std::thread::spawn(move || {
+        let Some(source) = source else {
+            return;
+        };
+        let mut reader = BufReader::new(source);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => {}
+            }
+            let _ = sink.write_all(&line).and_then(|_| sink.flush());
+            observe(&line);
+        }
+    })
+}
+
+/// Lines read from our stdin. One reader serves every child mcpz spawns, so a
+/// restarted server gets the input that arrives after its predecessor exited.
+fn stdin_lines() -> &'static Mutex<Receiver<Vec<u8>>> {
+    static LINES: OnceLock<Mutex<Receiver<Vec<u8>>>> = OnceLock::new();
+    LINES.get_or_init(|| {
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut stdin = std::io::stdin().lock();
+            loop {
+                let mut line = Vec::new();
+                match stdin.read_until(b'\n', &mut line) {
+                    Ok(0) | Err(_) => return,
+                    Ok(_) if tx.send(line).is_err() => return,
+                    Ok(_) => {}
+                }
+            }
+        });
+        Mutex::new(rx)
+    })
+}
+
+/// Feed our stdin to the child until it exits or stdin closes (then close the child's stdin)
+fn forward_stdin(mut child_stdin: std::process::ChildStdin, observe: Observer, done: Arc<AtomicBool>) {
+    std::thread::spawn(move || {
+        let lines = stdin_lines().lock().unwrap();
+        while !done.load(Ordering::SeqCst) {
+            match lines.recv_timeout(Duration::from_millis(100)) {
+                Ok(line) => {
+                    observe(&line);
+                    if child_stdin.write_all(&line).and_then(|_| child_stdin.flush()).is_err() {
+                        return;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_package_dir_name() {
+        assert_eq!(package_dir_name("mcp-server-time"), "mcp-server-time");
+        assert_eq!(package_dir_name("@modelcontextprotocol/server-filesystem@1.0.0"), "modelcontextprotocol_server-filesystem");
+        assert_eq!(package_dir_name("docker:mcp/fetch"), "docker_mcp_fetch");
+        assert_eq!(package_dir_name("../.."), "package");
+    }
+
+    #[test]
+    fn test_rotating_log_rotates_and_keeps_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stderr.log");
+        let mut log = RotatingLog::open(path.clone(), 100, 2).unwrap();
+        for i in 0..10 {
+            log.write_line("", format!("line {} {}\n", i, "x".repeat(40)).as_bytes()).unwrap();
+        }
+
+        let live = fs::read_to_string(&path).unwrap();
+        assert!(live.contains("line 9 "));
+        assert!(live.ends_with('\n') && !live.ends_with("\n\n"));
+        assert!(fs::read_to_string(dir.path().join("stderr.log.1")).unwrap().contains("line 8 "));
+        assert!(dir.path().join("stderr.log.2").exists());
+        assert!(!dir.path().join("stderr.log.3").exists());
+        assert!(fs::metadata(&path).unwrap().len() <= 100);
+    }
+
+    #[test]
+    fn test_rotating_log_appends_to_existing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("stderr.log");
+        RotatingLog::open(path.clone(), MAX_LOG_BYTES, KEEP_ROTATED).unwrap().write_line("", b"first").unwrap();
+        RotatingLog::open(path.clone(), MAX_LOG_BYTES, KEEP_ROTATED).unwrap().write_line("<-", b"second\n").unwrap();
+
+        let lines: Vec<String> = fs::read_to_string(&path).unwrap().lines().map(str::to_string).collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with(" first"));
+        assert!(lines[1].ends_with(" <- second"));
+    }
+
+    #[test]
+    fn test_spawn_logged_captures_stderr() {
+        let dir = tempfile::tempdir().unwrap();
+        let opts = LogOptions { dir: dir.path().to_path_buf(), traffic: false };
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "echo starting >&2; echo done >&2; exit 3"]);
+
+        let status = spawn_tapped(cmd, "noisy", Some(&opts), None).unwrap();
+        assert_eq!(status.code(), Some(3));
+        let log = fs::read_to_string(dir.path().join("stderr.log")).unwrap();
+        assert!(log.contains(" starting\n"));
+        assert!(log.contains(" done\n"));
+        assert!(!dir.path().join("traffic.log").exists());
+    }
+}
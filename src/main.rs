@@ -1,18 +1,39 @@
+mod call;
+mod client;
+mod config;
+mod connect;
+mod credentials;
+#[cfg(unix)]
+mod daemon;
+mod gateway;
 mod http;
+mod inspect;
+mod local;
+mod logs;
+mod record;
+mod picker;
+mod registry;
 mod servers;
+mod signals;
+mod verify;
 
 use anyhow::{anyhow, Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
-use servers::filesystem::FilesystemServerConfig;
-use servers::shell::ShellServerConfig;
+use serde_json::{json, Value};
+use servers::audit::{AuditLog, Audited};
+use servers::strict::Strict;
+use servers::prompts::{PromptTemplate, Prompted};
+use servers::filesystem::{FilesystemServerConfig, SymlinkPolicy};
+use servers::shell::{ShellServerConfig, DEFAULT_SHELL};
 use servers::sql::{AccessMode, DatabaseType, SqlServerConfig, connect_database};
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::io::{BufRead, BufReader, Write};
+use std::io::Write;
 use std::net::IpAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 
 /// Runtime MCP router tool for running MCP servers via npx, uvx, or cargo
@@ -20,6 +41,22 @@ use std::process::{Command, Stdio};
 #[command(name = "mcpz")]
 #[command(author, version, about, long_about = None)]
 struct Cli {
+    /// Never contact package registries; only use cached mappings
+    #[arg(long, global = true, env = "MCPZ_OFFLINE")]
+    offline: bool,
+
+    /// Token for private registries (sent to the npm registry and any MCPZ_* mirrors)
+    #[arg(long, global = true, env = "MCPZ_REGISTRY_TOKEN", hide_env_values = true)]
+    registry_token: Option<String>,
+
+    /// Config file describing named servers for gateway and daemon (default: ~/.config/mcpz/config.toml)
+    #[arg(long, global = true, env = "MCPZ_CONFIG", value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Use the servers and settings of [profile.NAME] from the config file
+    #[arg(long, global = true, env = "MCPZ_PROFILE", value_name = "NAME")]
+    profile: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -28,27 +65,354 @@ struct Cli {
 enum Commands {
     /// Run an MCP server package
     Run {
-        /// Package name (e.g., mcp-server-time, @modelcontextprotocol/server-filesystem)
+        /// Package name, optionally pinned with @version (e.g., mcp-server-time@1.2.3), or a local path
         package: String,
         /// Automatically pick the first match (no prompt)
         #[arg(long, short = 'f')]
         first: bool,
+        /// Only run packages recorded in mcpz.lock
+        #[arg(long)]
+        locked: bool,
+        /// Mount a volume into a docker: package (can specify multiple times)
+        #[arg(long = "volume", value_name = "HOST:CONTAINER")]
+        volumes: Vec<String>,
+        /// Set an environment variable for the package (can specify multiple times)
+        #[arg(short = 'e', long = "env", value_name = "KEY=VALUE")]
+        env: Vec<String>,
+        /// Load environment variables for the package from a dotenv file (can specify multiple times)
+        #[arg(long = "env-file", value_name = "PATH")]
+        env_files: Vec<PathBuf>,
+        /// Remember this run's --env/--env-file values for the package in the cache
+        #[arg(long)]
+        save_env: bool,
+        /// Restart the server when it exits non-zero: no, on-failure or on-failure:MAX
+        #[arg(long, default_value = "no", value_name = "POLICY")]
+        restart: RestartPolicy,
+        /// Check the package answers initialize and tools/list before serving it
+        #[arg(long)]
+        health_check: bool,
+        /// How long the health check waits for the server (includes package download)
+        #[arg(long, default_value = "60", value_name = "SECONDS")]
+        health_timeout: u64,
+        /// Runner for npm packages (default: npx, falling back to bun if Node isn't installed)
+        #[arg(long, value_enum, value_name = "RUNNER")]
+        runner: Option<NpmRunner>,
+        /// Container runtime for docker: packages (default: first of docker, podman, nerdctl found)
+        #[arg(long, value_enum, value_name = "RUNTIME")]
+        container_runtime: Option<ContainerRuntime>,
+        /// Check the package checksum against registry metadata before running
+        #[arg(long)]
+        verify: bool,
+        /// Refuse to run packages whose checksum can't be verified (implies --verify)
+        #[arg(long, env = "MCPZ_REQUIRE_VERIFIED")]
+        require_verified: bool,
+        /// Serve the package over HTTP instead of stdio
+        #[arg(long)]
+        http: bool,
+        /// Port to listen on (HTTP only)
+        #[arg(short = 'p', long, default_value = "3000")]
+        port: u16,
+        /// Address to bind to (HTTP only)
+        #[arg(short = 'H', long, default_value = "127.0.0.1")]
+        host: String,
+        /// Enable HTTPS (auto-generates self-signed cert if no --cert/--key)
+        #[arg(long)]
+        tls: bool,
+        /// Path to TLS certificate (PEM format)
+        #[arg(long, value_name = "PATH")]
+        cert: Option<PathBuf>,
+        /// Path to TLS private key (PEM format)
+        #[arg(long, value_name = "PATH")]
+        key: Option<PathBuf>,
+        /// Allowed origins for CORS (comma-separated)
+        #[arg(long, value_name = "ORIGINS")]
+        origin: Option<String>,
+        /// Suppress the startup banner (HTTP only)
+        #[arg(short = 'q', long)]
+        quiet: bool,
+        /// Allow at most CALLS calls per minute of each tool in each session (HTTP only)
+        #[arg(long, value_name = "CALLS", requires = "http")]
+        rate_limit: Option<u32>,
+        /// Require `Authorization: Bearer TOKEN` on every request (HTTP only)
+        #[arg(long, value_name = "TOKEN", env = "MCPZ_AUTH_TOKEN", hide_env_values = true)]
+        auth_token: Option<String>,
+        /// Also accept the tokens in this file, one per line (HTTP only)
+        #[arg(long, value_name = "PATH")]
+        auth_tokens_file: Option<PathBuf>,
+        /// Also write the server's stderr to rotating files under DIR/<package>/
+        /// (default DIR: ~/.local/state/mcpz/logs; pass another as --log-dir=DIR)
+        #[arg(long, value_name = "DIR", require_equals = true)]
+        log_dir: Option<Option<PathBuf>>,
+        /// With --log-dir, also record the JSON-RPC messages in traffic.log
+        #[arg(long, requires = "log_dir")]
+        log_traffic: bool,
+        /// Record every JSON-RPC message to a JSONL file (stdio only; see mcpz replay)
+        #[arg(long, value_name = "FILE")]
+        record: Option<PathBuf>,
+        /// Treat the remaining arguments as more packages and serve them all as one MCP server,
+        /// namespacing tools by package (e.g. server-filesystem.read_file)
+        #[arg(long)]
+        prefix_tools: bool,
         /// Additional arguments to pass to the package
         #[arg(trailing_var_arg = true)]
         args: Vec<String>,
     },
+    /// Resolve and pre-fetch a package without running it (for image builds and air-gapped hosts)
+    Install {
+        /// Package name, optionally pinned with @version
+        package: String,
+        /// Automatically pick the first match (no prompt)
+        #[arg(long, short = 'f')]
+        first: bool,
+        /// Container runtime for docker: packages (default: first of docker, podman, nerdctl found)
+        #[arg(long, value_enum, value_name = "RUNTIME")]
+        container_runtime: Option<ContainerRuntime>,
+    },
     /// Search for an MCP package in npm/pip/crates.io registries (non-interactive)
     Search {
         /// Package name to search for
         package: String,
+        /// Ignore cached search results and query registries again
+        #[arg(long)]
+        no_cache: bool,
+        /// How long cached search results stay fresh
+        #[arg(long, default_value = "3600", value_name = "SECONDS")]
+        cache_ttl: u64,
+        /// Only show packages tagged or named as MCP servers
+        #[arg(long)]
+        mcp_only: bool,
+        /// Print results as JSON on stdout (progress goes to stderr)
+        #[arg(long)]
+        json: bool,
+        /// Results per registry
+        #[arg(long, default_value = "10", value_parser = clap::value_parser!(u16).range(1..=100))]
+        limit: u16,
+        /// Page of results to show, starting at 1
+        #[arg(long, default_value = "1", value_parser = clap::value_parser!(u16).range(1..))]
+        page: u16,
+        /// How to order results
+        #[arg(long, value_enum, default_value_t = SearchSort::Downloads)]
+        sort: SearchSort,
     },
     /// Search and pick a package to save to cache
     Pick {
         /// Package name to search for
         package: String,
+        /// Ignore cached search results and query registries again
+        #[arg(long)]
+        no_cache: bool,
+        /// How long cached search results stay fresh
+        #[arg(long, default_value = "3600", value_name = "SECONDS")]
+        cache_ttl: u64,
+        /// Only show packages tagged or named as MCP servers
+        #[arg(long)]
+        mcp_only: bool,
+        /// Print the selected package as JSON instead of caching and running it
+        #[arg(long)]
+        print: bool,
+        /// Use the numbered prompt instead of the full-screen picker
+        #[arg(long, env = "MCPZ_NO_TUI")]
+        no_tui: bool,
+        /// Results per registry
+        #[arg(long, default_value = "10", value_parser = clap::value_parser!(u16).range(1..=100))]
+        limit: u16,
+        /// Page of results to show, starting at 1
+        #[arg(long, default_value = "1", value_parser = clap::value_parser!(u16).range(1..))]
+        page: u16,
+        /// How to order results
+        #[arg(long, value_enum, default_value_t = SearchSort::Downloads)]
+        sort: SearchSort,
+        /// Default arguments saved with the mapping, split like a shell would; `mcpz run` puts them before its own
+        #[arg(long, allow_hyphen_values = true, value_name = "ARGS")]
+        args: Option<String>,
     },
     /// Clear the package cache
     ClearCache,
+    /// Inspect and edit individual cache entries
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// Manage aliases that expand to a package with default arguments and environment
+    Alias {
+        #[command(subcommand)]
+        action: AliasAction,
+    },
+    /// Serve several MCP servers (built-in, packages or remote HTTP) as one, namespacing tools by server
+    Gateway {
+        /// Servers as [NAME=]SERVER, where SERVER is a package, alias or local path, builtin:shell,
+        /// builtin:filesystem[:DIR,...], builtin:sql:URL (read-only), or an http(s):// MCP endpoint.
+        /// Without any, the [servers] from the config file are served.
+        #[arg(value_name = "SERVER")]
+        servers: Vec<String>,
+        /// Automatically pick the first match when resolving packages (no prompt)
+        #[arg(long, short = 'f')]
+        first: bool,
+        /// How long each server may take to start and answer initialize, including package download (default: 60)
+        #[arg(long, value_name = "SECONDS")]
+        timeout: Option<u64>,
+        /// Log each routed tool call to stderr
+        #[arg(short = 'v', long)]
+        verbose: bool,
+        /// Serve the gateway over HTTP instead of stdio
+        #[arg(long)]
+        http: bool,
+        /// Port to listen on, HTTP only (default: 3000)
+        #[arg(short = 'p', long)]
+        port: Option<u16>,
+        /// Address to bind to, HTTP only (default: 127.0.0.1)
+        #[arg(short = 'H', long)]
+        host: Option<String>,
+        /// Enable HTTPS (auto-generates self-signed cert if no --cert/--key)
+        #[arg(long)]
+        tls: bool,
+        /// Path to TLS certificate (PEM format)
+        #[arg(long, value_name = "PATH")]
+        cert: Option<PathBuf>,
+        /// Path to TLS private key (PEM format)
+        #[arg(long, value_name = "PATH")]
+        key: Option<PathBuf>,
+        /// Allowed origins for CORS (comma-separated)
+        #[arg(long, value_name = "ORIGINS")]
+        origin: Option<String>,
+        /// Suppress the startup banner (HTTP only)
+        #[arg(short = 'q', long)]
+        quiet: bool,
+        /// Allow at most CALLS calls per minute of each tool in each session, for servers
+        /// without their own rate_limit in the config file
+        #[arg(long, value_name = "CALLS")]
+        rate_limit: Option<u32>,
+        /// Truncate tool results with more than BYTES of text, for servers without their
+        /// own max_result_size in the config file
+        #[arg(long, value_name = "BYTES")]
+        max_result_size: Option<usize>,
+        /// Ping package servers every SECONDS while idle, stopping any that doesn't answer in as long
+        #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+        ping_interval: Option<u64>,
+        /// Require `Authorization: Bearer TOKEN` on every request (HTTP only)
+        #[arg(long, value_name = "TOKEN", env = "MCPZ_AUTH_TOKEN", hide_env_values = true)]
+        auth_token: Option<String>,
+        /// Also accept the tokens in this file, one per line (HTTP only)
+        #[arg(long, value_name = "PATH")]
+        auth_tokens_file: Option<PathBuf>,
+        /// Also serve each server on its own at /mcp/NAME, with its tools unprefixed (HTTP only)
+        #[arg(long)]
+        paths: bool,
+        /// Record every JSON-RPC message to a JSONL file (stdio only; see mcpz replay)
+        #[arg(long, value_name = "FILE", conflicts_with = "http")]
+        record: Option<PathBuf>,
+        /// Append a JSONL entry for every tool call to FILE
+        #[arg(long, value_name = "FILE")]
+        audit_log: Option<PathBuf>,
+        /// Hide API tokens in the arguments written to the audit log
+        #[arg(long, requires = "audit_log")]
+        audit_redact: bool,
+        /// Refuse malformed JSON-RPC messages and tool arguments that don't match the tool's inputSchema
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Start a server, call one of its tools, print the result and exit
+    Call {
+        /// A server from the config file's [servers], a package, alias or local path, builtin:shell,
+        /// builtin:filesystem[:DIR,...], builtin:sql:URL (read-only), or an http(s):// MCP endpoint
+        #[arg(value_name = "SERVER")]
+        server: String,
+        /// Name of the tool to call
+        tool: String,
+        /// Tool arguments as a JSON object
+        #[arg(long, value_name = "JSON", default_value = "{}")]
+        args: String,
+        /// Print the whole result as JSON instead of its text content
+        #[arg(long)]
+        json: bool,
+        /// Automatically pick the first match when resolving a package (no prompt)
+        #[arg(long, short = 'f')]
+        first: bool,
+        /// Set an environment variable for the package (can specify multiple times)
+        #[arg(short = 'e', long = "env", value_name = "KEY=VALUE")]
+        env: Vec<String>,
+        /// How long the server may take to start and answer initialize, including package download
+        #[arg(long, default_value = "60", value_name = "SECONDS")]
+        timeout: u64,
+    },
+    /// Start a server, list its tools with their parameters and exit
+    Tools {
+        /// A server from the config file's [servers], a package, alias or local path, builtin:shell,
+        /// builtin:filesystem[:DIR,...], builtin:sql:URL (read-only), or an http(s):// MCP endpoint
+        #[arg(value_name = "SERVER", required_unless_present = "url", conflicts_with = "url")]
+        server: Option<String>,
+        /// Remote Streamable HTTP endpoint to list instead
+        #[arg(long, value_name = "URL")]
+        url: Option<String>,
+        /// Print the tools (with full input schemas) as JSON
+        #[arg(long)]
+        json: bool,
+        /// Automatically pick the first match when resolving a package (no prompt)
+        #[arg(long, short = 'f')]
+        first: bool,
+        /// Set an environment variable for the package (can specify multiple times)
+        #[arg(short = 'e', long = "env", value_name = "KEY=VALUE")]
+        env: Vec<String>,
+        /// How long the server may take to start and answer initialize, including package download
+        #[arg(long, default_value = "60", value_name = "SECONDS")]
+        timeout: u64,
+    },
+    /// Start a server and print everything it declares: initialize result, capabilities, tools,
+    /// resources and prompts
+    Inspect {
+        /// A server from the config file's [servers], a package, alias or local path, builtin:shell,
+        /// builtin:filesystem[:DIR,...], builtin:sql:URL (read-only), or an http(s):// MCP endpoint
+        #[arg(value_name = "SERVER")]
+        server: String,
+        /// Print the snapshot as JSON (save it to use as a --baseline later)
+        #[arg(long)]
+        json: bool,
+        /// Only report what changed since this saved `mcpz inspect --json` snapshot
+        #[arg(long, value_name = "FILE")]
+        baseline: Option<PathBuf>,
+        /// Automatically pick the first match when resolving a package (no prompt)
+        #[arg(long, short = 'f')]
+        first: bool,
+        /// Set an environment variable for the package (can specify multiple times)
+        #[arg(short = 'e', long = "env", value_name = "KEY=VALUE")]
+        env: Vec<String>,
+        /// How long the server may take to start and answer initialize, including package download
+        #[arg(long, default_value = "60", value_name = "SECONDS")]
+        timeout: u64,
+    },
+    /// Serve a remote Streamable HTTP MCP server on stdio, for clients that only speak stdio
+    Connect {
+        /// The server's endpoint, e.g. https://mcp.example.com/mcp
+        url: String,
+        /// Send `Authorization: Bearer TOKEN` with every request
+        #[arg(long, value_name = "TOKEN", env = "MCPZ_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+        /// How long to wait for each response
+        #[arg(long, default_value = "300", value_name = "SECONDS")]
+        timeout: u64,
+        /// Log every relayed message to stderr
+        #[arg(short = 'v', long)]
+        verbose: bool,
+    },
+    /// Re-drive a server with the client messages from a --record session and compare its responses
+    Replay {
+        /// Recording made with --record
+        session: PathBuf,
+        /// Package (or alias, or local path) to replay against
+        target: String,
+        /// How long to wait for each response
+        #[arg(long, default_value = "30", value_name = "SECONDS")]
+        timeout: u64,
+        /// Arguments to pass to the target
+        #[arg(trailing_var_arg = true)]
+        args: Vec<String>,
+    },
+    /// Re-resolve cached packages and report newer versions
+    Update {
+        /// Upgrade installed cargo binaries that are out of date
+        #[arg(long)]
+        upgrade: bool,
+    },
     /// Run a built-in MCP server (shell, filesystem, sql)
     #[command(after_help = "Available servers:\n  shell       Execute shell commands\n  filesystem  Filesystem operations\n  sql         SQL database queries\n\nRun 'mcpz server <SERVER> --help' for server-specific options.")]
     Server {
@@ -61,6 +425,93 @@ enum Commands {
     },
 
     /// List cached package mappings and available servers
+    List {
+        /// Print the cache contents as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Keep a set of packages running, each served over HTTP, and supervise them (see ps/stop/logs)
+    Daemon {
+        /// Packages to serve, as PACKAGE or NAME=PACKAGE. Without any, the [servers] from the
+        /// config file are served.
+        packages: Vec<String>,
+        /// Port for the first package; later packages get the following ports
+        #[arg(short = 'p', long, default_value = "3000")]
+        port: u16,
+        /// Restart crashed servers: no, on-failure or on-failure:MAX
+        #[arg(long, default_value = "on-failure", value_name = "POLICY")]
+        restart: RestartPolicy,
+    },
+    /// List servers managed by the running daemon
+    Ps,
+    /// Stop a server managed by the running daemon
+    Stop {
+        /// Server name (see `mcpz ps`)
+        name: String,
+    },
+    /// Show recent output of a server managed by the running daemon
+    Logs {
+        /// Server name (see `mcpz ps`)
+        name: String,
+        /// Number of lines to show
+        #[arg(short = 'n', long, default_value = "50")]
+        lines: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheAction {
+    /// List cached mappings with when they were cached
+    List,
+    /// Remove the mapping (and any pinned version) for a search term
+    Rm {
+        /// Search term to forget
+        term: String,
+    },
+    /// Map a search term to a registry without searching
+    Pin {
+        /// Search term to map
+        term: String,
+        /// Registry to use
+        #[arg(value_enum)]
+        registry: PackageType,
+        /// Package name in that registry (default: the search term)
+        #[arg(long)]
+        package: Option<String>,
+    },
+    /// Set the default arguments `mcpz run` passes for a search term (none clears them)
+    SetArgs {
+        /// Search term the arguments belong to
+        term: String,
+        /// Arguments placed before any given to `mcpz run`
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Rewrite a cache from an older mcpz at the current schema version
+    Migrate,
+}
+
+#[derive(Subcommand)]
+enum AliasAction {
+    /// Create or replace an alias (e.g. mcpz alias add timeserver mcp-server-time --args "--tz UTC")
+    Add {
+        /// Alias name, used in place of a package (mcpz run timeserver)
+        name: String,
+        /// Package the alias runs, optionally pinned with @version
+        package: String,
+        /// Default arguments, split like a shell would; run arguments are appended after them
+        #[arg(long, allow_hyphen_values = true, value_name = "ARGS")]
+        args: Option<String>,
+        /// Environment variable for the package (can specify multiple times)
+        #[arg(short = 'e', long = "env", value_name = "KEY=VALUE")]
+        env: Vec<String>,
+    },
+    /// Remove an alias
+    Rm {
+        /// Alias name
+        name: String,
+    },
+    /// List aliases
     List,
 }
 
@@ -77,7 +528,7 @@ enum ServerType {
         timeout: u64,
 
         /// Shell to use for command execution
-        #[arg(short = 's', long, default_value = "/bin/sh")]
+        #[arg(short = 's', long, default_value = DEFAULT_SHELL)]
         shell: String,
 
         /// Only allow commands matching these patterns (comma-separated)
@@ -124,14 +575,75 @@ enum ServerType {
         /// Allowed origins for CORS (comma-separated)
         #[arg(long, value_name = "ORIGINS")]
         origin: Option<String>,
+
+        /// Suppress the startup banner (HTTP only)
+        #[arg(short = 'q', long)]
+        quiet: bool,
+
+        /// Allow at most CALLS calls per minute of each tool in each session (HTTP only)
+        #[arg(long, value_name = "CALLS", requires = "http")]
+        rate_limit: Option<u32>,
+
+        /// Require `Authorization: Bearer TOKEN` on every request (HTTP only)
+        #[arg(long, value_name = "TOKEN", env = "MCPZ_AUTH_TOKEN", hide_env_values = true)]
+        auth_token: Option<String>,
+
+        /// Also accept the tokens in this file, one per line (HTTP only)
+        #[arg(long, value_name = "PATH")]
+        auth_tokens_file: Option<PathBuf>,
+
+        /// Record every JSON-RPC message to a JSONL file (stdio only; see mcpz replay)
+        #[arg(long, value_name = "FILE", conflicts_with = "http")]
+        record: Option<PathBuf>,
+
+        /// Append a JSONL entry for every tool call to FILE
+        #[arg(long, value_name = "FILE")]
+        audit_log: Option<PathBuf>,
+
+        /// Hide API tokens in the arguments written to the audit log
+        #[arg(long, requires = "audit_log")]
+        audit_redact: bool,
+
+        /// Refuse malformed JSON-RPC messages and tool arguments that don't match the tool's inputSchema
+        #[arg(long)]
+        strict: bool,
     },
 
     /// Start an MCP server for filesystem operations
     Filesystem {
-        /// Allowed directories (can specify multiple times, defaults to current directory)
-        #[arg(short = 'd', long = "dir", value_name = "PATH")]
+        /// Allowed directories (can specify multiple times, defaults to current directory); append
+        /// `:ro` to allow only reading in one, or `:rw` (the default)
+        #[arg(short = 'd', long = "dir", value_name = "PATH[:ro|:rw]")]
         allowed_directories: Vec<PathBuf>,
 
+        /// Directory for create_temp_dir (must be within an allowed directory)
+        #[arg(long = "scratch-dir", value_name = "PATH")]
+        scratch_dir: Option<PathBuf>,
+
+        /// Also limit each session to the roots its client reports, if the client supports roots
+        #[arg(long)]
+        client_roots: bool,
+
+        /// Offer delete_file and delete_directory tools
+        #[arg(long)]
+        allow_delete: bool,
+
+        /// Offer the set_permissions tool
+        #[arg(long)]
+        allow_chmod: bool,
+
+        /// Include .gitignored paths in directory_tree and searches unless a call asks otherwise
+        #[arg(long)]
+        no_gitignore: bool,
+
+        /// Which symlinks inside the allowed directories paths may go through
+        #[arg(long, value_enum, default_value_t = SymlinkPolicy::WithinAllowed)]
+        follow_symlinks: SymlinkPolicy,
+
+        /// Hide paths matching these globs from every tool (comma-separated, e.g. '*.env,**/secrets/**')
+        #[arg(long, value_name = "PATTERNS")]
+        exclude: Option<String>,
+
         /// Enable verbose logging to stderr
         #[arg(short = 'v', long)]
         verbose: bool,
@@ -164,6 +676,38 @@ enum ServerType {
         /// Allowed origins for CORS (comma-separated)
         #[arg(long, value_name = "ORIGINS")]
         origin: Option<String>,
+
+        /// Suppress the startup banner (HTTP only)
+        #[arg(short = 'q', long)]
+        quiet: bool,
+
+        /// Allow at most CALLS calls per minute of each tool in each session (HTTP only)
+        #[arg(long, value_name = "CALLS", requires = "http")]
+        rate_limit: Option<u32>,
+
+        /// Require `Authorization: Bearer TOKEN` on every request (HTTP only)
+        #[arg(long, value_name = "TOKEN", env = "MCPZ_AUTH_TOKEN", hide_env_values = true)]
+        auth_token: Option<String>,
+
+        /// Also accept the tokens in this file, one per line (HTTP only)
+        #[arg(long, value_name = "PATH")]
+        auth_tokens_file: Option<PathBuf>,
+
+        /// Record every JSON-RPC message to a JSONL file (stdio only; see mcpz replay)
+        #[arg(long, value_name = "FILE", conflicts_with = "http")]
+        record: Option<PathBuf>,
+
+        /// Append a JSONL entry for every tool call to FILE
+        #[arg(long, value_name = "FILE")]
+        audit_log: Option<PathBuf>,
+
+        /// Hide API tokens in the arguments written to the audit log
+        #[arg(long, requires = "audit_log")]
+        audit_redact: bool,
+
+        /// Refuse malformed JSON-RPC messages and tool arguments that don't match the tool's inputSchema
+        #[arg(long)]
+        strict: bool,
     },
 
     /// Start an MCP server for SQL database queries
@@ -238,19 +782,57 @@ SUPPORTED DATABASES:
         /// Allowed origins for CORS (comma-separated)
         #[arg(long, value_name = "ORIGINS")]
         origin: Option<String>,
+
+        /// Suppress the startup banner (HTTP only)
+        #[arg(short = 'q', long)]
+        quiet: bool,
+
+        /// Allow at most CALLS calls per minute of each tool in each session (HTTP only)
+        #[arg(long, value_name = "CALLS", requires = "http")]
+        rate_limit: Option<u32>,
+
+        /// Require `Authorization: Bearer TOKEN` on every request (HTTP only)
+        #[arg(long, value_name = "TOKEN", env = "MCPZ_AUTH_TOKEN", hide_env_values = true)]
+        auth_token: Option<String>,
+
+        /// Also accept the tokens in this file, one per line (HTTP only)
+        #[arg(long, value_name = "PATH")]
+        auth_tokens_file: Option<PathBuf>,
+
+        /// Record every JSON-RPC message to a JSONL file (stdio only; see mcpz replay)
+        #[arg(long, value_name = "FILE", conflicts_with = "http")]
+        record: Option<PathBuf>,
+
+        /// Append a JSONL entry for every tool call to FILE
+        #[arg(long, value_name = "FILE")]
+        audit_log: Option<PathBuf>,
+
+        /// Hide API tokens in the arguments written to the audit log
+        #[arg(long, requires = "audit_log")]
+        audit_redact: bool,
+
+        /// Refuse malformed JSON-RPC messages and tool arguments that don't match the tool's inputSchema
+        #[arg(long)]
+        strict: bool,
     },
 }
 
 /// Determines the package type based on the package name
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
 #[serde(rename_all = "lowercase")]
 pub enum PackageType {
     /// Cargo/Rust package (runs with cargo install)
     Cargo,
     /// Python package (runs with uvx)
+    #[value(alias = "pypi")]
     Python,
     /// npm package (runs with npx)
     Npm,
+    /// Container image (runs with docker run)
+    Docker,
+    /// Deno package from JSR or an npm: specifier (runs with deno run)
+    #[value(alias = "jsr")]
+    Deno,
 }
 
 impl PackageType {
@@ -260,6 +842,8 @@ impl PackageType {
             PackageType::Npm => "npx",
             PackageType::Python => "uvx",
             PackageType::Cargo => "cargo",
+            PackageType::Docker => "docker",
+            PackageType::Deno => "deno",
         }
     }
 
@@ -269,6 +853,32 @@ impl PackageType {
             PackageType::Npm => "Install Node.js/npm from https://nodejs.org/ or run: curl -fsSL https://deb.nodesource.com/setup_lts.x | sudo -E bash - && sudo apt-get install -y nodejs",
             PackageType::Python => "Install uv by running: curl -LsSf https://astral.sh/uv/install.sh | sh",
             PackageType::Cargo => "Install Rust/Cargo from https://rustup.rs/ or run: curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs | sh",
+            PackageType::Docker => "Install Docker from https://docs.docker.com/get-docker/",
+            PackageType::Deno => "Install Deno from https://deno.com/ or run: curl -fsSL https://deno.land/install.sh | sh",
+        }
+    }
+
+    /// Get the package argument for the runner, pinned to a version if given
+    pub fn package_spec(&self, name: &str, version: Option<&str>) -> String {
+        match (self, version) {
+            (PackageType::Npm, Some(v)) => format!("{}@{}", name, v),
+            (PackageType::Python, Some(v)) => format!("{}=={}", name, v),
+            (PackageType::Docker, Some(v)) if v.starts_with("sha256:") => format!("{}@{}", name, v),
+            (PackageType::Docker, Some(v)) => format!("{}:{}", name, v),
+            (PackageType::Deno, v) => {
+                // JSR names are stored bare (@scope/pkg); npm specifiers keep their prefix
+                let base = if name.starts_with("npm:") {
+                    name.to_string()
+                } else {
+                    format!("jsr:{}", name)
+                };
+                match v {
+                    Some(v) => format!("{}@{}", base, v),
+                    None => base,
+                }
+            }
+            // cargo takes the version as a separate --version flag
+            (PackageType::Cargo, Some(_)) | (_, None) => name.to_string(),
         }
     }
 
@@ -278,12 +888,14 @@ impl PackageType {
             PackageType::Npm => "npm",
             PackageType::Python => "PyPI",
             PackageType::Cargo => "crates.io",
+            PackageType::Docker => "Docker",
+            PackageType::Deno => "JSR",
         }
     }
 }
 
 /// Information about a found package
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct PackageInfo {
     name: String,
     version: String,
@@ -292,24 +904,99 @@ struct PackageInfo {
     published: String,
     downloads: Option<u64>,
     registry: PackageType,
+    /// Listing in the official MCP registry: server name and declared transport/runtime
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mcp_server: Option<String>,
+    /// Registry keywords (npm keywords, crates.io keywords, PyPI keywords and classifiers)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    keywords: Vec<String>,
+    /// Related URLs by kind (homepage, repository, npm, ...)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    links: BTreeMap<String, String>,
 }
 
 impl PackageInfo {
+    /// Whether the package presents itself as an MCP server: listed in the MCP registry,
+    /// tagged with an MCP keyword/classifier, or named like one (mcp-server-time, fastmcp)
+    fn is_mcp_server(&self) -> bool {
+        let is_mcp_term = |term: &str| {
+            let term = term.trim().to_lowercase();
+            term == "mcp"
+                || term.starts_with("mcp-")
+                || term.starts_with("mcp ")
+                || term.replace([' ', '-', '_'], "").contains("modelcontextprotocol")
+        };
+
+        let name = self.name.to_lowercase();
+
+        self.mcp_server.is_some()
+            || self.keywords.iter().any(|k| k.split("::").any(is_mcp_term))
+            || name.contains("modelcontextprotocol")
+            || name
+                .split(|c: char| !c.is_ascii_alphanumeric())
+                .any(|part| part.starts_with("mcp") || part.ends_with("mcp"))
+    }
+
+
     fn display(&self, index: usize) {
-        println!(
-            "{}",
+        println!("{}", self.render(index));
+    }
+
+    /// Where to read the package's code or docs: its repository, else its homepage
+    fn source_link(&self) -> Option<&str> {
+        self.links
+            .get("repository")
+            .or_else(|| self.links.get("homepage"))
+            .map(String::as_str)
+    }
+
+    /// The package as a picker entry, with details for the preview pane
+    fn pick_item(&self) -> picker::PickItem {
+        let mut label = format!("{} v{}  [{}]", self.name, self.version, self.registry.display_name());
+        if let Some(dl) = self.downloads {
+            label.push_str(&format!("  {} downloads", format_downloads(dl)));
+        }
+        let mut preview = vec![
+            self.description.clone(),
+            format!("Registry:  {}", self.registry.display_name()),
+            format!("Author:    {}", self.author),
+            format!("Published: {}", self.published),
+        ];
+        if let Some(dl) = self.downloads {
+            preview.push(format!("Downloads: {}", format_downloads(dl)));
+        }
+        if let Some(mcp_server) = &self.mcp_server {
+            preview.push(format!("MCP server: {}", mcp_server));
+        }
+        if let Some(source) = self.source_link() {
+            preview.push(format!("Source:    {}", source));
+        }
+        picker::PickItem { label, preview }
+    }
+
+    /// The package as shown in search results, ending with a blank line
+    fn render(&self, index: usize) -> String {
+        let mut lines = vec![
             format!("[{}] {} v{}", index + 1, self.name, self.version)
                 .green()
                 .bold()
-        );
-        println!("    Registry:    {}", self.registry.display_name().cyan());
-        println!("    Description: {}", self.description);
-        println!("    Author:      {}", self.author);
-        println!("    Published:   {}", self.published);
+                .to_string(),
+            format!("    Registry:    {}", self.registry.display_name().cyan()),
+            format!("    Description: {}", self.description),
+            format!("    Author:      {}", self.author),
+            format!("    Published:   {}", self.published),
+        ];
         if let Some(dl) = self.downloads {
-            println!("    Downloads:   {}", format_downloads(dl).yellow());
+            lines.push(format!("    Downloads:   {}", format_downloads(dl).yellow()));
         }
-        println!();
+        if let Some(mcp_server) = &self.mcp_server {
+            lines.push(format!("    MCP server:  {}", mcp_server.magenta()));
+        }
+        if let Some(source) = self.source_link() {
+            lines.push(format!("    Source:      {}", source));
+        }
+        lines.push(String::new());
+        lines.join("\n")
     }
 }
 
@@ -329,383 +1016,832 @@ fn sort_by_popularity(packages: &mut [PackageInfo]) {
     packages.sort_by(|a, b| {
         match (b.downloads, a.downloads) {
             (Some(b_dl), Some(a_dl)) => b_dl.cmp(&a_dl),
-            (Some(_), None) => std::cmp::Ordering::Less,
-            (None, Some(_)) => std::cmp::Ordering::Greater,
+            // Packages without a download count go last
+            (Some(_), None) => std::cmp::Ordering::Greater,
+            (None, Some(_)) => std::cmp::Ordering::Less,
             (None, None) => std::cmp::Ordering::Equal,
         }
     });
 }
 
-/// Package cache stored in ~/.cache/mcpz/package_mapping.toml
-#[derive(Debug, Default, Serialize, Deserialize)]
-struct PackageCache {
-    /// Maps search term -> (actual package name, package type)
-    packages: HashMap<String, (String, PackageType)>,
+/// Runner used to execute npm packages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum NpmRunner {
+    Npx,
+    Bun,
 }
 
-impl PackageCache {
-    fn cache_path() -> Result<PathBuf> {
-        let cache_dir = dirs::cache_dir()
-            .ok_or_else(|| anyhow!("Could not determine cache directory"))?
-            .join("mcpz");
-        Ok(cache_dir.join("package_mapping.toml"))
+impl NpmRunner {
+    fn command(&self) -> &'static str {
+        match self {
+            NpmRunner::Npx => "npx",
+            NpmRunner::Bun => "bunx",
+        }
     }
 
-    fn load() -> Result<Self> {
-        let path = Self::cache_path()?;
-        if !path.exists() {
-            return Ok(Self::default());
+    /// Arguments placed before the package; bunx installs without prompting
+    fn args(&self) -> &'static [&'static str] {
+        match self {
+            NpmRunner::Npx => &["-y"],
+            NpmRunner::Bun => &[],
         }
-
-        let content = fs::read_to_string(&path).context("Failed to read cache file")?;
-        toml::from_str(&content).context("Failed to parse cache file")
     }
 
-    fn save(&self) -> Result<()> {
-        let path = Self::cache_path()?;
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent).context("Failed to create cache directory")?;
+    /// Pick the requested runner, or npx if installed and bunx otherwise
+    fn detect(requested: Option<NpmRunner>) -> Result<Self> {
+        if let Some(runner) = requested {
+            return if command_exists(runner.command()) {
+                Ok(runner)
+            } else {
+                Err(anyhow!(
+                    "{} not found. {}",
+                    runner.command(),
+                    runner.install_instructions()
+                ))
+            };
         }
 
-        let content = toml::to_string_pretty(self).context("Failed to serialize cache")?;
-        fs::write(&path, content).context("Failed to write cache file")?;
-        Ok(())
+        [NpmRunner::Npx, NpmRunner::Bun]
+            .into_iter()
+            .find(|runner| command_exists(runner.command()))
+            .ok_or_else(|| {
+                anyhow!(
+                    "npx not found. {} Alternatively, {}",
+                    NpmRunner::Npx.install_instructions(),
+                    NpmRunner::Bun.install_instructions()
+                )
+            })
     }
 
-    fn get(&self, search_term: &str) -> Option<(String, PackageType)> {
-        self.packages.get(search_term).cloned()
+    fn install_instructions(&self) -> &'static str {
+        match self {
+            NpmRunner::Npx => PackageType::Npm.install_instructions(),
+            NpmRunner::Bun => "Install Bun from https://bun.sh/ or run: curl -fsSL https://bun.sh/install | bash",
+        }
     }
+}
 
-    fn set(&mut self, search_term: String, package_name: String, pkg_type: PackageType) {
-        self.packages.insert(search_term, (package_name, pkg_type));
+/// Order of search results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+enum SearchSort {
+    /// Most downloads first
+    #[default]
+    Downloads,
+    /// Alphabetical by package name
+    Name,
+    /// Most recently published first
+    Updated,
+}
+
+impl SearchSort {
+    fn describe(&self) -> &'static str {
+        match self {
+            SearchSort::Downloads => "by popularity",
+            SearchSort::Name => "by name",
+            SearchSort::Updated => "by last update",
+        }
     }
 
-    fn clear() -> Result<()> {
-        let path = Self::cache_path()?;
-        if path.exists() {
-            fs::remove_file(&path).context("Failed to remove cache file")?;
+    /// Order `packages` in place
+    fn apply(&self, packages: &mut [PackageInfo]) {
+        match self {
+            SearchSort::Downloads => sort_by_popularity(packages),
+            SearchSort::Name => packages.sort_by_key(|p| p.name.to_lowercase()),
+            // Dates are YYYY-MM-DD, so they sort as strings; "Unknown" goes last
+            SearchSort::Updated => packages.sort_by(|a, b| {
+                let known = |p: &PackageInfo| p.published != "Unknown";
+                known(b).cmp(&known(a)).then_with(|| b.published.cmp(&a.published))
+            }),
         }
-        Ok(())
     }
 }
 
-/// Check if a command exists on the system
-pub fn command_exists(cmd: &str) -> bool {
-    Command::new("which")
-        .arg(cmd)
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status()
-        .map(|s| s.success())
-        .unwrap_or(false)
+/// OCI runtime used to run container image packages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ContainerRuntime {
+    Docker,
+    Podman,
+    Nerdctl,
 }
 
-/// Get npm download count for a package
-fn get_npm_downloads(client: &reqwest::blocking::Client, package: &str) -> Option<u64> {
-    let url = format!(
-        "https://api.npmjs.org/downloads/point/last-month/{}",
-        package
-    );
-    let resp = client.get(&url).send().ok()?;
-    if !resp.status().is_success() {
-        return None;
-    }
-    let data: serde_json::Value = resp.json().ok()?;
-    data.get("downloads").and_then(|v| v.as_u64())
-}
+impl ContainerRuntime {
+    /// Runtimes in the order they are tried when none is specified
+    const ALL: [ContainerRuntime; 3] = [
+        ContainerRuntime::Docker,
+        ContainerRuntime::Podman,
+        ContainerRuntime::Nerdctl,
+    ];
 
-/// Search npm registry and return matching packages
-fn search_npm(query: &str) -> Vec<PackageInfo> {
-    if !command_exists("npm") {
-        return vec![];
+    fn command(&self) -> &'static str {
+        match self {
+            ContainerRuntime::Docker => "docker",
+            ContainerRuntime::Podman => "podman",
+            ContainerRuntime::Nerdctl => "nerdctl",
+        }
     }
 
-    let output = Command::new("npm")
-        .args(["search", "--json", query])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .output();
-
-    let output = match output {
-        Ok(o) if o.status.success() => o,
-        _ => return vec![],
-    };
+    /// Pick the requested runtime, or the first one installed
+    fn detect(requested: Option<ContainerRuntime>) -> Result<Self> {
+        match requested {
+            Some(runtime) if command_exists(runtime.command()) => Ok(runtime),
+            Some(runtime) => Err(anyhow!(
+                "{} not found. Install it or choose another --container-runtime",
+                runtime.command()
+            )),
+            None => Self::ALL
+                .into_iter()
+                .find(|runtime| command_exists(runtime.command()))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "No container runtime found (tried docker, podman, nerdctl). {}",
+                        PackageType::Docker.install_instructions()
+                    )
+                }),
+        }
+    }
 
-    let results: serde_json::Value = match serde_json::from_slice(&output.stdout) {
-        Ok(v) => v,
-        Err(_) => return vec![],
-    };
+    /// Map an image reference to what this runtime expects. Podman doesn't
+    /// assume Docker Hub for short names, so they are fully qualified.
+    fn image_ref(&self, image: &str) -> String {
+        if *self != ContainerRuntime::Podman {
+            return image.to_string();
+        }
 
-    let client = reqwest::blocking::Client::builder()
-        .timeout(std::time::Duration::from_secs(5))
-        .build()
-        .ok();
-
-    let mut packages = vec![];
-    if let Some(arr) = results.as_array() {
-        for item in arr.iter().take(10) {
-            let name = item.get("name").and_then(|v| v.as_str()).unwrap_or("");
-            let version = item.get("version").and_then(|v| v.as_str()).unwrap_or("?");
-            let description = item
-                .get("description")
-                .and_then(|v| v.as_str())
-                .unwrap_or("No description");
-            let author = item
-                .get("publisher")
-                .and_then(|p| p.get("username"))
-                .and_then(|v| v.as_str())
-                .unwrap_or("Unknown");
-            let date = item.get("date").and_then(|v| v.as_str()).unwrap_or("Unknown");
-            let published = date.split('T').next().unwrap_or(date).to_string();
-
-            // Get download count
-            let downloads = client
-                .as_ref()
-                .and_then(|c| get_npm_downloads(c, name));
-
-            if !name.is_empty() {
-                packages.push(PackageInfo {
-                    name: name.to_string(),
-                    version: version.to_string(),
-                    description: description.to_string(),
-                    author: author.to_string(),
-                    published,
-                    downloads,
-                    registry: PackageType::Npm,
-                });
-            }
+        let first = image.split('/').next().unwrap_or(image);
+        let has_registry = image.contains('/')
+            && (first.contains('.') || first.contains(':') || first == "localhost");
+        if has_registry {
+            image.to_string()
+        } else if image.contains('/') {
+            format!("docker.io/{}", image)
+        } else {
+            format!("docker.io/library/{}", image)
         }
     }
-
-    packages
 }
 
-/// Get PyPI download count for a package (last month)
-fn get_pypi_downloads(client: &reqwest::blocking::Client, package: &str) -> Option<u64> {
-    let url = format!("https://pypistats.org/api/packages/{}/recent", package);
-    let resp = client.get(&url).send().ok()?;
-    if !resp.status().is_success() {
-        return None;
+/// Prefix selecting a container image package, e.g. `docker:ghcr.io/foo/mcp-server`
+const DOCKER_PREFIX: &str = "docker:";
+
+/// Prefix selecting a JSR package run with Deno, e.g. `jsr:@scope/mcp-server`
+const JSR_PREFIX: &str = "jsr:";
+
+/// Prefix selecting a raw Deno specifier, e.g. `deno:npm:mcp-server`
+const DENO_PREFIX: &str = "deno:";
+
+/// Split a container image into name and optional tag, e.g. `alpine:3.19`. A `:` before the
+/// last `/` belongs to a registry port, as in `localhost:5000/img`.
+fn split_image_tag(image: &str) -> (&str, Option<&str>) {
+    let name_start = image.rfind('/').map_or(0, |i| i + 1);
+    match image[name_start..].rfind(':') {
+        Some(i) if name_start + i + 1 < image.len() => (&image[..name_start + i], Some(&image[name_start + i + 1..])),
+        _ => (image, None),
     }
-    let data: serde_json::Value = resp.json().ok()?;
-    data.get("data")
-        .and_then(|d| d.get("last_month"))
-        .and_then(|v| v.as_u64())
 }
 
-/// Search PyPI registry and return matching packages
-fn search_pypi(query: &str) -> Vec<PackageInfo> {
-    // PyPI doesn't have a search API, so we'll check if the exact package exists
-    // and also try common variations
-    let variations = vec![
-        query.to_string(),
-        query.replace('-', "_"),
-        query.replace('_', "-"),
-    ];
+/// Split a `name@version` package spec into name and optional version.
+/// A scope `@` (at the start, or after a `prefix:`) is not treated as a version separator.
+fn split_version(spec: &str) -> (&str, Option<&str>) {
+    let name_start = spec.rfind('/').map_or(0, |i| i + 1);
+    match spec.rfind('@') {
+        Some(i) if i >= name_start
+            && i > 0
+            && !spec[..i].ends_with(':')
+            && i + 1 < spec.len() =>
+        {
+            (&spec[..i], Some(&spec[i + 1..]))
+        }
+        _ => (spec, None),
+    }
+}
 
-    let mut packages = vec![];
-    let client = match reqwest::blocking::Client::builder()
-        .timeout(std::time::Duration::from_secs(10))
-        .build()
-    {
-        Ok(c) => c,
-        Err(_) => return packages,
-    };
+/// Schema version written to the package cache. Bump it and add a step to
+/// `CACHE_MIGRATIONS` when a field changes shape.
+const CACHE_VERSION: u32 = 1;
 
-    for pkg_name in variations {
-        let url = format!("https://pypi.org/pypi/{}/json", pkg_name);
-        let resp = match client.get(&url).send() {
-            Ok(r) if r.status().is_success() => r,
-            _ => continue,
-        };
+/// Upgrades from schema version N to N + 1, indexed by N
+const CACHE_MIGRATIONS: [fn(&mut toml::Table); CACHE_VERSION as usize] = [migrate_cache_v0];
 
-        let data: serde_json::Value = match resp.json() {
-            Ok(v) => v,
-            Err(_) => continue,
-        };
+/// v0 (unversioned): rewrite `[name, type]` package entries as tables
+fn migrate_cache_v0(table: &mut toml::Table) {
+    let Some(packages) = table.get_mut("packages").and_then(|p| p.as_table_mut()) else {
+        return;
+    };
+    for (_, entry) in packages.iter_mut() {
+        if let Some([name, registry]) = entry.as_array().map(Vec::as_slice) {
+            let mut converted = toml::Table::new();
+            converted.insert("package".to_string(), name.clone());
+            converted.insert("registry".to_string(), registry.clone());
+            *entry = toml::Value::Table(converted);
+        }
+    }
+}
 
-        let info = match data.get("info") {
-            Some(i) => i,
-            None => continue,
-        };
+/// Package cache stored in ~/.cache/mcpz/package_mapping.toml
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct PackageCache {
+    /// Schema version; missing in caches written before versioning (v0)
+    #[serde(default)]
+    version: u32,
+    /// Maps search term -> resolved package
+    packages: HashMap<String, CacheEntry>,
+    /// Maps search term -> (actual package name, package type, pinned version)
+    #[serde(default)]
+    pinned: HashMap<String, (String, PackageType, String)>,
+    /// Maps search term -> environment saved with `run --save-env`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    env: HashMap<String, BTreeMap<String, String>>,
+    /// Maps search term -> default arguments (`pick --args`, `cache set-args`)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    args: HashMap<String, Vec<String>>,
+    /// Maps alias name -> package with default args and env (`mcpz alias add`)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    aliases: HashMap<String, Alias>,
+}
 
-        let name = info.get("name").and_then(|v| v.as_str()).unwrap_or("");
-        let version = info.get("version").and_then(|v| v.as_str()).unwrap_or("?");
-        let description = info
-            .get("summary")
-            .and_then(|v| v.as_str())
-            .unwrap_or("No description");
-        let author = info
-            .get("author")
-            .and_then(|v| v.as_str())
-            .filter(|s| !s.is_empty())
-            .or_else(|| {
-                info.get("author_email")
-                    .and_then(|v| v.as_str())
-                    .map(|s| s.split('<').next().unwrap_or(s).trim())
-            })
-            .unwrap_or("Unknown");
-
-        // Get upload time from releases
-        let published = data
-            .get("urls")
-            .and_then(|u| u.as_array())
-            .and_then(|arr| arr.first())
-            .and_then(|u| u.get("upload_time"))
-            .and_then(|v| v.as_str())
-            .map(|s| s.split('T').next().unwrap_or(s))
-            .unwrap_or("Unknown")
-            .to_string();
-
-        // Get download count
-        let downloads = get_pypi_downloads(&client, name);
-
-        if !name.is_empty() && !packages.iter().any(|p: &PackageInfo| p.name == name) {
-            packages.push(PackageInfo {
-                name: name.to_string(),
-                version: version.to_string(),
-                description: description.to_string(),
-                author: author.to_string(),
-                published,
-                downloads,
-                registry: PackageType::Python,
-            });
+/// A name that expands to a package plus default arguments and environment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Alias {
+    package: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    args: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    env: BTreeMap<String, String>,
+}
+
+impl PackageCache {
+    fn cache_path() -> Result<PathBuf> {
+        let cache_dir = dirs::cache_dir()
+            .ok_or_else(|| anyhow!("Could not determine cache directory"))?
+            .join("mcpz");
+        Ok(cache_dir.join("package_mapping.toml"))
+    }
+
+    fn load() -> Result<Self> {
+        Self::load_from(&Self::cache_path()?)
+    }
+
+    fn load_from(path: &std::path::Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(path).context("Failed to read cache file")?;
+        Self::parse(&content).with_context(|| format!("Failed to parse cache file {}", path.display()))
+    }
+
+    /// Parse a cache of any schema version up to `CACHE_VERSION`, migrating it in memory.
+    /// Newer caches are refused rather than read, so an older mcpz never rewrites them
+    /// without the fields it doesn't know about.
+    fn parse(content: &str) -> Result<Self> {
+        let mut table: toml::Table = toml::from_str(content)?;
+        let version = Self::schema_version(&table);
+        if version > CACHE_VERSION {
+            return Err(anyhow!(
+                "it uses schema v{}, but this mcpz only understands up to v{}; upgrade mcpz",
+                version,
+                CACHE_VERSION
+            ));
+        }
+        for migration in &CACHE_MIGRATIONS[version as usize..] {
+            migration(&mut table);
+        }
+        table.insert("version".to_string(), toml::Value::Integer(CACHE_VERSION.into()));
+        Ok(toml::Value::Table(table).try_into()?)
+    }
+
+    fn schema_version(table: &toml::Table) -> u32 {
+        table
+            .get("version")
+            .and_then(|v| v.as_integer())
+            .map_or(0, |v| u32::try_from(v).unwrap_or(u32::MAX))
+    }
+
+    /// `mcpz cache migrate`: rewrite an older cache at the current schema, keeping the
+    /// original as `package_mapping.toml.bak`. Returns the version migrated from, or
+    /// None when there was nothing to do.
+    fn migrate_at(path: &std::path::Path) -> Result<Option<u32>> {
+        let _lock = Self::lock(path)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path).context("Failed to read cache file")?;
+        let table: toml::Table = toml::from_str(&content).context("Failed to parse cache file")?;
+        let from = Self::schema_version(&table);
+        if from == CACHE_VERSION {
+            return Ok(None);
+        }
+
+        let cache = Self::parse(&content).with_context(|| format!("Failed to migrate {}", path.display()))?;
+        fs::copy(path, path.with_extension("toml.bak")).context("Failed to back up cache file")?;
+        cache.write(path)?;
+        Ok(Some(from))
+    }
+
+    /// Load, change and save the cache while holding its lock. The cache is re-read
+    /// under the lock, so concurrent mcpz processes add to each other's changes
+    /// instead of overwriting them with a stale copy.
+    fn update<T>(change: impl FnOnce(&mut Self) -> T) -> Result<T> {
+        Self::update_at(&Self::cache_path()?, change)
+    }
+
+    fn update_at<T>(path: &std::path::Path, change: impl FnOnce(&mut Self) -> T) -> Result<T> {
+        let _lock = Self::lock(path)?;
+        let mut cache = Self::load_from(path)?;
+        let result = change(&mut cache);
+        cache.write(path)?;
+        Ok(result)
+    }
+
+    /// Take the advisory lock guarding writes to `path`; released when the file is dropped
+    fn lock(path: &std::path::Path) -> Result<fs::File> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("Failed to create cache directory")?;
+        }
+        lock_beside(path).context("Failed to lock cache file")
+    }
+
+    /// Replace the cache file atomically, so readers never see a half-written cache
+    fn write(&self, path: &std::path::Path) -> Result<()> {
+        let content = toml::to_string_pretty(&Self {
+            version: CACHE_VERSION,
+            ..self.clone()
+        })
+        .context("Failed to serialize cache")?;
+        // Saved env may hold API keys; keep the file private to the user
+        let private = !self.env.is_empty() || self.aliases.values().any(|alias| !alias.env.is_empty());
+        write_atomically(path, &content, private).context("Failed to write cache file")
+    }
+
+    /// Every name the cache knows a package by: search terms, the packages they resolved to,
+    /// and aliases
+    fn names(&self) -> Vec<String> {
+        let names = self.packages.iter().flat_map(|(term, entry)| [term, &entry.package]);
+        let mut names: Vec<String> =
+            names.chain(self.pinned.keys()).chain(self.aliases.keys()).cloned().collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    fn get(&self, search_term: &str) -> Option<(String, PackageType)> {
+        self.packages
+            .get(search_term)
+            .map(|entry| (entry.package.clone(), entry.registry))
+    }
+
+    fn set(&mut self, search_term: String, package_name: String, pkg_type: PackageType) {
+        self.packages.insert(
+            search_term,
+            CacheEntry {
+                package: package_name,
+                registry: pkg_type,
+                cached_at: Some(SearchCache::now()),
+            },
+        );
+    }
+
+    /// Forget a search term's mapping, pinned version, saved env and default args; false if there was none
+    fn remove(&mut self, search_term: &str) -> bool {
+        let mapping = self.packages.remove(search_term).is_some();
+        let pinned = self.pinned.remove(search_term).is_some();
+        let env = self.env.remove(search_term).is_some();
+        let args = self.args.remove(search_term).is_some();
+        mapping || pinned || env || args
+    }
+
+    /// Save default arguments for a search term; an empty list clears them
+    fn set_args(&mut self, search_term: String, args: Vec<String>) {
+        if args.is_empty() {
+            self.args.remove(&search_term);
+        } else {
+            self.args.insert(search_term, args);
         }
     }
 
-    packages
+    /// Saved default arguments for a search term followed by `args`
+    fn args_for(&self, search_term: &str, args: &[String]) -> Vec<String> {
+        self.args.get(search_term).into_iter().flatten().chain(args).cloned().collect()
+    }
+
+    fn get_pinned(&self, search_term: &str) -> Option<(String, PackageType, String)> {
+        self.pinned.get(search_term).cloned()
+    }
+
+    fn set_pinned(&mut self, search_term: String, package_name: String, pkg_type: PackageType, version: String) {
+        self.pinned.insert(search_term, (package_name, pkg_type, version));
+    }
+
+    fn clear() -> Result<()> {
+        let path = Self::cache_path()?;
+        let _lock = Self::lock(&path)?;
+        if path.exists() {
+            fs::remove_file(&path).context("Failed to remove cache file")?;
+        }
+        Ok(())
+    }
 }
 
-/// Search crates.io registry and return matching packages (using API for full details)
-fn search_cargo(query: &str) -> Vec<PackageInfo> {
-    let client = match reqwest::blocking::Client::builder()
-        .timeout(std::time::Duration::from_secs(10))
-        .user_agent("mcpz")
-        .build()
-    {
-        Ok(c) => c,
-        Err(_) => return vec![],
-    };
+/// Take an advisory lock on `<path>.lock`, guarding a read-modify-write of `path` against
+/// other mcpz processes; released when the returned file is dropped
+fn lock_beside(path: &std::path::Path) -> std::io::Result<fs::File> {
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let file = fs::OpenOptions::new().create(true).truncate(false).write(true).open(lock_path)?;
+    file.lock()?;
+    Ok(file)
+}
 
-    // Use crates.io API for search
-    let url = format!(
-        "https://crates.io/api/v1/crates?q={}&per_page=10",
-        urlencoding::encode(query)
-    );
+/// Replace `path` with `content` through a temp file and a rename, so readers never see it
+/// half-written. A `private` file is readable by the user only.
+fn write_atomically(path: &std::path::Path, content: &str, private: bool) -> std::io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".{}.tmp", std::process::id()));
+    let tmp = PathBuf::from(tmp);
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+    let _ = fs::remove_file(&tmp);
+    options.open(&tmp).and_then(|mut file| file.write_all(content.as_bytes()))?;
+    fs::rename(&tmp, path)
+}
 
-    let resp = match client.get(&url).send() {
-        Ok(r) if r.status().is_success() => r,
-        _ => return vec![],
-    };
+/// A cached search term mapping
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "CacheEntryRepr")]
+struct CacheEntry {
+    package: String,
+    registry: PackageType,
+    /// Unix timestamp (seconds) when the mapping was cached; unknown for old caches
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cached_at: Option<u64>,
+}
 
-    let data: serde_json::Value = match resp.json() {
-        Ok(v) => v,
-        Err(_) => return vec![],
-    };
+/// On-disk forms of a cache entry: the current table, or the `[name, type]` pair
+/// written by earlier versions
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CacheEntryRepr {
+    Entry {
+        package: String,
+        registry: PackageType,
+        #[serde(default)]
+        cached_at: Option<u64>,
+    },
+    Legacy(String, PackageType),
+}
 
-    let mut packages = vec![];
-
-    if let Some(crates) = data.get("crates").and_then(|c| c.as_array()) {
-        for item in crates.iter().take(10) {
-            let name = item.get("name").and_then(|v| v.as_str()).unwrap_or("");
-            let version = item
-                .get("newest_version")
-                .and_then(|v| v.as_str())
-                .unwrap_or("?");
-            let description = item
-                .get("description")
-                .and_then(|v| v.as_str())
-                .unwrap_or("No description");
-            let downloads = item.get("downloads").and_then(|v| v.as_u64());
-            let updated = item
-                .get("updated_at")
-                .and_then(|v| v.as_str())
-                .map(|s| s.split('T').next().unwrap_or(s))
-                .unwrap_or("Unknown")
-                .to_string();
-
-            if !name.is_empty() {
-                packages.push(PackageInfo {
-                    name: name.to_string(),
-                    version: version.to_string(),
-                    description: description.to_string(),
-                    author: "See crates.io".to_string(),
-                    published: updated,
-                    downloads,
-                    registry: PackageType::Cargo,
-                });
-            }
+impl From<CacheEntryRepr> for CacheEntry {
+    fn from(repr: CacheEntryRepr) -> Self {
+        match repr {
+            CacheEntryRepr::Entry { package, registry, cached_at } => Self { package, registry, cached_at },
+            CacheEntryRepr::Legacy(package, registry) => Self { package, registry, cached_at: None },
+        }
+    }
+}
+
+/// Check if a command exists on the system
+pub fn command_exists(cmd: &str) -> bool {
+    let path = std::env::var_os("PATH");
+    find_command(cmd, path.as_deref(), &executable_extensions()).is_some()
+}
+
+/// Suffixes tried after a bare command name: none on Unix, PATHEXT on Windows
+fn executable_extensions() -> Vec<String> {
+    if !cfg!(windows) {
+        return Vec::new();
+    }
+    std::env::var("PATHEXT")
+        .unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string())
+        .split(';')
+        .filter(|ext| !ext.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Locate `cmd` in the directories listed in `path`, like `which`/`where.exe`.
+/// A name with a directory component is checked as given.
+fn find_command(cmd: &str, path: Option<&std::ffi::OsStr>, extensions: &[String]) -> Option<PathBuf> {
+    if Path::new(cmd).components().count() > 1 {
+        return with_extensions(PathBuf::from(cmd), extensions).into_iter().find(|p| is_executable_file(p));
+    }
+    std::env::split_paths(path?)
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .flat_map(|dir| with_extensions(dir.join(cmd), extensions))
+        .find(|p| is_executable_file(p))
+}
+
+fn with_extensions(base: PathBuf, extensions: &[String]) -> Vec<PathBuf> {
+    let mut candidates: Vec<PathBuf> = extensions
+        .iter()
+        .map(|ext| {
+            let mut name = base.clone().into_os_string();
+            name.push(ext);
+            PathBuf::from(name)
+        })
+        .collect();
+    candidates.insert(0, base);
+    candidates
+}
+
+#[cfg(unix)]
+fn is_executable_file(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path)
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable_file(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Options for registry searches made by `search` and `pick`
+#[derive(Debug, Clone)]
+struct SearchOptions {
+    /// Reuse cached results that are still fresh
+    use_cache: bool,
+    /// How long cached results stay fresh
+    ttl: std::time::Duration,
+    /// Hide packages that don't look like MCP servers
+    mcp_only: bool,
+    /// Stdout carries JSON for scripts, so progress and listings go to stderr
+    machine_output: bool,
+    /// Choose with the full-screen picker when attached to a terminal
+    tui: bool,
+    /// Results per registry, page and order
+    page: registry::SearchPage,
+}
+
+impl SearchOptions {
+    /// Print human-facing output, to stderr when stdout is reserved for machine-readable output
+    fn say(&self, text: &str) {
+        if self.machine_output {
+            eprintln!("{}", text);
+        } else {
+            println!("{}", text);
         }
     }
+}
 
-    packages
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            use_cache: true,
+            ttl: std::time::Duration::from_secs(3600),
+            mcp_only: false,
+            machine_output: false,
+            tui: false,
+            page: registry::SearchPage::default(),
+        }
+    }
 }
 
-/// Search all registries and let user pick a package
-fn search_and_select(query: &str) -> Result<Option<(String, PackageType)>> {
-    println!(
-        "{}",
+impl SearchOptions {
+    /// Search cache key: the query alone for the default page, so existing entries stay valid
+    fn cache_key(&self, query: &str) -> String {
+        if self.page == registry::SearchPage::default() {
+            query.to_string()
+        } else {
+            format!(
+                "{}?limit={}&page={}&sort={:?}",
+                query, self.page.limit, self.page.page, self.page.sort
+            )
+        }
+    }
+}
+
+/// Registry search results cached in ~/.cache/mcpz/search/
+#[derive(Debug, Serialize, Deserialize)]
+struct SearchCache {
+    /// Unix timestamp (seconds) when the results were fetched
+    fetched_at: u64,
+    packages: Vec<PackageInfo>,
+}
+
+impl SearchCache {
+    fn cache_dir() -> Result<PathBuf> {
+        Ok(dirs::cache_dir()
+            .ok_or_else(|| anyhow!("Could not determine cache directory"))?
+            .join("mcpz/search"))
+    }
+
+    /// Queries are hashed so scoped names like @scope/pkg make safe file names
+    fn path(query: &str) -> Result<PathBuf> {
+        let hash = hex::encode(Sha256::digest(query.as_bytes()));
+        Ok(Self::cache_dir()?.join(format!("{}.json", hash)))
+    }
+
+    fn now() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+
+    fn is_fresh(&self, ttl: std::time::Duration) -> bool {
+        Self::now().saturating_sub(self.fetched_at) < ttl.as_secs()
+    }
+
+    /// Load cached results for a query if present and younger than the TTL
+    fn load(query: &str, ttl: std::time::Duration) -> Option<Vec<PackageInfo>> {
+        let content = fs::read_to_string(Self::path(query).ok()?).ok()?;
+        let cached: SearchCache = serde_json::from_str(&content).ok()?;
+        cached.is_fresh(ttl).then_some(cached.packages)
+    }
+
+    fn save(query: &str, packages: &[PackageInfo]) -> Result<()> {
+        let path = Self::path(query)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("Failed to create search cache directory")?;
+        }
+
+        let cached = SearchCache {
+            fetched_at: Self::now(),
+            packages: packages.to_vec(),
+        };
+        fs::write(&path, serde_json::to_string(&cached)?).context("Failed to write search cache")?;
+        Ok(())
+    }
+
+    fn clear() -> Result<()> {
+        let dir = Self::cache_dir()?;
+        if dir.exists() {
+            fs::remove_dir_all(&dir).context("Failed to remove search cache")?;
+        }
+        Ok(())
+    }
+}
+
+/// Search every registry for a query, keeping only MCP servers with --mcp-only
+fn search_all_registries(query: &str, search_opts: &SearchOptions) -> Result<Vec<PackageInfo>> {
+    let mut packages = search_registries_cached(query, search_opts)?;
+    if search_opts.mcp_only {
+        let total = packages.len();
+        packages.retain(PackageInfo::is_mcp_server);
+        search_opts.say(&format!(
+            "  {} MCP server{} ({} other package{} hidden by --mcp-only)\n",
+            packages.len(),
+            if packages.len() == 1 { "" } else { "s" },
+            total - packages.len(),
+            if total - packages.len() == 1 { "" } else { "s" }
+        ));
+    }
+    Ok(packages)
+}
+
+/// Search every registry for a query, reusing fresh cached results when allowed
+fn search_registries_cached(query: &str, search_opts: &SearchOptions) -> Result<Vec<PackageInfo>> {
+    search_opts.say(&format!(
+        "{}\n",
         format!("Searching for '{}' across all registries...", query).cyan()
-    );
-    println!();
+    ));
+
+    if search_opts.use_cache {
+        if let Some(packages) = SearchCache::load(&search_opts.cache_key(query), search_opts.ttl) {
+            search_opts.say(&format!(
+                "  Using cached results ({} found, --no-cache to refresh)\n",
+                packages.len()
+            ));
+            return Ok(packages);
+        }
+    }
 
-    let mut all_packages = vec![];
-
-    // Search cargo first
-    print!("  Searching crates.io... ");
-    std::io::stdout().flush()?;
-    let cargo_results = search_cargo(query);
-    println!("{} found", cargo_results.len());
-    all_packages.extend(cargo_results);
-
-    // Search PyPI
-    print!("  Searching PyPI... ");
-    std::io::stdout().flush()?;
-    let pypi_results = search_pypi(query);
-    println!("{} found", pypi_results.len());
-    all_packages.extend(pypi_results);
-
-    // Search npm
-    print!("  Searching npm... ");
-    std::io::stdout().flush()?;
-    let npm_results = search_npm(query);
-    println!("{} found", npm_results.len());
-    all_packages.extend(npm_results);
+    search_opts.say("  Searching crates.io, PyPI, npm, JSR and the MCP registry...");
+    let results = registry::block_on(registry::search_all(query, search_opts.page))?;
+    for (label, found) in [
+        ("crates.io", results.cargo.len()),
+        ("PyPI", results.pypi.len()),
+        ("npm", results.npm.len()),
+        ("JSR", results.jsr.len()),
+        ("MCP registry", results.mcp.len()),
+    ] {
+        search_opts.say(&format!("    {}: {} found", label, found));
+    }
+    search_opts.say("");
 
-    println!();
+    let all_packages = results.into_all();
+
+    // Empty results are not cached since they usually mean a network failure
+    if !all_packages.is_empty() {
+        if let Err(e) = SearchCache::save(&search_opts.cache_key(query), &all_packages) {
+            eprintln!(
+                "{}",
+                format!("Warning: Failed to save search cache: {}", e).yellow()
+            );
+        }
+    }
+
+    Ok(all_packages)
+}
+
+/// Compare dotted version strings numerically; true if `candidate` is newer than `current`
+fn is_newer_version(candidate: &str, current: &str) -> bool {
+    let parse = |v: &str| -> Vec<u64> {
+        v.trim_start_matches('v')
+            .split(['.', '-', '+'])
+            .map_while(|part| part.parse().ok())
+            .collect()
+    };
+    let (a, b) = (parse(candidate), parse(current));
+    if a.is_empty() || b.is_empty() {
+        return candidate != current;
+    }
+    a > b
+}
+
+/// Versions of binaries installed with `cargo install`, keyed by crate name
+fn cargo_installed_versions() -> HashMap<String, String> {
+    let output = match Command::new("cargo").args(["install", "--list"]).output() {
+        Ok(o) if o.status.success() => o,
+        _ => return HashMap::new(),
+    };
+
+    // Crate lines look like "name v1.2.3:"; binaries are indented below them
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.starts_with(char::is_whitespace))
+        .filter_map(|line| {
+            let mut parts = line.trim_end_matches(':').split_whitespace();
+            let name = parts.next()?;
+            let version = parts.next()?.trim_start_matches('v');
+            Some((name.to_string(), version.to_string()))
+        })
+        .collect()
+}
+
+/// Search all registries and let user pick a package
+fn search_and_select(query: &str, search_opts: &SearchOptions) -> Result<Option<PackageInfo>> {
+    let mut all_packages = search_all_registries(query, search_opts)?;
 
     if all_packages.is_empty() {
-        println!(
-            "{}",
-            format!("No packages found matching '{}'", query).red()
-        );
+        search_opts.say(&format!("No packages found matching '{}'", query).red().to_string());
         return Ok(None);
     }
 
-    // Sort by popularity (most downloads first)
-    sort_by_popularity(&mut all_packages);
+    search_opts.page.sort.apply(&mut all_packages);
+
+    let index = if search_opts.tui && picker::available() {
+        picker::pick(&format!("Select a package for '{}'", query), &all_packages.iter().map(PackageInfo::pick_item).collect::<Vec<_>>())?
+    } else {
+        prompt_selection(&all_packages, search_opts)?
+    };
+    let Some(index) = index else {
+        return Ok(None);
+    };
 
+    let selected = all_packages.swap_remove(index);
+    search_opts.say(&format!(
+        "\n{}",
+        format!(
+            "Selected: {} ({}) from {}",
+            selected.name,
+            selected.version,
+            selected.registry.display_name()
+        )
+        .green()
+    ));
+
+    Ok(Some(selected))
+}
+
+/// List the packages and read a 1-based choice from stdin; returns the chosen index
+fn prompt_selection(all_packages: &[PackageInfo], search_opts: &SearchOptions) -> Result<Option<usize>> {
     // Display all packages
-    println!(
-        "{}",
-        format!("Found {} packages (sorted by popularity):", all_packages.len())
-            .green()
-            .bold()
-    );
-    println!();
+    search_opts.say(&format!(
+        "{}\n",
+        format!(
+            "Found {} packages (sorted {}):",
+            all_packages.len(),
+            search_opts.page.sort.describe()
+        )
+        .green()
+        .bold()
+    ));
 
     for (i, pkg) in all_packages.iter().enumerate() {
-        pkg.display(i);
+        search_opts.say(&pkg.render(i));
     }
 
     // Let user select
-    print!(
-        "{}",
-        "Select a package (1-{}) or 'q' to quit: "
-            .replace("{}", &all_packages.len().to_string())
-            .yellow()
-    );
-    std::io::stdout().flush()?;
+    let prompt = "Select a package (1-{}) or 'q' to quit: "
+        .replace("{}", &all_packages.len().to_string())
+        .yellow();
+    if search_opts.machine_output {
+        eprint!("{}", prompt);
+        std::io::stderr().flush()?;
+    } else {
+        print!("{}", prompt);
+        std::io::stdout().flush()?;
+    }
 
     let mut input = String::new();
     std::io::stdin().read_line(&mut input)?;
@@ -720,34 +1856,34 @@ fn search_and_select(query: &str) -> Result<Option<(String, PackageType)>> {
         return Err(anyhow!("Selection out of range"));
     }
 
-    let selected = &all_packages[selection - 1];
-    println!();
-    println!(
-        "{}",
-        format!(
-            "Selected: {} ({}) from {}",
-            selected.name,
-            selected.version,
-            selected.registry.display_name()
-        )
-        .green()
-    );
-
-    Ok(Some((selected.name.clone(), selected.registry)))
+    Ok(Some(selection - 1))
 }
 
 /// Discover package type by searching registries
 /// If multiple exact matches found, let user pick (unless pick_first is true)
 fn discover_package_type(package: &str, pick_first: bool) -> Result<(String, PackageType)> {
-    // npm scoped packages start with @ - skip other checks
+    let mut exact_matches: Vec<PackageInfo> = vec![];
+
+    // Scoped packages start with @ and only exist on npm and JSR
     if package.starts_with('@') {
-        println!("{}", format!("Checking npm for '{}'...", package).cyan());
-        let results = search_npm(package);
-        if results.iter().any(|p| p.name == package) {
-            println!("{}", format!("✓ Found in npm: {}", package).green());
-            return Ok((package.to_string(), PackageType::Npm));
+        println!("{}", format!("Checking npm and JSR for '{}'...", package).cyan());
+        // Exact npm lookup also finds private packages that the search API can't
+        let (npm_package, jsr_results) = registry::block_on(async {
+            tokio::join!(
+                registry::npm_package(package),
+                registry::search_jsr(package, registry::SearchPage::default())
+            )
+        })?;
+        if let Some(pkg) = npm_package.filter(|p| p.name == package) {
+            exact_matches.push(pkg);
         }
-        return Err(anyhow!("Package '{}' not found in npm", package));
+        if let Some(pkg) = jsr_results.into_iter().find(|p| p.name == package) {
+            exact_matches.push(pkg);
+        }
+        if exact_matches.is_empty() {
+            return Err(anyhow!("Package '{}' not found in npm or JSR", package));
+        }
+        return select_exact_match(package, exact_matches, pick_first);
     }
 
     // Search all registries to find exact matches
@@ -756,16 +1892,21 @@ fn discover_package_type(package: &str, pick_first: bool) -> Result<(String, Pac
         format!("Searching for '{}' across registries...", package).cyan()
     );
 
-    let mut exact_matches: Vec<PackageInfo> = vec![];
+    // Query the registries concurrently, then check each for an exact match
+    let (cargo_results, pypi_results, npm_results) = registry::block_on(async {
+        tokio::join!(
+            registry::search_cargo(package, registry::SearchPage::default()),
+            registry::pypi_packages(package),
+            registry::search_npm(package, registry::SearchPage::default())
+        )
+    })?;
 
     // Check cargo
-    let cargo_results = search_cargo(package);
     if let Some(pkg) = cargo_results.iter().find(|p| p.name == package) {
         exact_matches.push(pkg.clone());
     }
 
     // Check PyPI
-    let pypi_results = search_pypi(package);
     if let Some(pkg) = pypi_results.iter().find(|p| {
         p.name == package
             || p.name == package.replace('-', "_")
@@ -775,17 +1916,25 @@ fn discover_package_type(package: &str, pick_first: bool) -> Result<(String, Pac
     }
 
     // Check npm
-    let npm_results = search_npm(package);
     if let Some(pkg) = npm_results.iter().find(|p| p.name == package) {
         exact_matches.push(pkg.clone());
     }
 
+    select_exact_match(package, exact_matches, pick_first)
+}
+
+/// Choose among exact matches from different registries, prompting if there are several
+fn select_exact_match(
+    package: &str,
+    mut exact_matches: Vec<PackageInfo>,
+    pick_first: bool,
+) -> Result<(String, PackageType)> {
     // Sort by popularity (most downloads first)
     sort_by_popularity(&mut exact_matches);
 
     match exact_matches.len() {
         0 => Err(anyhow!(
-            "Package '{}' not found in any registry (crates.io, PyPI, npm)",
+            "Package '{}' not found in any registry (crates.io, PyPI, npm, JSR)",
             package
         )),
         1 => {
@@ -862,9 +2011,20 @@ fn discover_package_type(package: &str, pick_first: bool) -> Result<(String, Pac
     }
 }
 
+/// Fail fast for commands that need registry access when running offline
+fn ensure_online(offline: bool, what: &str) -> Result<()> {
+    if offline {
+        return Err(anyhow!(
+            "{} needs network access to query registries (--offline / MCPZ_OFFLINE is set)",
+            what
+        ));
+    }
+    Ok(())
+}
+
 /// Get package type, using cache if available
-fn get_package_type(package: &str, pick_first: bool) -> Result<(String, PackageType)> {
-    let mut cache = PackageCache::load().unwrap_or_default();
+fn get_package_type(package: &str, pick_first: bool, offline: bool) -> Result<(String, PackageType)> {
+    let cache = PackageCache::load().unwrap_or_default();
 
     // Check cache first
     if let Some((pkg_name, pkg_type)) = cache.get(package) {
@@ -881,12 +2041,19 @@ fn get_package_type(package: &str, pick_first: bool) -> Result<(String, PackageT
         return Ok((pkg_name, pkg_type));
     }
 
+    if offline {
+        return Err(anyhow!(
+            "'{}' is not in the package cache and --offline is set. Run 'mcpz pick {}' while online to cache it.",
+            package,
+            package
+        ));
+    }
+
     // Discover package type
     let (pkg_name, pkg_type) = discover_package_type(package, pick_first)?;
 
     // Save to cache
-    cache.set(package.to_string(), pkg_name.clone(), pkg_type);
-    if let Err(e) = cache.save() {
+    if let Err(e) = PackageCache::update(|cache| cache.set(package.to_string(), pkg_name.clone(), pkg_type)) {
         eprintln!(
             "{}",
             format!("Warning: Failed to save cache: {}", e).yellow()
@@ -912,8 +2079,16 @@ fn install_uv() -> Result<()> {
 
     println!("{}", "Installing uv...".cyan());
 
-    let status = Command::new("sh")
-        .args(["-c", "curl -LsSf https://astral.sh/uv/install.sh | sh"])
+    let mut installer = if cfg!(windows) {
+        let mut cmd = Command::new("powershell");
+        cmd.args(["-ExecutionPolicy", "ByPass", "-Command", "irm https://astral.sh/uv/install.ps1 | iex"]);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "curl -LsSf https://astral.sh/uv/install.sh | sh"]);
+        cmd
+    };
+    let status = installer
         .status()
         .context("Failed to install uv")?;
 
@@ -925,283 +2100,1490 @@ fn install_uv() -> Result<()> {
     Ok(())
 }
 
-/// Run an MCP server package
-fn run_package(package: &str, args: &[String], pick_first: bool) -> Result<()> {
-    let (pkg_name, pkg_type) = get_package_type(package, pick_first)?;
-    let runner = pkg_type.runner();
+/// Options controlling how `mcpz run` resolves a package
+#[derive(Debug, Default, Clone)]
+struct RunOptions {
+    /// Automatically pick the most popular match
+    first: bool,
+    /// Only run packages recorded in mcpz.lock
+    locked: bool,
+    /// Volumes mounted into docker: packages
+    volumes: Vec<String>,
+    /// Environment variables (KEY=VALUE, or KEY to pass through) for the package
+    env: Vec<String>,
+    /// Persist `env` for the package in the cache
+    save_env: bool,
+    /// Whether to restart the server after it crashes
+    restart: RestartPolicy,
+    /// Probe the server with an MCP handshake (within this timeout) before running it
+    health_check: Option<std::time::Duration>,
+    /// Runner override for npm packages
+    runner: Option<NpmRunner>,
+    /// Container runtime override for docker: packages
+    container_runtime: Option<ContainerRuntime>,
+    /// Only use cached mappings, never contact registries
+    offline: bool,
+    /// Check the package checksum before running
+    verify: bool,
+    /// Refuse packages that can't be verified
+    require_verified: bool,
+    /// Serve the package over HTTP instead of forwarding stdio
+    http: Option<HttpOptions>,
+    /// Copy the server's output to log files (`dir` is the base; run_package appends the package)
+    logs: Option<logs::LogOptions>,
+    /// Append the JSON-RPC session to this recording (created by the `run` command)
+    record: Option<PathBuf>,
+    /// Ping the stdio package servers a gateway starts this often, stopping those that don't answer
+    ping_interval: Option<std::time::Duration>,
+    /// Hold the gateway's clients to the letter of JSON-RPC and of the tools' schemas
+    strict: bool,
+}
 
-    // Check if runner exists
-    if !command_exists(runner) {
-        match pkg_type {
-            PackageType::Python => {
-                install_uv()?;
-                if !command_exists(runner) {
-                    return Err(anyhow!(
-                        "{} still not found after installation. You may need to restart your shell or add it to PATH.",
-                        runner
-                    ));
-                }
-            }
-            PackageType::Npm | PackageType::Cargo => {
-                return Err(anyhow!(
-                    "{} not found. {}",
-                    runner,
-                    pkg_type.install_instructions()
-                ));
-            }
+/// HTTP transport settings for `mcpz run --http`
+#[derive(Debug, Clone)]
+struct HttpOptions {
+    port: u16,
+    host: String,
+    tls: bool,
+    cert: Option<PathBuf>,
+    key: Option<PathBuf>,
+    origin: Option<String>,
+    quiet: bool,
+    rate_limit: Option<u32>,
+    auth: Option<http::AuthTokens>,
+    /// Gateway only: also serve each server on its own at /mcp/NAME
+    paths: bool,
+}
+
+/// When `mcpz run` restarts a server that exited
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum RestartPolicy {
+    #[default]
+    Never,
+    /// Restart after non-zero exits, at most `max` times (unlimited if None)
+    OnFailure { max: Option<u32> },
+}
+
+impl std::str::FromStr for RestartPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "no" => Ok(RestartPolicy::Never),
+            None if s == "on-failure" => Ok(RestartPolicy::OnFailure { max: None }),
+            Some(("on-failure", max)) => max
+                .parse()
+                .map(|max| RestartPolicy::OnFailure { max: Some(max) })
+                .map_err(|_| format!("invalid restart count '{}'", max)),
+            _ => Err(format!("invalid restart policy '{}' (expected no, on-failure or on-failure:MAX)", s)),
         }
     }
+}
 
-    // Handle Cargo packages differently - install first, then run the binary
-    if pkg_type == PackageType::Cargo {
-        return run_cargo_package(&pkg_name, args);
+impl RestartPolicy {
+    /// Runs that last this long reset the backoff, so a server that crashes once a day restarts quickly
+    const STABLE_RUN: std::time::Duration = std::time::Duration::from_secs(60);
+
+    /// Delay before the nth consecutive restart (1-based): 1s, 2s, 4s... capped at 30s
+    fn backoff(attempt: u32) -> std::time::Duration {
+        let secs = 1u64 << attempt.saturating_sub(1).min(5);
+        std::time::Duration::from_secs(secs.min(30))
     }
 
-    println!(
-        "{}",
-        format!(
-            "Running: {} {} {} {}",
-            runner,
-            if pkg_type == PackageType::Npm {
-                "-y"
-            } else {
-                ""
-            },
-            pkg_name,
-            args.join(" ")
-        )
-        .trim()
-        .cyan()
-    );
+    fn allows(&self, restarts: u32) -> bool {
+        match self {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure { max } => max.is_none_or(|max| restarts < max),
+        }
+    }
+}
 
-    let mut cmd = Command::new(runner);
+/// A lockfile entry recording exactly how a package was run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct LockedPackage {
+    package: String,
+    registry: PackageType,
+    version: String,
+    command: String,
+}
+
+/// Lockfile (mcpz.lock in the current directory) for reproducible MCP server sets
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Lockfile {
+    /// Maps search term -> locked package; BTreeMap keeps the file stable across writes
+    #[serde(default)]
+    packages: BTreeMap<String, LockedPackage>,
+}
 
-    if pkg_type == PackageType::Npm {
-        cmd.arg("-y");
+impl Lockfile {
+    const FILE_NAME: &'static str = "mcpz.lock";
+
+    fn path() -> Result<PathBuf> {
+        Ok(std::env::current_dir()?.join(Self::FILE_NAME))
     }
 
-    cmd.arg(&pkg_name);
-    cmd.args(args);
+    fn load() -> Result<Self> {
+        Self::load_from(&Self::path()?)
+    }
 
-    cmd.stdin(Stdio::inherit());
-    cmd.stdout(Stdio::piped());
-    cmd.stderr(Stdio::piped());
+    fn load_from(path: &std::path::Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
 
-    let mut child = cmd.spawn().context(format!("Failed to spawn {}", runner))?;
+        let content = fs::read_to_string(path).context("Failed to read mcpz.lock")?;
+        toml::from_str(&content).context("Failed to parse mcpz.lock")
+    }
 
-    if let Some(stdout) = child.stdout.take() {
-        let reader = BufReader::new(stdout);
-        std::thread::spawn(move || {
-            for line in reader.lines() {
-                if let Ok(line) = line {
-                    println!("{}", line);
-                }
-            }
-        });
+    /// Load, change and save the lockfile while holding its lock, as `PackageCache::update`
+    /// does, so concurrent `mcpz run`s keep each other's entries. Nothing is written unless
+    /// `change` returns true.
+    fn update_at(path: &std::path::Path, change: impl FnOnce(&mut Self) -> bool) -> Result<()> {
+        let _lock = lock_beside(path).context("Failed to lock mcpz.lock")?;
+        let mut lockfile = Self::load_from(path)?;
+        if change(&mut lockfile) {
+            lockfile.write(path)?;
+        }
+        Ok(())
     }
 
-    if let Some(stderr) = child.stderr.take() {
-        let reader = BufReader::new(stderr);
-        std::thread::spawn(move || {
-            for line in reader.lines() {
-                if let Ok(line) = line {
-                    eprintln!("{}", line.red());
-                }
-            }
-        });
+    fn write(&self, path: &std::path::Path) -> Result<()> {
+        let content = toml::to_string_pretty(self).context("Failed to serialize lockfile")?;
+        let content = format!(
+            "# This file is generated by `mcpz run`. Commit it to share an exact MCP setup.\n\n{}",
+            content
+        );
+        write_atomically(path, &content, false).context("Failed to write mcpz.lock")
     }
 
-    let status = child.wait().context("Failed to wait for child process")?;
+    fn get(&self, search_term: &str) -> Option<&LockedPackage> {
+        self.packages.get(search_term)
+    }
 
-    if !status.success() {
-        return Err(anyhow!("Process exited with status: {}", status));
+    fn set(&mut self, search_term: String, entry: LockedPackage) {
+        self.packages.insert(search_term, entry);
     }
+}
 
-    Ok(())
+/// Names of the packages in the cache, for completing gateway tools' `package` arguments
+fn cached_package_names() -> Vec<String> {
+    PackageCache::load().unwrap_or_default().names()
+}
+
+/// Record a run in mcpz.lock, warning instead of failing if it can't be written
+fn record_lock(search_term: &str, entry: LockedPackage) {
+    let result = Lockfile::path().and_then(|path| {
+        Lockfile::update_at(&path, |lockfile| {
+            if lockfile.get(search_term) == Some(&entry) {
+                return false;
+            }
+            lockfile.set(search_term.to_string(), entry);
+            true
+        })
+    });
+
+    if let Err(e) = result {
+        eprintln!(
+            "{}",
+            format!("Warning: Failed to update mcpz.lock: {}", e).yellow()
+        );
+    }
+}
+
+/// Remember the version a package was last run with
+fn save_pinned_version(package: &str, pkg_name: &str, pkg_type: PackageType, version: &str) {
+    let pin = |cache: &mut PackageCache| {
+        cache.set_pinned(package.to_string(), pkg_name.to_string(), pkg_type, version.to_string())
+    };
+    if let Err(e) = PackageCache::update(pin) {
+        eprintln!(
+            "{}",
+            format!("Warning: Failed to save cache: {}", e).yellow()
+        );
+    }
 }
 
-/// Run a Cargo package by installing it first, then running the binary
-fn run_cargo_package(package: &str, args: &[String]) -> Result<()> {
-    if !command_exists(package) {
+/// Resolve a package spec to (package name, type, requested version)
+fn resolve_package(spec: &str, opts: &RunOptions) -> Result<(String, PackageType, Option<String>)> {
+    let (package, version) = split_version(spec);
+
+    if opts.locked {
+        let lockfile = Lockfile::load()?;
+        let entry = lockfile.get(package).ok_or_else(|| {
+            anyhow!(
+                "'{}' is not in {} (refusing to resolve with --locked)",
+                package,
+                Lockfile::FILE_NAME
+            )
+        })?;
+        if let Some(version) = version {
+            if version != entry.version {
+                return Err(anyhow!(
+                    "'{}@{}' does not match locked version {} (--locked)",
+                    package,
+                    version,
+                    entry.version
+                ));
+            }
+        }
         println!(
             "{}",
-            format!("Installing cargo package '{}'...", package).cyan()
+            format!(
+                "Using locked package for '{}': {} v{} ({})",
+                package,
+                entry.package,
+                entry.version,
+                entry.registry.display_name()
+            )
+            .cyan()
         );
+        return Ok((entry.package.clone(), entry.registry, Some(entry.version.clone())));
+    }
+
+    // Container images and explicit Deno specifiers are run as-is, with no registry discovery
+    if let Some(image) = package.strip_prefix(DOCKER_PREFIX) {
+        // An `@digest` wins over a `:tag`, as it pins the image more tightly
+        let (image, tag) = split_image_tag(image);
+        return Ok((image.to_string(), PackageType::Docker, version.or(tag).map(|v| v.to_string())));
+    }
+    if let Some(name) = package.strip_prefix(JSR_PREFIX) {
+        return Ok((name.to_string(), PackageType::Deno, version.map(|v| v.to_string())));
+    }
+    if let Some(specifier) = package.strip_prefix(DENO_PREFIX) {
+        let name = specifier.strip_prefix(JSR_PREFIX).unwrap_or(specifier);
+        return Ok((name.to_string(), PackageType::Deno, version.map(|v| v.to_string())));
+    }
+
+    // A pinned run reuses the package it was pinned against, even if the
+    // unpinned mapping has since changed
+    let pinned = version.and_then(|v| {
+        PackageCache::load()
+            .unwrap_or_default()
+            .get_pinned(package)
+            .filter(|(_, _, pinned_version)| pinned_version == v)
+    });
+    if let Some((pkg_name, pkg_type, version)) = pinned {
+        return Ok((pkg_name, pkg_type, Some(version)));
+    }
 
-        let status = Command::new("cargo")
-            .args(["install", package])
-            .status()
-            .context("Failed to run cargo install")?;
+    let (pkg_name, pkg_type) = get_package_type(package, opts.first, opts.offline)?;
+    if let Some(version) = version {
+        save_pinned_version(package, &pkg_name, pkg_type, version);
+    }
+    Ok((pkg_name, pkg_type, version.map(|v| v.to_string())))
+}
 
-        if !status.success() {
-            return Err(anyhow!("Failed to install cargo package: {}", package));
+/// The exact version a resolved package runs at, so the lockfile can reproduce it: the one
+/// asked for, else an image's `latest` tag or the registry's latest release
+fn resolve_version(
+    pkg_name: &str,
+    pkg_type: PackageType,
+    version: Option<String>,
+    offline: bool,
+) -> Result<Option<String>> {
+    Ok(match pkg_type {
+        PackageType::Docker => version.or_else(|| Some("latest".to_string())),
+        // An already-installed crate keeps its version rather than being upgraded on every run
+        PackageType::Cargo if version.is_none() && command_exists(pkg_name) => {
+            cargo_installed_versions().get(pkg_name).cloned()
         }
+        _ if offline => version,
+        _ => match version {
+            Some(v) => Some(v),
+            None => registry::block_on(registry::latest_version(pkg_name, pkg_type))?,
+        },
+    })
+}
 
-        println!("{}", format!("✓ Installed {}", package).green());
+/// Run an MCP server package
+fn run_package(package: &str, args: &[String], opts: &RunOptions) -> Result<()> {
+    let mut opts = opts.clone();
+    if let Some(logs) = &mut opts.logs {
+        logs.dir = logs.dir.join(logs::package_dir_name(package));
+        eprintln!("{}", format!("[mcpz] Logging {} to {}", package, logs.dir.display()).cyan());
     }
+    let prepared = prepare_package(package, args, &opts)?;
+    spawn_and_forward(prepared.cmd, &prepared.name, &prepared.opts)
+}
 
-    println!(
-        "{}",
-        format!("Running: {} {}", package, args.join(" ")).cyan()
-    );
+/// A resolved package, ready to spawn
+struct PreparedPackage {
+    cmd: Command,
+    /// Name used in status and error messages
+    name: String,
+    /// Run options with the package's saved env merged in
+    opts: RunOptions,
+    /// Kept alive until the package exits: it may own a temporary .npmrc
+    _auth: credentials::RunnerAuth,
+}
 
-    let mut cmd = Command::new(package);
-    cmd.args(args);
+/// Resolve, verify and install a package (or alias), returning the command that runs it
+fn prepare_package(package: &str, args: &[String], opts: &RunOptions) -> Result<PreparedPackage> {
+    let Some(alias) = PackageCache::load().unwrap_or_default().aliases.remove(package) else {
+        return prepare_target(package, args, opts);
+    };
 
-    cmd.stdin(Stdio::inherit());
-    cmd.stdout(Stdio::piped());
-    cmd.stderr(Stdio::piped());
+    // Alias defaults come first so explicit arguments and --env can override them
+    let args: Vec<String> = alias.args.iter().chain(args).cloned().collect();
+    let mut opts = opts.clone();
+    opts.env = alias
+        .env
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .chain(opts.env)
+        .collect();
+    println!("{}", format!("Alias '{}' -> {}", package, alias.package).cyan());
+    prepare_target(&alias.package, &args, &opts)
+}
 
-    let mut child = cmd.spawn().context(format!("Failed to spawn {}", package))?;
+/// Prepare a package spec or local path (aliases already expanded)
+fn prepare_target(package: &str, args: &[String], opts: &RunOptions) -> Result<PreparedPackage> {
+    let search_term = split_version(package).0;
+    let opts = with_saved_env(search_term, opts)?;
+    let args = &PackageCache::load().unwrap_or_default().args_for(search_term, args);
 
-    if let Some(stdout) = child.stdout.take() {
-        let reader = BufReader::new(stdout);
-        std::thread::spawn(move || {
-            for line in reader.lines() {
-                if let Ok(line) = line {
-                    println!("{}", line);
-                }
-            }
-        });
+    if local::is_local_path(package) {
+        return prepare_local_package(package, args, opts);
     }
+    let opts = &opts;
+
+    let (pkg_name, pkg_type, version) = resolve_package(package, opts)?;
+    let container_runtime = match pkg_type {
+        PackageType::Docker => Some(ContainerRuntime::detect(opts.container_runtime)?),
+        _ => None,
+    };
+    let npm_runner = match pkg_type {
+        PackageType::Npm => Some(NpmRunner::detect(opts.runner)?),
+        _ => None,
+    };
+    let runner = match (container_runtime, npm_runner) {
+        (Some(runtime), _) => runtime.command(),
+        (_, Some(npm_runner)) => npm_runner.command(),
+        _ => pkg_type.runner(),
+    };
 
-    if let Some(stderr) = child.stderr.take() {
-        let reader = BufReader::new(stderr);
-        std::thread::spawn(move || {
-            for line in reader.lines() {
-                if let Ok(line) = line {
-                    eprintln!("{}", line.red());
+    // Check if runner exists
+    if !command_exists(runner) {
+        match pkg_type {
+            PackageType::Python => {
+                install_uv()?;
+                if !command_exists(runner) {
+                    return Err(anyhow!(
+                        "{} still not found after installation. You may need to restart your shell or add it to PATH.",
+                        runner
+                    ));
                 }
             }
+            PackageType::Npm | PackageType::Cargo | PackageType::Docker | PackageType::Deno => {
+                return Err(anyhow!(
+                    "{} not found. {}",
+                    runner,
+                    pkg_type.install_instructions()
+                ));
+            }
+        }
+    }
+
+    if !opts.volumes.is_empty() && pkg_type != PackageType::Docker {
+        return Err(anyhow!("--volume is only supported for docker: packages"));
+    }
+
+    let version = resolve_version(&pkg_name, pkg_type, version, opts.offline)?;
+
+    if opts.verify {
+        verify_package(&pkg_name, pkg_type, version.as_deref(), opts)?;
+    }
+
+    // Handle Cargo packages differently - install first, then run the binary
+    if pkg_type == PackageType::Cargo {
+        install_cargo_package(&pkg_name, version.as_deref())?;
+
+        let installed = cargo_installed_versions().get(&pkg_name).cloned().or(version);
+        if let Some(installed) = installed {
+            record_lock(search_term, LockedPackage {
+                package: pkg_name.clone(),
+                registry: pkg_type,
+                version: installed,
+                command: pkg_name.clone(),
+            });
+        }
+
+        let mut cmd = Command::new(&pkg_name);
+        cmd.args(args);
+        cmd.envs(parse_env_pairs(&opts.env));
+        println!(
+            "{}",
+            format!("Running: {} {}", pkg_name, args.join(" ")).cyan()
+        );
+        return Ok(PreparedPackage {
+            cmd,
+            name: pkg_name,
+            opts: opts.clone(),
+            _auth: credentials::RunnerAuth::default(),
         });
     }
 
-    let status = child.wait().context("Failed to wait for child process")?;
+    let mut spec = pkg_type.package_spec(&pkg_name, version.as_deref());
+    if let Some(runtime) = container_runtime {
+        spec = runtime.image_ref(&spec);
+    }
 
-    if !status.success() {
-        return Err(anyhow!("Process exited with status: {}", status));
+    let mut invocation = vec![runner.to_string()];
+    match pkg_type {
+        PackageType::Npm => {
+            let npm_runner = npm_runner.unwrap_or(NpmRunner::Npx);
+            invocation.extend(npm_runner.args().iter().map(|a| a.to_string()));
+        }
+        PackageType::Docker => invocation.extend(["run", "-i", "--rm"].map(String::from)),
+        PackageType::Deno => invocation.extend(["run", "-A"].map(String::from)),
+        PackageType::Python | PackageType::Cargo => {}
+    }
+    invocation.push(spec.clone());
+
+    if let Some(version) = version {
+        record_lock(search_term, LockedPackage {
+            package: pkg_name.clone(),
+            registry: pkg_type,
+            version,
+            command: invocation.join(" "),
+        });
     }
 
-    Ok(())
-}
+    // Volumes and env go before the image for docker; env values are kept out of the lockfile
+    if pkg_type == PackageType::Docker {
+        invocation.pop();
+        for volume in &opts.volumes {
+            invocation.extend(["-v".to_string(), volume.clone()]);
+        }
+        for env in &opts.env {
+            invocation.extend(["-e".to_string(), env.clone()]);
+        }
+        invocation.push(spec);
+    }
 
-/// Non-interactive search - just display results
-fn search_package(query: &str) -> Result<()> {
     println!(
         "{}",
-        format!("Searching for '{}' across all registries...", query).cyan()
+        format!("Running: {} {}", invocation.join(" "), args.join(" "))
+            .trim()
+            .cyan()
     );
-    println!();
 
-    let mut all_packages = vec![];
-
-    // Search cargo first
-    print!("  Searching crates.io... ");
-    std::io::stdout().flush()?;
-    let cargo_results = search_cargo(query);
-    println!("{} found", cargo_results.len());
-    all_packages.extend(cargo_results);
-
-    // Search PyPI
-    print!("  Searching PyPI... ");
-    std::io::stdout().flush()?;
-    let pypi_results = search_pypi(query);
-    println!("{} found", pypi_results.len());
-    all_packages.extend(pypi_results);
-
-    // Search npm
-    print!("  Searching npm... ");
-    std::io::stdout().flush()?;
-    let npm_results = search_npm(query);
-    println!("{} found", npm_results.len());
-    all_packages.extend(npm_results);
+    let mut cmd = Command::new(runner);
+    cmd.args(&invocation[1..]);
+    cmd.args(args);
+    let endpoints = registry::endpoints();
+    cmd.envs(endpoints.runner_env(pkg_type));
+    let runner_auth = match endpoints.runner_registry(&pkg_name, pkg_type) {
+        Some(registry_url) => credentials::RunnerAuth::prepare(pkg_type, registry_url)?,
+        None => credentials::RunnerAuth::default(),
+    };
+    cmd.envs(runner_auth.env.iter().map(|(k, v)| (k, v)));
+    if pkg_type != PackageType::Docker {
+        cmd.envs(parse_env_pairs(&opts.env));
+    }
+
+    Ok(PreparedPackage {
+        cmd,
+        name: runner.to_string(),
+        opts: opts.clone(),
+        _auth: runner_auth,
+    })
+}
 
-    println!();
+/// Check a package against its registry checksum, failing on mismatch (or when unverifiable with --require-verified)
+fn verify_package(pkg_name: &str, pkg_type: PackageType, version: Option<&str>, opts: &RunOptions) -> Result<()> {
+    let label = match version {
+        Some(v) => format!("{} {}", pkg_name, v),
+        None => pkg_name.to_string(),
+    };
 
-    if all_packages.is_empty() {
+    let verification = if opts.offline {
+        verify::Verification::Unverifiable("--offline prevents contacting the registry".to_string())
+    } else {
+        println!("{}", format!("Verifying {}...", label).cyan());
+        registry::block_on(verify::verify(pkg_name, pkg_type, version))??
+    };
+
+    match verification {
+        verify::Verification::Verified(detail) => {
+            println!("{}", format!("✓ Verified {} ({})", label, detail).green());
+            Ok(())
+        }
+        verify::Verification::Unverifiable(reason) if opts.require_verified => Err(anyhow!(
+            "Refusing to run {}: it could not be verified ({})",
+            label,
+            reason
+        )),
+        verify::Verification::Unverifiable(reason) => {
+            eprintln!(
+                "{}",
+                format!("Warning: Could not verify {}: {}", label, reason).yellow()
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Prepend the environment saved for a search term, saving this run's env first with --save-env
+fn with_saved_env(search_term: &str, opts: &RunOptions) -> Result<RunOptions> {
+    if opts.save_env {
+        let env: BTreeMap<String, String> = parse_env_pairs(&opts.env).into_iter().collect();
+        PackageCache::update(|cache| {
+            if env.is_empty() {
+                cache.env.remove(search_term);
+            } else {
+                cache.env.insert(search_term.to_string(), env);
+            }
+        })?;
         println!(
             "{}",
-            format!("No packages found matching '{}'", query).red()
+            format!("✓ Saved environment for '{}' ({})", search_term, PackageCache::cache_path()?.display()).green()
         );
-        return Ok(());
     }
 
-    // Sort by popularity (most downloads first)
-    sort_by_popularity(&mut all_packages);
+    let cache = PackageCache::load().unwrap_or_default();
+    let mut opts = opts.clone();
+    if let Some(saved) = cache.env.get(search_term) {
+        let mut env: Vec<String> = saved.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        env.append(&mut opts.env);
+        opts.env = env;
+    }
+    Ok(opts)
+}
+
+/// Read KEY=VALUE pairs from a dotenv file
+fn read_env_file(path: &std::path::Path) -> Result<Vec<String>> {
+    let entries = dotenvy::from_path_iter(path)
+        .with_context(|| format!("Failed to read env file {}", path.display()))?;
+    entries
+        .map(|entry| {
+            let (key, value) = entry.with_context(|| format!("Failed to parse env file {}", path.display()))?;
+            Ok(format!("{}={}", key, value))
+        })
+        .collect()
+}
+
+/// Parse KEY=VALUE pairs; bare KEYs are skipped since the child inherits them anyway
+fn parse_env_pairs(env: &[String]) -> Vec<(String, String)> {
+    env.iter()
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Build the command for an unpublished server from a local file or directory, bypassing registries and the lockfile
+fn prepare_local_package(path: &str, args: &[String], opts: RunOptions) -> Result<PreparedPackage> {
+    if !opts.volumes.is_empty() {
+        return Err(anyhow!("--volume is only supported for docker: packages"));
+    }
+    if opts.locked {
+        return Err(anyhow!("Local paths can't be run with --locked"));
+    }
+    if opts.require_verified {
+        return Err(anyhow!("Local paths can't be verified; drop --require-verified to run {}", path));
+    }
+
+    let invocation = local::local_invocation(path)?;
+    let program = &invocation[0];
+    if !command_exists(program) && !std::path::Path::new(program).is_absolute() {
+        return Err(anyhow!("{} not found; it is needed to run {}", program, path));
+    }
 
-    // Display all packages
     println!(
         "{}",
-        format!("Found {} packages (sorted by popularity):", all_packages.len())
-            .green()
-            .bold()
+        format!("Running: {} {}", invocation.join(" "), args.join(" "))
+            .trim()
+            .cyan()
     );
-    println!();
 
-    for (i, pkg) in all_packages.iter().enumerate() {
-        pkg.display(i);
-    }
-
-    Ok(())
+    let mut cmd = Command::new(program);
+    cmd.args(&invocation[1..]);
+    cmd.args(args);
+    cmd.envs(parse_env_pairs(&opts.env));
+
+    Ok(PreparedPackage {
+        cmd,
+        name: program.clone(),
+        opts,
+        _auth: credentials::RunnerAuth::default(),
+    })
 }
 
-/// Interactive pick - show results and let user pick one to save to cache
-fn pick_package(query: &str) -> Result<()> {
-    let selection = search_and_select(query)?;
-
-    if let Some((pkg_name, pkg_type)) = selection {
-        // Ask if user wants to save to cache
-        print!(
+/// Spawn a package process (after an optional health check), restarting it on failure as the policy allows
+fn spawn_and_forward(cmd: Command, name: &str, opts: &RunOptions) -> Result<()> {
+    if let Some(http) = &opts.http {
+        return serve_over_http(cmd, name, http, opts.health_check.unwrap_or(DEFAULT_STARTUP_TIMEOUT));
+    }
+    if let Some(timeout) = opts.health_check {
+        println!("{}", format!("Checking {} responds to MCP requests...", name).cyan());
+        let report = client::health_check(copy_command(&cmd), timeout)
+            .with_context(|| format!("{} failed the MCP health check", name))?;
+        println!(
             "{}",
             format!(
-                "Save '{}' -> {} ({}) to cache for future runs? [Y/n]: ",
-                query,
-                pkg_name,
-                pkg_type.display_name()
+                "✓ {} v{} is healthy ({} tools)",
+                report.server_name, report.server_version, report.tool_count
             )
-            .yellow()
+            .green()
         );
-        std::io::stdout().flush()?;
+    }
 
-        let mut input = String::new();
-        std::io::stdin().read_line(&mut input)?;
-        let input = input.trim();
+    signals::install();
+    let restart = opts.restart;
+    let mut restarts = 0;
+    let mut consecutive = 0;
 
-        if input.is_empty() || input.eq_ignore_ascii_case("y") {
-            let mut cache = PackageCache::load().unwrap_or_default();
-            cache.set(query.to_string(), pkg_name.clone(), pkg_type);
-            cache.save()?;
-            println!("{}", "✓ Saved to cache".green());
+    loop {
+        let started = std::time::Instant::now();
+        let result = spawn_once(copy_command(&cmd), name, opts.logs.as_ref(), opts.record.as_deref());
+        let Err(err) = result else {
+            return Ok(());
+        };
+        // A server stopped by a signal sent to mcpz isn't restarted
+        if !restart.allows(restarts) || signals::stop_requested().is_some() {
+            return Err(err);
         }
 
-        // Ask if user wants to run it now
-        print!("{}", "Run it now? [y/N]: ".yellow());
-        std::io::stdout().flush()?;
-
-        let mut input = String::new();
-        std::io::stdin().read_line(&mut input)?;
-        let input = input.trim();
-
-        if input.eq_ignore_ascii_case("y") {
-            run_package(&pkg_name, &[], false)?;
+        if started.elapsed() >= RestartPolicy::STABLE_RUN {
+            consecutive = 0;
+        }
+        restarts += 1;
+        consecutive += 1;
+        let delay = RestartPolicy::backoff(consecutive);
+        eprintln!(
+            "{}",
+            format!("[mcpz] {}; restarting {} in {}s (restart {})", err, name, delay.as_secs(), restarts).yellow()
+        );
+        if !signals::sleep_unless_stopped(delay) {
+            return Err(err);
         }
     }
+}
 
-    Ok(())
+/// How long `mcpz run --http` waits for the package to finish its MCP handshake
+const DEFAULT_STARTUP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Spawn a package and expose it over the HTTP transport, shared by all sessions
+fn serve_over_http(cmd: Command, name: &str, http: &HttpOptions, startup_timeout: std::time::Duration) -> Result<()> {
+    let server = start_proxy(cmd, name, startup_timeout)?;
+    serve_http(server, http)
 }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+/// Spawn a package and complete its MCP handshake
+fn start_proxy(cmd: Command, name: &str, startup_timeout: std::time::Duration) -> Result<servers::proxy::ProxyServer> {
+    eprintln!("{}", format!("[mcpz] Starting {}...", name).cyan());
+    servers::proxy::ProxyServer::start(cmd, startup_timeout, false, false)
+        .with_context(|| format!("{} failed to start", name))
+}
 
-    match cli.command {
-        Commands::Run { package, first, args } => run_package(&package, &args, first),
-        Commands::Search { package } => search_package(&package),
-        Commands::Pick { package } => pick_package(&package),
-        Commands::ClearCache => {
-            PackageCache::clear()?;
+/// Serve an MCP server over the HTTP transport until interrupted
+fn serve_http<S: servers::common::McpServer + Send + Sync + 'static>(server: S, http: &HttpOptions) -> Result<()> {
+    serve_http_with_paths(server, vec![], http)
+}
+
+/// Serve `server` at /mcp and each of `paths` at /mcp/NAME on the same port
+fn serve_http_with_paths<S: servers::common::McpServer + Send + Sync + 'static>(
+    server: S,
+    paths: Vec<(String, S)>,
+    http: &HttpOptions,
+) -> Result<()> {
+    let host_addr: IpAddr = http.host.parse().context("Invalid host address")?;
+    let http_config = http::HttpServerConfig::new(
+        http.port,
+        host_addr,
+        http.tls,
+        http.cert.clone(),
+        http.key.clone(),
+        http.origin.clone(),
+        false,
+    )
+    .with_quiet(http.quiet)
+    .with_rate_limit(http.rate_limit)
+    .with_auth(http.auth.clone());
+    let rt = tokio::runtime::Runtime::new()?;
+    rt.block_on(http::run_http_server_with_paths(server, paths, http_config))
+}
+
+/// Run several packages behind one MCP endpoint (stdio, or HTTP with --http), namespacing their tools
+fn run_packages(packages: &[String], opts: &RunOptions) -> Result<()> {
+    let specs: Vec<gateway::ServerSpec> = packages
+        .iter()
+        .map(|package| gateway::ServerSpec {
+            name: tool_prefix(package),
+            upstream: gateway::Upstream::package(package),
+            tools: Default::default(),
+            middleware: vec![],
+            rate_limit: None,
+            replicas: 1,
+            circuit_breaker: Default::default(),
+            cache: Default::default(),
+            max_result_size: None,
+            affinity: None,
+        })
+        .collect();
+    run_gateway(
+        &specs,
+        opts,
+        opts.health_check.unwrap_or(DEFAULT_STARTUP_TIMEOUT),
+        false,
+        None,
+        None,
+        BTreeMap::new(),
+    )
+}
+
+/// Start the gateway's servers and serve them as one (stdio, or HTTP with --http), writing
+/// their tool calls to `audit` if given
+fn run_gateway(
+    specs: &[gateway::ServerSpec],
+    opts: &RunOptions,
+    timeout: std::time::Duration,
+    verbose: bool,
+    watch: Option<(PathBuf, gateway::LoadSpecs)>,
+    audit: Option<AuditLog>,
+    prompts: BTreeMap<String, PromptTemplate>,
+) -> Result<()> {
+    use servers::common::McpServer;
+    let mut gateway = gateway::start(specs, opts, timeout, verbose)?;
+    let serve = |server, audit| gateway_server(server, &prompts, audit, opts.strict);
+    let router = serve(gateway.router.clone(), audit.clone());
+    let endpoints = match &opts.http {
+        Some(http) if http.paths => gateway
+            .endpoints()?
+            .into_iter()
+            .map(|(name, endpoint)| (name, serve(endpoint, audit.clone())))
+            .collect(),
+        _ => vec![],
+    };
+    eprintln!(
+        "{}",
+        format!("[mcpz] Serving {} tools from {} servers", router.tools().len(), specs.len()).green()
+    );
+    if let Some((path, load)) = watch {
+        gateway.watch(path, load);
+    }
+
+    match &opts.http {
+        Some(http) => serve_http_with_paths(router, endpoints, http),
+        None => {
+            let recorder = opts.record.as_deref().map(record::Recorder::append).transpose()?;
+            router.run(recorder.as_ref())
+        }
+    }
+}
+
+/// The wrappers the gateway serves its router (or one server's endpoint) in: `--strict`
+/// checks, the audit log, and the gateway's own prompts
+fn gateway_server<S: servers::common::McpServer>(
+    server: S,
+    prompts: &BTreeMap<String, PromptTemplate>,
+    audit: Option<AuditLog>,
+    strict: bool,
+) -> Strict<Audited<Prompted<S>>> {
+    Strict::new(Audited::new(Prompted::new(server, prompts.clone()), audit), strict)
+}
+
+/// Remove `flag` from trailing arguments, returning whether it was present
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let before = args.len();
+    args.retain(|a| a != flag);
+    args.len() != before
+}
+
+/// Namespace for a package's tools with --prefix-tools (e.g. `@scope/server-fs@1.0` -> `server-fs`)
+fn tool_prefix(spec: &str) -> String {
+    let mut name = split_version(spec).0;
+    // Drop registry prefixes such as docker:, jsr: and deno:npm:
+    while let Some((scheme, rest)) = name.split_once(':') {
+        if scheme.is_empty() || !scheme.chars().all(|c| c.is_ascii_alphabetic()) || rest.is_empty() {
+            break;
+        }
+        name = rest;
+    }
+    let base = name.trim_end_matches('/').rsplit('/').next().unwrap_or(name);
+    let base = base.split(':').next().unwrap_or(base);
+    let base = if local::is_local_path(spec) {
+        std::path::Path::new(base).file_stem().and_then(|s| s.to_str()).unwrap_or(base)
+    } else {
+        base
+    };
+
+    base.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+/// Rebuild a command with the same program, arguments, environment and directory
+fn copy_command(cmd: &Command) -> Command {
+    let mut copy = Command::new(cmd.get_program());
+    copy.args(cmd.get_args());
+    for (key, value) in cmd.get_envs() {
+        match value {
+            Some(value) => copy.env(key, value),
+            None => copy.env_remove(key),
+        };
+    }
+    if let Some(dir) = cmd.get_current_dir() {
+        copy.current_dir(dir);
+    }
+    copy
+}
+
+/// Spawn a package process once and wait for it to exit. The child inherits our stdio,
+/// so JSON-RPC bytes reach the client untouched (no re-framing of long or partial
+/// lines) and its stderr logs stay separate from the protocol stream. With `logs` or
+/// `record`, the streams are teed through `logs::spawn_tapped` instead.
+/// Stop signals sent to mcpz are forwarded to the child (see `signals::wait`).
+fn spawn_once(mut cmd: Command, name: &str, logs: Option<&logs::LogOptions>, record: Option<&Path>) -> Result<()> {
+    signals::isolate(&mut cmd);
+    let status = match (logs, record) {
+        (None, None) => {
+            cmd.stdin(Stdio::inherit());
+            cmd.stdout(Stdio::inherit());
+            cmd.stderr(Stdio::inherit());
+            let mut child = cmd.spawn().context(format!("Failed to spawn {}", name))?;
+            signals::wait(&mut child, name, signals::STOP_GRACE).context(format!("Failed to wait for {}", name))?
+        }
+        (logs, record) => {
+            let recorder = record.map(record::Recorder::append).transpose()?;
+            logs::spawn_tapped(cmd, name, logs, recorder.map(std::sync::Arc::new))?
+        }
+    };
+
+    if !status.success() {
+        return Err(signals::ChildExit { status }.into());
+    }
+
+    Ok(())
+}
+
+/// Install a Cargo package unless the binary (or the requested version) is already installed
+fn install_cargo_package(package: &str, version: Option<&str>) -> Result<()> {
+    let needs_install = match version {
+        Some(v) => cargo_installed_versions().get(package).map(String::as_str) != Some(v),
+        None => !command_exists(package),
+    };
+    if !needs_install {
+        return Ok(());
+    }
+
+    println!(
+        "{}",
+        format!("Installing cargo package '{}'...", package).cyan()
+    );
+
+    let mut install = Command::new("cargo");
+    install.args(["install", package]);
+    if let Some(version) = version {
+        install.args(["--version", version]);
+    }
+
+    let status = install
+        .status()
+        .context("Failed to run cargo install")?;
+
+    if !status.success() {
+        return Err(anyhow!("Failed to install cargo package: {}", package));
+    }
+
+    println!("{}", format!("✓ Installed {}", package).green());
+    Ok(())
+}
+
+/// Resolve a package, cache its mapping and fetch it into the runner's cache without running it
+fn install_package(package: &str, opts: &RunOptions) -> Result<()> {
+    let alias = PackageCache::load().unwrap_or_default().aliases.remove(package);
+    let package = alias.as_ref().map_or(package, |alias| alias.package.as_str());
+    if local::is_local_path(package) {
+        return Err(anyhow!("Local paths don't need installing; run them with mcpz run {}", package));
+    }
+
+    let (pkg_name, pkg_type, version) = resolve_package(package, opts)?;
+    let spec = pkg_type.package_spec(&pkg_name, version.as_deref());
+
+    let mut cmd = match pkg_type {
+        PackageType::Cargo => {
+            if !command_exists("cargo") {
+                return Err(anyhow!("cargo not found. {}", pkg_type.install_instructions()));
+            }
+            install_cargo_package(&pkg_name, version.as_deref())?;
+            println!("{}", format!("✓ {} is ready; run it with mcpz run {}", pkg_name, package).green());
+            return Ok(());
+        }
+        PackageType::Npm => {
+            if !command_exists("npm") {
+                return Err(anyhow!("npm not found (needed to fill the npm cache). {}", pkg_type.install_instructions()));
+            }
+            let mut cmd = Command::new("npm");
+            cmd.args(["cache", "add", &spec]);
+            cmd
+        }
+        PackageType::Python => {
+            if !command_exists("uv") {
+                install_uv()?;
+            }
+            let mut cmd = Command::new("uv");
+            cmd.args(["tool", "install", &spec]);
+            cmd
+        }
+        PackageType::Docker => {
+            let runtime = ContainerRuntime::detect(opts.container_runtime)?;
+            let mut cmd = Command::new(runtime.command());
+            cmd.args(["pull", &runtime.image_ref(&spec)]);
+            cmd
+        }
+        PackageType::Deno => {
+            if !command_exists("deno") {
+                return Err(anyhow!("deno not found. {}", pkg_type.install_instructions()));
+            }
+            let mut cmd = Command::new("deno");
+            cmd.args(["cache", &spec]);
+            cmd
+        }
+    };
+
+    println!(
+        "{}",
+        format!("Installing {} ({})...", spec, pkg_type.display_name()).cyan()
+    );
+    let endpoints = registry::endpoints();
+    cmd.envs(endpoints.runner_env(pkg_type));
+    let runner_auth = match endpoints.runner_registry(&pkg_name, pkg_type) {
+        Some(registry_url) => credentials::RunnerAuth::prepare(pkg_type, registry_url)?,
+        None => credentials::RunnerAuth::default(),
+    };
+    cmd.envs(runner_auth.env.iter().map(|(k, v)| (k, v)));
+
+    let status = cmd.status().context("Failed to run installer")?;
+    if !status.success() {
+        return Err(anyhow!("Failed to install {}", spec));
+    }
+
+    println!("{}", format!("✓ {} is ready; run it with mcpz run {}", spec, package).green());
+    Ok(())
+}
+
+/// Non-interactive search - just display results
+fn search_package(query: &str, search_opts: &SearchOptions) -> Result<()> {
+    let mut all_packages = search_all_registries(query, search_opts)?;
+
+    search_opts.page.sort.apply(&mut all_packages);
+
+    if search_opts.machine_output {
+        println!("{}", serde_json::to_string_pretty(&all_packages)?);
+        return Ok(());
+    }
+
+    if all_packages.is_empty() {
+        println!(
+            "{}",
+            format!("No packages found matching '{}'", query).red()
+        );
+        return Ok(());
+    }
+
+    // Display all packages
+    println!(
+        "{}",
+        format!(
+            "Found {} packages (sorted {}):",
+            all_packages.len(),
+            search_opts.page.sort.describe()
+        )
+        .green()
+        .bold()
+    );
+    println!();
+
+    for (i, pkg) in all_packages.iter().enumerate() {
+        pkg.display(i);
+    }
+
+    Ok(())
+}
+
+/// Interactive pick - show results and let user pick one to save to cache
+fn pick_package(query: &str, search_opts: &SearchOptions, args: &[String]) -> Result<()> {
+    let selection = search_and_select(query, search_opts)?;
+
+    // --print hands the choice to a script instead of caching or running it
+    if search_opts.machine_output {
+        if let Some(selected) = selection {
+            println!("{}", serde_json::to_string_pretty(&selected)?);
+        }
+        return Ok(());
+    }
+
+    if let Some(PackageInfo { name: pkg_name, registry: pkg_type, .. }) = selection {
+        // Ask if user wants to save to cache
+        print!(
+            "{}",
+            format!(
+                "Save '{}' -> {} ({}) to cache for future runs? [Y/n]: ",
+                query,
+                pkg_name,
+                pkg_type.display_name()
+            )
+            .yellow()
+        );
+        std::io::stdout().flush()?;
+
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input)?;
+        let input = input.trim();
+
+        if input.is_empty() || input.eq_ignore_ascii_case("y") {
+            PackageCache::update(|cache| {
+                cache.set(query.to_string(), pkg_name.clone(), pkg_type);
+                if !args.is_empty() {
+                    cache.set_args(query.to_string(), args.to_vec());
+                }
+            })?;
+            println!("{}", "✓ Saved to cache".green());
+        }
+
+        // Ask if user wants to run it now
+        print!("{}", "Run it now? [y/N]: ".yellow());
+        std::io::stdout().flush()?;
+
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input)?;
+        let input = input.trim();
+
+        if input.eq_ignore_ascii_case("y") {
+            run_package(&pkg_name, args, &RunOptions::default())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Re-resolve every cached package and report (or apply) available updates
+fn update_packages(upgrade: bool) -> Result<()> {
+    let cache = PackageCache::load().unwrap_or_default();
+    if cache.packages.is_empty() && cache.pinned.is_empty() {
+        println!("  (no cached packages)");
+        return Ok(());
+    }
+
+    let installed = if command_exists("cargo") {
+        cargo_installed_versions()
+    } else {
+        HashMap::new()
+    };
+
+    println!("{}", "Checking cached packages for updates...".cyan());
+    println!();
+
+    let mut entries: Vec<_> = cache.packages.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    let mut missing = 0;
+    let mut outdated = 0;
+
+    // Look up every package's latest version concurrently
+    let latest_versions = registry::block_on(futures::future::join_all(
+        entries
+            .iter()
+            .map(|(_, entry)| registry::latest_version(&entry.package, entry.registry)),
+    ))?;
+
+    for ((search_term, entry), latest) in entries.into_iter().zip(latest_versions) {
+        let (package_name, pkg_type) = (&entry.package, &entry.registry);
+        let Some(latest) = latest else {
+            missing += 1;
+            println!(
+                "  {} -> {} ({}): {}",
+                search_term.cyan(),
+                package_name,
+                pkg_type.display_name(),
+                "not found in registry".red()
+            );
+            continue;
+        };
+
+        let current = match pkg_type {
+            PackageType::Cargo => installed.get(package_name).cloned(),
+            // npx/uvx fetch the latest version on each run
+            PackageType::Npm | PackageType::Python | PackageType::Docker | PackageType::Deno => None,
+        };
+
+        match current {
+            Some(current) if is_newer_version(&latest, &current) => {
+                outdated += 1;
+                println!(
+                    "  {} -> {} ({}): {}",
+                    search_term.cyan(),
+                    package_name,
+                    pkg_type.display_name(),
+                    format!("v{} installed, v{} available", current, latest).yellow()
+                );
+
+                if upgrade {
+                    let status = Command::new("cargo")
+                        .args(["install", package_name])
+                        .status()
+                        .context("Failed to run cargo install")?;
+                    if status.success() {
+                        println!("    {}", format!("✓ Upgraded to v{}", latest).green());
+                    } else {
+                        println!("    {}", format!("Failed to upgrade {}", package_name).red());
+                    }
+                }
+            }
+            Some(current) => println!(
+                "  {} -> {} ({}): v{} {}",
+                search_term.cyan(),
+                package_name,
+                pkg_type.display_name(),
+                current,
+                "(up to date)".green()
+            ),
+            None => println!(
+                "  {} -> {} ({}): latest v{}",
+                search_term.cyan(),
+                package_name,
+                pkg_type.display_name(),
+                latest
+            ),
+        }
+
+        if let Some((_, _, pinned)) = cache.get_pinned(search_term) {
+            if is_newer_version(&latest, &pinned) {
+                println!(
+                    "    {}",
+                    format!("pinned to v{}, v{} available (run {}@{})", pinned, latest, search_term, latest)
+                        .yellow()
+                );
+            }
+        }
+    }
+
+    println!();
+    if missing > 0 {
+        println!(
+            "{}",
+            format!("{} package(s) could not be resolved; use 'mcpz pick' to choose a replacement", missing)
+                .yellow()
+        );
+    }
+    if outdated > 0 && !upgrade {
+        println!(
+            "{}",
+            format!("{} cargo package(s) out of date; run 'mcpz update --upgrade' to upgrade", outdated)
+                .yellow()
+        );
+    } else if missing == 0 && outdated == 0 {
+        println!("{}", "✓ All cached packages resolved".green());
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let offline = cli.offline;
+    let config = cli.config;
+    let profile = cli.profile;
+    credentials::init(cli.registry_token, &registry::endpoints().token_registries());
+
+    match cli.command {
+        Commands::Run {
+            package,
+            first,
+            locked,
+            volumes,
+            env,
+            env_files,
+            save_env,
+            restart,
+            health_check,
+            health_timeout,
+            runner,
+            container_runtime,
+            verify,
+            require_verified,
+            http,
+            port,
+            host,
+            tls,
+            cert,
+            key,
+            origin,
+            quiet,
+            rate_limit,
+            auth_token,
+            auth_tokens_file,
+            log_dir,
+            log_traffic,
+            record,
+            prefix_tools,
+            mut args,
+        } => {
+            // `mcpz run a b --prefix-tools` leaves the flag among the trailing arguments
+            let prefix_tools = take_flag(&mut args, "--prefix-tools") || prefix_tools;
+            if http && restart != RestartPolicy::Never {
+                return Err(anyhow!("--restart isn't supported with --http"));
+            }
+            if prefix_tools && restart != RestartPolicy::Never {
+                return Err(anyhow!("--restart isn't supported with --prefix-tools"));
+            }
+            if log_dir.is_some() && (http || prefix_tools) {
+                return Err(anyhow!("--log-dir is only supported for a single package over stdio"));
+            }
+            if record.is_some() && http {
+                return Err(anyhow!("--record is only supported over stdio"));
+            }
+            if let Some(path) = &record {
+                // Start with an empty file; each (re)spawn appends to it
+                record::Recorder::create(path)?;
+            }
+            let logs = match log_dir {
+                Some(dir) => Some(logs::LogOptions {
+                    dir: dir.map_or_else(logs::default_log_dir, Ok)?,
+                    traffic: log_traffic,
+                }),
+                None => None,
+            };
+            // Later sources win: env files in order, then --env
+            let mut all_env = vec![];
+            for path in &env_files {
+                all_env.extend(read_env_file(path)?);
+            }
+            all_env.extend(env);
+            let opts = RunOptions {
+                first,
+                locked,
+                volumes,
+                env: all_env,
+                save_env,
+                restart,
+                health_check: health_check.then(|| std::time::Duration::from_secs(health_timeout)),
+                runner,
+                container_runtime,
+                offline,
+                verify: verify || require_verified,
+                require_verified,
+                http: if http {
+                    let auth = http::AuthTokens::load(auth_token, auth_tokens_file.as_deref())?;
+                    Some(HttpOptions { port, host, tls, cert, key, origin, quiet, rate_limit, auth, paths: false })
+                } else {
+                    None
+                },
+                logs,
+                record,
+                ping_interval: None,
+                strict: false,
+            };
+            if prefix_tools {
+                let packages: Vec<String> = std::iter::once(package).chain(args).collect();
+                run_packages(&packages, &opts)
+            } else {
+                exit_like_child(run_package(&package, &args, &opts))
+            }
+        }
+        Commands::Install { package, first, container_runtime } => {
+            ensure_online(offline, "install")?;
+            let opts = RunOptions {
+                first,
+                container_runtime,
+                ..Default::default()
+            };
+            install_package(&package, &opts)
+        }
+        Commands::Search { package, no_cache, cache_ttl, mcp_only, json, limit, page, sort } => {
+            ensure_online(offline, "search")?;
+            let search_opts = SearchOptions {
+                use_cache: !no_cache,
+                ttl: std::time::Duration::from_secs(cache_ttl),
+                mcp_only,
+                machine_output: json,
+                tui: false,
+                page: registry::SearchPage {
+                    limit: limit.into(),
+                    page: page.into(),
+                    sort,
+                },
+            };
+            search_package(&package, &search_opts)
+        }
+        Commands::Pick { package, no_cache, cache_ttl, mcp_only, print, no_tui, limit, page, sort, args } => {
+            ensure_online(offline, "pick")?;
+            let search_opts = SearchOptions {
+                use_cache: !no_cache,
+                ttl: std::time::Duration::from_secs(cache_ttl),
+                mcp_only,
+                machine_output: print,
+                tui: !no_tui,
+                page: registry::SearchPage {
+                    limit: limit.into(),
+                    page: page.into(),
+                    sort,
+                },
+            };
+            let args = match args {
+                Some(args) => shlex::split(&args).ok_or_else(|| anyhow!("Unbalanced quotes in --args: {}", args))?,
+                None => vec![],
+            };
+            pick_package(&package, &search_opts, &args)
+        }
+        Commands::ClearCache => {
+            PackageCache::clear()?;
+            SearchCache::clear()?;
             println!("{}", "✓ Cache cleared".green());
             Ok(())
         }
+        Commands::Cache { action } => manage_cache(action),
+        Commands::Alias { action } => manage_aliases(action),
+        Commands::Gateway {
+            servers,
+            first,
+            timeout,
+            verbose,
+            http,
+            port,
+            host,
+            tls,
+            cert,
+            key,
+            origin,
+            quiet,
+            rate_limit,
+            max_result_size,
+            ping_interval,
+            auth_token,
+            auth_tokens_file,
+            paths,
+            record,
+            audit_log,
+            audit_redact,
+            strict,
+        } => {
+            let given = servers
+                .iter()
+                .map(|server| gateway::ServerSpec::parse(server))
+                .collect::<Result<Vec<_>>>()?;
+            let (file, settings, named) = load_config(config.as_deref(), profile.as_deref(), given.len())?;
+            let prompts = settings.prompts()?;
+            // Servers without their own rate_limit and max_result_size get the flags', or the [gateway] ones
+            let default_limit = rate_limit.or(settings.gateway.rate_limit);
+            let default_size = max_result_size.or(settings.gateway.max_result_size);
+            let limit = move |mut spec: gateway::ServerSpec| {
+                spec.rate_limit = spec.rate_limit.or(default_limit);
+                spec.max_result_size = spec.max_result_size.or(default_size);
+                spec
+            };
+            let specs: Vec<_> = given.iter().cloned().chain(named.into_iter().map(|server| server.spec)).map(limit).collect();
+
+            // Follow edits to the config file when its servers are being served
+            let watch = (given.is_empty() || config.is_some() || profile.is_some()).then(|| {
+                let load: gateway::LoadSpecs = Box::new(move || {
+                    let (_, _, named) = load_config(config.as_deref(), profile.as_deref(), given.len())?;
+                    Ok(given.iter().cloned().chain(named.into_iter().map(|server| server.spec)).map(limit).collect())
+                });
+                (file, load)
+            });
+
+            let settings = settings.gateway;
+            let http = http || settings.transport == Some(config::Transport::Http);
+            if http && record.is_some() {
+                return Err(anyhow!("--record only works with the stdio transport"));
+            }
+            let paths = paths || settings.paths == Some(true);
+            if paths && !http {
+                return Err(anyhow!("--paths only works with the HTTP transport"));
+            }
+            if let Some(path) = &record {
+                record::Recorder::create(path)?;
+            }
+            let port = port.or(settings.port).unwrap_or(3000);
+            let host = host.or(settings.host).unwrap_or_else(|| "127.0.0.1".to_string());
+            let timeout = timeout.or(settings.timeout).unwrap_or(60);
+            let ping_interval = ping_interval.or(settings.ping_interval).filter(|secs| *secs > 0);
+            let opts = RunOptions {
+                first,
+                offline,
+                // The gateway limits each server's calls itself
+                http: if http {
+                    let auth = http::AuthTokens::load(auth_token, auth_tokens_file.as_deref())?;
+                    Some(HttpOptions { port, host, tls, cert, key, origin, quiet, rate_limit: None, auth, paths })
+                } else {
+                    None
+                },
+                record,
+                ping_interval: ping_interval.map(std::time::Duration::from_secs),
+                strict: strict || settings.strict == Some(true),
+                ..Default::default()
+            };
+            let audit = audit_log.as_deref().map(|path| AuditLog::open(path, audit_redact)).transpose()?;
+            run_gateway(&specs, &opts, std::time::Duration::from_secs(timeout), verbose, watch, audit, prompts)
+        }
+        Commands::Call { server, tool, args, json, first, env, timeout } => {
+            let spec = call::resolve(&server, &load_profile(config.as_deref(), profile.as_deref())?)?;
+            let opts = RunOptions {
+                first,
+                offline,
+                env,
+                ..Default::default()
+            };
+            call::call(&spec, &tool, &args, json, &opts, std::time::Duration::from_secs(timeout))
+        }
+        Commands::Tools { server, url, json, first, env, timeout } => {
+            let spec = match (server, url) {
+                (_, Some(url)) if !url.starts_with("http://") && !url.starts_with("https://") => {
+                    return Err(anyhow!("--url must start with http:// or https://: {}", url));
+                }
+                (_, Some(url)) => gateway::ServerSpec::parse(&url)?,
+                (Some(server), None) => call::resolve(&server, &load_profile(config.as_deref(), profile.as_deref())?)?,
+                (None, None) => unreachable!("clap requires a server or --url"),
+            };
+            let opts = RunOptions {
+                first,
+                offline,
+                env,
+                ..Default::default()
+            };
+            call::list_tools(&spec, json, &opts, std::time::Duration::from_secs(timeout))
+        }
+        Commands::Inspect { server, json, baseline, first, env, timeout } => {
+            let spec = call::resolve(&server, &load_profile(config.as_deref(), profile.as_deref())?)?;
+            let opts = RunOptions {
+                first,
+                offline,
+                env,
+                ..Default::default()
+            };
+            inspect::inspect(&spec, json, baseline.as_deref(), &opts, std::time::Duration::from_secs(timeout))
+        }
+        Commands::Connect { url, token, timeout, verbose } => {
+            connect::connect(&url, token, std::time::Duration::from_secs(timeout), verbose)
+        }
+        Commands::Replay { session, target, timeout, args } => {
+            let entries = record::load(&session)?;
+            let opts = RunOptions {
+                first: true,
+                offline,
+                ..Default::default()
+            };
+            let prepared = prepare_package(&target, &args, &opts)?;
+            println!(
+                "{}",
+                format!("Replaying {} ({} messages) against {}...", session.display(), entries.len(), prepared.name).cyan()
+            );
+            let summary = record::replay(&entries, prepared.cmd, std::time::Duration::from_secs(timeout))?;
+            if summary.differed == 0 {
+                println!("{}", format!("✓ All {} responses matched the recording", summary.requests).green());
+            } else {
+                println!(
+                    "{}",
+                    format!("{} of {} responses differed from the recording", summary.differed, summary.requests).yellow()
+                );
+            }
+            Ok(())
+        }
+        Commands::Update { upgrade } => {
+            ensure_online(offline, "update")?;
+            update_packages(upgrade)
+        }
         Commands::Server { list, server_type } => {
             if list || server_type.is_none() {
                 print_server_list();
@@ -1223,7 +3605,16 @@ fn main() -> Result<()> {
                     cert,
                     key,
                     origin,
+                    quiet,
+                    rate_limit,
+                    auth_token,
+                    auth_tokens_file,
+                    record,
+                    audit_log,
+                    audit_redact,
+                    strict,
                 } => {
+                    let audit = audit_log.as_deref().map(|path| AuditLog::open(path, audit_redact)).transpose()?;
                     let shell_config = ShellServerConfig::new(
                         working_dir,
                         timeout,
@@ -1247,17 +3638,28 @@ fn main() -> Result<()> {
                             key,
                             origin,
                             verbose,
-                        );
-                        let server = ShellServer::new(shell_config);
+                        )
+                        .with_quiet(quiet)
+                        .with_rate_limit(rate_limit)
+                        .with_auth(http::AuthTokens::load(auth_token, auth_tokens_file.as_deref())?);
+                        let server = Strict::new(Audited::new(ShellServer::new(shell_config), audit), strict);
                         let rt = tokio::runtime::Runtime::new()?;
                         rt.block_on(http::run_http_server(server, http_config))
                     } else {
                         // stdio transport
-                        servers::run_shell_server(shell_config)
+                        let recorder = record.as_deref().map(record::Recorder::create).transpose()?;
+                        servers::run_shell_server(shell_config, recorder.as_ref(), audit, strict)
                     }
                 }
                 ServerType::Filesystem {
                     allowed_directories,
+                    scratch_dir,
+                    client_roots,
+                    allow_delete,
+                    allow_chmod,
+                    no_gitignore,
+                    follow_symlinks,
+                    exclude,
                     verbose,
                     http,
                     port,
@@ -1266,14 +3668,30 @@ fn main() -> Result<()> {
                     cert,
                     key,
                     origin,
+                    quiet,
+                    rate_limit,
+                    auth_token,
+                    auth_tokens_file,
+                    record,
+                    audit_log,
+                    audit_redact,
+                    strict,
                 } => {
+                    let audit = audit_log.as_deref().map(|path| AuditLog::open(path, audit_redact)).transpose()?;
                     // Default to current directory if none specified
                     let dirs = if allowed_directories.is_empty() {
                         vec![std::env::current_dir()?]
                     } else {
                         allowed_directories
                     };
-                    let fs_config = FilesystemServerConfig::new(dirs, verbose)?;
+                    let fs_config = FilesystemServerConfig::new(dirs, verbose)?
+                        .with_scratch_root(scratch_dir)?
+                        .with_client_roots(client_roots)
+                        .with_allow_delete(allow_delete)
+                        .with_allow_chmod(allow_chmod)
+                        .with_respect_gitignore(!no_gitignore)
+                        .with_follow_symlinks(follow_symlinks)
+                        .with_exclude(exclude)?;
 
                     if http {
                         // HTTP transport
@@ -1288,13 +3706,17 @@ fn main() -> Result<()> {
                             key,
                             origin,
                             verbose,
-                        );
-                        let server = FilesystemServer::new(fs_config);
+                        )
+                        .with_quiet(quiet)
+                        .with_rate_limit(rate_limit)
+                        .with_auth(http::AuthTokens::load(auth_token, auth_tokens_file.as_deref())?);
+                        let server = Strict::new(Audited::new(FilesystemServer::new(fs_config), audit), strict);
                         let rt = tokio::runtime::Runtime::new()?;
                         rt.block_on(http::run_http_server(server, http_config))
                     } else {
                         // stdio transport
-                        servers::run_filesystem_server(fs_config)
+                        let recorder = record.as_deref().map(record::Recorder::create).transpose()?;
+                        servers::run_filesystem_server(fs_config, recorder.as_ref(), audit, strict)
                     }
                 }
                 ServerType::Sql {
@@ -1310,7 +3732,16 @@ fn main() -> Result<()> {
                     cert,
                     key,
                     origin,
+                    quiet,
+                    rate_limit,
+                    auth_token,
+                    auth_tokens_file,
+                    record,
+                    audit_log,
+                    audit_redact,
+                    strict,
                 } => {
+                    let audit = audit_log.as_deref().map(|path| AuditLog::open(path, audit_redact)).transpose()?;
                     let access_mode = if readonly {
                         AccessMode::ReadOnly
                     } else {
@@ -1328,8 +3759,8 @@ fn main() -> Result<()> {
                         // HTTP transport
                         use servers::sql::SqlServer;
 
-                        // Connect using native driver
-                        let rt = tokio::runtime::Runtime::new()?;
+                        // Connect using native driver, on the runtime that then serves HTTP
+                        let rt = std::sync::Arc::new(tokio::runtime::Runtime::new()?);
                         let pool = rt.block_on(connect_database(
                             &connection,
                             db_type,
@@ -1346,22 +3777,176 @@ fn main() -> Result<()> {
                             key,
                             origin,
                             verbose,
-                        );
+                        )
+                        .with_quiet(quiet)
+                        .with_rate_limit(rate_limit)
+                        .with_auth(http::AuthTokens::load(auth_token, auth_tokens_file.as_deref())?);
 
-                        let server = SqlServer::new(sql_config, pool, rt);
-                        let rt2 = tokio::runtime::Runtime::new()?;
-                        rt2.block_on(http::run_http_server(server, http_config))
+                        let server = SqlServer::new(sql_config, pool, rt.clone());
+                        let server = Strict::new(Audited::new(server, audit), strict);
+                        rt.block_on(http::run_http_server(server, http_config))
                     } else {
                         // stdio transport
-                        servers::run_sql_server(sql_config)
+                        let recorder = record.as_deref().map(record::Recorder::create).transpose()?;
+                        servers::run_sql_server(sql_config, recorder.as_ref(), audit, strict)
                     }
                 }
             }
         }
-        Commands::List => {
-            print_full_list()?;
+        Commands::List { json } => {
+            if json {
+                let cache = PackageCache::load().unwrap_or_default();
+                println!("{}", serde_json::to_string_pretty(&list_json(&cache))?);
+            } else {
+                print_full_list()?;
+            }
             Ok(())
         }
+        command @ (Commands::Daemon { .. } | Commands::Ps | Commands::Stop { .. } | Commands::Logs { .. }) => {
+            run_daemon_command(command, config.as_deref(), profile.as_deref())
+        }
+    }
+}
+
+/// Exit with a failed package's own exit code (128 + N if signal N killed it)
+/// rather than the generic 1, so supervisors see what the server did
+fn exit_like_child(result: Result<()>) -> Result<()> {
+    if let Err(err) = &result {
+        if let Some(exit) = err.downcast_ref::<signals::ChildExit>() {
+            eprintln!("{}", format!("[mcpz] {}", err).red());
+            std::process::exit(exit.code());
+        }
+    }
+    result
+}
+
+/// Load the config file, narrowed to `profile` if one is selected
+fn load_profile(path: Option<&Path>, profile: Option<&str>) -> Result<config::Config> {
+    let (file, config) = config::Config::load(path)?;
+    match profile {
+        Some(profile) => config.select_profile(profile).with_context(|| format!("In {}", file.display())),
+        None => Ok(config),
+    }
+}
+
+/// Load the config file (narrowed to `profile`) and pick its servers: all of them when none
+/// were given on the command line, and in addition to those with --config or --profile
+fn load_config(
+    path: Option<&Path>,
+    profile: Option<&str>,
+    given: usize,
+) -> Result<(PathBuf, config::Config, Vec<config::NamedServer>)> {
+    let file = match path {
+        Some(path) => path.to_path_buf(),
+        None => config::default_path()?,
+    };
+    let config = load_profile(path, profile)?;
+    let servers = if given == 0 || path.is_some() || profile.is_some() { config.servers()? } else { vec![] };
+    if given == 0 && servers.is_empty() {
+        return Err(anyhow!(
+            "No servers given; pass them as arguments or define [servers] in {}",
+            file.display()
+        ));
+    }
+    Ok((file, config, servers))
+}
+
+/// Handle `daemon`, `ps`, `stop` and `logs`
+#[cfg(unix)]
+fn run_daemon_command(command: Commands, config: Option<&Path>, profile: Option<&str>) -> Result<()> {
+    match command {
+        Commands::Daemon { packages, port, restart } => {
+            let (_, _, named) = load_config(config, profile, packages.len())?;
+            // Servers without a configured port take the next free one from --port up
+            let taken: std::collections::HashSet<u16> = named.iter().filter_map(|server| server.port).collect();
+            let mut next_port = port;
+            let mut assign = |fixed: Option<u16>| -> Result<u16> {
+                if let Some(fixed) = fixed {
+                    return Ok(fixed);
+                }
+                while taken.contains(&next_port) {
+                    next_port = next_port.checked_add(1).ok_or_else(|| anyhow!("Ran out of ports after {}", port))?;
+                }
+                let assigned = next_port;
+                next_port = next_port.checked_add(1).ok_or_else(|| anyhow!("Ran out of ports after {}", port))?;
+                Ok(assigned)
+            };
+
+            let mut specs = vec![];
+            for package in &packages {
+                specs.push(daemon::ServiceSpec::parse(package, assign(None)?)?);
+            }
+            for server in &named {
+                if matches!(server.spec.upstream, gateway::Upstream::Remote(_)) {
+                    eprintln!(
+                        "{}",
+                        format!("[mcpz] Skipping {}: remote servers are already running elsewhere", server.spec.name).yellow()
+                    );
+                    continue;
+                }
+                specs.push(daemon::ServiceSpec::from_server(&server.spec, assign(server.port)?, server.restart)?);
+            }
+            daemon::run(specs, restart)
+        }
+        Commands::Ps => {
+            let response = daemon::send(&daemon::Request::Ps)?;
+            print_daemon_services(&response.services);
+            Ok(())
+        }
+        Commands::Stop { name } => {
+            daemon::send(&daemon::Request::Stop { name: name.clone() })?;
+            println!("{}", format!("Stopped {}", name).green());
+            Ok(())
+        }
+        Commands::Logs { name, lines } => {
+            let response = daemon::send(&daemon::Request::Logs { name, lines })?;
+            for line in response.lines {
+                println!("{}", line);
+            }
+            Ok(())
+        }
+        _ => unreachable!("not a daemon command"),
+    }
+}
+
+/// The daemon talks to `ps`/`stop`/`logs` over a Unix domain socket
+#[cfg(not(unix))]
+fn run_daemon_command(_command: Commands, _config: Option<&Path>, _profile: Option<&str>) -> Result<()> {
+    Err(anyhow!("The daemon and its ps/stop/logs commands are only supported on Unix"))
+}
+
+/// Print `mcpz ps` output
+#[cfg(unix)]
+fn print_daemon_services(services: &[daemon::ServiceStatus]) {
+    if services.is_empty() {
+        println!("{}", "No servers running".yellow());
+        return;
+    }
+    println!(
+        "{}",
+        format!(
+            "{:<20} {:<11} {:>8} {:>8} {:>7}  {}",
+            "NAME", "STATE", "PID", "RESTARTS", "UPTIME", "ENDPOINT"
+        )
+        .bold()
+    );
+    for service in services {
+        let state = format!("{:<11}", format!("{:?}", service.state).to_lowercase());
+        let state = match service.state {
+            daemon::ServiceState::Running => state.green(),
+            daemon::ServiceState::Restarting => state.yellow(),
+            daemon::ServiceState::Stopped => state.normal(),
+            daemon::ServiceState::Failed => state.red(),
+        };
+        println!(
+            "{:<20} {} {:>8} {:>8} {:>7}  {}",
+            service.name,
+            state,
+            service.pid.map(|p| p.to_string()).unwrap_or_else(|| "-".to_string()),
+            service.restarts,
+            service.uptime_secs.map(daemon::format_uptime).unwrap_or_else(|| "-".to_string()),
+            service.endpoint
+        );
     }
 }
 
@@ -1374,17 +3959,25 @@ fn print_server_list() {
     println!("    Server Options:");
     println!("      -w, --working-dir <PATH>  Working directory");
     println!("      -t, --timeout <SECONDS>   Command timeout (default: 30)");
-    println!("      -s, --shell <PATH>        Shell to use (default: /bin/sh)");
+    println!("      -s, --shell <PATH>        Shell to use (default: {})", DEFAULT_SHELL);
     println!("      --allow <PATTERNS>        Allow only matching commands");
     println!("      --deny <PATTERNS>         Deny matching commands");
     println!("      --no-stderr               Suppress stderr in output");
     println!("      -v, --verbose             Enable debug logging");
+    println!("      --record <FILE>           Record the session (see mcpz replay)");
     println!();
     println!("  {} - Filesystem operations", "filesystem".cyan());
     println!("    Usage: mcpz server filesystem [OPTIONS]");
     println!("    Server Options:");
-    println!("      -d, --dir <PATH>          Allowed directory (default: current dir, can repeat)");
+    println!("      -d, --dir <PATH[:ro|:rw]> Allowed directory (default: current dir, can repeat; :ro for read-only)");
+    println!("      --scratch-dir <PATH>      Root for create_temp_dir (default: first allowed dir)");
+    println!("      --allow-delete            Offer delete_file and delete_directory");
+    println!("      --allow-chmod             Offer set_permissions");
+    println!("      --no-gitignore            Don't skip .gitignored paths in trees and searches");
+    println!("      --follow-symlinks <WHEN>  never, within-allowed (default) or always");
+    println!("      --exclude <PATTERNS>      Hide matching paths from every tool (comma-separated globs)");
     println!("      -v, --verbose             Enable debug logging");
+    println!("      --record <FILE>           Record the session (see mcpz replay)");
     println!();
     println!("  {} - SQL database queries", "sql".cyan());
     println!("    Usage: mcpz server sql --connection <URL> --readonly|--fullaccess");
@@ -1394,6 +3987,7 @@ fn print_server_list() {
     println!("      --fullaccess              Allow all SQL statements");
     println!("      -t, --timeout <SECONDS>   Query timeout (default: 30)");
     println!("      -v, --verbose             Enable debug logging");
+    println!("      --record <FILE>           Record the session (see mcpz replay)");
     println!("    Supported databases: PostgreSQL, MySQL, MariaDB, SQLite");
     println!();
     println!("{}", "HTTP Transport Options (add to any server):".yellow().bold());
@@ -1404,6 +3998,7 @@ fn print_server_list() {
     println!("      --cert <PATH>             TLS certificate path (use with --key)");
     println!("      --key <PATH>              TLS private key path (use with --cert)");
     println!("      --origin <ORIGINS>        Allowed CORS origins (comma-separated)");
+    println!("      -q, --quiet               Suppress the startup banner");
     println!();
     println!("{}", "Examples:".green());
     println!("  mcpz server shell                         # stdio transport");
@@ -1420,183 +4015,1554 @@ fn print_server_list() {
     println!("Run 'mcpz server <SERVER> --help' for more details.");
 }
 
-/// Print full list of cached packages and available servers
-fn print_full_list() -> Result<()> {
-    // Print cached package mappings
-    println!("{}", "Cached package mappings:".green().bold());
-    println!();
+/// Handle `mcpz cache` subcommands
+fn manage_aliases(action: AliasAction) -> Result<()> {
+    match action {
+        AliasAction::Add { name, package, args, env } => {
+            let alias = build_alias(&name, package, args.as_deref(), &env)?;
+            let summary = format_alias(&alias);
+            PackageCache::update(|cache| cache.aliases.insert(name.clone(), alias))?;
+            println!("{}", format!("✓ Alias '{}' -> {}", name, summary).green());
+        }
+        AliasAction::Rm { name } => {
+            if PackageCache::update(|cache| cache.aliases.remove(&name))?.is_none() {
+                return Err(anyhow!("No alias named '{}'", name));
+            }
+            println!("{}", format!("✓ Removed alias '{}'", name).green());
+        }
+        AliasAction::List => {
+            let cache = PackageCache::load()?;
+            if cache.aliases.is_empty() {
+                println!("  (no aliases)");
+                return Ok(());
+            }
+            let mut aliases: Vec<_> = cache.aliases.iter().collect();
+            aliases.sort_by(|a, b| a.0.cmp(b.0));
+            for (name, alias) in aliases {
+                println!("  {} -> {}", name.cyan(), format_alias(alias));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Validate `mcpz alias add` input and build the alias
+fn build_alias(name: &str, package: String, args: Option<&str>, env: &[String]) -> Result<Alias> {
+    if name.is_empty() || name.contains(['@', '/', ':']) || local::is_local_path(name) {
+        return Err(anyhow!(
+            "Invalid alias name '{}': it can't contain '@', '/' or ':' or look like a path",
+            name
+        ));
+    }
+    let args = match args {
+        Some(args) => shlex::split(args).ok_or_else(|| anyhow!("Unbalanced quotes in --args: {}", args))?,
+        None => vec![],
+    };
+    if let Some(bare) = env.iter().find(|pair| !pair.contains('=')) {
+        return Err(anyhow!("Alias environment needs KEY=VALUE, got '{}'", bare));
+    }
+
+    Ok(Alias {
+        package,
+        args,
+        env: parse_env_pairs(env).into_iter().collect(),
+    })
+}
+
+/// One-line description of an alias; env values may be secrets, so only keys are shown
+fn format_alias(alias: &Alias) -> String {
+    let mut summary = alias.package.clone();
+    if !alias.args.is_empty() {
+        summary.push(' ');
+        summary.push_str(&alias.args.join(" "));
+    }
+    if !alias.env.is_empty() {
+        summary.push_str(&format!(" (env: {})", alias.env.keys().cloned().collect::<Vec<_>>().join(", ")));
+    }
+    summary
+}
+
+fn manage_cache(action: CacheAction) -> Result<()> {
+    match action {
+        CacheAction::List => {
+            let cache = PackageCache::load()?;
+            if cache.packages.is_empty() && cache.pinned.is_empty() && cache.args.is_empty() {
+                println!("  (no cached packages)");
+                return Ok(());
+            }
+
+            let mut entries: Vec<_> = cache.packages.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            for (search_term, entry) in entries {
+                let cached_at = entry
+                    .cached_at
+                    .and_then(|secs| chrono::DateTime::from_timestamp(secs as i64, 0))
+                    .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_else(|| "unknown".to_string());
+                let pinned = cache
+                    .get_pinned(search_term)
+                    .map(|(_, _, version)| format!(", pinned to v{}", version))
+                    .unwrap_or_default();
+                // Values may be secrets, so only the keys are shown
+                let env = cache
+                    .env
+                    .get(search_term)
+                    .map(|env| format!(", env: {}", env.keys().cloned().collect::<Vec<_>>().join(", ")))
+                    .unwrap_or_default();
+                let args = cache
+                    .args
+                    .get(search_term)
+                    .map(|args| format!(", args: {}", args.join(" ")))
+                    .unwrap_or_default();
+                println!(
+                    "  {} -> {} ({}) cached {}{}{}{}",
+                    search_term.cyan(),
+                    entry.package,
+                    entry.registry.display_name(),
+                    cached_at,
+                    pinned,
+                    env,
+                    args
+                );
+            }
+
+            let mut pinned_only: Vec<_> = cache
+                .pinned
+                .iter()
+                .filter(|(term, _)| !cache.packages.contains_key(*term))
+                .collect();
+            pinned_only.sort_by(|a, b| a.0.cmp(b.0));
+            for (search_term, (package_name, pkg_type, version)) in pinned_only {
+                println!(
+                    "  {} -> {} ({}) pinned to v{}",
+                    search_term.cyan(),
+                    package_name,
+                    pkg_type.display_name(),
+                    version
+                );
+            }
+
+            let mut args_only: Vec<_> = cache
+                .args
+                .iter()
+                .filter(|(term, _)| !cache.packages.contains_key(*term))
+                .collect();
+            args_only.sort_by(|a, b| a.0.cmp(b.0));
+            for (search_term, args) in args_only {
+                println!("  {} args: {}", search_term.cyan(), args.join(" "));
+            }
+        }
+        CacheAction::Rm { term } => {
+            if !PackageCache::update(|cache| cache.remove(&term))? {
+                return Err(anyhow!("'{}' is not in the cache", term));
+            }
+            println!("{}", format!("✓ Removed '{}' from cache", term).green());
+        }
+        CacheAction::Pin { term, registry, package } => {
+            let package = package.unwrap_or_else(|| term.clone());
+            PackageCache::update(|cache| {
+                cache.set(term.clone(), package.clone(), registry);
+                // A pinned version belonged to the old mapping
+                if cache.pinned.get(&term).is_some_and(|(name, pkg_type, _)| *name != package || *pkg_type != registry) {
+                    cache.pinned.remove(&term);
+                }
+            })?;
+            println!(
+                "{}",
+                format!("✓ '{}' now maps to {} ({})", term, package, registry.display_name()).green()
+            );
+        }
+        CacheAction::SetArgs { term, args } => {
+            let summary = args.join(" ");
+            PackageCache::update(|cache| cache.set_args(term.clone(), args))?;
+            if summary.is_empty() {
+                println!("{}", format!("✓ Cleared default arguments for '{}'", term).green());
+            } else {
+                println!("{}", format!("✓ 'mcpz run {}' now passes: {}", term, summary).green());
+            }
+        }
+        CacheAction::Migrate => {
+            let path = PackageCache::cache_path()?;
+            match PackageCache::migrate_at(&path)? {
+                Some(from) => println!(
+                    "{}",
+                    format!(
+                        "✓ Migrated cache from schema v{} to v{} (previous copy: {})",
+                        from,
+                        CACHE_VERSION,
+                        path.with_extension("toml.bak").display()
+                    )
+                    .green()
+                ),
+                None => println!("Cache is already at schema v{}", CACHE_VERSION),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Print full list of cached packages and available servers
+/// `mcpz list --json`: cache contents sorted by search term. Alias env values may
+/// hold secrets, so only their keys are included.
+fn list_json(cache: &PackageCache) -> Value {
+    let mut packages: Vec<_> = cache.packages.iter().collect();
+    packages.sort_by(|a, b| a.0.cmp(b.0));
+    let mut pinned: Vec<_> = cache.pinned.iter().collect();
+    pinned.sort_by(|a, b| a.0.cmp(b.0));
+    let mut aliases: Vec<_> = cache.aliases.iter().collect();
+    aliases.sort_by(|a, b| a.0.cmp(b.0));
+
+    json!({
+        "packages": packages
+            .into_iter()
+            .map(|(term, entry)| json!({
+                "term": term,
+                "package": entry.package,
+                "registry": entry.registry,
+                "cached_at": entry.cached_at,
+                "args": cache.args.get(term).cloned().unwrap_or_default(),
+            }))
+            .collect::<Vec<_>>(),
+        "pinned": pinned
+            .into_iter()
+            .map(|(term, (package, registry, version))| json!({
+                "term": term,
+                "package": package,
+                "registry": registry,
+                "version": version,
+            }))
+            .collect::<Vec<_>>(),
+        "aliases": aliases
+            .into_iter()
+            .map(|(name, alias)| json!({
+                "name": name,
+                "package": alias.package,
+                "args": alias.args,
+                "env": alias.env.keys().collect::<Vec<_>>(),
+            }))
+            .collect::<Vec<_>>(),
+        "builtin_servers": ["shell", "filesystem", "sql"],
+    })
+}
+
+fn print_full_list() -> Result<()> {
+    // Print cached package mappings
+    println!("{}", "Cached package mappings:".green().bold());
+    println!();
+
+    let cache = PackageCache::load().unwrap_or_default();
+    if cache.packages.is_empty() {
+        println!("  (no cached packages)");
+    } else {
+        let mut entries: Vec<_> = cache.packages.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        for (search_term, entry) in entries {
+            println!(
+                "  {} -> {} ({})",
+                search_term.cyan(),
+                entry.package,
+                entry.registry.display_name()
+            );
+            println!(
+                "    Run: {}",
+                format!("mcpz run {}", search_term).yellow()
+            );
+        }
+    }
+
+    if !cache.pinned.is_empty() {
+        println!();
+        println!("{}", "Pinned versions:".green().bold());
+        println!();
+
+        let mut entries: Vec<_> = cache.pinned.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        for (search_term, (package_name, pkg_type, version)) in entries {
+            println!(
+                "  {}@{} -> {} ({})",
+                search_term.cyan(),
+                version,
+                package_name,
+                pkg_type.display_name()
+            );
+            println!(
+                "    Run: {}",
+                format!("mcpz run {}@{}", search_term, version).yellow()
+            );
+        }
+    }
+
+    println!();
+    println!("{}", "Built-in MCP servers:".green().bold());
+    println!();
+    println!("  {} - Execute shell commands", "shell".cyan());
+    println!("    Run: {}", "mcpz server shell".yellow());
+    println!();
+    println!("  {} - Filesystem operations", "filesystem".cyan());
+    println!("    Run: {}", "mcpz server filesystem".yellow());
+    println!();
+    println!("  {} - SQL database queries", "sql".cyan());
+    println!("    Run: {}", "mcpz server sql -c <connection> --readonly".yellow());
+    println!();
+    println!("Use 'mcpz server --list' for detailed server options.");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_package_type_runner() {
+        assert_eq!(PackageType::Npm.runner(), "npx");
+        assert_eq!(PackageType::Python.runner(), "uvx");
+        assert_eq!(PackageType::Cargo.runner(), "cargo");
+    }
+
+    #[test]
+    fn test_package_type_display_name() {
+        assert_eq!(PackageType::Npm.display_name(), "npm");
+        assert_eq!(PackageType::Python.display_name(), "PyPI");
+        assert_eq!(PackageType::Cargo.display_name(), "crates.io");
+    }
+
+    #[test]
+    fn test_command_exists_which() {
+        assert!(command_exists("which"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_command_searches_path() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        for (name, mode) in [("tool", 0o755), ("data", 0o644), ("app.EXE", 0o755)] {
+            let path = dir.path().join(name);
+            fs::write(&path, "").unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+        }
+        let path = std::env::join_paths(["/nonexistent".as_ref(), dir.path()]).unwrap();
+
+        assert_eq!(find_command("tool", Some(&path), &[]), Some(dir.path().join("tool")));
+        assert_eq!(find_command("data", Some(&path), &[]), None);
+        assert_eq!(find_command("tool", None, &[]), None);
+        assert_eq!(find_command("app", Some(&path), &[]), None);
+        assert_eq!(find_command("app", Some(&path), &[".EXE".to_string()]), Some(dir.path().join("app.EXE")));
+        let direct = dir.path().join("tool");
+        assert_eq!(find_command(direct.to_str().unwrap(), None, &[]), Some(direct.clone()));
+    }
+
+    #[test]
+    fn test_command_exists_nonexistent() {
+        assert!(!command_exists("this-command-definitely-does-not-exist-12345"));
+    }
+
+    #[test]
+    fn test_cli_parse_run() {
+        let cli = Cli::parse_from(["mcpz", "run", "@modelcontextprotocol/server-filesystem", "."]);
+        match cli.command {
+            Commands::Run { package, first, args, .. } => {
+                assert_eq!(package, "@modelcontextprotocol/server-filesystem");
+                assert!(!first);
+                assert_eq!(args, vec!["."]);
+            }
+            _ => panic!("Expected Run command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parse_run_no_args() {
+        let cli = Cli::parse_from(["mcpz", "run", "mcp-server-time"]);
+        match cli.command {
+            Commands::Run { package, first, args, .. } => {
+                assert_eq!(package, "mcp-server-time");
+                assert!(!first);
+                assert!(args.is_empty());
+            }
+            _ => panic!("Expected Run command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parse_run_first() {
+        let cli = Cli::parse_from(["mcpz", "run", "--first", "mcp-server-time"]);
+        match cli.command {
+            Commands::Run { package, first, args, .. } => {
+                assert_eq!(package, "mcp-server-time");
+                assert!(first);
+                assert!(args.is_empty());
+            }
+            _ => panic!("Expected Run command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parse_search() {
+        let cli = Cli::parse_from(["mcpz", "search", "mcp-server-time"]);
+        match cli.command {
+            Commands::Search { package, .. } => {
+                assert_eq!(package, "mcp-server-time");
+            }
+            _ => panic!("Expected Search command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parse_pick() {
+        let cli = Cli::parse_from(["mcpz", "pick", "mcp-server-time"]);
+        match cli.command {
+            Commands::Pick { package, .. } => {
+                assert_eq!(package, "mcp-server-time");
+            }
+            _ => panic!("Expected Pick command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parse_search_cache_options() {
+        let cli = Cli::parse_from(["mcpz", "search", "--no-cache", "--cache-ttl", "60", "mcp-server-time"]);
+        match cli.command {
+            Commands::Search { no_cache, cache_ttl, .. } => {
+                assert!(no_cache);
+                assert_eq!(cache_ttl, 60);
+            }
+            _ => panic!("Expected Search command"),
+        }
+
+        let cli = Cli::parse_from(["mcpz", "pick", "mcp-server-time"]);
+        match cli.command {
+            Commands::Pick { no_cache, cache_ttl, .. } => {
+                assert!(!no_cache);
+                assert_eq!(cache_ttl, 3600);
+            }
+            _ => panic!("Expected Pick command"),
+        }
+    }
+
+    #[test]
+    fn test_search_cache_freshness() {
+        let cache = SearchCache {
+            fetched_at: SearchCache::now() - 120,
+            packages: vec![],
+        };
+        assert!(cache.is_fresh(std::time::Duration::from_secs(3600)));
+        assert!(!cache.is_fresh(std::time::Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_search_cache_path_is_safe() {
+        let path = SearchCache::path("@scope/pkg").unwrap();
+        let file_name = path.file_name().unwrap().to_string_lossy();
+        assert!(file_name.ends_with(".json"));
+        assert!(!file_name.contains('/'));
+        assert_ne!(path, SearchCache::path("scope-pkg").unwrap());
+    }
+
+    #[test]
+    fn test_search_cache_round_trip() {
+        let cache = SearchCache {
+            fetched_at: 42,
+            packages: vec![PackageInfo {
+                name: "mcp-server-time".to_string(),
+                version: "1.0.0".to_string(),
+                description: "Time".to_string(),
+                author: "someone".to_string(),
+                published: "2025-01-01".to_string(),
+                downloads: Some(10),
+                registry: PackageType::Python,
+                mcp_server: None,
+                keywords: vec![],
+                links: BTreeMap::new(),
+            }],
+        };
+        let json = serde_json::to_string(&cache).unwrap();
+        let loaded: SearchCache = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.fetched_at, 42);
+        assert_eq!(loaded.packages[0].name, "mcp-server-time");
+        assert_eq!(loaded.packages[0].registry, PackageType::Python);
+    }
+
+    #[test]
+    fn test_cli_parse_clear_cache() {
+        let cli = Cli::parse_from(["mcpz", "clear-cache"]);
+        assert!(matches!(cli.command, Commands::ClearCache));
+    }
+
+    #[test]
+    fn test_package_type_install_instructions() {
+        let npm_instructions = PackageType::Npm.install_instructions();
+        assert!(npm_instructions.contains("nodejs") || npm_instructions.contains("Node"));
+
+        let python_instructions = PackageType::Python.install_instructions();
+        assert!(python_instructions.contains("astral.sh"));
+
+        let cargo_instructions = PackageType::Cargo.install_instructions();
+        assert!(cargo_instructions.contains("rustup") || cargo_instructions.contains("Rust"));
+    }
+
+    #[test]
+    fn test_cache_serialization() {
+        let mut cache = PackageCache::default();
+        cache.set(
+            "test-search".to_string(),
+            "actual-package".to_string(),
+            PackageType::Python,
+        );
+        cache.set(
+            "another".to_string(),
+            "another-pkg".to_string(),
+            PackageType::Npm,
+        );
+
+        let serialized = toml::to_string(&cache).unwrap();
+        let deserialized: PackageCache = toml::from_str(&serialized).unwrap();
+
+        assert_eq!(
+            deserialized.get("test-search"),
+            Some(("actual-package".to_string(), PackageType::Python))
+        );
+        assert_eq!(
+            deserialized.get("another"),
+            Some(("another-pkg".to_string(), PackageType::Npm))
+        );
+    }
+
+    #[test]
+    fn test_cache_names() {
+        let mut cache = PackageCache::default();
+        cache.set("time".to_string(), "mcp-server-time".to_string(), PackageType::Python);
+        cache.set("mcp-server-time".to_string(), "mcp-server-time".to_string(), PackageType::Python);
+        let alias = Alias { package: "server-filesystem".to_string(), args: vec![], env: BTreeMap::new() };
+        cache.aliases.insert("fs".to_string(), alias);
+        assert_eq!(cache.names(), ["fs", "mcp-server-time", "time"]);
+    }
+
+    #[test]
+    fn test_cli_parse_update() {
+        let cli = Cli::parse_from(["mcpz", "update"]);
+        assert!(matches!(cli.command, Commands::Update { upgrade: false }));
+
+        let cli = Cli::parse_from(["mcpz", "update", "--upgrade"]);
+        assert!(matches!(cli.command, Commands::Update { upgrade: true }));
+    }
+
+    #[test]
+    fn test_cli_parse_run_locked() {
+        let cli = Cli::parse_from(["mcpz", "run", "--locked", "mcp-server-time"]);
+        match cli.command {
+            Commands::Run { package, locked, .. } => {
+                assert_eq!(package, "mcp-server-time");
+                assert!(locked);
+            }
+            _ => panic!("Expected Run command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parse_run_docker() {
+        let cli = Cli::parse_from([
+            "mcpz", "run",
+            "--volume", "/data:/data",
+            "-e", "TOKEN=abc",
+            "--env", "DEBUG",
+            "docker:ghcr.io/foo/mcp-server",
+            "serve",
+        ]);
+        match cli.command {
+            Commands::Run { package, volumes, env, args, .. } => {
+                assert_eq!(package, "docker:ghcr.io/foo/mcp-server");
+                assert_eq!(volumes, vec!["/data:/data"]);
+                assert_eq!(env, vec!["TOKEN=abc", "DEBUG"]);
+                assert_eq!(args, vec!["serve"]);
+            }
+            _ => panic!("Expected Run command"),
+        }
+    }
+
+    #[test]
+    fn test_resolve_docker_package() {
+        let (name, pkg_type, version) =
+            resolve_package("docker:ghcr.io/foo/mcp-server@1.0", &RunOptions::default()).unwrap();
+        assert_eq!(name, "ghcr.io/foo/mcp-server");
+        assert_eq!(pkg_type, PackageType::Docker);
+        assert_eq!(version.as_deref(), Some("1.0"));
+    }
+
+    #[test]
+    fn test_resolve_docker_run_spec() {
+        let run_spec = |spec: &str| {
+            let (name, pkg_type, version) = resolve_package(spec, &RunOptions::default()).unwrap();
+            let version = resolve_version(&name, pkg_type, version, true).unwrap();
+            pkg_type.package_spec(&name, version.as_deref())
+        };
+        assert_eq!(run_spec("docker:alpine:3.19"), "alpine:3.19");
+        assert_eq!(run_spec("docker:alpine"), "alpine:latest");
+        assert_eq!(run_spec("docker:localhost:5000/team/img"), "localhost:5000/team/img:latest");
+        assert_eq!(run_spec("docker:localhost:5000/team/img:v2"), "localhost:5000/team/img:v2");
+        assert_eq!(run_spec("docker:alpine@sha256:abc"), "alpine@sha256:abc");
+        assert_eq!(run_spec("docker:ghcr.io/foo/mcp-server@1.0"), "ghcr.io/foo/mcp-server:1.0");
+    }
+
+    #[test]
+    fn test_docker_package_spec() {
+        assert_eq!(PackageType::Docker.runner(), "docker");
+        assert_eq!(PackageType::Docker.package_spec("img", Some("1.0")), "img:1.0");
+        assert_eq!(PackageType::Docker.package_spec("img", Some("sha256:abc")), "img@sha256:abc");
+        assert_eq!(PackageType::Docker.package_spec("img:tag", None), "img:tag");
+    }
+
+    #[test]
+    fn test_cli_parse_run_container_runtime() {
+        let cli = Cli::parse_from([
+            "mcpz", "run", "--container-runtime", "podman", "docker:ghcr.io/foo/mcp-server",
+        ]);
+        match cli.command {
+            Commands::Run { container_runtime, .. } => {
+                assert_eq!(container_runtime, Some(ContainerRuntime::Podman));
+            }
+            _ => panic!("Expected Run command"),
+        }
+
+        let cli = Cli::parse_from(["mcpz", "run", "docker:foo"]);
+        assert!(matches!(cli.command, Commands::Run { container_runtime: None, .. }));
+    }
+
+    #[test]
+    fn test_cli_parse_run_runner() {
+        let cli = Cli::parse_from(["mcpz", "run", "--runner", "bun", "mcp-server-time"]);
+        assert!(matches!(cli.command, Commands::Run { runner: Some(NpmRunner::Bun), .. }));
+
+        let cli = Cli::parse_from(["mcpz", "run", "mcp-server-time"]);
+        assert!(matches!(cli.command, Commands::Run { runner: None, .. }));
+    }
+
+    #[test]
+    fn test_npm_runner() {
+        assert_eq!(NpmRunner::Npx.command(), "npx");
+        assert_eq!(NpmRunner::Npx.args(), &["-y"]);
+        assert_eq!(NpmRunner::Bun.command(), "bunx");
+        assert!(NpmRunner::Bun.args().is_empty());
+        assert!(NpmRunner::Bun.install_instructions().contains("bun.sh"));
+    }
+
+    #[test]
+    fn test_container_runtime_image_ref() {
+        let podman = ContainerRuntime::Podman;
+        assert_eq!(podman.image_ref("alpine:3"), "docker.io/library/alpine:3");
+        assert_eq!(podman.image_ref("mcp/fetch"), "docker.io/mcp/fetch");
+        assert_eq!(podman.image_ref("ghcr.io/foo/mcp-server"), "ghcr.io/foo/mcp-server");
+        assert_eq!(podman.image_ref("localhost/mcp:dev"), "localhost/mcp:dev");
+        assert_eq!(podman.image_ref("registry:5000/mcp"), "registry:5000/mcp");
+
+        assert_eq!(ContainerRuntime::Docker.image_ref("mcp/fetch"), "mcp/fetch");
+        assert_eq!(ContainerRuntime::Nerdctl.command(), "nerdctl");
+    }
+
+    #[test]
+    fn test_deno_package_spec() {
+        assert_eq!(PackageType::Deno.runner(), "deno");
+        assert_eq!(PackageType::Deno.display_name(), "JSR");
+        assert_eq!(PackageType::Deno.package_spec("@scope/pkg", None), "jsr:@scope/pkg");
+        assert_eq!(PackageType::Deno.package_spec("@scope/pkg", Some("1.0.0")), "jsr:@scope/pkg@1.0.0");
+        assert_eq!(PackageType::Deno.package_spec("npm:mcp-server", Some("2.0.0")), "npm:mcp-server@2.0.0");
+    }
+
+    #[test]
+    fn test_resolve_deno_package() {
+        let opts = RunOptions::default();
+
+        let (name, pkg_type, version) = resolve_package("jsr:@scope/pkg@1.0.0", &opts).unwrap();
+        assert_eq!(name, "@scope/pkg");
+        assert_eq!(pkg_type, PackageType::Deno);
+        assert_eq!(version.as_deref(), Some("1.0.0"));
+
+        let (name, pkg_type, version) = resolve_package("deno:npm:mcp-server", &opts).unwrap();
+        assert_eq!(name, "npm:mcp-server");
+        assert_eq!(pkg_type, PackageType::Deno);
+        assert!(version.is_none());
+
+        let (name, _, _) = resolve_package("deno:jsr:@scope/pkg", &opts).unwrap();
+        assert_eq!(name, "@scope/pkg");
+    }
+
+    #[test]
+    fn test_parse_env_pairs() {
+        let env = vec!["A=1".to_string(), "B".to_string(), "C=x=y".to_string()];
+        assert_eq!(
+            parse_env_pairs(&env),
+            vec![("A".to_string(), "1".to_string()), ("C".to_string(), "x=y".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_lockfile_serialization() {
+        let mut lockfile = Lockfile::default();
+        lockfile.set(
+            "time".to_string(),
+            LockedPackage {
+                package: "mcp-server-time".to_string(),
+                registry: PackageType::Python,
+                version: "1.2.3".to_string(),
+                command: "uvx mcp-server-time==1.2.3".to_string(),
+            },
+        );
+
+        let serialized = toml::to_string_pretty(&lockfile).unwrap();
+        assert!(serialized.contains("[packages.time]"));
+
+        let deserialized: Lockfile = toml::from_str(&serialized).unwrap();
+        let entry = deserialized.get("time").unwrap();
+        assert_eq!(entry.package, "mcp-server-time");
+        assert_eq!(entry.registry, PackageType::Python);
+        assert_eq!(entry.version, "1.2.3");
+        assert_eq!(entry.command, "uvx mcp-server-time==1.2.3");
+    }
+
+    #[test]
+    fn test_lockfile_update_merges_concurrent_writers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(Lockfile::FILE_NAME);
+
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    Lockfile::update_at(&path, |lockfile| {
+                        let entry = LockedPackage {
+                            package: format!("pkg-{}", i),
+                            registry: PackageType::Npm,
+                            version: "1.0.0".to_string(),
+                            command: format!("npx pkg-{}@1.0.0", i),
+                        };
+                        lockfile.set(format!("term-{}", i), entry);
+                        true
+                    })
+                    .unwrap();
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let lockfile = Lockfile::load_from(&path).unwrap();
+        assert_eq!(lockfile.packages.len(), 8);
+        assert_eq!(lockfile.get("term-3").unwrap().package, "pkg-3");
+        // Only the lockfile and its lock file remain; no temp files are left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_gateway_server_offers_completions() {
+        use servers::common::McpServer;
+        use servers::filesystem::FilesystemServer;
+        use servers::router::{Route, RouterServer, ToolRules};
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::write(root.join("readme.md"), "hi").unwrap();
+        let config = FilesystemServerConfig::new(vec![root.clone()], false).unwrap();
+        let route = std::sync::Arc::new(Route {
+            prefix: "fs".to_string(),
+            server: Box::new(FilesystemServer::new(config)),
+            tools: ToolRules::default(),
+            max_result_size: None,
+        });
+        let router = std::sync::Arc::new(RouterServer::new(vec![route], true, false).unwrap());
+        let server = gateway_server(router, &BTreeMap::new(), None, true);
+
+        assert_eq!(server.handle_initialize()["capabilities"]["completions"], json!({}));
+        let request = json!({
+            "jsonrpc": "2.0", "id": 1, "method": "completion/complete",
+            "params": {
+                "ref": {"type": "ref/tool", "name": "fs.read_file"},
+                "argument": {"name": "path", "value": format!("{}/r", root.display())}
+            }
+        });
+        let response = server.handle_request(serde_json::from_value(request).unwrap()).unwrap();
+        let response = serde_json::to_value(response).unwrap();
+        assert_eq!(response["result"]["completion"]["values"], json!([format!("{}/readme.md", root.display())]));
+    }
+
+    #[test]
+    fn test_lockfile_empty() {
+        let lockfile: Lockfile = toml::from_str("").unwrap();
+        assert!(lockfile.get("anything").is_none());
+    }
+
+    #[test]
+    fn test_cli_parse_offline() {
+        let cli = Cli::parse_from(["mcpz", "run", "--offline", "mcp-server-time"]);
+        assert!(cli.offline);
+
+        let cli = Cli::parse_from(["mcpz", "--offline", "search", "mcp-server-time"]);
+        assert!(cli.offline);
+    }
+
+    #[test]
+    fn test_ensure_online() {
+        assert!(ensure_online(false, "search").is_ok());
+        let err = ensure_online(true, "search").unwrap_err().to_string();
+        assert!(err.contains("search"));
+        assert!(err.contains("--offline"));
+    }
+
+    #[test]
+    fn test_cli_parse_run_verify() {
+        let cli = Cli::parse_from(["mcpz", "run", "--require-verified", "mcp-server-time"]);
+        match cli.command {
+            Commands::Run { verify, require_verified, .. } => {
+                assert!(!verify);
+                assert!(require_verified);
+            }
+            _ => panic!("Expected Run command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parse_install() {
+        let cli = Cli::parse_from(["mcpz", "install", "--first", "mcp-server-time@1.2.3"]);
+        match cli.command {
+            Commands::Install { package, first, container_runtime } => {
+                assert_eq!(package, "mcp-server-time@1.2.3");
+                assert!(first);
+                assert!(container_runtime.is_none());
+            }
+            _ => panic!("Expected Install command"),
+        }
+    }
+
+    #[test]
+    fn test_install_package_rejects_local_path() {
+        let err = install_package("./server.py", &RunOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("don't need installing"));
+    }
+
+    #[test]
+    fn test_prepare_local_package_require_verified() {
+        let opts = RunOptions {
+            require_verified: true,
+            ..Default::default()
+        };
+        let err = prepare_local_package("./server.py", &[], opts).err().unwrap();
+        assert!(err.to_string().contains("can't be verified"));
+    }
+
+    #[test]
+    fn test_get_package_type_offline_uncached() {
+        let result = get_package_type("mcpz-test-definitely-not-cached-12345", false, true);
+        assert!(result.unwrap_err().to_string().contains("not in the package cache"));
+    }
+
+    #[test]
+    fn test_is_newer_version() {
+        assert!(is_newer_version("1.2.4", "1.2.3"));
+        assert!(is_newer_version("1.10.0", "1.9.9"));
+        assert!(is_newer_version("2.0.0", "v1.9.0"));
+        assert!(!is_newer_version("1.2.3", "1.2.3"));
+        assert!(!is_newer_version("1.2.3", "1.3.0"));
+        assert!(is_newer_version("abc", "def"));
+    }
+
+    #[test]
+    fn test_split_version() {
+        assert_eq!(split_version("mcp-server-time"), ("mcp-server-time", None));
+        assert_eq!(split_version("mcp-server-time@1.2.3"), ("mcp-server-time", Some("1.2.3")));
+        assert_eq!(
+            split_version("@modelcontextprotocol/server-filesystem"),
+            ("@modelcontextprotocol/server-filesystem", None)
+        );
+        assert_eq!(
+            split_version("@modelcontextprotocol/server-filesystem@2025.1.0"),
+            ("@modelcontextprotocol/server-filesystem", Some("2025.1.0"))
+        );
+        assert_eq!(split_version("mcp-server-time@"), ("mcp-server-time@", None));
+        assert_eq!(split_version("jsr:@scope/pkg"), ("jsr:@scope/pkg", None));
+        assert_eq!(split_version("jsr:@scope"), ("jsr:@scope", None));
+        assert_eq!(split_version("jsr:@scope/pkg@1.0"), ("jsr:@scope/pkg", Some("1.0")));
+    }
 
-    let cache = PackageCache::load().unwrap_or_default();
-    if cache.packages.is_empty() {
-        println!("  (no cached packages)");
-    } else {
-        let mut entries: Vec<_> = cache.packages.iter().collect();
-        entries.sort_by(|a, b| a.0.cmp(b.0));
-        for (search_term, (package_name, pkg_type)) in entries {
-            println!(
-                "  {} -> {} ({})",
-                search_term.cyan(),
-                package_name,
-                pkg_type.display_name()
-            );
-            println!(
-                "    Run: {}",
-                format!("mcpz run {}", search_term).yellow()
-            );
-        }
+    #[test]
+    fn test_package_type_package_spec() {
+        assert_eq!(PackageType::Npm.package_spec("pkg", Some("1.0.0")), "pkg@1.0.0");
+        assert_eq!(PackageType::Python.package_spec("pkg", Some("1.0.0")), "pkg==1.0.0");
+        assert_eq!(PackageType::Cargo.package_spec("pkg", Some("1.0.0")), "pkg");
+        assert_eq!(PackageType::Npm.package_spec("pkg", None), "pkg");
     }
 
-    println!();
-    println!("{}", "Built-in MCP servers:".green().bold());
-    println!();
-    println!("  {} - Execute shell commands", "shell".cyan());
-    println!("    Run: {}", "mcpz server shell".yellow());
-    println!();
-    println!("  {} - Filesystem operations", "filesystem".cyan());
-    println!("    Run: {}", "mcpz server filesystem".yellow());
-    println!();
-    println!("  {} - SQL database queries", "sql".cyan());
-    println!("    Run: {}", "mcpz server sql -c <connection> --readonly".yellow());
-    println!();
-    println!("Use 'mcpz server --list' for detailed server options.");
+    #[test]
+    fn test_cache_reads_legacy_tuple_entries() {
+        let legacy = "[packages]\ntime = [\"mcp-server-time\", \"python\"]\n";
+        let cache: PackageCache = toml::from_str(legacy).unwrap();
+        assert_eq!(
+            cache.get("time"),
+            Some(("mcp-server-time".to_string(), PackageType::Python))
+        );
+        assert!(cache.packages["time"].cached_at.is_none());
 
-    Ok(())
-}
+        // Re-saving upgrades to the table form with a timestamp
+        let mut cache = cache;
+        cache.set("time".to_string(), "mcp-server-time".to_string(), PackageType::Python);
+        let serialized = toml::to_string(&cache).unwrap();
+        assert!(serialized.contains("cached_at"));
+        let reloaded: PackageCache = toml::from_str(&serialized).unwrap();
+        assert!(reloaded.packages["time"].cached_at.is_some());
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_read_env_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join(".env");
+        fs::write(&path, "# secrets\nAPI_KEY=abc123\nQUOTED=\"hello world\"\n").unwrap();
+
+        let env = read_env_file(&path).unwrap();
+        assert_eq!(env, vec!["API_KEY=abc123", "QUOTED=hello world"]);
+        assert!(read_env_file(&dir.path().join("missing.env")).is_err());
+    }
 
     #[test]
-    fn test_package_type_runner() {
-        assert_eq!(PackageType::Npm.runner(), "npx");
-        assert_eq!(PackageType::Python.runner(), "uvx");
-        assert_eq!(PackageType::Cargo.runner(), "cargo");
+    fn test_cache_saved_env_roundtrip() {
+        let mut cache = PackageCache::default();
+        cache.env.insert(
+            "github".to_string(),
+            BTreeMap::from([("GITHUB_TOKEN".to_string(), "t0k3n".to_string())]),
+        );
+
+        let serialized = toml::to_string(&cache).unwrap();
+        let deserialized: PackageCache = toml::from_str(&serialized).unwrap();
+        assert_eq!(deserialized.env["github"]["GITHUB_TOKEN"], "t0k3n");
+
+        // Caches without saved env don't grow an empty table
+        assert!(!toml::to_string(&PackageCache::default()).unwrap().contains("env"));
     }
 
     #[test]
-    fn test_package_type_display_name() {
-        assert_eq!(PackageType::Npm.display_name(), "npm");
-        assert_eq!(PackageType::Python.display_name(), "PyPI");
-        assert_eq!(PackageType::Cargo.display_name(), "crates.io");
+    fn test_cli_parse_run_env_file() {
+        let cli = Cli::parse_from([
+            "mcpz", "run", "--env-file", ".env", "-e", "DEBUG=1", "--save-env", "mcp-server-time",
+        ]);
+        match cli.command {
+            Commands::Run { env, env_files, save_env, .. } => {
+                assert_eq!(env, vec!["DEBUG=1"]);
+                assert_eq!(env_files, vec![PathBuf::from(".env")]);
+                assert!(save_env);
+            }
+            _ => panic!("Expected Run command"),
+        }
     }
 
     #[test]
-    fn test_command_exists_which() {
-        assert!(command_exists("which"));
+    fn test_restart_policy_parse() {
+        assert_eq!("no".parse::<RestartPolicy>(), Ok(RestartPolicy::Never));
+        assert_eq!(
+            "on-failure".parse::<RestartPolicy>(),
+            Ok(RestartPolicy::OnFailure { max: None })
+        );
+        assert_eq!(
+            "on-failure:3".parse::<RestartPolicy>(),
+            Ok(RestartPolicy::OnFailure { max: Some(3) })
+        );
+        assert!("always".parse::<RestartPolicy>().is_err());
+        assert!("on-failure:x".parse::<RestartPolicy>().is_err());
+
+        let cli = Cli::parse_from(["mcpz", "run", "--restart", "on-failure:5", "mcp-server-time"]);
+        assert!(matches!(
+            cli.command,
+            Commands::Run { restart: RestartPolicy::OnFailure { max: Some(5) }, .. }
+        ));
     }
 
     #[test]
-    fn test_command_exists_nonexistent() {
-        assert!(!command_exists("this-command-definitely-does-not-exist-12345"));
+    fn test_restart_policy_backoff_and_limit() {
+        let secs: Vec<u64> = (1..=8).map(|n| RestartPolicy::backoff(n).as_secs()).collect();
+        assert_eq!(secs, vec![1, 2, 4, 8, 16, 30, 30, 30]);
+
+        let policy = RestartPolicy::OnFailure { max: Some(2) };
+        assert!(policy.allows(0));
+        assert!(policy.allows(1));
+        assert!(!policy.allows(2));
+        assert!(RestartPolicy::OnFailure { max: None }.allows(1000));
+        assert!(!RestartPolicy::Never.allows(0));
     }
 
+    #[cfg(unix)]
     #[test]
-    fn test_cli_parse_run() {
-        let cli = Cli::parse_from(["mcpz", "run", "@modelcontextprotocol/server-filesystem", "."]);
+    fn test_spawn_once_reports_exit_status() {
+        let mut ok = Command::new("sh");
+        ok.args(["-c", "exit 0"]);
+        assert!(spawn_once(ok, "ok", None, None).is_ok());
+
+        let mut failing = Command::new("sh");
+        failing.args(["-c", "exit 3"]);
+        let err = spawn_once(failing, "failing", None, None).err().unwrap().to_string();
+        assert!(err.contains("exit status: 3"));
+    }
+
+    #[test]
+    fn test_copy_command() {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "exit 0"]).env("MCPZ_TEST", "1").current_dir("/tmp");
+        let copy = copy_command(&cmd);
+
+        assert_eq!(copy.get_program(), "sh");
+        assert_eq!(copy.get_args().collect::<Vec<_>>(), vec!["-c", "exit 0"]);
+        assert_eq!(copy.get_envs().count(), 1);
+        assert_eq!(copy.get_current_dir(), Some(std::path::Path::new("/tmp")));
+    }
+
+    #[test]
+    fn test_cli_parse_run_health_check() {
+        let cli = Cli::parse_from(["mcpz", "run", "--health-check", "--health-timeout", "5", "mcp-server-time"]);
         match cli.command {
-            Commands::Run { package, first, args } => {
-                assert_eq!(package, "@modelcontextprotocol/server-filesystem");
-                assert!(!first);
-                assert_eq!(args, vec!["."]);
+            Commands::Run { health_check, health_timeout, .. } => {
+                assert!(health_check);
+                assert_eq!(health_timeout, 5);
             }
             _ => panic!("Expected Run command"),
         }
     }
 
     #[test]
-    fn test_cli_parse_run_no_args() {
-        let cli = Cli::parse_from(["mcpz", "run", "mcp-server-time"]);
+    fn test_cli_parse_run_log_dir() {
+        let cli = Cli::parse_from(["mcpz", "run", "--log-dir", "mcp-server-time"]);
         match cli.command {
-            Commands::Run { package, first, args } => {
+            Commands::Run { package, log_dir, log_traffic, .. } => {
                 assert_eq!(package, "mcp-server-time");
-                assert!(!first);
-                assert!(args.is_empty());
+                assert_eq!(log_dir, Some(None));
+                assert!(!log_traffic);
+            }
+            _ => panic!("Expected Run command"),
+        }
+
+        let cli = Cli::parse_from(["mcpz", "run", "--log-dir=/tmp/logs", "--log-traffic", "mcp-server-time"]);
+        match cli.command {
+            Commands::Run { log_dir, log_traffic, .. } => {
+                assert_eq!(log_dir, Some(Some(PathBuf::from("/tmp/logs"))));
+                assert!(log_traffic);
             }
             _ => panic!("Expected Run command"),
         }
+
+        assert!(Cli::try_parse_from(["mcpz", "run", "--log-traffic", "mcp-server-time"]).is_err());
     }
 
     #[test]
-    fn test_cli_parse_run_first() {
-        let cli = Cli::parse_from(["mcpz", "run", "--first", "mcp-server-time"]);
+    fn test_cli_parse_record_and_replay() {
+        let cli = Cli::parse_from(["mcpz", "run", "--record", "session.jsonl", "mcp-server-time"]);
+        assert!(matches!(cli.command, Commands::Run { record: Some(path), .. } if path.as_path() == Path::new("session.jsonl")));
+
+        let cli = Cli::parse_from(["mcpz", "server", "shell", "--record", "shell.jsonl"]);
+        assert!(matches!(
+            cli.command,
+            Commands::Server { server_type: Some(ServerType::Shell { record: Some(_), .. }), .. }
+        ));
+        assert!(Cli::try_parse_from(["mcpz", "server", "shell", "--http", "--record", "shell.jsonl"]).is_err());
+
+        let cli = Cli::parse_from(["mcpz", "replay", "session.jsonl", "./server.py", "--timeout", "5", "--", "--debug"]);
+        match cli.command {
+            Commands::Replay { session, target, timeout, args } => {
+                assert_eq!(session, PathBuf::from("session.jsonl"));
+                assert_eq!(target, "./server.py");
+                assert_eq!(timeout, 5);
+                assert_eq!(args, vec!["--debug"]);
+            }
+            _ => panic!("Expected Replay command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parse_gateway() {
+        let cli = Cli::parse_from([
+            "mcpz", "gateway", "builtin:shell", "docs=builtin:filesystem:/srv/docs", "https://mcp.example.com/mcp",
+            "--http", "-p", "8080",
+        ]);
+        match cli.command {
+            Commands::Gateway { servers, http, port, timeout, .. } => {
+                assert_eq!(servers.len(), 3);
+                assert!(http);
+                assert_eq!(port, Some(8080));
+                assert_eq!(timeout, None);
+            }
+            _ => panic!("Expected Gateway command"),
+        }
+        let cli = Cli::parse_from(["mcpz", "--profile", "work", "gateway", "--config", "fleet.toml"]);
+        assert_eq!(cli.config, Some(PathBuf::from("fleet.toml")));
+        assert_eq!(cli.profile.as_deref(), Some("work"));
+        assert!(matches!(cli.command, Commands::Gateway { servers, .. } if servers.is_empty()));
+        assert!(Cli::try_parse_from(["mcpz", "gateway", "builtin:shell", "--http", "--record", "s.jsonl"]).is_err());
+    }
+
+    #[test]
+    fn test_spawn_and_forward_fails_health_check() {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "exit 3"]);
+        let opts = RunOptions {
+            health_check: Some(std::time::Duration::from_secs(5)),
+            ..Default::default()
+        };
+
+        let err = spawn_and_forward(cmd, "broken-server", &opts).unwrap_err();
+        assert!(err.to_string().contains("failed the MCP health check"));
+    }
+
+    #[test]
+    fn test_cli_parse_run_http() {
+        let cli = Cli::parse_from([
+            "mcpz", "run", "--http", "-p", "8080", "--tls", "--origin", "https://app.example", "mcp-server-time",
+        ]);
         match cli.command {
-            Commands::Run { package, first, args } => {
+            Commands::Run { package, http, port, host, tls, origin, .. } => {
                 assert_eq!(package, "mcp-server-time");
-                assert!(first);
-                assert!(args.is_empty());
+                assert!(http);
+                assert_eq!(port, 8080);
+                assert_eq!(host, "127.0.0.1");
+                assert!(tls);
+                assert_eq!(origin.as_deref(), Some("https://app.example"));
             }
             _ => panic!("Expected Run command"),
         }
     }
 
     #[test]
-    fn test_cli_parse_search() {
-        let cli = Cli::parse_from(["mcpz", "search", "mcp-server-time"]);
+    fn test_spawn_and_forward_http_fails_handshake() {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "exit 3"]);
+        let opts = RunOptions {
+            http: Some(HttpOptions {
+                port: 0,
+                host: "127.0.0.1".to_string(),
+                tls: false,
+                cert: None,
+                key: None,
+                origin: None,
+                quiet: true,
+                rate_limit: None,
+                auth: None,
+                paths: false,
+            }),
+            ..Default::default()
+        };
+
+        let err = spawn_and_forward(cmd, "broken-server", &opts).unwrap_err();
+        assert!(err.to_string().contains("failed to start"));
+    }
+
+    #[test]
+    fn test_tool_prefix() {
+        assert_eq!(tool_prefix("mcp-server-time"), "mcp-server-time");
+        assert_eq!(tool_prefix("@modelcontextprotocol/server-filesystem@1.0.0"), "server-filesystem");
+        assert_eq!(tool_prefix("docker:ghcr.io/foo/mcp-server:latest"), "mcp-server");
+        assert_eq!(tool_prefix("docker:alpine"), "alpine");
+        assert_eq!(tool_prefix("deno:npm:mcp-server"), "mcp-server");
+        assert_eq!(tool_prefix("jsr:@scope/pkg"), "pkg");
+        assert_eq!(tool_prefix("./servers/weather.py"), "weather");
+        assert_eq!(tool_prefix("my.server"), "my_server");
+    }
+
+    #[test]
+    fn test_cli_parse_run_prefix_tools() {
+        let cli = Cli::parse_from(["mcpz", "run", "--prefix-tools", "mcp-server-time", "mcp-server-fetch"]);
         match cli.command {
-            Commands::Search { package } => {
+            Commands::Run { package, prefix_tools, args, .. } => {
                 assert_eq!(package, "mcp-server-time");
+                assert!(prefix_tools);
+                assert_eq!(args, vec!["mcp-server-fetch"]);
             }
-            _ => panic!("Expected Search command"),
+            _ => panic!("Expected Run command"),
+        }
+
+        let cli = Cli::parse_from(["mcpz", "run", "mcp-server-time", "mcp-server-fetch", "--prefix-tools"]);
+        match cli.command {
+            Commands::Run { prefix_tools, mut args, .. } => {
+                assert!(!prefix_tools);
+                assert!(take_flag(&mut args, "--prefix-tools"));
+                assert_eq!(args, vec!["mcp-server-fetch"]);
+            }
+            _ => panic!("Expected Run command"),
         }
     }
 
     #[test]
-    fn test_cli_parse_pick() {
-        let cli = Cli::parse_from(["mcpz", "pick", "mcp-server-time"]);
+    fn test_cli_parse_daemon() {
+        let cli = Cli::parse_from(["mcpz", "daemon", "-p", "4000", "time=mcp-server-time", "mcp-server-fetch"]);
+        match cli.command {
+            Commands::Daemon { packages, port, restart } => {
+                assert_eq!(packages, vec!["time=mcp-server-time", "mcp-server-fetch"]);
+                assert_eq!(port, 4000);
+                assert_eq!(restart, RestartPolicy::OnFailure { max: None });
+            }
+            _ => panic!("Expected Daemon command"),
+        }
+
+        assert!(matches!(
+            Cli::parse_from(["mcpz", "daemon", "--config", "fleet.toml"]).command,
+            Commands::Daemon { packages, .. } if packages.is_empty()
+        ));
+        assert!(matches!(Cli::parse_from(["mcpz", "ps"]).command, Commands::Ps));
+        assert!(matches!(
+            Cli::parse_from(["mcpz", "logs", "time", "-n", "5"]).command,
+            Commands::Logs { name, lines: 5 } if name == "time"
+        ));
+    }
+
+    #[test]
+    fn test_build_alias() {
+        let alias = build_alias(
+            "timeserver",
+            "mcp-server-time".to_string(),
+            Some("--tz 'America/New York'"),
+            &["TZ_DEBUG=1".to_string()],
+        )
+        .unwrap();
+        assert_eq!(alias.package, "mcp-server-time");
+        assert_eq!(alias.args, vec!["--tz", "America/New York"]);
+        assert_eq!(alias.env.get("TZ_DEBUG").map(String::as_str), Some("1"));
+        assert_eq!(format_alias(&alias), "mcp-server-time --tz America/New York (env: TZ_DEBUG)");
+
+        assert!(build_alias("time@1", "x".to_string(), None, &[]).is_err());
+        assert!(build_alias("./time", "x".to_string(), None, &[]).is_err());
+        assert!(build_alias("time", "x".to_string(), Some("'unclosed"), &[]).is_err());
+        assert!(build_alias("time", "x".to_string(), None, &["TOKEN".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_alias_serialization() {
+        let mut cache = PackageCache::default();
+        cache.set("time".to_string(), "mcp-server-time".to_string(), PackageType::Python);
+        cache.aliases.insert(
+            "timeserver".to_string(),
+            build_alias("timeserver", "mcp-server-time".to_string(), Some("--tz UTC"), &[]).unwrap(),
+        );
+
+        let toml = toml::to_string_pretty(&cache).unwrap();
+        let parsed: PackageCache = toml::from_str(&toml).unwrap();
+        assert_eq!(parsed.aliases, cache.aliases);
+        assert!(!toml.contains("env"));
+    }
+
+    #[test]
+    fn test_cli_parse_alias_add() {
+        let cli = Cli::parse_from([
+            "mcpz", "alias", "add", "timeserver", "mcp-server-time", "--args", "--tz UTC", "-e", "A=1",
+        ]);
         match cli.command {
-            Commands::Pick { package } => {
+            Commands::Alias { action: AliasAction::Add { name, package, args, env } } => {
+                assert_eq!(name, "timeserver");
                 assert_eq!(package, "mcp-server-time");
+                assert_eq!(args.as_deref(), Some("--tz UTC"));
+                assert_eq!(env, vec!["A=1"]);
             }
-            _ => panic!("Expected Pick command"),
+            _ => panic!("Expected alias add"),
         }
     }
 
     #[test]
-    fn test_cli_parse_clear_cache() {
-        let cli = Cli::parse_from(["mcpz", "clear-cache"]);
-        assert!(matches!(cli.command, Commands::ClearCache));
+    fn test_package_is_mcp_server() {
+        let pkg = |name: &str, keywords: &[&str]| PackageInfo {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+            description: "A time library".to_string(),
+            author: String::new(),
+            published: String::new(),
+            downloads: None,
+            registry: PackageType::Npm,
+            mcp_server: None,
+            keywords: keywords.iter().map(|k| k.to_string()).collect(),
+            links: BTreeMap::new(),
+        };
+
+        assert!(!pkg("moment-timezone", &["time", "date"]).is_mcp_server());
+        assert!(pkg("timekeeper", &["MCP"]).is_mcp_server());
+        assert!(pkg("timekeeper", &["mcp-server"]).is_mcp_server());
+        assert!(pkg("timekeeper", &["Topic :: Model Context Protocol"]).is_mcp_server());
+        assert!(pkg("mcp-server-time", &[]).is_mcp_server());
+        assert!(pkg("fastmcp", &[]).is_mcp_server());
+        assert!(pkg("@modelcontextprotocol/server-filesystem", &[]).is_mcp_server());
+        assert!(!pkg("timekeeper", &["mcpe-addon"]).is_mcp_server());
+
+        let mut listed = pkg("timekeeper", &[]);
+        listed.mcp_server = Some("io.github.x/time (stdio)".to_string());
+        assert!(listed.is_mcp_server());
     }
 
     #[test]
-    fn test_package_type_install_instructions() {
-        let npm_instructions = PackageType::Npm.install_instructions();
-        assert!(npm_instructions.contains("nodejs") || npm_instructions.contains("Node"));
+    fn test_cli_parse_search_mcp_only() {
+        let cli = Cli::parse_from(["mcpz", "search", "--mcp-only", "time"]);
+        assert!(matches!(cli.command, Commands::Search { mcp_only: true, .. }));
+        let cli = Cli::parse_from(["mcpz", "pick", "time"]);
+        assert!(matches!(cli.command, Commands::Pick { mcp_only: false, .. }));
+    }
 
-        let python_instructions = PackageType::Python.install_instructions();
-        assert!(python_instructions.contains("astral.sh"));
+    #[test]
+    fn test_cli_parse_json_output() {
+        let cli = Cli::try_parse_from(["mcpz", "search", "time", "--json"]).unwrap();
+        assert!(matches!(cli.command, Commands::Search { json: true, .. }));
+        let cli = Cli::try_parse_from(["mcpz", "pick", "time", "--print"]).unwrap();
+        assert!(matches!(cli.command, Commands::Pick { print: true, no_tui: false, .. }));
+        let cli = Cli::try_parse_from(["mcpz", "pick", "time", "--no-tui"]).unwrap();
+        assert!(matches!(cli.command, Commands::Pick { no_tui: true, .. }));
+        let cli = Cli::try_parse_from(["mcpz", "list", "--json"]).unwrap();
+        assert!(matches!(cli.command, Commands::List { json: true }));
+    }
 
-        let cargo_instructions = PackageType::Cargo.install_instructions();
-        assert!(cargo_instructions.contains("rustup") || cargo_instructions.contains("Rust"));
+    #[test]
+    fn test_cli_parse_search_paging() {
+        let cli = Cli::try_parse_from(["mcpz", "search", "time", "--limit", "25", "--page", "2", "--sort", "updated"])
+            .unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Search { limit: 25, page: 2, sort: SearchSort::Updated, .. }
+        ));
+        let cli = Cli::try_parse_from(["mcpz", "pick", "time"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Pick { limit: 10, page: 1, sort: SearchSort::Downloads, .. }
+        ));
+        assert!(Cli::try_parse_from(["mcpz", "search", "time", "--limit", "0"]).is_err());
+        assert!(Cli::try_parse_from(["mcpz", "search", "time", "--page", "0"]).is_err());
     }
 
     #[test]
-    fn test_cache_serialization() {
+    fn test_search_sort_apply() {
+        let package = |name: &str, published: &str, downloads: Option<u64>| PackageInfo {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+            description: String::new(),
+            author: String::new(),
+            published: published.to_string(),
+            downloads,
+            registry: PackageType::Npm,
+            mcp_server: None,
+            keywords: vec![],
+            links: BTreeMap::new(),
+        };
+        let mut packages = vec![
+            package("beta", "Unknown", Some(5)),
+            package("Alpha", "2024-01-01", None),
+            package("gamma", "2025-06-01", Some(50)),
+        ];
+        let names = |packages: &[PackageInfo]| packages.iter().map(|p| p.name.clone()).collect::<Vec<_>>();
+
+        SearchSort::Name.apply(&mut packages);
+        assert_eq!(names(&packages), vec!["Alpha", "beta", "gamma"]);
+        SearchSort::Updated.apply(&mut packages);
+        assert_eq!(names(&packages), vec!["gamma", "Alpha", "beta"]);
+        SearchSort::Downloads.apply(&mut packages);
+        assert_eq!(names(&packages), vec!["gamma", "beta", "Alpha"]);
+    }
+
+    #[test]
+    fn test_search_cache_key_includes_paging() {
+        let mut opts = SearchOptions::default();
+        assert_eq!(opts.cache_key("time"), "time");
+        opts.page.page = 2;
+        assert_eq!(opts.cache_key("time"), "time?limit=10&page=2&sort=Downloads");
+    }
+
+    #[test]
+    fn test_list_json_hides_alias_env_values() {
         let mut cache = PackageCache::default();
-        cache.set(
-            "test-search".to_string(),
-            "actual-package".to_string(),
+        cache.set("time".to_string(), "mcp-server-time".to_string(), PackageType::Python);
+        cache.aliases.insert(
+            "gh".to_string(),
+            Alias {
+                package: "github".to_string(),
+                args: vec!["--read-only".to_string()],
+                env: BTreeMap::from([("GITHUB_TOKEN".to_string(), "secret".to_string())]),
+            },
+        );
+
+        let list = list_json(&cache);
+        assert_eq!(list["packages"][0]["term"], "time");
+        assert_eq!(list["packages"][0]["registry"], "python");
+        assert_eq!(list["aliases"][0]["env"], json!(["GITHUB_TOKEN"]));
+        assert!(!list.to_string().contains("secret"));
+        assert_eq!(list["builtin_servers"].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_cache_update_merges_concurrent_writers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("package_mapping.toml");
+
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    PackageCache::update_at(&path, |cache| {
+                        cache.set(format!("term-{}", i), format!("pkg-{}", i), PackageType::Npm)
+                    })
+                    .unwrap();
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let cache = PackageCache::load_from(&path).unwrap();
+        assert_eq!(cache.packages.len(), 8);
+        assert_eq!(cache.get("term-3"), Some(("pkg-3".to_string(), PackageType::Npm)));
+        // Only the cache and its lock file remain; no temp files are left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_cache_parse_migrates_and_refuses_newer_schemas() {
+        let cache = PackageCache::parse("[packages]\ntime = [\"mcp-server-time\", \"python\"]\n").unwrap();
+        assert_eq!(cache.version, CACHE_VERSION);
+        assert_eq!(cache.get("time"), Some(("mcp-server-time".to_string(), PackageType::Python)));
+
+        // Fields from a future version are ignored only after the version check refuses it
+        let newer = format!("version = {}\n[profiles.work]\npackages = []\n[packages]\n", CACHE_VERSION + 1);
+        let err = PackageCache::parse(&newer).err().unwrap().to_string();
+        assert!(err.contains("upgrade mcpz"));
+    }
+
+    #[test]
+    fn test_cache_migrate_backs_up_and_stamps_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("package_mapping.toml");
+        assert_eq!(PackageCache::migrate_at(&path).unwrap(), None);
+
+        let legacy = "[packages]\ntime = [\"mcp-server-time\", \"python\"]\n";
+        fs::write(&path, legacy).unwrap();
+        assert_eq!(PackageCache::migrate_at(&path).unwrap(), Some(0));
+        assert_eq!(fs::read_to_string(path.with_extension("toml.bak")).unwrap(), legacy);
+
+        let migrated = fs::read_to_string(&path).unwrap();
+        assert!(migrated.starts_with(&format!("version = {}", CACHE_VERSION)));
+        assert!(migrated.contains("package = \"mcp-server-time\""));
+        assert_eq!(PackageCache::migrate_at(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn test_cli_parse_cache_migrate() {
+        let cli = Cli::try_parse_from(["mcpz", "cache", "migrate"]).unwrap();
+        assert!(matches!(cli.command, Commands::Cache { action: CacheAction::Migrate }));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cache_write_keeps_env_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("package_mapping.toml");
+
+        PackageCache::update_at(&path, |cache| {
+            cache
+                .env
+                .insert("github".to_string(), BTreeMap::from([("TOKEN".to_string(), "secret".to_string())]))
+        })
+        .unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_cache_remove() {
+        let mut cache = PackageCache::default();
+        cache.set("time".to_string(), "mcp-server-time".to_string(), PackageType::Python);
+        cache.set_pinned(
+            "time".to_string(),
+            "mcp-server-time".to_string(),
             PackageType::Python,
+            "1.2.3".to_string(),
         );
-        cache.set(
-            "another".to_string(),
-            "another-pkg".to_string(),
-            PackageType::Npm,
+
+        assert!(cache.remove("time"));
+        assert!(cache.get("time").is_none());
+        assert!(cache.get_pinned("time").is_none());
+        assert!(!cache.remove("time"));
+    }
+
+    #[test]
+    fn test_cache_default_args() {
+        let mut cache = PackageCache::default();
+        let run_args = vec!["--verbose".to_string()];
+        assert_eq!(cache.args_for("fs", &run_args), run_args);
+
+        cache.set_args("fs".to_string(), vec!["/home/me/code".to_string(), "/home/me/docs".to_string()]);
+        assert_eq!(cache.args_for("fs", &run_args), vec!["/home/me/code", "/home/me/docs", "--verbose"]);
+
+        let content = toml::to_string_pretty(&cache).unwrap();
+        assert_eq!(PackageCache::parse(&content).unwrap().args_for("fs", &[]), vec!["/home/me/code", "/home/me/docs"]);
+
+        cache.set_args("fs".to_string(), vec![]);
+        assert!(cache.args.is_empty());
+        cache.set_args("fs".to_string(), vec!["/tmp".to_string()]);
+        assert!(cache.remove("fs"));
+        assert!(cache.args.is_empty());
+    }
+
+    #[test]
+    fn test_cli_parse_default_args() {
+        let cli = Cli::parse_from(["mcpz", "cache", "set-args", "fs", "/tmp", "--read-only"]);
+        assert!(matches!(
+            cli.command,
+            Commands::Cache { action: CacheAction::SetArgs { term, args } } if term == "fs" && args == ["/tmp", "--read-only"]
+        ));
+        let cli = Cli::parse_from(["mcpz", "cache", "set-args", "fs"]);
+        assert!(matches!(cli.command, Commands::Cache { action: CacheAction::SetArgs { args, .. } } if args.is_empty()));
+
+        let cli = Cli::parse_from(["mcpz", "pick", "server-filesystem", "--args", "~/code '/my docs'"]);
+        assert!(matches!(cli.command, Commands::Pick { args: Some(args), .. } if args == "~/code '/my docs'"));
+    }
+
+    #[test]
+    fn test_cli_parse_cache_subcommands() {
+        let cli = Cli::parse_from(["mcpz", "cache", "rm", "time"]);
+        assert!(matches!(cli.command, Commands::Cache { action: CacheAction::Rm { term } } if term == "time"));
+
+        let cli = Cli::parse_from(["mcpz", "cache", "pin", "time", "pypi", "--package", "mcp-server-time"]);
+        match cli.command {
+            Commands::Cache { action: CacheAction::Pin { term, registry, package } } => {
+                assert_eq!(term, "time");
+                assert_eq!(registry, PackageType::Python);
+                assert_eq!(package.as_deref(), Some("mcp-server-time"));
+            }
+            _ => panic!("Expected cache pin"),
+        }
+
+        let cli = Cli::parse_from(["mcpz", "cache", "pin", "fs", "jsr"]);
+        assert!(matches!(
+            cli.command,
+            Commands::Cache { action: CacheAction::Pin { registry: PackageType::Deno, package: None, .. } }
+        ));
+    }
+
+    #[test]
+    fn test_cache_pinned_separate_from_mapping() {
+        let mut cache = PackageCache::default();
+        cache.set("time".to_string(), "mcp-server-time".to_string(), PackageType::Python);
+        cache.set_pinned(
+            "time".to_string(),
+            "mcp-server-time".to_string(),
+            PackageType::Python,
+            "1.2.3".to_string(),
         );
 
         let serialized = toml::to_string(&cache).unwrap();
         let deserialized: PackageCache = toml::from_str(&serialized).unwrap();
 
         assert_eq!(
-            deserialized.get("test-search"),
-            Some(("actual-package".to_string(), PackageType::Python))
+            deserialized.get("time"),
+            Some(("mcp-server-time".to_string(), PackageType::Python))
         );
         assert_eq!(
-            deserialized.get("another"),
-            Some(("another-pkg".to_string(), PackageType::Npm))
+            deserialized.get_pinned("time"),
+            Some(("mcp-server-time".to_string(), PackageType::Python, "1.2.3".to_string()))
         );
     }
 
+    #[test]
+    fn test_cache_without_pinned_table() {
+        let cache: PackageCache =
+            toml::from_str("[packages]\nfoo = [\"foo-pkg\", \"npm\"]\n").unwrap();
+        assert_eq!(cache.get("foo"), Some(("foo-pkg".to_string(), PackageType::Npm)));
+        assert!(cache.pinned.is_empty());
+    }
+
     // Shell server tests
 
     #[test]
@@ -1633,7 +5599,7 @@ mod tests {
                     Some(ServerType::Shell { working_dir, timeout, shell, allow, deny, no_stderr, verbose, http, .. }) => {
                         assert!(working_dir.is_none());
                         assert_eq!(timeout, 30);
-                        assert_eq!(shell, "/bin/sh");
+                        assert_eq!(shell, DEFAULT_SHELL);
                         assert!(allow.is_none());
                         assert!(deny.is_none());
                         assert!(!no_stderr);
@@ -1801,7 +5767,7 @@ mod tests {
     #[test]
     fn test_cli_parse_list() {
         let cli = Cli::parse_from(["mcpz", "list"]);
-        assert!(matches!(cli.command, Commands::List));
+        assert!(matches!(cli.command, Commands::List { json: false }));
     }
 
     #[test]
@@ -0,0 +1,271 @@
+use anyhow::Result;
+use crossterm::cursor;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Print, Stylize};
+use crossterm::terminal::{self, ClearType};
+use crossterm::{execute, queue};
+use std::io::{IsTerminal, Stderr, Write};
+
+/// Rows reserved for the preview pane below the list
+const PREVIEW_HEIGHT: u16 = 7;
+
+/// One choice offered by the picker
+pub struct PickItem {
+    /// Single-line label shown in the list and matched against the filter
+    pub label: String,
+    /// Detail lines shown in the preview pane while the item is highlighted
+    pub preview: Vec<String>,
+}
+
+/// Whether the full-screen picker can run: it reads keys from stdin and draws on
+/// stderr, so stdout stays free for `pick --print`
+pub fn available() -> bool {
+    std::io::stdin().is_terminal() && std::io::stderr().is_terminal()
+}
+
+/// Let the user choose one of `items`; returns its index, or None when cancelled
+pub fn pick(title: &str, items: &[PickItem]) -> Result<Option<usize>> {
+    let mut screen = Screen::enter()?;
+    let mut state = Picker::new(items);
+
+    loop {
+        screen.draw(title, &state, items)?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match state.handle_key(key, items) {
+            Some(Outcome::Selected(index)) => return Ok(Some(index)),
+            Some(Outcome::Cancelled) => return Ok(None),
+            None => {}
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Selected(usize),
+    Cancelled,
+}
+
+/// Filter text, the items matching it (best match first) and the highlighted row
+struct Picker {
+    query: String,
+    matches: Vec<usize>,
+    cursor: usize,
+}
+
+impl Picker {
+    fn new(items: &[PickItem]) -> Self {
+        Self {
+            query: String::new(),
+            matches: (0..items.len()).collect(),
+            cursor: 0,
+        }
+    }
+
+    fn selected(&self) -> Option<usize> {
+        self.matches.get(self.cursor).copied()
+    }
+
+    fn refilter(&mut self, items: &[PickItem]) {
+        let mut scored: Vec<(i64, usize)> = items
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| Some((fuzzy_score(&self.query, &item.label)?, index)))
+            .collect();
+        // Stable sort keeps the original (popularity) order among equal scores
+        scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+        self.matches = scored.into_iter().map(|(_, index)| index).collect();
+        self.cursor = 0;
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, items: &[PickItem]) -> Option<Outcome> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let last = self.matches.len().saturating_sub(1);
+        match key.code {
+            KeyCode::Esc => return Some(Outcome::Cancelled),
+            KeyCode::Char('c') if ctrl => return Some(Outcome::Cancelled),
+            KeyCode::Enter => return self.selected().map(Outcome::Selected),
+            KeyCode::Up => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Char('p') if ctrl => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Down => self.cursor = (self.cursor + 1).min(last),
+            KeyCode::Char('n') if ctrl => self.cursor = (self.cursor + 1).min(last),
+            KeyCode::PageUp => self.cursor = self.cursor.saturating_sub(10),
+            KeyCode::PageDown => self.cursor = (self.cursor + 10).min(last),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = last,
+            KeyCode::Backspace if !self.query.is_empty() => {
+                self.query.pop();
+                self.refilter(items);
+            }
+            KeyCode::Char(c) if !ctrl => {
+                self.query.push(c);
+                self.refilter(items);
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+/// Case-insensitive subsequence match: None if `query` doesn't match `text`,
+/// otherwise higher for consecutive runs, word starts and prefixes
+fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+
+    for q in query.chars().flat_map(char::to_lowercase) {
+        if q.is_whitespace() {
+            continue;
+        }
+        let found = position + text[position..].iter().position(|&c| c == q)?;
+        score += 1;
+        if previous == Some(found.wrapping_sub(1)) {
+            score += 5;
+        }
+        if found == 0 {
+            score += 15;
+        } else if !text[found - 1].is_alphanumeric() {
+            score += 10;
+        }
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
+
+/// Raw mode on the alternate screen, restored on drop (including on error)
+struct Screen {
+    out: Stderr,
+}
+
+impl Screen {
+    fn enter() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut out = std::io::stderr();
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(Self { out })
+    }
+
+    fn draw(&mut self, title: &str, state: &Picker, items: &[PickItem]) -> Result<()> {
+        // Some ptys report 0x0 until resized
+        let (width, height) = match terminal::size()? {
+            (0, _) | (_, 0) => (80, 24),
+            size => size,
+        };
+        let width = width as usize;
+        // Title, filter line, separator and help line around the list and preview
+        let list_height = height.saturating_sub(PREVIEW_HEIGHT + 4).max(1) as usize;
+        let offset = state.cursor.saturating_sub(list_height - 1);
+
+        let out = &mut self.out;
+        queue!(out, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+        let counts = format!(" ({}/{})", state.matches.len(), items.len());
+        queue!(out, Print(fit(title, width).bold()), Print(fit(&counts, width).dark_grey()))?;
+        queue!(out, cursor::MoveTo(0, 1), Print("> ".yellow()), Print(fit(&state.query, width)))?;
+
+        let visible = state.matches.iter().enumerate().skip(offset).take(list_height);
+        for (row, (i, &index)) in (2..).zip(visible) {
+            let label = fit(&items[index].label, width.saturating_sub(2));
+            queue!(out, cursor::MoveTo(0, row))?;
+            if i == state.cursor {
+                queue!(out, Print("▶ ".green()), Print(label.reverse()))?;
+            } else {
+                queue!(out, Print("  "), Print(label))?;
+            }
+        }
+
+        let preview_top = 2 + list_height as u16;
+        queue!(out, cursor::MoveTo(0, preview_top), Print("─".repeat(width).dark_grey()))?;
+        if let Some(index) = state.selected() {
+            for (i, line) in items[index].preview.iter().take(PREVIEW_HEIGHT as usize).enumerate() {
+                queue!(out, cursor::MoveTo(0, preview_top + 1 + i as u16), Print(fit(line, width)))?;
+            }
+        }
+
+        let help = "↑/↓ move · type to filter · Enter select · Esc cancel";
+        queue!(out, cursor::MoveTo(0, height.saturating_sub(1)), Print(fit(help, width).dark_grey()))?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(self.out, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Truncate to `width` characters so lines never wrap and break the layout
+fn fit(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(labels: &[&str]) -> Vec<PickItem> {
+        labels
+            .iter()
+            .map(|label| PickItem {
+                label: label.to_string(),
+                preview: vec![],
+            })
+            .collect()
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+        assert!(fuzzy_score("tme", "mcp-server-time").is_some());
+        assert!(fuzzy_score("xyz", "mcp-server-time").is_none());
+        assert!(fuzzy_score("TIME", "mcp-server-time").is_some());
+        // A whole word beats letters buried mid-word, and a prefix beats both
+        assert!(fuzzy_score("time", "mcp-server-time") > fuzzy_score("time", "stimulate"));
+        assert!(fuzzy_score("time", "time-tools") > fuzzy_score("time", "mcp-server-time"));
+    }
+
+    #[test]
+    fn test_picker_filters_and_selects() {
+        let items = items(&["mcp-server-time", "filesystem", "time-tools"]);
+        let mut picker = Picker::new(&items);
+        assert_eq!(picker.handle_key(key(KeyCode::Down), &items), None);
+        assert_eq!(picker.selected(), Some(1));
+
+        for c in "time".chars() {
+            picker.handle_key(key(KeyCode::Char(c)), &items);
+        }
+        assert_eq!(picker.matches.len(), 2);
+        assert_eq!(picker.cursor, 0);
+        // "time-tools" matches at the start of the label, so it ranks first
+        assert_eq!(picker.handle_key(key(KeyCode::Enter), &items), Some(Outcome::Selected(2)));
+
+        picker.handle_key(key(KeyCode::Char('z')), &items);
+        assert_eq!(picker.handle_key(key(KeyCode::Enter), &items), None);
+        picker.handle_key(key(KeyCode::Backspace), &items);
+        assert_eq!(picker.matches.len(), 2);
+    }
+
+    #[test]
+    fn test_picker_cursor_bounds_and_cancel() {
+        let items = items(&["a", "b"]);
+        let mut picker = Picker::new(&items);
+        picker.handle_key(key(KeyCode::Up), &items);
+        assert_eq!(picker.cursor, 0);
+        picker.handle_key(key(KeyCode::PageDown), &items);
+        assert_eq!(picker.cursor, 1);
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert_eq!(picker.handle_key(ctrl_c, &items), Some(Outcome::Cancelled));
+    }
+}
@@ -0,0 +1,223 @@
+use anyhow::{anyhow, Context, Result};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::client::{McpClient, StdioClient};
+
+/// Which side of the connection sent a recorded message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sender {
+    Client,
+    Server,
+}
+
+/// One line of a session recording
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// RFC 3339 timestamp with milliseconds
+    pub time: String,
+    pub from: Sender,
+    /// The JSON-RPC message, or the raw line as a string if it wasn't JSON
+    pub message: Value,
+}
+
+/// Appends every JSON-RPC message of a session to a JSONL file (`--record`)
+pub struct Recorder {
+    file: Mutex<File>,
+}
+
+impl Recorder {
+    /// Start a new recording, replacing any existing file
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("Failed to create recording {}", path.display()))?;
+        Ok(Self { file: Mutex::new(file) })
+    }
+
+    /// Continue an existing recording (used when a restarted server gets a new recorder)
+    pub fn append(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open recording {}", path.display()))?;
+        Ok(Self { file: Mutex::new(file) })
+    }
+
+    /// Record one line of the session; blank lines are skipped
+    pub fn record(&self, from: Sender, line: &[u8]) {
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+        let entry = Entry {
+            time: chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+            from,
+            message: serde_json::from_str(line).unwrap_or_else(|_| Value::String(line.to_string())),
+        };
+        if let Ok(json) = serde_json::to_string(&entry) {
+            let mut file = self.file.lock().unwrap();
+            let _ = writeln!(file, "{}", json).and_then(|_| file.flush());
+        }
+    }
+}
+
+/// Read a recording made with `--record`
+pub fn load(path: &Path) -> Result<Vec<Entry>> {
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).with_context(|| format!("{}:{}: not a recorded message", path.display(), i + 1))
+        })
+        .collect()
+}
+
+/// How a replayed request's response compared with the recording
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Matched,
+    /// The recording has no response to compare with
+    Unrecorded,
+    Differed { recorded: Value, replayed: Value },
+}
+
+/// Compare responses by `result`/`error` only, since the envelope is always the same
+pub fn compare(recorded: Option<&Value>, replayed: &Value) -> Outcome {
+    let Some(recorded) = recorded else {
+        return Outcome::Unrecorded;
+    };
+    let body = |message: &Value| (message.get("result").cloned(), message.get("error").cloned());
+    if body(recorded) == body(replayed) {
+        Outcome::Matched
+    } else {
+        Outcome::Differed {
+            recorded: recorded.clone(),
+            replayed: replayed.clone(),
+        }
+    }
+}
+
+/// The server's recorded response to the client request with `id`
+fn recorded_response<'a>(entries: &'a [Entry], id: &Value) -> Option<&'a Value> {
+    entries
+        .iter()
+        .filter(|entry| entry.from == Sender::Server)
+        .map(|entry| &entry.message)
+        .find(|message| message.get("id") == Some(id) && message.get("method").is_none())
+}
+
+/// Counts from a replay
+#[derive(Debug, Default, PartialEq)]
+pub struct ReplaySummary {
+    pub requests: usize,
+    pub differed: usize,
+}
+
+/// Send the client side of a recording to a freshly spawned server, in order, and
+/// report how each response compares with the recorded one
+pub fn replay(entries: &[Entry], cmd: Command, timeout: Duration) -> Result<ReplaySummary> {
+    let mut client = StdioClient::spawn(cmd)?;
+    let mut summary = ReplaySummary::default();
+
+    for entry in entries.iter().filter(|entry| entry.from == Sender::Client) {
+        let message = &entry.message;
+        if !message.is_object() {
+            continue;
+        }
+        let Some(response) = client.exchange(message, timeout)? else {
+            continue;
+        };
+        summary.requests += 1;
+
+        let method = message.get("method").and_then(|m| m.as_str()).unwrap_or("?");
+        let id = message.get("id").cloned().unwrap_or(Value::Null);
+        match compare(recorded_response(entries, &id), &response) {
+            Outcome::Matched => println!("  {} {} (id {})", "✓".green(), method, id),
+            Outcome::Unrecorded => println!("  {} {} (id {}): no recorded response", "?".yellow(), method, id),
+            Outcome::Differed { recorded, replayed } => {
+                summary.differed += 1;
+                println!("  {} {} (id {}): response differs", "✗".red(), method, id);
+                println!("      recorded: {}", recorded);
+                println!("      replayed: {}", replayed);
+            }
+        }
+    }
+
+    if summary.requests == 0 {
+        return Err(anyhow!("The recording has no client requests to replay"));
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_recorder_writes_loadable_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.jsonl");
+        let recorder = Recorder::create(&path).unwrap();
+        recorder.record(Sender::Client, b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"tools/list\"}\n");
+        recorder.record(Sender::Server, b"not json\n");
+        recorder.record(Sender::Server, b"\n");
+        drop(recorder);
+        Recorder::append(&path).unwrap().record(Sender::Server, b"{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{}}");
+
+        let entries = load(&path).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].from, Sender::Client);
+        assert_eq!(entries[0].message["method"], "tools/list");
+        assert_eq!(entries[1].message, json!("not json"));
+        assert_eq!(recorded_response(&entries, &json!(1)), Some(&json!({"jsonrpc": "2.0", "id": 1, "result": {}})));
+        assert!(chrono::DateTime::parse_from_rfc3339(&entries[0].time).is_ok());
+    }
+
+    #[test]
+    fn test_compare_responses() {
+        let recorded = json!({"jsonrpc": "2.0", "id": 2, "result": {"tools": []}});
+        assert_eq!(compare(Some(&recorded), &json!({"id": 2, "result": {"tools": []}})), Outcome::Matched);
+        assert_eq!(compare(None, &recorded), Outcome::Unrecorded);
+        assert!(matches!(
+            compare(Some(&recorded), &json!({"id": 2, "error": {"code": -32601}})),
+            Outcome::Differed { .. }
+        ));
+    }
+
+    #[test]
+    fn test_replay_drives_server() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.jsonl");
+        let recorder = Recorder::create(&path).unwrap();
+        recorder.record(Sender::Client, br#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+        recorder.record(Sender::Server, br#"{"jsonrpc":"2.0","id":1,"result":"pong"}"#);
+        recorder.record(Sender::Client, br#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#);
+        recorder.record(Sender::Client, br#"{"jsonrpc":"2.0","id":2,"method":"ping"}"#);
+        recorder.record(Sender::Server, br#"{"jsonrpc":"2.0","id":2,"result":"pang"}"#);
+        drop(recorder);
+
+        // Answers every request with "pong", so the second response differs
+        let mut cmd = Command::new("sh");
+        cmd.args([
+            "-c",
+            r#"while read -r line; do
+                id=$(echo "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+                [ -n "$id" ] && echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":\"pong\"}"
+            done"#,
+        ]);
+        let summary = replay(&load(&path).unwrap(), cmd, Duration::from_secs(5)).unwrap();
+        assert_eq!(summary, ReplaySummary { requests: 2, differed: 1 });
+    }
+}
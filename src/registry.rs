@@ -0,0 +1,1394 @@
+use anyhow::{Context, Result};
+use futures::future::join_all;
+use reqwest::Client;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::credentials::{credentials, Auth, Npmrc};
+use crate::{PackageInfo, PackageType, SearchSort};
+
+const DEFAULT_NPM_REGISTRY: &str = "https://registry.npmjs.org";
+const DEFAULT_PYPI_INDEX: &str = "https://pypi.org/simple";
+const DEFAULT_CRATES_API: &str = "https://crates.io/api/v1";
+const DEFAULT_JSR_API: &str = "https://api.jsr.io";
+const DEFAULT_MCP_REGISTRY: &str = "https://registry.modelcontextprotocol.io";
+/// Package search used for PyPI when `MCPZ_LIBRARIES_IO_KEY` is set
+const LIBRARIES_IO_API: &str = "https://libraries.io/api";
+
+/// Largest page the registry APIs accept
+const MAX_PAGE_SIZE: usize = 100;
+
+/// Which slice of each registry's results a search asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchPage {
+    /// Results per registry
+    pub limit: usize,
+    /// 1-based page number
+    pub page: usize,
+    pub sort: SearchSort,
+}
+
+impl Default for SearchPage {
+    fn default() -> Self {
+        Self {
+            limit: 10,
+            page: 1,
+            sort: SearchSort::Downloads,
+        }
+    }
+}
+
+impl SearchPage {
+    /// Number of results before this page
+    fn offset(&self) -> usize {
+        self.page.saturating_sub(1) * self.limit
+    }
+
+    /// Number of results up to the end of this page
+    fn end(&self) -> usize {
+        self.offset() + self.limit
+    }
+
+    /// Cut this page out of results that start at the first match, for
+    /// registries that can't page server-side
+    fn slice<T>(&self, items: Vec<T>) -> Vec<T> {
+        items.into_iter().skip(self.offset()).take(self.limit).collect()
+    }
+}
+
+/// Registry base URLs, overridable for private mirrors (Artifactory, Nexus, devpi...)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoints {
+    /// npm registry (`MCPZ_NPM_REGISTRY`)
+    pub npm_registry: String,
+    /// PyPI simple index (`MCPZ_PYPI_INDEX_URL`); the JSON API is expected next to it
+    pub pypi_index: String,
+    /// crates.io-compatible API base (`MCPZ_CRATES_API`)
+    pub crates_api: String,
+    /// JSR API base (`MCPZ_JSR_API`)
+    pub jsr_api: String,
+    /// Official MCP server registry (`MCPZ_MCP_REGISTRY`)
+    pub mcp_registry: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            npm_registry: DEFAULT_NPM_REGISTRY.to_string(),
+            pypi_index: DEFAULT_PYPI_INDEX.to_string(),
+            crates_api: DEFAULT_CRATES_API.to_string(),
+            jsr_api: DEFAULT_JSR_API.to_string(),
+            mcp_registry: DEFAULT_MCP_REGISTRY.to_string(),
+        }
+    }
+}
+
+impl Endpoints {
+    /// Read overrides using `lookup` (normally the process environment)
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let get = |key: &str, default: &str| {
+            lookup(key)
+                .map(|v| v.trim().trim_end_matches('/').to_string())
+                .filter(|v| !v.is_empty())
+                .unwrap_or_else(|| default.to_string())
+        };
+        Self {
+            npm_registry: get("MCPZ_NPM_REGISTRY", DEFAULT_NPM_REGISTRY),
+            pypi_index: get("MCPZ_PYPI_INDEX_URL", DEFAULT_PYPI_INDEX),
+            crates_api: get("MCPZ_CRATES_API", DEFAULT_CRATES_API),
+            jsr_api: get("MCPZ_JSR_API", DEFAULT_JSR_API),
+            mcp_registry: get("MCPZ_MCP_REGISTRY", DEFAULT_MCP_REGISTRY),
+        }
+    }
+
+    fn is_default_npm(&self) -> bool {
+        self.npm_registry == DEFAULT_NPM_REGISTRY
+    }
+
+    /// PyPI JSON API base: the index URL without its trailing `/simple`
+    pub fn pypi_json_base(&self) -> &str {
+        self.pypi_index
+            .strip_suffix("/simple")
+            .unwrap_or(&self.pypi_index)
+    }
+
+    /// Registry serving an npm package: its scope's `.npmrc` registry, else the default
+    pub fn npm_registry_for<'a>(&'a self, package: &str, npmrc: &'a Npmrc) -> &'a str {
+        package
+            .split_once('/')
+            .filter(|(scope, _)| scope.starts_with('@'))
+            .and_then(|(scope, _)| npmrc.registry_for_scope(scope))
+            .unwrap_or(&self.npm_registry)
+    }
+
+    fn npm_latest_url(&self, package: &str, npmrc: &Npmrc) -> String {
+        format!("{}/{}/latest", self.npm_registry_for(package, npmrc), package)
+    }
+
+    /// Registries an explicit `--registry-token` belongs to: npm, plus any overridden mirrors
+    pub fn token_registries(&self) -> Vec<&str> {
+        let mut registries = vec![self.npm_registry.as_str()];
+        for (url, default) in [
+            (&self.pypi_index, DEFAULT_PYPI_INDEX),
+            (&self.crates_api, DEFAULT_CRATES_API),
+            (&self.jsr_api, DEFAULT_JSR_API),
+        ] {
+            if url != default {
+                registries.push(url);
+            }
+        }
+        registries
+    }
+
+    /// Registry a runner will fetch `package` from, when it may need the explicit token
+    pub fn runner_registry(&self, package: &str, pkg_type: PackageType) -> Option<&str> {
+        let npmrc = credentials().npmrc();
+        match pkg_type {
+            PackageType::Npm => Some(self.npm_registry_for(package, npmrc)),
+            PackageType::Deno => package
+                .strip_prefix("npm:")
+                .map(|npm| self.npm_registry_for(npm, npmrc)),
+            PackageType::Python if self.pypi_index != DEFAULT_PYPI_INDEX => Some(&self.pypi_index),
+            _ => None,
+        }
+    }
+
+    fn pypi_project_url(&self, package: &str) -> String {
+        format!("{}/pypi/{}/json", self.pypi_json_base(), package)
+    }
+
+    /// npm's search API sorts by its own relevance score; the caller re-sorts the page
+    fn npm_search_url(&self, query: &str, page: &SearchPage) -> String {
+        format!(
+            "{}/-/v1/search?text={}&size={}&from={}",
+            self.npm_registry,
+            urlencoding::encode(query),
+            page.limit.min(MAX_PAGE_SIZE),
+            page.offset()
+        )
+    }
+
+    fn crates_search_url(&self, query: &str, page: &SearchPage) -> String {
+        let sort = match page.sort {
+            // Relevance order; the caller ranks the page by downloads
+            SearchSort::Downloads => "",
+            SearchSort::Name => "&sort=alpha",
+            SearchSort::Updated => "&sort=recent-updates",
+        };
+        format!(
+            "{}/crates?q={}&per_page={}&page={}{}",
+            self.crates_api,
+            urlencoding::encode(query),
+            page.limit.min(MAX_PAGE_SIZE),
+            page.page,
+            sort
+        )
+    }
+
+    fn crates_keyword_search_url(&self, query: &str, keyword: &str) -> String {
+        format!(
+            "{}/crates?q={}&keyword={}&per_page=100",
+            self.crates_api,
+            urlencoding::encode(query),
+            urlencoding::encode(keyword)
+        )
+    }
+
+    pub fn crate_url(&self, name: &str) -> String {
+        format!("{}/crates/{}", self.crates_api, name)
+    }
+
+    fn jsr_search_url(&self, query: &str, page: &SearchPage) -> String {
+        format!(
+            "{}/packages?query={}&limit={}&page={}",
+            self.jsr_api,
+            urlencoding::encode(query.trim_start_matches('@')),
+            page.limit.min(MAX_PAGE_SIZE),
+            page.page
+        )
+    }
+
+    /// The MCP registry pages by cursor, so this asks for everything up to the end of `page`
+    fn mcp_search_url(&self, query: &str, page: &SearchPage) -> String {
+        format!(
+            "{}/v0/servers?search={}&limit={}",
+            self.mcp_registry,
+            urlencoding::encode(query),
+            page.end().min(MAX_PAGE_SIZE)
+        )
+    }
+
+    fn jsr_package_url(&self, scope: &str, name: &str) -> String {
+        format!("{}/scopes/{}/packages/{}", self.jsr_api, scope, name)
+    }
+
+    /// Environment for a runner so it fetches from the same mirror mcpz searched
+    pub fn runner_env(&self, pkg_type: PackageType) -> Vec<(&'static str, String)> {
+        let mut env = vec![];
+        match pkg_type {
+            PackageType::Npm | PackageType::Deno if !self.is_default_npm() => {
+                env.push(("NPM_CONFIG_REGISTRY", self.npm_registry.clone()));
+                env.push(("BUN_CONFIG_REGISTRY", self.npm_registry.clone()));
+            }
+            PackageType::Python if self.pypi_index != DEFAULT_PYPI_INDEX => {
+                env.push(("UV_INDEX_URL", self.pypi_index.clone()));
+            }
+            _ => {}
+        }
+        if pkg_type == PackageType::Deno && self.jsr_api != DEFAULT_JSR_API {
+            env.push(("JSR_URL", self.jsr_api.clone()));
+        }
+        env
+    }
+}
+
+/// Registry endpoints for this process, read once from the environment
+pub fn endpoints() -> &'static Endpoints {
+    static ENDPOINTS: OnceLock<Endpoints> = OnceLock::new();
+    ENDPOINTS.get_or_init(|| Endpoints::from_lookup(|key| std::env::var(key).ok()))
+}
+
+/// Results of searching every registry for one query
+#[derive(Debug, Default)]
+pub struct SearchResults {
+    pub cargo: Vec<PackageInfo>,
+    pub pypi: Vec<PackageInfo>,
+    pub npm: Vec<PackageInfo>,
+    pub jsr: Vec<PackageInfo>,
+    /// Packages listed in the official MCP registry
+    pub mcp: Vec<PackageInfo>,
+}
+
+impl SearchResults {
+    /// All results in registry order (crates.io, PyPI, npm, JSR). MCP registry listings
+    /// annotate the matching package, or are appended if no package registry returned it.
+    pub fn into_all(self) -> Vec<PackageInfo> {
+        let mut all = self.cargo;
+        all.extend(self.pypi);
+        all.extend(self.npm);
+        all.extend(self.jsr);
+
+        for listed in self.mcp {
+            match all
+                .iter_mut()
+                .find(|p| p.name == listed.name && p.registry == listed.registry)
+            {
+                Some(existing) => existing.mcp_server = listed.mcp_server,
+                None => all.push(listed),
+            }
+        }
+        all
+    }
+}
+
+/// Run registry queries to completion from synchronous code
+pub fn block_on<F: Future>(future: F) -> Result<F::Output> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start async runtime")?;
+    Ok(runtime.block_on(future))
+}
+
+/// Build an HTTP client for registry APIs
+pub fn client(timeout: Duration) -> Option<Client> {
+    Client::builder()
+        .timeout(timeout)
+        .user_agent("mcpz")
+        .build()
+        .ok()
+}
+
+/// Start a GET request carrying any credentials configured for the URL
+fn authorized_get(client: &Client, url: &str) -> reqwest::RequestBuilder {
+    let request = client.get(url);
+    match credentials().authorization(url) {
+        Some(Auth::Bearer(token)) => request.bearer_auth(token),
+        Some(Auth::Basic { login, password }) => request.basic_auth(login, Some(password)),
+        None => request,
+    }
+}
+
+/// GET a URL and parse the JSON body, treating any failure as no data
+pub async fn get_json(client: &Client, url: &str) -> Option<serde_json::Value> {
+    let resp = authorized_get(client, url).send().await.ok()?;
+    if !resp.status().is_success() {
+        return None;
+    }
+    resp.json().await.ok()
+}
+
+/// GET a URL and return the raw body
+pub async fn get_bytes(client: &Client, url: &str) -> Result<Vec<u8>> {
+    let resp = authorized_get(client, url)
+        .send()
+        .await
+        .with_context(|| format!("Failed to download {}", url))?
+        .error_for_status()
+        .with_context(|| format!("Failed to download {}", url))?;
+    Ok(resp.bytes().await.context("Failed to read download")?.to_vec())
+}
+
+/// Query crates.io, PyPI, npm, JSR and the MCP registry concurrently
+pub async fn search_all(query: &str, page: SearchPage) -> SearchResults {
+    let (cargo, pypi, npm, jsr, mcp) = tokio::join!(
+        search_cargo(query, page),
+        search_pypi(query, page),
+        search_npm(query, page),
+        search_jsr(query, page),
+        search_mcp_registry(query, page)
+    );
+    SearchResults { cargo, pypi, npm, jsr, mcp }
+}
+
+/// Get npm download count for a package
+async fn get_npm_downloads(client: &Client, package: &str) -> Option<u64> {
+    let url = format!(
+        "https://api.npmjs.org/downloads/point/last-month/{}",
+        package
+    );
+    let data = get_json(client, &url).await?;
+    data.get("downloads").and_then(|v| v.as_u64())
+}
+
+/// Search the npm registry's HTTP search API and return matching packages
+pub async fn search_npm(query: &str, page: SearchPage) -> Vec<PackageInfo> {
+    let client = match client(Duration::from_secs(10)) {
+        Some(c) => c,
+        None => return vec![],
+    };
+
+    let Some(data) = get_json(&client, &endpoints().npm_search_url(query, &page)).await else {
+        return vec![];
+    };
+    let mut packages = parse_npm_search(&data);
+
+    // registry.npmjs.org includes monthly downloads; fetch them for mirrors that don't
+    let missing: Vec<usize> = (0..packages.len()).filter(|&i| packages[i].downloads.is_none()).collect();
+    let downloads = join_all(missing.iter().map(|&i| get_npm_downloads(&client, &packages[i].name))).await;
+    for (i, downloads) in missing.into_iter().zip(downloads) {
+        packages[i].downloads = downloads;
+    }
+
+    packages
+}
+
+/// Look up one npm package by exact name in the registry that serves it.
+/// Unlike the search API, this works for private scoped packages.
+pub async fn npm_package(package: &str) -> Option<PackageInfo> {
+    let client = client(Duration::from_secs(10))?;
+    let url = endpoints().npm_latest_url(package, credentials().npmrc());
+    let data = get_json(&client, &url).await?;
+    let mut info = parse_npm_manifest(&data)?;
+    info.downloads = get_npm_downloads(&client, &info.name).await;
+    Some(info)
+}
+
+/// Parse a package's `/latest` manifest
+fn parse_npm_manifest(data: &serde_json::Value) -> Option<PackageInfo> {
+    let name = data.get("name").and_then(|v| v.as_str()).filter(|n| !n.is_empty())?;
+    let version = data.get("version").and_then(|v| v.as_str()).unwrap_or("?");
+    let description = data
+        .get("description")
+        .and_then(|v| v.as_str())
+        .unwrap_or("No description");
+    // "author" is either a string or {name, email}
+    let author = data
+        .get("author")
+        .and_then(|a| a.as_str().or_else(|| a.get("name").and_then(|n| n.as_str())))
+        .or_else(|| data.pointer("/_npmUser/name").and_then(|v| v.as_str()))
+        .unwrap_or("Unknown");
+
+    Some(PackageInfo {
+        name: name.to_string(),
+        version: version.to_string(),
+        description: description.to_string(),
+        author: author.to_string(),
+        published: "Unknown".to_string(),
+        downloads: None,
+        registry: PackageType::Npm,
+        mcp_server: None,
+        keywords: string_array(data.get("keywords")),
+        links: BTreeMap::new(),
+    })
+}
+
+/// Strings from a JSON array, ignoring anything else
+fn string_array(value: Option<&serde_json::Value>) -> Vec<String> {
+    value
+        .and_then(|v| v.as_array())
+        .map(|items| items.iter().filter_map(|i| i.as_str()).map(String::from).collect())
+        .unwrap_or_default()
+}
+
+/// Parse a `/-/v1/search` response
+fn parse_npm_search(data: &serde_json::Value) -> Vec<PackageInfo> {
+    let mut packages = vec![];
+    let Some(objects) = data.get("objects").and_then(|o| o.as_array()) else {
+        return packages;
+    };
+
+    for object in objects {
+        let Some(item) = object.get("package") else {
+            continue;
+        };
+        let name = item.get("name").and_then(|v| v.as_str()).unwrap_or("");
+        if name.is_empty() {
+            continue;
+        }
+        let version = item.get("version").and_then(|v| v.as_str()).unwrap_or("?");
+        let description = item
+            .get("description")
+            .and_then(|v| v.as_str())
+            .unwrap_or("No description");
+        let author = item
+            .pointer("/publisher/username")
+            .or_else(|| item.pointer("/author/name"))
+            .and_then(|v| v.as_str())
+            .unwrap_or("Unknown");
+        let date = item.get("date").and_then(|v| v.as_str()).unwrap_or("Unknown");
+        let published = date.split('T').next().unwrap_or(date).to_string();
+        let links = item
+            .get("links")
+            .and_then(|l| l.as_object())
+            .map(|links| {
+                links
+                    .iter()
+                    .filter_map(|(kind, url)| Some((kind.clone(), url.as_str()?.to_string())))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        packages.push(PackageInfo {
+            name: name.to_string(),
+            version: version.to_string(),
+            description: description.to_string(),
+            author: author.to_string(),
+            published,
+            downloads: object.pointer("/downloads/monthly").and_then(|v| v.as_u64()),
+            registry: PackageType::Npm,
+            mcp_server: None,
+            keywords: string_array(item.get("keywords")),
+            links,
+        });
+    }
+
+    packages
+}
+
+/// Get PyPI download count for a package (last month)
+async fn get_pypi_downloads(client: &Client, package: &str) -> Option<u64> {
+    let url = format!("https://pypistats.org/api/packages/{}/recent", package);
+    let data = get_json(client, &url).await?;
+    data.get("data")
+        .and_then(|d| d.get("last_month"))
+        .and_then(|v| v.as_u64())
+}
+
+/// Fetch one PyPI project by exact name
+async fn fetch_pypi_package(client: &Client, pkg_name: &str) -> Option<PackageInfo> {
+    let data = get_json(client, &endpoints().pypi_project_url(pkg_name)).await?;
+    let info = data.get("info")?;
+
+    let name = info.get("name").and_then(|v| v.as_str()).unwrap_or("");
+    if name.is_empty() {
+        return None;
+    }
+    let version = info.get("version").and_then(|v| v.as_str()).unwrap_or("?");
+    let description = info
+        .get("summary")
+        .and_then(|v| v.as_str())
+        .unwrap_or("No description");
+    let author = info
+        .get("author")
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty())
+        .or_else(|| {
+            info.get("author_email")
+                .and_then(|v| v.as_str())
+                .map(|s| s.split('<').next().unwrap_or(s).trim())
+        })
+        .unwrap_or("Unknown");
+
+    // Get upload time from releases
+    let published = data
+        .get("urls")
+        .and_then(|u| u.as_array())
+        .and_then(|arr| arr.first())
+        .and_then(|u| u.get("upload_time"))
+        .and_then(|v| v.as_str())
+        .map(|s| s.split('T').next().unwrap_or(s))
+        .unwrap_or("Unknown")
+        .to_string();
+
+    Some(PackageInfo {
+        name: name.to_string(),
+        version: version.to_string(),
+        description: description.to_string(),
+        author: author.to_string(),
+        published,
+        downloads: None,
+        registry: PackageType::Python,
+        mcp_server: None,
+        keywords: pypi_keywords(info),
+        links: BTreeMap::new(),
+    })
+}
+
+/// PyPI keywords (a free-form comma/space separated string) followed by trove classifiers
+fn pypi_keywords(info: &serde_json::Value) -> Vec<String> {
+    let keywords = info.get("keywords").and_then(|v| v.as_str()).unwrap_or("");
+    let separator = if keywords.contains(',') { ',' } else { ' ' };
+    keywords
+        .split(separator)
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+        .map(String::from)
+        .chain(string_array(info.get("classifiers")))
+        .collect()
+}
+
+/// Search PyPI: through libraries.io when `MCPZ_LIBRARIES_IO_KEY` is set, otherwise
+/// by matching the query against the project names in the simple index
+pub async fn search_pypi(query: &str, page: SearchPage) -> Vec<PackageInfo> {
+    let client = match client(Duration::from_secs(10)) {
+        Some(c) => c,
+        None => return vec![],
+    };
+
+    let libraries_io_key = std::env::var("MCPZ_LIBRARIES_IO_KEY")
+        .ok()
+        .filter(|k| !k.trim().is_empty());
+    let mut packages = match libraries_io_key {
+        Some(key) => match get_json(&client, &libraries_io_search_url(query, &page, key.trim())).await {
+            Some(data) => parse_libraries_io(&data),
+            None => vec![],
+        },
+        None => search_pypi_names(&client, query, page).await,
+    };
+
+    let downloads = join_all(packages.iter().map(|p| get_pypi_downloads(&client, &p.name))).await;
+    for (pkg, downloads) in packages.iter_mut().zip(downloads) {
+        pkg.downloads = downloads;
+    }
+
+    packages
+}
+
+/// Rank simple-index project names against the query and fetch details for this page.
+/// Falls back to exact-name variations when the index can't be downloaded.
+async fn search_pypi_names(client: &Client, query: &str, page: SearchPage) -> Vec<PackageInfo> {
+    let candidates = match pypi_project_names().await {
+        Some(names) => rank_project_names(&names, query),
+        None => pypi_name_variations(query),
+    };
+    fetch_pypi_packages(client, &page.slice(candidates)).await
+}
+
+/// Look up a PyPI project by exact name, also trying `-`/`_` spellings, with download counts
+pub async fn pypi_packages(name: &str) -> Vec<PackageInfo> {
+    let client = match client(Duration::from_secs(10)) {
+        Some(c) => c,
+        None => return vec![],
+    };
+
+    let mut packages = fetch_pypi_packages(&client, &pypi_name_variations(name)).await;
+    let downloads = join_all(packages.iter().map(|p| get_pypi_downloads(&client, &p.name))).await;
+    for (pkg, downloads) in packages.iter_mut().zip(downloads) {
+        pkg.downloads = downloads;
+    }
+    packages
+}
+
+fn pypi_name_variations(name: &str) -> Vec<String> {
+    let mut variations = vec![name.to_string(), name.replace('-', "_"), name.replace('_', "-")];
+    variations.dedup();
+    variations
+}
+
+/// Fetch several projects concurrently, dropping missing ones and duplicates
+async fn fetch_pypi_packages(client: &Client, names: &[String]) -> Vec<PackageInfo> {
+    let found = join_all(names.iter().map(|n| fetch_pypi_package(client, n))).await;
+    let mut packages: Vec<PackageInfo> = vec![];
+    for pkg in found.into_iter().flatten() {
+        if !packages.iter().any(|p| p.name == pkg.name) {
+            packages.push(pkg);
+        }
+    }
+    packages
+}
+
+/// How long the downloaded list of PyPI project names is reused
+const PYPI_NAMES_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// All project names in the configured simple index, cached next to the search
+/// results (one file per index, so switching mirrors doesn't reuse the wrong list)
+async fn pypi_project_names() -> Option<Vec<String>> {
+    let index = &endpoints().pypi_index;
+    let digest = hex::encode(Sha256::digest(index.as_bytes()));
+    let path = dirs::cache_dir()?
+        .join("mcpz/search")
+        .join(format!("pypi-names-{}.txt", &digest[..12]));
+
+    let fresh = std::fs::metadata(&path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age < PYPI_NAMES_TTL);
+    if fresh {
+        if let Ok(content) = std::fs::read_to_string(&path) {
+            return Some(content.lines().map(String::from).collect());
+        }
+    }
+
+    // The full index is tens of megabytes, so allow much longer than a normal lookup
+    let client = client(Duration::from_secs(120))?;
+    let resp = authorized_get(&client, &format!("{}/", index))
+        .header(reqwest::header::ACCEPT, "application/vnd.pypi.simple.v1+json, text/html;q=0.1")
+        .send()
+        .await
+        .ok()?;
+    if !resp.status().is_success() {
+        return None;
+    }
+    let names = parse_simple_index(&resp.text().await.ok()?);
+    if names.is_empty() {
+        return None;
+    }
+
+    // A failed write only costs a re-download next time
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let _ = std::fs::write(&path, names.join("\n"));
+    Some(names)
+}
+
+/// Project names from a simple index root, in the JSON form (PEP 691) or HTML (PEP 503)
+fn parse_simple_index(body: &str) -> Vec<String> {
+    if let Ok(data) = serde_json::from_str::<serde_json::Value>(body) {
+        return data
+            .get("projects")
+            .and_then(|p| p.as_array())
+            .map(|projects| {
+                projects
+                    .iter()
+                    .filter_map(|p| p.get("name")?.as_str())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+    }
+
+    body.split("</a>")
+        .filter_map(|chunk| chunk.rsplit_once('>').map(|(_, name)| name.trim()))
+        .filter(|name| !name.is_empty() && !name.contains('<'))
+        .map(String::from)
+        .collect()
+}
+
+/// PEP 503 normalization: lowercase with runs of `-`, `_` and `.` collapsed to `-`
+fn normalize_project_name(name: &str) -> String {
+    name.to_lowercase()
+        .split(['-', '_', '.', ' '])
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Names containing every word of the query, best first: exact match, then prefix,
+/// then the whole query anywhere, then the words scattered; shorter names win ties
+fn rank_project_names(names: &[String], query: &str) -> Vec<String> {
+    let query = normalize_project_name(query);
+    if query.is_empty() {
+        return vec![];
+    }
+    let words: Vec<&str> = query.split('-').collect();
+
+    let mut ranked: Vec<(u8, &String)> = names
+        .iter()
+        .filter_map(|name| {
+            let normalized = normalize_project_name(name);
+            let tier = if normalized == query {
+                0
+            } else if normalized.starts_with(&query) {
+                1
+            } else if normalized.contains(&query) {
+                2
+            } else if words.iter().all(|w| normalized.contains(w)) {
+                3
+            } else {
+                return None;
+            };
+            Some((tier, name))
+        })
+        .collect();
+    ranked.sort_by(|a, b| (a.0, a.1.len(), a.1).cmp(&(b.0, b.1.len(), b.1)));
+    ranked.into_iter().map(|(_, name)| name.clone()).collect()
+}
+
+fn libraries_io_search_url(query: &str, page: &SearchPage, key: &str) -> String {
+    let sort = match page.sort {
+        SearchSort::Updated => "&sort=latest_release_published_at",
+        // Relevance order; the caller ranks the page
+        SearchSort::Downloads | SearchSort::Name => "",
+    };
+    format!(
+        "{}/search?q={}&platforms=PyPI&per_page={}&page={}{}&api_key={}",
+        LIBRARIES_IO_API,
+        urlencoding::encode(query),
+        page.limit.min(MAX_PAGE_SIZE),
+        page.page,
+        sort,
+        urlencoding::encode(key)
+    )
+}
+
+/// Parse a libraries.io `/search` response (download counts are filled in separately)
+fn parse_libraries_io(data: &serde_json::Value) -> Vec<PackageInfo> {
+    let Some(projects) = data.as_array() else {
+        return vec![];
+    };
+
+    projects
+        .iter()
+        .filter_map(|project| {
+            let text = |key: &str| project.get(key).and_then(|v| v.as_str()).filter(|v| !v.is_empty());
+            let name = text("name")?;
+            let published = text("latest_release_published_at")
+                .map(|s| s.split('T').next().unwrap_or(s))
+                .unwrap_or("Unknown");
+            let mut links = BTreeMap::new();
+            if let Some(repository) = text("repository_url") {
+                links.insert("repository".to_string(), repository.to_string());
+            }
+            if let Some(homepage) = text("homepage") {
+                links.insert("homepage".to_string(), homepage.to_string());
+            }
+
+            Some(PackageInfo {
+                name: name.to_string(),
+                version: text("latest_release_number").unwrap_or("?").to_string(),
+                description: text("description").unwrap_or("No description").to_string(),
+                author: "See PyPI".to_string(),
+                published: published.to_string(),
+                downloads: None,
+                registry: PackageType::Python,
+                mcp_server: None,
+                keywords: string_array(project.get("keywords")),
+                links,
+            })
+        })
+        .collect()
+}
+
+/// Search the JSR registry and return matching packages
+pub async fn search_jsr(query: &str, page: SearchPage) -> Vec<PackageInfo> {
+    let client = match client(Duration::from_secs(10)) {
+        Some(c) => c,
+        None => return vec![],
+    };
+
+    match get_json(&client, &endpoints().jsr_search_url(query, &page)).await {
+        Some(data) => parse_jsr_search(&data),
+        None => vec![],
+    }
+}
+
+/// Parse a JSR `/packages` search response
+fn parse_jsr_search(data: &serde_json::Value) -> Vec<PackageInfo> {
+    let mut packages = vec![];
+
+    if let Some(items) = data.get("items").and_then(|i| i.as_array()) {
+        for item in items {
+            let scope = item.get("scope").and_then(|v| v.as_str()).unwrap_or("");
+            let name = item.get("name").and_then(|v| v.as_str()).unwrap_or("");
+            if scope.is_empty() || name.is_empty() {
+                continue;
+            }
+            let version = item
+                .get("latestVersion")
+                .and_then(|v| v.as_str())
+                .unwrap_or("?");
+            let description = item
+                .get("description")
+                .and_then(|v| v.as_str())
+                .filter(|d| !d.is_empty())
+                .unwrap_or("No description");
+            let updated = item
+                .get("updatedAt")
+                .and_then(|v| v.as_str())
+                .unwrap_or("Unknown");
+            let published = updated.split('T').next().unwrap_or(updated).to_string();
+
+            packages.push(PackageInfo {
+                name: format!("@{}/{}", scope, name),
+                version: version.to_string(),
+                description: description.to_string(),
+                author: format!("@{}", scope),
+                published,
+                // JSR doesn't publish download counts
+                downloads: None,
+                registry: PackageType::Deno,
+                mcp_server: None,
+                keywords: vec![],
+                links: BTreeMap::new(),
+            });
+        }
+    }
+
+    packages
+}
+
+/// Search the official MCP registry; each server contributes one entry per runnable package
+pub async fn search_mcp_registry(query: &str, page: SearchPage) -> Vec<PackageInfo> {
+    let client = match client(Duration::from_secs(10)) {
+        Some(c) => c,
+        None => return vec![],
+    };
+
+    match get_json(&client, &endpoints().mcp_search_url(query, &page)).await {
+        Some(data) => page.slice(parse_mcp_registry(&data)),
+        None => vec![],
+    }
+}
+
+/// Parse a `/v0/servers` response. Accepts both the current shape
+/// (`{server: {packages: [{registryType, identifier, transport}]}}`) and the
+/// earlier flat one (`{packages: [{registry_name, name}]}`).
+fn parse_mcp_registry(data: &serde_json::Value) -> Vec<PackageInfo> {
+    let mut packages = vec![];
+    let Some(servers) = data.get("servers").and_then(|s| s.as_array()) else {
+        return packages;
+    };
+
+    for entry in servers {
+        let server = entry.get("server").unwrap_or(entry);
+        let server_name = server.get("name").and_then(|v| v.as_str()).unwrap_or("");
+        let description = server
+            .get("description")
+            .and_then(|v| v.as_str())
+            .unwrap_or("No description");
+        let published = entry
+            .pointer("/_meta/io.modelcontextprotocol.registry~1official/publishedAt")
+            .and_then(|v| v.as_str())
+            .map(|s| s.split('T').next().unwrap_or(s))
+            .unwrap_or("Unknown");
+
+        let Some(server_packages) = server.get("packages").and_then(|p| p.as_array()) else {
+            continue;
+        };
+        for pkg in server_packages {
+            let field = |keys: &[&str]| {
+                keys.iter()
+                    .find_map(|k| pkg.get(*k).and_then(|v| v.as_str()))
+                    .unwrap_or("")
+            };
+            let registry = match field(&["registryType", "registry_type", "registry_name"]) {
+                "npm" => PackageType::Npm,
+                "pypi" => PackageType::Python,
+                "cargo" | "crates" => PackageType::Cargo,
+                "oci" | "docker" => PackageType::Docker,
+                // nuget, mcpb and other package types can't be run by mcpz
+                _ => continue,
+            };
+            let identifier = field(&["identifier", "name"]);
+            if identifier.is_empty() {
+                continue;
+            }
+            let version = match field(&["version"]) {
+                "" => server.get("version").and_then(|v| v.as_str()).unwrap_or("?"),
+                v => v,
+            };
+
+            let transport = pkg
+                .pointer("/transport/type")
+                .and_then(|v| v.as_str())
+                .unwrap_or("stdio");
+            let listing = match field(&["runtimeHint", "runtime_hint"]) {
+                "" => format!("{} ({})", server_name, transport),
+                hint => format!("{} ({} via {})", server_name, transport, hint),
+            };
+
+            packages.push(PackageInfo {
+                name: identifier.to_string(),
+                version: version.to_string(),
+                description: description.to_string(),
+                author: server_name.split('/').next().unwrap_or(server_name).to_string(),
+                published: published.to_string(),
+                downloads: None,
+                registry,
+                mcp_server: Some(listing),
+                keywords: vec![],
+                links: BTreeMap::new(),
+            });
+        }
+    }
+
+    packages
+}
+
+/// Search crates.io registry and return matching packages (using API for full details)
+pub async fn search_cargo(query: &str, page: SearchPage) -> Vec<PackageInfo> {
+    let client = match client(Duration::from_secs(10)) {
+        Some(c) => c,
+        None => return vec![],
+    };
+
+    // Search listings omit keywords, so a second query finds which results are tagged `mcp`
+    // (among the 100 most relevant tagged crates)
+    let search_url = endpoints().crates_search_url(query, &page);
+    let keyword_url = endpoints().crates_keyword_search_url(query, "mcp");
+    let (data, tagged) = tokio::join!(get_json(&client, &search_url), get_json(&client, &keyword_url));
+    let mut packages = data.map(|d| parse_cargo_search(&d)).unwrap_or_default();
+    let tagged = tagged.map(|d| parse_cargo_search(&d)).unwrap_or_default();
+    for pkg in packages.iter_mut() {
+        if tagged.iter().any(|t| t.name == pkg.name) {
+            pkg.keywords.push("mcp".to_string());
+        }
+    }
+    packages
+}
+
+/// Parse a crates.io `/crates` search response
+fn parse_cargo_search(data: &serde_json::Value) -> Vec<PackageInfo> {
+    let mut packages = vec![];
+
+    if let Some(crates) = data.get("crates").and_then(|c| c.as_array()) {
+        for item in crates {
+            let name = item.get("name").and_then(|v| v.as_str()).unwrap_or("");
+            let version = item
+                .get("newest_version")
+                .and_then(|v| v.as_str())
+                .unwrap_or("?");
+            let description = item
+                .get("description")
+                .and_then(|v| v.as_str())
+                .unwrap_or("No description");
+            let downloads = item.get("downloads").and_then(|v| v.as_u64());
+            let updated = item
+                .get("updated_at")
+                .and_then(|v| v.as_str())
+                .map(|s| s.split('T').next().unwrap_or(s))
+                .unwrap_or("Unknown")
+                .to_string();
+
+            if !name.is_empty() {
+                packages.push(PackageInfo {
+                    name: name.to_string(),
+                    version: version.to_string(),
+                    description: description.to_string(),
+                    author: "See crates.io".to_string(),
+                    published: updated,
+                    downloads,
+                    registry: PackageType::Cargo,
+                    mcp_server: None,
+                    keywords: vec![],
+                    links: BTreeMap::new(),
+                });
+            }
+        }
+    }
+
+    packages
+}
+
+/// Look up the latest published version of a package in its registry
+pub async fn latest_version(package: &str, pkg_type: PackageType) -> Option<String> {
+    let client = client(Duration::from_secs(10))?;
+    let endpoints = endpoints();
+
+    let (url, pointer) = match pkg_type {
+        PackageType::Npm => (endpoints.npm_latest_url(package, credentials().npmrc()), "/version"),
+        PackageType::Python => (endpoints.pypi_project_url(package), "/info/version"),
+        PackageType::Cargo => (endpoints.crate_url(package), "/crate/max_stable_version"),
+        PackageType::Deno => match package.strip_prefix("npm:") {
+            Some(npm) => (endpoints.npm_latest_url(npm, credentials().npmrc()), "/version"),
+            None => {
+                let (scope, name) = package.strip_prefix('@')?.split_once('/')?;
+                (endpoints.jsr_package_url(scope, name), "/latestVersion")
+            }
+        },
+        // Image tags aren't ordered, so there is no "latest version" to look up
+        PackageType::Docker => return None,
+    };
+
+    let data = get_json(&client, &url).await?;
+    data.pointer(pointer)
+        .and_then(|v| v.as_str())
+        .map(|v| v.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoints_default_urls() {
+        let endpoints = Endpoints::default();
+        assert_eq!(
+            endpoints.npm_latest_url("@scope/pkg", &Npmrc::default()),
+            "https://registry.npmjs.org/@scope/pkg/latest"
+        );
+        assert_eq!(
+            endpoints.pypi_project_url("mcp-server-time"),
+            "https://pypi.org/pypi/mcp-server-time/json"
+        );
+        assert_eq!(
+            endpoints.npm_search_url("mcp server", &SearchPage::default()),
+            "https://registry.npmjs.org/-/v1/search?text=mcp%20server&size=10&from=0"
+        );
+        assert_eq!(
+            endpoints.crates_search_url("mcp server", &SearchPage::default()),
+            "https://crates.io/api/v1/crates?q=mcp%20server&per_page=10&page=1"
+        );
+        assert_eq!(
+            endpoints.crates_keyword_search_url("time", "mcp"),
+            "https://crates.io/api/v1/crates?q=time&keyword=mcp&per_page=100"
+        );
+        assert_eq!(
+            endpoints.jsr_search_url("@foo", &SearchPage::default()),
+            "https://api.jsr.io/packages?query=foo&limit=10&page=1"
+        );
+        assert!(endpoints.runner_env(PackageType::Npm).is_empty());
+        assert!(endpoints.runner_env(PackageType::Python).is_empty());
+    }
+
+    #[test]
+    fn test_search_page_urls_and_slice() {
+        let endpoints = Endpoints::from_lookup(|_| None);
+        let page = SearchPage {
+            limit: 25,
+            page: 3,
+            sort: SearchSort::Updated,
+        };
+        assert_eq!(
+            endpoints.crates_search_url("time", &page),
+            "https://crates.io/api/v1/crates?q=time&per_page=25&page=3&sort=recent-updates"
+        );
+        assert_eq!(
+            endpoints.jsr_search_url("time", &page),
+            "https://api.jsr.io/packages?query=time&limit=25&page=3"
+        );
+        assert_eq!(
+            endpoints.mcp_search_url("time", &page),
+            "https://registry.modelcontextprotocol.io/v0/servers?search=time&limit=75"
+        );
+
+        let page = SearchPage {
+            limit: 2,
+            page: 2,
+            sort: SearchSort::Name,
+        };
+        assert_eq!(page.slice(vec![1, 2, 3, 4, 5]), vec![3, 4]);
+        assert!(page.slice(vec![1, 2]).is_empty());
+    }
+
+    #[test]
+    fn test_endpoints_mirror_overrides() {
+        let endpoints = Endpoints::from_lookup(|key| match key {
+            "MCPZ_NPM_REGISTRY" => Some("https://artifactory.corp/api/npm/npm/".to_string()),
+            "MCPZ_PYPI_INDEX_URL" => Some("https://artifactory.corp/api/pypi/pypi/simple".to_string()),
+            "MCPZ_CRATES_API" => Some("  ".to_string()),
+            _ => None,
+        });
+
+        assert_eq!(
+            endpoints.npm_latest_url("left-pad", &Npmrc::default()),
+            "https://artifactory.corp/api/npm/npm/left-pad/latest"
+        );
+        assert_eq!(
+            endpoints.pypi_project_url("mcp-server-time"),
+            "https://artifactory.corp/api/pypi/pypi/pypi/mcp-server-time/json"
+        );
+        // Blank values fall back to the public registry
+        assert_eq!(endpoints.crates_api, DEFAULT_CRATES_API);
+
+        let npm_env = endpoints.runner_env(PackageType::Npm);
+        assert!(npm_env.contains(&("NPM_CONFIG_REGISTRY", "https://artifactory.corp/api/npm/npm".to_string())));
+        let python_env = endpoints.runner_env(PackageType::Python);
+        assert_eq!(
+            python_env,
+            vec![("UV_INDEX_URL", "https://artifactory.corp/api/pypi/pypi/simple".to_string())]
+        );
+        assert!(endpoints.runner_env(PackageType::Cargo).is_empty());
+    }
+
+    #[test]
+    fn test_npm_scope_registry_and_token_registries() {
+        let endpoints = Endpoints::from_lookup(|key| {
+            (key == "MCPZ_PYPI_INDEX_URL").then(|| "https://pypi.corp.example/simple".to_string())
+        });
+        let npmrc = Npmrc::parse("@mycorp:registry=https://npm.corp.example/\n", |_| None);
+
+        assert_eq!(
+            endpoints.npm_latest_url("@mycorp/internal-mcp", &npmrc),
+            "https://npm.corp.example/@mycorp/internal-mcp/latest"
+        );
+        assert_eq!(
+            endpoints.npm_latest_url("@other/pkg", &npmrc),
+            "https://registry.npmjs.org/@other/pkg/latest"
+        );
+        assert_eq!(
+            endpoints.token_registries(),
+            vec!["https://registry.npmjs.org", "https://pypi.corp.example/simple"]
+        );
+    }
+
+    #[test]
+    fn test_parse_npm_manifest() {
+        let data = serde_json::json!({
+            "name": "@mycorp/internal-mcp",
+            "version": "2.1.0",
+            "author": {"name": "Platform Team"}
+        });
+
+        let info = parse_npm_manifest(&data).unwrap();
+        assert_eq!(info.name, "@mycorp/internal-mcp");
+        assert_eq!(info.version, "2.1.0");
+        assert_eq!(info.author, "Platform Team");
+        assert_eq!(info.description, "No description");
+        assert!(parse_npm_manifest(&serde_json::json!({"error": "not found"})).is_none());
+    }
+
+    #[test]
+    fn test_block_on() {
+        let value = block_on(async { 1 + 1 }).unwrap();
+        assert_eq!(value, 2);
+    }
+
+    #[test]
+    fn test_search_results_into_all_keeps_registry_order() {
+        let pkg = |name: &str, registry| PackageInfo {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+            description: String::new(),
+            author: String::new(),
+            published: String::new(),
+            downloads: None,
+            registry,
+            mcp_server: None,
+            keywords: vec![],
+            links: BTreeMap::new(),
+        };
+        let results = SearchResults {
+            cargo: vec![pkg("a", PackageType::Cargo)],
+            pypi: vec![pkg("b", PackageType::Python)],
+            npm: vec![pkg("c", PackageType::Npm)],
+            jsr: vec![pkg("@d/e", PackageType::Deno)],
+            mcp: vec![],
+        };
+
+        let names: Vec<String> = results.into_all().into_iter().map(|p| p.name).collect();
+        assert_eq!(names, vec!["a", "b", "c", "@d/e"]);
+    }
+
+    #[test]
+    fn test_search_results_merges_mcp_listings() {
+        let pkg = |name: &str, registry, mcp_server: Option<&str>| PackageInfo {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+            description: String::new(),
+            author: String::new(),
+            published: String::new(),
+            downloads: None,
+            registry,
+            mcp_server: mcp_server.map(String::from),
+            keywords: vec![],
+            links: BTreeMap::new(),
+        };
+        let results = SearchResults {
+            npm: vec![pkg("mcp-time", PackageType::Npm, None)],
+            mcp: vec![
+                pkg("mcp-time", PackageType::Npm, Some("io.github.x/time (stdio)")),
+                pkg("ghcr.io/x/time", PackageType::Docker, Some("io.github.x/time (stdio)")),
+            ],
+            ..Default::default()
+        };
+
+        let all = results.into_all();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].mcp_server.as_deref(), Some("io.github.x/time (stdio)"));
+        assert_eq!(all[1].registry, PackageType::Docker);
+    }
+
+    #[test]
+    fn test_parse_mcp_registry() {
+        let data = serde_json::json!({
+            "servers": [
+                {
+                    "server": {
+                        "name": "io.github.acme/weather",
+                        "description": "Weather forecasts",
+                        "version": "1.2.0",
+                        "packages": [
+                            {"registryType": "npm", "identifier": "@acme/weather-mcp", "version": "1.2.0",
+                             "runtimeHint": "npx", "transport": {"type": "stdio"}},
+                            {"registryType": "nuget", "identifier": "Acme.Weather"}
+                        ]
+                    },
+                    "_meta": {"io.modelcontextprotocol.registry/official": {"publishedAt": "2025-09-01T00:00:00Z"}}
+                },
+                {
+                    "name": "io.github.old/server",
+                    "description": "Legacy shape",
+                    "packages": [{"registry_name": "pypi", "name": "old-server", "version": "0.1.0"}]
+                }
+            ]
+        });
+
+        let packages = parse_mcp_registry(&data);
+        assert_eq!(packages.len(), 2);
+        assert_eq!(packages[0].name, "@acme/weather-mcp");
+        assert_eq!(packages[0].registry, PackageType::Npm);
+        assert_eq!(packages[0].published, "2025-09-01");
+        assert_eq!(
+            packages[0].mcp_server.as_deref(),
+            Some("io.github.acme/weather (stdio via npx)")
+        );
+        assert_eq!(packages[1].name, "old-server");
+        assert_eq!(packages[1].registry, PackageType::Python);
+        assert_eq!(packages[1].mcp_server.as_deref(), Some("io.github.old/server (stdio)"));
+    }
+
+    #[test]
+    fn test_parse_cargo_search() {
+        let data = serde_json::json!({
+            "crates": [{
+                "name": "mcp-server-foo",
+                "newest_version": "0.2.0",
+                "description": "Foo server",
+                "downloads": 1234,
+                "updated_at": "2025-01-02T03:04:05Z"
+            }]
+        });
+
+        let packages = parse_cargo_search(&data);
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].name, "mcp-server-foo");
+        assert_eq!(packages[0].version, "0.2.0");
+        assert_eq!(packages[0].downloads, Some(1234));
+        assert_eq!(packages[0].published, "2025-01-02");
+        assert_eq!(packages[0].registry, PackageType::Cargo);
+    }
+
+    #[test]
+    fn test_parse_jsr_search() {
+        let data = serde_json::json!({
+            "items": [
+                {"scope": "foo", "name": "mcp", "latestVersion": "1.0.0", "description": "", "updatedAt": "2025-05-06T00:00:00Z"},
+                {"scope": "", "name": "skipped"}
+            ]
+        });
+
+        let packages = parse_jsr_search(&data);
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].name, "@foo/mcp");
+        assert_eq!(packages[0].description, "No description");
+        assert_eq!(packages[0].registry, PackageType::Deno);
+        assert!(packages[0].downloads.is_none());
+    }
+
+    #[test]
+    fn test_parse_npm_search() {
+        let data = serde_json::json!({"objects": [
+            {
+                "package": {
+                    "name": "mcp-server-bar",
+                    "version": "3.0.0",
+                    "publisher": {"username": "bob"},
+                    "date": "2025-07-08T09:10:11Z",
+                    "keywords": ["mcp", 1],
+                    "links": {"npm": "https://www.npmjs.com/package/mcp-server-bar", "repository": "https://github.com/bob/bar"}
+                },
+                "downloads": {"monthly": 1234, "weekly": 300}
+            },
+            {"package": {"name": "mcp-server-baz", "author": {"name": "Carol"}}},
+            {"package": {"name": ""}}
+        ]});
+
+        let packages = parse_npm_search(&data);
+        assert_eq!(packages.len(), 2);
+        assert_eq!(packages[0].author, "bob");
+        assert_eq!(packages[0].keywords, vec!["mcp"]);
+        assert_eq!(packages[0].published, "2025-07-08");
+        assert_eq!(packages[0].downloads, Some(1234));
+        assert_eq!(packages[0].links["repository"], "https://github.com/bob/bar");
+        assert_eq!(packages[0].registry, PackageType::Npm);
+        assert_eq!(packages[1].author, "Carol");
+        assert_eq!(packages[1].downloads, None);
+    }
+
+    #[test]
+    fn test_parse_simple_index() {
+        let json = r#"{"meta": {"api-version": "1.0"}, "projects": [{"name": "mcp-server-time"}, {"name": "Flask"}]}"#;
+        assert_eq!(parse_simple_index(json), vec!["mcp-server-time", "Flask"]);
+
+        let html = "<!DOCTYPE html><html><body>\n<a href=\"/simple/mcp-server-time/\">mcp-server-time</a>\n<a href=\"/simple/flask/\">Flask</a>\n</body></html>";
+        assert_eq!(parse_simple_index(html), vec!["mcp-server-time", "Flask"]);
+    }
+
+    #[test]
+    fn test_rank_project_names() {
+        let names: Vec<String> = ["sqlite-utils", "mcp-server-sqlite", "SQLite", "pysqlite3", "sqlite_mcp", "flask"]
+            .iter()
+            .map(|n| n.to_string())
+            .collect();
+
+        assert_eq!(
+            rank_project_names(&names, "sqlite"),
+            vec!["SQLite", "sqlite_mcp", "sqlite-utils", "pysqlite3", "mcp-server-sqlite"]
+        );
+        assert_eq!(rank_project_names(&names, "sqlite mcp"), vec!["sqlite_mcp", "mcp-server-sqlite"]);
+        assert!(rank_project_names(&names, "  ").is_empty());
+    }
+
+    #[test]
+    fn test_parse_libraries_io() {
+        let data = serde_json::json!([
+            {
+                "name": "mcp-server-sqlite",
+                "description": "SQLite MCP server",
+                "latest_release_number": "0.6.2",
+                "latest_release_published_at": "2025-03-04T05:06:07.000Z",
+                "repository_url": "https://github.com/modelcontextprotocol/servers",
+                "homepage": "",
+                "keywords": ["mcp"]
+            },
+            {"description": "no name"}
+        ]);
+
+        let packages = parse_libraries_io(&data);
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].version, "0.6.2");
+        assert_eq!(packages[0].published, "2025-03-04");
+        assert_eq!(packages[0].registry, PackageType::Python);
+        assert_eq!(packages[0].keywords, vec!["mcp"]);
+        assert_eq!(packages[0].links.len(), 1);
+        assert!(libraries_io_search_url("sql lite", &SearchPage::default(), "k")
+            .ends_with("/search?q=sql%20lite&platforms=PyPI&per_page=10&page=1&api_key=k"));
+    }
+
+    #[test]
+    fn test_pypi_keywords() {
+        let info = serde_json::json!({
+            "keywords": "time, timezone, mcp",
+            "classifiers": ["Programming Language :: Python :: 3"]
+        });
+        assert_eq!(
+            pypi_keywords(&info),
+            vec!["time", "timezone", "mcp", "Programming Language :: Python :: 3"]
+        );
+        assert_eq!(pypi_keywords(&serde_json::json!({"keywords": "llm mcp"})), vec!["llm", "mcp"]);
+        assert!(pypi_keywords(&serde_json::json!({"keywords": null})).is_empty());
+    }
+
+    #[test]
+    fn test_latest_version_docker_is_none() {
+        let version = block_on(latest_version("ghcr.io/foo/bar", PackageType::Docker)).unwrap();
+        assert!(version.is_none());
+    }
+}
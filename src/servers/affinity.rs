@@ -0,0 +1,231 @@
+use anyhow::Result;
+use colored::Colorize;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use super::common::{ClientAnswer, CompletionRef, McpPrompt, McpResource, McpServer, McpTool, Notifier, RequestContext};
+
+/// `affinity` of a gateway server: which of its processes a session's calls go to
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Affinity {
+    /// Every call of a session goes to the same replica
+    Session,
+    /// Each session gets a copy of the server of its own, stopped when the session ends
+    Dedicated,
+}
+
+/// Starts another copy of a server, along with whatever has to live as long as it does
+/// (credentials its package needs, say)
+pub type Launcher = Box<dyn Fn() -> Result<(Box<dyn McpServer + Send + Sync>, Box<dyn Send + Sync>)> + Send + Sync>;
+
+/// A started copy, kept for as long as its session lasts
+struct Dedicated {
+    server: Box<dyn McpServer + Send + Sync>,
+    _keep: Box<dyn Send + Sync>,
+}
+
+/// A session's copy, started on its first call
+type Slot = Arc<Mutex<Option<Arc<Dedicated>>>>;
+
+/// A server started afresh for each HTTP session (`affinity = "dedicated"`), for servers
+/// that keep per-connection state such as an open transaction. The first copy lists the
+/// tools and serves stdio, which is a single session.
+pub struct PerSession {
+    name: String,
+    first: Box<dyn McpServer + Send + Sync>,
+    launch: Launcher,
+    sessions: Mutex<HashMap<String, Slot>>,
+}
+
+impl PerSession {
+    pub fn new(name: &str, first: Box<dyn McpServer + Send + Sync>, launch: Launcher) -> Self {
+        Self {
+            name: name.to_string(),
+            first,
+            launch,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The session's copy, starting it if this is the session's first call. Only calls of
+    /// the same session wait for the start.
+    fn session(&self, session_id: &str) -> Result<Arc<Dedicated>> {
+        let slot = self.sessions.lock().unwrap().entry(session_id.to_string()).or_default().clone();
+        let mut slot = slot.lock().unwrap();
+        if let Some(dedicated) = &*slot {
+            return Ok(dedicated.clone());
+        }
+        let (server, keep) = (self.launch)()?;
+        let dedicated = Arc::new(Dedicated { server, _keep: keep });
+        *slot = Some(dedicated.clone());
+        Ok(dedicated)
+    }
+}
+
+impl McpServer for PerSession {
+    fn name(&self) -> &str {
+        self.first.name()
+    }
+
+    fn version(&self) -> &str {
+        self.first.version()
+    }
+
+    fn tools(&self) -> Vec<McpTool> {
+        self.first.tools()
+    }
+
+    fn call_tool(&self, name: &str, arguments: &Value) -> Result<Value> {
+        self.call_tool_with_context(name, arguments, &RequestContext::default())
+    }
+
+    fn call_tool_with_context(&self, name: &str, arguments: &Value, ctx: &RequestContext) -> Result<Value> {
+        match &ctx.session_id {
+            Some(session_id) => self.session(session_id)?.server.call_tool_with_context(name, arguments, ctx),
+            None => self.first.call_tool_with_context(name, arguments, ctx),
+        }
+    }
+
+    fn has_resources(&self) -> bool {
+        self.first.has_resources()
+    }
+
+    fn resources(&self, ctx: &RequestContext) -> Vec<McpResource> {
+        self.first.resources(ctx)
+    }
+
+    fn read_resource(&self, uri: &str, ctx: &RequestContext) -> Result<Value> {
+        self.first.read_resource(uri, ctx)
+    }
+
+    fn has_subscriptions(&self) -> bool {
+        self.first.has_subscriptions()
+    }
+
+    fn subscribe(&self, uri: &str, ctx: &RequestContext) -> Result<()> {
+        self.first.subscribe(uri, ctx)
+    }
+
+    fn unsubscribe(&self, uri: &str, ctx: &RequestContext) -> Result<()> {
+        self.first.unsubscribe(uri, ctx)
+    }
+
+    fn has_prompts(&self) -> bool {
+        self.first.has_prompts()
+    }
+
+    fn prompts(&self) -> Vec<McpPrompt> {
+        self.first.prompts()
+    }
+
+    fn get_prompt(&self, name: &str, arguments: &Value) -> Result<Value> {
+        self.first.get_prompt(name, arguments)
+    }
+
+    fn has_completions(&self) -> bool {
+        self.first.has_completions()
+    }
+
+    fn complete(
+        &self,
+        reference: &CompletionRef,
+        argument: &str,
+        value: &str,
+        ctx: &RequestContext,
+    ) -> Result<Vec<String>> {
+        self.first.complete(reference, argument, value, ctx)
+    }
+
+    fn on_initialize(&self, params: &Value, ctx: &RequestContext) {
+        self.first.on_initialize(params, ctx)
+    }
+
+    fn on_notification(&self, method: &str, params: &Value, ctx: &RequestContext) {
+        self.first.on_notification(method, params, ctx)
+    }
+
+    fn on_response(&self, id: &Value, response: ClientAnswer, ctx: &RequestContext) {
+        self.first.on_response(id, response, ctx)
+    }
+
+    fn on_session_end(&self, session_id: Option<&str>) {
+        let Some(session_id) = session_id else {
+            return self.first.on_session_end(None);
+        };
+        let slot = self.sessions.lock().unwrap().remove(session_id);
+        // Calls still in flight hold the copy; it stops once they're done
+        if let Some(dedicated) = slot.and_then(|slot| slot.lock().unwrap().take()) {
+            eprintln!("{}", format!("[mcpz] Stopping {} of session {}", self.name, session_id).cyan());
+            dedicated.server.on_session_end(Some(session_id));
+        }
+    }
+
+    fn notifier(&self) -> Option<&Notifier> {
+        self.first.notifier()
+    }
+
+    fn verbose(&self) -> bool {
+        self.first.verbose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    /// Answers with the number it was started as
+    struct Instance(u64);
+
+    impl McpServer for Instance {
+        fn name(&self) -> &str {
+            "copy"
+        }
+
+        fn version(&self) -> &str {
+            "1.0.0"
+        }
+
+        fn tools(&self) -> Vec<McpTool> {
+            vec![]
+        }
+
+        fn call_tool(&self, _name: &str, _arguments: &Value) -> Result<Value> {
+            Ok(json!(self.0))
+        }
+
+        fn verbose(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn test_copy_per_session() {
+        let started = Arc::new(AtomicU64::new(0));
+        let counter = started.clone();
+        let launch: Launcher = Box::new(move || {
+            let number = counter.fetch_add(1, Ordering::Relaxed) + 1;
+            Ok((Box::new(Instance(number)), Box::new(())))
+        });
+        let servers = PerSession::new("db", Box::new(Instance(0)), launch);
+        let call = |session: Option<&str>| {
+            let ctx = RequestContext { session_id: session.map(str::to_string), ..Default::default() };
+            servers.call_tool_with_context("query", &json!({}), &ctx).unwrap()
+        };
+
+        assert_eq!(call(None), json!(0));
+        assert_eq!(call(Some("a")), json!(1));
+        assert_eq!(call(Some("b")), json!(2));
+        assert_eq!(call(Some("a")), json!(1));
+        assert_eq!(servers.sessions.lock().unwrap().len(), 2);
+
+        servers.on_session_end(Some("a"));
+        assert_eq!(servers.sessions.lock().unwrap().len(), 1);
+        assert_eq!(call(Some("b")), json!(2));
+        assert_eq!(started.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,400 @@
+use anyhow::{anyhow, Context, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+/// Archive formats the filesystem server's `create_archive` and `extract_archive` handle
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveFormat {
+    Zip,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "zip" => Some(ArchiveFormat::Zip),
+            "tar.gz" | "tgz" => Some(ArchiveFormat::TarGz),
+            _ => None,
+        }
+    }
+
+    /// The format an archive's file name implies
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Files an archive was made of or unpacked into, and entries left out: symlinks, and in
+/// archives being extracted, links and devices as well
+#[derive(Debug, Default, PartialEq)]
+pub struct ArchiveCounts {
+    pub files: usize,
+    pub skipped: usize,
+}
+
+/// What an archive entry is
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EntryKind {
+    File,
+    Directory,
+    Other,
+}
+
+/// Pack `source` (a file, or a directory with everything in it) into a new archive at
+/// `destination`. Entry names start with `source`'s own name. Symlinks are left out, and so
+/// are paths `excluded` holds, without being counted.
+pub fn create(
+    source: &Path,
+    destination: &Path,
+    format: ArchiveFormat,
+    excluded: &dyn Fn(&Path) -> bool,
+) -> Result<ArchiveCounts> {
+    let base = source.parent().unwrap_or(source);
+    let mut counts = ArchiveCounts::default();
+    let mut entries = vec![];
+    collect(source, base, destination, excluded, &mut entries, &mut counts)?;
+
+    let out = File::create(destination).with_context(|| format!("Failed to create {}", destination.display()))?;
+    match format {
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipWriter::new(out);
+            let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+            for (path, name, kind) in entries {
+                if kind == EntryKind::Directory {
+                    zip.add_directory(name, options)?;
+                } else {
+                    zip.start_file(name, options.unix_permissions(file_mode(&path)?))?;
+                    io::copy(&mut File::open(&path)?, &mut zip)?;
+                    counts.files += 1;
+                }
+            }
+            zip.finish()?;
+        }
+        ArchiveFormat::TarGz => {
+            let mut tar = tar::Builder::new(GzEncoder::new(out, Compression::default()));
+            for (path, name, kind) in entries {
+                if kind == EntryKind::Directory {
+                    tar.append_dir(name, &path)?;
+                } else {
+                    tar.append_path_with_name(&path, name)?;
+                    counts.files += 1;
+                }
+            }
+            tar.into_inner()?.finish()?;
+        }
+    }
+    Ok(counts)
+}
+
+/// Add `path` and everything under it to `entries` as (path, entry name, kind), leaving out
+/// symlinks, excluded paths and the archive being written
+fn collect(
+    path: &Path,
+    base: &Path,
+    archive: &Path,
+    excluded: &dyn Fn(&Path) -> bool,
+    entries: &mut Vec<(PathBuf, String, EntryKind)>,
+    counts: &mut ArchiveCounts,
+) -> Result<()> {
+    if path == archive || excluded(path) {
+        return Ok(());
+    }
+    let file_type = fs::symlink_metadata(path)?.file_type();
+    let relative = path.strip_prefix(base).unwrap_or(path);
+    let name: Vec<String> = relative.components().map(|part| part.as_os_str().to_string_lossy().to_string()).collect();
+    let name = name.join("/");
+    if file_type.is_symlink() {
+        counts.skipped += 1;
+    } else if file_type.is_dir() {
+        entries.push((path.to_path_buf(), name, EntryKind::Directory));
+        let mut children: Vec<PathBuf> =
+            fs::read_dir(path)?.map(|entry| entry.map(|entry| entry.path())).collect::<io::Result<_>>()?;
+        children.sort();
+        for child in children {
+            collect(&child, base, archive, excluded, entries, counts)?;
+        }
+    } else {
+        entries.push((path.to_path_buf(), name, EntryKind::File));
+    }
+    Ok(())
+}
+
+/// Unpack `archive` into the directory `destination`, creating it if needed. Every entry is
+/// checked before anything is written: entries that would land outside `destination`
+/// (absolute, or climbing out with `..`) fail the extraction, and so do files that exist
+/// unless `overwrite` and entries `writable` refuses. Only files and directories are
+/// unpacked; links and devices are left out, and of file modes only whether a file is
+/// executable is kept.
+pub fn extract(
+    archive: &Path,
+    destination: &Path,
+    format: ArchiveFormat,
+    overwrite: bool,
+    writable: &dyn Fn(&Path) -> Result<()>,
+) -> Result<ArchiveCounts> {
+    if destination.exists() && !destination.is_dir() {
+        return Err(anyhow!("{} is not a directory", destination.display()));
+    }
+    let (ancestor, root) = resolve_destination(destination)?;
+
+    for_each_entry(archive, format, |name, kind, _, _| {
+        let target = entry_path(&root, name)
+            .ok_or_else(|| anyhow!("Refusing to extract '{}': it would land outside {}", name, root.display()))?;
+        if kind != EntryKind::Other {
+            writable(&target)?;
+        }
+        if kind == EntryKind::File && !overwrite && fs::symlink_metadata(&target).is_ok() {
+            return Err(anyhow!("{} already exists (pass overwrite: true to replace it)", target.display()));
+        }
+        Ok(())
+    })?;
+
+    create_inside(&ancestor, &root)?;
+    let mut counts = ArchiveCounts::default();
+    for_each_entry(archive, format, |name, kind, mode, content| {
+        let target = entry_path(&root, name).expect("checked above");
+        match kind {
+            EntryKind::Other => counts.skipped += 1,
+            EntryKind::Directory => create_inside(&root, &target)?,
+            EntryKind::File => {
+                create_inside(&root, target.parent().unwrap_or(&root))?;
+                if fs::symlink_metadata(&target).is_ok_and(|metadata| !metadata.is_file()) {
+                    return Err(anyhow!("Refusing to replace {} with a file", target.display()));
+                }
+                let mut file =
+                    File::create(&target).with_context(|| format!("Failed to create {}", target.display()))?;
+                io::copy(content, &mut file).with_context(|| format!("Failed to extract {}", name))?;
+                set_executable(&file, mode)?;
+                counts.files += 1;
+            }
+        }
+        Ok(())
+    })?;
+    Ok(counts)
+}
+
+/// Call `f` with each entry of `archive`: its name, kind, Unix mode and content
+fn for_each_entry(
+    archive: &Path,
+    format: ArchiveFormat,
+    mut f: impl FnMut(&str, EntryKind, Option<u32>, &mut dyn Read) -> Result<()>,
+) -> Result<()> {
+    let file = File::open(archive).with_context(|| format!("Failed to open {}", archive.display()))?;
+    match format {
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipArchive::new(file).context("Not a zip archive")?;
+            for i in 0..zip.len() {
+                let mut entry = zip.by_index(i)?;
+                let kind = if entry.is_symlink() {
+                    EntryKind::Other
+                } else if entry.is_dir() {
+                    EntryKind::Directory
+                } else {
+                    EntryKind::File
+                };
+                let (name, mode) = (entry.name().to_string(), entry.unix_mode());
+                f(&name, kind, mode, &mut entry)?;
+            }
+        }
+        ArchiveFormat::TarGz => {
+            let mut tar = tar::Archive::new(GzDecoder::new(file));
+            for entry in tar.entries().context("Not a tar.gz archive")? {
+                let mut entry = entry?;
+                let kind = match entry.header().entry_type() {
+                    tar::EntryType::Regular | tar::EntryType::Continuous => EntryKind::File,
+                    tar::EntryType::Directory => EntryKind::Directory,
+                    // Headers for the entries after them; the tar crate reads them itself
+                    tar::EntryType::XGlobalHeader | tar::EntryType::XHeader => continue,
+                    tar::EntryType::GNULongName | tar::EntryType::GNULongLink => continue,
+                    _ => EntryKind::Other,
+                };
+                let name = entry.path()?.to_string_lossy().to_string();
+                let mode = entry.header().mode().ok();
+                f(&name, kind, mode, &mut entry)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Where the entry `name` goes under `root`; None if it would land outside it
+fn entry_path(root: &Path, name: &str) -> Option<PathBuf> {
+    let mut path = root.to_path_buf();
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(path)
+}
+
+/// The canonical form of `destination`'s nearest existing ancestor, and `destination` itself
+/// resolved against it, so entries can be checked before anything is created
+fn resolve_destination(destination: &Path) -> Result<(PathBuf, PathBuf)> {
+    let mut ancestor = destination;
+    let mut missing = vec![];
+    while fs::symlink_metadata(ancestor).is_err() {
+        let name = ancestor.file_name().ok_or_else(|| anyhow!("{} has no existing ancestor", destination.display()))?;
+        missing.push(name);
+        ancestor = ancestor.parent().ok_or_else(|| anyhow!("{} has no existing ancestor", destination.display()))?;
+    }
+    let ancestor = ancestor.canonicalize()?;
+    let mut root = ancestor.clone();
+    root.extend(missing.iter().rev());
+    Ok((ancestor, root))
+}
+
+/// Create the directory `dir` under `root` one component at a time, refusing anything in the
+/// way that is not a directory and any symlink that leads outside `root`, so nothing is ever
+/// created outside it
+fn create_inside(root: &Path, dir: &Path) -> Result<()> {
+    let relative = dir
+        .strip_prefix(root)
+        .map_err(|_| anyhow!("Refusing to extract into {}: it is outside {}", dir.display(), root.display()))?;
+    let mut current = root.to_path_buf();
+    for component in relative.components() {
+        current.push(component);
+        match fs::symlink_metadata(&current) {
+            Ok(metadata) if metadata.is_dir() => {}
+            Ok(metadata) if metadata.is_symlink() => {
+                let target = current.canonicalize().ok();
+                if !target.is_some_and(|target| target.starts_with(root) && target.is_dir()) {
+                    return Err(anyhow!(
+                        "Refusing to extract into {}: it leads outside {}",
+                        current.display(),
+                        root.display()
+                    ));
+                }
+            }
+            Ok(_) => return Err(anyhow!("{} is not a directory", current.display())),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                fs::create_dir(&current).with_context(|| format!("Failed to create {}", current.display()))?;
+            }
+            Err(error) => return Err(error).with_context(|| format!("Failed to read {}", current.display())),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn file_mode(path: &Path) -> Result<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(fs::metadata(path)?.permissions().mode() & 0o777)
+}
+
+#[cfg(not(unix))]
+fn file_mode(_path: &Path) -> Result<u32> {
+    Ok(0o644)
+}
+
+#[cfg(unix)]
+fn set_executable(file: &File, mode: Option<u32>) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    if mode.is_some_and(|mode| mode & 0o111 != 0) {
+        file.set_permissions(fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_executable(_file: &File, _mode: Option<u32>) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_path() {
+        let root = Path::new("/srv/out");
+        assert_eq!(entry_path(root, "pkg/./lib.rs"), Some(PathBuf::from("/srv/out/pkg/lib.rs")));
+        assert_eq!(entry_path(root, "../etc/passwd"), None);
+        assert_eq!(entry_path(root, "pkg/../../x"), None);
+        assert_eq!(entry_path(root, "/etc/passwd"), None);
+    }
+
+    #[test]
+    fn test_create_and_extract() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("pkg/bin")).unwrap();
+        fs::write(root.join("pkg/README"), "hello").unwrap();
+        fs::write(root.join("pkg/bin/run"), "#!/bin/sh").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(root.join("pkg/bin/run"), fs::Permissions::from_mode(0o755)).unwrap();
+            std::os::unix::fs::symlink("/etc/passwd", root.join("pkg/passwd")).unwrap();
+        }
+
+        for (archive, format) in [("pkg.zip", ArchiveFormat::Zip), ("pkg.tar.gz", ArchiveFormat::TarGz)] {
+            assert_eq!(ArchiveFormat::from_path(Path::new(archive)), Some(format));
+            let counts = create(&root.join("pkg"), &root.join(archive), format, &|_| false).unwrap();
+            assert_eq!(counts.files, 2);
+
+            let out = root.join(format!("out-{}", archive));
+            assert_eq!(extract(&root.join(archive), &out, format, false, &|_| Ok(())).unwrap().files, 2);
+            assert_eq!(fs::read_to_string(out.join("pkg/README")).unwrap(), "hello");
+            assert!(!out.join("pkg/passwd").exists());
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mode = fs::metadata(out.join("pkg/bin/run")).unwrap().permissions().mode();
+                assert_eq!(mode & 0o111, 0o111);
+            }
+            let again = extract(&root.join(archive), &out, format, false, &|_| Ok(())).unwrap_err();
+            assert!(again.to_string().contains("already exists"));
+            assert!(extract(&root.join(archive), &out, format, true, &|_| Ok(())).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_extract_refuses_traversal() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let archive = root.join("evil.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&archive).unwrap());
+        zip.start_file("fine.txt", zip::write::SimpleFileOptions::default()).unwrap();
+        zip.start_file("../escaped.txt", zip::write::SimpleFileOptions::default()).unwrap();
+        zip.finish().unwrap();
+
+        let error = extract(&archive, &root.join("out"), ArchiveFormat::Zip, false, &|_| Ok(())).unwrap_err();
+        assert!(error.to_string().contains("Refusing to extract '../escaped.txt'"));
+        // Nothing was written, not even the destination
+        assert!(!root.join("out").exists());
+        assert!(!dir.path().parent().unwrap().join("escaped.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_extract_refuses_symlink_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("out")).unwrap();
+        fs::create_dir_all(root.join("outside")).unwrap();
+        std::os::unix::fs::symlink(root.join("outside"), root.join("out/link")).unwrap();
+        let archive = root.join("evil.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&archive).unwrap());
+        zip.add_directory("link/made/", zip::write::SimpleFileOptions::default()).unwrap();
+        zip.start_file("link/sub/file.txt", zip::write::SimpleFileOptions::default()).unwrap();
+        zip.finish().unwrap();
+
+        let error = extract(&archive, &root.join("out"), ArchiveFormat::Zip, false, &|_| Ok(())).unwrap_err();
+        assert!(error.to_string().contains("leads outside"));
+        assert_eq!(fs::read_dir(root.join("outside")).unwrap().count(), 0);
+    }
+}
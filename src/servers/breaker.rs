@@ -0,0 +1,353 @@
+use anyhow::Result;
+use colored::Colorize;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::common::{
+    ClientAnswer, CompletionRef, JsonRpcResponse, McpPrompt, McpResource, McpServer, McpTool, Notifier, RequestContext,
+};
+use super::proxy::UpstreamError;
+
+/// JSON-RPC error code for calls refused while a server's circuit is open (server error range; 53 as in HTTP 503)
+pub const CIRCUIT_OPEN_CODE: i32 = -32053;
+
+/// `circuit_breaker` of a gateway server: after `failures` failed calls in a row, calls
+/// are refused for `cooldown` seconds, then one is let through to probe the server
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct BreakerSettings {
+    /// Failed calls in a row that open the circuit (0 disables the breaker)
+    pub failures: u32,
+    /// Seconds the circuit stays open before a probe
+    pub cooldown: u64,
+}
+
+impl Default for BreakerSettings {
+    fn default() -> Self {
+        Self { failures: 5, cooldown: 30 }
+    }
+}
+
+impl BreakerSettings {
+    pub fn enabled(&self) -> bool {
+        self.failures > 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    /// Calls go through; counts failures in a row
+    Closed { failures: u32 },
+    /// Calls are refused until the cooldown has passed
+    Open { since: Instant },
+    /// One probe call is in flight; others are refused until it's answered
+    Probing,
+}
+
+/// A call refused by an open circuit
+#[derive(Debug)]
+pub struct CircuitOpen {
+    pub server: String,
+    /// When the next probe will be let through
+    pub retry_after: Duration,
+}
+
+impl fmt::Display for CircuitOpen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is unavailable after repeated failures; retry in {}s",
+            self.server,
+            self.retry_secs()
+        )
+    }
+}
+
+impl std::error::Error for CircuitOpen {}
+
+impl CircuitOpen {
+    /// `retry_after` in whole seconds, rounded up
+    pub fn retry_secs(&self) -> u64 {
+        self.retry_after.as_secs() + u64::from(self.retry_after.subsec_nanos() > 0)
+    }
+
+    /// The JSON-RPC error for the refused request, with the retry hint in `data`
+    pub fn to_response(&self, id: Option<Value>) -> JsonRpcResponse {
+        let mut response = JsonRpcResponse::error(id, CIRCUIT_OPEN_CODE, self.to_string());
+        if let Some(error) = &mut response.error {
+            error.data = Some(serde_json::json!({ "retryAfter": self.retry_secs() }));
+        }
+        response
+    }
+}
+
+/// A server whose tool calls fail fast while it keeps failing (a gateway server's
+/// `circuit_breaker`), rather than each waiting out a timeout behind a dead process.
+/// Calls the server answers, even with a JSON-RPC error, count as successes.
+pub struct CircuitBreaker {
+    name: String,
+    server: Box<dyn McpServer + Send + Sync>,
+    settings: BreakerSettings,
+    state: Mutex<State>,
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &str, server: Box<dyn McpServer + Send + Sync>, settings: BreakerSettings) -> Self {
+        Self {
+            name: name.to_string(),
+            server,
+            settings,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    /// Whether a call may go through now, turning an open circuit whose cooldown has
+    /// passed into a probe
+    fn admit(&self, now: Instant) -> Result<(), CircuitOpen> {
+        let cooldown = Duration::from_secs(self.settings.cooldown);
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { since } if now.duration_since(since) >= cooldown => {
+                *state = State::Probing;
+                Ok(())
+            }
+            State::Open { since } => Err(CircuitOpen {
+                server: self.name.clone(),
+                retry_after: cooldown.saturating_sub(now.duration_since(since)),
+            }),
+            State::Probing => Err(CircuitOpen {
+                server: self.name.clone(),
+                retry_after: Duration::from_secs(1),
+            }),
+        }
+    }
+
+    /// Record how an admitted call went
+    fn record(&self, ok: bool, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+        *state = match (*state, ok) {
+            (State::Probing, true) => {
+                eprintln!("{}", format!("[mcpz] {} is answering again; circuit closed", self.name).green());
+                State::Closed { failures: 0 }
+            }
+            (_, true) => State::Closed { failures: 0 },
+            (State::Closed { failures }, false) if failures + 1 < self.settings.failures => {
+                State::Closed { failures: failures + 1 }
+            }
+            (State::Closed { failures }, false) => {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "[mcpz] {} failed {} calls in a row; refusing calls for {}s",
+                        self.name,
+                        failures + 1,
+                        self.settings.cooldown
+                    )
+                    .yellow()
+                );
+                State::Open { since: now }
+            }
+            (_, false) => State::Open { since: now },
+        };
+    }
+}
+
+impl McpServer for CircuitBreaker {
+    fn name(&self) -> &str {
+        self.server.name()
+    }
+
+    fn version(&self) -> &str {
+        self.server.version()
+    }
+
+    fn tools(&self) -> Vec<McpTool> {
+        self.server.tools()
+    }
+
+    fn call_tool(&self, name: &str, arguments: &Value) -> Result<Value> {
+        self.call_tool_with_context(name, arguments, &RequestContext::default())
+    }
+
+    fn call_tool_with_context(&self, name: &str, arguments: &Value, ctx: &RequestContext) -> Result<Value> {
+        self.admit(Instant::now())?;
+        let result = self.server.call_tool_with_context(name, arguments, ctx);
+        // A server that answers with an error (bad arguments, say) is still up
+        let answered = result.as_ref().map_or_else(|e| e.downcast_ref::<UpstreamError>().is_some(), |_| true);
+        self.record(answered, Instant::now());
+        result
+    }
+
+    fn has_resources(&self) -> bool {
+        self.server.has_resources()
+    }
+
+    fn resources(&self, ctx: &RequestContext) -> Vec<McpResource> {
+        self.server.resources(ctx)
+    }
+
+    fn read_resource(&self, uri: &str, ctx: &RequestContext) -> Result<Value> {
+        self.server.read_resource(uri, ctx)
+    }
+
+    fn has_subscriptions(&self) -> bool {
+        self.server.has_subscriptions()
+    }
+
+    fn subscribe(&self, uri: &str, ctx: &RequestContext) -> Result<()> {
+        self.server.subscribe(uri, ctx)
+    }
+
+    fn unsubscribe(&self, uri: &str, ctx: &RequestContext) -> Result<()> {
+        self.server.unsubscribe(uri, ctx)
+    }
+
+    fn has_prompts(&self) -> bool {
+        self.server.has_prompts()
+    }
+
+    fn prompts(&self) -> Vec<McpPrompt> {
+        self.server.prompts()
+    }
+
+    fn get_prompt(&self, name: &str, arguments: &Value) -> Result<Value> {
+        self.server.get_prompt(name, arguments)
+    }
+
+    fn has_completions(&self) -> bool {
+        self.server.has_completions()
+    }
+
+    fn complete(
+        &self,
+        reference: &CompletionRef,
+        argument: &str,
+        value: &str,
+        ctx: &RequestContext,
+    ) -> Result<Vec<String>> {
+        self.server.complete(reference, argument, value, ctx)
+    }
+
+    fn on_initialize(&self, params: &Value, ctx: &RequestContext) {
+        self.server.on_initialize(params, ctx)
+    }
+
+    fn on_notification(&self, method: &str, params: &Value, ctx: &RequestContext) {
+        self.server.on_notification(method, params, ctx)
+    }
+
+    fn on_response(&self, id: &Value, response: ClientAnswer, ctx: &RequestContext) {
+        self.server.on_response(id, response, ctx)
+    }
+
+    fn on_session_end(&self, session_id: Option<&str>) {
+        self.server.on_session_end(session_id)
+    }
+
+    fn notifier(&self) -> Option<&Notifier> {
+        self.server.notifier()
+    }
+
+    fn verbose(&self) -> bool {
+        self.server.verbose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    struct Failing;
+
+    impl McpServer for Failing {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        fn version(&self) -> &str {
+            "1.0.0"
+        }
+
+        fn tools(&self) -> Vec<McpTool> {
+            vec![]
+        }
+
+        fn call_tool(&self, _name: &str, _arguments: &Value) -> Result<Value> {
+            Err(anyhow::anyhow!("No response to tools/call within 300s"))
+        }
+
+        fn verbose(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn test_breaker_opens_and_probes() {
+        let settings = BreakerSettings { failures: 2, cooldown: 30 };
+        let breaker = CircuitBreaker::new("db", Box::new(Failing), settings);
+        assert!(breaker.call_tool("q", &json!({})).unwrap_err().to_string().contains("within 300s"));
+        assert!(breaker.call_tool("q", &json!({})).is_err());
+
+        // Open: refused without reaching the server
+        let refused = breaker.call_tool("q", &json!({})).unwrap_err();
+        let open = refused.downcast_ref::<CircuitOpen>().unwrap();
+        assert_eq!(open.retry_secs(), 30);
+        assert_eq!(
+            open.to_string(),
+            "db is unavailable after repeated failures; retry in 30s"
+        );
+
+        // After the cooldown one probe goes through; others wait for its answer
+        let later = Instant::now() + Duration::from_secs(30);
+        assert!(breaker.admit(later).is_ok());
+        assert!(breaker.admit(later).is_err());
+        breaker.record(false, later);
+        assert!(breaker.admit(later + Duration::from_secs(29)).is_err());
+        assert!(breaker.admit(later + Duration::from_secs(30)).is_ok());
+        breaker.record(true, later + Duration::from_secs(30));
+        assert_eq!(*breaker.state.lock().unwrap(), State::Closed { failures: 0 });
+    }
+
+    #[test]
+    fn test_upstream_errors_keep_the_circuit_closed() {
+        struct Rejecting;
+        impl McpServer for Rejecting {
+            fn name(&self) -> &str {
+                "rejecting"
+            }
+            fn version(&self) -> &str {
+                "1.0.0"
+            }
+            fn tools(&self) -> Vec<McpTool> {
+                vec![]
+            }
+            fn call_tool(&self, _name: &str, _arguments: &Value) -> Result<Value> {
+                Err(UpstreamError { code: -32602, message: "Invalid params".to_string(), data: None }.into())
+            }
+            fn verbose(&self) -> bool {
+                false
+            }
+        }
+        let breaker = CircuitBreaker::new("strict", Box::new(Rejecting), BreakerSettings { failures: 1, cooldown: 30 });
+        for _ in 0..3 {
+            assert_eq!(breaker.call_tool("q", &json!({})).unwrap_err().to_string(), "Invalid params");
+        }
+        assert_eq!(*breaker.state.lock().unwrap(), State::Closed { failures: 0 });
+    }
+
+    #[test]
+    fn test_circuit_open_response() {
+        let open = CircuitOpen {
+            server: "db".to_string(),
+            retry_after: Duration::from_millis(1500),
+        };
+        let response = serde_json::to_value(open.to_response(Some(json!(3)))).unwrap();
+        assert_eq!(response["error"]["code"], CIRCUIT_OPEN_CODE);
+        assert_eq!(response["error"]["data"]["retryAfter"], 2);
+    }
+}
@@ -0,0 +1,232 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::common::{ClientAnswer, CompletionRef, McpPrompt, McpResource, McpServer, McpTool, Notifier, RequestContext};
+
+/// A session (None for stdio), a tool and its arguments as JSON
+type CacheKey = (Option<String>, String, String);
+
+/// A server whose results for some tools are reused for repeated calls with the same
+/// arguments in the same session (a gateway server's `cache`), each tool for its own
+/// number of seconds. Errors and error results are never cached.
+pub struct Cached {
+    server: Box<dyn McpServer + Send + Sync>,
+    /// Tool (as the server names it) -> how long its results are reused
+    ttls: HashMap<String, Duration>,
+    /// Results still fresh, and when they expire
+    entries: Mutex<HashMap<CacheKey, (Instant, Value)>>,
+}
+
+impl Cached {
+    /// `ttls` maps tools to seconds; tools missing from it, or at 0, aren't cached
+    pub fn new(server: Box<dyn McpServer + Send + Sync>, ttls: &BTreeMap<String, u64>) -> Self {
+        Self {
+            server,
+            ttls: ttls
+                .iter()
+                .filter(|(_, secs)| **secs > 0)
+                .map(|(tool, secs)| (tool.clone(), Duration::from_secs(*secs)))
+                .collect(),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn lookup(&self, key: &CacheKey, now: Instant) -> Option<Value> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((expires, result)) if *expires > now => Some(result.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn store(&self, key: CacheKey, result: &Value, ttl: Duration, now: Instant) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (expires, _)| *expires > now);
+        entries.insert(key, (now + ttl, result.clone()));
+    }
+}
+
+impl McpServer for Cached {
+    fn name(&self) -> &str {
+        self.server.name()
+    }
+
+    fn version(&self) -> &str {
+        self.server.version()
+    }
+
+    fn tools(&self) -> Vec<McpTool> {
+        self.server.tools()
+    }
+
+    fn call_tool(&self, name: &str, arguments: &Value) -> Result<Value> {
+        self.call_tool_with_context(name, arguments, &RequestContext::default())
+    }
+
+    fn call_tool_with_context(&self, name: &str, arguments: &Value, ctx: &RequestContext) -> Result<Value> {
+        let Some(ttl) = self.ttls.get(name).copied() else {
+            return self.server.call_tool_with_context(name, arguments, ctx);
+        };
+        let key = (ctx.session_id.clone(), name.to_string(), arguments.to_string());
+        if let Some(result) = self.lookup(&key, Instant::now()) {
+            self.log(&format!("Reusing cached result of {}", name));
+            return Ok(result);
+        }
+        let result = self.server.call_tool_with_context(name, arguments, ctx)?;
+        if result.get("isError") != Some(&Value::Bool(true)) {
+            self.store(key, &result, ttl, Instant::now());
+        }
+        Ok(result)
+    }
+
+    fn has_resources(&self) -> bool {
+        self.server.has_resources()
+    }
+
+    fn resources(&self, ctx: &RequestContext) -> Vec<McpResource> {
+        self.server.resources(ctx)
+    }
+
+    fn read_resource(&self, uri: &str, ctx: &RequestContext) -> Result<Value> {
+        self.server.read_resource(uri, ctx)
+    }
+
+    fn has_subscriptions(&self) -> bool {
+        self.server.has_subscriptions()
+    }
+
+    fn subscribe(&self, uri: &str, ctx: &RequestContext) -> Result<()> {
+        self.server.subscribe(uri, ctx)
+    }
+
+    fn unsubscribe(&self, uri: &str, ctx: &RequestContext) -> Result<()> {
+        self.server.unsubscribe(uri, ctx)
+    }
+
+    fn has_prompts(&self) -> bool {
+        self.server.has_prompts()
+    }
+
+    fn prompts(&self) -> Vec<McpPrompt> {
+        self.server.prompts()
+    }
+
+    fn get_prompt(&self, name: &str, arguments: &Value) -> Result<Value> {
+        self.server.get_prompt(name, arguments)
+    }
+
+    fn has_completions(&self) -> bool {
+        self.server.has_completions()
+    }
+
+    fn complete(
+        &self,
+        reference: &CompletionRef,
+        argument: &str,
+        value: &str,
+        ctx: &RequestContext,
+    ) -> Result<Vec<String>> {
+        self.server.complete(reference, argument, value, ctx)
+    }
+
+    fn on_initialize(&self, params: &Value, ctx: &RequestContext) {
+        self.server.on_initialize(params, ctx)
+    }
+
+    fn on_notification(&self, method: &str, params: &Value, ctx: &RequestContext) {
+        self.server.on_notification(method, params, ctx)
+    }
+
+    fn on_response(&self, id: &Value, response: ClientAnswer, ctx: &RequestContext) {
+        self.server.on_response(id, response, ctx)
+    }
+
+    fn on_session_end(&self, session_id: Option<&str>) {
+        self.entries.lock().unwrap().retain(|(id, _, _), _| id.as_deref() != session_id);
+        self.server.on_session_end(session_id)
+    }
+
+    fn notifier(&self) -> Option<&Notifier> {
+        self.server.notifier()
+    }
+
+    fn verbose(&self) -> bool {
+        self.server.verbose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::servers::common::error_content;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    /// Counts its calls, answering each with the count (the `fail` tool with an error result)
+    #[derive(Default)]
+    struct Counter {
+        calls: AtomicU64,
+    }
+
+    impl McpServer for Counter {
+        fn name(&self) -> &str {
+            "counter"
+        }
+
+        fn version(&self) -> &str {
+            "1.0.0"
+        }
+
+        fn tools(&self) -> Vec<McpTool> {
+            vec![]
+        }
+
+        fn call_tool(&self, name: &str, _arguments: &Value) -> Result<Value> {
+            let count = self.calls.fetch_add(1, Ordering::Relaxed) + 1;
+            if name == "fail" {
+                return Ok(error_content("no"));
+            }
+            Ok(json!(count))
+        }
+
+        fn verbose(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn test_cached_per_tool_arguments_and_session() {
+        let ttls = BTreeMap::from([("list".to_string(), 60), ("fail".to_string(), 60), ("off".to_string(), 0)]);
+        let cached = Cached::new(Box::new(Counter::default()), &ttls);
+        let a = RequestContext::with_session("a");
+        let call = |tool: &str, arguments: Value, ctx: &RequestContext| {
+            cached.call_tool_with_context(tool, &arguments, ctx).unwrap()
+        };
+
+        assert_eq!(call("list", json!({"db": 1}), &a), json!(1));
+        assert_eq!(call("list", json!({"db": 1}), &a), json!(1));
+        assert_eq!(call("list", json!({"db": 2}), &a), json!(2));
+        assert_eq!(call("list", json!({"db": 1}), &RequestContext::with_session("b")), json!(3));
+        // Uncached tools, and error results, always reach the server
+        assert_eq!(call("other", json!({}), &a), json!(4));
+        assert_eq!(call("off", json!({}), &a), json!(5));
+        assert_eq!(call("off", json!({}), &a), json!(6));
+        call("fail", json!({}), &a);
+        call("fail", json!({}), &a);
+        assert_eq!(call("list", json!({"db": 1}), &a), json!(1));
+
+        // Entries expire, and go with their session
+        let key = (Some("a".to_string()), "list".to_string(), json!({"db": 1}).to_string());
+        assert!(cached.lookup(&key, Instant::now() + Duration::from_secs(60)).is_none());
+        assert_eq!(call("list", json!({"db": 2}), &a), json!(2));
+        cached.on_session_end(Some("a"));
+        assert_eq!(call("list", json!({"db": 2}), &a), json!(9));
+    }
+}
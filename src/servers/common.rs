@@ -1,6 +1,18 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+use crate::record::{Recorder, Sender};
+
+use super::breaker::CircuitOpen;
+use super::proxy::UpstreamError;
+use super::ratelimit::RateLimitExceeded;
+use super::strict::{check_message, refusal};
 
 /// JSON-RPC request structure
 #[derive(Deserialize, Debug)]
@@ -40,7 +52,7 @@ impl JsonRpcResponse {
             jsonrpc: "2.0".to_string(),
             id,
             result: None,
-            error: Some(JsonRpcError { code, message }),
+            error: Some(JsonRpcError { code, message, data: None }),
         }
     }
 
@@ -53,7 +65,39 @@ impl JsonRpcResponse {
     }
 
     pub fn internal_error(id: Option<serde_json::Value>, message: String) -> Self {
-        Self::error(id, -32603, message)
+        Self::error(id, INTERNAL_ERROR_CODE, message)
+    }
+
+    /// The answer to a request that failed with `error`: `code`, unless the error says
+    /// otherwise (an `RpcError`, a refusal, or the upstream server's own error)
+    pub fn from_error(id: Option<serde_json::Value>, error: &anyhow::Error, code: i32) -> Self {
+        if let Some(limited) = error.downcast_ref::<RateLimitExceeded>() {
+            return limited.to_response(id);
+        }
+        if let Some(open) = error.downcast_ref::<CircuitOpen>() {
+            return open.to_response(id);
+        }
+        let (code, data) = if let Some(upstream) = error.downcast_ref::<UpstreamError>() {
+            (upstream.code as i32, upstream.data.clone())
+        } else if let Some(rpc) = error.chain().find_map(|cause| cause.downcast_ref::<RpcError>()) {
+            (rpc.code.unwrap_or(code), rpc.data.clone())
+        } else {
+            (code, None)
+        };
+        let mut response = Self::error(id, code, error.to_string());
+        if let Some(error) = &mut response.error {
+            error.data = data;
+        }
+        response
+    }
+
+    /// The answer to an `initialize` asking for a protocol version we don't speak
+    pub fn unsupported_version(id: Option<serde_json::Value>, requested: Option<serde_json::Value>) -> Self {
+        let mut response = Self::error(id, INVALID_PARAMS_CODE, "Unsupported protocol version".to_string());
+        if let Some(error) = &mut response.error {
+            error.data = Some(serde_json::json!({ "supported": PROTOCOL_VERSIONS, "requested": requested }));
+        }
+        response
     }
 }
 
@@ -62,6 +106,82 @@ impl JsonRpcResponse {
 pub struct JsonRpcError {
     pub code: i32,
     pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+/// An error that tells JSON-RPC clients more than its message: a code other than the
+/// request's usual one, and what went wrong in `data`
+#[derive(Debug)]
+pub struct RpcError {
+    /// None: the request's usual code
+    pub code: Option<i32>,
+    pub message: String,
+    pub data: Option<serde_json::Value>,
+}
+
+impl RpcError {
+    /// Parameters (or tool arguments) missing or of the wrong kind
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        Self { code: Some(INVALID_PARAMS_CODE), message: message.into(), data: None }
+    }
+
+    /// A failure described in `data`, such as `{"kind": "access_denied", "path": ...}`
+    pub fn with_data(message: impl Into<String>, data: serde_json::Value) -> Self {
+        Self { code: None, message: message.into(), data: Some(data) }
+    }
+}
+
+impl std::fmt::Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+/// Per-request context supplied by the transport
+#[derive(Debug, Clone, Default)]
+pub struct RequestContext {
+    /// HTTP session ID (None for stdio, which has a single implicit session)
+    pub session_id: Option<String>,
+    /// Protocol version the session agreed on in `initialize` (None if unknown)
+    pub protocol_version: Option<String>,
+    /// Reaches the session's client with requests from servers behind this one, such as
+    /// `sampling/createMessage` (None if the client can't take them)
+    pub client: Option<Arc<Notifier>>,
+}
+
+impl RequestContext {
+    pub fn with_session(session_id: &str) -> Self {
+        Self {
+            session_id: Some(session_id.to_string()),
+            ..Self::default()
+        }
+    }
+}
+
+/// Protocol versions the servers speak, oldest first
+pub const PROTOCOL_VERSIONS: [&str; 3] = ["2024-11-05", "2025-03-26", "2025-06-18"];
+
+/// Version assumed for clients whose `initialize` doesn't name one
+pub const DEFAULT_PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// First version whose tool results may carry `structuredContent`
+const STRUCTURED_OUTPUT_VERSION: &str = "2025-06-18";
+
+/// The version to answer `initialize` with: the client's if we speak it, or our latest if the
+/// client asked for a newer one (it then decides whether to carry on). None for versions
+/// older than any we speak, and for ones that aren't versions at all.
+pub fn negotiate_version(requested: &str) -> Option<&'static str> {
+    if let Some(version) = PROTOCOL_VERSIONS.iter().find(|version| **version == requested) {
+        return Some(version);
+    }
+    let latest = PROTOCOL_VERSIONS[PROTOCOL_VERSIONS.len() - 1];
+    // Versions are dates, so they compare as strings
+    let is_date = requested.len() == 10
+        && requested.char_indices().all(|(i, c)| if i == 4 || i == 7 { c == '-' } else { c.is_ascii_digit() });
+    (is_date && requested > latest).then_some(latest)
 }
 
 /// MCP tool definition
@@ -71,15 +191,288 @@ pub struct McpTool {
     pub description: String,
     #[serde(rename = "inputSchema")]
     pub input_schema: serde_json::Value,
+    /// JSON Schema of the tool's `structuredContent`, for tools whose successful results always carry it
+    #[serde(rename = "outputSchema", skip_serializing_if = "Option::is_none")]
+    pub output_schema: Option<serde_json::Value>,
+}
+
+/// MCP resource definition
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct McpResource {
+    pub uri: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+/// MCP prompt definition
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct McpPrompt {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub arguments: Vec<PromptArgument>,
+}
+
+/// An argument a prompt takes
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PromptArgument {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// What a `completion/complete` request completes an argument of
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum CompletionRef {
+    /// A tool's argument (an mcpz extension: MCP itself only completes prompt and resource arguments)
+    #[serde(rename = "ref/tool")]
+    Tool { name: String },
+    #[serde(rename = "ref/prompt")]
+    Prompt { name: String },
+    #[serde(rename = "ref/resource")]
+    Resource { uri: String },
+}
+
+/// Most values a `completion/complete` result holds, as the MCP spec limits them
+pub const MAX_COMPLETIONS: usize = 100;
+
+/// JSON-RPC error code for resources that can't be read (as the MCP spec suggests)
+pub const RESOURCE_NOT_FOUND_CODE: i32 = -32002;
+
+/// JSON-RPC error code for malformed messages (`--strict`)
+pub const INVALID_REQUEST_CODE: i32 = -32600;
+
+/// JSON-RPC error code for invalid cursors, unknown prompts, missing prompt or tool
+/// arguments, malformed completion requests and unknown log levels
+pub const INVALID_PARAMS_CODE: i32 = -32602;
+
+/// JSON-RPC error code for tool calls that fail without saying why in their own terms
+pub const INTERNAL_ERROR_CODE: i32 = -32603;
+
+/// Most items a `tools/list`, `resources/list` or `prompts/list` page holds
+pub const PAGE_SIZE: usize = 100;
+
+/// The page of `items` starting at the request's `cursor`, as a list result with the items
+/// under `key` and a `nextCursor` if more follow. Cursors are opaque to clients: they're the
+/// offset of the page, base64-encoded.
+pub fn paginate<T: Serialize>(key: &str, items: Vec<T>, params: &serde_json::Value) -> Result<serde_json::Value> {
+    use base64::Engine;
+    let engine = base64::engine::general_purpose::STANDARD;
+    let start = match params.get("cursor").and_then(|v| v.as_str()) {
+        Some(cursor) => engine
+            .decode(cursor)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .and_then(|offset| offset.parse::<usize>().ok())
+            // Past the end if the list shrank since: an empty last page
+            .map(|offset| offset.min(items.len()))
+            .ok_or_else(|| anyhow::anyhow!("Invalid cursor: {}", cursor))?,
+        None => 0,
+    };
+    let end = items.len().min(start + PAGE_SIZE);
+    let more = end < items.len();
+    let page: Vec<T> = items.into_iter().skip(start).take(end - start).collect();
+    let mut result = serde_json::json!({ key: page });
+    if more {
+        result["nextCursor"] = serde_json::Value::String(engine.encode(end.to_string()));
+    }
+    Ok(result)
+}
+
+/// Severity of a log message, as `logging/setLevel` and `notifications/message` name them
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Notice,
+    Warning,
+    Error,
+    Critical,
+    Alert,
+    Emergency,
+}
+
+/// A message from the server to its clients
+#[derive(Debug, Clone)]
+pub struct Outgoing {
+    /// HTTP session it is for (None: every client, including stdio's)
+    pub to: Option<String>,
+    pub message: serde_json::Value,
+}
+
+/// Fans server-initiated notifications (such as `notifications/tools/list_changed`) and
+/// requests out to connected clients: the stdio loop and each HTTP session's SSE stream
+#[derive(Debug)]
+pub struct Notifier {
+    sender: broadcast::Sender<Outgoing>,
+    /// Whether the server's tools can change (advertised as `tools.listChanged`)
+    tools_change: bool,
+    /// Least severe log messages clients get as `notifications/message`; none until a
+    /// client sends `logging/setLevel`, and shared by all of them
+    level: Mutex<Option<LogLevel>>,
+    /// Numbers the requests sent to clients
+    next_id: AtomicU64,
+    /// Requests sent with `ask`, by id, and where their answers go
+    pending: Mutex<HashMap<String, mpsc::Sender<ClientAnswer>>>,
+}
+
+impl Default for Notifier {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(16).0,
+            tools_change: true,
+            level: Mutex::new(None),
+            next_id: AtomicU64::new(1),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Notifier {
+    /// A notifier for a server whose tools never change, which only sends log messages
+    pub fn logs_only() -> Self {
+        Self {
+            tools_change: false,
+            ..Self::default()
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Outgoing> {
+        self.sender.subscribe()
+    }
+
+    pub fn tools_change(&self) -> bool {
+        self.tools_change
+    }
+
+    /// Send a parameterless notification to every subscriber (dropped if there are none)
+    pub fn notify(&self, method: &str) {
+        self.send(None, serde_json::json!({ "jsonrpc": "2.0", "method": method }));
+    }
+
+    /// Send a request to the client of `session` (None: stdio), returning its id, which the
+    /// answer comes back to `McpServer::on_response` with
+    pub fn request(&self, session: Option<&str>, method: &str, params: serde_json::Value) -> String {
+        let id = format!("mcpz-{}", self.next_id.fetch_add(1, Ordering::Relaxed));
+        let message = serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        self.send(session.map(str::to_string), message);
+        id
+    }
+
+    /// Send a request to the client of `session` and wait up to `timeout` for its answer
+    pub fn ask(
+        &self,
+        session: Option<&str>,
+        method: &str,
+        params: serde_json::Value,
+        timeout: Duration,
+    ) -> Result<ClientAnswer> {
+        let id = format!("mcpz-{}", self.next_id.fetch_add(1, Ordering::Relaxed));
+        let (sender, answer) = mpsc::channel();
+        self.pending.lock().unwrap().insert(id.clone(), sender);
+        let message = serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        self.send(session.map(str::to_string), message);
+        let answer = answer.recv_timeout(timeout);
+        self.pending.lock().unwrap().remove(&id);
+        answer.map_err(|_| anyhow::anyhow!("The client didn't answer {} within {}s", method, timeout.as_secs()))
+    }
+
+    /// Pass a client's answer to the `ask` waiting for it, or give it back if none is
+    pub fn answer(&self, id: &serde_json::Value, answer: ClientAnswer) -> Option<ClientAnswer> {
+        let waiting = id.as_str().and_then(|id| self.pending.lock().unwrap().remove(id));
+        match waiting {
+            Some(sender) => sender.send(answer).err().map(|unsent| unsent.0),
+            None => Some(answer),
+        }
+    }
+
+    fn send(&self, to: Option<String>, message: serde_json::Value) {
+        // Dropped if nobody is listening
+        let _ = self.sender.send(Outgoing { to, message });
+    }
+
+    /// Tell the client of `session` (None: stdio) that the resource at `uri` changed
+    pub fn resource_updated(&self, session: Option<&str>, uri: &str) {
+        let message = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/resources/updated",
+            "params": { "uri": uri }
+        });
+        self.send(session.map(str::to_string), message);
+    }
+
+    pub fn set_level(&self, level: LogLevel) {
+        *self.level.lock().unwrap() = Some(level);
+    }
+
+    /// Send a log message from `logger` as `notifications/message`, if clients asked for its level
+    pub fn message(&self, level: LogLevel, logger: &str, data: &str) {
+        self.message_to(None, level, logger, data)
+    }
+
+    /// Send a log message to the client of `session` only (None: stdio), if clients asked for
+    /// its level
+    pub fn message_to(&self, session: Option<&str>, level: LogLevel, logger: &str, data: &str) {
+        if self.level.lock().unwrap().is_some_and(|least| level >= least) {
+            self.send(
+                session.map(str::to_string),
+                serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "method": "notifications/message",
+                    "params": { "level": level, "logger": logger, "data": data }
+                }),
+            );
+        }
+    }
+}
+
+/// A client's answer to a request from the server: its result, or its error
+pub type ClientAnswer = std::result::Result<serde_json::Value, serde_json::Value>;
+
+/// A client's answer to a request the server sent it: its id, and its result or error.
+/// None if `message` isn't an answer.
+pub fn client_response(message: &serde_json::Value) -> Option<(serde_json::Value, ClientAnswer)> {
+    if message.get("method").is_some() {
+        return None;
+    }
+    let id = message.get("id")?.clone();
+    match (message.get("result"), message.get("error")) {
+        (Some(result), _) => Some((id, Ok(result.clone()))),
+        (None, Some(error)) => Some((id, Err(error.clone()))),
+        (None, None) => None,
+    }
 }
 
 /// Create a text content response for MCP tools
 pub fn text_content(text: &str) -> serde_json::Value {
+    Content::default().text(text).build()
+}
+
+/// Create a response carrying a typed result as both text and `structuredContent`
+pub fn structured_content<T: Serialize>(value: &T) -> Result<serde_json::Value> {
+    let structured = serde_json::to_value(value)?;
+    let text = serde_json::to_string_pretty(&structured)?;
+    Ok(text_with_structured(&text, structured))
+}
+
+/// Create a text content response that also carries `structured` as `structuredContent`, for
+/// results whose text isn't simply their JSON
+pub fn text_with_structured(text: &str, structured: serde_json::Value) -> serde_json::Value {
     serde_json::json!({
         "content": [{
             "type": "text",
             "text": text
-        }]
+        }],
+        "structuredContent": structured
     })
 }
 
@@ -94,6 +487,123 @@ pub fn error_content(message: &str) -> serde_json::Value {
     })
 }
 
+/// Create an image content response for MCP tools, base64-encoding `data`
+pub fn image_content(data: &[u8], mime_type: &str) -> serde_json::Value {
+    Content::default().image(data, mime_type).build()
+}
+
+/// Create a response for MCP tools embedding one resource (see `resource_contents`)
+pub fn resource_content(uri: &str, mime_type: Option<&str>, bytes: Vec<u8>) -> serde_json::Value {
+    Content::default().resource(uri, mime_type, bytes).build()
+}
+
+/// Builds a tool response of several content items, in order: say, a caption and a screenshot
+#[derive(Debug, Default)]
+pub struct Content {
+    items: Vec<serde_json::Value>,
+}
+
+impl Content {
+    pub fn text(mut self, text: &str) -> Self {
+        self.items.push(serde_json::json!({ "type": "text", "text": text }));
+        self
+    }
+
+    /// An image, base64-encoding `data`
+    pub fn image(mut self, data: &[u8], mime_type: &str) -> Self {
+        use base64::Engine;
+        let data = base64::engine::general_purpose::STANDARD.encode(data);
+        self.items.push(serde_json::json!({ "type": "image", "data": data, "mimeType": mime_type }));
+        self
+    }
+
+    /// An embedded resource (see `resource_contents`)
+    pub fn resource(mut self, uri: &str, mime_type: Option<&str>, bytes: Vec<u8>) -> Self {
+        let resource = resource_contents(uri, mime_type, bytes);
+        self.items.push(serde_json::json!({ "type": "resource", "resource": resource }));
+        self
+    }
+
+    pub fn build(self) -> serde_json::Value {
+        serde_json::json!({ "content": self.items })
+    }
+}
+
+/// The contents of a resource as `resources/read` and embedded resources carry them: `text`
+/// if `bytes` are UTF-8, or else a base64 `blob`. Without a `mime_type`, they're text/plain
+/// or application/octet-stream.
+pub fn resource_contents(uri: &str, mime_type: Option<&str>, bytes: Vec<u8>) -> serde_json::Value {
+    match String::from_utf8(bytes) {
+        Ok(text) => serde_json::json!({ "uri": uri, "mimeType": mime_type.unwrap_or("text/plain"), "text": text }),
+        Err(e) => {
+            use base64::Engine;
+            serde_json::json!({
+                "uri": uri,
+                "mimeType": mime_type.unwrap_or("application/octet-stream"),
+                "blob": base64::engine::general_purpose::STANDARD.encode(e.into_bytes())
+            })
+        }
+    }
+}
+
+/// Create a `resources/read` result holding one text resource
+pub fn resource_text(uri: &str, mime_type: &str, text: &str) -> serde_json::Value {
+    serde_json::json!({
+        "contents": [{
+            "uri": uri,
+            "mimeType": mime_type,
+            "text": text
+        }]
+    })
+}
+
+/// Cut a tool result's text content down to `max_bytes` in all, ending the cut text with
+/// `[truncated N bytes]` and setting `isTruncated`. `structuredContent` repeats the text, so
+/// it goes too. Returns whether anything was cut.
+pub fn truncate_result(result: &mut serde_json::Value, max_bytes: usize) -> bool {
+    let Some(content) = result.get_mut("content").and_then(serde_json::Value::as_array_mut) else {
+        return false;
+    };
+    let mut left = max_bytes;
+    let mut cut = 0;
+    // Index of the first item cut, which carries the marker; items cut after it are dropped
+    let mut marked = None;
+    let mut kept = vec![];
+    for mut item in std::mem::take(content) {
+        if let Some(text) = item.get("text").and_then(serde_json::Value::as_str) {
+            if text.len() > left {
+                let mut end = left;
+                while !text.is_char_boundary(end) {
+                    end -= 1;
+                }
+                cut += text.len() - end;
+                left = 0;
+                if marked.is_some() {
+                    continue;
+                }
+                item["text"] = serde_json::Value::String(text[..end].to_string());
+                marked = Some(kept.len());
+            } else {
+                left -= text.len();
+            }
+        }
+        kept.push(item);
+    }
+    if let Some(index) = marked {
+        let text = kept[index]["text"].as_str().unwrap_or_default();
+        kept[index]["text"] = serde_json::Value::String(format!("{}\n[truncated {} bytes]", text, cut));
+    }
+    *content = kept;
+    if marked.is_none() {
+        return false;
+    }
+    if let Some(object) = result.as_object_mut() {
+        object.remove("structuredContent");
+        object.insert("isTruncated".to_string(), serde_json::Value::Bool(true));
+    }
+    true
+}
+
 /// MCP server runner trait - implement this for each server type
 pub trait McpServer {
     /// Get the server name
@@ -108,23 +618,145 @@ pub trait McpServer {
     /// Handle a tool call
     fn call_tool(&self, name: &str, arguments: &serde_json::Value) -> Result<serde_json::Value>;
 
+    /// Handle a tool call with transport context (override for session-aware tools)
+    fn call_tool_with_context(
+        &self,
+        name: &str,
+        arguments: &serde_json::Value,
+        _ctx: &RequestContext,
+    ) -> Result<serde_json::Value> {
+        self.call_tool(name, arguments)
+    }
+
+    /// Whether the server offers resources (advertised as the `resources` capability)
+    fn has_resources(&self) -> bool {
+        false
+    }
+
+    /// Get the list of resources this server provides
+    fn resources(&self, _ctx: &RequestContext) -> Vec<McpResource> {
+        vec![]
+    }
+
+    /// Read a resource, returning the `resources/read` result
+    fn read_resource(&self, uri: &str, _ctx: &RequestContext) -> Result<serde_json::Value> {
+        Err(anyhow::anyhow!("Unknown resource: {}", uri))
+    }
+
+    /// Whether clients can subscribe to changes of resources (advertised as `resources.subscribe`)
+    fn has_subscriptions(&self) -> bool {
+        false
+    }
+
+    /// Send the session `notifications/resources/updated` whenever the resource at `uri` changes
+    fn subscribe(&self, uri: &str, _ctx: &RequestContext) -> Result<()> {
+        Err(anyhow::anyhow!("Can't subscribe to {}", uri))
+    }
+
+    /// Stop sending the session updates of the resource at `uri`
+    fn unsubscribe(&self, _uri: &str, _ctx: &RequestContext) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether the server offers prompts (advertised as the `prompts` capability)
+    fn has_prompts(&self) -> bool {
+        false
+    }
+
+    /// Get the list of prompts this server provides
+    fn prompts(&self) -> Vec<McpPrompt> {
+        vec![]
+    }
+
+    /// Fill in a prompt with `arguments` (an object of strings), returning the `prompts/get` result
+    fn get_prompt(&self, name: &str, _arguments: &serde_json::Value) -> Result<serde_json::Value> {
+        Err(anyhow::anyhow!("Unknown prompt: {}", name))
+    }
+
+    /// Whether the server completes argument values (advertised as the `completions` capability)
+    fn has_completions(&self) -> bool {
+        false
+    }
+
+    /// Values `argument` could take that start with `value`, best first
+    fn complete(
+        &self,
+        _reference: &CompletionRef,
+        _argument: &str,
+        _value: &str,
+        _ctx: &RequestContext,
+    ) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+
+    /// Called with a client's `initialize` params (its capabilities) before it is answered
+    fn on_initialize(&self, _params: &serde_json::Value, _ctx: &RequestContext) {}
+
+    /// Called for each notification from a client, e.g. `notifications/roots/list_changed`
+    fn on_notification(&self, _method: &str, _params: &serde_json::Value, _ctx: &RequestContext) {}
+
+    /// Called with a client's answer to a request sent through the notifier
+    fn on_response(&self, _id: &serde_json::Value, _response: ClientAnswer, _ctx: &RequestContext) {}
+
+    /// Called when a session ends (HTTP session deleted/expired, or stdio closed with None)
+    fn on_session_end(&self, _session_id: Option<&str>) {}
+
+    /// Where the server sends notifications to clients (None if it never sends any); servers
+    /// with one offer the `logging` capability
+    fn notifier(&self) -> Option<&Notifier> {
+        None
+    }
+
+    /// Whether clients are held to the letter of JSON-RPC (`--strict`, see `strict::Strict`):
+    /// the transports refuse malformed messages, and notifications are never answered
+    fn strict(&self) -> bool {
+        false
+    }
+
     /// Whether verbose logging is enabled
     fn verbose(&self) -> bool;
 
-    /// Log a message if verbose is enabled
+    /// Log a message at info level
     fn log(&self, message: &str) {
+        self.log_at(LogLevel::Info, message)
+    }
+
+    /// Log a message to stderr if verbose is enabled, and to clients that asked for its level
+    fn log_at(&self, level: LogLevel, message: &str) {
         if self.verbose() {
             eprintln!("[mcpz] {}", message);
         }
+        if let Some(notifier) = self.notifier() {
+            notifier.message(level, self.name(), message);
+        }
     }
 
     /// Handle the initialize request
     fn handle_initialize(&self) -> serde_json::Value {
+        let tools = if self.notifier().is_some_and(Notifier::tools_change) {
+            serde_json::json!({ "listChanged": true })
+        } else {
+            serde_json::json!({})
+        };
+        let mut capabilities = serde_json::json!({ "tools": tools });
+        if self.has_resources() {
+            capabilities["resources"] = serde_json::json!({});
+            if self.has_subscriptions() {
+                capabilities["resources"]["subscribe"] = serde_json::json!(true);
+            }
+        }
+        if self.has_prompts() {
+            capabilities["prompts"] = serde_json::json!({});
+        }
+        if self.has_completions() {
+            capabilities["completions"] = serde_json::json!({});
+        }
+        if self.notifier().is_some() {
+            capabilities["logging"] = serde_json::json!({});
+        }
         serde_json::json!({
-            "protocolVersion": "2024-11-05",
-            "capabilities": {
-                "tools": {}
-            },
+            "protocolVersion": DEFAULT_PROTOCOL_VERSION,
+            "capabilities": capabilities,
             "serverInfo": {
                 "name": self.name(),
                 "version": self.version()
@@ -132,86 +764,479 @@ pub trait McpServer {
         })
     }
 
-    /// Handle the tools/list request
-    fn handle_tools_list(&self) -> serde_json::Value {
-        serde_json::json!({
-            "tools": self.tools()
-        })
+    /// Handle the tools/list request, a page at a time
+    fn handle_tools_list(&self, params: &serde_json::Value) -> Result<serde_json::Value> {
+        paginate("tools", self.tools(), params)
     }
 
     /// Handle the tools/call request
-    fn handle_tools_call(&self, params: &serde_json::Value) -> Result<serde_json::Value> {
+    fn handle_tools_call(&self, params: &serde_json::Value, ctx: &RequestContext) -> Result<serde_json::Value> {
         let name = params
             .get("name")
             .and_then(|v| v.as_str())
-            .ok_or_else(|| anyhow::anyhow!("Missing tool name"))?;
+            .ok_or_else(|| RpcError::invalid_params("Missing tool name"))?;
 
         let arguments = params
             .get("arguments")
             .cloned()
             .unwrap_or(serde_json::json!({}));
 
-        self.call_tool(name, &arguments)
+        self.call_tool_with_context(name, &arguments, ctx)
+    }
+
+    /// Handle the resources/read request
+    fn handle_resources_read(&self, params: &serde_json::Value, ctx: &RequestContext) -> Result<serde_json::Value> {
+        let uri = params
+            .get("uri")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| RpcError::invalid_params("Missing resource uri"))?;
+        self.read_resource(uri, ctx)
+    }
+
+    /// Handle the resources/subscribe and resources/unsubscribe requests
+    fn handle_subscription(
+        &self,
+        params: &serde_json::Value,
+        subscribe: bool,
+        ctx: &RequestContext,
+    ) -> Result<serde_json::Value> {
+        let uri = params
+            .get("uri")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| RpcError::invalid_params("Missing resource uri"))?;
+        if subscribe {
+            self.subscribe(uri, ctx)?;
+        } else {
+            self.unsubscribe(uri, ctx)?;
+        }
+        Ok(serde_json::json!({}))
+    }
+
+    /// Handle the prompts/get request
+    fn handle_prompts_get(&self, params: &serde_json::Value) -> Result<serde_json::Value> {
+        let name = params
+            .get("name")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Missing prompt name"))?;
+        let arguments = params.get("arguments").cloned().unwrap_or(serde_json::json!({}));
+        self.get_prompt(name, &arguments)
+    }
+
+    /// Handle the completion/complete request
+    fn handle_complete(&self, params: &serde_json::Value, ctx: &RequestContext) -> Result<serde_json::Value> {
+        let reference = params.get("ref").ok_or_else(|| anyhow::anyhow!("Missing completion ref"))?;
+        let reference: CompletionRef = serde_json::from_value(reference.clone())
+            .map_err(|_| anyhow::anyhow!("Invalid completion ref: {}", reference))?;
+        let argument = params.get("argument").unwrap_or(&serde_json::Value::Null);
+        let name = argument
+            .get("name")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Missing argument name"))?;
+        let value = argument.get("value").and_then(|v| v.as_str()).unwrap_or_default();
+
+        let mut values = self.complete(&reference, name, value, ctx)?;
+        let total = values.len();
+        values.truncate(MAX_COMPLETIONS);
+        Ok(serde_json::json!({
+            "completion": { "values": values, "total": total, "hasMore": total > MAX_COMPLETIONS }
+        }))
+    }
+
+    /// Handle a client's answer to a request sent through the notifier: to the `ask` waiting
+    /// for it, if any, or else to `on_response`
+    fn handle_client_response(&self, id: &serde_json::Value, answer: ClientAnswer, ctx: &RequestContext) {
+        let unclaimed = match self.notifier() {
+            Some(notifier) => notifier.answer(id, answer),
+            None => Some(answer),
+        };
+        if let Some(answer) = unclaimed {
+            self.on_response(id, answer, ctx);
+        }
+    }
+
+    /// Handle the logging/setLevel request
+    fn handle_set_level(&self, params: &serde_json::Value) -> Result<serde_json::Value> {
+        let level = params.get("level").ok_or_else(|| anyhow::anyhow!("Missing log level"))?;
+        let level: LogLevel =
+            serde_json::from_value(level.clone()).map_err(|_| anyhow::anyhow!("Unknown log level: {}", level))?;
+        if let Some(notifier) = self.notifier() {
+            notifier.set_level(level);
+        }
+        Ok(serde_json::json!({}))
     }
 
     /// Handle a JSON-RPC request
     fn handle_request(&self, req: JsonRpcRequest) -> Option<JsonRpcResponse> {
-        match req.method.as_str() {
-            "initialize" => Some(JsonRpcResponse::success(req.id, self.handle_initialize())),
-            "initialized" | "notifications/initialized" => None,
-            "tools/list" => Some(JsonRpcResponse::success(req.id, self.handle_tools_list())),
-            "tools/call" => match self.handle_tools_call(&req.params) {
+        self.handle_request_with_context(req, &RequestContext::default())
+    }
+
+    /// Handle a JSON-RPC request with transport context
+    fn handle_request_with_context(&self, req: JsonRpcRequest, ctx: &RequestContext) -> Option<JsonRpcResponse> {
+        // Notifications, even of methods nobody knows, don't get an answer in strict mode
+        let unanswered = self.strict() && req.id.is_none();
+        let response = match req.method.as_str() {
+            "initialize" => {
+                self.on_initialize(&req.params, ctx);
+                let requested = req.params.get("protocolVersion").cloned();
+                let version = match &requested {
+                    None => Some(DEFAULT_PROTOCOL_VERSION),
+                    Some(serde_json::Value::String(requested)) => negotiate_version(requested),
+                    Some(_) => None,
+                };
+                match version {
+                    Some(version) => {
+                        let mut result = self.handle_initialize();
+                        result["protocolVersion"] = serde_json::json!(version);
+                        Some(JsonRpcResponse::success(req.id, result))
+                    }
+                    None => Some(JsonRpcResponse::unsupported_version(req.id, requested)),
+                }
+            }
+            "initialized" => {
+                self.on_notification("notifications/initialized", &req.params, ctx);
+                None
+            }
+            method if method.starts_with("notifications/") => {
+                self.on_notification(method, &req.params, ctx);
+                None
+            }
+            "ping" => Some(JsonRpcResponse::success(req.id, serde_json::json!({}))),
+            "tools/list" => match self.handle_tools_list(&req.params) {
+                Ok(mut result) => {
+                    if ctx.protocol_version.as_deref().is_some_and(|version| version < STRUCTURED_OUTPUT_VERSION) {
+                        let tools = result.get_mut("tools").and_then(|tools| tools.as_array_mut());
+                        for tool in tools.into_iter().flatten().filter_map(|tool| tool.as_object_mut()) {
+                            tool.remove("outputSchema");
+                        }
+                    }
+                    Some(JsonRpcResponse::success(req.id, result))
+                }
+                Err(e) => Some(JsonRpcResponse::from_error(req.id, &e, INVALID_PARAMS_CODE)),
+            },
+            "tools/call" => match self.handle_tools_call(&req.params, ctx) {
+                Ok(mut result) => {
+                    // Clients on older versions don't know structured output
+                    if ctx.protocol_version.as_deref().is_some_and(|version| version < STRUCTURED_OUTPUT_VERSION) {
+                        if let Some(result) = result.as_object_mut() {
+                            result.remove("structuredContent");
+                        }
+                    }
+                    Some(JsonRpcResponse::success(req.id, result))
+                }
+                Err(e) => Some(JsonRpcResponse::from_error(req.id, &e, INTERNAL_ERROR_CODE)),
+            },
+            "resources/list" if self.has_resources() => match paginate("resources", self.resources(ctx), &req.params) {
+                Ok(result) => Some(JsonRpcResponse::success(req.id, result)),
+                Err(e) => Some(JsonRpcResponse::from_error(req.id, &e, INVALID_PARAMS_CODE)),
+            },
+            "resources/read" if self.has_resources() => match self.handle_resources_read(&req.params, ctx) {
+                Ok(result) => Some(JsonRpcResponse::success(req.id, result)),
+                Err(e) => Some(JsonRpcResponse::from_error(req.id, &e, RESOURCE_NOT_FOUND_CODE)),
+            },
+            method @ ("resources/subscribe" | "resources/unsubscribe") if self.has_subscriptions() => {
+                match self.handle_subscription(&req.params, method == "resources/subscribe", ctx) {
+                    Ok(result) => Some(JsonRpcResponse::success(req.id, result)),
+                    Err(e) => Some(JsonRpcResponse::from_error(req.id, &e, RESOURCE_NOT_FOUND_CODE)),
+                }
+            }
+            "prompts/list" if self.has_prompts() => match paginate("prompts", self.prompts(), &req.params) {
+                Ok(result) => Some(JsonRpcResponse::success(req.id, result)),
+                Err(e) => Some(JsonRpcResponse::from_error(req.id, &e, INVALID_PARAMS_CODE)),
+            },
+            "prompts/get" if self.has_prompts() => match self.handle_prompts_get(&req.params) {
                 Ok(result) => Some(JsonRpcResponse::success(req.id, result)),
-                Err(e) => Some(JsonRpcResponse::internal_error(req.id, e.to_string())),
+                Err(e) => Some(JsonRpcResponse::from_error(req.id, &e, INVALID_PARAMS_CODE)),
+            },
+            "completion/complete" if self.has_completions() => match self.handle_complete(&req.params, ctx) {
+                Ok(result) => Some(JsonRpcResponse::success(req.id, result)),
+                Err(e) => Some(JsonRpcResponse::from_error(req.id, &e, INVALID_PARAMS_CODE)),
+            },
+            "logging/setLevel" if self.notifier().is_some() => match self.handle_set_level(&req.params) {
+                Ok(result) => Some(JsonRpcResponse::success(req.id, result)),
+                Err(e) => Some(JsonRpcResponse::from_error(req.id, &e, INVALID_PARAMS_CODE)),
             },
             _ => Some(JsonRpcResponse::method_not_found(req.id, &req.method)),
-        }
+        };
+        response.filter(|_| !unanswered)
     }
 
-    /// Run the server main loop
-    fn run(&self) -> Result<()> {
+    /// Run the server main loop, copying every message to `recorder` (`--record`)
+    fn run(&self, recorder: Option<&Recorder>) -> Result<()> {
         self.log(&format!("{} server started", self.name()));
 
-        let stdin = std::io::stdin();
-        let mut stdout = std::io::stdout();
+        let done = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            if let Some(notifier) = self.notifier() {
+                let mut notifications = notifier.subscribe();
+                let done = &done;
+                scope.spawn(move || forward_notifications(&mut notifications, recorder, done));
+            }
+            let result = serve_stdio(self, recorder);
+            done.store(true, Ordering::SeqCst);
+            result
+        })?;
+
+        self.on_session_end(None);
+        self.log(&format!("{} server stopped", self.name()));
+        Ok(())
+    }
+}
 
-        for line in stdin.lock().lines() {
-            let line = match line {
-                Ok(l) => l,
-                Err(e) => {
-                    self.log(&format!("Error reading stdin: {}", e));
+/// Answer requests from stdin on stdout until stdin closes. Stdin is read on a thread of its
+/// own, so the client's answers reach `Notifier::ask` while a request waits for them.
+fn serve_stdio<S: McpServer + ?Sized>(server: &S, recorder: Option<&Recorder>) -> Result<()> {
+    let (sender, lines) = mpsc::channel();
+    let notifier = server.notifier();
+    let strict = server.strict();
+    std::thread::scope(|scope| {
+        scope.spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let answer = line.as_ref().ok().and_then(|line| serde_json::from_str(line).ok());
+                // Malformed answers are left to the loop to refuse
+                let answer = answer.filter(|message| !strict || check_message(message).is_ok());
+                if let (Some(notifier), Some((id, answer))) = (notifier, answer.as_ref().and_then(client_response)) {
+                    if notifier.answer(&id, answer).is_none() {
+                        if let (Some(recorder), Ok(line)) = (recorder, &line) {
+                            recorder.record(Sender::Client, line.as_bytes());
+                        }
+                        continue;
+                    }
+                }
+                if sender.send(line).is_err() {
                     break;
                 }
-            };
+            }
+        });
+        serve_lines(server, lines, recorder)
+    })
+}
 
-            if line.is_empty() {
-                continue;
+/// Answer the requests among `lines` until they end
+fn serve_lines<S: McpServer + ?Sized>(
+    server: &S,
+    lines: mpsc::Receiver<std::io::Result<String>>,
+    recorder: Option<&Recorder>,
+) -> Result<()> {
+    let mut ctx = RequestContext::default();
+
+    for line in lines {
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => {
+                server.log_at(LogLevel::Error, &format!("Error reading stdin: {}", e));
+                break;
             }
+        };
 
-            self.log(&format!("Received: {}", line));
+        if line.is_empty() {
+            continue;
+        }
 
-            let request: JsonRpcRequest = match serde_json::from_str(&line) {
-                Ok(r) => r,
-                Err(e) => {
-                    self.log(&format!("Parse error: {}", e));
-                    let error_response = JsonRpcResponse::parse_error(format!("Parse error: {}", e));
-                    let response_json = serde_json::to_string(&error_response)?;
-                    writeln!(stdout, "{}", response_json)?;
-                    stdout.flush()?;
-                    continue;
+        server.log_at(LogLevel::Debug, &format!("Received: {}", line));
+        if let Some(recorder) = recorder {
+            recorder.record(Sender::Client, line.as_bytes());
+        }
+
+        let message: Option<serde_json::Value> = serde_json::from_str(&line).ok();
+        if let Some(message) = message.as_ref().filter(|_| server.strict()) {
+            if let Err(reason) = check_message(message) {
+                server.log_at(LogLevel::Warning, &format!("Refusing invalid message: {}", reason));
+                if let Some(answer) = refusal(message, reason) {
+                    write_response(server, &answer, recorder)?;
                 }
-            };
+                continue;
+            }
+        }
+
+        if let Some((id, response)) = message.as_ref().and_then(client_response) {
+            server.handle_client_response(&id, response, &ctx);
+            continue;
+        }
+
+        let request: JsonRpcRequest = match serde_json::from_str(&line) {
+            Ok(r) => r,
+            Err(e) => {
+                server.log_at(LogLevel::Error, &format!("Parse error: {}", e));
+                write_response(server, &JsonRpcResponse::parse_error(format!("Parse error: {}", e)), recorder)?;
+                continue;
+            }
+        };
+
+        let initialize = request.method == "initialize";
+        if let Some(response) = server.handle_request_with_context(request, &ctx) {
+            if initialize {
+                // Later requests follow the version agreed on
+                ctx.protocol_version = response
+                    .result
+                    .as_ref()
+                    .and_then(|result| result.get("protocolVersion"))
+                    .and_then(|version| version.as_str())
+                    .map(str::to_string);
+            }
+            write_response(server, &response, recorder)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Write `response` to stdout, copying it to `recorder`
+fn write_response<S: McpServer + ?Sized>(
+    server: &S,
+    response: &JsonRpcResponse,
+    recorder: Option<&Recorder>,
+) -> Result<()> {
+    let response_json = serde_json::to_string(response)?;
+    server.log_at(LogLevel::Debug, &format!("Sending: {}", response_json));
+    if let Some(recorder) = recorder {
+        recorder.record(Sender::Server, response_json.as_bytes());
+    }
+    write_line(&response_json)?;
+    Ok(())
+}
+
+/// Write one message line to stdout; the lock keeps it whole when notifications interleave
+fn write_line(line: &str) -> std::io::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "{}", line)?;
+    stdout.flush()
+}
 
-            if let Some(response) = self.handle_request(request) {
-                let response_json = serde_json::to_string(&response)?;
-                self.log(&format!("Sending: {}", response_json));
-                writeln!(stdout, "{}", response_json)?;
-                stdout.flush()?;
+/// Copy notifications to stdout until the stdio loop is `done`
+fn forward_notifications(
+    notifications: &mut broadcast::Receiver<Outgoing>,
+    recorder: Option<&Recorder>,
+    done: &AtomicBool,
+) {
+    while !done.load(Ordering::SeqCst) {
+        match notifications.try_recv() {
+            Ok(outgoing) => {
+                let line = outgoing.message.to_string();
+                if let Some(recorder) = recorder {
+                    recorder.record(Sender::Server, line.as_bytes());
+                }
+                if write_line(&line).is_err() {
+                    return;
+                }
             }
+            Err(broadcast::error::TryRecvError::Lagged(_)) => {}
+            Err(broadcast::error::TryRecvError::Empty) => std::thread::sleep(Duration::from_millis(100)),
+            Err(broadcast::error::TryRecvError::Closed) => return,
         }
+    }
+}
 
-        self.log(&format!("{} server stopped", self.name()));
-        Ok(())
+/// A shared server, e.g. a gateway router that is reloaded from another thread while it serves
+impl<T: McpServer + ?Sized> McpServer for Arc<T> {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn version(&self) -> &str {
+        (**self).version()
+    }
+
+    fn tools(&self) -> Vec<McpTool> {
+        (**self).tools()
+    }
+
+    fn call_tool(&self, name: &str, arguments: &serde_json::Value) -> Result<serde_json::Value> {
+        (**self).call_tool(name, arguments)
+    }
+
+    fn call_tool_with_context(
+        &self,
+        name: &str,
+        arguments: &serde_json::Value,
+        ctx: &RequestContext,
+    ) -> Result<serde_json::Value> {
+        (**self).call_tool_with_context(name, arguments, ctx)
+    }
+
+    fn has_resources(&self) -> bool {
+        (**self).has_resources()
+    }
+
+    fn resources(&self, ctx: &RequestContext) -> Vec<McpResource> {
+        (**self).resources(ctx)
+    }
+
+    fn read_resource(&self, uri: &str, ctx: &RequestContext) -> Result<serde_json::Value> {
+        (**self).read_resource(uri, ctx)
+    }
+
+    fn has_subscriptions(&self) -> bool {
+        (**self).has_subscriptions()
+    }
+
+    fn subscribe(&self, uri: &str, ctx: &RequestContext) -> Result<()> {
+        (**self).subscribe(uri, ctx)
+    }
+
+    fn unsubscribe(&self, uri: &str, ctx: &RequestContext) -> Result<()> {
+        (**self).unsubscribe(uri, ctx)
+    }
+
+    fn has_prompts(&self) -> bool {
+        (**self).has_prompts()
+    }
+
+    fn prompts(&self) -> Vec<McpPrompt> {
+        (**self).prompts()
+    }
+
+    fn get_prompt(&self, name: &str, arguments: &serde_json::Value) -> Result<serde_json::Value> {
+        (**self).get_prompt(name, arguments)
+    }
+
+    fn has_completions(&self) -> bool {
+        (**self).has_completions()
+    }
+
+    fn complete(
+        &self,
+        reference: &CompletionRef,
+        argument: &str,
+        value: &str,
+        ctx: &RequestContext,
+    ) -> Result<Vec<String>> {
+        (**self).complete(reference, argument, value, ctx)
+    }
+
+    fn on_initialize(&self, params: &serde_json::Value, ctx: &RequestContext) {
+        (**self).on_initialize(params, ctx)
+    }
+
+    fn on_notification(&self, method: &str, params: &serde_json::Value, ctx: &RequestContext) {
+        (**self).on_notification(method, params, ctx)
+    }
+
+    fn on_response(&self, id: &serde_json::Value, response: ClientAnswer, ctx: &RequestContext) {
+        (**self).on_response(id, response, ctx)
+    }
+
+    fn on_session_end(&self, session_id: Option<&str>) {
+        (**self).on_session_end(session_id)
+    }
+
+    fn notifier(&self) -> Option<&Notifier> {
+        (**self).notifier()
+    }
+
+    fn strict(&self) -> bool {
+        (**self).strict()
+    }
+
+    fn verbose(&self) -> bool {
+        (**self).verbose()
+    }
+
+    fn handle_initialize(&self) -> serde_json::Value {
+        (**self).handle_initialize()
+    }
+
+    fn handle_request_with_context(&self, req: JsonRpcRequest, ctx: &RequestContext) -> Option<JsonRpcResponse> {
+        (**self).handle_request_with_context(req, ctx)
     }
 }
 
@@ -219,6 +1244,70 @@ pub trait McpServer {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_truncate_result() {
+        let mut small = text_content("short");
+        assert!(!truncate_result(&mut small, 100));
+        assert_eq!(small, text_content("short"));
+
+        let mut result = serde_json::json!({
+            "content": [
+                {"type": "text", "text": "abcdef"},
+                {"type": "image", "data": "AAAA", "mimeType": "image/png"},
+                {"type": "text", "text": "héllo"},
+                {"type": "text", "text": "more"}
+            ],
+            "structuredContent": {"lines": 3}
+        });
+        assert!(truncate_result(&mut result, 8));
+        assert_eq!(
+            result,
+            serde_json::json!({
+                "content": [
+                    {"type": "text", "text": "abcdef"},
+                    {"type": "image", "data": "AAAA", "mimeType": "image/png"},
+                    // 'é' is two bytes, so it isn't split
+                    {"type": "text", "text": "h\n[truncated 9 bytes]"}
+                ],
+                "isTruncated": true
+            })
+        );
+    }
+
+    #[test]
+    fn test_paginate() {
+        let items: Vec<usize> = (0..PAGE_SIZE * 2 + 5).collect();
+        let mut params = serde_json::json!({});
+        let mut pages = vec![];
+        loop {
+            let page = paginate("tools", items.clone(), &params).unwrap();
+            pages.push(page["tools"].as_array().unwrap().len());
+            match page.get("nextCursor") {
+                Some(cursor) => params = serde_json::json!({ "cursor": cursor }),
+                None => break,
+            }
+        }
+        assert_eq!(pages, [PAGE_SIZE, PAGE_SIZE, 5]);
+        let all = paginate("tools", vec![1, 2], &serde_json::json!({})).unwrap();
+        assert_eq!(all, serde_json::json!({"tools": [1, 2]}));
+        assert!(paginate("tools", items.clone(), &serde_json::json!({"cursor": "bogus"})).is_err());
+        let past = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, "1000");
+        let after_end = paginate("tools", items, &serde_json::json!({ "cursor": past })).unwrap();
+        assert_eq!(after_end, serde_json::json!({"tools": []}));
+    }
+
+    #[test]
+    fn test_negotiate_version() {
+        assert_eq!(negotiate_version("2024-11-05"), Some("2024-11-05"));
+        assert_eq!(negotiate_version("2025-03-26"), Some("2025-03-26"));
+        assert_eq!(negotiate_version("2025-06-18"), Some("2025-06-18"));
+        // Newer than ours: offer our latest
+        assert_eq!(negotiate_version("2099-01-01"), Some("2025-06-18"));
+        assert_eq!(negotiate_version("2024-10-07"), None);
+        assert_eq!(negotiate_version("1.0.0"), None);
+        assert_eq!(negotiate_version("2099-1-1x"), None);
+    }
+
     #[test]
     fn test_json_rpc_response_success() {
         let resp = JsonRpcResponse::success(Some(serde_json::json!(1)), serde_json::json!({"test": true}));
@@ -227,6 +1316,24 @@ mod tests {
         assert!(resp.error.is_none());
     }
 
+    #[test]
+    fn test_json_rpc_response_from_error() {
+        let answer = |error: anyhow::Error| {
+            let response = JsonRpcResponse::from_error(Some(serde_json::json!(1)), &error, INTERNAL_ERROR_CODE);
+            serde_json::to_value(response).unwrap()["error"].clone()
+        };
+
+        assert_eq!(answer(anyhow::anyhow!("boom")), serde_json::json!({"code": -32603, "message": "boom"}));
+        let missing = answer(RpcError::invalid_params("Missing 'path' argument").into());
+        assert_eq!(missing, serde_json::json!({"code": -32602, "message": "Missing 'path' argument"}));
+        // Context keeps the error's data, under the request's code
+        let data = serde_json::json!({"kind": "access_denied", "path": "/etc"});
+        let denied = anyhow::Error::from(RpcError::with_data("Access denied", data.clone())).context("Listing /etc");
+        assert_eq!(answer(denied), serde_json::json!({"code": -32603, "message": "Listing /etc", "data": data}));
+        let upstream = UpstreamError { code: -32001, message: "Busy".to_string(), data: Some(serde_json::json!(3)) };
+        assert_eq!(answer(upstream.into()), serde_json::json!({"code": -32001, "message": "Busy", "data": 3}));
+    }
+
     #[test]
     fn test_json_rpc_response_error() {
         let resp = JsonRpcResponse::error(Some(serde_json::json!(1)), -32600, "Invalid Request".to_string());
@@ -252,6 +1359,14 @@ mod tests {
         assert_eq!(content["content"][0]["text"], "Hello, World!");
     }
 
+    #[test]
+    fn test_structured_content() {
+        let content = structured_content(&serde_json::json!({"rows_affected": 3})).unwrap();
+        assert_eq!(content["structuredContent"]["rows_affected"], 3);
+        assert!(content["content"][0]["text"].as_str().unwrap().contains("\"rows_affected\": 3"));
+        assert!(content.get("isError").is_none());
+    }
+
     #[test]
     fn test_error_content() {
         let content = error_content("Something went wrong");
@@ -260,6 +1375,31 @@ mod tests {
         assert_eq!(content["isError"], true);
     }
 
+    #[test]
+    fn test_multi_part_content() {
+        let content = Content::default()
+            .text("Screenshot:")
+            .image(&[0x89, b'P', b'N', b'G'], "image/png")
+            .resource("file:///notes.md", Some("text/markdown"), b"# Notes".to_vec())
+            .resource("file:///data.bin", None, vec![0xff, 0xfe])
+            .build();
+        let items = content["content"].as_array().unwrap();
+        assert_eq!(items[0]["text"], "Screenshot:");
+        assert_eq!(items[1], serde_json::json!({"type": "image", "data": "iVBORw==", "mimeType": "image/png"}));
+        assert_eq!(items[2]["type"], "resource");
+        assert_eq!(items[2]["resource"]["text"], "# Notes");
+        assert_eq!(items[2]["resource"]["mimeType"], "text/markdown");
+        assert_eq!(items[3]["resource"]["blob"], "//4=");
+        assert_eq!(items[3]["resource"]["mimeType"], "application/octet-stream");
+        assert_eq!(image_content(b"GIF8", "image/gif")["content"][0]["type"], "image");
+
+        // Only text is truncated
+        let mut content = Content::default().image(&[0; 64], "image/png").text("abcdef").build();
+        assert!(truncate_result(&mut content, 3));
+        assert_eq!(content["content"][0]["mimeType"], "image/png");
+        assert_eq!(content["content"][1]["text"], "abc\n[truncated 3 bytes]");
+    }
+
     #[test]
     fn test_mcp_tool_serialization() {
         let tool = McpTool {
@@ -269,9 +1409,11 @@ mod tests {
                 "type": "object",
                 "properties": {}
             }),
+            output_schema: None,
         };
         let json = serde_json::to_string(&tool).unwrap();
         assert!(json.contains("\"name\":\"test_tool\""));
         assert!(json.contains("\"inputSchema\""));
+        assert!(!json.contains("outputSchema"));
     }
 }
@@ -1,24 +1,109 @@
 use anyhow::{anyhow, Context, Result};
 use serde::{Deserialize, Serialize};
 use std::fs::{self, File};
-use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
-use std::os::unix::fs::PermissionsExt;
-use std::path::{Path, PathBuf};
-use std::time::SystemTime;
-
-use super::common::{error_content, text_content, McpServer, McpTool};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use super::gitignore::GitIgnores;
+use super::glob::Globs;
+use super::patch::{self, FilePatch, PatchOutcome, PatchedFile};
+use super::common::{
+    error_content, image_content, Content, resource_content, resource_contents, resource_text, text_content,
+    text_with_structured, ClientAnswer, CompletionRef, LogLevel, McpResource, McpServer, McpTool, Notifier,
+    RequestContext, RpcError,
+};
+use super::archive::{self, ArchiveFormat};
+use super::audit::{AuditLog, Audited};
+use super::strict::Strict;
+use super::subscriptions::FileSubscriptions;
+use super::watches::{Changes, PathWatches};
+use crate::record::Recorder;
+
+/// Session key used to track temp directories created over stdio
+const STDIO_SESSION: &str = "stdio";
+
+/// Largest file `read_file_binary` and `resources/read` return, in bytes
+const MAX_BINARY_READ: u64 = 10 * 1024 * 1024;
+
+/// Most bytes `read_file` returns for a `byteOffset`/`length` chunk
+const MAX_CHUNK_READ: usize = 1024 * 1024;
+
+/// Unchanged lines `edit_file`'s diff shows around each change unless asked for others
+const DIFF_CONTEXT: usize = 3;
+
+/// Longest `poll_changes` waits for changes, in seconds
+const MAX_POLL_WAIT: u64 = 60;
+
+/// Most files `resources/list` returns; the rest can still be read by URI
+const MAX_RESOURCES: usize = 1000;
+
+/// Largest text file `get_file_info` counts the lines of, in bytes
+const MAX_LINE_COUNT: u64 = 100 * 1024 * 1024;
+
+/// Entries `list_directory` and `directory_tree` return per page unless asked for more or fewer
+const DEFAULT_MAX_ENTRIES: usize = 1000;
+
+/// Tools that change the filesystem, with the arguments naming what they change; paths in
+/// read-only directories are refused
+const WRITE_ARGUMENTS: &[(&str, &[&str])] = &[
+    ("write_file", &["path"]),
+    ("append_file", &["path"]),
+    ("edit_file", &["path"]),
+    ("create_directory", &["path"]),
+    ("move_file", &["source", "destination"]),
+    ("copy_file", &["destination"]),
+    ("copy_directory", &["destination"]),
+    ("create_archive", &["destination"]),
+    ("extract_archive", &["destination"]),
+    ("create_symlink", &["path"]),
+    ("set_permissions", &["path"]),
+    ("delete_file", &["path"]),
+    ("delete_directory", &["path"]),
+];
+
+/// A file `apply_patch` changes: the file it was (unless created) and is (unless deleted),
+/// and its new content
+type PatchChange = (Option<PathBuf>, Option<PathBuf>, String);
+
+/// Tool arguments that take paths, and are completed as paths
+const PATH_ARGUMENTS: &[&str] = &["path", "source", "destination", "paths"];
 
 /// Configuration for the filesystem server
 pub struct FilesystemServerConfig {
     pub allowed_directories: Vec<PathBuf>,
+    /// Allowed directories given as `PATH:ro`, in which tools may only read
+    pub read_only_directories: Vec<PathBuf>,
+    /// Root for `create_temp_dir` (defaults to the first allowed directory)
+    pub scratch_root: Option<PathBuf>,
+    /// Narrow each session's access to the roots its client reports (`--client-roots`)
+    pub client_roots: bool,
+    /// Offer `delete_file` and `delete_directory` (`--allow-delete`)
+    pub allow_delete: bool,
+    /// Offer `set_permissions` (`--allow-chmod`)
+    pub allow_chmod: bool,
+    /// Which symlinks paths may lead through (`--follow-symlinks`)
+    pub follow_symlinks: SymlinkPolicy,
+    /// Leave out `.gitignore`d paths in `directory_tree` and the searches unless a call says
+    /// otherwise (on unless `--no-gitignore`)
+    pub respect_gitignore: bool,
+    /// Globs for paths no tool may see or touch, e.g. credentials (`--exclude`)
+    pub exclude: Vec<String>,
+    excluded: Globs,
     pub verbose: bool,
 }
 
 impl FilesystemServerConfig {
+    /// `allowed_directories` may end in `:ro` to allow only reading, or `:rw` (the default)
     pub fn new(allowed_directories: Vec<PathBuf>, verbose: bool) -> Result<Self> {
         // Validate and resolve all directories
         let mut resolved_dirs = Vec::new();
+        let mut read_only_dirs = Vec::new();
         for dir in allowed_directories {
+            let (dir, read_only) = split_access(&dir);
             let expanded = expand_home(&dir);
             let absolute = if expanded.is_absolute() {
                 expanded
@@ -40,6 +125,9 @@ impl FilesystemServerConfig {
                 return Err(anyhow!("{} is not a directory", resolved.display()));
             }
 
+            if read_only {
+                read_only_dirs.push(resolved.clone());
+            }
             resolved_dirs.push(resolved);
         }
 
@@ -51,9 +139,135 @@ impl FilesystemServerConfig {
 
         Ok(Self {
             allowed_directories: resolved_dirs,
+            read_only_directories: read_only_dirs,
+            scratch_root: None,
+            client_roots: false,
+            allow_delete: false,
+            allow_chmod: false,
+            respect_gitignore: true,
+            follow_symlinks: SymlinkPolicy::default(),
+            exclude: vec![],
+            excluded: Globs::default(),
             verbose,
         })
     }
+
+    /// Only allow what is also within the roots each client reports, for clients supporting roots
+    pub fn with_client_roots(mut self, client_roots: bool) -> Self {
+        self.client_roots = client_roots;
+        self
+    }
+
+    /// Offer tools that delete files and directories
+    pub fn with_allow_delete(mut self, allow_delete: bool) -> Self {
+        self.allow_delete = allow_delete;
+        self
+    }
+
+    /// Offer a tool that changes file permissions
+    pub fn with_allow_chmod(mut self, allow_chmod: bool) -> Self {
+        self.allow_chmod = allow_chmod;
+        self
+    }
+
+    /// Which symlinks inside the allowed directories paths may go through
+    pub fn with_follow_symlinks(mut self, follow_symlinks: SymlinkPolicy) -> Self {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Whether `directory_tree` and the searches skip `.gitignore`d paths by default
+    pub fn with_respect_gitignore(mut self, respect_gitignore: bool) -> Self {
+        self.respect_gitignore = respect_gitignore;
+        self
+    }
+
+    /// Hide paths matching any of the comma-separated globs from every tool
+    pub fn with_exclude(mut self, exclude: Option<String>) -> Result<Self> {
+        if let Some(exclude) = exclude {
+            self.exclude = exclude.split(',').map(|p| p.trim().to_string()).filter(|p| !p.is_empty()).collect();
+            self.excluded = Globs::new(&self.exclude, false).context("Invalid --exclude")?;
+        }
+        Ok(self)
+    }
+
+    /// Set the scratch root for temp directories; it must lie within the allowed directories
+    pub fn with_scratch_root(mut self, scratch_root: Option<PathBuf>) -> Result<Self> {
+        if let Some(root) = scratch_root {
+            let expanded = expand_home(&root);
+            let resolved = fs::canonicalize(&expanded)
+                .with_context(|| format!("Cannot access scratch directory: {}", expanded.display()))?;
+
+            if !resolved.is_dir() {
+                return Err(anyhow!("{} is not a directory", resolved.display()));
+            }
+            if !is_within_allowed(&resolved, &self.allowed_directories) {
+                return Err(anyhow!(
+                    "Scratch directory must be within allowed directories: {}",
+                    resolved.display()
+                ));
+            }
+
+            self.scratch_root = Some(resolved);
+        }
+        Ok(self)
+    }
+
+    /// Directory in which temp directories are created
+    fn scratch_root(&self) -> &Path {
+        self.scratch_root
+            .as_deref()
+            .unwrap_or(&self.allowed_directories[0])
+    }
+
+    /// Whether `path` may only be read: the innermost allowed directory holding it is read-only
+    fn is_read_only(&self, path: &Path) -> bool {
+        let holding = self.allowed_directories.iter().filter(|dir| path.starts_with(dir));
+        let innermost = holding.max_by_key(|dir| dir.as_os_str().len());
+        innermost.is_some_and(|dir| self.read_only_directories.contains(dir))
+    }
+
+    /// A read-only allowed directory at or under `path`, which a tool changing everything
+    /// under `path` would change too
+    fn read_only_within(&self, path: &Path) -> Option<&Path> {
+        self.read_only_directories.iter().find(|dir| dir.starts_with(path)).map(PathBuf::as_path)
+    }
+
+    /// Whether `path` is hidden by the `exclude` globs, matched against its path relative to an
+    /// allowed directory holding it. Everything in a matching directory is hidden with it.
+    /// `path` is canonical, as `validate_path` returns it; one still climbing with `..` can't
+    /// be matched reliably, so it counts as hidden.
+    fn excludes(&self, path: &Path) -> bool {
+        if self.excluded.is_empty() {
+            return false;
+        }
+        if path.components().any(|part| matches!(part, Component::ParentDir)) {
+            return true;
+        }
+        let mut relatives: Vec<&Path> =
+            self.allowed_directories.iter().filter_map(|dir| path.strip_prefix(dir).ok()).collect();
+        if relatives.is_empty() {
+            relatives.push(path);
+        }
+        relatives.into_iter().any(|relative| {
+            let names: Vec<String> = relative
+                .components()
+                .filter(|part| !matches!(part, Component::RootDir | Component::Prefix(_)))
+                .map(|part| part.as_os_str().to_string_lossy().to_string())
+                .collect();
+            (1..=names.len()).any(|end| self.excluded.matches(&names[..end].join("/")))
+        })
+    }
+}
+
+/// A `--dir` value's path, and whether it ends in `:ro`. A `:rw` suffix is dropped too.
+fn split_access(dir: &Path) -> (PathBuf, bool) {
+    let text = dir.to_string_lossy();
+    match text.rsplit_once(':') {
+        Some((path, "ro")) => (PathBuf::from(path), true),
+        Some((path, "rw")) => (PathBuf::from(path), false),
+        _ => (dir.to_path_buf(), false),
+    }
 }
 
 /// Expand ~ to home directory
@@ -66,14 +280,65 @@ fn expand_home(path: &Path) -> PathBuf {
     path.to_path_buf()
 }
 
-/// Validate that a path is within allowed directories
-fn validate_path(path: &str, allowed_dirs: &[PathBuf]) -> Result<PathBuf> {
+/// The innermost of `path` and its ancestors that exists, made absolute, and the rest of
+/// `path` below it. The rest names directories to be created, so it may not climb with `..`.
+fn existing_ancestor(path: &Path) -> Result<(PathBuf, PathBuf)> {
+    let absolute = if path.is_absolute() { path.to_path_buf() } else { std::env::current_dir()?.join(path) };
+    let ancestor = absolute
+        .ancestors()
+        .find(|ancestor| fs::symlink_metadata(ancestor).is_ok())
+        .ok_or_else(|| anyhow!("Cannot access path: {}", absolute.display()))?;
+    let rest = absolute.strip_prefix(ancestor)?;
+    if !rest.components().all(|part| matches!(part, Component::Normal(_))) {
+        return Err(access_denied("path climbs out of a directory that doesn't exist", &absolute));
+    }
+    Ok((ancestor.to_path_buf(), rest.to_path_buf()))
+}
+
+/// Which symlinks inside the allowed directories paths may lead through (`--follow-symlinks`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum SymlinkPolicy {
+    /// Refuse paths that go through a symlink inside the allowed directories
+    Never,
+    /// Follow symlinks as long as they lead somewhere inside the allowed directories
+    #[default]
+    WithinAllowed,
+    /// Follow symlinks inside the allowed directories wherever they lead
+    Always,
+}
+
+/// The first symlink on `path` that lives inside the allowed directories, if any. Links
+/// above them, such as `/tmp` on macOS, don't count.
+fn sandboxed_link(path: &Path, allowed_dirs: &[PathBuf]) -> Option<PathBuf> {
+    let mut current = PathBuf::new();
+    for component in path.components() {
+        current.push(component);
+        if !fs::symlink_metadata(&current).is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+            continue;
+        }
+        let parent = current.parent().and_then(|parent| fs::canonicalize(parent).ok());
+        if parent.is_some_and(|parent| is_within_allowed(&parent, allowed_dirs)) {
+            return Some(current);
+        }
+    }
+    None
+}
+
+/// Validate that a path is within allowed directories, following symlinks as `policy` allows
+fn validate_path(path: &str, allowed_dirs: &[PathBuf], policy: SymlinkPolicy) -> Result<PathBuf> {
     let expanded = expand_home(Path::new(path));
     let absolute = if expanded.is_absolute() {
         expanded
     } else {
         std::env::current_dir()?.join(&expanded)
     };
+    let link = match policy {
+        SymlinkPolicy::WithinAllowed => None,
+        _ => sandboxed_link(&absolute, allowed_dirs),
+    };
+    if let (SymlinkPolicy::Never, Some(link)) = (policy, &link) {
+        return Err(access_denied("path goes through a symlink (--follow-symlinks never)", link));
+    }
 
     // Try to resolve symlinks to get the real path
     let resolved = match fs::canonicalize(&absolute) {
@@ -81,40 +346,83 @@ fn validate_path(path: &str, allowed_dirs: &[PathBuf]) -> Result<PathBuf> {
         Err(e) => {
             // For new files, check parent directory
             if e.kind() == std::io::ErrorKind::NotFound {
-                if let Some(parent) = absolute.parent() {
+                if let Some((parent, file_name)) = absolute.parent().zip(absolute.file_name()) {
                     let parent_resolved = fs::canonicalize(parent)
                         .with_context(|| format!("Parent directory does not exist: {}", parent.display()))?;
 
                     // Check if parent is within allowed directories
-                    if !is_within_allowed(&parent_resolved, allowed_dirs) {
-                        return Err(anyhow!(
-                            "Access denied - parent directory outside allowed directories: {}",
-                            parent_resolved.display()
-                        ));
+                    if !is_within_allowed(&parent_resolved, allowed_dirs) && link.is_none() {
+                        return Err(access_denied("parent directory outside allowed directories", &parent_resolved));
                     }
-                    return Ok(absolute);
+                    // Canonical like existing paths, so `..` can't make it look like it is
+                    // somewhere else
+                    return Ok(parent_resolved.join(file_name));
                 }
             }
             return Err(anyhow!("Cannot access path: {} - {}", absolute.display(), e));
         }
     };
 
-    // Check if resolved path is within allowed directories
-    if !is_within_allowed(&resolved, allowed_dirs) {
-        return Err(anyhow!(
-            "Access denied - path outside allowed directories: {}",
-            resolved.display()
-        ));
+    // Check if resolved path is within allowed directories, or reached through a symlink
+    // inside them that `policy` follows anywhere
+    if !is_within_allowed(&resolved, allowed_dirs) && link.is_none() {
+        return Err(access_denied("path outside allowed directories", &resolved));
     }
 
     Ok(resolved)
 }
 
+/// The error for a path outside the allowed directories, naming it in the JSON-RPC
+/// error's `data` as well
+fn access_denied(reason: &str, path: &Path) -> anyhow::Error {
+    let data = serde_json::json!({ "kind": "access_denied", "path": path });
+    RpcError::with_data(format!("Access denied - {}: {}", reason, path.display()), data).into()
+}
+
 /// Check if a path is within any of the allowed directories
 fn is_within_allowed(path: &Path, allowed_dirs: &[PathBuf]) -> bool {
     allowed_dirs.iter().any(|allowed| path.starts_with(allowed))
 }
 
+/// Paths inside `dirs` that start with `value`: the directories themselves, then the entries
+/// of the directory `value` ends in, directories with a trailing `/`. Hidden entries are only
+/// offered once `value` starts naming one, and entries `excluded` holds never are.
+fn complete_path(value: &str, dirs: &[PathBuf], excluded: impl Fn(&Path) -> bool) -> Vec<String> {
+    let mut values: Vec<String> = dirs
+        .iter()
+        .map(|dir| format!("{}/", dir.display()))
+        .filter(|dir| dir.starts_with(value) && dir != value)
+        .collect();
+    let (parent, partial) = match value.rfind('/') {
+        Some(i) => value.split_at(i + 1),
+        None => ("", value),
+    };
+    let listed = if parent.is_empty() { PathBuf::from(".") } else { expand_home(Path::new(parent)) };
+    let Ok(listed) = fs::canonicalize(listed) else {
+        return values;
+    };
+    if !is_within_allowed(&listed, dirs) {
+        return values;
+    }
+    let mut entries: Vec<String> = fs::read_dir(&listed)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let hidden = name.starts_with('.') && !partial.starts_with('.');
+            if !name.starts_with(partial) || hidden || excluded(&entry.path()) {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", parent, name, slash))
+        })
+        .collect();
+    entries.sort();
+    values.extend(entries);
+    values
+}
+
 /// Format file size in human-readable format
 fn format_size(bytes: u64) -> String {
     const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
@@ -143,6 +451,227 @@ fn format_time(time: SystemTime) -> String {
     datetime.format("%Y-%m-%d %H:%M:%S").to_string()
 }
 
+/// Octal mode bits on Unix; elsewhere only the read-only flag is available
+#[cfg(unix)]
+fn format_permissions(permissions: &fs::Permissions) -> String {
+    use std::os::unix::fs::PermissionsExt;
+    format!("{:o}", permissions.mode() & 0o777)
+}
+
+#[cfg(not(unix))]
+fn format_permissions(permissions: &fs::Permissions) -> String {
+    if permissions.readonly() { "readonly" } else { "readwrite" }.to_string()
+}
+
+/// The names of the user and group owning a file, or their ids when they have no name
+#[cfg(unix)]
+fn owner_and_group(metadata: &fs::Metadata) -> (String, String) {
+    use std::ffi::CStr;
+    use std::os::unix::fs::MetadataExt;
+
+    let (uid, gid) = (metadata.uid(), metadata.gid());
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+    // SAFETY: getpwuid_r and getgrgid_r only write to the structs and buffer they're given,
+    // and on success point `result` at the struct, whose name points into the buffer
+    let owner = unsafe {
+        let mut passwd: libc::passwd = std::mem::zeroed();
+        let mut result = std::ptr::null_mut();
+        let found = libc::getpwuid_r(uid, &mut passwd, buf.as_mut_ptr(), buf.len(), &mut result) == 0;
+        match found && !result.is_null() {
+            true => CStr::from_ptr(passwd.pw_name).to_string_lossy().into_owned(),
+            false => uid.to_string(),
+        }
+    };
+    let group = unsafe {
+        let mut group: libc::group = std::mem::zeroed();
+        let mut result = std::ptr::null_mut();
+        let found = libc::getgrgid_r(gid, &mut group, buf.as_mut_ptr(), buf.len(), &mut result) == 0;
+        match found && !result.is_null() {
+            true => CStr::from_ptr(group.gr_name).to_string_lossy().into_owned(),
+            false => gid.to_string(),
+        }
+    };
+    (owner, group)
+}
+
+#[cfg(not(unix))]
+fn owner_and_group(_metadata: &fs::Metadata) -> (String, String) {
+    ("Unknown".to_string(), "Unknown".to_string())
+}
+
+/// A file's extended attributes, by name. Values that are text are given as text, others
+/// in hex with a `0x` prefix. Filesystems without them have none.
+#[cfg(unix)]
+fn extended_attributes(path: &Path) -> BTreeMap<String, String> {
+    let Ok(names) = xattr::list_deref(path) else {
+        return BTreeMap::new();
+    };
+    names
+        .filter_map(|name| {
+            let value = xattr::get_deref(path, &name).ok().flatten().unwrap_or_default();
+            let value = match std::str::from_utf8(value.strip_suffix(b"\0").unwrap_or(&value)) {
+                Ok(text) if !text.contains('\0') => text.to_string(),
+                _ => format!("0x{}", hex::encode(&value)),
+            };
+            Some((name.to_str()?.to_string(), value))
+        })
+        .collect()
+}
+
+#[cfg(not(unix))]
+fn extended_attributes(_path: &Path) -> BTreeMap<String, String> {
+    BTreeMap::new()
+}
+
+/// Whether `bytes`, the start of a file, look like text: UTF-8 without NUL bytes. A
+/// character cut off at the end still counts.
+fn is_text(bytes: &[u8]) -> bool {
+    let valid = match std::str::from_utf8(bytes) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+    valid && !bytes.contains(&0)
+}
+
+/// Lines, words, characters and bytes of text, as `wc` counts them, except that a last line
+/// without a newline counts
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+struct TextCounts {
+    lines: u64,
+    words: u64,
+    chars: u64,
+    bytes: u64,
+}
+
+impl TextCounts {
+    /// Count what `reader` reads, as UTF-8
+    fn of(mut reader: impl Read) -> std::io::Result<Self> {
+        let mut counts = TextCounts::default();
+        let mut buffer = vec![0; 64 * 1024];
+        let (mut last, mut in_word) = (b'\n', false);
+        loop {
+            let n = reader.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            for &byte in &buffer[..n] {
+                counts.lines += u64::from(byte == b'\n');
+                // Continuation bytes are part of the character before them
+                counts.chars += u64::from(byte & 0xc0 != 0x80);
+                let space = byte.is_ascii_whitespace();
+                counts.words += u64::from(!space && !in_word);
+                in_word = !space;
+            }
+            counts.bytes += n as u64;
+            last = buffer[n - 1];
+        }
+        counts.lines += u64::from(last != b'\n');
+        Ok(counts)
+    }
+
+    fn add(&mut self, other: &TextCounts) {
+        self.lines += other.lines;
+        self.words += other.words;
+        self.chars += other.chars;
+        self.bytes += other.bytes;
+    }
+}
+
+impl fmt::Display for TextCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>8} {:>8} {:>8} {:>8}", self.lines, self.words, self.chars, self.bytes)
+    }
+}
+
+/// The counts for one file `stats` counted, relative to the path it was given
+#[derive(Serialize)]
+struct FileCounts {
+    path: String,
+    #[serde(flatten)]
+    counts: TextCounts,
+}
+
+/// What `stats` counted: each text file, up to a limit, and the totals of them all
+#[derive(Serialize)]
+struct Stats {
+    files: Vec<FileCounts>,
+    total: TextCounts,
+    /// Text files counted, listed or not
+    counted: u64,
+    /// Matching files that aren't text or couldn't be read, and aren't counted
+    skipped: u64,
+    /// Whether there were more files than are listed
+    truncated: bool,
+}
+
+impl fmt::Display for Stats {
+    /// Like `wc -lwmc`, with a header and a total line
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>8} {:>8} {:>8} {:>8}", "lines", "words", "chars", "bytes")?;
+        for file in &self.files {
+            write!(f, "\n{} {}", file.counts, file.path)?;
+        }
+        write!(f, "\n{} total ({} files)", self.total, self.counted)?;
+        if self.truncated {
+            write!(f, "\n[only the first {} files are listed]", self.files.len())?;
+        }
+        if self.skipped > 0 {
+            write!(f, "\n[{} binary or unreadable files skipped]", self.skipped)?;
+        }
+        Ok(())
+    }
+}
+
+/// Permission bits `set_permissions` accepts, as octal digits ("755" or "0755"). Setuid,
+/// setgid and sticky bits are refused.
+fn parse_mode(mode: &str) -> Option<u32> {
+    let digits = mode.strip_prefix("0o").unwrap_or(mode);
+    u32::from_str_radix(digits, 8).ok().filter(|mode| !digits.is_empty() && *mode <= 0o777)
+}
+
+#[cfg(unix)]
+fn make_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn make_symlink(_target: &Path, _link: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "symlinks are only supported on Unix"))
+}
+
+/// Set the permission bits of `path`
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        .with_context(|| format!("Failed to set permissions on {}", path.display()))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
+    Err(anyhow!("Setting permissions is only supported on Unix"))
+}
+
+/// Set the permission bits of `dir` and everything under it. Symlinks are skipped: their
+/// targets may lie outside the allowed directories.
+fn set_mode_tree(dir: &Path, mode: u32, changed: &mut usize, skipped_links: &mut usize) -> Result<()> {
+    set_mode(dir, mode)?;
+    *changed += 1;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            *skipped_links += 1;
+        } else if file_type.is_dir() {
+            set_mode_tree(&entry.path(), mode, changed, skipped_links)?;
+        } else {
+            set_mode(&entry.path(), mode)?;
+            *changed += 1;
+        }
+    }
+    Ok(())
+}
+
 /// File information structure
 #[derive(Serialize)]
 struct FileInfo {
@@ -155,6 +684,37 @@ struct FileInfo {
     is_file: bool,
     is_symlink: bool,
     permissions: String,
+    owner: String,
+    group: String,
+    /// From a file's extension, or else its content; directories have none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mime_type: Option<String>,
+    /// Lines in a text file no larger than `MAX_LINE_COUNT`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lines: Option<u64>,
+    xattrs: BTreeMap<String, String>,
+}
+
+impl fmt::Display for FileInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "size: {}\nsize_formatted: {}\ncreated: {}\nmodified: {}\naccessed: {}\n\
+             is_directory: {}\nis_file: {}\nis_symlink: {}\npermissions: {}\nowner: {}\ngroup: {}",
+            self.size, self.size_formatted, self.created, self.modified, self.accessed,
+            self.is_directory, self.is_file, self.is_symlink, self.permissions, self.owner, self.group
+        )?;
+        if let Some(mime_type) = &self.mime_type {
+            write!(f, "\nmime_type: {}", mime_type)?;
+        }
+        if let Some(lines) = self.lines {
+            write!(f, "\nlines: {}", lines)?;
+        }
+        for (name, value) in &self.xattrs {
+            write!(f, "\nxattr {}: {}", name, value)?;
+        }
+        Ok(())
+    }
 }
 
 /// Directory entry with size
@@ -165,6 +725,267 @@ struct DirectoryEntry {
     size: u64,
 }
 
+/// Checksums `hash_file` computes
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum HashAlgorithm {
+    Sha256,
+    Md5,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "sha256" => Some(HashAlgorithm::Sha256),
+            "md5" => Some(HashAlgorithm::Md5),
+            "blake3" => Some(HashAlgorithm::Blake3),
+            _ => None,
+        }
+    }
+
+    /// Hex digest of everything `reader` reads, read a block at a time
+    fn hash(self, mut reader: impl Read) -> std::io::Result<String> {
+        use sha2::Digest;
+        let mut sha256 = sha2::Sha256::new();
+        let mut md5 = md5::Md5::new();
+        let mut blake3 = blake3::Hasher::new();
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let n = reader.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            match self {
+                HashAlgorithm::Sha256 => sha256.update(&buffer[..n]),
+                HashAlgorithm::Md5 => md5.update(&buffer[..n]),
+                HashAlgorithm::Blake3 => {
+                    blake3.update(&buffer[..n]);
+                }
+            }
+        }
+        Ok(match self {
+            HashAlgorithm::Sha256 => hex::encode(sha256.finalize()),
+            HashAlgorithm::Md5 => hex::encode(md5.finalize()),
+            HashAlgorithm::Blake3 => blake3.finalize().to_hex().to_string(),
+        })
+    }
+}
+
+/// A checksum `hash_file` computed, or why it couldn't
+#[derive(Serialize)]
+struct FileHash {
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// A file as `read_file_binary` returns it
+#[derive(Serialize)]
+struct BinaryFile {
+    #[serde(rename = "mimeType")]
+    mime_type: String,
+    size: u64,
+    /// The content, base64-encoded
+    data: String,
+}
+
+/// A stretch of a file `read_file` returns for `byteOffset`/`length`: the text of bytes
+/// `start..end` of a file of `size` bytes
+struct FileChunk {
+    text: String,
+    start: u64,
+    end: u64,
+    size: u64,
+}
+
+impl fmt::Display for FileChunk {
+    /// Where the chunk lies in the file, and where the next one starts
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[Bytes {}-{} of {}; ", self.start, self.end, self.size)?;
+        if self.end < self.size {
+            write!(f, "next byteOffset: {}]", self.end)
+        } else {
+            write!(f, "end of file]")
+        }
+    }
+}
+
+/// A line `search_content` found, with the lines around it
+#[derive(Serialize)]
+struct ContentMatch {
+    path: String,
+    line: usize,
+    text: String,
+    before: Vec<String>,
+    after: Vec<String>,
+}
+
+/// What `search_content` found: up to `max_results` matches, and whether there were more
+#[derive(Serialize)]
+struct ContentMatches {
+    matches: Vec<ContentMatch>,
+    truncated: bool,
+}
+
+impl fmt::Display for ContentMatches {
+    /// Like `grep -n`: `path:line: text` for matches and `path-line- text` for context, with
+    /// `--` between matches shown with context
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.matches.is_empty() {
+            return write!(f, "No matches found");
+        }
+        for (i, found) in self.matches.iter().enumerate() {
+            let has_context = !found.before.is_empty() || !found.after.is_empty();
+            if i > 0 && has_context {
+                writeln!(f, "--")?;
+            }
+            let first = found.line - found.before.len();
+            for (n, line) in found.before.iter().enumerate() {
+                writeln!(f, "{}-{}- {}", found.path, first + n, line)?;
+            }
+            writeln!(f, "{}:{}: {}", found.path, found.line, found.text)?;
+            for (n, line) in found.after.iter().enumerate() {
+                writeln!(f, "{}-{}- {}", found.path, found.line + 1 + n, line)?;
+            }
+        }
+        if self.truncated {
+            write!(f, "[stopped after {} matches]", self.matches.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// Apparent size of everything under a directory entry, as `disk_usage` tallies it
+#[derive(Debug, Default, Serialize)]
+struct Usage {
+    name: String,
+    is_directory: bool,
+    size: u64,
+    files: u64,
+    directories: u64,
+    /// Entries that couldn't be read and aren't counted
+    #[serde(skip)]
+    unreadable: u64,
+}
+
+impl Usage {
+    fn add(&mut self, other: &Usage) {
+        self.size += other.size;
+        self.files += other.files;
+        self.directories += other.directories;
+        self.unreadable += other.unreadable;
+    }
+}
+
+/// What `disk_usage` found: the totals for a directory and its largest children
+#[derive(Serialize)]
+struct DiskUsage {
+    path: String,
+    size: u64,
+    files: u64,
+    directories: u64,
+    largest: Vec<Usage>,
+    unreadable: u64,
+}
+
+impl fmt::Display for DiskUsage {
+    /// Like `du -sh`, then the largest children with directories marked by a trailing `/`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>10}  {} ({} files, {} directories)",
+            format_size(self.size), self.path, self.files, self.directories
+        )?;
+        if !self.largest.is_empty() {
+            write!(f, "\n\nLargest:")?;
+        }
+        for usage in &self.largest {
+            let suffix = if usage.is_directory { "/" } else { "" };
+            write!(f, "\n{:>10}  {}{}", format_size(usage.size), usage.name, suffix)?;
+            if usage.is_directory {
+                write!(f, " ({} files)", usage.files)?;
+            }
+        }
+        if self.unreadable > 0 {
+            write!(f, "\n[{} entries couldn't be read and aren't counted]", self.unreadable)?;
+        }
+        Ok(())
+    }
+}
+
+/// Files `find_duplicates` found to have the same content
+#[derive(Serialize)]
+struct DuplicateGroup {
+    size: u64,
+    hash: String,
+    /// Relative to the directory searched, in path order
+    paths: Vec<String>,
+}
+
+impl DuplicateGroup {
+    /// Bytes taken up by every copy but one
+    fn wasted(&self) -> u64 {
+        self.size * (self.paths.len() as u64 - 1)
+    }
+}
+
+/// What `find_duplicates` found: groups of identical files, those wasting the most space first
+#[derive(Serialize)]
+struct Duplicates {
+    groups: Vec<DuplicateGroup>,
+    /// Files compared, of at least the minimum size
+    files: u64,
+    /// Bytes taken up by the extra copies in every group, listed or not
+    wasted: u64,
+    /// Files that couldn't be read and aren't compared
+    unreadable: u64,
+    /// Whether there were more groups than are listed
+    truncated: bool,
+}
+
+impl fmt::Display for Duplicates {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.groups.is_empty() {
+            write!(f, "No duplicates among {} files", self.files)?;
+        } else {
+            write!(
+                f,
+                "{} groups of duplicates among {} files, {} in extra copies",
+                self.groups.len(), self.files, format_size(self.wasted)
+            )?;
+        }
+        for group in &self.groups {
+            write!(f, "\n\n{} x {} ({})", format_size(group.size), group.paths.len(), group.hash)?;
+            for path in &group.paths {
+                write!(f, "\n  {}", path)?;
+            }
+        }
+        if self.truncated {
+            write!(f, "\n\n[only the {} groups wasting the most space are listed]", self.groups.len())?;
+        }
+        if self.unreadable > 0 {
+            write!(f, "\n[{} files couldn't be read and aren't compared]", self.unreadable)?;
+        }
+        Ok(())
+    }
+}
+
+/// How `find_duplicates` picks files and reports groups
+struct DuplicateOptions {
+    /// Globs files must match, as in `search_files`
+    pattern: Globs,
+    exclude: Globs,
+    gitignore: bool,
+    /// Smallest file compared, in bytes; empty files are all alike, so 1 by default
+    min_size: u64,
+    algorithm: HashAlgorithm,
+    max_groups: usize,
+}
+
 /// Tree entry for directory_tree
 #[derive(Serialize, Deserialize)]
 struct TreeEntry {
@@ -175,30 +996,287 @@ struct TreeEntry {
     children: Option<Vec<TreeEntry>>,
 }
 
+/// Which stretch of a long listing a call returns: page `number` (counting from 1) of
+/// `size` entries
+#[derive(Debug, Clone, Copy)]
+struct Page {
+    number: usize,
+    size: usize,
+}
+
+impl Page {
+    /// The page a call's `page` and `maxEntries` arguments ask for
+    fn from_arguments(arguments: &serde_json::Value) -> Self {
+        let argument = |name: &str| arguments.get(name).and_then(|v| v.as_u64()).map(|n| n.max(1) as usize);
+        Page { number: argument("page").unwrap_or(1), size: argument("maxEntries").unwrap_or(DEFAULT_MAX_ENTRIES) }
+    }
+
+    /// The page holding every entry
+    fn all() -> Self {
+        Page { number: 1, size: usize::MAX }
+    }
+
+    fn start(&self) -> usize {
+        (self.number - 1).saturating_mul(self.size)
+    }
+
+    fn end(&self) -> usize {
+        self.start().saturating_add(self.size)
+    }
+
+    /// What to tell a client that didn't get every entry
+    fn more(&self) -> String {
+        format!("[More entries follow; pass page: {} for the next {}]", self.number + 1, self.size)
+    }
+}
+
+/// A page of what `list_directory` found: directories first, then files, by name
+#[derive(Serialize)]
+struct Listing {
+    entries: Vec<TreeEntry>,
+    page: usize,
+    total: usize,
+    #[serde(rename = "hasMore")]
+    has_more: bool,
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines: Vec<String> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let prefix = if entry.entry_type == "directory" { "[DIR]" } else { "[FILE]" };
+                format!("{} {}", prefix, entry.name)
+            })
+            .collect();
+        write!(f, "{}", lines.join("\n"))?;
+        if self.has_more {
+            let start = (self.page - 1) * self.entries.len();
+            write!(
+                f,
+                "\n\n[Entries {}-{} of {}; pass page: {} for more]",
+                start + 1,
+                start + self.entries.len(),
+                self.total,
+                self.page + 1
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// How `directory_tree` walks a directory: what it leaves out, how deep it goes, and which
+/// page of entries, counted depth-first, it returns
+struct TreeWalk<'a> {
+    exclude_patterns: &'a Globs,
+    ignores: GitIgnores,
+    max_depth: Option<usize>,
+    page: Page,
+    /// Entries walked so far
+    seen: usize,
+    has_more: bool,
+}
+
 /// Edit operation for edit_file
-#[derive(Deserialize)]
+#[derive(Default, Deserialize)]
 struct EditOperation {
     #[serde(rename = "oldText")]
     old_text: String,
     #[serde(rename = "newText")]
     new_text: String,
+    /// `oldText` is a regular expression, replaced wherever it matches; `newText` may refer
+    /// to its groups as `$1` or `${name}`
+    #[serde(default)]
+    regex: bool,
+    /// Most matches a regex edit may replace; more fail the edit (default 1)
+    #[serde(rename = "maxReplacements")]
+    max_replacements: Option<usize>,
+}
+
+/// What a session's client said about its roots
+#[derive(Default)]
+struct ClientRoots {
+    /// It advertised the `roots` capability in `initialize`
+    supported: bool,
+    /// Directories of its `file://` roots, once it has answered `roots/list`
+    dirs: Option<Vec<PathBuf>>,
 }
 
 /// Filesystem MCP server
 pub struct FilesystemServer {
     config: FilesystemServerConfig,
+    /// Temp directories created per session, removed when the session ends
+    temp_dirs: Mutex<HashMap<String, Vec<PathBuf>>>,
+    /// Client roots per session (with `--client-roots`)
+    roots: Mutex<HashMap<String, ClientRoots>>,
+    /// Session each unanswered `roots/list` request was sent for, by request id
+    roots_requests: Mutex<HashMap<String, String>>,
+    notifier: Arc<Notifier>,
+    subscriptions: FileSubscriptions,
+    /// Paths watched with `watch_path`, per session
+    watches: PathWatches,
 }
 
 impl FilesystemServer {
     pub fn new(config: FilesystemServerConfig) -> Self {
-        Self { config }
+        let notifier = Arc::new(Notifier::logs_only());
+        Self {
+            config,
+            temp_dirs: Mutex::new(HashMap::new()),
+            roots: Mutex::new(HashMap::new()),
+            roots_requests: Mutex::new(HashMap::new()),
+            subscriptions: FileSubscriptions::new(notifier.clone()),
+            watches: PathWatches::new(notifier.clone()),
+            notifier,
+        }
     }
 
     fn allowed_dirs(&self) -> &[PathBuf] {
         &self.config.allowed_directories
     }
 
-    // Tool implementations
+    /// `validate_path` against the allowed directories, under the server's symlink policy
+    fn validate(&self, path: &str) -> Result<PathBuf> {
+        let valid_path = validate_path(path, self.allowed_dirs(), self.config.follow_symlinks)?;
+        if self.config.excludes(&valid_path) {
+            return Err(access_denied("path matches --exclude", &valid_path));
+        }
+        Ok(valid_path)
+    }
+
+    /// The directories a session may access: the allowed directories, narrowed to its
+    /// client's roots. Nothing while a client supporting roots hasn't reported them.
+    fn session_dirs(&self, session: &str) -> Vec<PathBuf> {
+        let roots = self.roots.lock().unwrap();
+        let Some(roots) = roots.get(session) else {
+            return self.allowed_dirs().to_vec();
+        };
+        let Some(roots) = roots.dirs.as_ref() else {
+            return vec![];
+        };
+        let mut dirs = vec![];
+        for allowed in self.allowed_dirs() {
+            for root in roots {
+                let dir = if root.starts_with(allowed) {
+                    root
+                } else if allowed.starts_with(root) {
+                    allowed
+                } else {
+                    continue;
+                };
+                if !dirs.contains(dir) {
+                    dirs.push(dir.clone());
+                }
+            }
+        }
+        dirs
+    }
+
+    /// Whether a call skips `.gitignore`d paths: its `respectGitignore`, or the server's default
+    fn respect_gitignore(&self, arguments: &serde_json::Value) -> bool {
+        arguments.get("respectGitignore").and_then(|v| v.as_bool()).unwrap_or(self.config.respect_gitignore)
+    }
+
+    /// Check the paths a tool is given against the session's client roots, if its client
+    /// supports them. Until it has reported them, every path is refused.
+    fn check_roots(&self, session: &str, arguments: &serde_json::Value) -> Result<()> {
+        match self.roots.lock().unwrap().get(session) {
+            None => return Ok(()),
+            Some(roots) if roots.dirs.is_none() => {
+                return Err(anyhow!("Access denied - the client hasn't reported its roots yet"));
+            }
+            Some(_) => {}
+        }
+        let dirs = self.session_dirs(session);
+        let paths = ["path", "source", "destination"]
+            .iter()
+            .filter_map(|key| arguments.get(key))
+            .chain(arguments.get("paths").and_then(|v| v.as_array()).into_iter().flatten())
+            .filter_map(|v| v.as_str());
+        for path in paths {
+            validate_path(path, &dirs, self.config.follow_symlinks)
+                .map_err(|e| anyhow!("{} (outside the client's roots)", e))?;
+        }
+        Ok(())
+    }
+
+    /// Refuse tool calls that would change something in a read-only directory. A symlink
+    /// counts as being where it is as well as where it points.
+    fn check_writable(&self, name: &str, arguments: &serde_json::Value) -> Result<()> {
+        let Some((_, keys)) = WRITE_ARGUMENTS.iter().find(|(tool, _)| *tool == name) else {
+            return Ok(());
+        };
+        if name == "edit_file" && arguments.get("dryRun").and_then(|v| v.as_bool()).unwrap_or(false) {
+            return Ok(());
+        }
+        for path in keys.iter().filter_map(|key| arguments.get(key)).filter_map(|v| v.as_str()) {
+            let mut places = vec![self.validate(path)];
+            let expanded = expand_home(Path::new(path));
+            let link = expanded.is_symlink().then(|| expanded.parent().zip(expanded.file_name())).flatten();
+            if let Some((parent, file_name)) = link {
+                places.push(self.validate(&parent.to_string_lossy()).map(|parent| parent.join(file_name)));
+            }
+            // A path under directories that don't exist yet is where its nearest existing
+            // ancestor resolves to, as tools that create those directories would write there
+            if places[0].is_err() {
+                let (ancestor, rest) = existing_ancestor(&expanded)?;
+                places.push(self.validate(&ancestor.to_string_lossy()).map(|ancestor| ancestor.join(rest)));
+            }
+            // Paths that don't validate are refused by the tool itself
+            let places: Vec<PathBuf> = places.into_iter().flatten().collect();
+            if let Some(place) = places.iter().find(|place| self.config.is_read_only(place)) {
+                return Err(access_denied("path in a read-only directory", place));
+            }
+            // Tools changing a whole tree change the read-only directories nested in it too
+            let recursive = arguments.get("recursive").and_then(|v| v.as_bool()).unwrap_or(false);
+            let whole_tree = match name {
+                "move_file" => true,
+                "delete_directory" | "set_permissions" => recursive,
+                _ => false,
+            };
+            let nested = places.iter().filter(|_| whole_tree).find_map(|place| self.config.read_only_within(place));
+            if let Some(dir) = nested {
+                return Err(access_denied("path holds a read-only directory", dir));
+            }
+        }
+        Ok(())
+    }
+
+    /// Refuse a path `copy_directory` or `extract_archive` would write: one hidden by
+    /// `--exclude`, or in a read-only directory nested in the destination
+    fn check_tree_write(&self, path: &Path) -> Result<()> {
+        if self.config.excludes(path) {
+            return Err(access_denied("path matches --exclude", path));
+        }
+        if self.config.is_read_only(path) {
+            return Err(access_denied("path in a read-only directory", path));
+        }
+        Ok(())
+    }
+
+    /// The path of a `file://` resource, checked like a tool's `path` argument against the
+    /// allowed directories and the session's client roots
+    fn resource_path(&self, uri: &str, ctx: &RequestContext) -> Result<PathBuf> {
+        let path = uri_path(uri)?;
+        self.check_roots(ctx.session_id.as_deref().unwrap_or(STDIO_SESSION), &serde_json::json!({ "path": path }))?;
+        self.validate(&path)
+    }
+
+    /// Whether a file found walking a directory may be read: a symlink only if it validates,
+    /// so the `--follow-symlinks` policy applies as it does to `read_file`
+    fn readable_entry(&self, path: &Path) -> bool {
+        !path.is_symlink() || self.validate(&path.to_string_lossy()).is_ok()
+    }
+
+    /// Ask a session's client for its roots
+    fn request_roots(&self, session_id: Option<&str>) {
+        let id = self.notifier.request(session_id, "roots/list", serde_json::json!({}));
+        let session = session_id.unwrap_or(STDIO_SESSION).to_string();
+        self.roots_requests.lock().unwrap().insert(id, session);
+    }
+
+    // Tool implementations
 
     fn read_file(
         &self,
@@ -208,7 +1286,7 @@ impl FilesystemServer {
         offset: Option<usize>,
         limit: Option<usize>,
     ) -> Result<String> {
-        let valid_path = validate_path(path, self.allowed_dirs())?;
+        let valid_path = self.validate(path)?;
 
         // Check for conflicting parameters
         let has_head_tail = head.is_some() || tail.is_some();
@@ -240,6 +1318,91 @@ impl FilesystemServer {
             .with_context(|| format!("Failed to read file: {}", valid_path.display()))
     }
 
+    /// Up to `length` bytes of a file from byte `offset`, as text. The chunk is moved to
+    /// whole UTF-8 characters, so reading on from its end never splits one.
+    fn read_file_chunk(&self, path: &str, offset: u64, length: usize) -> Result<FileChunk> {
+        let valid_path = self.validate(path)?;
+        let mut file =
+            File::open(&valid_path).with_context(|| format!("Failed to read file: {}", valid_path.display()))?;
+        let size = file.metadata()?.len();
+        let offset = offset.min(size);
+        file.seek(SeekFrom::Start(offset))?;
+        let mut bytes = Vec::with_capacity(length.min(MAX_CHUNK_READ));
+        file.take(length as u64).read_to_end(&mut bytes)?;
+
+        // Skip the rest of a character the chunk starts inside, and leave a character it ends
+        // inside for the next chunk
+        let skip = if offset > 0 { bytes.iter().take(3).take_while(|b| **b & 0xC0 == 0x80).count() } else { 0 };
+        let body = &bytes[skip..];
+        let at_end = offset + bytes.len() as u64 >= size;
+        let keep = match std::str::from_utf8(body) {
+            Err(e) if e.error_len().is_none() && !at_end && e.valid_up_to() > 0 => e.valid_up_to(),
+            _ => body.len(),
+        };
+        let start = offset + skip as u64;
+        Ok(FileChunk {
+            text: String::from_utf8_lossy(&body[..keep]).into_owned(),
+            start,
+            end: start + keep as u64,
+            size,
+        })
+    }
+
+    /// A whole file as tool content: an image, text, or else an embedded resource holding
+    /// its bytes
+    fn read_file_content(&self, path: &str) -> Result<serde_json::Value> {
+        let valid_path = self.validate(path)?;
+        let file = File::open(&valid_path).with_context(|| format!("Failed to read file: {}", valid_path.display()))?;
+        let size = file.metadata()?.len();
+        // Text is returned whole, but binary content only up to what read_file_binary returns
+        let mut head = vec![];
+        file.take(8192).read_to_end(&mut head)?;
+        if size > MAX_BINARY_READ && !is_text(&head) {
+            return Err(anyhow!(
+                "{} is {} of binary content, more than read_file returns at once ({}); read it in chunks with \
+                 byteOffset and length",
+                valid_path.display(),
+                format_size(size),
+                format_size(MAX_BINARY_READ)
+            ));
+        }
+        let bytes = fs::read(&valid_path).with_context(|| format!("Failed to read file: {}", valid_path.display()))?;
+        let mime = mime_type(&valid_path).or_else(|| sniff_mime_type(&bytes));
+        if let Some(mime) = mime.filter(|mime| mime.starts_with("image/") && *mime != "image/svg+xml") {
+            return Ok(image_content(&bytes, mime));
+        }
+        match String::from_utf8(bytes) {
+            Ok(text) => Ok(text_content(&text)),
+            Err(e) => Ok(resource_content(&file_uri(&valid_path), mime, e.into_bytes())),
+        }
+    }
+
+    /// A file's bytes as base64, with its MIME type (from its extension, or else its content)
+    fn read_file_binary(&self, path: &str) -> Result<BinaryFile> {
+        use base64::Engine;
+        let valid_path = self.validate(path)?;
+        let size = fs::metadata(&valid_path)
+            .with_context(|| format!("Failed to read file: {}", valid_path.display()))?
+            .len();
+        if size > MAX_BINARY_READ {
+            return Err(anyhow!(
+                "{} is {}, more than read_file_binary returns ({})",
+                valid_path.display(),
+                format_size(size),
+                format_size(MAX_BINARY_READ)
+            ));
+        }
+        let bytes = fs::read(&valid_path).with_context(|| format!("Failed to read file: {}", valid_path.display()))?;
+        let mime_type = mime_type(&valid_path).or_else(|| sniff_mime_type(&bytes)).unwrap_or_else(|| {
+            if std::str::from_utf8(&bytes).is_ok() { "text/plain" } else { "application/octet-stream" }
+        });
+        Ok(BinaryFile {
+            mime_type: mime_type.to_string(),
+            size: bytes.len() as u64,
+            data: base64::engine::general_purpose::STANDARD.encode(&bytes),
+        })
+    }
+
     fn read_file_range(&self, path: &Path, offset: usize, limit: Option<usize>) -> Result<String> {
         let file = File::open(path)?;
         let reader = BufReader::new(file);
@@ -342,8 +1505,29 @@ impl FilesystemServer {
         Ok(results.join("\n---\n"))
     }
 
+    /// Checksums of `paths`; files that can't be hashed get an error of their own
+    fn hash_files(&self, paths: &[String], algorithm: HashAlgorithm) -> Vec<FileHash> {
+        let hash = |path: &str| -> Result<(u64, String)> {
+            let valid_path = self.validate(path)?;
+            if valid_path.is_dir() {
+                return Err(anyhow!("{} is a directory", valid_path.display()));
+            }
+            let file = File::open(&valid_path).with_context(|| format!("Failed to open {}", valid_path.display()))?;
+            let size = file.metadata()?.len();
+            let hash = algorithm.hash(file).with_context(|| format!("Failed to read {}", valid_path.display()))?;
+            Ok((size, hash))
+        };
+        paths
+            .iter()
+            .map(|path| match hash(path) {
+                Ok((size, hash)) => FileHash { path: path.clone(), size: Some(size), hash: Some(hash), error: None },
+                Err(e) => FileHash { path: path.clone(), size: None, hash: None, error: Some(e.to_string()) },
+            })
+            .collect()
+    }
+
     fn write_file(&self, path: &str, content: &str) -> Result<String> {
-        let valid_path = validate_path(path, self.allowed_dirs())?;
+        let valid_path = self.validate(path)?;
 
         // Write atomically to prevent race conditions
         let temp_path = format!("{}.{}.tmp", valid_path.display(), std::process::id());
@@ -362,19 +1546,37 @@ impl FilesystemServer {
         Ok(format!("Successfully wrote to {}", path))
     }
 
-    fn edit_file(&self, path: &str, edits: Vec<EditOperation>, dry_run: bool) -> Result<String> {
-        let valid_path = validate_path(path, self.allowed_dirs())?;
+    /// Add `content` to the end of a file, creating it if needed
+    fn append_file(&self, path: &str, content: &str) -> Result<String> {
+        let valid_path = self.validate(path)?;
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&valid_path)
+            .with_context(|| format!("Failed to open file: {}", valid_path.display()))?;
+        file.write_all(content.as_bytes())
+            .with_context(|| format!("Failed to append to file: {}", valid_path.display()))?;
+
+        Ok(format!("Successfully appended {} bytes to {}", content.len(), path))
+    }
+
+    /// Apply `edits` in order, returning a unified diff with `context` lines around each change
+    fn edit_file(&self, path: &str, edits: Vec<EditOperation>, dry_run: bool, context: usize) -> Result<String> {
+        let valid_path = self.validate(path)?;
         let original_content = fs::read_to_string(&valid_path)?;
 
         // Normalize line endings
-        let mut content = original_content.replace("\r\n", "\n");
+        let normalized = original_content.replace("\r\n", "\n");
+        let mut content = normalized.clone();
 
         // Apply edits sequentially
         for edit in edits {
             let old_text = edit.old_text.replace("\r\n", "\n");
             let new_text = edit.new_text.replace("\r\n", "\n");
 
-            if content.contains(&old_text) {
+            if edit.regex {
+                content = replace_regex(&content, &old_text, &new_text, edit.max_replacements.unwrap_or(1))?;
+            } else if content.contains(&old_text) {
                 content = content.replacen(&old_text, &new_text, 1);
             } else {
                 // Try whitespace-flexible matching
@@ -428,8 +1630,7 @@ impl FilesystemServer {
             }
         }
 
-        // Create unified diff
-        let diff = create_unified_diff(&original_content, &content, path);
+        let diff = create_unified_diff(&normalized, &content, path, context);
 
         if !dry_run {
             // Write atomically
@@ -450,49 +1651,47 @@ impl FilesystemServer {
             std::env::current_dir()?.join(&expanded)
         };
 
-        // Find the first existing parent and validate that
-        let mut check_path = absolute.clone();
-        while !check_path.exists() {
-            check_path = match check_path.parent() {
-                Some(p) => p.to_path_buf(),
-                None => break,
-            };
-        }
-
-        if check_path.exists() {
-            let resolved = fs::canonicalize(&check_path)?;
-            if !is_within_allowed(&resolved, self.allowed_dirs()) {
-                return Err(anyhow!(
-                    "Access denied - path outside allowed directories: {}",
-                    absolute.display()
-                ));
-            }
+        // Validate the first existing parent, under the symlink policy, as the rest is created
+        let (check_path, rest) = existing_ancestor(&absolute)?;
+        let resolved = self.validate(&check_path.to_string_lossy())?.join(rest);
+        if self.config.excludes(&resolved) {
+            return Err(access_denied("path matches --exclude", &resolved));
         }
 
-        fs::create_dir_all(&absolute)
-            .with_context(|| format!("Failed to create directory: {}", absolute.display()))?;
+        fs::create_dir_all(&resolved)
+            .with_context(|| format!("Failed to create directory: {}", resolved.display()))?;
         Ok(format!("Successfully created directory {}", path))
     }
 
-    fn list_directory(&self, path: &str) -> Result<String> {
-        let valid_path = validate_path(path, self.allowed_dirs())?;
+    fn list_directory(&self, path: &str, page: Page) -> Result<Listing> {
+        let valid_path = self.validate(path)?;
         let entries = fs::read_dir(&valid_path)
             .with_context(|| format!("Failed to read directory: {}", valid_path.display()))?;
 
-        let mut result: Vec<String> = Vec::new();
+        let mut result: Vec<TreeEntry> = Vec::new();
         for entry in entries {
             let entry = entry?;
+            if self.config.excludes(&entry.path()) {
+                continue;
+            }
             let file_type = entry.file_type()?;
-            let prefix = if file_type.is_dir() { "[DIR]" } else { "[FILE]" };
-            result.push(format!("{} {}", prefix, entry.file_name().to_string_lossy()));
+            let entry_type = if file_type.is_dir() { "directory" } else { "file" };
+            result.push(TreeEntry {
+                name: entry.file_name().to_string_lossy().to_string(),
+                entry_type: entry_type.to_string(),
+                children: None,
+            });
         }
 
-        result.sort();
-        Ok(result.join("\n"))
+        // Directories first, as "[DIR]" sorts before "[FILE]"
+        result.sort_by(|a, b| (a.entry_type != "directory", &a.name).cmp(&(b.entry_type != "directory", &b.name)));
+        let total = result.len();
+        let entries: Vec<TreeEntry> = result.into_iter().skip(page.start()).take(page.size).collect();
+        Ok(Listing { has_more: page.start() + entries.len() < total, entries, page: page.number, total })
     }
 
     fn list_directory_with_sizes(&self, path: &str, sort_by: &str) -> Result<String> {
-        let valid_path = validate_path(path, self.allowed_dirs())?;
+        let valid_path = self.validate(path)?;
         let entries = fs::read_dir(&valid_path)?;
 
         let mut detailed_entries: Vec<DirectoryEntry> = Vec::new();
@@ -502,6 +1701,9 @@ impl FilesystemServer {
 
         for entry in entries {
             let entry = entry?;
+            if self.config.excludes(&entry.path()) {
+                continue;
+            }
             let metadata = entry.metadata()?;
             let is_dir = metadata.is_dir();
             let size = if is_dir { 0 } else { metadata.len() };
@@ -547,42 +1749,71 @@ impl FilesystemServer {
         Ok(result.join("\n"))
     }
 
-    fn directory_tree(&self, path: &str, exclude_patterns: &[String]) -> Result<String> {
-        let valid_path = validate_path(path, self.allowed_dirs())?;
-        let tree = self.build_tree(&valid_path, &valid_path, exclude_patterns)?;
-        Ok(serde_json::to_string_pretty(&tree)?)
+    /// The tree under `path` as JSON, and whether entries past `page` were left out
+    fn directory_tree(
+        &self,
+        path: &str,
+        exclude_patterns: &Globs,
+        gitignore: bool,
+        max_depth: Option<usize>,
+        page: Page,
+    ) -> Result<(String, bool)> {
+        let valid_path = self.validate(path)?;
+        let ignores = GitIgnores::new(gitignore, &valid_path);
+        let mut walk = TreeWalk { exclude_patterns, ignores, max_depth, page, seen: 0, has_more: false };
+        let tree = self.build_tree(&valid_path, &valid_path, 1, &mut walk)?;
+        Ok((serde_json::to_string_pretty(&tree)?, walk.has_more))
     }
 
-    fn build_tree(&self, root: &Path, current: &Path, exclude_patterns: &[String]) -> Result<Vec<TreeEntry>> {
-        let entries = fs::read_dir(current)?;
+    /// The entries of `current`, which is `depth` levels below the tree's root, that fall on
+    /// the walk's page, with the directories leading to them
+    fn build_tree(&self, root: &Path, current: &Path, depth: usize, walk: &mut TreeWalk) -> Result<Vec<TreeEntry>> {
+        let mut entries = fs::read_dir(current)?.collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
         let mut result: Vec<TreeEntry> = Vec::new();
 
         for entry in entries {
-            let entry = entry?;
             let entry_path = entry.path();
             let relative_path = entry_path.strip_prefix(root).unwrap_or(&entry_path);
             let relative_str = relative_path.to_string_lossy();
 
             // Check exclusion patterns
-            let should_exclude = exclude_patterns.iter().any(|pattern| {
-                matches_glob(pattern, &relative_str)
-            });
+            let should_exclude = walk.exclude_patterns.matches(&relative_str);
 
-            if should_exclude {
+            if should_exclude || self.config.excludes(&entry_path) {
                 continue;
             }
 
             let file_type = entry.file_type()?;
+            if walk.ignores.ignores(&entry_path, file_type.is_dir()) {
+                continue;
+            }
+            if walk.seen >= walk.page.end() {
+                walk.has_more = true;
+                break;
+            }
+            let on_page = walk.seen >= walk.page.start();
+            walk.seen += 1;
             let name = entry.file_name().to_string_lossy().to_string();
 
             if file_type.is_dir() {
-                let children = self.build_tree(root, &entry_path, exclude_patterns)?;
-                result.push(TreeEntry {
-                    name,
-                    entry_type: "directory".to_string(),
-                    children: Some(children),
-                });
-            } else {
+                // Directories at `max_depth` are listed without their children
+                let children = if walk.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+                    None
+                } else {
+                    walk.ignores.enter(&entry_path);
+                    let children = self.build_tree(root, &entry_path, depth + 1, walk);
+                    walk.ignores.leave();
+                    Some(children?)
+                };
+                if on_page || children.as_ref().is_some_and(|children| !children.is_empty()) {
+                    result.push(TreeEntry {
+                        name,
+                        entry_type: "directory".to_string(),
+                        children,
+                    });
+                }
+            } else if on_page {
                 result.push(TreeEntry {
                     name,
                     entry_type: "file".to_string(),
@@ -591,13 +1822,93 @@ impl FilesystemServer {
             }
         }
 
-        result.sort_by(|a, b| a.name.cmp(&b.name));
         Ok(result)
     }
 
+    /// Apply a unified diff to the files it names, resolving relative paths against
+    /// `directory` (by default the session's first allowed directory). Every hunk of every
+    /// file is checked first, and nothing is written unless all of them apply.
+    fn apply_patch(&self, diff: &str, directory: Option<&str>, dry_run: bool, session: &str) -> Result<PatchOutcome> {
+        let base = match directory {
+            Some(directory) => self.validate(directory)?,
+            None => self.session_dirs(session).into_iter().next().ok_or_else(|| anyhow!("No allowed directories"))?,
+        };
+        let mut outcome = PatchOutcome { files: vec![], applied: true, dry_run };
+        let mut changes = vec![];
+        for file in patch::parse(diff)? {
+            let (patched, change) = self.plan_patch(&file, &base, dry_run, session).unwrap_or_else(|e| {
+                let path = file.path().to_string();
+                (PatchedFile { path, status: "rejected", hunks: vec![], error: Some(e.to_string()) }, None)
+            });
+            outcome.applied &= change.is_some();
+            outcome.files.push(patched);
+            changes.extend(change);
+        }
+        if !outcome.applied || dry_run {
+            return Ok(outcome);
+        }
+
+        for (old, new, content) in changes {
+            if let Some(new) = &new {
+                fs::write(new, content).with_context(|| format!("Failed to write {}", new.display()))?;
+            }
+            if let Some(old) = old.filter(|old| new.as_ref() != Some(old)) {
+                fs::remove_file(&old).with_context(|| format!("Failed to remove {}", old.display()))?;
+            }
+        }
+        Ok(outcome)
+    }
+
+    /// What patching one file would do, and the change to make if every hunk applies
+    fn plan_patch(
+        &self,
+        file: &FilePatch,
+        base: &Path,
+        dry_run: bool,
+        session: &str,
+    ) -> Result<(PatchedFile, Option<PatchChange>)> {
+        let resolve = |path: &String| -> Result<PathBuf> {
+            let path = base.join(path).to_string_lossy().to_string();
+            self.check_roots(session, &serde_json::json!({ "path": path }))?;
+            let valid_path = self.validate(&path)?;
+            if !dry_run && self.config.is_read_only(&valid_path) {
+                return Err(access_denied("path in a read-only directory", &valid_path));
+            }
+            Ok(valid_path)
+        };
+        let old = file.old_path.as_ref().map(resolve).transpose()?;
+        let new = file.new_path.as_ref().map(resolve).transpose()?;
+        if new.is_none() && !self.config.allow_delete {
+            return Err(anyhow!("Deleting is disabled; start the server with --allow-delete"));
+        }
+        if let Some(new) = new.as_ref().filter(|new| old.as_ref() != Some(*new) && new.exists()) {
+            return Err(anyhow!("{} already exists", new.display()));
+        }
+        let content = match &old {
+            Some(old) => fs::read_to_string(old).with_context(|| format!("Failed to read {}", old.display()))?,
+            None => String::new(),
+        };
+
+        let (patched, hunks) = patch::apply(&content, &file.hunks);
+        let status = match (&old, &new) {
+            _ if patched.is_none() => "rejected",
+            (None, _) => "created",
+            (_, None) => "deleted",
+            (old, new) if old != new => "renamed",
+            _ => "modified",
+        };
+        let mut result = PatchedFile { path: file.path().to_string(), status, hunks, error: None };
+        if status == "deleted" && patched.as_ref().is_some_and(|patched| !patched.is_empty()) {
+            result.status = "rejected";
+            result.error = Some("the patch deletes the file but leaves lines in it".to_string());
+            return Ok((result, None));
+        }
+        Ok((result, patched.map(|patched| (old, new, patched))))
+    }
+
     fn move_file(&self, source: &str, destination: &str) -> Result<String> {
-        let valid_source = validate_path(source, self.allowed_dirs())?;
-        let valid_dest = validate_path(destination, self.allowed_dirs())?;
+        let valid_source = self.validate(source)?;
+        let valid_dest = self.validate(destination)?;
 
         fs::rename(&valid_source, &valid_dest)
             .with_context(|| format!("Failed to move {} to {}", source, destination))?;
@@ -605,10 +1916,206 @@ impl FilesystemServer {
         Ok(format!("Successfully moved {} to {}", source, destination))
     }
 
-    fn search_files(&self, path: &str, pattern: &str, exclude_patterns: &[String]) -> Result<String> {
-        let valid_path = validate_path(path, self.allowed_dirs())?;
+    fn copy_file(&self, source: &str, destination: &str, overwrite: bool) -> Result<String> {
+        let valid_source = self.validate(source)?;
+        let valid_dest = self.validate(destination)?;
+        if !valid_source.is_file() {
+            return Err(anyhow!("{} is not a file; use copy_directory for directories", valid_source.display()));
+        }
+        if valid_dest == valid_source {
+            return Err(anyhow!("Source and destination are the same file: {}", valid_source.display()));
+        }
+        if valid_dest.is_dir() {
+            return Err(anyhow!("Destination is a directory: {}", valid_dest.display()));
+        }
+        if valid_dest.exists() && !overwrite {
+            return Err(anyhow!("Destination already exists: {} (pass overwrite: true to replace it)", destination));
+        }
+
+        fs::copy(&valid_source, &valid_dest)
+            .with_context(|| format!("Failed to copy {} to {}", source, destination))?;
+        Ok(format!("Successfully copied {} to {}", source, destination))
+    }
+
+    fn copy_directory(&self, source: &str, destination: &str, overwrite: bool) -> Result<String> {
+        let valid_source = self.validate(source)?;
+        let valid_dest = self.validate(destination)?;
+        if !valid_source.is_dir() {
+            return Err(anyhow!("{} is not a directory; use copy_file for files", valid_source.display()));
+        }
+        if valid_dest.starts_with(&valid_source) {
+            return Err(anyhow!("Cannot copy a directory into itself: {}", valid_dest.display()));
+        }
+        if valid_dest.exists() && !overwrite {
+            return Err(anyhow!(
+                "Destination already exists: {} (pass overwrite: true to copy into it, replacing files)",
+                destination
+            ));
+        }
+
+        let mut copied = CopyCounts::default();
+        let excluded = |path: &Path| self.config.excludes(path);
+        copy_tree(&valid_source, &valid_dest, &excluded, &|path| self.check_tree_write(path), &mut copied)
+            .with_context(|| format!("Failed to copy {} to {}", source, destination))?;
+        let mut summary = format!("Successfully copied {} files from {} to {}", copied.files, source, destination);
+        if copied.skipped_links > 0 {
+            summary.push_str(&format!(" (skipped {} symlinks)", copied.skipped_links));
+        }
+        Ok(summary)
+    }
+
+    fn create_archive(&self, source: &str, destination: &str, format: Option<&str>, overwrite: bool) -> Result<String> {
+        let valid_source = self.validate(source)?;
+        let valid_dest = self.validate(destination)?;
+        let format = archive_format(format, &valid_dest)?;
+        if valid_dest.is_dir() {
+            return Err(anyhow!("Destination is a directory: {}", valid_dest.display()));
+        }
+        if valid_dest.exists() && !overwrite {
+            return Err(anyhow!("Destination already exists: {} (pass overwrite: true to replace it)", destination));
+        }
+
+        let counts = archive::create(&valid_source, &valid_dest, format, &|path| self.config.excludes(path))
+            .with_context(|| format!("Failed to archive {} to {}", source, destination))?;
+        let mut summary = format!("Successfully archived {} files from {} to {}", counts.files, source, destination);
+        if counts.skipped > 0 {
+            summary.push_str(&format!(" (skipped {} symlinks)", counts.skipped));
+        }
+        Ok(summary)
+    }
+
+    /// Unpack an archive into a directory. Every entry has to land inside the destination,
+    /// which is itself inside the allowed directories; an archive with one that doesn't is
+    /// refused before anything is written.
+    fn extract_archive(
+        &self,
+        source: &str,
+        destination: &str,
+        format: Option<&str>,
+        overwrite: bool,
+    ) -> Result<String> {
+        let valid_source = self.validate(source)?;
+        let valid_dest = self.validate(destination)?;
+        let format = archive_format(format, &valid_source)?;
+
+        let writable = |path: &Path| self.check_tree_write(path);
+        let counts = archive::extract(&valid_source, &valid_dest, format, overwrite, &writable)
+            .with_context(|| format!("Failed to extract {} to {}", source, destination))?;
+        let mut summary = format!("Successfully extracted {} files from {} to {}", counts.files, source, destination);
+        if counts.skipped > 0 {
+            summary.push_str(&format!(" (skipped {} links and special files)", counts.skipped));
+        }
+        Ok(summary)
+    }
+
+    /// The path `delete_file`/`delete_directory` remove: a symlink itself rather than what it
+    /// points to. The allowed directories, and directories holding one, are never deleted.
+    fn deletable_path(&self, path: &str) -> Result<PathBuf> {
+        let expanded = expand_home(Path::new(path));
+        let absolute = if expanded.is_absolute() { expanded } else { std::env::current_dir()?.join(&expanded) };
+        let is_link = fs::symlink_metadata(&absolute).is_ok_and(|metadata| metadata.file_type().is_symlink());
+        let valid_path = match (is_link, absolute.parent(), absolute.file_name()) {
+            (true, Some(parent), Some(name)) => {
+                self.validate(&parent.to_string_lossy())?.join(name)
+            }
+            _ => self.validate(path)?,
+        };
+        if !is_link && !valid_path.exists() {
+            return Err(anyhow!("Path does not exist: {}", valid_path.display()));
+        }
+        if self.allowed_dirs().iter().any(|allowed| allowed.starts_with(&valid_path)) {
+            return Err(anyhow!("Refusing to delete an allowed directory: {}", valid_path.display()));
+        }
+        Ok(valid_path)
+    }
+
+    fn delete_file(&self, path: &str) -> Result<String> {
+        let valid_path = self.deletable_path(path)?;
+        if valid_path.is_dir() && !valid_path.is_symlink() {
+            return Err(anyhow!("{} is a directory; use delete_directory", valid_path.display()));
+        }
+        fs::remove_file(&valid_path).with_context(|| format!("Failed to delete {}", valid_path.display()))?;
+        Ok(format!("Successfully deleted {}", path))
+    }
+
+    fn delete_directory(&self, path: &str, recursive: bool) -> Result<String> {
+        let valid_path = self.deletable_path(path)?;
+        if !valid_path.is_dir() || valid_path.is_symlink() {
+            return Err(anyhow!("{} is not a directory; use delete_file", valid_path.display()));
+        }
+        if recursive {
+            fs::remove_dir_all(&valid_path)
+        } else {
+            if fs::read_dir(&valid_path)?.next().is_some() {
+                return Err(anyhow!(
+                    "{} is not empty; pass recursive: true to delete it with everything in it",
+                    valid_path.display()
+                ));
+            }
+            fs::remove_dir(&valid_path)
+        }
+        .with_context(|| format!("Failed to delete {}", valid_path.display()))?;
+        Ok(format!("Successfully deleted {}", path))
+    }
+
+    /// Create a symlink at `path` pointing to `target`, which may be relative to the link's
+    /// directory and has to lie inside the allowed directories
+    fn create_symlink(&self, target: &str, path: &str) -> Result<String> {
+        let valid_path = self.validate(path)?;
+        if fs::symlink_metadata(&valid_path).is_ok() {
+            return Err(anyhow!("{} already exists", valid_path.display()));
+        }
+        let dir = valid_path.parent().unwrap_or(&valid_path);
+        self.validate(&dir.join(expand_home(Path::new(target))).to_string_lossy())?;
+        make_symlink(Path::new(target), &valid_path)
+            .with_context(|| format!("Failed to create symlink {}", valid_path.display()))?;
+        Ok(format!("Created symlink {} -> {}", path, target))
+    }
+
+    /// Where the symlink `path` points, without following it
+    fn read_link(&self, path: &str) -> Result<String> {
+        let expanded = expand_home(Path::new(path));
+        let (Some(parent), Some(name)) = (expanded.parent(), expanded.file_name()) else {
+            return Err(anyhow!("{} is not a symlink", path));
+        };
+        let parent = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+        let valid_path = self.validate(&parent.to_string_lossy())?.join(name);
+        let target = fs::read_link(&valid_path).map_err(|_| anyhow!("{} is not a symlink", valid_path.display()))?;
+
+        let note = if !valid_path.exists() {
+            " (dangling)"
+        } else if self.validate(&valid_path.to_string_lossy()).is_err() {
+            " (not followed: outside the allowed directories, or refused by --follow-symlinks)"
+        } else {
+            ""
+        };
+        Ok(format!("{} -> {}{}", valid_path.display(), target.display(), note))
+    }
+
+    fn set_permissions(&self, path: &str, mode: u32, recursive: bool) -> Result<String> {
+        let valid_path = self.validate(path)?;
+        if !valid_path.exists() {
+            return Err(anyhow!("Path does not exist: {}", valid_path.display()));
+        }
+        if !recursive || !valid_path.is_dir() {
+            set_mode(&valid_path, mode)?;
+            return Ok(format!("Set permissions of {} to {:o}", path, mode));
+        }
+
+        let (mut changed, mut skipped_links) = (0, 0);
+        set_mode_tree(&valid_path, mode, &mut changed, &mut skipped_links)?;
+        let mut summary = format!("Set permissions of {} paths under {} to {:o}", changed, path, mode);
+        if skipped_links > 0 {
+            summary.push_str(&format!(" (skipped {} symlinks)", skipped_links));
+        }
+        Ok(summary)
+    }
+
+    fn search_files(&self, path: &str, pattern: &Globs, exclude_patterns: &Globs, gitignore: bool) -> Result<String> {
+        let valid_path = self.validate(path)?;
         let mut results: Vec<String> = Vec::new();
-        self.search_recursive(&valid_path, &valid_path, pattern, exclude_patterns, &mut results)?;
+        let mut ignores = GitIgnores::new(gitignore, &valid_path);
+        self.search_recursive(&valid_path, &valid_path, pattern, exclude_patterns, &mut ignores, &mut results)?;
 
         if results.is_empty() {
             Ok("No matches found".to_string())
@@ -621,8 +2128,9 @@ impl FilesystemServer {
         &self,
         root: &Path,
         current: &Path,
-        pattern: &str,
-        exclude_patterns: &[String],
+        pattern: &Globs,
+        exclude_patterns: &Globs,
+        ignores: &mut GitIgnores,
         results: &mut Vec<String>,
     ) -> Result<()> {
         let entries = match fs::read_dir(current) {
@@ -639,7 +2147,7 @@ impl FilesystemServer {
             let entry_path = entry.path();
 
             // Validate path is still within allowed directories
-            if !is_within_allowed(&entry_path, self.allowed_dirs()) {
+            if !is_within_allowed(&entry_path, self.allowed_dirs()) || self.config.excludes(&entry_path) {
                 continue;
             }
 
@@ -647,175 +2155,633 @@ impl FilesystemServer {
             let relative_str = relative_path.to_string_lossy();
 
             // Check exclusion patterns
-            let should_exclude = exclude_patterns.iter().any(|p| matches_glob(p, &relative_str));
+            let should_exclude = exclude_patterns.matches(&relative_str);
             if should_exclude {
                 continue;
             }
+            let is_dir = entry.file_type().map(|ft| ft.is_dir()).unwrap_or(false);
+            if ignores.ignores(&entry_path, is_dir) {
+                continue;
+            }
 
             // Check if matches search pattern
-            if matches_glob(pattern, &relative_str) {
+            if pattern.matches(&relative_str) {
                 results.push(entry_path.to_string_lossy().to_string());
             }
 
             // Recurse into directories
-            if entry.file_type().map(|ft| ft.is_dir()).unwrap_or(false) {
-                self.search_recursive(root, &entry_path, pattern, exclude_patterns, results)?;
+            if is_dir {
+                ignores.enter(&entry_path);
+                let searched = self.search_recursive(root, &entry_path, pattern, exclude_patterns, ignores, results);
+                ignores.leave();
+                searched?;
             }
         }
 
         Ok(())
     }
 
-    fn get_file_info(&self, path: &str) -> Result<String> {
-        let valid_path = validate_path(path, self.allowed_dirs())?;
-        let metadata = fs::metadata(&valid_path)?;
-        let symlink_metadata = fs::symlink_metadata(&valid_path)?;
-
-        let info = FileInfo {
-            size: metadata.len(),
-            size_formatted: format_size(metadata.len()),
-            created: metadata.created().map(format_time).unwrap_or_else(|_| "Unknown".to_string()),
-            modified: metadata.modified().map(format_time).unwrap_or_else(|_| "Unknown".to_string()),
-            accessed: metadata.accessed().map(format_time).unwrap_or_else(|_| "Unknown".to_string()),
-            is_directory: metadata.is_dir(),
-            is_file: metadata.is_file(),
-            is_symlink: symlink_metadata.file_type().is_symlink(),
-            permissions: format!("{:o}", metadata.permissions().mode() & 0o777),
-        };
-
-        let result = format!(
-            "size: {}\nsize_formatted: {}\ncreated: {}\nmodified: {}\naccessed: {}\nis_directory: {}\nis_file: {}\nis_symlink: {}\npermissions: {}",
-            info.size, info.size_formatted, info.created, info.modified, info.accessed,
-            info.is_directory, info.is_file, info.is_symlink, info.permissions
-        );
-
-        Ok(result)
-    }
-
-    fn list_allowed_directories(&self) -> String {
-        let dirs: Vec<String> = self.allowed_dirs()
-            .iter()
-            .map(|p| p.to_string_lossy().to_string())
-            .collect();
-        format!("Allowed directories:\n{}", dirs.join("\n"))
+    /// Lines matching `regex` in the files under `path` (or in `path` itself), in path order.
+    /// Files that aren't UTF-8 are skipped.
+    fn search_content(&self, path: &str, regex: &regex::Regex, options: &SearchOptions) -> Result<ContentMatches> {
+        let valid_path = self.validate(path)?;
+        let mut found = ContentMatches { matches: vec![], truncated: false };
+        if valid_path.is_file() {
+            grep_file(&valid_path, regex, options, &mut found);
+        } else {
+            let mut ignores = GitIgnores::new(options.gitignore, &valid_path);
+            self.grep_recursive(&valid_path, &valid_path, regex, options, &mut ignores, &mut found);
+        }
+        Ok(found)
     }
-}
 
-/// Simple glob matching (supports * and **)
-fn matches_glob(pattern: &str, path: &str) -> bool {
-    let pattern_parts: Vec<&str> = pattern.split('/').collect();
-    let path_parts: Vec<&str> = path.split('/').collect();
-
-    matches_glob_recursive(&pattern_parts, &path_parts)
-}
+    fn grep_recursive(
+        &self,
+        root: &Path,
+        current: &Path,
+        regex: &regex::Regex,
+        options: &SearchOptions,
+        ignores: &mut GitIgnores,
+        found: &mut ContentMatches,
+    ) {
+        let Ok(entries) = fs::read_dir(current) else {
+            return;
+        };
+        let mut entries: Vec<fs::DirEntry> = entries.filter_map(|entry| entry.ok()).collect();
+        entries.sort_by_key(|entry| entry.file_name());
 
-fn matches_glob_recursive(pattern: &[&str], path: &[&str]) -> bool {
-    if pattern.is_empty() {
-        return path.is_empty();
+        for entry in entries {
+            if found.truncated {
+                return;
+            }
+            let entry_path = entry.path();
+            if !is_within_allowed(&entry_path, self.allowed_dirs()) || self.config.excludes(&entry_path) {
+                continue;
+            }
+            let relative = entry_path.strip_prefix(root).unwrap_or(&entry_path).to_string_lossy().to_string();
+            if options.exclude.matches(&relative) {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if ignores.ignores(&entry_path, file_type.is_dir()) {
+                continue;
+            }
+            if file_type.is_dir() {
+                ignores.enter(&entry_path);
+                self.grep_recursive(root, &entry_path, regex, options, ignores, found);
+                ignores.leave();
+            } else if (options.include.is_empty() || options.include.matches(&relative))
+                && self.readable_entry(&entry_path)
+            {
+                grep_file(&entry_path, regex, options, found);
+            }
+        }
     }
 
-    let p = pattern[0];
+    fn disk_usage(&self, path: &str, exclude: &Globs, top: usize) -> Result<DiskUsage> {
+        let valid_path = self.validate(path)?;
+        if !valid_path.is_dir() {
+            return Err(anyhow!("{} is not a directory", valid_path.display()));
+        }
 
-    if p == "**" {
-        // ** matches zero or more path segments
-        if matches_glob_recursive(&pattern[1..], path) {
-            return true;
+        let mut children = vec![];
+        for entry in fs::read_dir(&valid_path)? {
+            let Ok(entry) = entry else {
+                continue;
+            };
+            if let Some(usage) = self.usage_of(&valid_path, &entry, exclude) {
+                children.push(usage);
+            }
         }
-        if !path.is_empty() && matches_glob_recursive(pattern, &path[1..]) {
-            return true;
+        let mut total = Usage::default();
+        for child in &children {
+            total.add(child);
         }
-        return false;
-    }
-
-    if path.is_empty() {
-        return false;
-    }
-
-    if matches_segment(p, path[0]) {
-        matches_glob_recursive(&pattern[1..], &path[1..])
-    } else {
-        false
-    }
-}
-
-fn matches_segment(pattern: &str, segment: &str) -> bool {
-    if pattern == "*" {
-        return true;
+        children.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+        children.truncate(top);
+
+        Ok(DiskUsage {
+            path: valid_path.to_string_lossy().to_string(),
+            size: total.size,
+            files: total.files,
+            directories: total.directories,
+            largest: children,
+            unreadable: total.unreadable,
+        })
     }
 
-    let mut pattern_chars = pattern.chars().peekable();
-    let mut segment_chars = segment.chars().peekable();
+    /// The usage of `entry` and everything under it, or None if it's excluded. Symlinks
+    /// aren't followed and take up no space.
+    fn usage_of(&self, root: &Path, entry: &fs::DirEntry, exclude: &Globs) -> Option<Usage> {
+        let entry_path = entry.path();
+        if !is_within_allowed(&entry_path, self.allowed_dirs()) || self.config.excludes(&entry_path) {
+            return None;
+        }
+        let relative = entry_path.strip_prefix(root).unwrap_or(&entry_path).to_string_lossy().to_string();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if exclude.matches(&relative) {
+            return None;
+        }
 
-    while let Some(p) = pattern_chars.next() {
-        match p {
-            '*' => {
-                // * matches any sequence of characters within a segment
-                if pattern_chars.peek().is_none() {
-                    return true;
-                }
-                // Try matching remaining pattern at each position
-                let remaining_pattern: String = pattern_chars.collect();
-                let mut remaining_segment: String = segment_chars.collect();
-                while !remaining_segment.is_empty() {
-                    if matches_segment(&remaining_pattern, &remaining_segment) {
-                        return true;
+        let mut usage = Usage { name, ..Usage::default() };
+        let Ok(metadata) = entry.metadata() else {
+            usage.unreadable = 1;
+            return Some(usage);
+        };
+        if metadata.is_dir() {
+            usage.is_directory = true;
+            usage.directories = 1;
+            let Ok(entries) = fs::read_dir(&entry_path) else {
+                usage.unreadable = 1;
+                return Some(usage);
+            };
+            for entry in entries {
+                match entry {
+                    Ok(entry) => {
+                        if let Some(child) = self.usage_of(root, &entry, exclude) {
+                            usage.add(&child);
+                        }
                     }
-                    remaining_segment = remaining_segment.chars().skip(1).collect();
-                }
-                return matches_segment(&remaining_pattern, "");
-            }
-            '?' => {
-                if segment_chars.next().is_none() {
-                    return false;
-                }
-            }
-            c => {
-                if segment_chars.next() != Some(c) {
-                    return false;
+                    Err(_) => usage.unreadable += 1,
                 }
             }
+        } else if metadata.is_file() {
+            usage.size = metadata.len();
+            usage.files = 1;
         }
+        Some(usage)
     }
 
-    segment_chars.next().is_none()
-}
-
-/// Create a simple unified diff
-fn create_unified_diff(original: &str, modified: &str, filename: &str) -> String {
-    let original_lines: Vec<&str> = original.lines().collect();
-    let modified_lines: Vec<&str> = modified.lines().collect();
-
-    let mut diff = String::new();
-    diff.push_str(&format!("--- {}\n", filename));
-    diff.push_str(&format!("+++ {}\n", filename));
+    /// Count the lines, words, characters and bytes of `path`, or of the text files under it
+    /// matching `pattern`, like a `wc` that stays in the allowed directories
+    fn stats(
+        &self,
+        path: &str,
+        pattern: &Globs,
+        exclude: &Globs,
+        gitignore: bool,
+        max_files: usize,
+    ) -> Result<Stats> {
+        let valid_path = self.validate(path)?;
+        let files: Vec<PathBuf> = if valid_path.is_dir() {
+            let mut found = vec![];
+            let mut ignores = GitIgnores::new(gitignore, &valid_path);
+            self.search_recursive(&valid_path, &valid_path, pattern, exclude, &mut ignores, &mut found)?;
+            let mut files: Vec<PathBuf> = found
+                .into_iter()
+                .map(PathBuf::from)
+                .filter(|path| path.is_file() && self.readable_entry(path))
+                .collect();
+            files.sort();
+            files
+        } else {
+            vec![valid_path.clone()]
+        };
+
+        let mut stats = Stats { files: vec![], total: TextCounts::default(), counted: 0, skipped: 0, truncated: false };
+        for file in files {
+            let counts = File::open(&file).and_then(|mut opened| {
+                let mut head = vec![];
+                (&mut opened).take(8192).read_to_end(&mut head)?;
+                opened.rewind()?;
+                is_text(&head).then(|| TextCounts::of(opened)).transpose()
+            });
+            let Ok(Some(counts)) = counts else {
+                stats.skipped += 1;
+                continue;
+            };
+            stats.total.add(&counts);
+            stats.counted += 1;
+            if stats.files.len() == max_files {
+                stats.truncated = true;
+                continue;
+            }
+            let relative = file.strip_prefix(&valid_path).ok().filter(|relative| !relative.as_os_str().is_empty());
+            let path = relative.unwrap_or(&file).to_string_lossy().to_string();
+            stats.files.push(FileCounts { path, counts });
+        }
+        Ok(stats)
+    }
+
+    /// Groups of files under `path` with the same content. Only files of the same size are
+    /// hashed, and symlinks are left out.
+    fn find_duplicates(&self, path: &str, options: &DuplicateOptions) -> Result<Duplicates> {
+        let valid_path = self.validate(path)?;
+        if !valid_path.is_dir() {
+            return Err(anyhow!("{} is not a directory", valid_path.display()));
+        }
+        let mut found = vec![];
+        let mut ignores = GitIgnores::new(options.gitignore, &valid_path);
+        self.search_recursive(&valid_path, &valid_path, &options.pattern, &options.exclude, &mut ignores, &mut found)?;
+
+        let mut duplicates = Duplicates { groups: vec![], files: 0, wasted: 0, unreadable: 0, truncated: false };
+        let mut by_size: BTreeMap<u64, Vec<PathBuf>> = BTreeMap::new();
+        for file in found.into_iter().map(PathBuf::from) {
+            let Ok(metadata) = fs::symlink_metadata(&file) else {
+                duplicates.unreadable += 1;
+                continue;
+            };
+            if metadata.is_file() && metadata.len() >= options.min_size {
+                duplicates.files += 1;
+                by_size.entry(metadata.len()).or_default().push(file);
+            }
+        }
+
+        for (size, files) in by_size.into_iter().filter(|(_, files)| files.len() > 1) {
+            let mut by_hash: BTreeMap<String, Vec<String>> = BTreeMap::new();
+            for file in files {
+                let Ok(hash) = File::open(&file).and_then(|opened| options.algorithm.hash(opened)) else {
+                    duplicates.unreadable += 1;
+                    continue;
+                };
+                let relative = file.strip_prefix(&valid_path).unwrap_or(&file).to_string_lossy().to_string();
+                by_hash.entry(hash).or_default().push(relative);
+            }
+            for (hash, mut paths) in by_hash.into_iter().filter(|(_, paths)| paths.len() > 1) {
+                paths.sort();
+                duplicates.groups.push(DuplicateGroup { size, hash, paths });
+            }
+        }
+        duplicates.wasted = duplicates.groups.iter().map(DuplicateGroup::wasted).sum();
+        duplicates.groups.sort_by(|a, b| b.wasted().cmp(&a.wasted()).then_with(|| a.paths.cmp(&b.paths)));
+        duplicates.truncated = duplicates.groups.len() > options.max_groups;
+        duplicates.groups.truncate(options.max_groups);
+        Ok(duplicates)
+    }
+
+    fn get_file_info(&self, path: &str) -> Result<FileInfo> {
+        let valid_path = self.validate(path)?;
+        let metadata = fs::metadata(&valid_path)?;
+        let symlink_metadata = fs::symlink_metadata(&valid_path)?;
+        let (owner, group) = owner_and_group(&metadata);
+        let (mut mime, mut lines) = (None, None);
+        if metadata.is_file() {
+            let mut head = vec![];
+            File::open(&valid_path)?.take(8192).read_to_end(&mut head)?;
+            let text = is_text(&head);
+            mime = Some(mime_type(&valid_path).or_else(|| sniff_mime_type(&head)).unwrap_or(match text {
+                true => "text/plain",
+                false => "application/octet-stream",
+            }));
+            if text && metadata.len() <= MAX_LINE_COUNT {
+                lines = Some(TextCounts::of(File::open(&valid_path)?)?.lines);
+            }
+        }
+
+        let info = FileInfo {
+            size: metadata.len(),
+            size_formatted: format_size(metadata.len()),
+            created: metadata.created().map(format_time).unwrap_or_else(|_| "Unknown".to_string()),
+            modified: metadata.modified().map(format_time).unwrap_or_else(|_| "Unknown".to_string()),
+            accessed: metadata.accessed().map(format_time).unwrap_or_else(|_| "Unknown".to_string()),
+            is_directory: metadata.is_dir(),
+            is_file: metadata.is_file(),
+            is_symlink: symlink_metadata.file_type().is_symlink(),
+            permissions: format_permissions(&metadata.permissions()),
+            owner,
+            group,
+            mime_type: mime.map(str::to_string),
+            lines,
+            xattrs: extended_attributes(&valid_path),
+        };
+
+        Ok(info)
+    }
+
+    fn watch_path(&self, path: &str, session: Option<&str>) -> Result<PathBuf> {
+        let valid_path = self.validate(path)?;
+        if !valid_path.exists() {
+            return Err(anyhow!("Path does not exist: {}", valid_path.display()));
+        }
+        self.watches.watch(&valid_path, session)?;
+        Ok(valid_path)
+    }
+
+    fn poll_changes(&self, path: Option<&str>, session: Option<&str>, wait: Duration) -> Result<Changes> {
+        let valid_path = path.map(|path| self.validate(path)).transpose()?;
+        let mut found = self.watches.poll(valid_path.as_deref(), session, wait)?;
+        found.changes.retain(|changed| !self.config.excludes(Path::new(&changed.path)));
+        Ok(found)
+    }
+
+    fn unwatch_path(&self, path: &str, session: Option<&str>) -> Result<PathBuf> {
+        let valid_path = self.validate(path)?;
+        if !self.watches.unwatch(&valid_path, session) {
+            return Err(anyhow!("Not watching {}", valid_path.display()));
+        }
+        Ok(valid_path)
+    }
+
+    fn create_temp_dir(&self, prefix: Option<&str>, session: &str) -> Result<String> {
+        let prefix = prefix.unwrap_or("mcpz-tmp");
+        if prefix.is_empty()
+            || !prefix.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(anyhow!(
+                "Invalid prefix: only letters, digits, '-' and '_' are allowed"
+            ));
+        }
+
+        let root = self.config.scratch_root();
+        if self.config.is_read_only(root) {
+            return Err(access_denied("scratch directory is read-only", root));
+        }
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let path = root.join(format!("{}-{}", prefix, &id[..12]));
+
+        // create_dir (not create_dir_all) fails if the name is somehow taken
+        fs::create_dir(&path)
+            .with_context(|| format!("Failed to create temp directory: {}", path.display()))?;
+
+        self.log(&format!("Created temp directory {} for session {}", path.display(), session));
+        self.temp_dirs
+            .lock()
+            .unwrap()
+            .entry(session.to_string())
+            .or_default()
+            .push(path.clone());
+
+        Ok(path.to_string_lossy().to_string())
+    }
 
-    // Simple line-by-line diff
-    let max_len = std::cmp::max(original_lines.len(), modified_lines.len());
-    let mut i = 0;
-    while i < max_len {
-        let orig = original_lines.get(i).copied();
-        let modi = modified_lines.get(i).copied();
+    fn cleanup_temp_dir(&self, path: &str, session: &str) -> Result<String> {
+        let valid_path = self.validate(path)?;
+
+        let mut temp_dirs = self.temp_dirs.lock().unwrap();
+        let owned = temp_dirs.get_mut(session);
+        let index = owned
+            .as_ref()
+            .and_then(|dirs| dirs.iter().position(|d| *d == valid_path))
+            .ok_or_else(|| {
+                anyhow!(
+                    "Not a temp directory created by this session: {}",
+                    valid_path.display()
+                )
+            })?;
+
+        fs::remove_dir_all(&valid_path)
+            .with_context(|| format!("Failed to remove temp directory: {}", valid_path.display()))?;
+        owned.unwrap().remove(index);
+
+        Ok(format!("Successfully removed temp directory {}", path))
+    }
 
-        match (orig, modi) {
-            (Some(o), Some(m)) if o == m => {
-                diff.push_str(&format!(" {}\n", o));
+    /// Remove all temp directories tracked for a session
+    fn cleanup_session_temp_dirs(&self, session: &str) {
+        let dirs = self.temp_dirs.lock().unwrap().remove(session).unwrap_or_default();
+        for dir in dirs {
+            match fs::remove_dir_all(&dir) {
+                Ok(()) => self.log(&format!("Removed temp directory {}", dir.display())),
+                Err(e) => {
+                    self.log_at(LogLevel::Warning, &format!("Failed to remove temp directory {}: {}", dir.display(), e))
+                }
             }
-            (Some(o), Some(m)) => {
-                diff.push_str(&format!("-{}\n", o));
-                diff.push_str(&format!("+{}\n", m));
+        }
+    }
+
+    fn list_allowed_directories(&self, session: &str) -> String {
+        let dirs: Vec<String> = self.session_dirs(session)
+            .iter()
+            .map(|p| {
+                let access = if self.config.is_read_only(p) { " (read-only)" } else { "" };
+                format!("{}{}", p.to_string_lossy(), access)
+            })
+            .collect();
+        format!("Allowed directories:\n{}", dirs.join("\n"))
+    }
+
+    // Resources
+
+    /// Files under `dir`, depth first in name order, skipping hidden entries, until
+    /// `resources` holds `MAX_RESOURCES`
+    fn collect_file_resources(&self, root: &Path, dir: &Path, resources: &mut Vec<McpResource>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        let mut entries: Vec<_> = entries.filter_map(|entry| entry.ok()).collect();
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            if resources.len() >= MAX_RESOURCES {
+                return;
             }
-            (Some(o), None) => {
-                diff.push_str(&format!("-{}\n", o));
+            if entry.file_name().to_string_lossy().starts_with('.') || self.config.excludes(&entry.path()) {
+                continue;
             }
-            (None, Some(m)) => {
-                diff.push_str(&format!("+{}\n", m));
+            let path = entry.path();
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => self.collect_file_resources(root, &path, resources),
+                Ok(file_type) if file_type.is_file() => resources.push(McpResource {
+                    uri: file_uri(&path),
+                    name: path.strip_prefix(root).unwrap_or(&path).to_string_lossy().to_string(),
+                    description: None,
+                    mime_type: mime_type(&path).map(str::to_string),
+                }),
+                _ => {}
             }
-            (None, None) => break,
         }
-        i += 1;
     }
+}
+
+/// `file://` URI of an absolute path, percent-encoding each segment
+fn file_uri(path: &Path) -> String {
+    let encoded: Vec<String> = path
+        .to_string_lossy()
+        .split('/')
+        .map(|segment| urlencoding::encode(segment).into_owned())
+        .collect();
+    format!("file://{}", encoded.join("/"))
+}
+
+/// The path a `file://` URI names
+fn uri_path(uri: &str) -> Result<String> {
+    let path = uri
+        .strip_prefix("file://")
+        .ok_or_else(|| anyhow!("Not a file:// URI: {}", uri))?;
+    Ok(urlencoding::decode(path)?.into_owned())
+}
+
+/// MIME type for common file extensions
+fn mime_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    Some(match extension.as_str() {
+        "txt" | "log" | "rs" | "py" | "go" | "c" | "h" | "java" | "sh" | "ts" => "text/plain",
+        "md" => "text/markdown",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "csv" => "text/csv",
+        "js" => "text/javascript",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "yaml" | "yml" => "application/yaml",
+        "toml" => "application/toml",
+        "pdf" => "application/pdf",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        _ => return None,
+    })
+}
+
+/// The first and last lines of a `lineRange` argument, `[first, last]` counting from 1
+fn parse_line_range(range: &serde_json::Value) -> Option<(usize, usize)> {
+    match range.as_array()?.as_slice() {
+        [first, last] => {
+            let (first, last) = (first.as_u64()? as usize, last.as_u64()? as usize);
+            (first >= 1 && last >= first).then_some((first, last))
+        }
+        _ => None,
+    }
+}
+
+/// The archive format `create_archive`/`extract_archive` were given, or else the one the
+/// archive's name implies
+fn archive_format(format: Option<&str>, archive: &Path) -> Result<ArchiveFormat> {
+    format.and_then(ArchiveFormat::parse).or_else(|| ArchiveFormat::from_path(archive)).ok_or_else(|| {
+        anyhow!("Can't tell the format of {}: name it .zip, .tar.gz or .tgz, or pass format", archive.display())
+    })
+}
+
+/// What `copy_tree` copied
+#[derive(Default)]
+struct CopyCounts {
+    files: usize,
+    /// Symlinks aren't copied: a link to outside the allowed directories must not turn into
+    /// a copy of what it points to
+    skipped_links: usize,
+}
+
+/// Copy the directory `source` to `destination` with everything in it but what `excluded`
+/// holds, creating directories as needed and replacing files that exist. A path `writable`
+/// refuses stops the copy.
+fn copy_tree(
+    source: &Path,
+    destination: &Path,
+    excluded: &dyn Fn(&Path) -> bool,
+    writable: &dyn Fn(&Path) -> Result<()>,
+    copied: &mut CopyCounts,
+) -> Result<()> {
+    writable(destination)?;
+    fs::create_dir_all(destination)?;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        if excluded(&entry.path()) {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        let target = destination.join(entry.file_name());
+        if file_type.is_symlink() {
+            copied.skipped_links += 1;
+        } else if file_type.is_dir() {
+            copy_tree(&entry.path(), &target, excluded, writable, copied)?;
+        } else {
+            writable(&target)?;
+            fs::copy(entry.path(), &target)?;
+            copied.files += 1;
+        }
+    }
+    Ok(())
+}
+
+/// The globs in the array argument `name`; invalid ones are an invalid params error
+fn glob_argument(arguments: &serde_json::Value, name: &str, ignore_case: bool) -> Result<Globs> {
+    let patterns: Vec<String> = arguments.get(name)
+        .and_then(|v| v.as_array())
+        .map(|arr| arr.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+        .unwrap_or_default();
+    Globs::new(&patterns, ignore_case).map_err(|e| RpcError::invalid_params(e.to_string()).into())
+}
+
+/// How `search_content` picks files and shows matches
+struct SearchOptions {
+    /// Globs matched against each file's path relative to the searched directory; none means
+    /// every file
+    include: Globs,
+    /// Globs for files and directories to skip, matched the same way
+    exclude: Globs,
+    /// Lines shown before and after each match
+    context: usize,
+    max_results: usize,
+    /// Skip `.gitignore`d files and directories
+    gitignore: bool,
+}
+
+/// Add the lines of `path` matching `regex` to `found`
+fn grep_file(path: &Path, regex: &regex::Regex, options: &SearchOptions, found: &mut ContentMatches) {
+    let Ok(text) = fs::read_to_string(path) else {
+        return;
+    };
+    let lines: Vec<&str> = text.lines().collect();
+    for (i, line) in lines.iter().enumerate() {
+        if !regex.is_match(line) {
+            continue;
+        }
+        if found.matches.len() == options.max_results {
+            found.truncated = true;
+            return;
+        }
+        let owned = |lines: &[&str]| lines.iter().map(|line| line.to_string()).collect();
+        found.matches.push(ContentMatch {
+            path: path.to_string_lossy().to_string(),
+            line: i + 1,
+            text: line.to_string(),
+            before: owned(&lines[i.saturating_sub(options.context)..i]),
+            after: owned(&lines[i + 1..(i + 1 + options.context).min(lines.len())]),
+        });
+    }
+}
+
+/// MIME type of common binary formats, from their first bytes
+fn sniff_mime_type(bytes: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"\x7fELF", "application/x-elf"),
+    ];
+    if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    SIGNATURES.iter().find(|(signature, _)| bytes.starts_with(signature)).map(|(_, mime)| *mime)
+}
+
+/// `content` with every match of the regex `pattern` replaced by `replacement` (which may use
+/// `$1`/`${name}` groups). `^` and `$` match at line ends. Fails if nothing matches, or if
+/// more than `max_replacements` places do, so that a loose pattern can't rewrite a whole file.
+fn replace_regex(content: &str, pattern: &str, replacement: &str, max_replacements: usize) -> Result<String> {
+    let regex = regex::RegexBuilder::new(pattern)
+        .multi_line(true)
+        .build()
+        .map_err(|e| anyhow!("Invalid regex '{}': {}", pattern, e))?;
+    let count = regex.find_iter(content).count();
+    if count == 0 {
+        return Err(anyhow!("No match for regex edit:\n{}", pattern));
+    }
+    if count > max_replacements {
+        return Err(anyhow!(
+            "Regex edit matches {} times, more than maxReplacements ({}); raise maxReplacements to replace them all:\n{}",
+            count,
+            max_replacements,
+            pattern
+        ));
+    }
+    Ok(regex.replace_all(content, replacement).into_owned())
+}
 
-    diff
+/// A unified diff of `original` to `modified`, with standard `@@` hunk headers and `context`
+/// unchanged lines around each change; empty if nothing changed
+fn create_unified_diff(original: &str, modified: &str, filename: &str, context: usize) -> String {
+    similar::TextDiff::from_lines(original, modified)
+        .unified_diff()
+        .context_radius(context)
+        .header(filename, filename)
+        .to_string()
 }
 
 impl McpServer for FilesystemServer {
@@ -831,11 +2797,32 @@ impl McpServer for FilesystemServer {
         self.config.verbose
     }
 
+    fn notifier(&self) -> Option<&Notifier> {
+        Some(&self.notifier)
+    }
+
     fn tools(&self) -> Vec<McpTool> {
-        vec![
+        let respect_gitignore = serde_json::json!({
+            "type": "boolean",
+            "description": "Skip paths ignored by .gitignore files, and the .git directory",
+            "default": self.config.respect_gitignore
+        });
+        let max_entries = serde_json::json!({
+            "type": "integer",
+            "minimum": 1,
+            "description": "Entries per page",
+            "default": DEFAULT_MAX_ENTRIES
+        });
+        let page = serde_json::json!({
+            "type": "integer",
+            "minimum": 1,
+            "description": "Page of entries to return, counting from 1",
+            "default": 1
+        });
+        let mut tools = vec![
             McpTool {
                 name: "read_file".to_string(),
-                description: "Read the contents of a file. Use 'head' to read first N lines, 'tail' to read last N lines, or 'offset'/'limit' to read a specific range of lines.".to_string(),
+                description: "Read the contents of a file. Use 'head' to read first N lines, 'tail' to read last N lines, 'offset'/'limit' or 'lineRange' to read a specific range of lines, or 'byteOffset'/'length' to read a file too large to load in chunks of bytes. A whole image file is returned as an image, and other binary files as an embedded resource.".to_string(),
                 input_schema: serde_json::json!({
                     "type": "object",
                     "properties": {
@@ -858,10 +2845,52 @@ impl McpServer for FilesystemServer {
                         "limit": {
                             "type": "integer",
                             "description": "Maximum number of lines to read (cannot combine with head/tail)"
+                        },
+                        "lineRange": {
+                            "type": "array",
+                            "items": { "type": "integer", "minimum": 1 },
+                            "description": "First and last line to read, e.g. [100, 200] (1-indexed, inclusive; cannot \
+                                combine with head/tail/offset/limit)"
+                        },
+                        "byteOffset": {
+                            "type": "integer",
+                            "minimum": 0,
+                            "description": "Byte to start reading from (0-indexed); the result ends with the byteOffset \
+                                to read on from (cannot combine with line parameters)"
+                        },
+                        "length": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "description": "Bytes to read from byteOffset (default and most: 1 MiB)"
+                        }
+                    },
+                    "required": ["path"]
+                }),
+                output_schema: None,
+            },
+            McpTool {
+                name: "read_file_binary".to_string(),
+                description: "Read a file of any kind (image, archive, executable...) as base64, with its MIME type \
+                    and size. Files over 10 MB are refused.".to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path to the file to read"
                         }
                     },
                     "required": ["path"]
                 }),
+                output_schema: Some(serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "mimeType": {"type": "string", "description": "From the file's extension, or else its content"},
+                        "size": {"type": "integer", "description": "Size in bytes"},
+                        "data": {"type": "string", "description": "The file's content, base64-encoded"}
+                    },
+                    "required": ["mimeType", "size", "data"]
+                })),
             },
             McpTool {
                 name: "read_multiple_files".to_string(),
@@ -885,10 +2914,12 @@ impl McpServer for FilesystemServer {
                     },
                     "required": ["paths"]
                 }),
+                output_schema: None,
             },
             McpTool {
                 name: "write_file".to_string(),
-                description: "Create or overwrite a file with new content.".to_string(),
+                description: "Create or overwrite a file with new content, or with mode 'append' add it to the end."
+                    .to_string(),
                 input_schema: serde_json::json!({
                     "type": "object",
                     "properties": {
@@ -899,10 +2930,37 @@ impl McpServer for FilesystemServer {
                         "content": {
                             "type": "string",
                             "description": "Content to write"
+                        },
+                        "mode": {
+                            "type": "string",
+                            "enum": ["overwrite", "append"],
+                            "description": "Replace the file's content, or append to it (creating the file if needed)",
+                            "default": "overwrite"
+                        }
+                    },
+                    "required": ["path", "content"]
+                }),
+                output_schema: None,
+            },
+            McpTool {
+                name: "append_file".to_string(),
+                description: "Append content to the end of a file, creating it if it doesn't exist. Use it to extend \
+                    logs and generated files without resending what they already hold.".to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path to the file"
+                        },
+                        "content": {
+                            "type": "string",
+                            "description": "Content to append (add a trailing newline for whole lines)"
                         }
                     },
                     "required": ["path", "content"]
                 }),
+                output_schema: None,
             },
             McpTool {
                 name: "edit_file".to_string(),
@@ -919,177 +2977,1034 @@ impl McpServer for FilesystemServer {
                             "items": {
                                 "type": "object",
                                 "properties": {
-                                    "oldText": { "type": "string", "description": "Text to find" },
-                                    "newText": { "type": "string", "description": "Text to replace with" }
+                                    "oldText": { "type": "string", "description": "Text to find" },
+                                    "newText": { "type": "string", "description": "Text to replace with" },
+                                    "regex": {
+                                        "type": "boolean",
+                                        "description": "Treat oldText as a regular expression and replace every \
+                                            match; newText may use capture groups ($1, ${name})",
+                                        "default": false
+                                    },
+                                    "maxReplacements": {
+                                        "type": "integer",
+                                        "minimum": 1,
+                                        "description": "With regex, fail rather than replace more matches than this",
+                                        "default": 1
+                                    }
+                                },
+                                "required": ["oldText", "newText"]
+                            },
+                            "description": "Array of edit operations"
+                        },
+                        "dryRun": {
+                            "type": "boolean",
+                            "description": "Preview changes without writing",
+                            "default": false
+                        },
+                        "contextLines": {
+                            "type": "integer",
+                            "minimum": 0,
+                            "description": "Unchanged lines to show around each change in the diff",
+                            "default": DIFF_CONTEXT
+                        }
+                    },
+                    "required": ["path", "edits"]
+                }),
+                output_schema: None,
+            },
+            McpTool {
+                name: "apply_patch".to_string(),
+                description: "Apply a unified diff (as written by diff -u or git diff) to the files it names, \
+                    creating, deleting or renaming them as it says. Hunks are found even if their lines have moved. \
+                    Nothing is written unless every hunk applies; rejected hunks are reported with the reason."
+                    .to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "patch": {
+                            "type": "string",
+                            "description": "The unified diff"
+                        },
+                        "directory": {
+                            "type": "string",
+                            "description": "Directory the patch's relative paths are in (default: the first allowed \
+                                directory)"
+                        },
+                        "dryRun": {
+                            "type": "boolean",
+                            "description": "Check that the patch applies without writing",
+                            "default": false
+                        }
+                    },
+                    "required": ["patch"]
+                }),
+                output_schema: Some(serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "files": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "path": {"type": "string"},
+                                    "status": {
+                                        "type": "string",
+                                        "enum": ["modified", "created", "deleted", "renamed", "rejected"]
+                                    },
+                                    "hunks": {
+                                        "type": "array",
+                                        "items": {
+                                            "type": "object",
+                                            "properties": {
+                                                "hunk": {"type": "integer", "description": "Counting from 1"},
+                                                "applied": {"type": "boolean"},
+                                                "line": {"type": "integer", "description": "Line applied at"},
+                                                "offset": {
+                                                    "type": "integer",
+                                                    "description": "Lines from where the hunk header put it"
+                                                },
+                                                "reason": {"type": "string", "description": "Why it was rejected"}
+                                            },
+                                            "required": ["hunk", "applied"]
+                                        }
+                                    },
+                                    "error": {"type": "string"}
+                                },
+                                "required": ["path", "status", "hunks"]
+                            }
+                        },
+                        "applied": {"type": "boolean"},
+                        "dryRun": {"type": "boolean"}
+                    },
+                    "required": ["files", "applied", "dryRun"]
+                })),
+            },
+            McpTool {
+                name: "create_directory".to_string(),
+                description: "Create a new directory (including parent directories).".to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path to the directory to create"
+                        }
+                    },
+                    "required": ["path"]
+                }),
+                output_schema: None,
+            },
+            McpTool {
+                name: "list_directory".to_string(),
+                description: "List contents of a directory with [FILE] and [DIR] prefixes, a page at a time."
+                    .to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path to the directory"
+                        },
+                        "maxEntries": max_entries,
+                        "page": page
+                    },
+                    "required": ["path"]
+                }),
+                output_schema: Some(serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "entries": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "name": {"type": "string"},
+                                    "type": {"type": "string", "enum": ["file", "directory"]}
+                                },
+                                "required": ["name", "type"]
+                            }
+                        },
+                        "page": {"type": "integer"},
+                        "total": {"type": "integer", "description": "Entries in the directory"},
+                        "hasMore": {"type": "boolean", "description": "Whether later pages hold more entries"}
+                    },
+                    "required": ["entries", "page", "total", "hasMore"]
+                })),
+            },
+            McpTool {
+                name: "list_directory_with_sizes".to_string(),
+                description: "List directory contents with file sizes.".to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path to the directory"
+                        },
+                        "sortBy": {
+                            "type": "string",
+                            "enum": ["name", "size"],
+                            "description": "Sort by name or size",
+                            "default": "name"
+                        }
+                    },
+                    "required": ["path"]
+                }),
+                output_schema: None,
+            },
+            McpTool {
+                name: "directory_tree".to_string(),
+                description: "Get a recursive tree view of files and directories as JSON. Large trees come a \
+                    page of entries at a time, counted depth-first.".to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path to the root directory"
+                        },
+                        "excludePatterns": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Glob patterns to exclude",
+                            "default": []
+                        },
+                        "respectGitignore": respect_gitignore,
+                        "maxDepth": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "description": "Levels to descend; directories at the last level are listed without \
+                                children (default: no limit)"
+                        },
+                        "maxEntries": max_entries,
+                        "page": page
+                    },
+                    "required": ["path"]
+                }),
+                output_schema: None,
+            },
+            McpTool {
+                name: "create_symlink".to_string(),
+                description: "Create a symbolic link to a file or directory inside the allowed directories."
+                    .to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "target": {
+                            "type": "string",
+                            "description": "What the link points to, absolute or relative to the link's directory"
+                        },
+                        "path": {
+                            "type": "string",
+                            "description": "Path of the link to create"
+                        }
+                    },
+                    "required": ["target", "path"]
+                }),
+                output_schema: None,
+            },
+            McpTool {
+                name: "read_link".to_string(),
+                description: "Show where a symbolic link points, without following it.".to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path of the link"
+                        }
+                    },
+                    "required": ["path"]
+                }),
+                output_schema: None,
+            },
+            McpTool {
+                name: "move_file".to_string(),
+                description: "Move or rename a file or directory.".to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "source": {
+                            "type": "string",
+                            "description": "Source path"
+                        },
+                        "destination": {
+                            "type": "string",
+                            "description": "Destination path"
+                        }
+                    },
+                    "required": ["source", "destination"]
+                }),
+                output_schema: None,
+            },
+            McpTool {
+                name: "copy_file".to_string(),
+                description: "Copy a file to a new path, without passing its contents through the conversation."
+                    .to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "source": {
+                            "type": "string",
+                            "description": "File to copy"
+                        },
+                        "destination": {
+                            "type": "string",
+                            "description": "Path of the copy"
+                        },
+                        "overwrite": {
+                            "type": "boolean",
+                            "description": "Replace the destination if it exists",
+                            "default": false
+                        }
+                    },
+                    "required": ["source", "destination"]
+                }),
+                output_schema: None,
+            },
+            McpTool {
+                name: "copy_directory".to_string(),
+                description: "Copy a directory with everything in it to a new path. Symlinks inside it are skipped."
+                    .to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "source": {
+                            "type": "string",
+                            "description": "Directory to copy"
+                        },
+                        "destination": {
+                            "type": "string",
+                            "description": "Path of the copy"
+                        },
+                        "overwrite": {
+                            "type": "boolean",
+                            "description": "Copy into the destination if it exists, replacing files of the same name",
+                            "default": false
+                        }
+                    },
+                    "required": ["source", "destination"]
+                }),
+                output_schema: None,
+            },
+            McpTool {
+                name: "create_archive".to_string(),
+                description: "Pack a file or directory into a zip or tar.gz archive. Symlinks are skipped.".to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "source": {
+                            "type": "string",
+                            "description": "File or directory to archive; entries are named from its own name down"
+                        },
+                        "destination": {
+                            "type": "string",
+                            "description": "Path of the archive to create"
+                        },
+                        "format": {
+                            "type": "string",
+                            "enum": ["zip", "tar.gz", "tgz"],
+                            "description": "Archive format (default: from the destination's extension)"
+                        },
+                        "overwrite": {
+                            "type": "boolean",
+                            "description": "Replace the destination if it exists",
+                            "default": false
+                        }
+                    },
+                    "required": ["source", "destination"]
+                }),
+                output_schema: None,
+            },
+            McpTool {
+                name: "extract_archive".to_string(),
+                description: "Unpack a zip or tar.gz archive into a directory. Archives with entries that would land \
+                    outside it are refused; links and special files are skipped.".to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "source": {
+                            "type": "string",
+                            "description": "Archive to unpack"
+                        },
+                        "destination": {
+                            "type": "string",
+                            "description": "Directory to unpack into (created if needed)"
+                        },
+                        "format": {
+                            "type": "string",
+                            "enum": ["zip", "tar.gz", "tgz"],
+                            "description": "Archive format (default: from the source's extension)"
+                        },
+                        "overwrite": {
+                            "type": "boolean",
+                            "description": "Replace files that already exist",
+                            "default": false
+                        }
+                    },
+                    "required": ["source", "destination"]
+                }),
+                output_schema: None,
+            },
+            McpTool {
+                name: "search_files".to_string(),
+                description: "Search for files matching a glob pattern.".to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Directory to search in"
+                        },
+                        "pattern": {
+                            "type": "string",
+                            "description": "Glob pattern: without a '/' it matches names at any depth, with one \
+                                the path relative to 'path' (e.g., '*.rs', 'src/**/*.{rs,toml}', 'file[0-9].txt')"
+                        },
+                        "excludePatterns": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Patterns to exclude",
+                            "default": []
+                        },
+                        "ignoreCase": {
+                            "type": "boolean",
+                            "description": "Match the patterns regardless of case",
+                            "default": false
+                        },
+                        "respectGitignore": respect_gitignore
+                    },
+                    "required": ["path", "pattern"]
+                }),
+                output_schema: None,
+            },
+            McpTool {
+                name: "hash_file".to_string(),
+                description: "Compute checksums of one or more files without reading them into the conversation, \
+                    e.g. to verify a download, find duplicates or confirm a write landed.".to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "File to hash"
+                        },
+                        "paths": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Files to hash, instead of 'path'"
+                        },
+                        "algorithm": {
+                            "type": "string",
+                            "enum": ["sha256", "md5", "blake3"],
+                            "default": "sha256"
+                        }
+                    }
+                }),
+                output_schema: Some(serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "algorithm": {"type": "string"},
+                        "files": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "path": {"type": "string"},
+                                    "size": {"type": "integer", "description": "Size in bytes"},
+                                    "hash": {"type": "string", "description": "Hex digest"},
+                                    "error": {"type": "string", "description": "Why the file couldn't be hashed"}
+                                },
+                                "required": ["path"]
+                            }
+                        }
+                    },
+                    "required": ["algorithm", "files"]
+                })),
+            },
+            McpTool {
+                name: "search_content".to_string(),
+                description: "Search the contents of files for lines matching a regular expression (or, with \
+                    'literal', a plain string), returning each file path, line number and matching line, with context \
+                    lines if asked for. Searches a directory recursively, or a single file.".to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Directory (searched recursively) or file to search"
+                        },
+                        "pattern": {
+                            "type": "string",
+                            "description": "Regular expression to look for in each line"
+                        },
+                        "literal": {
+                            "type": "boolean",
+                            "description": "Treat the pattern as a plain string",
+                            "default": false
+                        },
+                        "ignoreCase": {
+                            "type": "boolean",
+                            "description": "Match regardless of case",
+                            "default": false
+                        },
+                        "includePatterns": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Only search files whose name or relative path matches one of these globs \
+                                (e.g., '*.rs', 'src/**')",
+                            "default": []
+                        },
+                        "excludePatterns": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Skip files and directories whose name or relative path matches one of \
+                                these globs (e.g., 'target', '*.min.js')",
+                            "default": []
+                        },
+                        "contextLines": {
+                            "type": "integer",
+                            "minimum": 0,
+                            "description": "Lines to show before and after each match",
+                            "default": 0
+                        },
+                        "maxResults": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "description": "Stop after this many matches",
+                            "default": 100
+                        },
+                        "respectGitignore": respect_gitignore
+                    },
+                    "required": ["path", "pattern"]
+                }),
+                output_schema: Some(serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "matches": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "path": {"type": "string"},
+                                    "line": {"type": "integer", "description": "Line number, from 1"},
+                                    "text": {"type": "string"},
+                                    "before": {"type": "array", "items": {"type": "string"}},
+                                    "after": {"type": "array", "items": {"type": "string"}}
+                                },
+                                "required": ["path", "line", "text", "before", "after"]
+                            }
+                        },
+                        "truncated": {"type": "boolean", "description": "Whether maxResults cut the search short"}
+                    },
+                    "required": ["matches", "truncated"]
+                })),
+            },
+            McpTool {
+                name: "disk_usage".to_string(),
+                description: "Total up the size of a directory and everything in it, listing its largest \
+                    children, to find what is taking up space. Symlinks aren't followed.".to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Directory to measure"
+                        },
+                        "excludePatterns": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Glob patterns for files and directories to leave out",
+                            "default": []
+                        },
+                        "top": {
+                            "type": "integer",
+                            "description": "How many of the largest children to list",
+                            "minimum": 0,
+                            "default": 10
+                        }
+                    },
+                    "required": ["path"]
+                }),
+                output_schema: Some(serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {"type": "string"},
+                        "size": {"type": "integer", "description": "Total size in bytes"},
+                        "files": {"type": "integer"},
+                        "directories": {"type": "integer"},
+                        "largest": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "name": {"type": "string"},
+                                    "is_directory": {"type": "boolean"},
+                                    "size": {"type": "integer", "description": "Size in bytes, with everything in it"},
+                                    "files": {"type": "integer"},
+                                    "directories": {"type": "integer"}
+                                },
+                                "required": ["name", "is_directory", "size", "files", "directories"]
+                            }
+                        },
+                        "unreadable": {"type": "integer", "description": "Entries that couldn't be read"}
+                    },
+                    "required": ["path", "size", "files", "directories", "largest", "unreadable"]
+                })),
+            },
+            McpTool {
+                name: "find_duplicates".to_string(),
+                description: "Find groups of identical files under a directory, e.g. to clean up copies. Files \
+                    are compared by size, then by hash; symlinks are left out. Groups wasting the most space come \
+                    first.".to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Directory to search in"
+                        },
+                        "pattern": {
+                            "type": "string",
+                            "description": "Glob pattern for the files to compare: without a '/' it matches names at \
+                                any depth, with one the path relative to 'path' (e.g., '*.jpg', 'photos/**')",
+                            "default": "*"
+                        },
+                        "excludePatterns": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Glob patterns for files and directories to leave out",
+                            "default": []
+                        },
+                        "respectGitignore": respect_gitignore,
+                        "minSize": {
+                            "type": "integer",
+                            "minimum": 0,
+                            "description": "Smallest file to compare, in bytes",
+                            "default": 1
+                        },
+                        "algorithm": {
+                            "type": "string",
+                            "enum": ["sha256", "md5", "blake3"],
+                            "default": "sha256"
+                        },
+                        "maxGroups": {
+                            "type": "integer",
+                            "minimum": 0,
+                            "description": "Most groups to list",
+                            "default": 100
+                        }
+                    },
+                    "required": ["path"]
+                }),
+                output_schema: Some(serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "groups": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "size": {"type": "integer", "description": "Size of each file in bytes"},
+                                    "hash": {"type": "string"},
+                                    "paths": {
+                                        "type": "array",
+                                        "items": {"type": "string"},
+                                        "description": "Relative to 'path'"
+                                    }
+                                },
+                                "required": ["size", "hash", "paths"]
+                            }
+                        },
+                        "files": {"type": "integer", "description": "Files compared"},
+                        "wasted": {"type": "integer", "description": "Bytes in extra copies, across every group"},
+                        "unreadable": {"type": "integer", "description": "Files that couldn't be read"},
+                        "truncated": {"type": "boolean", "description": "Whether maxGroups cut the list short"}
+                    },
+                    "required": ["groups", "files", "wasted", "unreadable", "truncated"]
+                })),
+            },
+            McpTool {
+                name: "stats".to_string(),
+                description: "Count the lines, words, characters and bytes of a file, or of each text file under \
+                    a directory matching a glob pattern, with totals, like wc. Binary files are skipped."
+                    .to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "File, or directory to count the files in"
+                        },
+                        "pattern": {
+                            "type": "string",
+                            "description": "Glob pattern for the files to count: without a '/' it matches names at \
+                                any depth, with one the path relative to 'path' (e.g., '*.rs', 'src/**/*.{rs,toml}')",
+                            "default": "*"
+                        },
+                        "excludePatterns": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Glob patterns for files and directories to leave out",
+                            "default": []
+                        },
+                        "ignoreCase": {
+                            "type": "boolean",
+                            "description": "Match the patterns regardless of case",
+                            "default": false
+                        },
+                        "respectGitignore": respect_gitignore,
+                        "maxFiles": {
+                            "type": "integer",
+                            "minimum": 0,
+                            "description": "Most files to list; the totals count every file",
+                            "default": DEFAULT_MAX_ENTRIES
+                        }
+                    },
+                    "required": ["path"]
+                }),
+                output_schema: Some(serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "files": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "path": {"type": "string", "description": "Relative to 'path'"},
+                                    "lines": {"type": "integer"},
+                                    "words": {"type": "integer"},
+                                    "chars": {"type": "integer"},
+                                    "bytes": {"type": "integer"}
+                                },
+                                "required": ["path", "lines", "words", "chars", "bytes"]
+                            }
+                        },
+                        "total": {
+                            "type": "object",
+                            "properties": {
+                                "lines": {"type": "integer"},
+                                "words": {"type": "integer"},
+                                "chars": {"type": "integer"},
+                                "bytes": {"type": "integer"}
+                            },
+                            "required": ["lines", "words", "chars", "bytes"]
+                        },
+                        "counted": {"type": "integer", "description": "Text files counted"},
+                        "skipped": {"type": "integer", "description": "Binary or unreadable files"},
+                        "truncated": {"type": "boolean", "description": "Whether maxFiles cut the list short"}
+                    },
+                    "required": ["files", "total", "counted", "skipped", "truncated"]
+                })),
+            },
+            McpTool {
+                name: "get_file_info".to_string(),
+                description: "Get detailed metadata about a file or directory: size, times, permissions, owner, \
+                    group and extended attributes, and for files the MIME type and, if text, the line count."
+                    .to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path to the file or directory"
+                        }
+                    },
+                    "required": ["path"]
+                }),
+                output_schema: Some(serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "size": {"type": "integer", "description": "Size in bytes"},
+                        "size_formatted": {"type": "string"},
+                        "created": {"type": "string"},
+                        "modified": {"type": "string"},
+                        "accessed": {"type": "string"},
+                        "is_directory": {"type": "boolean"},
+                        "is_file": {"type": "boolean"},
+                        "is_symlink": {"type": "boolean"},
+                        "permissions": {"type": "string"},
+                        "owner": {"type": "string", "description": "User name, or uid if it has none"},
+                        "group": {"type": "string", "description": "Group name, or gid if it has none"},
+                        "mime_type": {
+                            "type": "string",
+                            "description": "Of a file, from its extension or else its content"
+                        },
+                        "lines": {"type": "integer", "description": "Lines in a text file"},
+                        "xattrs": {
+                            "type": "object",
+                            "additionalProperties": {"type": "string"},
+                            "description": "Extended attributes by name; values that aren't text are hex, 0x-prefixed"
+                        }
+                    },
+                    "required": [
+                        "size", "size_formatted", "created", "modified", "accessed",
+                        "is_directory", "is_file", "is_symlink", "permissions", "owner", "group", "xattrs"
+                    ]
+                })),
+            },
+            McpTool {
+                name: "list_allowed_directories".to_string(),
+                description: "List directories this server is allowed to access.".to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {}
+                }),
+                output_schema: None,
+            },
+            McpTool {
+                name: "watch_path".to_string(),
+                description: "Start watching a file or directory (recursively) for changes. Call poll_changes to get \
+                    the entries created, modified or deleted since the last poll, instead of walking the tree again. \
+                    Watches end with the session.".to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "File or directory to watch"
+                        }
+                    },
+                    "required": ["path"]
+                }),
+                output_schema: None,
+            },
+            McpTool {
+                name: "poll_changes".to_string(),
+                description: "Get the entries created, modified or deleted under watched paths since the last \
+                    poll_changes, optionally waiting for some.".to_string(),
+                input_schema: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Watched path to poll (default: every watched path)"
+                        },
+                        "wait": {
+                            "type": "integer",
+                            "minimum": 0,
+                            "maximum": MAX_POLL_WAIT,
+                            "description": "Seconds to wait for changes if there are none yet",
+                            "default": 0
+                        }
+                    }
+                }),
+                output_schema: Some(serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "changes": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "path": {"type": "string"},
+                                    "change": {"type": "string", "enum": ["created", "modified", "deleted"]}
                                 },
-                                "required": ["oldText", "newText"]
-                            },
-                            "description": "Array of edit operations"
+                                "required": ["path", "change"]
+                            }
                         },
-                        "dryRun": {
+                        "overflowed": {
                             "type": "boolean",
-                            "description": "Preview changes without writing",
-                            "default": false
+                            "description": "Whether changes were dropped, so watched paths should be listed again"
                         }
                     },
-                    "required": ["path", "edits"]
-                }),
+                    "required": ["changes", "overflowed"]
+                })),
             },
             McpTool {
-                name: "create_directory".to_string(),
-                description: "Create a new directory (including parent directories).".to_string(),
+                name: "unwatch_path".to_string(),
+                description: "Stop watching a path given to watch_path.".to_string(),
                 input_schema: serde_json::json!({
                     "type": "object",
                     "properties": {
                         "path": {
                             "type": "string",
-                            "description": "Path to the directory to create"
+                            "description": "Watched file or directory"
                         }
                     },
                     "required": ["path"]
                 }),
+                output_schema: None,
             },
             McpTool {
-                name: "list_directory".to_string(),
-                description: "List contents of a directory with [FILE] and [DIR] prefixes.".to_string(),
+                name: "create_temp_dir".to_string(),
+                description: "Create a uniquely-named scratch directory and return its path. It is removed automatically when the session ends.".to_string(),
                 input_schema: serde_json::json!({
                     "type": "object",
                     "properties": {
-                        "path": {
+                        "prefix": {
                             "type": "string",
-                            "description": "Path to the directory"
+                            "description": "Directory name prefix (letters, digits, '-' and '_')",
+                            "default": "mcpz-tmp"
                         }
-                    },
-                    "required": ["path"]
+                    }
                 }),
+                output_schema: None,
             },
             McpTool {
-                name: "list_directory_with_sizes".to_string(),
-                description: "List directory contents with file sizes.".to_string(),
+                name: "cleanup_temp_dir".to_string(),
+                description: "Remove a scratch directory previously created with create_temp_dir.".to_string(),
                 input_schema: serde_json::json!({
                     "type": "object",
                     "properties": {
                         "path": {
                             "type": "string",
-                            "description": "Path to the directory"
-                        },
-                        "sortBy": {
-                            "type": "string",
-                            "enum": ["name", "size"],
-                            "description": "Sort by name or size",
-                            "default": "name"
+                            "description": "Path returned by create_temp_dir"
                         }
                     },
                     "required": ["path"]
                 }),
+                output_schema: None,
             },
-            McpTool {
-                name: "directory_tree".to_string(),
-                description: "Get a recursive tree view of files and directories as JSON.".to_string(),
+        ];
+        if self.config.allow_delete {
+            tools.push(McpTool {
+                name: "delete_file".to_string(),
+                description: "Delete a file (or a symlink, not what it points to).".to_string(),
                 input_schema: serde_json::json!({
                     "type": "object",
                     "properties": {
                         "path": {
                             "type": "string",
-                            "description": "Path to the root directory"
-                        },
-                        "excludePatterns": {
-                            "type": "array",
-                            "items": { "type": "string" },
-                            "description": "Glob patterns to exclude",
-                            "default": []
+                            "description": "Path to the file to delete"
                         }
                     },
                     "required": ["path"]
                 }),
-            },
-            McpTool {
-                name: "move_file".to_string(),
-                description: "Move or rename a file or directory.".to_string(),
+                output_schema: None,
+            });
+            tools.push(McpTool {
+                name: "delete_directory".to_string(),
+                description: "Delete a directory. Only empty directories are deleted unless 'recursive' is true. \
+                    The allowed directories themselves can't be deleted.".to_string(),
                 input_schema: serde_json::json!({
                     "type": "object",
                     "properties": {
-                        "source": {
+                        "path": {
                             "type": "string",
-                            "description": "Source path"
+                            "description": "Path to the directory to delete"
                         },
-                        "destination": {
-                            "type": "string",
-                            "description": "Destination path"
+                        "recursive": {
+                            "type": "boolean",
+                            "description": "Also delete everything in the directory",
+                            "default": false
                         }
                     },
-                    "required": ["source", "destination"]
+                    "required": ["path"]
                 }),
-            },
-            McpTool {
-                name: "search_files".to_string(),
-                description: "Search for files matching a glob pattern.".to_string(),
+                output_schema: None,
+            });
+        }
+        if self.config.allow_chmod {
+            tools.push(McpTool {
+                name: "set_permissions".to_string(),
+                description: "Set the permission bits of a file or directory, e.g. mode '755' to make a script \
+                    executable.".to_string(),
                 input_schema: serde_json::json!({
                     "type": "object",
                     "properties": {
                         "path": {
                             "type": "string",
-                            "description": "Directory to search in"
+                            "description": "Path to the file or directory"
                         },
-                        "pattern": {
+                        "mode": {
                             "type": "string",
-                            "description": "Glob pattern (e.g., '*.rs', '**/*.txt')"
+                            "description": "Octal permissions, e.g. '755' or '0644' (setuid, setgid and sticky bits \
+                                aren't allowed)"
                         },
-                        "excludePatterns": {
-                            "type": "array",
-                            "items": { "type": "string" },
-                            "description": "Patterns to exclude",
-                            "default": []
-                        }
-                    },
-                    "required": ["path", "pattern"]
-                }),
-            },
-            McpTool {
-                name: "get_file_info".to_string(),
-                description: "Get detailed metadata about a file or directory.".to_string(),
-                input_schema: serde_json::json!({
-                    "type": "object",
-                    "properties": {
-                        "path": {
-                            "type": "string",
-                            "description": "Path to the file or directory"
+                        "recursive": {
+                            "type": "boolean",
+                            "description": "Also set the mode of everything in the directory (symlinks are skipped)",
+                            "default": false
                         }
                     },
-                    "required": ["path"]
-                }),
-            },
-            McpTool {
-                name: "list_allowed_directories".to_string(),
-                description: "List directories this server is allowed to access.".to_string(),
-                input_schema: serde_json::json!({
-                    "type": "object",
-                    "properties": {}
+                    "required": ["path", "mode"]
                 }),
-            },
-        ]
+                output_schema: None,
+            });
+        }
+        tools
     }
 
     fn call_tool(&self, name: &str, arguments: &serde_json::Value) -> Result<serde_json::Value> {
+        self.call_tool_with_context(name, arguments, &RequestContext::default())
+    }
+
+    fn call_tool_with_context(
+        &self,
+        name: &str,
+        arguments: &serde_json::Value,
+        ctx: &RequestContext,
+    ) -> Result<serde_json::Value> {
+        let session = ctx.session_id.as_deref().unwrap_or(STDIO_SESSION);
+        if let Err(e) = self.check_roots(session, arguments).and_then(|_| self.check_writable(name, arguments)) {
+            return Ok(error_content(&e.to_string()));
+        }
         match name {
             "read_file" => {
                 let path = arguments.get("path")
                     .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow!("Missing 'path' argument"))?;
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'path' argument"))?;
                 let head = arguments.get("head").and_then(|v| v.as_u64()).map(|n| n as usize);
                 let tail = arguments.get("tail").and_then(|v| v.as_u64()).map(|n| n as usize);
                 let offset = arguments.get("offset").and_then(|v| v.as_u64()).map(|n| n as usize);
                 let limit = arguments.get("limit").and_then(|v| v.as_u64()).map(|n| n as usize);
+                let line_range = match arguments.get("lineRange") {
+                    Some(range) => Some(parse_line_range(range).ok_or_else(|| {
+                        RpcError::invalid_params("'lineRange' must be [first, last] line numbers, counting from 1")
+                    })?),
+                    None => None,
+                };
+                let byte_offset = arguments.get("byteOffset").and_then(|v| v.as_u64());
+                let length = arguments.get("length").and_then(|v| v.as_u64()).map(|n| n as usize);
+                let by_lines = head.is_some() || tail.is_some() || offset.is_some() || limit.is_some();
+
+                if byte_offset.is_some() || length.is_some() {
+                    if by_lines || line_range.is_some() {
+                        return Ok(error_content("Cannot combine byteOffset/length with line parameters"));
+                    }
+                    let length = length.unwrap_or(MAX_CHUNK_READ).min(MAX_CHUNK_READ);
+                    return match self.read_file_chunk(path, byte_offset.unwrap_or(0), length) {
+                        Ok(chunk) => Ok(Content::default().text(&chunk.text).text(&chunk.to_string()).build()),
+                        Err(e) => Ok(error_content(&e.to_string())),
+                    };
+                }
+                let (offset, limit) = match line_range {
+                    Some(_) if by_lines => {
+                        return Ok(error_content("Cannot combine lineRange with head, tail, offset or limit"));
+                    }
+                    Some((first, last)) => (Some(first), Some(last - first + 1)),
+                    None => (offset, limit),
+                };
 
+                if head.is_none() && tail.is_none() && offset.is_none() && limit.is_none() {
+                    return match self.read_file_content(path) {
+                        Ok(content) => Ok(content),
+                        Err(e) => Ok(error_content(&e.to_string())),
+                    };
+                }
                 match self.read_file(path, head, tail, offset, limit) {
                     Ok(content) => Ok(text_content(&content)),
                     Err(e) => Ok(error_content(&e.to_string())),
                 }
             }
+            "read_file_binary" => {
+                let path = arguments.get("path")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'path' argument"))?;
+
+                match self.read_file_binary(path) {
+                    Ok(file) => {
+                        let text = format!("{} ({} bytes), base64:\n{}", file.mime_type, file.size, file.data);
+                        Ok(text_with_structured(&text, serde_json::to_value(&file)?))
+                    }
+                    Err(e) => Ok(error_content(&e.to_string())),
+                }
+            }
             "read_multiple_files" => {
                 let paths: Vec<String> = arguments.get("paths")
                     .and_then(|v| v.as_array())
-                    .ok_or_else(|| anyhow!("Missing 'paths' argument"))?
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'paths' argument"))?
                     .iter()
                     .filter_map(|v| v.as_str().map(String::from))
                     .collect();
@@ -1104,12 +4019,33 @@ impl McpServer for FilesystemServer {
             "write_file" => {
                 let path = arguments.get("path")
                     .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow!("Missing 'path' argument"))?;
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'path' argument"))?;
                 let content = arguments.get("content")
                     .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow!("Missing 'content' argument"))?;
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'content' argument"))?;
+                let written = match arguments.get("mode").and_then(|v| v.as_str()).unwrap_or("overwrite") {
+                    "overwrite" => self.write_file(path, content),
+                    "append" => self.append_file(path, content),
+                    other => {
+                        let message = format!("Invalid mode '{}': expected 'overwrite' or 'append'", other);
+                        return Err(RpcError::invalid_params(message).into());
+                    }
+                };
+
+                match written {
+                    Ok(msg) => Ok(text_content(&msg)),
+                    Err(e) => Ok(error_content(&e.to_string())),
+                }
+            }
+            "append_file" => {
+                let path = arguments.get("path")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'path' argument"))?;
+                let content = arguments.get("content")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'content' argument"))?;
 
-                match self.write_file(path, content) {
+                match self.append_file(path, content) {
                     Ok(msg) => Ok(text_content(&msg)),
                     Err(e) => Ok(error_content(&e.to_string())),
                 }
@@ -1117,21 +4053,38 @@ impl McpServer for FilesystemServer {
             "edit_file" => {
                 let path = arguments.get("path")
                     .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow!("Missing 'path' argument"))?;
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'path' argument"))?;
                 let edits: Vec<EditOperation> = arguments.get("edits")
-                    .ok_or_else(|| anyhow!("Missing 'edits' argument"))
-                    .and_then(|v| serde_json::from_value(v.clone()).map_err(|e| anyhow!("Invalid edits: {}", e)))?;
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'edits' argument"))
+                    .and_then(|v| {
+                        serde_json::from_value(v.clone())
+                            .map_err(|e| RpcError::invalid_params(format!("Invalid edits: {}", e)))
+                    })?;
                 let dry_run = arguments.get("dryRun").and_then(|v| v.as_bool()).unwrap_or(false);
+                let context = arguments.get("contextLines").and_then(|v| v.as_u64()).unwrap_or(DIFF_CONTEXT as u64);
 
-                match self.edit_file(path, edits, dry_run) {
+                match self.edit_file(path, edits, dry_run, context as usize) {
                     Ok(diff) => Ok(text_content(&diff)),
                     Err(e) => Ok(error_content(&e.to_string())),
                 }
             }
+            "apply_patch" => {
+                let diff = arguments.get("patch")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'patch' argument"))?;
+                let directory = arguments.get("directory").and_then(|v| v.as_str());
+                let dry_run = arguments.get("dryRun").and_then(|v| v.as_bool()).unwrap_or(false);
+
+                match self.apply_patch(diff, directory, dry_run, session) {
+                    Ok(outcome) if !outcome.applied => Ok(error_content(&outcome.to_string())),
+                    Ok(outcome) => Ok(text_with_structured(&outcome.to_string(), serde_json::to_value(&outcome)?)),
+                    Err(e) => Ok(error_content(&e.to_string())),
+                }
+            }
             "create_directory" => {
                 let path = arguments.get("path")
                     .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow!("Missing 'path' argument"))?;
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'path' argument"))?;
 
                 match self.create_directory(path) {
                     Ok(msg) => Ok(text_content(&msg)),
@@ -1141,17 +4094,17 @@ impl McpServer for FilesystemServer {
             "list_directory" => {
                 let path = arguments.get("path")
                     .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow!("Missing 'path' argument"))?;
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'path' argument"))?;
 
-                match self.list_directory(path) {
-                    Ok(content) => Ok(text_content(&content)),
+                match self.list_directory(path, Page::from_arguments(arguments)) {
+                    Ok(listing) => Ok(text_with_structured(&listing.to_string(), serde_json::to_value(&listing)?)),
                     Err(e) => Ok(error_content(&e.to_string())),
                 }
             }
             "list_directory_with_sizes" => {
                 let path = arguments.get("path")
                     .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow!("Missing 'path' argument"))?;
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'path' argument"))?;
                 let sort_by = arguments.get("sortBy")
                     .and_then(|v| v.as_str())
                     .unwrap_or("name");
@@ -1164,77 +4117,498 @@ impl McpServer for FilesystemServer {
             "directory_tree" => {
                 let path = arguments.get("path")
                     .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow!("Missing 'path' argument"))?;
-                let exclude_patterns: Vec<String> = arguments.get("excludePatterns")
-                    .and_then(|v| v.as_array())
-                    .map(|arr| arr.iter().filter_map(|v| v.as_str().map(String::from)).collect())
-                    .unwrap_or_default();
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'path' argument"))?;
+                let exclude_patterns = glob_argument(arguments, "excludePatterns", false)?;
 
-                match self.directory_tree(path, &exclude_patterns) {
-                    Ok(content) => Ok(text_content(&content)),
+                let max_depth = arguments.get("maxDepth").and_then(|v| v.as_u64()).map(|n| n.max(1) as usize);
+                let page = Page::from_arguments(arguments);
+
+                match self.directory_tree(path, &exclude_patterns, self.respect_gitignore(arguments), max_depth, page) {
+                    Ok((tree, false)) => Ok(text_content(&tree)),
+                    Ok((tree, true)) => Ok(Content::default().text(&tree).text(&page.more()).build()),
+                    Err(e) => Ok(error_content(&e.to_string())),
+                }
+            }
+            "create_symlink" => {
+                let target = arguments.get("target")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'target' argument"))?;
+                let path = arguments.get("path")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'path' argument"))?;
+
+                match self.create_symlink(target, path) {
+                    Ok(msg) => Ok(text_content(&msg)),
+                    Err(e) => Ok(error_content(&e.to_string())),
+                }
+            }
+            "read_link" => {
+                let path = arguments.get("path")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'path' argument"))?;
+
+                match self.read_link(path) {
+                    Ok(msg) => Ok(text_content(&msg)),
                     Err(e) => Ok(error_content(&e.to_string())),
                 }
             }
             "move_file" => {
                 let source = arguments.get("source")
                     .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow!("Missing 'source' argument"))?;
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'source' argument"))?;
                 let destination = arguments.get("destination")
                     .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow!("Missing 'destination' argument"))?;
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'destination' argument"))?;
 
                 match self.move_file(source, destination) {
                     Ok(msg) => Ok(text_content(&msg)),
                     Err(e) => Ok(error_content(&e.to_string())),
                 }
             }
+            "copy_file" | "copy_directory" => {
+                let source = arguments.get("source")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'source' argument"))?;
+                let destination = arguments.get("destination")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'destination' argument"))?;
+                let overwrite = arguments.get("overwrite").and_then(|v| v.as_bool()).unwrap_or(false);
+
+                let copied = if name == "copy_file" {
+                    self.copy_file(source, destination, overwrite)
+                } else {
+                    self.copy_directory(source, destination, overwrite)
+                };
+                match copied {
+                    Ok(msg) => Ok(text_content(&msg)),
+                    Err(e) => Ok(error_content(&format!("{:#}", e))),
+                }
+            }
+            "create_archive" | "extract_archive" => {
+                let source = arguments.get("source")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'source' argument"))?;
+                let destination = arguments.get("destination")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'destination' argument"))?;
+                let format = arguments.get("format").and_then(|v| v.as_str());
+                if format.is_some_and(|format| ArchiveFormat::parse(format).is_none()) {
+                    return Err(RpcError::invalid_params("Invalid format: expected 'zip', 'tar.gz' or 'tgz'").into());
+                }
+                let overwrite = arguments.get("overwrite").and_then(|v| v.as_bool()).unwrap_or(false);
+
+                let done = if name == "create_archive" {
+                    self.create_archive(source, destination, format, overwrite)
+                } else {
+                    self.extract_archive(source, destination, format, overwrite)
+                };
+                match done {
+                    Ok(msg) => Ok(text_content(&msg)),
+                    Err(e) => Ok(error_content(&format!("{:#}", e))),
+                }
+            }
             "search_files" => {
                 let path = arguments.get("path")
                     .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow!("Missing 'path' argument"))?;
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'path' argument"))?;
                 let pattern = arguments.get("pattern")
                     .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow!("Missing 'pattern' argument"))?;
-                let exclude_patterns: Vec<String> = arguments.get("excludePatterns")
-                    .and_then(|v| v.as_array())
-                    .map(|arr| arr.iter().filter_map(|v| v.as_str().map(String::from)).collect())
-                    .unwrap_or_default();
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'pattern' argument"))?;
+                let ignore_case = arguments.get("ignoreCase").and_then(|v| v.as_bool()).unwrap_or(false);
+                let pattern = Globs::new(&[pattern.to_string()], ignore_case)
+                    .map_err(|e| RpcError::invalid_params(e.to_string()))?;
+                let exclude_patterns = glob_argument(arguments, "excludePatterns", ignore_case)?;
 
-                match self.search_files(path, pattern, &exclude_patterns) {
+                match self.search_files(path, &pattern, &exclude_patterns, self.respect_gitignore(arguments)) {
                     Ok(content) => Ok(text_content(&content)),
                     Err(e) => Ok(error_content(&e.to_string())),
                 }
             }
+            "hash_file" => {
+                let paths: Vec<String> = match (arguments.get("path"), arguments.get("paths")) {
+                    (_, Some(paths)) => paths
+                        .as_array()
+                        .ok_or_else(|| RpcError::invalid_params("'paths' must be an array"))?
+                        .iter()
+                        .filter_map(|v| v.as_str().map(String::from))
+                        .collect(),
+                    (Some(path), None) => path.as_str().map(String::from).into_iter().collect(),
+                    (None, None) => return Err(RpcError::invalid_params("Missing 'path' or 'paths' argument").into()),
+                };
+                let name = arguments.get("algorithm").and_then(|v| v.as_str()).unwrap_or("sha256");
+                let algorithm = HashAlgorithm::parse(name).ok_or_else(|| {
+                    RpcError::invalid_params(format!("Unknown algorithm '{}': expected sha256, md5 or blake3", name))
+                })?;
+
+                let files = self.hash_files(&paths, algorithm);
+                let lines: Vec<String> = files
+                    .iter()
+                    .map(|file| match (&file.hash, &file.error) {
+                        (Some(hash), _) => format!("{}  {}", hash, file.path),
+                        (None, error) => format!("{}: Error - {}", file.path, error.as_deref().unwrap_or_default()),
+                    })
+                    .collect();
+                let structured = serde_json::json!({ "algorithm": name, "files": files });
+                Ok(text_with_structured(&lines.join("\n"), structured))
+            }
+            "search_content" => {
+                let path = arguments.get("path")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'path' argument"))?;
+                let pattern = arguments.get("pattern")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'pattern' argument"))?;
+                let flag = |name: &str| arguments.get(name).and_then(|v| v.as_bool()).unwrap_or(false);
+                let options = SearchOptions {
+                    include: glob_argument(arguments, "includePatterns", false)?,
+                    exclude: glob_argument(arguments, "excludePatterns", false)?,
+                    context: arguments.get("contextLines").and_then(|v| v.as_u64()).unwrap_or(0) as usize,
+                    max_results: arguments.get("maxResults").and_then(|v| v.as_u64()).unwrap_or(100).max(1) as usize,
+                    gitignore: self.respect_gitignore(arguments),
+                };
+                let pattern = if flag("literal") { regex::escape(pattern) } else { pattern.to_string() };
+                let regex = match regex::RegexBuilder::new(&pattern).case_insensitive(flag("ignoreCase")).build() {
+                    Ok(regex) => regex,
+                    Err(e) => return Err(RpcError::invalid_params(format!("Invalid pattern: {}", e)).into()),
+                };
+
+                match self.search_content(path, &regex, &options) {
+                    Ok(found) => Ok(text_with_structured(&found.to_string(), serde_json::to_value(&found)?)),
+                    Err(e) => Ok(error_content(&e.to_string())),
+                }
+            }
+            "disk_usage" => {
+                let path = arguments.get("path")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'path' argument"))?;
+                let exclude_patterns = glob_argument(arguments, "excludePatterns", false)?;
+                let top = arguments.get("top").and_then(|v| v.as_u64()).unwrap_or(10) as usize;
+
+                match self.disk_usage(path, &exclude_patterns, top) {
+                    Ok(usage) => Ok(text_with_structured(&usage.to_string(), serde_json::to_value(&usage)?)),
+                    Err(e) => Ok(error_content(&e.to_string())),
+                }
+            }
+            "find_duplicates" => {
+                let path = arguments.get("path")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'path' argument"))?;
+                let pattern = arguments.get("pattern").and_then(|v| v.as_str()).unwrap_or("*");
+                let pattern = Globs::new(&[pattern.to_string()], false)
+                    .map_err(|e| RpcError::invalid_params(e.to_string()))?;
+                let name = arguments.get("algorithm").and_then(|v| v.as_str()).unwrap_or("sha256");
+                let algorithm = HashAlgorithm::parse(name).ok_or_else(|| {
+                    RpcError::invalid_params(format!("Unknown algorithm '{}': expected sha256, md5 or blake3", name))
+                })?;
+                let options = DuplicateOptions {
+                    pattern,
+                    exclude: glob_argument(arguments, "excludePatterns", false)?,
+                    gitignore: self.respect_gitignore(arguments),
+                    min_size: arguments.get("minSize").and_then(|v| v.as_u64()).unwrap_or(1),
+                    algorithm,
+                    max_groups: arguments.get("maxGroups").and_then(|v| v.as_u64()).unwrap_or(100) as usize,
+                };
+
+                match self.find_duplicates(path, &options) {
+                    Ok(found) => Ok(text_with_structured(&found.to_string(), serde_json::to_value(&found)?)),
+                    Err(e) => Ok(error_content(&e.to_string())),
+                }
+            }
+            "stats" => {
+                let path = arguments.get("path")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'path' argument"))?;
+                let pattern = arguments.get("pattern").and_then(|v| v.as_str()).unwrap_or("*");
+                let ignore_case = arguments.get("ignoreCase").and_then(|v| v.as_bool()).unwrap_or(false);
+                let pattern = Globs::new(&[pattern.to_string()], ignore_case)
+                    .map_err(|e| RpcError::invalid_params(e.to_string()))?;
+                let exclude_patterns = glob_argument(arguments, "excludePatterns", ignore_case)?;
+                let max_files = arguments.get("maxFiles").and_then(|v| v.as_u64());
+                let max_files = max_files.map_or(DEFAULT_MAX_ENTRIES, |n| n as usize);
+                let gitignore = self.respect_gitignore(arguments);
+
+                match self.stats(path, &pattern, &exclude_patterns, gitignore, max_files) {
+                    Ok(stats) => Ok(text_with_structured(&stats.to_string(), serde_json::to_value(&stats)?)),
+                    Err(e) => Ok(error_content(&e.to_string())),
+                }
+            }
             "get_file_info" => {
                 let path = arguments.get("path")
                     .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow!("Missing 'path' argument"))?;
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'path' argument"))?;
+
+                match self.get_file_info(path) {
+                    Ok(info) => Ok(text_with_structured(&info.to_string(), serde_json::to_value(&info)?)),
+                    Err(e) => Ok(error_content(&e.to_string())),
+                }
+            }
+            "list_allowed_directories" => {
+                Ok(text_content(&self.list_allowed_directories(session)))
+            }
+            "watch_path" => {
+                let path = arguments.get("path")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'path' argument"))?;
+
+                match self.watch_path(path, ctx.session_id.as_deref()) {
+                    Ok(watched) => Ok(text_content(&format!("Watching {}", watched.display()))),
+                    Err(e) => Ok(error_content(&e.to_string())),
+                }
+            }
+            "poll_changes" => {
+                let path = arguments.get("path").and_then(|v| v.as_str());
+                let wait = arguments.get("wait").and_then(|v| v.as_u64()).unwrap_or(0).min(MAX_POLL_WAIT);
+
+                match self.poll_changes(path, ctx.session_id.as_deref(), Duration::from_secs(wait)) {
+                    Ok(changes) => Ok(text_with_structured(&changes.to_string(), serde_json::to_value(&changes)?)),
+                    Err(e) => Ok(error_content(&e.to_string())),
+                }
+            }
+            "unwatch_path" => {
+                let path = arguments.get("path")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'path' argument"))?;
+
+                match self.unwatch_path(path, ctx.session_id.as_deref()) {
+                    Ok(unwatched) => Ok(text_content(&format!("Stopped watching {}", unwatched.display()))),
+                    Err(e) => Ok(error_content(&e.to_string())),
+                }
+            }
+            "create_temp_dir" => {
+                let prefix = arguments.get("prefix").and_then(|v| v.as_str());
+
+                match self.create_temp_dir(prefix, session) {
+                    Ok(path) => Ok(text_content(&path)),
+                    Err(e) => Ok(error_content(&e.to_string())),
+                }
+            }
+            "cleanup_temp_dir" => {
+                let path = arguments.get("path")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'path' argument"))?;
+
+                match self.cleanup_temp_dir(path, session) {
+                    Ok(msg) => Ok(text_content(&msg)),
+                    Err(e) => Ok(error_content(&e.to_string())),
+                }
+            }
+            "delete_file" | "delete_directory" if !self.config.allow_delete => {
+                Ok(error_content("Deleting is disabled; start the server with --allow-delete"))
+            }
+            "delete_file" => {
+                let path = arguments.get("path")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'path' argument"))?;
+
+                match self.delete_file(path) {
+                    Ok(msg) => Ok(text_content(&msg)),
+                    Err(e) => Ok(error_content(&e.to_string())),
+                }
+            }
+            "delete_directory" => {
+                let path = arguments.get("path")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'path' argument"))?;
+                let recursive = arguments.get("recursive").and_then(|v| v.as_bool()).unwrap_or(false);
+
+                match self.delete_directory(path, recursive) {
+                    Ok(msg) => Ok(text_content(&msg)),
+                    Err(e) => Ok(error_content(&e.to_string())),
+                }
+            }
+            "set_permissions" if !self.config.allow_chmod => {
+                Ok(error_content("Changing permissions is disabled; start the server with --allow-chmod"))
+            }
+            "set_permissions" => {
+                let path = arguments.get("path")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'path' argument"))?;
+                let mode = arguments.get("mode")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing 'mode' argument"))?;
+                let mode = parse_mode(mode).ok_or_else(|| {
+                    RpcError::invalid_params(format!("Invalid mode '{}': expected octal permissions such as 755", mode))
+                })?;
+                let recursive = arguments.get("recursive").and_then(|v| v.as_bool()).unwrap_or(false);
 
-                match self.get_file_info(path) {
-                    Ok(content) => Ok(text_content(&content)),
+                match self.set_permissions(path, mode, recursive) {
+                    Ok(msg) => Ok(text_content(&msg)),
                     Err(e) => Ok(error_content(&e.to_string())),
                 }
             }
-            "list_allowed_directories" => {
-                Ok(text_content(&self.list_allowed_directories()))
-            }
             _ => Ok(error_content(&format!("Unknown tool: {}", name))),
         }
     }
+
+    fn has_resources(&self) -> bool {
+        true
+    }
+
+    /// The directories the session may access, then the files in them
+    fn resources(&self, ctx: &RequestContext) -> Vec<McpResource> {
+        let session_dirs = self.session_dirs(ctx.session_id.as_deref().unwrap_or(STDIO_SESSION));
+        let mut resources: Vec<McpResource> = session_dirs
+            .iter()
+            .map(|dir| McpResource {
+                uri: file_uri(dir),
+                name: dir.to_string_lossy().to_string(),
+                description: Some("Allowed directory".to_string()),
+                mime_type: None,
+            })
+            .collect();
+        let dirs = resources.len();
+        for dir in &session_dirs {
+            self.collect_file_resources(dir, dir, &mut resources);
+        }
+        if resources.len() - dirs >= MAX_RESOURCES {
+            self.log(&format!("Listing only the first {} files as resources", MAX_RESOURCES));
+        }
+        resources
+    }
+
+    /// A directory reads as its listing; a file as text, or base64 if it isn't UTF-8
+    fn read_resource(&self, uri: &str, ctx: &RequestContext) -> Result<serde_json::Value> {
+        let path = self.resource_path(uri, ctx)?;
+        if path.is_dir() {
+            let listing = self.list_directory(&path.to_string_lossy(), Page::all())?;
+            return Ok(resource_text(uri, "text/plain", &listing.to_string()));
+        }
+        let size = fs::metadata(&path).with_context(|| format!("Failed to read file: {}", path.display()))?.len();
+        if size > MAX_BINARY_READ {
+            return Err(anyhow!(
+                "{} is {}, more than resources/read returns ({}); read it in chunks with read_file",
+                path.display(),
+                format_size(size),
+                format_size(MAX_BINARY_READ)
+            ));
+        }
+        let bytes = fs::read(&path).with_context(|| format!("Failed to read file: {}", path.display()))?;
+        Ok(serde_json::json!({ "contents": [resource_contents(uri, mime_type(&path), bytes)] }))
+    }
+
+    fn has_subscriptions(&self) -> bool {
+        true
+    }
+
+    fn subscribe(&self, uri: &str, ctx: &RequestContext) -> Result<()> {
+        let path = self.resource_path(uri, ctx)?;
+        if !path.exists() {
+            return Err(anyhow!("Unknown resource: {}", uri));
+        }
+        self.log(&format!("Watching {} for {}", path.display(), ctx.session_id.as_deref().unwrap_or(STDIO_SESSION)));
+        self.subscriptions.subscribe(&path, uri, ctx.session_id.as_deref())
+    }
+
+    fn unsubscribe(&self, uri: &str, ctx: &RequestContext) -> Result<()> {
+        let path = self.resource_path(uri, ctx)?;
+        self.subscriptions.unsubscribe(&path, ctx.session_id.as_deref());
+        Ok(())
+    }
+
+    fn has_completions(&self) -> bool {
+        true
+    }
+
+    /// Path arguments of any tool, within the directories the session may access
+    fn complete(
+        &self,
+        reference: &CompletionRef,
+        argument: &str,
+        value: &str,
+        ctx: &RequestContext,
+    ) -> Result<Vec<String>> {
+        if !matches!(reference, CompletionRef::Tool { .. }) || !PATH_ARGUMENTS.contains(&argument) {
+            return Ok(vec![]);
+        }
+        let session = ctx.session_id.as_deref().unwrap_or(STDIO_SESSION);
+        Ok(complete_path(value, &self.session_dirs(session), |path| self.config.excludes(path)))
+    }
+
+    fn on_initialize(&self, params: &serde_json::Value, ctx: &RequestContext) {
+        if self.config.client_roots && params.pointer("/capabilities/roots").is_some() {
+            let session = ctx.session_id.as_deref().unwrap_or(STDIO_SESSION).to_string();
+            self.roots.lock().unwrap().insert(session, ClientRoots { supported: true, dirs: None });
+        }
+    }
+
+    /// Ask for the client's roots once it is initialized, and again whenever they change
+    fn on_notification(&self, method: &str, _params: &serde_json::Value, ctx: &RequestContext) {
+        if !matches!(method, "notifications/initialized" | "notifications/roots/list_changed") {
+            return;
+        }
+        let session = ctx.session_id.as_deref().unwrap_or(STDIO_SESSION);
+        if self.roots.lock().unwrap().get(session).is_some_and(|roots| roots.supported) {
+            self.request_roots(ctx.session_id.as_deref());
+        }
+    }
+
+    fn on_response(&self, id: &serde_json::Value, response: ClientAnswer, _ctx: &RequestContext) {
+        let Some(session) = id.as_str().and_then(|id| self.roots_requests.lock().unwrap().remove(id)) else {
+            return;
+        };
+        let result = match response {
+            Ok(result) => result,
+            Err(error) => {
+                self.log_at(LogLevel::Warning, &format!("Client couldn't list its roots: {}", error));
+                return;
+            }
+        };
+        // Roots that aren't local directories can't match any path
+        let dirs: Vec<PathBuf> = result
+            .get("roots")
+            .and_then(|roots| roots.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|root| root.get("uri").and_then(|uri| uri.as_str()))
+            .filter_map(|uri| uri_path(uri).ok())
+            .map(|path| fs::canonicalize(&path).unwrap_or_else(|_| PathBuf::from(path)))
+            .collect();
+        self.log(&format!("Client roots of session {}: {:?}", session, dirs));
+        if let Some(roots) = self.roots.lock().unwrap().get_mut(&session) {
+            roots.dirs = Some(dirs);
+        }
+    }
+
+    fn on_session_end(&self, session_id: Option<&str>) {
+        let session = session_id.unwrap_or(STDIO_SESSION);
+        self.roots.lock().unwrap().remove(session);
+        self.roots_requests.lock().unwrap().retain(|_, requested| requested != session);
+        self.cleanup_session_temp_dirs(session);
+        self.subscriptions.end_session(session_id);
+        self.watches.end_session(session_id);
+    }
+}
+
+impl Drop for FilesystemServer {
+    fn drop(&mut self) {
+        let sessions: Vec<String> = self.temp_dirs.lock().unwrap().keys().cloned().collect();
+        for session in sessions {
+            self.cleanup_session_temp_dirs(&session);
+        }
+    }
 }
 
 /// Run the filesystem MCP server
-pub fn run_filesystem_server(config: FilesystemServerConfig) -> Result<()> {
+pub fn run_filesystem_server(
+    config: FilesystemServerConfig,
+    recorder: Option<&Recorder>,
+    audit: Option<AuditLog>,
+    strict: bool,
+) -> Result<()> {
     if config.verbose {
         eprintln!("[mcpz] Filesystem server configuration:");
         eprintln!("[mcpz]   Allowed directories:");
         for dir in &config.allowed_directories {
             eprintln!("[mcpz]     - {}", dir.display());
         }
+        eprintln!("[mcpz]   Scratch root: {}", config.scratch_root().display());
+        if config.client_roots {
+            eprintln!("[mcpz]   Client roots: narrow access");
+        }
     }
 
     let server = FilesystemServer::new(config);
-    server.run()
+    Strict::new(Audited::new(server, audit), strict).run(recorder)
 }
 
 #[cfg(test)]
@@ -1263,24 +4637,6 @@ mod tests {
         assert_eq!(format_size(1073741824), "1.00 GB");
     }
 
-    #[test]
-    fn test_matches_glob() {
-        // Simple patterns
-        assert!(matches_glob("*.rs", "main.rs"));
-        assert!(matches_glob("*.rs", "lib.rs"));
-        assert!(!matches_glob("*.rs", "main.txt"));
-
-        // ** patterns
-        assert!(matches_glob("**/*.rs", "src/main.rs"));
-        assert!(matches_glob("**/*.rs", "src/lib/mod.rs"));
-        assert!(matches_glob("**/test.rs", "test.rs"));
-        assert!(matches_glob("**/test.rs", "src/test.rs"));
-
-        // Mixed patterns
-        assert!(matches_glob("src/*.rs", "src/main.rs"));
-        assert!(!matches_glob("src/*.rs", "lib/main.rs"));
-    }
-
     #[test]
     fn test_read_file() {
         let (server, temp_dir) = create_test_server();
@@ -1472,6 +4828,107 @@ mod tests {
         assert_eq!(content, "Hello, World!");
     }
 
+    #[test]
+    fn test_read_file_chunks() {
+        let (server, temp_dir) = create_test_server();
+        let path = temp_dir.path().join("app.log");
+        fs::write(&path, "line 1\nline 2 héllo\nline 3\n").unwrap();
+        let read = |arguments: serde_json::Value| {
+            let result = server.call_tool("read_file", &arguments).unwrap();
+            let texts = result["content"].as_array().unwrap().iter();
+            texts.map(|item| item["text"].as_str().unwrap().to_string()).collect::<Vec<_>>()
+        };
+
+        let chunk = read(serde_json::json!({"path": path, "byteOffset": 0, "length": 7}));
+        assert_eq!(chunk, ["line 1\n", "[Bytes 0-7 of 28; next byteOffset: 7]"]);
+        // "é" is bytes 15-16: a chunk ending inside it leaves it for the next, and one
+        // starting inside it skips it
+        let chunk = read(serde_json::json!({"path": path, "byteOffset": 7, "length": 9}));
+        assert_eq!(chunk, ["line 2 h", "[Bytes 7-15 of 28; next byteOffset: 15]"]);
+        let chunk = read(serde_json::json!({"path": path, "byteOffset": 16, "length": 100}));
+        assert_eq!(chunk, ["llo\nline 3\n", "[Bytes 17-28 of 28; end of file]"]);
+
+        assert_eq!(read(serde_json::json!({"path": path, "lineRange": [2, 3]})), ["line 2 héllo\nline 3"]);
+        let result = server.call_tool("read_file", &serde_json::json!({"path": path, "lineRange": [3, 2]}));
+        assert!(result.unwrap_err().to_string().contains("lineRange"));
+        let mixed = read(serde_json::json!({"path": path, "lineRange": [1, 2], "head": 1}));
+        assert!(mixed[0].contains("Cannot combine lineRange"));
+        assert!(read(serde_json::json!({"path": path, "byteOffset": 0, "tail": 1}))[0].contains("Cannot combine"));
+    }
+
+    #[test]
+    fn test_read_file_binary() {
+        let (server, temp_dir) = create_test_server();
+        fs::write(temp_dir.path().join("photo"), b"\xff\xd8\xff\xe0").unwrap();
+        fs::write(temp_dir.path().join("archive.pdf"), b"%PDF-1.7").unwrap();
+        fs::write(temp_dir.path().join("data"), [0x00, 0xff]).unwrap();
+        let read = |file: &str| {
+            let result = server.call_tool("read_file_binary", &serde_json::json!({"path": temp_dir.path().join(file)}));
+            result.unwrap()["structuredContent"].clone()
+        };
+
+        assert_eq!(read("photo"), serde_json::json!({"mimeType": "image/jpeg", "size": 4, "data": "/9j/4A=="}));
+        assert_eq!(read("archive.pdf")["mimeType"], "application/pdf");
+        assert_eq!(read("data")["mimeType"], "application/octet-stream");
+        // Sniffed images are images to read_file too
+        let image = server.call_tool("read_file", &serde_json::json!({"path": temp_dir.path().join("photo")}));
+        assert_eq!(image.unwrap()["content"][0]["mimeType"], "image/jpeg");
+
+        let big = File::create(temp_dir.path().join("big.bin")).unwrap();
+        big.set_len(MAX_BINARY_READ + 1).unwrap();
+        let arguments = serde_json::json!({"path": temp_dir.path().join("big.bin")});
+        let refused = server.call_tool("read_file_binary", &arguments);
+        assert!(refused.unwrap()["content"][0]["text"].as_str().unwrap().contains("more than read_file_binary"));
+        let refused = server.call_tool("read_file", &arguments).unwrap();
+        let text = refused["content"][0]["text"].as_str().unwrap();
+        assert!(refused["isError"] == true && text.contains("read it in chunks with byteOffset"), "{}", text);
+        let chunk = server.call_tool("read_file", &serde_json::json!({"path": arguments["path"], "byteOffset": 0,
+            "length": 4})).unwrap();
+        assert!(chunk.get("isError").is_none(), "{}", chunk);
+    }
+
+    #[test]
+    fn test_hash_file() {
+        let (server, temp_dir) = create_test_server();
+        let file = temp_dir.path().join("download.tar");
+        fs::write(&file, "abc").unwrap();
+        let hash = |arguments: serde_json::Value| server.call_tool("hash_file", &arguments);
+
+        let sha256 = hash(serde_json::json!({"path": file})).unwrap();
+        let digest = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        assert_eq!(sha256["structuredContent"]["files"][0]["hash"], digest);
+        assert_eq!(sha256["structuredContent"]["files"][0]["size"], 3);
+        assert_eq!(sha256["content"][0]["text"], format!("{}  {}", digest, file.display()));
+        let md5 = hash(serde_json::json!({"paths": [file, temp_dir.path()], "algorithm": "md5"})).unwrap();
+        assert_eq!(md5["structuredContent"]["files"][0]["hash"], "900150983cd24fb0d6963f7d28e17f72");
+        assert!(md5["structuredContent"]["files"][1]["error"].as_str().unwrap().contains("is a directory"));
+        let blake3 = hash(serde_json::json!({"path": file, "algorithm": "blake3"})).unwrap();
+        let digest = "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85";
+        assert_eq!(blake3["structuredContent"]["files"][0]["hash"], digest);
+
+        assert!(hash(serde_json::json!({"path": file, "algorithm": "crc32"})).is_err());
+        assert!(hash(serde_json::json!({})).is_err());
+    }
+
+    #[test]
+    fn test_append_file() {
+        let (server, temp_dir) = create_test_server();
+        let file_path = temp_dir.path().join("build.log");
+
+        let result = server.call_tool("append_file", &serde_json::json!({"path": file_path, "content": "one\n"}));
+        let expected = format!("Successfully appended 4 bytes to {}", file_path.display());
+        assert_eq!(result.unwrap()["content"][0]["text"], expected);
+        let arguments = serde_json::json!({"path": file_path, "content": "two\n", "mode": "append"});
+        server.call_tool("write_file", &arguments).unwrap();
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "one\ntwo\n");
+
+        let arguments = serde_json::json!({"path": file_path, "content": "", "mode": "prepend"});
+        let invalid = server.call_tool("write_file", &arguments).unwrap_err();
+        assert!(invalid.to_string().starts_with("Invalid mode 'prepend'"));
+        let outside = server.call_tool("append_file", &serde_json::json!({"path": "/etc/x", "content": ""})).unwrap();
+        assert_eq!(outside["isError"], true);
+    }
+
     #[test]
     fn test_create_directory() {
         let (server, temp_dir) = create_test_server();
@@ -1492,7 +4949,7 @@ mod tests {
         File::create(temp_dir.path().join("file2.txt")).unwrap();
         fs::create_dir(temp_dir.path().join("subdir")).unwrap();
 
-        let result = server.list_directory(temp_dir.path().to_str().unwrap()).unwrap();
+        let result = server.list_directory(temp_dir.path().to_str().unwrap(), Page::all()).unwrap().to_string();
         assert!(result.contains("[FILE] file1.txt"));
         assert!(result.contains("[FILE] file2.txt"));
         assert!(result.contains("[DIR] subdir"));
@@ -1519,10 +4976,141 @@ mod tests {
         let mut file = File::create(&file_path).unwrap();
         write!(file, "test content").unwrap();
 
-        let result = server.get_file_info(file_path.to_str().unwrap()).unwrap();
+        let result = server.get_file_info(file_path.to_str().unwrap()).unwrap().to_string();
         assert!(result.contains("size: 12"));
         assert!(result.contains("is_file: true"));
         assert!(result.contains("is_directory: false"));
+
+        let result = server.call_tool("get_file_info", &serde_json::json!({"path": file_path})).unwrap();
+        assert!(result["content"][0]["text"].as_str().unwrap().contains("size: 12"));
+        assert_eq!(result["structuredContent"]["size"], 12);
+        assert_eq!(result["structuredContent"]["is_file"], true);
+        assert!(!result["structuredContent"]["owner"].as_str().unwrap().is_empty());
+        assert!(!result["structuredContent"]["group"].as_str().unwrap().is_empty());
+        assert_eq!(result["structuredContent"]["mime_type"], "text/plain");
+        assert_eq!(result["structuredContent"]["lines"], 1);
+
+        fs::write(temp_dir.path().join("data"), b"\x7fELF\0\0").unwrap();
+        let info = server.get_file_info(temp_dir.path().join("data").to_str().unwrap()).unwrap();
+        assert_eq!((info.mime_type.as_deref(), info.lines), (Some("application/x-elf"), None));
+        let info = server.get_file_info(temp_dir.path().to_str().unwrap()).unwrap();
+        assert_eq!((info.mime_type, info.lines), (None, None));
+        // Where the filesystem has user attributes
+        if xattr::set(&file_path, "user.origin", b"https://example.com").is_ok() {
+            let info = server.get_file_info(file_path.to_str().unwrap()).unwrap();
+            assert_eq!(info.xattrs.get("user.origin").map(String::as_str), Some("https://example.com"));
+            assert!(info.to_string().contains("xattr user.origin: https://example.com"));
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let (server, temp_dir) = create_test_server();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("src/bin")).unwrap();
+        fs::write(root.join("src/lib.rs"), "pub mod a;\npub mod b;\n").unwrap();
+        fs::write(root.join("src/bin/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(root.join("src/logo.rs"), b"\x89PNG\r\n\x1a\n\0").unwrap();
+        fs::write(root.join("README.md"), "# Title\n").unwrap();
+
+        let call = |arguments: serde_json::Value| server.call_tool("stats", &arguments).unwrap();
+        let result = call(serde_json::json!({"path": root, "pattern": "*.rs"}));
+        let stats = &result["structuredContent"];
+        assert_eq!(stats["files"][0]["path"], "src/bin/main.rs");
+        let lib = serde_json::json!({"path": "src/lib.rs", "lines": 2, "words": 6, "chars": 22, "bytes": 22});
+        assert_eq!(stats["files"][1], lib);
+        assert_eq!(stats["total"]["lines"], 3);
+        assert_eq!((stats["counted"].as_u64(), stats["skipped"].as_u64()), (Some(2), Some(1)));
+        let text = result["content"][0]["text"].as_str().unwrap();
+        assert!(text.lines().any(|line| line.ends_with(" 3        9       35       35 total (2 files)")), "{}", text);
+
+        let result = call(serde_json::json!({"path": root, "excludePatterns": ["src/**"], "maxFiles": 0}));
+        assert_eq!(result["structuredContent"]["total"]["bytes"], 8);
+        assert_eq!(result["structuredContent"]["truncated"], true);
+        let result = call(serde_json::json!({"path": root.join("README.md")}));
+        assert_eq!(result["structuredContent"]["files"][0]["path"], root.join("README.md").to_string_lossy().as_ref());
+
+        // Links out of the allowed directories aren't counted
+        let outside = TempDir::new().unwrap();
+        fs::write(outside.path().join("secret.rs"), "let token = 1;\n").unwrap();
+        std::os::unix::fs::symlink(outside.path().join("secret.rs"), root.join("src/secret.rs")).unwrap();
+        let result = call(serde_json::json!({"path": root, "pattern": "*.rs"}));
+        assert_eq!(result["structuredContent"]["counted"], 2);
+        assert_eq!(result["structuredContent"]["total"]["lines"], 3);
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let (server, temp_dir) = create_test_server();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("photos/backup")).unwrap();
+        fs::write(root.join("photos/a.jpg"), "same picture").unwrap();
+        fs::write(root.join("photos/backup/a.jpg"), "same picture").unwrap();
+        fs::write(root.join("photos/b.jpg"), "same size!!!").unwrap();
+        fs::write(root.join("notes.txt"), "hi").unwrap();
+        fs::write(root.join("copy.txt"), "hi").unwrap();
+        fs::write(root.join("empty1"), "").unwrap();
+        fs::write(root.join("empty2"), "").unwrap();
+        std::os::unix::fs::symlink(root.join("notes.txt"), root.join("link.txt")).unwrap();
+
+        let call = |arguments: serde_json::Value| server.call_tool("find_duplicates", &arguments).unwrap();
+        let result = call(serde_json::json!({"path": root}));
+        let found = &result["structuredContent"];
+        assert_eq!(found["groups"][0]["paths"], serde_json::json!(["photos/a.jpg", "photos/backup/a.jpg"]));
+        assert_eq!(found["groups"][0]["size"], 12);
+        assert_eq!(found["groups"][1]["paths"], serde_json::json!(["copy.txt", "notes.txt"]));
+        assert_eq!((found["groups"].as_array().unwrap().len(), found["files"].as_u64()), (2, Some(5)));
+        assert_eq!(found["wasted"], 14);
+        let text = result["content"][0]["text"].as_str().unwrap();
+        assert!(text.starts_with("2 groups of duplicates among 5 files"), "{}", text);
+
+        let result = call(serde_json::json!({"path": root, "pattern": "*.jpg", "maxGroups": 0, "algorithm": "blake3"}));
+        assert_eq!(result["structuredContent"]["truncated"], true);
+        assert_eq!(result["structuredContent"]["wasted"], 12);
+        let result = call(serde_json::json!({"path": root, "excludePatterns": ["backup", "*.txt"], "minSize": 0}));
+        assert_eq!(result["structuredContent"]["groups"][0]["paths"], serde_json::json!(["empty1", "empty2"]));
+        assert!(server.call_tool("find_duplicates", &serde_json::json!({"path": root, "algorithm": "crc"})).is_err());
+    }
+
+    #[test]
+    fn test_text_counts() {
+        let count = |text: &str| TextCounts::of(text.as_bytes()).unwrap();
+        assert_eq!(count(""), TextCounts::default());
+        assert_eq!(count("one two\n  three\n"), TextCounts { lines: 2, words: 3, chars: 16, bytes: 16 });
+        assert_eq!(count("caf\u{e9} ol\u{e9}"), TextCounts { lines: 1, words: 2, chars: 8, bytes: 10 });
+        assert!(is_text("caf\u{e9}".as_bytes()) && is_text(&"caf\u{e9}".as_bytes()[..4]));
+        assert!(!is_text(b"a\0b") && !is_text(b"\xff\xfe"));
+    }
+
+    #[test]
+    fn test_disk_usage() {
+        let (server, temp_dir) = create_test_server();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("target/debug/app"), vec![0u8; 5000]).unwrap();
+        fs::write(root.join("target/debug/app.d"), vec![0u8; 1000]).unwrap();
+        fs::write(root.join("src/main.rs"), vec![b'x'; 300]).unwrap();
+        fs::write(root.join("Cargo.toml"), vec![b'x'; 100]).unwrap();
+        std::os::unix::fs::symlink("/etc", root.join("etc")).unwrap();
+
+        let result = server.call_tool("disk_usage", &serde_json::json!({"path": root, "top": 2})).unwrap();
+        let usage = &result["structuredContent"];
+        assert_eq!(usage["size"], 6400);
+        assert_eq!(usage["files"], 4);
+        assert_eq!(usage["directories"], 3);
+        assert_eq!(usage["largest"].as_array().unwrap().len(), 2);
+        assert_eq!(usage["largest"][0]["name"], "target");
+        assert_eq!(usage["largest"][0]["size"], 6000);
+        assert_eq!(usage["largest"][1]["name"], "src");
+        let text = result["content"][0]["text"].as_str().unwrap();
+        assert!(text.contains("Largest:") && text.contains("target/ (2 files)"), "{}", text);
+
+        let arguments = serde_json::json!({"path": root, "excludePatterns": ["*.d", "src"]});
+        let result = server.call_tool("disk_usage", &arguments).unwrap();
+        assert_eq!(result["structuredContent"]["size"], 5100);
+        let result = server.call_tool("disk_usage", &serde_json::json!({"path": root.join("Cargo.toml")})).unwrap();
+        assert!(result["content"][0]["text"].as_str().unwrap().contains("is not a directory"));
     }
 
     #[test]
@@ -1536,10 +5124,447 @@ mod tests {
         fs::create_dir(temp_dir.path().join("src")).unwrap();
         File::create(temp_dir.path().join("src/main.rs")).unwrap();
 
-        let result = server.search_files(temp_dir.path().to_str().unwrap(), "*.rs", &[]).unwrap();
+        let pattern = Globs::new(&["*.rs".to_string()], false).unwrap();
+        let result = server.search_files(temp_dir.path().to_str().unwrap(), &pattern, &Globs::default(), true).unwrap();
         assert!(result.contains("test1.rs"));
         assert!(result.contains("test2.rs"));
         assert!(!result.contains("other.txt"));
+        // A pattern without a '/' matches names at any depth
+        assert!(result.contains("src/main.rs"));
+    }
+
+    #[test]
+    fn test_copy_tools() {
+        let (server, temp_dir) = create_test_server();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("src/nested")).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(root.join("src/nested/lib.rs"), "pub fn f() {}").unwrap();
+        std::os::unix::fs::symlink("/etc/hostname", root.join("src/host")).unwrap();
+        let copy = |tool: &str, source: &str, destination: &str, overwrite: bool| {
+            let arguments = serde_json::json!({
+                "source": root.join(source), "destination": root.join(destination), "overwrite": overwrite
+            });
+            let result = server.call_tool(tool, &arguments).unwrap();
+            (result.get("isError").is_none(), result["content"][0]["text"].as_str().unwrap().to_string())
+        };
+
+        assert!(copy("copy_file", "src/main.rs", "main.rs", false).0);
+        assert_eq!(fs::read_to_string(root.join("main.rs")).unwrap(), "fn main() {}");
+        let (ok, text) = copy("copy_file", "src/nested/lib.rs", "main.rs", false);
+        assert!(!ok && text.contains("overwrite: true"));
+        assert!(copy("copy_file", "src/nested/lib.rs", "main.rs", true).0);
+        assert_eq!(fs::read_to_string(root.join("main.rs")).unwrap(), "pub fn f() {}");
+        assert!(!copy("copy_file", "src", "copy", false).0);
+
+        let (ok, text) = copy("copy_directory", "src", "backup", false);
+        assert!(ok, "{}", text);
+        assert!(text.contains("copied 2 files") && text.contains("skipped 1 symlinks"));
+        assert_eq!(fs::read_to_string(root.join("backup/nested/lib.rs")).unwrap(), "pub fn f() {}");
+        assert!(!root.join("backup/host").exists());
+        assert!(!copy("copy_directory", "src", "backup", false).0);
+        assert!(copy("copy_directory", "src", "backup", true).0);
+        let (ok, text) = copy("copy_directory", "src", "src/nested/again", false);
+        assert!(!ok && text.contains("into itself"));
+        let arguments = serde_json::json!({"source": "/etc/hostname", "destination": root.join("h")});
+        let outside = server.call_tool("copy_file", &arguments);
+        assert!(outside.unwrap()["content"][0]["text"].as_str().unwrap().contains("Access denied"));
+    }
+
+    #[test]
+    fn test_archive_tools() {
+        let (server, temp_dir) = create_test_server();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("src/nested")).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(root.join("src/nested/lib.rs"), "pub fn f() {}").unwrap();
+        let call = |tool: &str, source: &str, destination: &str, format: Option<&str>| {
+            let arguments = serde_json::json!({
+                "source": root.join(source), "destination": root.join(destination), "format": format
+            });
+            let result = server.call_tool(tool, &arguments).unwrap();
+            (result.get("isError").is_none(), result["content"][0]["text"].as_str().unwrap().to_string())
+        };
+
+        let (ok, text) = call("create_archive", "src", "src.tgz", None);
+        assert!(ok && text.contains("archived 2 files"), "{}", text);
+        let (ok, text) = call("create_archive", "src", "src.bin", None);
+        assert!(!ok && text.contains("Can't tell the format"));
+        assert!(call("create_archive", "src", "src.bin", Some("zip")).0);
+
+        let (ok, text) = call("extract_archive", "src.tgz", "out", None);
+        assert!(ok, "{}", text);
+        assert_eq!(fs::read_to_string(root.join("out/src/nested/lib.rs")).unwrap(), "pub fn f() {}");
+        let (ok, text) = call("extract_archive", "src.bin", "out", Some("zip"));
+        assert!(!ok && text.contains("already exists"));
+
+        // An entry climbing out of the destination fails the whole extraction
+        let mut zip = zip::ZipWriter::new(File::create(root.join("evil.zip")).unwrap());
+        zip.start_file("../../escaped.txt", zip::write::SimpleFileOptions::default()).unwrap();
+        zip.finish().unwrap();
+        let (ok, text) = call("extract_archive", "evil.zip", "evil", None);
+        assert!(!ok && text.contains("would land outside"), "{}", text);
+        assert!(call("extract_archive", "src.tgz", "/tmp/mcpz-out", None).1.contains("Access denied"));
+    }
+
+    #[test]
+    fn test_delete_tools() {
+        let (server, temp_dir) = create_test_server();
+        let root = temp_dir.path().canonicalize().unwrap();
+        let names = |server: &FilesystemServer| server.tools().into_iter().map(|tool| tool.name).collect::<Vec<_>>();
+        assert!(!names(&server).contains(&"delete_file".to_string()));
+        let disabled = server.call_tool("delete_file", &serde_json::json!({"path": root.join("x")})).unwrap();
+        assert!(disabled["content"][0]["text"].as_str().unwrap().contains("--allow-delete"));
+
+        let config = FilesystemServerConfig::new(vec![root.clone()], false).unwrap().with_allow_delete(true);
+        let server = FilesystemServer::new(config);
+        assert!(names(&server).contains(&"delete_directory".to_string()));
+        let delete = |tool: &str, arguments: serde_json::Value| {
+            let result = server.call_tool(tool, &arguments).unwrap();
+            (result.get("isError").is_none(), result["content"][0]["text"].as_str().unwrap().to_string())
+        };
+        fs::create_dir_all(root.join("build/out")).unwrap();
+        fs::write(root.join("build/out/a.o"), "").unwrap();
+        fs::write(root.join("notes.txt"), "keep").unwrap();
+        std::os::unix::fs::symlink(root.join("notes.txt"), root.join("link")).unwrap();
+
+        assert!(delete("delete_file", serde_json::json!({"path": root.join("build/out/a.o")})).0);
+        assert!(!root.join("build/out/a.o").exists());
+        assert!(delete("delete_file", serde_json::json!({"path": root.join("link")})).0);
+        assert_eq!(fs::read_to_string(root.join("notes.txt")).unwrap(), "keep");
+        let (ok, text) = delete("delete_file", serde_json::json!({"path": root.join("build")}));
+        assert!(!ok && text.contains("use delete_directory"));
+
+        let (ok, text) = delete("delete_directory", serde_json::json!({"path": root.join("build")}));
+        assert!(!ok && text.contains("recursive: true"));
+        assert!(delete("delete_directory", serde_json::json!({"path": root.join("build"), "recursive": true})).0);
+        assert!(!root.join("build").exists());
+        let (ok, text) = delete("delete_directory", serde_json::json!({"path": root, "recursive": true}));
+        assert!(!ok && text.contains("Refusing to delete an allowed directory"));
+        let (ok, text) = delete("delete_file", serde_json::json!({"path": "/etc/hostname"}));
+        assert!(!ok && text.contains("Access denied"));
+    }
+
+    #[test]
+    fn test_symlink_policies() {
+        let (server, temp_dir) = create_test_server();
+        let root = temp_dir.path().canonicalize().unwrap();
+        let outside = TempDir::new().unwrap();
+        fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+        fs::create_dir(root.join("docs")).unwrap();
+        fs::write(root.join("docs/guide.md"), "guide").unwrap();
+        std::os::unix::fs::symlink(outside.path(), root.join("escape")).unwrap();
+        let text = |server: &FilesystemServer, tool: &str, arguments: serde_json::Value| {
+            server.call_tool(tool, &arguments).unwrap()["content"][0]["text"].as_str().unwrap().to_string()
+        };
+
+        let manual = serde_json::json!({"target": "docs", "path": root.join("manual")});
+        let created = text(&server, "create_symlink", manual);
+        assert!(created.starts_with("Created symlink"), "{}", created);
+        assert_eq!(text(&server, "read_file", serde_json::json!({"path": root.join("manual/guide.md")})), "guide");
+        let refused = serde_json::json!({"target": outside.path(), "path": root.join("out")});
+        assert!(text(&server, "create_symlink", refused).contains("Access denied"));
+        let link = text(&server, "read_link", serde_json::json!({"path": root.join("escape")}));
+        assert!(link.ends_with("(not followed: outside the allowed directories, or refused by --follow-symlinks)"));
+        assert!(text(&server, "read_link", serde_json::json!({"path": root.join("docs")})).contains("not a symlink"));
+
+        // within-allowed (the default) refuses the link out; always follows it; never refuses
+        // even the link that stays inside
+        let secret = serde_json::json!({"path": root.join("escape/secret.txt")});
+        let guide = serde_json::json!({"path": root.join("manual/guide.md")});
+        assert!(text(&server, "read_file", secret.clone()).contains("Access denied"));
+        let with_policy = |policy: SymlinkPolicy| {
+            let config = FilesystemServerConfig::new(vec![root.clone()], false).unwrap();
+            FilesystemServer::new(config.with_follow_symlinks(policy))
+        };
+        let always = with_policy(SymlinkPolicy::Always);
+        assert_eq!(text(&always, "read_file", secret.clone()), "secret");
+        let never = with_policy(SymlinkPolicy::Never);
+        assert!(text(&never, "read_file", guide).contains("goes through a symlink"));
+        assert!(text(&never, "read_file", secret).contains("goes through a symlink"));
+        assert_eq!(text(&never, "read_file", serde_json::json!({"path": root.join("docs/guide.md")})), "guide");
+        let link = text(&never, "read_link", serde_json::json!({"path": root.join("manual")}));
+        assert!(link.contains("-> docs (not followed"), "{}", link);
+
+        // Directories can't be created through links the policy doesn't follow
+        let through = serde_json::json!({"path": root.join("manual/new/deeper")});
+        assert!(text(&never, "create_directory", through.clone()).contains("goes through a symlink"));
+        assert!(!root.join("docs/new").exists());
+        let escaping = serde_json::json!({"path": root.join("escape/new")});
+        assert!(text(&server, "create_directory", escaping).contains("Access denied"));
+        assert!(!outside.path().join("new").exists());
+        assert!(text(&server, "create_directory", through).starts_with("Successfully"));
+    }
+
+    #[test]
+    fn test_read_only_directories() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("out")).unwrap();
+        fs::write(root.join("notes.txt"), "notes").unwrap();
+        std::os::unix::fs::symlink(root.join("notes.txt"), root.join("out/notes")).unwrap();
+        let read_only = PathBuf::from(format!("{}:ro", root.display()));
+        let writable = PathBuf::from(format!("{}:rw", root.join("out").display()));
+        let server = FilesystemServer::new(FilesystemServerConfig::new(vec![read_only, writable], false).unwrap());
+        let text = |tool: &str, arguments: serde_json::Value| {
+            server.call_tool(tool, &arguments).unwrap()["content"][0]["text"].as_str().unwrap().to_string()
+        };
+
+        assert_eq!(text("read_file", serde_json::json!({"path": root.join("notes.txt")})), "notes");
+        let refused = text("write_file", serde_json::json!({"path": root.join("notes.txt"), "content": "x"}));
+        assert!(refused.contains("Access denied - path in a read-only directory"), "{}", refused);
+        let edit = serde_json::json!({"path": root.join("notes.txt"), "edits": [{"oldText": "notes", "newText": "x"}]});
+        assert!(text("edit_file", edit.clone()).contains("read-only"));
+        let mut dry_run = edit;
+        dry_run["dryRun"] = serde_json::json!(true);
+        assert!(!text("edit_file", dry_run).contains("read-only"));
+        let moved = serde_json::json!({"source": root.join("notes.txt"), "destination": root.join("out/notes.txt")});
+        assert!(text("move_file", moved).contains("read-only"));
+        // Copying out of the read-only directory is fine, and the innermost directory decides
+        let copied = serde_json::json!({"source": root.join("notes.txt"), "destination": root.join("out/copy.txt")});
+        assert!(!text("copy_file", copied).contains("read-only"));
+        let written = text("write_file", serde_json::json!({"path": root.join("out/new.txt"), "content": "x"}));
+        assert!(written.starts_with("Successfully"));
+        // Writing through a link in a writable directory into a read-only one isn't
+        let appended = text("append_file", serde_json::json!({"path": root.join("out/notes"), "content": "x"}));
+        assert!(appended.contains("read-only"));
+        assert_eq!(fs::read_to_string(root.join("notes.txt")).unwrap(), "notes");
+
+        // Nor is creating directories under ones that don't exist yet
+        let nested = text("create_directory", serde_json::json!({"path": root.join("new/deeper")}));
+        assert!(nested.contains("read-only"), "{}", nested);
+        assert!(!root.join("new").exists());
+        let copied = serde_json::json!({"source": root.join("out"), "destination": root.join("new/out")});
+        assert!(text("copy_directory", copied).contains("read-only"));
+        let created = text("create_directory", serde_json::json!({"path": root.join("out/a/b")}));
+        assert!(created.starts_with("Successfully"), "{}", created);
+
+        let listed = server.list_allowed_directories(STDIO_SESSION);
+        assert!(listed.contains(&format!("{} (read-only)", root.display())));
+        assert!(!listed.contains(&format!("{} (read-only)", root.join("out").display())));
+    }
+
+    #[test]
+    fn test_read_only_directory_through_parent_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("rw")).unwrap();
+        fs::create_dir_all(root.join("ro")).unwrap();
+        let writable = root.join("rw");
+        let read_only = PathBuf::from(format!("{}:ro", root.join("ro").display()));
+        let server = FilesystemServer::new(FilesystemServerConfig::new(vec![writable, read_only], false).unwrap());
+        let text = |tool: &str, arguments: serde_json::Value| {
+            server.call_tool(tool, &arguments).unwrap()["content"][0]["text"].as_str().unwrap().to_string()
+        };
+
+        let climbing = root.join("rw/../ro/new.txt");
+        let written = text("write_file", serde_json::json!({"path": climbing, "content": "x"}));
+        assert!(written.contains("read-only directory"), "{}", written);
+        let created = text("create_directory", serde_json::json!({"path": root.join("rw/../ro/new")}));
+        assert!(created.contains("read-only directory"), "{}", created);
+        let created = text("create_directory", serde_json::json!({"path": root.join("rw/new/../../ro/new")}));
+        assert!(created.contains("Access denied"), "{}", created);
+        assert_eq!(fs::read_dir(root.join("ro")).unwrap().count(), 0);
+        assert!(!root.join("rw/new").exists());
+    }
+
+    #[test]
+    fn test_nested_read_only_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("w/ro")).unwrap();
+        fs::write(root.join("w/ro/keep.txt"), "keep").unwrap();
+        fs::create_dir_all(root.join("src/ro")).unwrap();
+        fs::write(root.join("src/ro/keep.txt"), "replaced").unwrap();
+        let read_only = PathBuf::from(format!("{}:ro", root.join("w/ro").display()));
+        let config = FilesystemServerConfig::new(vec![root.clone(), read_only], false).unwrap();
+        let server = FilesystemServer::new(config.with_allow_delete(true).with_allow_chmod(true));
+        let text = |tool: &str, arguments: serde_json::Value| {
+            server.call_tool(tool, &arguments).unwrap()["content"][0]["text"].as_str().unwrap().to_string()
+        };
+
+        let deleted = text("delete_directory", serde_json::json!({"path": root.join("w"), "recursive": true}));
+        assert!(deleted.contains("read-only directory"), "{}", deleted);
+        let chmod = serde_json::json!({"path": root.join("w"), "mode": "700", "recursive": true});
+        assert!(text("set_permissions", chmod).contains("read-only directory"));
+        let moved = serde_json::json!({"source": root.join("w"), "destination": root.join("moved")});
+        assert!(text("move_file", moved).contains("read-only directory"));
+        let copied = serde_json::json!({"source": root.join("src"), "destination": root.join("w"), "overwrite": true});
+        let copied = text("copy_directory", copied);
+        assert!(copied.contains("read-only directory"), "{}", copied);
+
+        let mut zip = zip::ZipWriter::new(File::create(root.join("ro.zip")).unwrap());
+        zip.start_file("ro/keep.txt", zip::write::SimpleFileOptions::default()).unwrap();
+        zip.finish().unwrap();
+        let extracted = serde_json::json!({"source": root.join("ro.zip"), "destination": root.join("w")});
+        assert!(text("extract_archive", extracted).contains("read-only directory"));
+        assert_eq!(fs::read_to_string(root.join("w/ro/keep.txt")).unwrap(), "keep");
+
+        // Changing only the directory itself, or a sibling tree, is still fine
+        let chmod = serde_json::json!({"path": root.join("w"), "mode": "755"});
+        let chmod = text("set_permissions", chmod);
+        assert!(chmod.starts_with("Set permissions"), "{}", chmod);
+        let deleted = text("delete_directory", serde_json::json!({"path": root.join("src"), "recursive": true}));
+        assert!(deleted.starts_with("Successfully"), "{}", deleted);
+    }
+
+    #[test]
+    fn test_exclude() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("app/secrets")).unwrap();
+        fs::write(root.join("app/.env"), "TOKEN=abc").unwrap();
+        fs::write(root.join("app/secrets/key.pem"), "TOKEN=def").unwrap();
+        fs::write(root.join("app/id_rsa.pub"), "TOKEN=ghi").unwrap();
+        fs::write(root.join("app/main.rs"), "TOKEN=none").unwrap();
+        let config = FilesystemServerConfig::new(vec![root.clone()], false).unwrap();
+        let config = config.with_exclude(Some("*.env, **/secrets/**,id_rsa*".to_string())).unwrap();
+        let server = FilesystemServer::new(config);
+        let text = |tool: &str, arguments: serde_json::Value| {
+            server.call_tool(tool, &arguments).unwrap()["content"][0]["text"].as_str().unwrap().to_string()
+        };
+        let app = serde_json::json!({"path": root.join("app")});
+
+        for hidden in ["app/.env", "app/secrets", "app/secrets/key.pem", "app/id_rsa.pub"] {
+            let read = text("get_file_info", serde_json::json!({"path": root.join(hidden)}));
+            assert!(read.contains("Access denied - path matches --exclude"), "{}", read);
+        }
+        let written = text("write_file", serde_json::json!({"path": root.join("app/prod.env"), "content": "x"}));
+        assert!(written.contains("Access denied"));
+        let created = text("create_directory", serde_json::json!({"path": root.join("secrets/new")}));
+        assert!(created.contains("Access denied"));
+        assert_eq!(text("list_directory", app.clone()), "[FILE] main.rs");
+        let tree = text("directory_tree", app.clone());
+        assert!(tree.contains("main.rs") && !tree.contains("secrets") && !tree.contains(".env"), "{}", tree);
+        let found = text("search_content", serde_json::json!({"path": root, "pattern": "TOKEN"}));
+        assert!(found.contains("main.rs") && !found.contains("abc") && !found.contains("def"), "{}", found);
+        let found = text("search_files", serde_json::json!({"path": root, "pattern": "**/*"}));
+        assert!(!found.contains("key.pem") && !found.contains("id_rsa"), "{}", found);
+
+        // Copies and archives leave them out, so they can't resurface under another name
+        text("copy_directory", serde_json::json!({"source": root.join("app"), "destination": root.join("copy")}));
+        assert!(root.join("copy/main.rs").exists());
+        assert!(!root.join("copy/secrets").exists() && !root.join("copy/.env").exists());
+        let archive = serde_json::json!({"source": root.join("app"), "destination": root.join("app.zip")});
+        assert!(text("create_archive", archive).contains("archived 1 files"));
+    }
+
+    #[test]
+    fn test_exclude_through_parent_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("x")).unwrap();
+        fs::create_dir_all(root.join("secrets")).unwrap();
+        let config = FilesystemServerConfig::new(vec![root.clone()], false).unwrap();
+        let config = config.with_exclude(Some("secrets/**".to_string())).unwrap();
+        assert!(config.excludes(&root.join("x/../secrets/new.txt")));
+        assert!(!config.excludes(&root.join("x/new.txt")));
+        let server = FilesystemServer::new(config);
+        let text = |tool: &str, arguments: serde_json::Value| {
+            server.call_tool(tool, &arguments).unwrap()["content"][0]["text"].as_str().unwrap().to_string()
+        };
+
+        let climbing = root.join("x/../secrets/new.txt");
+        let written = text("write_file", serde_json::json!({"path": climbing, "content": "x"}));
+        assert!(written.contains("Access denied - path matches --exclude"), "{}", written);
+        let created = text("create_directory", serde_json::json!({"path": root.join("x/../secrets/new")}));
+        assert!(created.contains("Access denied"), "{}", created);
+        assert_eq!(fs::read_dir(root.join("secrets")).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_set_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let (server, temp_dir) = create_test_server();
+        let root = temp_dir.path().canonicalize().unwrap();
+        let disabled = server.call_tool("set_permissions", &serde_json::json!({"path": root, "mode": "755"})).unwrap();
+        assert!(disabled["content"][0]["text"].as_str().unwrap().contains("--allow-chmod"));
+
+        let config = FilesystemServerConfig::new(vec![root.clone()], false).unwrap().with_allow_chmod(true);
+        let server = FilesystemServer::new(config);
+        assert!(server.tools().iter().any(|tool| tool.name == "set_permissions"));
+        fs::create_dir_all(root.join("bin")).unwrap();
+        fs::write(root.join("bin/build.sh"), "#!/bin/sh").unwrap();
+        fs::write(root.join("bin/deploy.sh"), "#!/bin/sh").unwrap();
+        std::os::unix::fs::symlink("/etc/hostname", root.join("bin/host")).unwrap();
+        let mode = |path: &str| fs::metadata(root.join(path)).unwrap().permissions().mode() & 0o777;
+        let chmod = |arguments: serde_json::Value| server.call_tool("set_permissions", &arguments);
+
+        let result = chmod(serde_json::json!({"path": root.join("bin/build.sh"), "mode": "0755"})).unwrap();
+        assert!(result.get("isError").is_none());
+        assert_eq!(mode("bin/build.sh"), 0o755);
+        let result = chmod(serde_json::json!({"path": root.join("bin"), "mode": "750", "recursive": true})).unwrap();
+        let text = result["content"][0]["text"].as_str().unwrap();
+        assert!(text.contains("3 paths") && text.contains("skipped 1 symlinks"), "{}", text);
+        assert_eq!(mode("bin/deploy.sh"), 0o750);
+        assert_ne!(fs::metadata("/etc/hostname").unwrap().permissions().mode() & 0o777, 0o750);
+
+        assert!(chmod(serde_json::json!({"path": root.join("bin"), "mode": "4755"})).is_err());
+        assert!(chmod(serde_json::json!({"path": root.join("bin"), "mode": "rwx"})).is_err());
+        let outside = chmod(serde_json::json!({"path": "/etc/hostname", "mode": "777"})).unwrap();
+        assert!(outside["content"][0]["text"].as_str().unwrap().contains("Access denied"));
+    }
+
+    #[test]
+    fn test_search_content() {
+        let (server, temp_dir) = create_test_server();
+        fs::create_dir_all(temp_dir.path().join("src")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("target")).unwrap();
+        fs::write(temp_dir.path().join("src/main.rs"), "fn main() {\n    run(1);\n}\nfn run(n: u8) {}\n").unwrap();
+        fs::write(temp_dir.path().join("notes.txt"), "Run it with run(2)\n").unwrap();
+        fs::write(temp_dir.path().join("target/build.rs"), "run(3);\n").unwrap();
+        fs::write(temp_dir.path().join("data.bin"), [0xff, b'r', b'u', b'n']).unwrap();
+        let search = |arguments: serde_json::Value| {
+            let mut arguments = arguments;
+            arguments["path"] = serde_json::json!(temp_dir.path());
+            server.call_tool("search_content", &arguments).unwrap()
+        };
+        let lines = |result: &serde_json::Value| -> Vec<(String, u64)> {
+            let root = temp_dir.path().canonicalize().unwrap().to_string_lossy().to_string();
+            let matches = result["structuredContent"]["matches"].as_array().unwrap().iter();
+            matches
+                .map(|m| (m["path"].as_str().unwrap().replace(&root, ""), m["line"].as_u64().unwrap()))
+                .collect()
+        };
+
+        let found = search(serde_json::json!({"pattern": r"run\(\d\)"}));
+        let expected = [("/notes.txt", 1), ("/src/main.rs", 2), ("/target/build.rs", 1)];
+        assert_eq!(lines(&found), expected.map(|(path, line)| (path.to_string(), line)));
+        let found = search(serde_json::json!({"pattern": "run(", "literal": true, "includePatterns": ["*.rs"],
+            "excludePatterns": ["target"], "contextLines": 1}));
+        assert_eq!(lines(&found), [("/src/main.rs".to_string(), 2), ("/src/main.rs".to_string(), 4)]);
+        let first = &found["structuredContent"]["matches"][0];
+        assert_eq!(first["before"], serde_json::json!(["fn main() {"]));
+        assert_eq!(first["after"], serde_json::json!(["}"]));
+        let text = found["content"][0]["text"].as_str().unwrap();
+        assert!(text.contains("/src/main.rs-1- fn main() {\n"));
+        assert!(text.contains("/src/main.rs:2:     run(1);\n"));
+        assert!(text.contains("\n--\n"));
+
+        let found = search(serde_json::json!({"pattern": "^run", "ignoreCase": true, "maxResults": 1}));
+        assert_eq!(lines(&found), [("/notes.txt".to_string(), 1)]);
+        assert_eq!(found["structuredContent"]["truncated"], true);
+        assert_eq!(search(serde_json::json!({"pattern": "nothing"}))["content"][0]["text"], "No matches found");
+        let invalid = server.call_tool("search_content", &serde_json::json!({"path": temp_dir.path(), "pattern": "("}));
+        assert!(invalid.unwrap_err().to_string().starts_with("Invalid pattern"));
+    }
+
+    #[test]
+    fn test_search_content_skips_links_outside() {
+        let (server, temp_dir) = create_test_server();
+        let outside = TempDir::new().unwrap();
+        fs::write(outside.path().join("secret.txt"), "TOPSECRET line\n").unwrap();
+        fs::write(temp_dir.path().join("inside.txt"), "TOPSECRET too\n").unwrap();
+        std::os::unix::fs::symlink(outside.path().join("secret.txt"), temp_dir.path().join("link.txt")).unwrap();
+        std::os::unix::fs::symlink(temp_dir.path().join("inside.txt"), temp_dir.path().join("alias.txt")).unwrap();
+
+        let arguments = serde_json::json!({"path": temp_dir.path(), "pattern": "TOPSECRET"});
+        let found = server.call_tool("search_content", &arguments).unwrap();
+        let paths: Vec<&str> = found["structuredContent"]["matches"].as_array().unwrap().iter()
+            .map(|m| m["path"].as_str().unwrap())
+            .collect();
+        assert_eq!(paths.len(), 2, "{:?}", paths);
+        assert!(paths.iter().all(|path| !path.ends_with("link.txt")));
     }
 
     #[test]
@@ -1551,13 +5576,88 @@ mod tests {
         fs::create_dir(temp_dir.path().join("subdir")).unwrap();
         File::create(temp_dir.path().join("subdir/nested.txt")).unwrap();
 
-        let result = server.directory_tree(temp_dir.path().to_str().unwrap(), &[]).unwrap();
+        let path = temp_dir.path().to_str().unwrap();
+        let result = server.directory_tree(path, &Globs::default(), true, None, Page::all()).unwrap().0;
         let tree: Vec<TreeEntry> = serde_json::from_str(&result).unwrap();
 
         assert!(tree.iter().any(|e| e.name == "file.txt" && e.entry_type == "file"));
         assert!(tree.iter().any(|e| e.name == "subdir" && e.entry_type == "directory"));
     }
 
+    #[test]
+    fn test_directory_paging() {
+        let (server, temp_dir) = create_test_server();
+        let root = temp_dir.path().canonicalize().unwrap();
+        for dir in ["a", "b/c"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in ["a/1", "a/2", "b/c/3", "x", "y"] {
+            fs::write(root.join(file), "").unwrap();
+        }
+        let call = |tool: &str, arguments: serde_json::Value| server.call_tool(tool, &arguments).unwrap();
+
+        let first = call("list_directory", serde_json::json!({"path": root, "maxEntries": 3}));
+        assert_eq!(first["structuredContent"]["total"], 4);
+        assert_eq!(first["structuredContent"]["hasMore"], true);
+        let text = first["content"][0]["text"].as_str().unwrap();
+        assert!(text.starts_with("[DIR] a\n[DIR] b\n[FILE] x"), "{}", text);
+        assert!(text.ends_with("[Entries 1-3 of 4; pass page: 2 for more]"));
+        let second = call("list_directory", serde_json::json!({"path": root, "maxEntries": 3, "page": 2}));
+        assert_eq!(second["content"][0]["text"], "[FILE] y");
+        assert_eq!(second["structuredContent"]["hasMore"], false);
+
+        // Depth-first: a, a/1, a/2, b, b/c, b/c/3, x, y; later pages keep the directories leading
+        // to their entries
+        let tree = |arguments: serde_json::Value| {
+            let result = call("directory_tree", arguments);
+            let tree: Vec<TreeEntry> = serde_json::from_str(result["content"][0]["text"].as_str().unwrap()).unwrap();
+            (tree, result["content"].get(1).map(|note| note["text"].as_str().unwrap().to_string()))
+        };
+        let (first, more) = tree(serde_json::json!({"path": root, "maxEntries": 4}));
+        assert_eq!(first.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(), ["a", "b"]);
+        assert!(first[1].children.as_ref().unwrap().is_empty());
+        assert!(more.unwrap().contains("pass page: 2"));
+        let (second, more) = tree(serde_json::json!({"path": root, "maxEntries": 4, "page": 2}));
+        assert_eq!(second.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(), ["b", "x", "y"]);
+        assert_eq!(second[0].children.as_ref().unwrap()[0].children.as_ref().unwrap()[0].name, "3");
+        assert!(more.is_none());
+
+        let (shallow, _) = tree(serde_json::json!({"path": root, "maxDepth": 1}));
+        assert_eq!(shallow.len(), 4);
+        assert!(shallow[0].children.is_none() && shallow[0].entry_type == "directory");
+    }
+
+    #[test]
+    fn test_gitignore() {
+        let (server, temp_dir) = create_test_server();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::create_dir_all(root.join("node_modules/left-pad")).unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join(".gitignore"), "node_modules/\n*.log\n").unwrap();
+        fs::write(root.join("node_modules/left-pad/index.js"), "module.exports = pad").unwrap();
+        fs::write(root.join("src/index.js"), "const pad = require('left-pad')").unwrap();
+        fs::write(root.join("debug.log"), "pad failed").unwrap();
+        let text = |tool: &str, arguments: serde_json::Value| {
+            server.call_tool(tool, &arguments).unwrap()["content"][0]["text"].as_str().unwrap().to_string()
+        };
+
+        let tree = text("directory_tree", serde_json::json!({"path": root}));
+        assert!(tree.contains("index.js") && tree.contains(".gitignore"));
+        assert!(!tree.contains("node_modules") && !tree.contains("debug.log") && !tree.contains("\".git\""));
+        let found = text("search_files", serde_json::json!({"path": root, "pattern": "**/*.js"}));
+        assert_eq!(found, root.join("src/index.js").to_string_lossy());
+        let found = text("search_content", serde_json::json!({"path": root.join("src"), "pattern": "pad"}));
+        assert_eq!(found.lines().count(), 1);
+
+        let arguments = serde_json::json!({"path": root, "pattern": "pad", "respectGitignore": false});
+        assert_eq!(text("search_content", arguments).lines().count(), 3);
+        let config = FilesystemServerConfig::new(vec![root.clone()], false).unwrap().with_respect_gitignore(false);
+        let server = FilesystemServer::new(config);
+        let tree = server.call_tool("directory_tree", &serde_json::json!({"path": root})).unwrap();
+        assert!(tree["content"][0]["text"].as_str().unwrap().contains("left-pad"));
+    }
+
     #[test]
     fn test_path_validation_outside_allowed() {
         let (server, _temp_dir) = create_test_server();
@@ -1571,11 +5671,130 @@ mod tests {
     #[test]
     fn test_list_allowed_directories() {
         let (server, temp_dir) = create_test_server();
-        let result = server.list_allowed_directories();
+        let result = server.list_allowed_directories(STDIO_SESSION);
         assert!(result.contains("Allowed directories:"));
         assert!(result.contains(&temp_dir.path().to_string_lossy().to_string()));
     }
 
+    #[test]
+    fn test_resources() {
+        let (server, temp_dir) = create_test_server();
+        fs::create_dir(temp_dir.path().join("docs")).unwrap();
+        fs::write(temp_dir.path().join("docs/read me.md"), "# Hello").unwrap();
+        fs::write(temp_dir.path().join("data.bin"), [0xff, 0xfe]).unwrap();
+        fs::write(temp_dir.path().join(".secret"), "hidden").unwrap();
+
+        let init = server.handle_initialize();
+        assert!(init["capabilities"]["resources"].is_object());
+        let ctx = RequestContext::default();
+        let resources = server.resources(&ctx);
+        let names: Vec<&str> = resources.iter().map(|r| r.name.as_str()).collect();
+        let root = temp_dir.path().canonicalize().unwrap();
+        assert_eq!(names, [root.to_string_lossy().as_ref(), "data.bin", "docs/read me.md"]);
+        assert!(resources[2].uri.ends_with("/docs/read%20me.md"));
+        assert_eq!(resources[2].mime_type.as_deref(), Some("text/markdown"));
+
+        let read = server.read_resource(&resources[2].uri, &ctx).unwrap();
+        assert_eq!(read["contents"][0]["text"], "# Hello");
+        assert_eq!(read["contents"][0]["mimeType"], "text/markdown");
+        let binary = server.read_resource(&resources[1].uri, &ctx).unwrap();
+        assert_eq!(binary["contents"][0]["blob"], "//4=");
+        let listing = server.read_resource(&resources[0].uri, &ctx).unwrap();
+        assert!(listing["contents"][0]["text"].as_str().unwrap().contains("[DIR] docs"));
+
+        assert!(server.read_resource("file:///etc/passwd", &ctx).unwrap_err().to_string().contains("Access denied"));
+        assert!(server.read_resource("https://example.com", &ctx).is_err());
+
+        let big = File::create(temp_dir.path().join("big.bin")).unwrap();
+        big.set_len(MAX_BINARY_READ + 1).unwrap();
+        let refused = server.read_resource(&file_uri(&root.join("big.bin")), &ctx).unwrap_err();
+        assert!(refused.to_string().contains("more than resources/read returns"), "{}", refused);
+    }
+
+    #[test]
+    fn test_read_image_and_binary_files() {
+        let (server, temp_dir) = create_test_server();
+        fs::write(temp_dir.path().join("logo.png"), [0x89, b'P', b'N', b'G']).unwrap();
+        fs::write(temp_dir.path().join("data.bin"), [0xff, 0xfe]).unwrap();
+        let read = |file: &str| {
+            let path = temp_dir.path().join(file);
+            server.call_tool("read_file", &serde_json::json!({"path": path})).unwrap()
+        };
+
+        let image = read("logo.png");
+        assert_eq!(image["content"][0]["type"], "image");
+        assert_eq!(image["content"][0]["data"], "iVBORw==");
+        assert_eq!(image["content"][0]["mimeType"], "image/png");
+        let binary = read("data.bin");
+        assert_eq!(binary["content"][0]["type"], "resource");
+        assert_eq!(binary["content"][0]["resource"]["blob"], "//4=");
+        assert!(binary["content"][0]["resource"]["uri"].as_str().unwrap().ends_with("/data.bin"));
+    }
+
+    #[test]
+    fn test_subscribe_to_resources() {
+        let (server, temp_dir) = create_test_server();
+        let file = temp_dir.path().canonicalize().unwrap().join("watched.txt");
+        fs::write(&file, "one").unwrap();
+        let mut outgoing = server.notifier().unwrap().subscribe();
+        let request = |method: &str, uri: &str| {
+            let request = serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": {"uri": uri}});
+            let response = server.handle_request(serde_json::from_value(request).unwrap()).unwrap();
+            serde_json::to_value(response).unwrap()
+        };
+
+        assert_eq!(server.handle_initialize()["capabilities"]["resources"]["subscribe"], true);
+        let denied = request("resources/subscribe", "file:///etc/passwd");
+        assert_eq!(denied["error"]["code"], -32002);
+        assert_eq!(denied["error"]["data"], serde_json::json!({"kind": "access_denied", "path": "/etc/passwd"}));
+        assert_eq!(request("resources/subscribe", &file_uri(&file.with_extension("md")))["error"]["code"], -32002);
+        assert_eq!(request("resources/subscribe", &file_uri(&file))["result"], serde_json::json!({}));
+
+        fs::write(&file, "two").unwrap();
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        let update = loop {
+            match outgoing.try_recv() {
+                Ok(update) => break update,
+                Err(_) if std::time::Instant::now() < deadline => {
+                    std::thread::sleep(std::time::Duration::from_millis(10))
+                }
+                Err(e) => panic!("no update: {}", e),
+            }
+        };
+        assert_eq!(update.message["method"], "notifications/resources/updated");
+        assert_eq!(update.message["params"]["uri"], file_uri(&file));
+        assert_eq!(request("resources/unsubscribe", &file_uri(&file))["result"], serde_json::json!({}));
+    }
+
+    #[test]
+    fn test_watch_path_and_poll_changes() {
+        let (server, temp_dir) = create_test_server();
+        let root = temp_dir.path().canonicalize().unwrap();
+        server.notifier().unwrap().set_level(LogLevel::Info);
+        let mut outgoing = server.notifier().unwrap().subscribe();
+        let ctx = RequestContext::with_session("build");
+        let call = |name: &str, arguments: serde_json::Value| {
+            server.call_tool_with_context(name, &arguments, &ctx).unwrap()
+        };
+
+        let not_watching = call("poll_changes", serde_json::json!({}));
+        assert_eq!(not_watching["isError"], true);
+        let watching = call("watch_path", serde_json::json!({"path": root}));
+        assert_eq!(watching["content"][0]["text"], format!("Watching {}", root.display()));
+        fs::write(root.join("out.o"), "").unwrap();
+        let polled = call("poll_changes", serde_json::json!({"wait": 5}));
+        assert_eq!(polled["structuredContent"]["changes"][0]["path"], root.join("out.o").to_string_lossy().as_ref());
+        assert_eq!(polled["structuredContent"]["changes"][0]["change"], "created");
+        assert!(polled["content"][0]["text"].as_str().unwrap().starts_with("created "));
+        let announced = outgoing.try_recv().unwrap();
+        assert_eq!(announced.to.as_deref(), Some("build"));
+        assert_eq!(announced.message["params"]["logger"], "watch_path");
+
+        assert_eq!(call("poll_changes", serde_json::json!({}))["content"][0]["text"], "No changes");
+        assert!(call("unwatch_path", serde_json::json!({"path": root})).get("isError").is_none());
+        assert_eq!(call("unwatch_path", serde_json::json!({"path": root}))["isError"], true);
+    }
+
     #[test]
     fn test_filesystem_server_tools() {
         let (server, _temp_dir) = create_test_server();
@@ -1600,6 +5819,55 @@ mod tests {
         assert_eq!(result["serverInfo"]["name"], "mcpz-filesystem");
     }
 
+    #[test]
+    fn test_regex_edits() {
+        let (server, temp_dir) = create_test_server();
+        let path = temp_dir.path().join("Cargo.toml");
+        fs::write(&path, "[package]\nversion = \"1.2.3\"\n\n[dep]\nversion = \"1.2.3\"\nname = \"x\"\n").unwrap();
+        let edit = |edit: serde_json::Value| {
+            let result = server.call_tool("edit_file", &serde_json::json!({"path": path, "edits": [edit]})).unwrap();
+            (result.get("isError").is_none(), result["content"][0]["text"].as_str().unwrap().to_string())
+        };
+        let bump = serde_json::json!({
+            "oldText": r#"^version = "(\d+)\.(\d+)\.\d+""#,
+            "newText": r#"version = "$1.${2}.4""#,
+            "regex": true
+        });
+
+        let (ok, text) = edit(bump.clone());
+        assert!(!ok && text.contains("matches 2 times, more than maxReplacements (1)"), "{}", text);
+        let mut all = bump;
+        all["maxReplacements"] = serde_json::json!(2);
+        let (ok, text) = edit(all);
+        assert!(ok && text.contains("+version = \"1.2.4\""), "{}", text);
+        assert_eq!(fs::read_to_string(&path).unwrap().matches("version = \"1.2.4\"").count(), 2);
+
+        let (ok, text) = edit(serde_json::json!({"oldText": "^edition", "newText": "", "regex": true}));
+        assert!(!ok && text.contains("No match for regex edit"));
+        let (ok, text) = edit(serde_json::json!({"oldText": "(unclosed", "newText": "", "regex": true}));
+        assert!(!ok && text.contains("Invalid regex '(unclosed'"));
+        // Without regex, the text is matched literally
+        let (ok, _) = edit(serde_json::json!({"oldText": "^version", "newText": "v"}));
+        assert!(!ok);
+    }
+
+    #[test]
+    fn test_create_unified_diff() {
+        let original: String = (1..=10).map(|n| format!("line {}\n", n)).collect();
+        let modified = original.replace("line 2\n", "line 2\ninserted\n").replace("line 9\n", "line nine\n");
+
+        // An inserted line shows as one addition, not as every following line changed
+        let diff = create_unified_diff(&original, &modified, "notes.txt", 1);
+        assert_eq!(
+            diff,
+            "--- notes.txt\n+++ notes.txt\n@@ -2,2 +2,3 @@\n line 2\n+inserted\n line 3\n\
+             @@ -8,3 +9,3 @@\n line 8\n-line 9\n+line nine\n line 10\n"
+        );
+        // With more context the two changes share a hunk
+        assert_eq!(create_unified_diff(&original, &modified, "notes.txt", 3).matches("@@ ").count(), 1);
+        assert_eq!(create_unified_diff(&original, &original, "notes.txt", 3), "");
+    }
+
     #[test]
     fn test_edit_file() {
         let (server, temp_dir) = create_test_server();
@@ -1612,10 +5880,11 @@ mod tests {
             EditOperation {
                 old_text: "Hello World".to_string(),
                 new_text: "Hello Rust".to_string(),
+                ..Default::default()
             },
         ];
 
-        let result = server.edit_file(file_path.to_str().unwrap(), edits, false).unwrap();
+        let result = server.edit_file(file_path.to_str().unwrap(), edits, false, DIFF_CONTEXT).unwrap();
         assert!(result.contains("diff"));
 
         let content = fs::read_to_string(&file_path).unwrap();
@@ -1623,6 +5892,146 @@ mod tests {
         assert!(content.contains("Goodbye World"));
     }
 
+    #[test]
+    fn test_create_and_cleanup_temp_dir() {
+        let (server, temp_dir) = create_test_server();
+
+        let path = server.create_temp_dir(Some("scratch"), "s1").unwrap();
+        let path = PathBuf::from(path);
+        assert!(path.is_dir());
+        assert!(path.starts_with(fs::canonicalize(temp_dir.path()).unwrap()));
+        assert!(path.file_name().unwrap().to_string_lossy().starts_with("scratch-"));
+
+        // Another session cannot remove it
+        let result = server.cleanup_temp_dir(path.to_str().unwrap(), "s2");
+        assert!(result.is_err());
+        assert!(path.exists());
+
+        let result = server.cleanup_temp_dir(path.to_str().unwrap(), "s1").unwrap();
+        assert!(result.contains("Successfully removed"));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_complete_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir(root.join("src")).unwrap();
+        fs::write(root.join("setup.py"), "").unwrap();
+        fs::write(root.join(".secret"), "").unwrap();
+        fs::write(root.join("readme.md"), "").unwrap();
+        let dirs = vec![root.clone()];
+        let base = format!("{}/", root.display());
+
+        assert_eq!(complete_path(&base[..base.len() - 2], &dirs, |_| false), vec![base.clone()]);
+        assert_eq!(
+            complete_path(&format!("{}s", base), &dirs, |_| false),
+            [format!("{}setup.py", base), format!("{}src/", base)]
+        );
+        assert_eq!(complete_path(&base, &dirs, |_| false).len(), 3);
+        assert_eq!(complete_path(&format!("{}.", base), &dirs, |_| false), [format!("{}.secret", base)]);
+        // Outside the allowed directories, only the directories themselves are offered
+        let parent = format!("{}/", root.parent().unwrap().display());
+        assert_eq!(complete_path(&parent, &dirs, |_| false), vec![base.clone()]);
+
+        let config = FilesystemServerConfig::new(dirs, false).unwrap();
+        let server = FilesystemServer::new(config);
+        let tool = CompletionRef::Tool { name: "read_file".to_string() };
+        let ctx = RequestContext::default();
+        let values = server.complete(&tool, "path", &format!("{}r", base), &ctx).unwrap();
+        assert_eq!(values, [format!("{}readme.md", base)]);
+        assert!(server.complete(&tool, "content", &base, &ctx).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_client_roots_narrow_access() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = FilesystemServerConfig::new(vec![temp_dir.path().to_path_buf()], false).unwrap();
+        let server = FilesystemServer::new(config.with_client_roots(true));
+        let shared = temp_dir.path().canonicalize().unwrap().join("shared");
+        fs::create_dir(&shared).unwrap();
+        fs::write(shared.join("a.txt"), "a").unwrap();
+        fs::write(temp_dir.path().join("secret.txt"), "s").unwrap();
+
+        let ctx = RequestContext::with_session("s1");
+        let mut outgoing = server.notifier().unwrap().subscribe();
+        let message = |method: &str, params: serde_json::Value| {
+            let message = serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+            server.handle_request_with_context(serde_json::from_value(message).unwrap(), &ctx);
+        };
+        message("initialize", serde_json::json!({"capabilities": {"roots": {"listChanged": true}}}));
+        let notification = serde_json::json!({"jsonrpc": "2.0", "method": "notifications/initialized"});
+        server.handle_request_with_context(serde_json::from_value(notification).unwrap(), &ctx);
+
+        let request = outgoing.try_recv().unwrap();
+        assert_eq!(request.to.as_deref(), Some("s1"));
+        assert_eq!(request.message["method"], "roots/list");
+        let read = |path: &Path| {
+            let result = server.call_tool_with_context("read_file", &serde_json::json!({"path": path}), &ctx).unwrap();
+            result["content"][0]["text"].as_str().unwrap().to_string()
+        };
+        // Until the client answers, nothing may be accessed
+        assert!(read(&temp_dir.path().join("secret.txt")).contains("hasn't reported its roots"));
+        assert!(server.resources(&ctx).is_empty());
+        assert!(server.read_resource(&file_uri(&shared.join("a.txt")), &ctx).is_err());
+
+        let roots = serde_json::json!({"roots": [{"uri": file_uri(&shared)}, {"uri": "https://example.com"}]});
+        server.on_response(&request.message["id"], Ok(roots), &ctx);
+        assert_eq!(read(&shared.join("a.txt")), "a");
+        assert!(read(&temp_dir.path().join("secret.txt")).contains("outside the client's roots"));
+        // Resources are narrowed the same way
+        let uris: Vec<String> = server.resources(&ctx).into_iter().map(|resource| resource.uri).collect();
+        assert_eq!(uris, [file_uri(&shared), file_uri(&shared.join("a.txt"))]);
+        let secret = file_uri(&temp_dir.path().join("secret.txt"));
+        assert!(server.read_resource(&secret, &ctx).unwrap_err().to_string().contains("outside the client's roots"));
+        assert!(server.subscribe(&secret, &ctx).is_err());
+        assert_eq!(server.read_resource(&file_uri(&shared.join("a.txt")), &ctx).unwrap()["contents"][0]["text"], "a");
+        assert!(server.list_allowed_directories("s1").ends_with(&*shared.to_string_lossy()));
+        // Other sessions aren't affected
+        assert!(server.list_allowed_directories(STDIO_SESSION).contains(&*temp_dir.path().to_string_lossy()));
+
+        server.on_session_end(Some("s1"));
+        assert!(server.roots.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_temp_dirs_removed_on_session_end() {
+        let (server, _temp_dir) = create_test_server();
+        let ctx = RequestContext::with_session("abc");
+
+        let result = server
+            .call_tool_with_context("create_temp_dir", &serde_json::json!({}), &ctx)
+            .unwrap();
+        let path = PathBuf::from(result["content"][0]["text"].as_str().unwrap());
+        assert!(path.is_dir());
+
+        server.on_session_end(Some("abc"));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_create_temp_dir_rejects_bad_prefix() {
+        let (server, _temp_dir) = create_test_server();
+        assert!(server.create_temp_dir(Some("../escape"), STDIO_SESSION).is_err());
+    }
+
+    #[test]
+    fn test_scratch_root_must_be_allowed() {
+        let allowed = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+
+        let config = FilesystemServerConfig::new(vec![allowed.path().to_path_buf()], false).unwrap();
+        assert!(config.with_scratch_root(Some(outside.path().to_path_buf())).is_err());
+
+        let scratch = allowed.path().join("scratch");
+        fs::create_dir(&scratch).unwrap();
+        let config = FilesystemServerConfig::new(vec![allowed.path().to_path_buf()], false)
+            .unwrap()
+            .with_scratch_root(Some(scratch.clone()))
+            .unwrap();
+        assert_eq!(config.scratch_root(), fs::canonicalize(&scratch).unwrap());
+    }
+
     #[test]
     fn test_edit_file_dry_run() {
         let (server, temp_dir) = create_test_server();
@@ -1634,10 +6043,11 @@ mod tests {
             EditOperation {
                 old_text: "Original content".to_string(),
                 new_text: "Modified content".to_string(),
+                ..Default::default()
             },
         ];
 
-        let result = server.edit_file(file_path.to_str().unwrap(), edits, true).unwrap();
+        let result = server.edit_file(file_path.to_str().unwrap(), edits, true, DIFF_CONTEXT).unwrap();
         assert!(result.contains("diff"));
 
         // File should NOT be modified in dry run
@@ -1645,4 +6055,41 @@ mod tests {
         assert!(content.contains("Original content"));
         assert!(!content.contains("Modified content"));
     }
+
+    #[test]
+    fn test_apply_patch() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        fs::write(root.join("main.rs"), "fn main() {\n    println!(\"hi\");\n}\n").unwrap();
+        fs::write(root.join("old.txt"), "bye\n").unwrap();
+        let server = FilesystemServer::new(FilesystemServerConfig::new(vec![root.clone()], false).unwrap());
+        let apply = |patch: &str, dry_run: bool| {
+            server.call_tool("apply_patch", &serde_json::json!({"patch": patch, "dryRun": dry_run})).unwrap()
+        };
+
+        let patch = "--- a/main.rs\n+++ b/main.rs\n@@ -1,3 +1,3 @@\n fn main() {\n-    println!(\"hi\");\n\
+                     +    println!(\"hello\");\n }\n--- /dev/null\n+++ b/new.txt\n@@ -0,0 +1 @@\n+new\n";
+        let result = apply(patch, true);
+        assert_eq!(result["structuredContent"]["files"][1]["status"], "created");
+        assert!(!root.join("new.txt").exists());
+        let result = apply(patch, false);
+        assert_eq!(result["structuredContent"]["applied"], true);
+        assert!(fs::read_to_string(root.join("main.rs")).unwrap().contains("hello"));
+        assert_eq!(fs::read_to_string(root.join("new.txt")).unwrap(), "new\n");
+
+        // One rejected hunk keeps every file as it was
+        let patch = "--- a/old.txt\n+++ b/old.txt\n@@ -1 +1 @@\n-bye\n+farewell\n\
+                     --- a/main.rs\n+++ b/main.rs\n@@ -2 +2 @@\n-    println!(\"hi\");\n+    todo!();\n";
+        let result = apply(patch, false);
+        assert_eq!(result["isError"], true);
+        let text = result["content"][0]["text"].as_str().unwrap();
+        assert!(text.contains("hunk 1 rejected: its 1 original lines weren't found near line 2"), "{}", text);
+        assert_eq!(fs::read_to_string(root.join("old.txt")).unwrap(), "bye\n");
+
+        // Deleting needs --allow-delete
+        let patch = "--- a/old.txt\n+++ /dev/null\n@@ -1 +0,0 @@\n-bye\n";
+        assert!(apply(patch, false)["content"][0]["text"].as_str().unwrap().contains("--allow-delete"));
+        let outside = apply("--- a/../escape.txt\n+++ b/../escape.txt\n@@ -0,0 +1 @@\n+x\n", false);
+        assert!(outside["content"][0]["text"].as_str().unwrap().contains("Access denied"));
+    }
 }
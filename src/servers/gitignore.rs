@@ -0,0 +1,95 @@
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use std::path::Path;
+
+/// The `.gitignore` rules in force while walking a directory tree: those of the
+/// repository's directories from its root down to the directory being walked, innermost
+/// last. Outside a repository only the `.gitignore` files inside the walked tree count.
+pub struct GitIgnores {
+    enabled: bool,
+    stack: Vec<Gitignore>,
+}
+
+impl GitIgnores {
+    /// Rules for walking `dir`, picking up the `.gitignore` files of `dir` and its parents
+    /// up to the repository root. A disabled set ignores nothing.
+    pub fn new(enabled: bool, dir: &Path) -> Self {
+        let mut ignores = Self { enabled, stack: vec![] };
+        if enabled {
+            let top = dir.ancestors().find(|ancestor| ancestor.join(".git").exists()).unwrap_or(dir);
+            let mut dirs: Vec<&Path> = dir.ancestors().take_while(|ancestor| *ancestor != top).collect();
+            dirs.push(top);
+            for ancestor in dirs.into_iter().rev() {
+                ignores.enter(ancestor);
+            }
+        }
+        ignores
+    }
+
+    /// Descend into `dir`, adding the rules of its `.gitignore` if it has one. Unreadable
+    /// or malformed files add what could be read of them.
+    pub fn enter(&mut self, dir: &Path) {
+        if !self.enabled {
+            return;
+        }
+        let mut builder = GitignoreBuilder::new(dir);
+        builder.add(dir.join(".gitignore"));
+        self.stack.push(builder.build().unwrap_or_else(|_| Gitignore::empty()));
+    }
+
+    /// Come back out of the directory last entered
+    pub fn leave(&mut self) {
+        self.stack.pop();
+    }
+
+    /// Whether `path` is ignored. The `.git` directory always is; otherwise the innermost
+    /// `.gitignore` with a rule for it decides, `!pattern` rules included.
+    pub fn ignores(&self, path: &Path, is_dir: bool) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        if is_dir && path.file_name().is_some_and(|name| name == ".git") {
+            return true;
+        }
+        for rules in self.stack.iter().rev() {
+            match rules.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_git_ignores() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::create_dir_all(root.join("web/src")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(root.join("web/.gitignore"), "node_modules\n!keep.log\n").unwrap();
+
+        // Walking a subdirectory still picks up the repository root's rules
+        let mut ignores = GitIgnores::new(true, &root.join("web"));
+        assert!(ignores.ignores(&root.join("web/node_modules"), true));
+        assert!(ignores.ignores(&root.join("web/debug.log"), false));
+        assert!(!ignores.ignores(&root.join("web/keep.log"), false));
+        ignores.enter(&root.join("web/src"));
+        assert!(ignores.ignores(&root.join("web/src/target"), true));
+        assert!(!ignores.ignores(&root.join("web/src/target"), false));
+        assert!(!ignores.ignores(&root.join("web/src/main.js"), false));
+        ignores.leave();
+
+        let ignores = GitIgnores::new(true, &root);
+        assert!(ignores.ignores(&root.join(".git"), true));
+        assert!(!ignores.ignores(&root.join("web/node_modules"), true));
+        assert!(!GitIgnores::new(false, &root).ignores(&root.join("debug.log"), false));
+    }
+}
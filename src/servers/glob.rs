@@ -0,0 +1,109 @@
+use anyhow::{anyhow, Result};
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+
+/// Glob patterns, compiled once to match many paths. `*` and `?` match within one name and
+/// `**` any number of whole names (`**/test.rs`; `src/**` matches `src` too); `[abc]`,
+/// `[a-z]` and `[!a-z]` match one character of a class, and `{rs,toml}` any one of the
+/// alternatives. A pattern without a `/` matches a name at any depth, as in `.gitignore`; one
+/// with a `/` matches the whole relative path.
+#[derive(Default)]
+pub struct Globs {
+    names: GlobSet,
+    paths: GlobSet,
+}
+
+impl Globs {
+    pub fn new(patterns: &[String], ignore_case: bool) -> Result<Self> {
+        let (mut names, mut paths) = (GlobSetBuilder::new(), GlobSetBuilder::new());
+        for pattern in patterns {
+            if !pattern.contains('/') {
+                names.add(build(pattern, ignore_case)?);
+                continue;
+            }
+            paths.add(build(pattern, ignore_case)?);
+            // As `**` matches no names as well, the directory itself
+            if let Some(dir) = pattern.strip_suffix("/**").filter(|dir| !dir.is_empty()) {
+                paths.add(build(dir, ignore_case)?);
+            }
+        }
+        Ok(Self { names: names.build()?, paths: paths.build()? })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.paths.is_empty()
+    }
+
+    /// Whether a pattern matches `path`, relative and `/`-separated
+    pub fn matches(&self, path: &str) -> bool {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        self.names.is_match(name) || self.paths.is_match(path)
+    }
+}
+
+fn build(pattern: &str, ignore_case: bool) -> Result<Glob> {
+    GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|e| anyhow!("Invalid glob pattern '{}': {}", pattern, e.kind()))
+}
+
+/// Whether the single name `name` (a tool or command name) matches `pattern`. A pattern that
+/// isn't a valid glob only matches itself.
+pub fn matches_name(pattern: &str, name: &str) -> bool {
+    match build(pattern, false) {
+        Ok(glob) => glob.compile_matcher().is_match(name),
+        Err(_) => pattern == name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        Globs::new(&[pattern.to_string()], false).unwrap().matches(path)
+    }
+
+    #[test]
+    fn test_globs() {
+        // Simple patterns, which match names at any depth
+        assert!(matches("*.rs", "main.rs"));
+        assert!(matches("*.rs", "lib.rs"));
+        assert!(!matches("*.rs", "main.txt"));
+        assert!(matches("*.rs", "src/main.rs"));
+
+        // ** patterns
+        assert!(matches("**/*.rs", "src/main.rs"));
+        assert!(matches("**/*.rs", "src/lib/mod.rs"));
+        assert!(matches("**/test.rs", "test.rs"));
+        assert!(matches("**/test.rs", "src/test.rs"));
+        assert!(matches("src/**", "src/lib/mod.rs"));
+        assert!(matches("**/secrets/**", "app/secrets"));
+
+        // Mixed patterns
+        assert!(matches("src/*.rs", "src/main.rs"));
+        assert!(!matches("src/*.rs", "lib/main.rs"));
+        assert!(!matches("src/*.rs", "src/bin/main.rs"));
+
+        // Classes, alternatives and case
+        assert!(matches("*.{rs,toml}", "Cargo.toml"));
+        assert!(matches("file[0-9].txt", "file7.txt"));
+        assert!(!matches("file[!0-9].txt", "file7.txt"));
+        assert!(!matches("*.md", "README.MD"));
+        assert!(Globs::new(&["*.md".to_string()], true).unwrap().matches("README.MD"));
+
+        let error = Globs::new(&["file[0-9.txt".to_string()], false).err().unwrap();
+        assert!(error.to_string().starts_with("Invalid glob pattern 'file[0-9.txt'"), "{}", error);
+        assert!(Globs::default().is_empty() && !Globs::default().matches("main.rs"));
+    }
+
+    #[test]
+    fn test_matches_name() {
+        assert!(matches_name("read_*", "read_file"));
+        assert!(matches_name("ls*", "ls"));
+        assert!(!matches_name("ls*", "cat"));
+        assert!(matches_name("{ls,cat}", "cat"));
+        assert!(matches_name("[", "["));
+    }
+}
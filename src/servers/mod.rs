@@ -1,7 +1,24 @@
+pub mod affinity;
+pub mod archive;
+pub mod audit;
+pub mod breaker;
+pub mod cache;
 pub mod common;
 pub mod filesystem;
+pub mod gitignore;
+pub mod glob;
+pub mod middleware;
+pub mod patch;
+pub mod prompts;
+pub mod proxy;
+pub mod ratelimit;
+pub mod replicas;
+pub mod router;
 pub mod shell;
 pub mod sql;
+pub mod strict;
+pub mod subscriptions;
+pub mod watches;
 
 pub use filesystem::run_filesystem_server;
 pub use shell::run_shell_server;
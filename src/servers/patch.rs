@@ -0,0 +1,330 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::fmt;
+
+/// One file's changes in a unified diff
+#[derive(Debug)]
+pub struct FilePatch {
+    /// The path before and after the change; None for `/dev/null`, so a created file has no
+    /// old path and a deleted one no new path
+    pub old_path: Option<String>,
+    pub new_path: Option<String>,
+    pub hunks: Vec<Hunk>,
+}
+
+/// One `@@` hunk
+#[derive(Debug, Default)]
+pub struct Hunk {
+    /// Line of the original file the hunk starts at, from 1 (0 for an empty file); None if
+    /// its header has no line numbers, so it's looked for anywhere
+    old_start: Option<usize>,
+    old_count: Option<usize>,
+    lines: Vec<(LineKind, String)>,
+    /// Whether `\ No newline at end of file` followed the original's or the new text's last line
+    old_no_newline: bool,
+    new_no_newline: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LineKind {
+    Context,
+    Remove,
+    Add,
+}
+
+impl FilePatch {
+    /// The file the patch is about: its new path, or its old one if it's deleted
+    pub fn path(&self) -> &str {
+        self.new_path.as_deref().or(self.old_path.as_deref()).unwrap_or_default()
+    }
+}
+
+impl Hunk {
+    /// The lines the hunk expects to find
+    fn old_lines(&self) -> Vec<&str> {
+        self.lines.iter().filter(|(kind, _)| *kind != LineKind::Add).map(|(_, line)| line.as_str()).collect()
+    }
+
+    /// The lines it puts in their place
+    fn new_lines(&self) -> Vec<&str> {
+        self.lines.iter().filter(|(kind, _)| *kind != LineKind::Remove).map(|(_, line)| line.as_str()).collect()
+    }
+}
+
+/// Parse a unified diff of one or more files, as `diff -u` and `git diff` write them. Lines
+/// outside the files' hunks (`diff --git`, `index`, commentary) are skipped, and `a/` and
+/// `b/` path prefixes are dropped. Hunk headers without line numbers (`@@ ... @@`) are
+/// accepted, as are context lines whose leading space was lost.
+pub fn parse(diff: &str) -> Result<Vec<FilePatch>> {
+    let lines: Vec<&str> = diff.lines().collect();
+    let mut files: Vec<FilePatch> = vec![];
+    let mut i = 0;
+    while i < lines.len() {
+        if let Some((old, new)) = file_header(&lines, i) {
+            let git = (old.starts_with("a/") || old == "/dev/null") && (new.starts_with("b/") || new == "/dev/null");
+            let path = |path: &str, prefix: &str| {
+                let path = if git { path.strip_prefix(prefix).unwrap_or(path) } else { path };
+                (path != "/dev/null").then(|| path.to_string())
+            };
+            files.push(FilePatch { old_path: path(old, "a/"), new_path: path(new, "b/"), hunks: vec![] });
+            i += 2;
+        } else if lines[i].starts_with("@@") {
+            let file = files
+                .last_mut()
+                .ok_or_else(|| anyhow!("Hunk before any '--- '/'+++ ' file header: {}", lines[i]))?;
+            let (hunk, next) = parse_hunk(&lines, i);
+            file.hunks.push(hunk);
+            i = next;
+        } else {
+            i += 1;
+        }
+    }
+    if files.is_empty() {
+        return Err(anyhow!("No '--- a/path' and '+++ b/path' file headers found in the patch"));
+    }
+    Ok(files)
+}
+
+/// The old and new paths of the file header starting at `lines[i]`, without timestamps
+fn file_header<'a>(lines: &[&'a str], i: usize) -> Option<(&'a str, &'a str)> {
+    let old = lines[i].strip_prefix("--- ")?;
+    let new = lines.get(i + 1)?.strip_prefix("+++ ")?;
+    let path = |header: &'a str| header.split('\t').next().unwrap_or(header).trim();
+    Some((path(old), path(new)))
+}
+
+/// The hunk whose header is `lines[start]`, and the index of the line after it
+fn parse_hunk(lines: &[&str], start: usize) -> (Hunk, usize) {
+    let ranges: Vec<&str> = lines[start].trim_start_matches('@').split_whitespace().take(2).collect();
+    let range = |sign: &str| -> Option<(usize, usize)> {
+        let range = ranges.iter().find_map(|range| range.strip_prefix(sign))?;
+        match range.split_once(',') {
+            Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+            None => Some((range.parse().ok()?, 1)),
+        }
+    };
+    let old_range = range("-");
+    let mut hunk = Hunk {
+        old_start: old_range.map(|(start, _)| start),
+        old_count: old_range.map(|(_, count)| count),
+        ..Hunk::default()
+    };
+
+    let mut i = start + 1;
+    while i < lines.len() {
+        let line = lines[i];
+        if line.starts_with("@@") || line.starts_with("diff ") || file_header(lines, i).is_some() {
+            break;
+        }
+        let (kind, text) = match line.chars().next() {
+            Some(' ') => (LineKind::Context, &line[1..]),
+            Some('-') => (LineKind::Remove, &line[1..]),
+            Some('+') => (LineKind::Add, &line[1..]),
+            None => (LineKind::Context, ""),
+            Some('\\') => {
+                match hunk.lines.last().map(|(kind, _)| *kind) {
+                    Some(LineKind::Remove) => hunk.old_no_newline = true,
+                    Some(LineKind::Add) => hunk.new_no_newline = true,
+                    _ => (hunk.old_no_newline, hunk.new_no_newline) = (true, true),
+                }
+                i += 1;
+                continue;
+            }
+            Some(_) => break,
+        };
+        hunk.lines.push((kind, text.to_string()));
+        i += 1;
+    }
+    // Blank lines past what the header counts are the patch's own, not context
+    while hunk.old_count.is_some_and(|count| hunk.old_lines().len() > count)
+        && hunk.lines.last().is_some_and(|(kind, line)| *kind == LineKind::Context && line.is_empty())
+    {
+        hunk.lines.pop();
+    }
+    (hunk, i)
+}
+
+/// What became of one hunk
+#[derive(Debug, Serialize)]
+pub struct HunkResult {
+    /// The hunk's number in its file, from 1
+    pub hunk: usize,
+    pub applied: bool,
+    /// Line the hunk was applied at, from 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    /// Lines between that and where the hunk's header put it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<i64>,
+    /// Why it wasn't applied
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Apply `hunks` in order to `content`. Each hunk is looked for where its header says (moved
+/// as far as earlier hunks were, and by what they added or removed), then ever further from
+/// there, and never before an earlier hunk; lines that differ only in trailing whitespace
+/// still match. Returns the patched content if every hunk applied, and what became of each.
+pub fn apply(content: &str, hunks: &[Hunk]) -> (Option<String>, Vec<HunkResult>) {
+    let crlf = content.contains("\r\n");
+    let mut ends_with_newline = content.is_empty() || content.ends_with('\n');
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let (mut shift, mut floor, mut all_applied) = (0i64, 0usize, true);
+    let mut results = vec![];
+
+    for (n, hunk) in hunks.iter().enumerate() {
+        let (old, new) = (hunk.old_lines(), hunk.new_lines());
+        let expected = hunk.old_start.map(|start| (start.saturating_sub(1) as i64 + shift).max(0) as usize);
+        let Some(at) = find(&lines, &old, expected.unwrap_or(floor), floor) else {
+            all_applied = false;
+            let near = expected.map(|line| format!(" near line {}", line + 1)).unwrap_or_default();
+            let reason = format!("its {} original lines weren't found{}", old.len(), near);
+            results.push(HunkResult { hunk: n + 1, applied: false, line: None, offset: None, reason: Some(reason) });
+            continue;
+        };
+        lines.splice(at..at + old.len(), new.iter().map(|line| line.to_string()));
+        if at + new.len() == lines.len() {
+            if hunk.new_no_newline {
+                ends_with_newline = false;
+            } else if hunk.old_no_newline {
+                ends_with_newline = true;
+            }
+        }
+        // Later hunks have likely moved as far as this one did
+        let offset = expected.map(|expected| at as i64 - expected as i64).filter(|offset| *offset != 0);
+        shift += offset.unwrap_or(0) + new.len() as i64 - old.len() as i64;
+        floor = at + new.len();
+        results.push(HunkResult { hunk: n + 1, applied: true, line: Some(at + 1), offset, reason: None });
+    }
+
+    if !all_applied {
+        return (None, results);
+    }
+    let mut patched = lines.join(if crlf { "\r\n" } else { "\n" });
+    if ends_with_newline && !lines.is_empty() {
+        patched.push_str(if crlf { "\r\n" } else { "\n" });
+    }
+    (Some(patched), results)
+}
+
+/// Where `old` is in `lines`, at or after `floor`, nearest `expected` first
+fn find(lines: &[String], old: &[&str], expected: usize, floor: usize) -> Option<usize> {
+    let last = lines.len().checked_sub(old.len())?;
+    if floor > last {
+        return None;
+    }
+    let expected = expected.clamp(floor, last);
+    let at = |start: usize, same: &dyn Fn(&str, &str) -> bool| {
+        old.iter().enumerate().all(|(i, line)| same(&lines[start + i], line))
+    };
+    let candidates: Vec<usize> = (0..=last.max(expected))
+        .flat_map(|distance| [expected.checked_add(distance), expected.checked_sub(distance).filter(|_| distance > 0)])
+        .flatten()
+        .filter(|start| (floor..=last).contains(start))
+        .collect();
+    let exact = |a: &str, b: &str| a == b;
+    let loose = |a: &str, b: &str| a.trim_end() == b.trim_end();
+    candidates
+        .iter()
+        .find(|start| at(**start, &exact))
+        .or_else(|| candidates.iter().find(|start| at(**start, &loose)))
+        .copied()
+}
+
+/// What `apply_patch` did, or would do, to one file
+#[derive(Debug, Serialize)]
+pub struct PatchedFile {
+    pub path: String,
+    /// "modified", "created", "deleted", "renamed" or "rejected"
+    pub status: &'static str,
+    pub hunks: Vec<HunkResult>,
+    /// Why the file can't be patched, when no hunk is to blame
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What `apply_patch` did: every file changed, or none did
+#[derive(Debug, Serialize)]
+pub struct PatchOutcome {
+    pub files: Vec<PatchedFile>,
+    pub applied: bool,
+    #[serde(rename = "dryRun")]
+    pub dry_run: bool,
+}
+
+impl fmt::Display for PatchOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let summary = match (self.applied, self.dry_run) {
+            (true, false) => format!("Patched {} files", self.files.len()),
+            (true, true) => format!("Patch applies cleanly to {} files (dry run; nothing written)", self.files.len()),
+            (false, _) => "Patch not applied; no files were changed".to_string(),
+        };
+        write!(f, "{}", summary)?;
+        for file in &self.files {
+            write!(f, "\n{}: {}", file.path, file.status)?;
+            if let Some(error) = &file.error {
+                write!(f, " - {}", error)?;
+            }
+            for hunk in &file.hunks {
+                match (hunk.line, &hunk.reason) {
+                    (Some(line), _) => {
+                        write!(f, "\n  hunk {} applied at line {}", hunk.hunk, line)?;
+                        if let Some(offset) = hunk.offset {
+                            write!(f, " (offset {:+} lines)", offset)?;
+                        }
+                    }
+                    (None, reason) => {
+                        write!(f, "\n  hunk {} rejected: {}", hunk.hunk, reason.as_deref().unwrap_or_default())?
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let diff = "diff --git a/src/lib.rs b/src/lib.rs\nindex 1..2 100644\n--- a/src/lib.rs\n+++ b/src/lib.rs\n\
+                    @@ -1,3 +1,3 @@ fn main\n fn one() {}\n-fn two() {}\n+fn deux() {}\n\n\
+                    --- /dev/null\n+++ b/NEW.md\n@@ -0,0 +1 @@\n+# New\n\\ No newline at end of file\n";
+        let files = parse(diff).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].old_path.as_deref(), Some("src/lib.rs"));
+        assert_eq!(files[0].hunks[0].old_lines(), ["fn one() {}", "fn two() {}", ""]);
+        assert_eq!(files[0].hunks[0].new_lines(), ["fn one() {}", "fn deux() {}", ""]);
+        assert_eq!((files[1].old_path.as_deref(), files[1].path()), (None, "NEW.md"));
+        assert!(files[1].hunks[0].new_no_newline);
+        assert!(parse("just some text").is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let content: String = (1..=10).map(|n| format!("line {}\n", n)).collect();
+        // The file gained two lines at the top since the diff was made
+        let content = format!("new a\nnew b\n{}", content);
+        let diff = "--- a/f\n+++ b/f\n@@ -2,3 +2,4 @@\n line 2\n line 3\n+inserted\n line 4\n\
+                    @@ -8,3 +9,2 @@\n line 8\n-line 9\n line 10\n";
+        let files = parse(diff).unwrap();
+        let (patched, results) = apply(&content, &files[0].hunks);
+        let patched = patched.unwrap();
+        assert!(patched.contains("line 3\ninserted\nline 4\n") && patched.contains("line 8\nline 10\n"));
+        assert_eq!((results[0].line, results[0].offset), (Some(4), Some(2)));
+
+        // A hunk whose context isn't there is rejected, and nothing comes back
+        let diff = "--- a/f\n+++ b/f\n@@ -2,2 +2,2 @@\n line 2\n-line 3\n+line three\n@@ -5 +5 @@\n-line 99\n+x\n";
+        let (patched, results) = apply(&content, &parse(diff).unwrap()[0].hunks);
+        assert!(patched.is_none());
+        assert!(results[0].applied);
+        assert_eq!(results[1].reason.as_deref(), Some("its 1 original lines weren't found near line 7"));
+
+        // Line endings are kept, and a missing final newline is honoured
+        let diff = "--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n a\n-b\n+c\n\\ No newline at end of file\n";
+        let (patched, _) = apply("a\r\nb\r\n", &parse(diff).unwrap()[0].hunks);
+        assert_eq!(patched.as_deref(), Some("a\r\nc"));
+    }
+}
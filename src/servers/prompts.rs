@@ -0,0 +1,263 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+use super::common::{
+    ClientAnswer, CompletionRef, McpPrompt, McpResource, McpServer, McpTool, Notifier, PromptArgument, RequestContext,
+};
+
+/// `[prompts.NAME]`: a reusable prompt the gateway offers its clients, with `{{argument}}`
+/// placeholders filled in from the `prompts/get` arguments
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PromptTemplate {
+    pub description: Option<String>,
+    #[serde(default)]
+    pub arguments: Vec<PromptArgument>,
+    pub template: String,
+}
+
+impl PromptTemplate {
+    /// Reject placeholders that aren't declared arguments, and arguments declared twice
+    pub fn check(&self) -> Result<()> {
+        for (i, argument) in self.arguments.iter().enumerate() {
+            if self.arguments[..i].iter().any(|other| other.name == argument.name) {
+                return Err(anyhow!("Argument '{}' is declared twice", argument.name));
+            }
+        }
+        let mut rest = self.template.as_str();
+        while let Some((name, after)) = next_placeholder(rest)? {
+            if !self.arguments.iter().any(|argument| argument.name == name) {
+                return Err(anyhow!("Template uses undeclared argument '{}'", name));
+            }
+            rest = after;
+        }
+        Ok(())
+    }
+
+    /// The template with its placeholders filled in. Missing optional arguments are left empty.
+    pub fn render(&self, arguments: &Value) -> Result<String> {
+        let value = |name: &str| match arguments.get(name) {
+            Some(Value::String(s)) => Some(s.clone()),
+            Some(Value::Null) | None => None,
+            Some(other) => Some(other.to_string()),
+        };
+        let missing = self.arguments.iter().find(|argument| argument.required && value(&argument.name).is_none());
+        if let Some(missing) = missing {
+            return Err(anyhow!("Missing required argument: {}", missing.name));
+        }
+
+        let mut text = String::with_capacity(self.template.len());
+        let mut rest = self.template.as_str();
+        while let Some(start) = rest.find("{{") {
+            let (name, after) = next_placeholder(rest)?.expect("placeholder follows {{");
+            text.push_str(&rest[..start]);
+            text.push_str(&value(name).unwrap_or_default());
+            rest = after;
+        }
+        text.push_str(rest);
+        Ok(text)
+    }
+}
+
+/// The name of the first `{{name}}` in `text` and what follows it
+fn next_placeholder(text: &str) -> Result<Option<(&str, &str)>> {
+    let Some(start) = text.find("{{") else {
+        return Ok(None);
+    };
+    let after = &text[start + 2..];
+    let end = after.find("}}").ok_or_else(|| anyhow!("Unclosed '{{{{' in template"))?;
+    Ok(Some((after[..end].trim(), &after[end + 2..])))
+}
+
+/// A server that also offers the prompts defined in the config file, ahead of any the
+/// server has of its own
+pub struct Prompted<S> {
+    server: S,
+    prompts: BTreeMap<String, PromptTemplate>,
+}
+
+impl<S: McpServer> Prompted<S> {
+    pub fn new(server: S, prompts: BTreeMap<String, PromptTemplate>) -> Self {
+        Self { server, prompts }
+    }
+}
+
+impl<S: McpServer> McpServer for Prompted<S> {
+    fn name(&self) -> &str {
+        self.server.name()
+    }
+
+    fn version(&self) -> &str {
+        self.server.version()
+    }
+
+    fn tools(&self) -> Vec<McpTool> {
+        self.server.tools()
+    }
+
+    fn call_tool(&self, name: &str, arguments: &Value) -> Result<Value> {
+        self.server.call_tool(name, arguments)
+    }
+
+    fn call_tool_with_context(&self, name: &str, arguments: &Value, ctx: &RequestContext) -> Result<Value> {
+        self.server.call_tool_with_context(name, arguments, ctx)
+    }
+
+    fn has_resources(&self) -> bool {
+        self.server.has_resources()
+    }
+
+    fn resources(&self, ctx: &RequestContext) -> Vec<McpResource> {
+        self.server.resources(ctx)
+    }
+
+    fn read_resource(&self, uri: &str, ctx: &RequestContext) -> Result<Value> {
+        self.server.read_resource(uri, ctx)
+    }
+
+    fn has_subscriptions(&self) -> bool {
+        self.server.has_subscriptions()
+    }
+
+    fn subscribe(&self, uri: &str, ctx: &RequestContext) -> Result<()> {
+        self.server.subscribe(uri, ctx)
+    }
+
+    fn unsubscribe(&self, uri: &str, ctx: &RequestContext) -> Result<()> {
+        self.server.unsubscribe(uri, ctx)
+    }
+
+    fn has_prompts(&self) -> bool {
+        !self.prompts.is_empty() || self.server.has_prompts()
+    }
+
+    fn prompts(&self) -> Vec<McpPrompt> {
+        let own = self.prompts.iter().map(|(name, prompt)| McpPrompt {
+            name: name.clone(),
+            description: prompt.description.clone(),
+            arguments: prompt.arguments.clone(),
+        });
+        let theirs = self.server.prompts().into_iter().filter(|prompt| !self.prompts.contains_key(&prompt.name));
+        own.chain(theirs).collect()
+    }
+
+    fn get_prompt(&self, name: &str, arguments: &Value) -> Result<Value> {
+        let Some(prompt) = self.prompts.get(name) else {
+            return self.server.get_prompt(name, arguments);
+        };
+        let mut result = json!({
+            "messages": [{ "role": "user", "content": { "type": "text", "text": prompt.render(arguments)? } }]
+        });
+        if let Some(description) = &prompt.description {
+            result["description"] = json!(description);
+        }
+        Ok(result)
+    }
+
+    fn has_completions(&self) -> bool {
+        self.server.has_completions()
+    }
+
+    fn complete(
+        &self,
+        reference: &CompletionRef,
+        argument: &str,
+        value: &str,
+        ctx: &RequestContext,
+    ) -> Result<Vec<String>> {
+        self.server.complete(reference, argument, value, ctx)
+    }
+
+    fn on_initialize(&self, params: &Value, ctx: &RequestContext) {
+        self.server.on_initialize(params, ctx)
+    }
+
+    fn on_notification(&self, method: &str, params: &Value, ctx: &RequestContext) {
+        self.server.on_notification(method, params, ctx)
+    }
+
+    fn on_response(&self, id: &Value, response: ClientAnswer, ctx: &RequestContext) {
+        self.server.on_response(id, response, ctx)
+    }
+
+    fn on_session_end(&self, session_id: Option<&str>) {
+        self.server.on_session_end(session_id)
+    }
+
+    fn notifier(&self) -> Option<&Notifier> {
+        self.server.notifier()
+    }
+
+    fn strict(&self) -> bool {
+        self.server.strict()
+    }
+
+    fn verbose(&self) -> bool {
+        self.server.verbose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::servers::common::JsonRpcRequest;
+    use crate::servers::shell::{ShellServer, ShellServerConfig};
+
+    fn summarize() -> PromptTemplate {
+        toml::from_str(
+            r#"
+description = "Summarize a directory"
+template = "Summarize {{ path }} for {{audience}}."
+arguments = [
+    { name = "path", description = "Directory to summarize", required = true },
+    { name = "audience" },
+]
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_render_template() {
+        let prompt = summarize();
+        prompt.check().unwrap();
+        assert_eq!(
+            prompt.render(&json!({"path": "src", "audience": "new hires"})).unwrap(),
+            "Summarize src for new hires."
+        );
+        assert_eq!(prompt.render(&json!({"path": "src"})).unwrap(), "Summarize src for .");
+        assert_eq!(prompt.render(&json!({})).unwrap_err().to_string(), "Missing required argument: path");
+
+        let undeclared = PromptTemplate { template: "{{path}} {{depth}}".to_string(), ..summarize() };
+        assert!(undeclared.check().unwrap_err().to_string().contains("undeclared argument 'depth'"));
+        let unclosed = PromptTemplate { template: "{{path".to_string(), ..summarize() };
+        assert!(unclosed.check().unwrap_err().to_string().contains("Unclosed"));
+    }
+
+    #[test]
+    fn test_prompts_list_and_get() {
+        let config = ShellServerConfig::new(None, 30, "/bin/sh".to_string(), None, None, false, false);
+        let server = Prompted::new(ShellServer::new(config), BTreeMap::from([("summarize".to_string(), summarize())]));
+        let request = |method: &str, params: Value| {
+            let request: JsonRpcRequest =
+                serde_json::from_value(json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params})).unwrap();
+            serde_json::to_value(server.handle_request(request).unwrap()).unwrap()
+        };
+
+        let init = request("initialize", json!({}));
+        assert_eq!(init["result"]["capabilities"]["prompts"], json!({}));
+        let list = request("prompts/list", json!({}));
+        assert_eq!(list["result"]["prompts"][0]["name"], "summarize");
+        assert_eq!(list["result"]["prompts"][0]["arguments"][0]["required"], true);
+
+        let get = request("prompts/get", json!({"name": "summarize", "arguments": {"path": "docs"}}));
+        assert_eq!(get["result"]["description"], "Summarize a directory");
+        assert_eq!(get["result"]["messages"][0]["content"]["text"], "Summarize docs for .");
+        let missing = request("prompts/get", json!({"name": "summarize"}));
+        assert_eq!(missing["error"]["code"], -32602);
+        let unknown = request("prompts/get", json!({"name": "nope"}));
+        assert_eq!(unknown["error"]["message"], "Unknown prompt: nope");
+    }
+}
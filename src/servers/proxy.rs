@@ -0,0 +1,475 @@
+use anyhow::Result;
+use colored::Colorize;
+use serde_json::{json, Value};
+use std::fmt;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::common::{ClientAnswer, JsonRpcRequest, JsonRpcResponse, McpServer, McpTool, Notifier, RequestContext};
+use crate::client::{refuse, McpClient, StdioClient};
+
+/// How long a forwarded request may take before the HTTP client gets an error
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Most `tools/list` pages read from a server
+const MAX_PAGES: usize = 100;
+
+/// A JSON-RPC error the upstream server answered a tool call with (as opposed to no answer at all)
+#[derive(Debug)]
+pub struct UpstreamError {
+    pub code: i64,
+    pub message: String,
+    pub data: Option<Value>,
+}
+
+impl fmt::Display for UpstreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for UpstreamError {}
+
+/// Serves a spawned stdio MCP server (or a remote HTTP one) through the `McpServer`
+/// trait, so the HTTP transport and the router can expose it. All sessions share the
+/// one upstream connection.
+pub struct ProxyServer {
+    client: Arc<Mutex<Box<dyn McpClient + Send>>>,
+    name: String,
+    version: String,
+    /// The child's `initialize` result, replayed to every HTTP session
+    init: Value,
+    /// Tools reported at startup, or since the server last said they changed (live
+    /// `tools/list` calls are forwarded)
+    tools: Mutex<Vec<McpTool>>,
+    /// Set by the server's `notifications/tools/list_changed` until `tools` is read again
+    tools_changed: Arc<AtomicBool>,
+    /// Passes the server's `notifications/tools/list_changed` on to clients and the router
+    notifier: Arc<Notifier>,
+    verbose: bool,
+}
+
+impl ProxyServer {
+    /// Spawn the server and complete the MCP handshake within `timeout`
+    pub fn start(cmd: Command, timeout: Duration, sampling: bool, verbose: bool) -> Result<Self> {
+        Self::connect(Box::new(StdioClient::spawn(cmd)?), timeout, sampling, verbose)
+    }
+
+    /// Complete the MCP handshake over an existing connection within `timeout`. With
+    /// `sampling`, the server is told mcpz can sample, and its `sampling/createMessage`
+    /// requests are passed on to the client of the call (see `RequestContext::client`).
+    pub fn connect(
+        mut client: Box<dyn McpClient + Send>,
+        timeout: Duration,
+        sampling: bool,
+        verbose: bool,
+    ) -> Result<Self> {
+        let capabilities = if sampling { json!({"sampling": {}}) } else { json!({}) };
+        let init = client.initialize_with(capabilities, timeout)?;
+
+        let server_info = init.get("serverInfo");
+        let field = |key: &str| {
+            server_info
+                .and_then(|info| info.get(key))
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown")
+                .to_string()
+        };
+
+        let tools = match init.pointer("/capabilities/tools") {
+            Some(_) => list_tools(client.as_mut(), timeout)?,
+            None => vec![],
+        };
+
+        // Servers whose tools can change say when they do
+        let tools_changed = Arc::new(AtomicBool::new(false));
+        let notifier = if init.pointer("/capabilities/tools/listChanged") == Some(&json!(true)) {
+            let notifier = Arc::new(Notifier::default());
+            let (changed, notify) = (tools_changed.clone(), notifier.clone());
+            client.listen(Arc::new(move |message: Value| {
+                if message.get("method").and_then(Value::as_str) == Some("notifications/tools/list_changed") {
+                    changed.store(true, Ordering::Relaxed);
+                    notify.notify("notifications/tools/list_changed");
+                }
+            }))?;
+            notifier
+        } else {
+            Arc::new(Notifier::logs_only())
+        };
+
+        Ok(Self {
+            name: field("name"),
+            version: field("version"),
+            client: Arc::new(Mutex::new(client)),
+            init,
+            tools: Mutex::new(tools),
+            tools_changed,
+            notifier,
+            verbose,
+        })
+    }
+
+    /// Ping the server every `interval` while the connection is idle, stopping it if it
+    /// doesn't answer within another `interval`, so a hung process fails its calls instead
+    /// of holding each for the whole request timeout. Pinging ends with the proxy.
+    pub fn keepalive(&self, interval: Duration) {
+        let client = Arc::downgrade(&self.client);
+        let name = self.name.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(interval);
+            let Some(client) = client.upgrade() else {
+                return;
+            };
+            // A call in progress shows the server is busy rather than hung
+            let Ok(mut client) = client.try_lock() else {
+                continue;
+            };
+            // Servers that don't know ping still answer, with an error
+            if let Err(e) = client.call("ping", json!({}), interval) {
+                let message = format!("[mcpz] {} didn't answer a ping; stopping it: {}", name, e);
+                eprintln!("{}", message.yellow());
+                client.stop();
+                return;
+            }
+        });
+    }
+
+    /// Relay a request (or notification) to the child and translate its answer
+    fn forward(&self, req: JsonRpcRequest) -> Option<JsonRpcResponse> {
+        let params = if req.params.is_null() { json!({}) } else { req.params };
+        let mut client = self.client.lock().unwrap();
+
+        let Some(id) = req.id else {
+            if let Err(e) = client.notify(&req.method, params) {
+                self.log(&format!("Failed to forward {}: {}", req.method, e));
+            }
+            return None;
+        };
+
+        Some(match client.call(&req.method, params, REQUEST_TIMEOUT) {
+            Ok(Ok(result)) => JsonRpcResponse::success(Some(id), result),
+            Ok(Err(error)) => JsonRpcResponse::error(
+                Some(id),
+                error.get("code").and_then(|c| c.as_i64()).unwrap_or(-32603) as i32,
+                error
+                    .get("message")
+                    .and_then(|m| m.as_str())
+                    .unwrap_or("unknown error")
+                    .to_string(),
+            ),
+            Err(e) => JsonRpcResponse::internal_error(Some(id), e.to_string()),
+        })
+    }
+
+    /// Answer a request the server makes during a call: sampling goes to the call's
+    /// client, if it can sample, and anything else is refused
+    fn answer(&self, method: &str, params: &Value, ctx: &RequestContext) -> ClientAnswer {
+        let Some(client) = ctx.client.as_ref().filter(|_| method == "sampling/createMessage") else {
+            return refuse(method, params);
+        };
+        self.log(&format!("Passing {} on to the client", method));
+        match client.ask(ctx.session_id.as_deref(), method, params.clone(), REQUEST_TIMEOUT) {
+            Ok(answer) => answer,
+            Err(e) => Err(json!({"code": -32603, "message": e.to_string()})),
+        }
+    }
+}
+
+/// Every page of the server's tools
+fn list_tools(client: &mut dyn McpClient, timeout: Duration) -> Result<Vec<McpTool>> {
+    let mut tools = vec![];
+    let mut params = json!({});
+    for _ in 0..MAX_PAGES {
+        let page = client.request("tools/list", params, timeout)?;
+        tools.extend(parse_tools(&page));
+        match page.get("nextCursor").and_then(Value::as_str) {
+            Some(cursor) => params = json!({ "cursor": cursor }),
+            None => break,
+        }
+    }
+    Ok(tools)
+}
+
+/// Tool definitions from a `tools/list` result, skipping entries without a name
+fn parse_tools(result: &Value) -> Vec<McpTool> {
+    result
+        .get("tools")
+        .and_then(|t| t.as_array())
+        .map(|tools| {
+            tools
+                .iter()
+                .filter_map(|tool| {
+                    Some(McpTool {
+                        name: tool.get("name")?.as_str()?.to_string(),
+                        description: tool
+                            .get("description")
+                            .and_then(|d| d.as_str())
+                            .unwrap_or_default()
+                            .to_string(),
+                        input_schema: tool.get("inputSchema").cloned().unwrap_or(json!({})),
+                        output_schema: tool.get("outputSchema").cloned(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl McpServer for ProxyServer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// The tools, read again if the server said they changed
+    fn tools(&self) -> Vec<McpTool> {
+        if self.tools_changed.swap(false, Ordering::Relaxed) {
+            let listed = list_tools(self.client.lock().unwrap().as_mut(), REQUEST_TIMEOUT);
+            match listed {
+                Ok(tools) => *self.tools.lock().unwrap() = tools,
+                Err(e) => {
+                    self.log(&format!("Failed to list the changed tools: {}", e));
+                    self.tools_changed.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+        self.tools
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|tool| McpTool {
+                name: tool.name.clone(),
+                description: tool.description.clone(),
+                input_schema: tool.input_schema.clone(),
+                output_schema: tool.output_schema.clone(),
+            })
+            .collect()
+    }
+
+    fn call_tool(&self, name: &str, arguments: &Value) -> Result<Value> {
+        self.call_tool_with_context(name, arguments, &RequestContext::default())
+    }
+
+    fn call_tool_with_context(&self, name: &str, arguments: &Value, ctx: &RequestContext) -> Result<Value> {
+        let params = json!({"name": name, "arguments": arguments});
+        let mut answer = |method: &str, params: &Value| self.answer(method, params, ctx);
+        let mut client = self.client.lock().unwrap();
+        client
+            .call_answering("tools/call", params, REQUEST_TIMEOUT, &mut answer)?
+            .map_err(|error| {
+                UpstreamError {
+                    code: error.get("code").and_then(|c| c.as_i64()).unwrap_or(-32603),
+                    message: error
+                        .get("message")
+                        .and_then(|m| m.as_str())
+                        .unwrap_or("unknown error")
+                        .to_string(),
+                    data: error.get("data").cloned(),
+                }
+                .into()
+            })
+    }
+
+    fn verbose(&self) -> bool {
+        self.verbose
+    }
+
+    fn notifier(&self) -> Option<&Notifier> {
+        Some(&self.notifier)
+    }
+
+    fn handle_initialize(&self) -> Value {
+        self.init.clone()
+    }
+
+    /// Answer the handshake locally (the child is already initialized) and forward everything else
+    fn handle_request_with_context(&self, req: JsonRpcRequest, _ctx: &RequestContext) -> Option<JsonRpcResponse> {
+        match req.method.as_str() {
+            "initialize" => Some(JsonRpcResponse::success(req.id, self.handle_initialize())),
+            "initialized" | "notifications/initialized" => None,
+            _ => self.forward(req),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A shell MCP server that echoes the id of each request back
+    const FAKE_SERVER: &str = r#"
+        while read -r line; do
+            id=$(echo "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+            case "$line" in
+                *'"initialize"'*)
+                    echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"protocolVersion\":\"2024-11-05\",\"serverInfo\":{\"name\":\"fake\",\"version\":\"0.2.0\"},\"capabilities\":{\"tools\":{}}}}" ;;
+                *'"tools/list"'*'"cursor"'*)
+                    echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"tools\":[{\"name\":\"ping\",\"inputSchema\":{\"type\":\"object\"}}]}}" ;;
+                *'"tools/list"'*)
+                    echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"tools\":[{\"name\":\"echo\",\"inputSchema\":{\"type\":\"object\"}}],\"nextCursor\":\"2\"}}" ;;
+                *'"tools/call"'*)
+                    echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"content\":[{\"type\":\"text\",\"text\":\"pong\"}]}}" ;;
+                *'"id"'*)
+                    echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"error\":{\"code\":-32601,\"message\":\"Method not found\"}}" ;;
+            esac
+        done
+    "#;
+
+    fn start() -> ProxyServer {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", FAKE_SERVER]);
+        ProxyServer::start(cmd, Duration::from_secs(5), false, false).unwrap()
+    }
+
+    fn request(id: u64, method: &str) -> JsonRpcRequest {
+        serde_json::from_value(json!({"jsonrpc": "2.0", "id": id, "method": method, "params": {}})).unwrap()
+    }
+
+    #[test]
+    fn test_proxy_start_reads_server_info() {
+        let proxy = start();
+        assert_eq!(proxy.name(), "fake");
+        assert_eq!(proxy.version(), "0.2.0");
+        // Every page of tools/list is read
+        let names: Vec<String> = proxy.tools().into_iter().map(|tool| tool.name).collect();
+        assert_eq!(names, ["echo", "ping"]);
+
+        let init = proxy.handle_request(request(1, "initialize")).unwrap();
+        assert_eq!(init.result.unwrap()["serverInfo"]["name"], "fake");
+    }
+
+    #[test]
+    fn test_proxy_forwards_requests() {
+        let proxy = start();
+
+        let response = proxy.handle_request(request(7, "tools/call")).unwrap();
+        assert_eq!(response.id, Some(json!(7)));
+        assert_eq!(response.result.unwrap()["content"][0]["text"], "pong");
+
+        let response = proxy.handle_request(request(8, "resources/list")).unwrap();
+        let error = response.error.unwrap();
+        assert_eq!(error.code, -32601);
+        assert_eq!(error.message, "Method not found");
+    }
+
+    #[test]
+    fn test_proxy_notifications_have_no_response() {
+        let proxy = start();
+        let notification: JsonRpcRequest =
+            serde_json::from_value(json!({"jsonrpc": "2.0", "method": "notifications/cancelled"})).unwrap();
+        assert!(proxy.handle_request(notification).is_none());
+    }
+
+    /// Asks for a sampling on every tool call, answering with what the sampling said
+    const SAMPLING_SERVER: &str = r#"
+        while read -r line; do
+            id=$(echo "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+            case "$line" in
+                *'"initialize"'*'"sampling"'*)
+                    echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"capabilities\":{}}}" ;;
+                *'"tools/call"'*)
+                    echo '{"jsonrpc":"2.0","id":"s1","method":"sampling/createMessage","params":{"maxTokens":5}}'
+                    read -r answer
+                    case "$answer" in
+                        *'"text":"hi"'*) text=sampled ;;
+                        *'-32601'*) text=refused ;;
+                        *) text=unexpected ;;
+                    esac
+                    echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"content\":[{\"type\":\"text\",\"text\":\"$text\"}]}}" ;;
+            esac
+        done
+    "#;
+
+    #[test]
+    fn test_proxy_passes_sampling_to_the_client() {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", SAMPLING_SERVER]);
+        let proxy = ProxyServer::start(cmd, Duration::from_secs(5), true, false).unwrap();
+        let text = |result: Value| result["content"][0]["text"].as_str().unwrap().to_string();
+
+        // Nobody to ask: the server is refused
+        assert_eq!(text(proxy.call_tool("ask", &json!({})).unwrap()), "refused");
+
+        let client = std::sync::Arc::new(crate::servers::common::Notifier::default());
+        let mut requests = client.subscribe();
+        let answering = client.clone();
+        let answerer = std::thread::spawn(move || {
+            let request = loop {
+                match requests.try_recv() {
+                    Ok(request) => break request,
+                    Err(_) => std::thread::sleep(Duration::from_millis(10)),
+                }
+            };
+            assert_eq!(request.to.as_deref(), Some("s1"));
+            assert_eq!(request.message["method"], "sampling/createMessage");
+            assert_eq!(request.message["params"]["maxTokens"], 5);
+            let sampled = json!({"role": "assistant", "content": {"type": "text", "text": "hi"}, "model": "m"});
+            assert!(answering.answer(&request.message["id"], Ok(sampled)).is_none());
+        });
+        let ctx = RequestContext { client: Some(client), ..RequestContext::with_session("s1") };
+        assert_eq!(text(proxy.call_tool_with_context("ask", &json!({}), &ctx).unwrap()), "sampled");
+        answerer.join().unwrap();
+    }
+
+    #[test]
+    fn test_keepalive_stops_hung_servers() {
+        let proxy = start();
+        proxy.keepalive(Duration::from_millis(50));
+        std::thread::sleep(Duration::from_millis(300));
+        // Answering ping with an error still shows the server is alive
+        assert!(proxy.call_tool("echo", &json!({})).is_ok());
+
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", &FAKE_SERVER.replace("*'\"id\"'*)", "*'\"ping\"'*) exec sleep 30 ;;\n*'\"id\"'*)")]);
+        let hung = ProxyServer::start(cmd, Duration::from_secs(5), false, false).unwrap();
+        hung.keepalive(Duration::from_millis(50));
+        std::thread::sleep(Duration::from_millis(500));
+        let error = hung.call_tool("echo", &json!({})).unwrap_err();
+        assert!(error.to_string().contains("Server exited"), "{}", error);
+    }
+
+    /// Gains a tool with its first tool call, and says so
+    const CHANGING_SERVER: &str = r#"
+        tools='{"name":"echo","inputSchema":{"type":"object"}}'
+        while read -r line; do
+            id=$(echo "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+            case "$line" in
+                *'"initialize"'*)
+                    capabilities='{"tools":{"listChanged":true}}'
+                    echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"capabilities\":$capabilities}}" ;;
+                *'"tools/list"'*)
+                    echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"tools\":[$tools]}}" ;;
+                *'"tools/call"'*)
+                    tools="$tools,{\"name\":\"added\",\"inputSchema\":{}}"
+                    echo '{"jsonrpc":"2.0","method":"notifications/tools/list_changed"}'
+                    echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"content\":[]}}" ;;
+            esac
+        done
+    "#;
+
+    #[test]
+    fn test_proxy_follows_tool_changes() {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", CHANGING_SERVER]);
+        let proxy = ProxyServer::start(cmd, Duration::from_secs(5), false, false).unwrap();
+        let mut notifications = proxy.notifier().unwrap().subscribe();
+        let names = || proxy.tools().into_iter().map(|tool| tool.name).collect::<Vec<_>>();
+
+        assert_eq!(names(), ["echo"]);
+        proxy.call_tool("echo", &json!({})).unwrap();
+        assert_eq!(notifications.try_recv().unwrap().message["method"], "notifications/tools/list_changed");
+        assert_eq!(names(), ["echo", "added"]);
+    }
+
+    #[test]
+    fn test_parse_tools_skips_unnamed() {
+        let tools = parse_tools(&json!({"tools": [{"name": "a", "description": "A"}, {"description": "no name"}]}));
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].description, "A");
+    }
+}
@@ -0,0 +1,258 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::common::{
+    ClientAnswer, CompletionRef, JsonRpcResponse, McpPrompt, McpResource, McpServer, McpTool, Notifier, RequestContext,
+};
+
+/// The window rate limits are counted over
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// JSON-RPC error code for calls refused by a rate limit (server error range; 29 as in HTTP 429)
+pub const RATE_LIMITED_CODE: i32 = -32029;
+
+/// A session (None for stdio) and one of its tools
+type CallKey = (Option<String>, String);
+
+/// Allows at most `limit` calls of each tool per session in any 60 seconds
+pub struct RateLimiter {
+    limit: u32,
+    /// (session, tool) -> times of the calls still inside the window
+    calls: Mutex<HashMap<CallKey, VecDeque<Instant>>>,
+}
+
+/// A call refused by a `RateLimiter`
+#[derive(Debug)]
+pub struct RateLimitExceeded {
+    pub tool: String,
+    pub limit: u32,
+    /// When the oldest call in the window expires and the tool can be called again
+    pub retry_after: Duration,
+}
+
+impl fmt::Display for RateLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Rate limit exceeded for {}: {} calls per minute; retry in {}s",
+            self.tool,
+            self.limit,
+            self.retry_secs()
+        )
+    }
+}
+
+impl std::error::Error for RateLimitExceeded {}
+
+impl RateLimitExceeded {
+    /// `retry_after` in whole seconds, rounded up so retrying then succeeds
+    pub fn retry_secs(&self) -> u64 {
+        self.retry_after.as_secs() + u64::from(self.retry_after.subsec_nanos() > 0)
+    }
+
+    /// The JSON-RPC error for the refused request, with the retry hint in `data`
+    pub fn to_response(&self, id: Option<Value>) -> JsonRpcResponse {
+        let mut response = JsonRpcResponse::error(id, RATE_LIMITED_CODE, self.to_string());
+        if let Some(error) = &mut response.error {
+            error.data = Some(serde_json::json!({ "retryAfter": self.retry_secs() }));
+        }
+        response
+    }
+}
+
+impl RateLimiter {
+    pub fn new(limit: u32) -> Self {
+        Self {
+            limit,
+            calls: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Count a call of `tool` in `session`, or refuse it if the limit is reached
+    pub fn check(&self, session: Option<&str>, tool: &str) -> Result<(), RateLimitExceeded> {
+        self.check_at(session, tool, Instant::now())
+    }
+
+    fn check_at(&self, session: Option<&str>, tool: &str, now: Instant) -> Result<(), RateLimitExceeded> {
+        let mut calls = self.calls.lock().unwrap();
+        let times = calls.entry((session.map(str::to_string), tool.to_string())).or_default();
+        while times.front().is_some_and(|time| now.duration_since(*time) >= WINDOW) {
+            times.pop_front();
+        }
+        if times.len() >= self.limit as usize {
+            let oldest = times.front().copied().unwrap_or(now);
+            return Err(RateLimitExceeded {
+                tool: tool.to_string(),
+                limit: self.limit,
+                retry_after: WINDOW.saturating_sub(now.duration_since(oldest)),
+            });
+        }
+        times.push_back(now);
+        Ok(())
+    }
+
+    /// Forget a finished session's calls
+    pub fn end_session(&self, session: Option<&str>) {
+        self.calls.lock().unwrap().retain(|(id, _), _| id.as_deref() != session);
+    }
+}
+
+/// A server whose tool calls are rate limited per tool and session (a gateway server's `rate_limit`)
+pub struct RateLimited {
+    server: Box<dyn McpServer + Send + Sync>,
+    limiter: RateLimiter,
+}
+
+impl RateLimited {
+    pub fn new(server: Box<dyn McpServer + Send + Sync>, limit: u32) -> Self {
+        Self {
+            server,
+            limiter: RateLimiter::new(limit),
+        }
+    }
+}
+
+impl McpServer for RateLimited {
+    fn name(&self) -> &str {
+        self.server.name()
+    }
+
+    fn version(&self) -> &str {
+        self.server.version()
+    }
+
+    fn tools(&self) -> Vec<McpTool> {
+        self.server.tools()
+    }
+
+    fn call_tool(&self, name: &str, arguments: &Value) -> Result<Value> {
+        self.call_tool_with_context(name, arguments, &RequestContext::default())
+    }
+
+    fn call_tool_with_context(&self, name: &str, arguments: &Value, ctx: &RequestContext) -> Result<Value> {
+        self.limiter.check(ctx.session_id.as_deref(), name)?;
+        self.server.call_tool_with_context(name, arguments, ctx)
+    }
+
+    fn has_resources(&self) -> bool {
+        self.server.has_resources()
+    }
+
+    fn resources(&self, ctx: &RequestContext) -> Vec<McpResource> {
+        self.server.resources(ctx)
+    }
+
+    fn read_resource(&self, uri: &str, ctx: &RequestContext) -> Result<Value> {
+        self.server.read_resource(uri, ctx)
+    }
+
+    fn has_subscriptions(&self) -> bool {
+        self.server.has_subscriptions()
+    }
+
+    fn subscribe(&self, uri: &str, ctx: &RequestContext) -> Result<()> {
+        self.server.subscribe(uri, ctx)
+    }
+
+    fn unsubscribe(&self, uri: &str, ctx: &RequestContext) -> Result<()> {
+        self.server.unsubscribe(uri, ctx)
+    }
+
+    fn has_prompts(&self) -> bool {
+        self.server.has_prompts()
+    }
+
+    fn prompts(&self) -> Vec<McpPrompt> {
+        self.server.prompts()
+    }
+
+    fn get_prompt(&self, name: &str, arguments: &Value) -> Result<Value> {
+        self.server.get_prompt(name, arguments)
+    }
+
+    fn has_completions(&self) -> bool {
+        self.server.has_completions()
+    }
+
+    fn complete(
+        &self,
+        reference: &CompletionRef,
+        argument: &str,
+        value: &str,
+        ctx: &RequestContext,
+    ) -> Result<Vec<String>> {
+        self.server.complete(reference, argument, value, ctx)
+    }
+
+    fn on_initialize(&self, params: &Value, ctx: &RequestContext) {
+        self.server.on_initialize(params, ctx)
+    }
+
+    fn on_notification(&self, method: &str, params: &Value, ctx: &RequestContext) {
+        self.server.on_notification(method, params, ctx)
+    }
+
+    fn on_response(&self, id: &Value, response: ClientAnswer, ctx: &RequestContext) {
+        self.server.on_response(id, response, ctx)
+    }
+
+    fn on_session_end(&self, session_id: Option<&str>) {
+        self.limiter.end_session(session_id);
+        self.server.on_session_end(session_id)
+    }
+
+    fn notifier(&self) -> Option<&Notifier> {
+        self.server.notifier()
+    }
+
+    fn verbose(&self) -> bool {
+        self.server.verbose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_limit_per_tool_and_session() {
+        let limiter = RateLimiter::new(2);
+        let start = Instant::now();
+        assert!(limiter.check_at(Some("a"), "query", start).is_ok());
+        assert!(limiter.check_at(Some("a"), "query", start + Duration::from_secs(10)).is_ok());
+        let refused = limiter.check_at(Some("a"), "query", start + Duration::from_secs(20)).unwrap_err();
+        assert_eq!(refused.retry_after, Duration::from_secs(40));
+        assert_eq!(refused.to_string(), "Rate limit exceeded for query: 2 calls per minute; retry in 40s");
+
+        // Other tools and sessions have their own budget
+        assert!(limiter.check_at(Some("a"), "exec", start + Duration::from_secs(20)).is_ok());
+        assert!(limiter.check_at(Some("b"), "query", start + Duration::from_secs(20)).is_ok());
+        assert!(limiter.check_at(None, "query", start + Duration::from_secs(20)).is_ok());
+
+        // The oldest call leaves the window after a minute
+        assert!(limiter.check_at(Some("a"), "query", start + Duration::from_secs(60)).is_ok());
+        assert!(limiter.check_at(Some("a"), "query", start + Duration::from_secs(61)).is_err());
+
+        limiter.end_session(Some("a"));
+        assert!(limiter.check_at(Some("a"), "query", start + Duration::from_secs(61)).is_ok());
+    }
+
+    #[test]
+    fn test_rate_limited_response() {
+        let error = RateLimitExceeded {
+            tool: "exec".to_string(),
+            limit: 10,
+            retry_after: Duration::from_millis(2500),
+        };
+        let response = serde_json::to_value(error.to_response(Some(json!(7)))).unwrap();
+        assert_eq!(response["id"], 7);
+        assert_eq!(response["error"]["code"], RATE_LIMITED_CODE);
+        assert_eq!(response["error"]["data"]["retryAfter"], 3);
+        assert!(response["error"]["message"].as_str().unwrap().contains("retry in 3s"));
+    }
+}
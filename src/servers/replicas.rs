@@ -0,0 +1,353 @@
+use anyhow::Result;
+use colored::Colorize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::common::{ClientAnswer, CompletionRef, McpPrompt, McpResource, McpServer, McpTool, Notifier, RequestContext};
+
+/// Consecutive failed calls after which a replica is taken out of rotation
+const FAILURES_BEFORE_DOWN: u32 = 3;
+
+/// How long a replica stays out of rotation before a call is sent to probe it
+const PROBE_AFTER: Duration = Duration::from_secs(30);
+
+/// One copy of the server, and how its recent calls went
+struct Replica {
+    server: Box<dyn McpServer + Send + Sync>,
+    health: Mutex<Health>,
+}
+
+#[derive(Debug, Default)]
+struct Health {
+    /// Failed calls in a row
+    failures: u32,
+    /// When the replica was taken out of rotation (or last failed a probe)
+    down_since: Option<Instant>,
+}
+
+impl Health {
+    fn available(&self, now: Instant) -> bool {
+        self.down_since.is_none_or(|since| now.duration_since(since) >= PROBE_AFTER)
+    }
+}
+
+/// Several copies of one server (a gateway server's `replicas`), with tool calls sent to
+/// each in turn. Replicas whose calls keep failing are skipped until a probe succeeds.
+pub struct Replicas {
+    name: String,
+    replicas: Vec<Replica>,
+    next: AtomicUsize,
+    /// Replica each session is pinned to, with `affinity = "session"` (None otherwise)
+    pinned: Option<Mutex<HashMap<Option<String>, usize>>>,
+}
+
+impl Replicas {
+    /// `servers` must not be empty
+    pub fn new(name: &str, servers: Vec<Box<dyn McpServer + Send + Sync>>) -> Self {
+        assert!(!servers.is_empty(), "Replicas needs at least one server");
+        Self {
+            name: name.to_string(),
+            replicas: servers
+                .into_iter()
+                .map(|server| Replica {
+                    server,
+                    health: Mutex::new(Health::default()),
+                })
+                .collect(),
+            next: AtomicUsize::new(0),
+            pinned: None,
+        }
+    }
+
+    /// Send all calls of a session to the replica its first call went to, even while that
+    /// replica is failing, since the state the session relies on lives there
+    pub fn pinned(mut self) -> Self {
+        self.pinned = Some(Mutex::new(HashMap::new()));
+        self
+    }
+
+    /// Index of the replica for a call of `session`
+    fn pick_for(&self, session: Option<&str>, now: Instant) -> usize {
+        let Some(pinned) = &self.pinned else {
+            return self.pick(now);
+        };
+        let mut pinned = pinned.lock().unwrap();
+        *pinned.entry(session.map(str::to_string)).or_insert_with(|| self.pick(now))
+    }
+
+    /// Index of the replica for the next call: the next one in turn that is in rotation
+    /// (or due a probe), or simply the next one if none is
+    fn pick(&self, now: Instant) -> usize {
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % self.replicas.len();
+        (0..self.replicas.len())
+            .map(|offset| (start + offset) % self.replicas.len())
+            .find(|&index| self.replicas[index].health.lock().unwrap().available(now))
+            .unwrap_or(start)
+    }
+
+    /// Record how a call to replica `index` went
+    fn record(&self, index: usize, ok: bool, now: Instant) {
+        let mut health = self.replicas[index].health.lock().unwrap();
+        if ok {
+            if health.down_since.take().is_some() {
+                eprintln!("{}", format!("[mcpz] {} replica {} recovered", self.name, index + 1).green());
+            }
+            health.failures = 0;
+            return;
+        }
+        health.failures += 1;
+        let was_down = health.down_since.is_some();
+        if was_down || health.failures >= FAILURES_BEFORE_DOWN {
+            // Taken out of rotation, or a failed probe: wait a full period before the next one
+            health.down_since = Some(now);
+        }
+        let failures = health.failures;
+        drop(health);
+        if !was_down && failures == FAILURES_BEFORE_DOWN {
+            eprintln!(
+                "{}",
+                format!(
+                    "[mcpz] {} replica {} failed {} calls in a row; out of rotation ({} of {} left)",
+                    self.name,
+                    index + 1,
+                    failures,
+                    self.healthy(),
+                    self.replicas.len()
+                )
+                .yellow()
+            );
+        }
+    }
+
+    /// How many replicas are in rotation
+    pub fn healthy(&self) -> usize {
+        self.replicas
+            .iter()
+            .filter(|replica| replica.health.lock().unwrap().down_since.is_none())
+            .count()
+    }
+}
+
+impl McpServer for Replicas {
+    fn name(&self) -> &str {
+        self.replicas[0].server.name()
+    }
+
+    fn version(&self) -> &str {
+        self.replicas[0].server.version()
+    }
+
+    fn tools(&self) -> Vec<McpTool> {
+        self.replicas[0].server.tools()
+    }
+
+    fn call_tool(&self, name: &str, arguments: &Value) -> Result<Value> {
+        self.call_tool_with_context(name, arguments, &RequestContext::default())
+    }
+
+    fn call_tool_with_context(&self, name: &str, arguments: &Value, ctx: &RequestContext) -> Result<Value> {
+        let index = self.pick_for(ctx.session_id.as_deref(), Instant::now());
+        self.log(&format!("Calling {} on {} replica {}", name, self.name, index + 1));
+        let result = self.replicas[index].server.call_tool_with_context(name, arguments, ctx);
+        self.record(index, result.is_ok(), Instant::now());
+        result
+    }
+
+    fn has_resources(&self) -> bool {
+        self.replicas[0].server.has_resources()
+    }
+
+    fn resources(&self, ctx: &RequestContext) -> Vec<McpResource> {
+        self.replicas[0].server.resources(ctx)
+    }
+
+    fn read_resource(&self, uri: &str, ctx: &RequestContext) -> Result<Value> {
+        self.replicas[0].server.read_resource(uri, ctx)
+    }
+
+    fn has_subscriptions(&self) -> bool {
+        self.replicas[0].server.has_subscriptions()
+    }
+
+    fn subscribe(&self, uri: &str, ctx: &RequestContext) -> Result<()> {
+        self.replicas[0].server.subscribe(uri, ctx)
+    }
+
+    fn unsubscribe(&self, uri: &str, ctx: &RequestContext) -> Result<()> {
+        self.replicas[0].server.unsubscribe(uri, ctx)
+    }
+
+    fn has_prompts(&self) -> bool {
+        self.replicas[0].server.has_prompts()
+    }
+
+    fn prompts(&self) -> Vec<McpPrompt> {
+        self.replicas[0].server.prompts()
+    }
+
+    fn get_prompt(&self, name: &str, arguments: &Value) -> Result<Value> {
+        self.replicas[0].server.get_prompt(name, arguments)
+    }
+
+    fn has_completions(&self) -> bool {
+        self.replicas[0].server.has_completions()
+    }
+
+    fn complete(
+        &self,
+        reference: &CompletionRef,
+        argument: &str,
+        value: &str,
+        ctx: &RequestContext,
+    ) -> Result<Vec<String>> {
+        self.replicas[0].server.complete(reference, argument, value, ctx)
+    }
+
+    fn on_initialize(&self, params: &Value, ctx: &RequestContext) {
+        for replica in &self.replicas {
+            replica.server.on_initialize(params, ctx);
+        }
+    }
+
+    fn on_notification(&self, method: &str, params: &Value, ctx: &RequestContext) {
+        for replica in &self.replicas {
+            replica.server.on_notification(method, params, ctx);
+        }
+    }
+
+    /// Every replica hears the answer; only the one that asked knows its id
+    fn on_response(&self, id: &Value, response: ClientAnswer, ctx: &RequestContext) {
+        for replica in &self.replicas {
+            replica.server.on_response(id, response.clone(), ctx);
+        }
+    }
+
+    fn on_session_end(&self, session_id: Option<&str>) {
+        if let Some(pinned) = &self.pinned {
+            pinned.lock().unwrap().remove(&session_id.map(str::to_string));
+        }
+        for replica in &self.replicas {
+            replica.server.on_session_end(session_id);
+        }
+    }
+
+    fn notifier(&self) -> Option<&Notifier> {
+        self.replicas[0].server.notifier()
+    }
+
+    fn verbose(&self) -> bool {
+        self.replicas[0].server.verbose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use serde_json::json;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    /// Answers with its number, or fails while `broken` is set
+    struct Numbered {
+        number: u64,
+        broken: Arc<AtomicBool>,
+    }
+
+    impl McpServer for Numbered {
+        fn name(&self) -> &str {
+            "numbered"
+        }
+
+        fn version(&self) -> &str {
+            "1.0.0"
+        }
+
+        fn tools(&self) -> Vec<McpTool> {
+            vec![]
+        }
+
+        fn call_tool(&self, _name: &str, _arguments: &Value) -> Result<Value> {
+            if self.broken.load(Ordering::Relaxed) {
+                return Err(anyhow!("replica {} is down", self.number));
+            }
+            Ok(json!(self.number))
+        }
+
+        fn verbose(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn test_round_robin_skips_failing_replicas() {
+        let broken = Arc::new(AtomicBool::new(false));
+        let replicas = Replicas::new(
+            "numbered",
+            (1..=3)
+                .map(|number| {
+                    let broken = Arc::new(AtomicBool::new(false));
+                    Box::new(Numbered { number, broken }) as Box<dyn McpServer + Send + Sync>
+                })
+                .collect(),
+        );
+        let answers: Vec<Value> = (0..4).map(|_| replicas.call_tool("n", &json!({})).unwrap()).collect();
+        assert_eq!(answers, [json!(1), json!(2), json!(3), json!(1)]);
+
+        // Replica 2 goes down after three failures in a row, and is probed after a while
+        let start = Instant::now();
+        for _ in 0..FAILURES_BEFORE_DOWN {
+            replicas.record(1, false, start);
+        }
+        assert_eq!(replicas.healthy(), 2);
+        let picks: Vec<usize> = (0..4).map(|_| replicas.pick(start)).collect();
+        assert!(!picks.contains(&1));
+        let later = start + PROBE_AFTER;
+        while replicas.pick(later) != 1 {}
+
+        // A failed probe keeps it out for another period; a successful one brings it back
+        replicas.record(1, false, later);
+        assert!(!replicas.replicas[1].health.lock().unwrap().available(later));
+        replicas.record(1, true, later + PROBE_AFTER);
+        assert_eq!(replicas.healthy(), 3);
+
+        // With every replica down, calls still go somewhere
+        let all_down = Replicas::new("numbered", vec![Box::new(Numbered { number: 1, broken: broken.clone() })]);
+        broken.store(true, Ordering::Relaxed);
+        for _ in 0..FAILURES_BEFORE_DOWN {
+            assert!(all_down.call_tool("n", &json!({})).is_err());
+        }
+        assert_eq!(all_down.healthy(), 0);
+        broken.store(false, Ordering::Relaxed);
+        assert_eq!(all_down.call_tool("n", &json!({})).unwrap(), json!(1));
+        assert_eq!(all_down.healthy(), 1);
+    }
+
+    #[test]
+    fn test_sessions_pinned_to_a_replica() {
+        let replicas = Replicas::new(
+            "numbered",
+            (1..=2)
+                .map(|number| {
+                    let broken = Arc::new(AtomicBool::new(false));
+                    Box::new(Numbered { number, broken }) as Box<dyn McpServer + Send + Sync>
+                })
+                .collect(),
+        )
+        .pinned();
+        let call = |session: &str| replicas.call_tool_with_context("n", &json!({}), &RequestContext::with_session(session)).unwrap();
+        assert_eq!(call("a"), json!(1));
+        assert_eq!(call("b"), json!(2));
+        assert_eq!(call("a"), json!(1));
+        assert_eq!(call("a"), json!(1));
+        assert_eq!(call("b"), json!(2));
+
+        // An ended session's pin goes with it
+        replicas.on_session_end(Some("a"));
+        assert_eq!(call("a"), json!(1));
+        assert_eq!(call("c"), json!(2));
+    }
+}
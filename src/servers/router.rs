@@ -0,0 +1,758 @@
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::broadcast::error::RecvError;
+
+use super::common::{
+    truncate_result, CompletionRef, McpPrompt, McpResource, McpServer, McpTool, Notifier, RequestContext, RpcError,
+};
+use super::glob::Globs;
+
+/// Separator between a route's prefix and its tool names (e.g. `time.get_current_time`)
+pub const PREFIX_SEPARATOR: char = '.';
+
+/// A downstream server mounted in a `RouterServer`
+pub struct Route {
+    /// Namespace for this server's tools when prefixing is enabled
+    pub prefix: String,
+    pub server: Box<dyn McpServer + Send + Sync>,
+    /// Which of the server's tools are exposed, and under what names
+    pub tools: ToolRules,
+    /// Bytes of text a tool result may carry before it's truncated (unlimited if None)
+    pub max_result_size: Option<usize>,
+}
+
+/// How a server's tools are presented to clients. All keys are the downstream tool names.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ToolRules {
+    /// Glob patterns; when set, only matching tools are exposed
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Glob patterns for tools that are never exposed
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// New names for tools
+    #[serde(default)]
+    pub rename: BTreeMap<String, String>,
+    /// Replacement descriptions and input schemas
+    #[serde(default, rename = "override")]
+    pub overrides: BTreeMap<String, ToolOverride>,
+}
+
+/// `tools.override.NAME`: what to show the model instead of the server's own text
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ToolOverride {
+    pub description: Option<String>,
+    pub input_schema: Option<Value>,
+}
+
+impl ToolRules {
+    /// Check that the allow and deny patterns are valid globs
+    pub fn check(&self) -> Result<()> {
+        Globs::new(&self.allow, false)?;
+        Globs::new(&self.deny, false)?;
+        Ok(())
+    }
+
+    /// Whether a tool is exposed. Patterns that aren't valid globs (which `check` refuses)
+    /// match nothing.
+    pub fn allows(&self, tool: &str) -> bool {
+        let matches = |patterns: &[String]| Globs::new(patterns, false).is_ok_and(|globs| globs.matches(tool));
+        !matches(&self.deny) && (self.allow.is_empty() || matches(&self.allow))
+    }
+
+    /// The tool as clients see it (renamed and overridden), or None if it is hidden
+    pub fn apply(&self, tool: McpTool) -> Option<McpTool> {
+        if !self.allows(&tool.name) {
+            return None;
+        }
+        let overrides = self.overrides.get(&tool.name);
+        Some(McpTool {
+            name: self.rename.get(&tool.name).cloned().unwrap_or(tool.name),
+            description: overrides
+                .and_then(|o| o.description.clone())
+                .unwrap_or(tool.description),
+            input_schema: overrides
+                .and_then(|o| o.input_schema.clone())
+                .unwrap_or(tool.input_schema),
+            output_schema: tool.output_schema,
+        })
+    }
+}
+
+impl Route {
+    /// The server's exposed tools, paired with their downstream names
+    fn exposed_tools(&self) -> Vec<(String, McpTool)> {
+        self.server
+            .tools()
+            .into_iter()
+            .filter_map(|mut tool| {
+                // Truncated results lose their structuredContent, so it can't be promised
+                if self.max_result_size.is_some() {
+                    tool.output_schema = None;
+                }
+                let downstream = tool.name.clone();
+                self.tools.apply(tool).map(|tool| (downstream, tool))
+            })
+            .collect()
+    }
+}
+
+/// Lists the names a tool's `package` argument can be completed with
+pub type PackageNames = fn() -> Vec<String>;
+
+/// Presents several MCP servers as one, dispatching each tool call to the server that owns the tool
+pub struct RouterServer {
+    table: RwLock<RouteTable>,
+    prefix_tools: bool,
+    verbose: bool,
+    notifier: Arc<Notifier>,
+    /// Sessions (None: stdio) whose clients offered sampling, which servers' sampling
+    /// requests are passed on to
+    sampling: Mutex<HashSet<Option<String>>>,
+    /// Offered for `package` arguments the tool's own server can't complete
+    package_names: Option<PackageNames>,
+    /// Set when a server says its tools changed, until they are indexed again
+    tools_changed: Arc<AtomicBool>,
+}
+
+/// The mounted servers; calls hold a route's `Arc`, so `replace` never waits for them
+struct RouteTable {
+    routes: Vec<Arc<Route>>,
+    /// Exposed tool name -> (route index, downstream tool name)
+    tool_map: HashMap<String, (usize, String)>,
+}
+
+impl RouteTable {
+    /// Index the routes' tools, failing if two servers would expose the same tool name
+    fn new(routes: Vec<Arc<Route>>, prefix_tools: bool) -> Result<Self> {
+        let mut tool_map = HashMap::new();
+        for (index, route) in routes.iter().enumerate() {
+            for (downstream, tool) in route.exposed_tools() {
+                let exposed = exposed_name(&route.prefix, &tool.name, prefix_tools);
+                if let Some((other, _)) = tool_map.insert(exposed.clone(), (index, downstream)) {
+                    return Err(anyhow!(
+                        "Tool {} is provided by both {} and {}{}",
+                        exposed,
+                        routes[other].prefix,
+                        route.prefix,
+                        if prefix_tools { "" } else { "; use --prefix-tools to namespace them" }
+                    ));
+                }
+            }
+        }
+        Ok(Self { routes, tool_map })
+    }
+}
+
+impl RouterServer {
+    /// Build the router, failing if two servers would expose the same tool name
+    pub fn new(routes: Vec<Arc<Route>>, prefix_tools: bool, verbose: bool) -> Result<Self> {
+        let table = RouteTable::new(routes, prefix_tools)?;
+        let router = Self {
+            table: RwLock::new(table),
+            prefix_tools,
+            verbose,
+            notifier: Arc::new(Notifier::default()),
+            sampling: Mutex::new(HashSet::new()),
+            package_names: None,
+            tools_changed: Arc::new(AtomicBool::new(false)),
+        };
+        router.relay(&router.routes());
+        Ok(router)
+    }
+
+    /// Complete `package` arguments with `names` when servers don't complete them themselves
+    pub fn with_package_names(mut self, names: PackageNames) -> Self {
+        self.package_names = Some(names);
+        self
+    }
+
+    /// Swap in a new set of servers and tell clients the tool list changed. Calls already
+    /// routed finish on the server they started on.
+    pub fn replace(&self, routes: Vec<Arc<Route>>) -> Result<()> {
+        let table = RouteTable::new(routes, self.prefix_tools)?;
+        let old = std::mem::replace(&mut *self.table.write().unwrap(), table).routes;
+        let added: Vec<_> =
+            self.routes().into_iter().filter(|route| !old.iter().any(|o| Arc::ptr_eq(o, route))).collect();
+        self.relay(&added);
+        self.notifier.notify("notifications/tools/list_changed");
+        Ok(())
+    }
+
+    /// Pass on the `notifications/tools/list_changed` of the routes' servers, and index
+    /// their tools again before the next lookup. Relaying ends with the server.
+    fn relay(&self, routes: &[Arc<Route>]) {
+        for route in routes {
+            let Some(notifier) = route.server.notifier().filter(|notifier| notifier.tools_change()) else {
+                continue;
+            };
+            let mut notifications = notifier.subscribe();
+            let (changed, notifier) = (self.tools_changed.clone(), self.notifier.clone());
+            std::thread::spawn(move || loop {
+                match notifications.blocking_recv() {
+                    Ok(outgoing) if outgoing.message["method"] == "notifications/tools/list_changed" => {
+                        changed.store(true, Ordering::Relaxed);
+                        notifier.notify("notifications/tools/list_changed");
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return,
+                }
+            });
+        }
+    }
+
+    /// Index the tools again if a server said they changed. If two servers would then expose
+    /// the same name, calls keep going where they went before.
+    fn reindex(&self) {
+        if !self.tools_changed.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        let mut table = self.table.write().unwrap();
+        match RouteTable::new(table.routes.clone(), self.prefix_tools) {
+            Ok(reindexed) => *table = reindexed,
+            Err(e) => eprintln!("{}", format!("[mcpz] Keeping the previous tool routes: {}", e).yellow()),
+        }
+    }
+
+    /// The servers currently mounted
+    pub fn routes(&self) -> Vec<Arc<Route>> {
+        self.table.read().unwrap().routes.clone()
+    }
+
+    /// The route serving an exposed tool, and the tool's downstream name
+    fn route_of(&self, name: &str) -> Result<(Arc<Route>, String)> {
+        self.reindex();
+        let table = self.table.read().unwrap();
+        let (index, tool) = table
+            .tool_map
+            .get(name)
+            .ok_or_else(|| RpcError::invalid_params(format!("Unknown tool: {}", name)))?;
+        Ok((table.routes[*index].clone(), tool.clone()))
+    }
+}
+
+fn exposed_name(prefix: &str, tool: &str, prefix_tools: bool) -> String {
+    if prefix_tools {
+        format!("{}{}{}", prefix, PREFIX_SEPARATOR, tool)
+    } else {
+        tool.to_string()
+    }
+}
+
+impl McpServer for RouterServer {
+    fn name(&self) -> &str {
+        "mcpz-router"
+    }
+
+    fn version(&self) -> &str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    fn tools(&self) -> Vec<McpTool> {
+        self.reindex();
+        self.routes()
+            .iter()
+            .flat_map(|route| {
+                route.exposed_tools().into_iter().map(|(_, tool)| McpTool {
+                    name: exposed_name(&route.prefix, &tool.name, self.prefix_tools),
+                    description: tool.description,
+                    input_schema: tool.input_schema,
+                    output_schema: tool.output_schema,
+                })
+            })
+            .collect()
+    }
+
+    fn call_tool(&self, name: &str, arguments: &Value) -> Result<Value> {
+        self.call_tool_with_context(name, arguments, &RequestContext::default())
+    }
+
+    fn call_tool_with_context(&self, name: &str, arguments: &Value, ctx: &RequestContext) -> Result<Value> {
+        let (route, tool) = self.route_of(name)?;
+        self.log(&format!("Routing {} to {}", name, route.prefix));
+        let ctx = match &ctx.client {
+            None if self.sampling.lock().unwrap().contains(&ctx.session_id) => {
+                RequestContext { client: Some(self.notifier.clone()), ..ctx.clone() }
+            }
+            _ => ctx.clone(),
+        };
+        let mut result = route.server.call_tool_with_context(&tool, arguments, &ctx)?;
+        if let Some(max_bytes) = route.max_result_size {
+            if truncate_result(&mut result, max_bytes) {
+                self.log(&format!("Truncated the result of {} to {} bytes", name, max_bytes));
+            }
+        }
+        Ok(result)
+    }
+
+    fn has_resources(&self) -> bool {
+        self.routes().iter().any(|route| route.server.has_resources())
+    }
+
+    /// Every route's resources, as they are (URIs aren't namespaced)
+    fn resources(&self, ctx: &RequestContext) -> Vec<McpResource> {
+        self.routes().iter().flat_map(|route| route.server.resources(ctx)).collect()
+    }
+
+    /// Read from the first route that can
+    fn read_resource(&self, uri: &str, ctx: &RequestContext) -> Result<Value> {
+        let mut last_error = anyhow!("Unknown resource: {}", uri);
+        for route in self.routes().iter().filter(|route| route.server.has_resources()) {
+            match route.server.read_resource(uri, ctx) {
+                Ok(result) => return Ok(result),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    fn has_prompts(&self) -> bool {
+        self.routes().iter().any(|route| route.server.has_prompts())
+    }
+
+    /// Every route's prompts, as they are (names aren't namespaced)
+    fn prompts(&self) -> Vec<McpPrompt> {
+        self.routes().iter().flat_map(|route| route.server.prompts()).collect()
+    }
+
+    /// Fill in the prompt from the first route offering one of that name
+    fn get_prompt(&self, name: &str, arguments: &Value) -> Result<Value> {
+        match self.routes().iter().find(|route| route.server.prompts().iter().any(|prompt| prompt.name == name)) {
+            Some(route) => route.server.get_prompt(name, arguments),
+            None => Err(anyhow!("Unknown prompt: {}", name)),
+        }
+    }
+
+    fn has_completions(&self) -> bool {
+        self.package_names.is_some() || self.routes().iter().any(|route| route.server.has_completions())
+    }
+
+    /// Ask the server owning the tool or prompt; resource arguments are completed by every
+    /// server that can
+    fn complete(
+        &self,
+        reference: &CompletionRef,
+        argument: &str,
+        value: &str,
+        ctx: &RequestContext,
+    ) -> Result<Vec<String>> {
+        let values = match reference {
+            CompletionRef::Tool { name } => {
+                let (route, tool) = self.route_of(name)?;
+                route.server.complete(&CompletionRef::Tool { name: tool }, argument, value, ctx)?
+            }
+            CompletionRef::Prompt { name } => {
+                let routes = self.routes();
+                let offering = routes.iter().find(|route| route.server.prompts().iter().any(|p| p.name == *name));
+                match offering {
+                    Some(route) => route.server.complete(reference, argument, value, ctx)?,
+                    None => return Err(anyhow!("Unknown prompt: {}", name)),
+                }
+            }
+            CompletionRef::Resource { .. } => {
+                let mut values = vec![];
+                for route in self.routes().iter().filter(|route| route.server.has_completions()) {
+                    values.extend(route.server.complete(reference, argument, value, ctx)?);
+                }
+                values
+            }
+        };
+        let is_tool = matches!(reference, CompletionRef::Tool { .. });
+        match self.package_names {
+            Some(names) if values.is_empty() && is_tool && argument == "package" => {
+                Ok(names().into_iter().filter(|name| name.starts_with(value)).collect())
+            }
+            _ => Ok(values),
+        }
+    }
+
+    fn on_initialize(&self, params: &Value, ctx: &RequestContext) {
+        let mut sampling = self.sampling.lock().unwrap();
+        if params.pointer("/capabilities/sampling").is_some() {
+            sampling.insert(ctx.session_id.clone());
+        } else {
+            sampling.remove(&ctx.session_id);
+        }
+    }
+
+    fn on_session_end(&self, session_id: Option<&str>) {
+        self.sampling.lock().unwrap().remove(&session_id.map(str::to_string));
+        for route in self.routes() {
+            route.server.on_session_end(session_id);
+        }
+    }
+
+    fn notifier(&self) -> Option<&Notifier> {
+        Some(&self.notifier)
+    }
+
+    fn verbose(&self) -> bool {
+        self.verbose
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A downstream server whose tools answer with "<server>:<tool>"
+    struct Stub {
+        name: &'static str,
+        tools: &'static [&'static str],
+    }
+
+    impl McpServer for Stub {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn version(&self) -> &str {
+            "0.0.0"
+        }
+
+        fn tools(&self) -> Vec<McpTool> {
+            self.tools
+                .iter()
+                .map(|tool| McpTool {
+                    name: tool.to_string(),
+                    description: String::new(),
+                    input_schema: json!({"type": "object"}),
+                    output_schema: None,
+                })
+                .collect()
+        }
+
+        fn call_tool(&self, name: &str, _arguments: &Value) -> Result<Value> {
+            Ok(json!(format!("{}:{}", self.name, name)))
+        }
+
+        fn has_completions(&self) -> bool {
+            true
+        }
+
+        /// "<tool>:<value>", for any argument but `package`
+        fn complete(
+            &self,
+            reference: &CompletionRef,
+            argument: &str,
+            value: &str,
+            _ctx: &RequestContext,
+        ) -> Result<Vec<String>> {
+            match reference {
+                CompletionRef::Tool { name } if argument != "package" => Ok(vec![format!("{}:{}", name, value)]),
+                _ => Ok(vec![]),
+            }
+        }
+
+        fn verbose(&self) -> bool {
+            false
+        }
+    }
+
+    fn route(name: &'static str, tools: &'static [&'static str]) -> Arc<Route> {
+        Arc::new(Route {
+            prefix: name.to_string(),
+            server: Box::new(Stub { name, tools }),
+            tools: ToolRules::default(),
+            max_result_size: None,
+        })
+    }
+
+    #[test]
+    fn test_router_prefixes_and_dispatches() {
+        let router = RouterServer::new(
+            vec![route("fs", &["read_file", "list"]), route("git", &["list"])],
+            true,
+            false,
+        )
+        .unwrap();
+
+        let names: Vec<String> = router.tools().into_iter().map(|t| t.name).collect();
+        assert_eq!(names, vec!["fs.read_file", "fs.list", "git.list"]);
+        assert_eq!(router.call_tool("git.list", &json!({})).unwrap(), json!("git:list"));
+        assert_eq!(router.call_tool("fs.list", &json!({})).unwrap(), json!("fs:list"));
+        assert!(router.call_tool("list", &json!({})).is_err());
+    }
+
+    #[test]
+    fn test_router_unprefixed_merge() {
+        let router = RouterServer::new(vec![route("fs", &["read_file"]), route("time", &["now"])], false, false)
+            .unwrap();
+        assert_eq!(router.call_tool("now", &json!({})).unwrap(), json!("time:now"));
+    }
+
+    #[test]
+    fn test_router_replace_swaps_routes_and_notifies() {
+        let router = RouterServer::new(vec![route("fs", &["read_file"])], true, false).unwrap();
+        let mut notifications = router.notifier().unwrap().subscribe();
+        assert_eq!(router.handle_initialize()["capabilities"]["tools"]["listChanged"], true);
+
+        let mut routes = router.routes();
+        routes.push(route("time", &["now"]));
+        router.replace(routes).unwrap();
+        assert_eq!(router.call_tool("time.now", &json!({})).unwrap(), json!("time:now"));
+        assert_eq!(router.call_tool("fs.read_file", &json!({})).unwrap(), json!("fs:read_file"));
+        assert_eq!(notifications.try_recv().unwrap().message["method"], "notifications/tools/list_changed");
+
+        router.replace(vec![route("time", &["now"])]).unwrap();
+        assert!(router.call_tool("fs.read_file", &json!({})).is_err());
+        assert_eq!(router.tools().len(), 1);
+    }
+
+    #[test]
+    fn test_router_completes_tool_arguments() {
+        let names = || vec!["mcp-server-time".to_string(), "server-filesystem".to_string()];
+        let router = RouterServer::new(vec![route("fs", &["read_file"])], true, false).unwrap();
+        let router = router.with_package_names(names);
+        let complete = |tool: &str, argument: &str, value: &str| {
+            let reference = CompletionRef::Tool { name: tool.to_string() };
+            router.complete(&reference, argument, value, &RequestContext::default())
+        };
+
+        assert_eq!(router.handle_initialize()["capabilities"]["completions"], json!({}));
+        assert_eq!(complete("fs.read_file", "path", "/tm").unwrap(), ["read_file:/tm"]);
+        assert_eq!(complete("fs.read_file", "package", "mcp").unwrap(), ["mcp-server-time"]);
+        assert!(complete("read_file", "path", "").is_err());
+    }
+
+    #[test]
+    fn test_router_keeps_output_schemas_of_untruncated_routes() {
+        use crate::servers::shell::{ShellServer, ShellServerConfig};
+        let shell = |prefix: &str, max_result_size| {
+            let config = ShellServerConfig::new(None, 30, "/bin/sh".to_string(), None, None, false, false);
+            Arc::new(Route {
+                prefix: prefix.to_string(),
+                server: Box::new(ShellServer::new(config)),
+                tools: ToolRules::default(),
+                max_result_size,
+            })
+        };
+        let router = RouterServer::new(vec![shell("full", None), shell("cut", Some(100))], true, false).unwrap();
+
+        let tools = router.tools();
+        assert_eq!(tools[0].name, "full.execute_command");
+        assert_eq!(tools[0].output_schema.as_ref().unwrap()["required"][2], "return_code");
+        assert_eq!(tools[1].name, "cut.execute_command");
+        assert!(tools[1].output_schema.is_none());
+    }
+
+    /// Answers with the tool's name, and can gain tools
+    #[derive(Default)]
+    struct Growing {
+        tools: Mutex<Vec<&'static str>>,
+        notifier: Notifier,
+    }
+
+    impl McpServer for Growing {
+        fn name(&self) -> &str {
+            "growing"
+        }
+
+        fn version(&self) -> &str {
+            "0.0.0"
+        }
+
+        fn tools(&self) -> Vec<McpTool> {
+            let tools = self.tools.lock().unwrap();
+            tools
+                .iter()
+                .map(|tool| McpTool {
+                    name: tool.to_string(),
+                    description: String::new(),
+                    input_schema: json!({}),
+                    output_schema: None,
+                })
+                .collect()
+        }
+
+        fn call_tool(&self, name: &str, _arguments: &Value) -> Result<Value> {
+            Ok(json!(name))
+        }
+
+        fn notifier(&self) -> Option<&Notifier> {
+            Some(&self.notifier)
+        }
+
+        fn verbose(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn test_router_follows_tool_changes_of_its_servers() {
+        let growing = Arc::new(Growing::default());
+        let route = Route {
+            prefix: "g".to_string(),
+            server: Box::new(growing.clone()),
+            tools: ToolRules::default(),
+            max_result_size: None,
+        };
+        let router = RouterServer::new(vec![Arc::new(route)], true, false).unwrap();
+        let mut notifications = router.notifier().unwrap().subscribe();
+        assert!(router.call_tool("g.new", &json!({})).is_err());
+
+        growing.tools.lock().unwrap().push("new");
+        growing.notifier.notify("notifications/tools/list_changed");
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        let relayed = loop {
+            match notifications.try_recv() {
+                Ok(relayed) => break relayed,
+                Err(_) if std::time::Instant::now() < deadline => {
+                    std::thread::sleep(std::time::Duration::from_millis(10))
+                }
+                Err(e) => panic!("not relayed: {}", e),
+            }
+        };
+        assert_eq!(relayed.message["method"], "notifications/tools/list_changed");
+        assert_eq!(router.call_tool("g.new", &json!({})).unwrap(), json!("new"));
+    }
+
+    /// Answers whether the call can reach its client with requests
+    struct Reaches;
+
+    impl McpServer for Reaches {
+        fn name(&self) -> &str {
+            "reaches"
+        }
+
+        fn version(&self) -> &str {
+            "0.0.0"
+        }
+
+        fn tools(&self) -> Vec<McpTool> {
+            vec![McpTool {
+                name: "ask".to_string(),
+                description: String::new(),
+                input_schema: json!({}),
+                output_schema: None,
+            }]
+        }
+
+        fn call_tool(&self, _name: &str, _arguments: &Value) -> Result<Value> {
+            Ok(json!(false))
+        }
+
+        fn call_tool_with_context(&self, _name: &str, _arguments: &Value, ctx: &RequestContext) -> Result<Value> {
+            Ok(json!(ctx.client.is_some()))
+        }
+
+        fn verbose(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn test_router_passes_the_client_to_sampling_sessions() {
+        let route = Route {
+            prefix: "llm".to_string(),
+            server: Box::new(Reaches),
+            tools: ToolRules::default(),
+            max_result_size: None,
+        };
+        let router = RouterServer::new(vec![Arc::new(route)], true, false).unwrap();
+        let sampling = RequestContext::with_session("a");
+        let other = RequestContext::with_session("b");
+        router.on_initialize(&json!({"capabilities": {"sampling": {}}}), &sampling);
+        router.on_initialize(&json!({"capabilities": {}}), &other);
+
+        assert_eq!(router.call_tool_with_context("llm.ask", &json!({}), &sampling).unwrap(), json!(true));
+        assert_eq!(router.call_tool_with_context("llm.ask", &json!({}), &other).unwrap(), json!(false));
+        router.on_session_end(Some("a"));
+        assert_eq!(router.call_tool_with_context("llm.ask", &json!({}), &sampling).unwrap(), json!(false));
+    }
+
+    #[test]
+    fn test_tool_filter() {
+        let filter = ToolRules {
+            deny: vec!["execute".to_string(), "delete_*".to_string()],
+            ..Default::default()
+        };
+        assert!(filter.allows("read_file"));
+        assert!(!filter.allows("execute"));
+        assert!(!filter.allows("delete_repo"));
+        assert!(filter.allows("execute_query"));
+
+        let filter = ToolRules {
+            allow: vec!["get_*".to_string(), "list_?".to_string()],
+            deny: vec!["get_secret".to_string()],
+            ..Default::default()
+        };
+        assert!(filter.allows("get_issue"));
+        assert!(filter.allows("list_a"));
+        assert!(!filter.allows("list_ab"));
+        assert!(!filter.allows("get_secret"));
+        assert!(!filter.allows("create_issue"));
+        assert!(filter.check().is_ok());
+
+        // The same glob syntax as the filesystem tools
+        let filter = ToolRules { allow: vec!["{get,list}_*".to_string()], ..Default::default() };
+        assert!(filter.allows("list_issues") && !filter.allows("create_issue"));
+        let filter = ToolRules { deny: vec!["get_[".to_string()], ..Default::default() };
+        assert!(filter.check().unwrap_err().to_string().starts_with("Invalid glob pattern 'get_['"));
+    }
+
+    #[test]
+    fn test_router_hides_filtered_tools() {
+        let risky = Arc::new(Route {
+            prefix: "gh".to_string(),
+            server: Box::new(Stub { name: "gh", tools: &["get_issue", "delete_repo"] }),
+            tools: ToolRules {
+                deny: vec!["delete_*".to_string()],
+                ..Default::default()
+            },
+            max_result_size: None,
+        });
+        let router = RouterServer::new(vec![risky], true, false).unwrap();
+        let names: Vec<String> = router.tools().into_iter().map(|t| t.name).collect();
+        assert_eq!(names, vec!["gh.get_issue"]);
+        assert_eq!(router.call_tool("gh.get_issue", &json!({})).unwrap(), json!("gh:get_issue"));
+        let err = router.call_tool("gh.delete_repo", &json!({})).unwrap_err().to_string();
+        assert!(err.contains("Unknown tool: gh.delete_repo"));
+    }
+
+    #[test]
+    fn test_router_renames_and_overrides_tools() {
+        let mut rules = ToolRules::default();
+        rules.rename.insert("execute_command".to_string(), "shell_run".to_string());
+        rules.overrides.insert(
+            "execute_command".to_string(),
+            ToolOverride {
+                description: Some("Run a command in the repo".to_string()),
+                input_schema: None,
+            },
+        );
+        let shell = Arc::new(Route {
+            prefix: "sh".to_string(),
+            server: Box::new(Stub { name: "sh", tools: &["execute_command", "pwd"] }),
+            tools: rules,
+            max_result_size: None,
+        });
+        let router = RouterServer::new(vec![shell], true, false).unwrap();
+
+        let tools = router.tools();
+        assert_eq!(tools[0].name, "sh.shell_run");
+        assert_eq!(tools[0].description, "Run a command in the repo");
+        assert_eq!(tools[0].input_schema, json!({"type": "object"}));
+        assert_eq!(tools[1].name, "sh.pwd");
+        assert_eq!(router.call_tool("sh.shell_run", &json!({})).unwrap(), json!("sh:execute_command"));
+        assert!(router.call_tool("sh.execute_command", &json!({})).is_err());
+    }
+
+    #[test]
+    fn test_router_rejects_collisions() {
+        let err = RouterServer::new(vec![route("fs", &["list"]), route("git", &["list"])], false, false)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains("Tool list is provided by both fs and git"));
+        assert!(err.contains("--prefix-tools"));
+    }
+}
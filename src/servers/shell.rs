@@ -4,7 +4,31 @@ use std::path::PathBuf;
 use std::process::Command;
 use std::time::Duration;
 
-use super::common::{error_content, text_content, McpServer, McpTool};
+use super::glob::matches_name;
+use super::common::{error_content, structured_content, LogLevel, McpServer, McpTool, Notifier, RpcError};
+use super::audit::{AuditLog, Audited};
+use super::strict::Strict;
+use crate::record::Recorder;
+
+/// Shell used when `--shell` isn't given
+#[cfg(unix)]
+pub const DEFAULT_SHELL: &str = "/bin/sh";
+#[cfg(windows)]
+pub const DEFAULT_SHELL: &str = "cmd";
+
+/// The flag that makes `shell` run a single command string: `/C` for cmd,
+/// `-Command` for PowerShell and `-c` for everything else
+fn command_flag(shell: &str) -> &'static str {
+    let name = std::path::Path::new(shell)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match name.as_str() {
+        "cmd" => "/C",
+        "powershell" | "pwsh" => "-Command",
+        _ => "-c",
+    }
+}
 
 /// Configuration for the shell server
 pub struct ShellServerConfig {
@@ -65,15 +89,11 @@ impl ShellServerConfig {
         false
     }
 
+    /// Whether the command's first word matches the glob `pattern`: "ls*" matches "ls -la",
+    /// "{git,gh}" matches "gh pr list"
     pub fn matches_pattern(command: &str, pattern: &str) -> bool {
-        // Simple wildcard matching: "ls*" matches "ls -la"
         let cmd_first_word = command.split_whitespace().next().unwrap_or("");
-        if pattern.ends_with('*') {
-            let prefix = &pattern[..pattern.len() - 1];
-            cmd_first_word.starts_with(prefix)
-        } else {
-            cmd_first_word == pattern
-        }
+        matches_name(pattern, cmd_first_word)
     }
 }
 
@@ -88,17 +108,21 @@ pub struct ShellCommandResult {
 /// Shell MCP server
 pub struct ShellServer {
     config: ShellServerConfig,
+    notifier: Notifier,
 }
 
 impl ShellServer {
     pub fn new(config: ShellServerConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            notifier: Notifier::logs_only(),
+        }
     }
 
     fn execute_command(&self, command: &str) -> ShellCommandResult {
         // Check sandboxing rules
         if !self.config.is_command_allowed(command) {
-            self.log(&format!("Command denied by security policy: {}", command));
+            self.log_at(LogLevel::Warning, &format!("Command denied by security policy: {}", command));
             return ShellCommandResult {
                 command: command.to_string(),
                 output: "Command denied by security policy".to_string(),
@@ -109,7 +133,7 @@ impl ShellServer {
         self.log(&format!("Executing: {}", command));
 
         let mut cmd = Command::new(&self.config.shell);
-        cmd.arg("-c").arg(command);
+        cmd.arg(command_flag(&self.config.shell)).arg(command);
 
         // Set working directory if specified
         if let Some(ref dir) = self.config.working_dir {
@@ -138,7 +162,7 @@ impl ShellServer {
                 }
             }
             Err(e) => {
-                self.log(&format!("Error: {}", e));
+                self.log_at(LogLevel::Error, &format!("Error: {}", e));
                 ShellCommandResult {
                     command: command.to_string(),
                     output: format!("Failed to execute: {}", e),
@@ -162,6 +186,10 @@ impl McpServer for ShellServer {
         self.config.verbose
     }
 
+    fn notifier(&self) -> Option<&Notifier> {
+        Some(&self.notifier)
+    }
+
     fn tools(&self) -> Vec<McpTool> {
         vec![McpTool {
             name: "execute_command".to_string(),
@@ -176,6 +204,15 @@ impl McpServer for ShellServer {
                 },
                 "required": ["command"]
             }),
+            output_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "command": {"type": "string"},
+                    "output": {"type": "string", "description": "stdout, and stderr if the server includes it"},
+                    "return_code": {"type": "integer", "description": "Exit code, -1 if the command didn't run"}
+                },
+                "required": ["command", "output", "return_code"]
+            })),
         }]
     }
 
@@ -187,17 +224,21 @@ impl McpServer for ShellServer {
         let command = arguments
             .get("command")
             .and_then(|c| c.as_str())
-            .ok_or_else(|| anyhow::anyhow!("Missing command argument"))?;
+            .ok_or_else(|| RpcError::invalid_params("Missing command argument"))?;
 
         let result = self.execute_command(command);
-        let result_json = serde_json::to_string_pretty(&result)?;
 
-        Ok(text_content(&result_json))
+        structured_content(&result)
     }
 }
 
 /// Run the shell MCP server
-pub fn run_shell_server(config: ShellServerConfig) -> Result<()> {
+pub fn run_shell_server(
+    config: ShellServerConfig,
+    recorder: Option<&Recorder>,
+    audit: Option<AuditLog>,
+    strict: bool,
+) -> Result<()> {
     if config.verbose {
         eprintln!("[mcpz] Shell server configuration:");
         eprintln!("[mcpz]   Working dir: {:?}", config.working_dir);
@@ -212,12 +253,13 @@ pub fn run_shell_server(config: ShellServerConfig) -> Result<()> {
     }
 
     let server = ShellServer::new(config);
-    server.run()
+    Strict::new(Audited::new(server, audit), strict).run(recorder)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::servers::common::RequestContext;
 
     #[test]
     fn test_shell_config_pattern_matching() {
@@ -230,6 +272,11 @@ mod tests {
         // Test exact matching
         assert!(ShellServerConfig::matches_pattern("ls -la", "ls"));
         assert!(!ShellServerConfig::matches_pattern("lsblk", "ls"));
+
+        // Alternatives and classes
+        assert!(ShellServerConfig::matches_pattern("gh pr list", "{git,gh}"));
+        assert!(ShellServerConfig::matches_pattern("python3 x.py", "python[0-9]"));
+        assert!(!ShellServerConfig::matches_pattern("/bin/rm -rf x", "*rm"));
     }
 
     #[test]
@@ -325,6 +372,82 @@ mod tests {
         assert_eq!(result["serverInfo"]["name"], "mcpz-shell");
     }
 
+    #[test]
+    fn test_protocol_version_negotiation() {
+        let config = ShellServerConfig::new(None, 30, "/bin/sh".to_string(), None, None, false, false);
+        let server = ShellServer::new(config);
+        let request = |method: &str, params: serde_json::Value, version: Option<&str>| {
+            let request = serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+            let ctx = RequestContext { protocol_version: version.map(str::to_string), ..Default::default() };
+            let response = server.handle_request_with_context(serde_json::from_value(request).unwrap(), &ctx);
+            serde_json::to_value(response.unwrap()).unwrap()
+        };
+
+        let init = |params| request("initialize", params, None);
+        assert_eq!(init(serde_json::json!({}))["result"]["protocolVersion"], "2024-11-05");
+        let latest = init(serde_json::json!({"protocolVersion": "2025-06-18"}));
+        assert_eq!(latest["result"]["protocolVersion"], "2025-06-18");
+        let unsupported = init(serde_json::json!({"protocolVersion": "0.1"}));
+        assert_eq!(unsupported["error"]["code"], -32602);
+        assert_eq!(unsupported["error"]["data"]["requested"], "0.1");
+        assert_eq!(unsupported["error"]["data"]["supported"][0], "2024-11-05");
+
+        // Structured output only goes to sessions on a version that has it
+        let call = |version| {
+            let params = serde_json::json!({"name": "execute_command", "arguments": {"command": "echo hi"}});
+            request("tools/call", params, Some(version))["result"].clone()
+        };
+        assert_eq!(call("2025-06-18")["structuredContent"]["output"], "hi\n");
+        assert!(call("2025-03-26").get("structuredContent").is_none());
+        let list = |version| request("tools/list", serde_json::json!({}), Some(version))["result"]["tools"][0].clone();
+        assert_eq!(list("2025-06-18")["outputSchema"]["type"], "object");
+        assert!(list("2025-03-26").get("outputSchema").is_none());
+    }
+
+    #[test]
+    fn test_invalid_tool_calls() {
+        let config = ShellServerConfig::new(None, 30, "/bin/sh".to_string(), None, None, false, false);
+        let server = ShellServer::new(config);
+        let call = |params: serde_json::Value| {
+            let request = serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "tools/call", "params": params});
+            serde_json::to_value(server.handle_request(serde_json::from_value(request).unwrap()).unwrap()).unwrap()
+        };
+
+        let missing = call(serde_json::json!({"name": "execute_command", "arguments": {}}));
+        assert_eq!(missing["error"]["code"], -32602);
+        assert_eq!(missing["error"]["message"], "Missing command argument");
+        assert_eq!(call(serde_json::json!({"arguments": {}}))["error"]["code"], -32602);
+    }
+
+    #[test]
+    fn test_log_messages_sent_from_set_level() {
+        let config = ShellServerConfig::new(None, 30, "/bin/sh".to_string(), Some("ls*".to_string()), None, false, false);
+        let server = ShellServer::new(config);
+        assert_eq!(server.handle_initialize()["capabilities"]["logging"], serde_json::json!({}));
+        let mut messages = server.notifier().unwrap().subscribe();
+
+        // Nothing is sent until the client picks a level
+        server.execute_command("rm file");
+        assert!(messages.try_recv().is_err());
+
+        let request = |level: &str| {
+            let request = serde_json::json!({
+                "jsonrpc": "2.0", "id": 1, "method": "logging/setLevel", "params": {"level": level}
+            });
+            serde_json::to_value(server.handle_request(serde_json::from_value(request).unwrap()).unwrap()).unwrap()
+        };
+        assert_eq!(request("loud")["error"]["code"], -32602);
+        assert_eq!(request("warning")["result"], serde_json::json!({}));
+        server.execute_command("ls /");
+        server.execute_command("rm file");
+        let message = messages.try_recv().unwrap().message;
+        assert_eq!(message["method"], "notifications/message");
+        assert_eq!(message["params"]["level"], "warning");
+        assert_eq!(message["params"]["logger"], "mcpz-shell");
+        assert_eq!(message["params"]["data"], "Command denied by security policy: rm file");
+        assert!(messages.try_recv().is_err());
+    }
+
     #[test]
     fn test_shell_server_call_tool() {
         let config = ShellServerConfig::new(None, 30, "/bin/sh".to_string(), None, None, false, false);
@@ -334,5 +457,27 @@ mod tests {
             .unwrap();
         let text = result["content"][0]["text"].as_str().unwrap();
         assert!(text.contains("test"));
+        assert_eq!(result["structuredContent"]["return_code"], 0);
+        assert_eq!(result["structuredContent"]["command"], "echo test");
+    }
+
+    #[test]
+    fn test_ping() {
+        let config = ShellServerConfig::new(None, 30, "/bin/sh".to_string(), None, None, false, false);
+        let server = ShellServer::new(config);
+        let request = serde_json::json!({"jsonrpc": "2.0", "id": 3, "method": "ping"});
+        let response = server.handle_request(serde_json::from_value(request).unwrap()).unwrap();
+        assert_eq!(response.id, Some(serde_json::json!(3)));
+        assert_eq!(response.result, Some(serde_json::json!({})));
+    }
+
+    #[test]
+    fn test_command_flag_per_shell() {
+        assert_eq!(command_flag("/bin/sh"), "-c");
+        assert_eq!(command_flag("bash"), "-c");
+        assert_eq!(command_flag("cmd"), "/C");
+        assert_eq!(command_flag("CMD.EXE"), "/C");
+        assert_eq!(command_flag("powershell.exe"), "-Command");
+        assert_eq!(command_flag("/usr/local/bin/pwsh"), "-Command");
     }
 }
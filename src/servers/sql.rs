@@ -4,9 +4,16 @@ use sqlx::mysql::{MySqlPool, MySqlRow};
 use sqlx::postgres::{PgPool, PgRow};
 use sqlx::sqlite::{SqlitePool, SqliteRow};
 use sqlx::{Column, Row, TypeInfo};
+use std::sync::Arc;
 use std::time::Duration;
 
-use super::common::{error_content, text_content, McpServer, McpTool};
+use super::common::{
+    error_content, structured_content, text_content, CompletionRef, McpServer, McpTool, Notifier, RequestContext,
+    RpcError,
+};
+use super::audit::{AuditLog, Audited};
+use super::strict::Strict;
+use crate::record::Recorder;
 
 /// Access mode for the SQL server
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -132,15 +139,19 @@ pub struct ColumnInfo {
 pub struct SqlServer {
     config: SqlServerConfig,
     pool: DatabasePool,
-    runtime: tokio::runtime::Runtime,
+    /// Runs the queries: the pool's own, or the one serving HTTP requests, whose blocking
+    /// threads call the server
+    runtime: Arc<tokio::runtime::Runtime>,
+    notifier: Notifier,
 }
 
 impl SqlServer {
-    pub fn new(config: SqlServerConfig, pool: DatabasePool, runtime: tokio::runtime::Runtime) -> Self {
+    pub fn new(config: SqlServerConfig, pool: DatabasePool, runtime: impl Into<Arc<tokio::runtime::Runtime>>) -> Self {
         Self {
             config,
             pool,
-            runtime,
+            runtime: runtime.into(),
+            notifier: Notifier::logs_only(),
         }
     }
 
@@ -535,6 +546,34 @@ impl McpServer for SqlServer {
         self.config.verbose
     }
 
+    fn notifier(&self) -> Option<&Notifier> {
+        Some(&self.notifier)
+    }
+
+    fn has_completions(&self) -> bool {
+        true
+    }
+
+    /// Table names for `describe_table`, case-insensitively
+    fn complete(
+        &self,
+        reference: &CompletionRef,
+        argument: &str,
+        value: &str,
+        _ctx: &RequestContext,
+    ) -> Result<Vec<String>> {
+        if !matches!(reference, CompletionRef::Tool { name } if name == "describe_table") || argument != "table_name" {
+            return Ok(vec![]);
+        }
+        let value = value.to_lowercase();
+        Ok(self
+            .list_tables()?
+            .into_iter()
+            .map(|table| table.name)
+            .filter(|name| name.to_lowercase().starts_with(&value))
+            .collect())
+    }
+
     fn tools(&self) -> Vec<McpTool> {
         let mut tools = vec![
             McpTool {
@@ -550,6 +589,19 @@ impl McpServer for SqlServer {
                     },
                     "required": ["sql"]
                 }),
+                output_schema: Some(serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "columns": {"type": "array", "items": {"type": "string"}},
+                        "rows": {
+                            "type": "array",
+                            "description": "Each row's values, in the order of columns",
+                            "items": {"type": "array"}
+                        },
+                        "row_count": {"type": "integer"}
+                    },
+                    "required": ["columns", "rows", "row_count"]
+                })),
             },
             McpTool {
                 name: "list_tables".to_string(),
@@ -559,6 +611,7 @@ impl McpServer for SqlServer {
                     "properties": {},
                     "required": []
                 }),
+                output_schema: None,
             },
             McpTool {
                 name: "describe_table".to_string(),
@@ -573,6 +626,7 @@ impl McpServer for SqlServer {
                     },
                     "required": ["table_name"]
                 }),
+                output_schema: None,
             },
         ];
 
@@ -591,6 +645,14 @@ impl McpServer for SqlServer {
                     },
                     "required": ["sql"]
                 }),
+                output_schema: Some(serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "rows_affected": {"type": "integer"},
+                        "message": {"type": "string"}
+                    },
+                    "required": ["rows_affected", "message"]
+                })),
             });
         }
 
@@ -603,13 +665,10 @@ impl McpServer for SqlServer {
                 let sql = arguments
                     .get("sql")
                     .and_then(|s| s.as_str())
-                    .ok_or_else(|| anyhow!("Missing sql argument"))?;
+                    .ok_or_else(|| RpcError::invalid_params("Missing sql argument"))?;
 
                 match self.execute_query(sql) {
-                    Ok(result) => {
-                        let result_json = serde_json::to_string_pretty(&result)?;
-                        Ok(text_content(&result_json))
-                    }
+                    Ok(result) => structured_content(&result),
                     Err(e) => Ok(error_content(&e.to_string())),
                 }
             }
@@ -617,13 +676,10 @@ impl McpServer for SqlServer {
                 let sql = arguments
                     .get("sql")
                     .and_then(|s| s.as_str())
-                    .ok_or_else(|| anyhow!("Missing sql argument"))?;
+                    .ok_or_else(|| RpcError::invalid_params("Missing sql argument"))?;
 
                 match self.execute_statement(sql) {
-                    Ok(result) => {
-                        let result_json = serde_json::to_string_pretty(&result)?;
-                        Ok(text_content(&result_json))
-                    }
+                    Ok(result) => structured_content(&result),
                     Err(e) => Ok(error_content(&e.to_string())),
                 }
             }
@@ -638,7 +694,7 @@ impl McpServer for SqlServer {
                 let table_name = arguments
                     .get("table_name")
                     .and_then(|s| s.as_str())
-                    .ok_or_else(|| anyhow!("Missing table_name argument"))?;
+                    .ok_or_else(|| RpcError::invalid_params("Missing table_name argument"))?;
 
                 match self.describe_table(table_name) {
                     Ok(columns) => {
@@ -684,7 +740,12 @@ pub async fn connect_database(connection_string: &str, db_type: DatabaseType, ti
 }
 
 /// Create and run the SQL MCP server
-pub fn run_sql_server(config: SqlServerConfig) -> Result<()> {
+pub fn run_sql_server(
+    config: SqlServerConfig,
+    recorder: Option<&Recorder>,
+    audit: Option<AuditLog>,
+    strict: bool,
+) -> Result<()> {
     if config.verbose {
         eprintln!("[mcpz] SQL server configuration:");
         eprintln!("[mcpz]   Database: {}", config.db_type.name());
@@ -707,7 +768,7 @@ pub fn run_sql_server(config: SqlServerConfig) -> Result<()> {
     }
 
     let server = SqlServer::new(config, pool, runtime);
-    server.run()
+    Strict::new(Audited::new(server, audit), strict).run(recorder)
 }
 
 #[cfg(test)]
@@ -1005,6 +1066,39 @@ mod tests {
         assert!(email_col.is_nullable);
     }
 
+    #[test]
+    fn test_sql_server_completes_table_names() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let pool = runtime.block_on(async {
+            let pool = sqlx::sqlite::SqlitePoolOptions::new()
+                .max_connections(1)
+                .connect("sqlite::memory:")
+                .await
+                .unwrap();
+            for table in ["users", "user_roles", "orders"] {
+                sqlx::query(&format!("CREATE TABLE {} (id INTEGER)", table)).execute(&pool).await.unwrap();
+            }
+            pool
+        });
+        let config = SqlServerConfig::new("sqlite::memory:".to_string(), AccessMode::ReadOnly, 30, false).unwrap();
+        let server = SqlServer::new(config, DatabasePool::SQLite(pool), runtime);
+
+        let request = serde_json::json!({
+            "jsonrpc": "2.0", "id": 1, "method": "completion/complete",
+            "params": {
+                "ref": {"type": "ref/tool", "name": "describe_table"},
+                "argument": {"name": "table_name", "value": "US"}
+            }
+        });
+        let response = server.handle_request(serde_json::from_value(request).unwrap()).unwrap();
+        let response = serde_json::to_value(response).unwrap();
+        assert_eq!(response["result"]["completion"]["values"], serde_json::json!(["user_roles", "users"]));
+        assert_eq!(response["result"]["completion"]["hasMore"], false);
+
+        let other = CompletionRef::Tool { name: "query".to_string() };
+        assert!(server.complete(&other, "sql", "", &RequestContext::default()).unwrap().is_empty());
+    }
+
     #[test]
     fn test_sql_server_call_tool_query() {
         let runtime = tokio::runtime::Runtime::new().unwrap();
@@ -1042,6 +1136,45 @@ mod tests {
         let text = result["content"][0]["text"].as_str().unwrap();
         assert!(text.contains("hello"));
         assert!(text.contains("row_count"));
+        assert_eq!(result["structuredContent"]["columns"], serde_json::json!(["id", "value"]));
+        assert_eq!(result["structuredContent"]["rows"], serde_json::json!([[1, "hello"]]));
+        assert_eq!(result["structuredContent"]["row_count"], 1);
+    }
+
+    #[test]
+    fn test_sql_server_call_tool_execute_structured() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        let pool = runtime.block_on(async {
+            let pool = sqlx::sqlite::SqlitePoolOptions::new()
+                .max_connections(1)
+                .connect("sqlite::memory:")
+                .await
+                .unwrap();
+
+            sqlx::query("CREATE TABLE test (id INTEGER PRIMARY KEY, value TEXT)")
+                .execute(&pool)
+                .await
+                .unwrap();
+
+            pool
+        });
+
+        let config = SqlServerConfig::new(
+            "sqlite::memory:".to_string(),
+            AccessMode::FullAccess,
+            30,
+            false,
+        ).unwrap();
+
+        let server = SqlServer::new(config, DatabasePool::SQLite(pool), runtime);
+
+        let result = server
+            .call_tool("execute", &serde_json::json!({"sql": "INSERT INTO test VALUES (1, 'a'), (2, 'b')"}))
+            .unwrap();
+        assert_eq!(result["structuredContent"]["rows_affected"], 2);
+        let text = result["content"][0]["text"].as_str().unwrap();
+        assert!(text.contains("rows_affected"));
     }
 
     #[test]
@@ -0,0 +1,309 @@
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use super::common::{
+    ClientAnswer, CompletionRef, JsonRpcResponse, McpPrompt, McpResource, McpServer, McpTool, Notifier, RequestContext,
+    RpcError, INVALID_REQUEST_CODE,
+};
+
+/// A server that holds its clients to the letter of JSON-RPC and of its tools' schemas
+/// (`--strict`): malformed messages are refused, notifications are never answered, and
+/// tool calls whose arguments don't match the tool's `inputSchema` fail before they reach it
+pub struct Strict<S> {
+    server: S,
+    enabled: bool,
+}
+
+impl<S: McpServer> Strict<S> {
+    pub fn new(server: S, enabled: bool) -> Self {
+        Self { server, enabled }
+    }
+}
+
+/// Why `message` isn't a JSON-RPC 2.0 message, if it isn't
+pub fn check_message(message: &Value) -> Result<(), &'static str> {
+    let reason = if !message.is_object() {
+        "not a JSON object"
+    } else if message.get("jsonrpc") != Some(&json!("2.0")) {
+        "\"jsonrpc\" must be \"2.0\""
+    } else if message.get("method").is_some() && (message.get("result").is_some() || message.get("error").is_some()) {
+        "a request can't carry \"result\" or \"error\""
+    } else if message.get("method").is_none() && message.get("result").is_some() == message.get("error").is_some() {
+        "a response carries exactly one of \"result\" and \"error\""
+    } else {
+        return Ok(());
+    };
+    Err(reason)
+}
+
+/// The Invalid Request error a message refused for `reason` is answered with, if it's a
+/// request: notifications and responses get no answer
+pub fn refusal(message: &Value, reason: &str) -> Option<JsonRpcResponse> {
+    message.get("method")?;
+    let id = message.get("id")?.clone();
+    Some(JsonRpcResponse::error(Some(id), INVALID_REQUEST_CODE, format!("Invalid Request: {}", reason)))
+}
+
+/// Where `arguments` break the tool's `schema`. Covers the JSON Schema keywords tools
+/// describe their arguments with: `type`, `enum`, `required`, `properties`,
+/// `additionalProperties`, `items`, `minimum` and `maximum`; others are not checked.
+pub fn check_arguments(schema: &Value, arguments: &Value) -> Vec<String> {
+    let mut errors = vec![];
+    check(schema, arguments, "arguments", &mut errors);
+    errors
+}
+
+fn check(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    if let Some(types) = schema.get("type") {
+        let types: Vec<&str> = match types {
+            Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+            other => other.as_str().into_iter().collect(),
+        };
+        if !types.is_empty() && !types.iter().any(|kind| has_type(value, kind)) {
+            errors.push(format!("{} must be of type {}", path, types.join(" or ")));
+            return;
+        }
+    }
+    if let Some(choices) = schema.get("enum").and_then(Value::as_array) {
+        if !choices.contains(value) {
+            errors.push(format!("{} must be one of {}", path, Value::Array(choices.clone())));
+        }
+    }
+    if let Some(number) = value.as_f64() {
+        if schema.get("minimum").and_then(Value::as_f64).is_some_and(|minimum| number < minimum) {
+            errors.push(format!("{} must be at least {}", path, schema["minimum"]));
+        }
+        if schema.get("maximum").and_then(Value::as_f64).is_some_and(|maximum| number > maximum) {
+            errors.push(format!("{} must be at most {}", path, schema["maximum"]));
+        }
+    }
+    if let Some(object) = value.as_object() {
+        let required = schema.get("required").and_then(Value::as_array).into_iter().flatten();
+        for name in required.filter_map(Value::as_str) {
+            if !object.contains_key(name) {
+                errors.push(format!("{}.{} is required", path, name));
+            }
+        }
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for (name, value) in object {
+            let path = format!("{}.{}", path, name);
+            match (properties.and_then(|properties| properties.get(name)), schema.get("additionalProperties")) {
+                (Some(property), _) => check(property, value, &path, errors),
+                (None, Some(Value::Bool(false))) => errors.push(format!("{} is not allowed", path)),
+                (None, Some(additional @ Value::Object(_))) => check(additional, value, &path, errors),
+                (None, _) => {}
+            }
+        }
+    }
+    if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+        for (i, item) in array.iter().enumerate() {
+            check(items, item, &format!("{}[{}]", path, i), errors);
+        }
+    }
+}
+
+/// Whether `value` is of the JSON Schema type `kind` (unknown types match anything)
+fn has_type(value: &Value, kind: &str) -> bool {
+    match kind {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0),
+        _ => true,
+    }
+}
+
+impl<S: McpServer> McpServer for Strict<S> {
+    fn name(&self) -> &str {
+        self.server.name()
+    }
+
+    fn version(&self) -> &str {
+        self.server.version()
+    }
+
+    fn tools(&self) -> Vec<McpTool> {
+        self.server.tools()
+    }
+
+    fn call_tool(&self, name: &str, arguments: &Value) -> Result<Value> {
+        self.call_tool_with_context(name, arguments, &RequestContext::default())
+    }
+
+    fn call_tool_with_context(&self, name: &str, arguments: &Value, ctx: &RequestContext) -> Result<Value> {
+        let tool = self.enabled.then(|| self.server.tools().into_iter().find(|tool| tool.name == name)).flatten();
+        if let Some(tool) = tool {
+            let errors = check_arguments(&tool.input_schema, arguments);
+            if !errors.is_empty() {
+                return Err(RpcError {
+                    data: Some(json!({ "kind": "invalid_arguments", "errors": errors })),
+                    ..RpcError::invalid_params(format!("Invalid arguments for {}: {}", name, errors.join("; ")))
+                }
+                .into());
+            }
+        }
+        self.server.call_tool_with_context(name, arguments, ctx)
+    }
+
+    fn has_resources(&self) -> bool {
+        self.server.has_resources()
+    }
+
+    fn resources(&self, ctx: &RequestContext) -> Vec<McpResource> {
+        self.server.resources(ctx)
+    }
+
+    fn read_resource(&self, uri: &str, ctx: &RequestContext) -> Result<Value> {
+        self.server.read_resource(uri, ctx)
+    }
+
+    fn has_subscriptions(&self) -> bool {
+        self.server.has_subscriptions()
+    }
+
+    fn subscribe(&self, uri: &str, ctx: &RequestContext) -> Result<()> {
+        self.server.subscribe(uri, ctx)
+    }
+
+    fn unsubscribe(&self, uri: &str, ctx: &RequestContext) -> Result<()> {
+        self.server.unsubscribe(uri, ctx)
+    }
+
+    fn has_prompts(&self) -> bool {
+        self.server.has_prompts()
+    }
+
+    fn prompts(&self) -> Vec<McpPrompt> {
+        self.server.prompts()
+    }
+
+    fn get_prompt(&self, name: &str, arguments: &Value) -> Result<Value> {
+        self.server.get_prompt(name, arguments)
+    }
+
+    fn has_completions(&self) -> bool {
+        self.server.has_completions()
+    }
+
+    fn complete(
+        &self,
+        reference: &CompletionRef,
+        argument: &str,
+        value: &str,
+        ctx: &RequestContext,
+    ) -> Result<Vec<String>> {
+        self.server.complete(reference, argument, value, ctx)
+    }
+
+    fn on_initialize(&self, params: &Value, ctx: &RequestContext) {
+        self.server.on_initialize(params, ctx)
+    }
+
+    fn on_notification(&self, method: &str, params: &Value, ctx: &RequestContext) {
+        self.server.on_notification(method, params, ctx)
+    }
+
+    fn on_response(&self, id: &Value, response: ClientAnswer, ctx: &RequestContext) {
+        self.server.on_response(id, response, ctx)
+    }
+
+    fn on_session_end(&self, session_id: Option<&str>) {
+        self.server.on_session_end(session_id)
+    }
+
+    fn notifier(&self) -> Option<&Notifier> {
+        self.server.notifier()
+    }
+
+    fn strict(&self) -> bool {
+        self.enabled
+    }
+
+    fn verbose(&self) -> bool {
+        self.server.verbose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::servers::shell::{ShellServer, ShellServerConfig};
+
+    #[test]
+    fn test_check_message() {
+        let refuse = |message: Value| {
+            let reason = check_message(&message).unwrap_err();
+            (reason, refusal(&message, reason).map(|answer| serde_json::to_value(answer).unwrap()))
+        };
+        assert!(check_message(&json!({"jsonrpc": "2.0", "id": 1, "method": "ping"})).is_ok());
+        assert!(check_message(&json!({"jsonrpc": "2.0", "id": 1, "result": {}})).is_ok());
+
+        let (reason, answer) = refuse(json!({"jsonrpc": "1.0", "id": 4, "method": "ping"}));
+        assert_eq!(reason, "\"jsonrpc\" must be \"2.0\"");
+        let answer = answer.unwrap();
+        assert_eq!(answer["id"], 4);
+        assert_eq!(answer["error"]["code"], -32600);
+        assert!(refuse(json!({"jsonrpc": "2.0", "id": 1, "method": "ping", "result": {}})).1.is_some());
+        // Notifications and responses are refused without an answer
+        assert!(refuse(json!({"jsonrpc": "2.0", "method": "notifications/x", "error": {}})).1.is_none());
+        let (reason, answer) = refuse(json!({"jsonrpc": "2.0", "id": 1, "result": 1, "error": {}}));
+        assert_eq!(reason, "a response carries exactly one of \"result\" and \"error\"");
+        assert!(answer.is_none());
+        assert!(check_message(&json!([1])).is_err());
+    }
+
+    #[test]
+    fn test_check_arguments() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "path": {"type": "string"},
+                "depth": {"type": "integer", "minimum": 1},
+                "sort": {"enum": ["name", "size"]},
+                "edits": {"type": "array", "items": {"type": "object", "required": ["oldText"]}}
+            },
+            "required": ["path"],
+            "additionalProperties": false
+        });
+        assert!(check_arguments(&schema, &json!({"path": "src", "depth": 2, "edits": [{"oldText": ""}]})).is_empty());
+        assert_eq!(
+            check_arguments(&schema, &json!({"depth": 0, "sort": "date", "edits": [{}], "extra": true})),
+            [
+                "arguments.path is required",
+                "arguments.depth must be at least 1",
+                "arguments.edits[0].oldText is required",
+                "arguments.extra is not allowed",
+                "arguments.sort must be one of [\"name\",\"size\"]",
+            ]
+        );
+        assert_eq!(check_arguments(&schema, &json!("src")), ["arguments must be of type object"]);
+        assert_eq!(check_arguments(&schema, &json!({"path": 1.5})), ["arguments.path must be of type string"]);
+    }
+
+    #[test]
+    fn test_strict_requests() {
+        let config = ShellServerConfig::new(None, 30, "/bin/sh".to_string(), None, None, false, false);
+        let server = Strict::new(ShellServer::new(config), true);
+        let request = |message: Value| {
+            let response = server.handle_request(serde_json::from_value(message).unwrap());
+            response.map(|response| serde_json::to_value(response).unwrap())
+        };
+
+        let call = |arguments: Value| {
+            let params = json!({"name": "execute_command", "arguments": arguments});
+            request(json!({"jsonrpc": "2.0", "id": 1, "method": "tools/call", "params": params})).unwrap()
+        };
+        let invalid = call(json!({"command": 42}));
+        assert_eq!(invalid["error"]["code"], -32602);
+        assert_eq!(invalid["error"]["data"]["errors"][0], "arguments.command must be of type string");
+        assert_eq!(call(json!({"command": "echo hi"}))["result"]["structuredContent"]["output"], "hi\n");
+
+        // Unknown notifications go unanswered, unknown requests don't
+        assert!(request(json!({"jsonrpc": "2.0", "method": "bogus"})).is_none());
+        let unknown = request(json!({"jsonrpc": "2.0", "id": 2, "method": "bogus"})).unwrap();
+        assert_eq!(unknown["error"]["code"], -32601);
+    }
+}
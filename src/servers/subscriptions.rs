@@ -0,0 +1,206 @@
+use anyhow::{Context, Result};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use super::common::Notifier;
+
+/// A file or directory clients subscribed to
+struct Subscribed {
+    uri: String,
+    /// The directory watched for it: a directory itself, or a file's parent, so that a file
+    /// replaced by renaming another over it (as editors save) is still followed
+    watched: PathBuf,
+    /// Sessions subscribed to it (None: stdio)
+    sessions: HashSet<Option<String>>,
+}
+
+/// Subscribed paths
+type Subscribers = Arc<Mutex<HashMap<PathBuf, Subscribed>>>;
+
+/// `resources/subscribe` to files and directories: their changes are sent to the subscribed
+/// sessions as `notifications/resources/updated`. A directory changes when entries are added
+/// to, removed from or changed in it. Watching starts with the first subscription.
+pub struct FileSubscriptions {
+    subscribed: Subscribers,
+    notifier: Arc<Notifier>,
+    /// Held while subscriptions change, so watches follow them
+    watcher: Mutex<Option<RecommendedWatcher>>,
+}
+
+impl FileSubscriptions {
+    pub fn new(notifier: Arc<Notifier>) -> Self {
+        Self {
+            subscribed: Arc::default(),
+            notifier,
+            watcher: Mutex::new(None),
+        }
+    }
+
+    /// Subscribe `session` to `path`, which `uri` names
+    pub fn subscribe(&self, path: &Path, uri: &str, session: Option<&str>) -> Result<()> {
+        let mut watcher = self.watcher.lock().unwrap();
+        if watcher.is_none() {
+            let (subscribed, notifier) = (self.subscribed.clone(), self.notifier.clone());
+            let handler = move |event: notify::Result<Event>| {
+                if let Ok(event) = event {
+                    changed(&subscribed, &notifier, &event);
+                }
+            };
+            *watcher = Some(notify::recommended_watcher(handler).context("Failed to start watching files")?);
+        }
+        let watcher = watcher.as_mut().expect("watcher started above");
+
+        let session = session.map(str::to_string);
+        let watched = if path.is_dir() { path } else { path.parent().unwrap_or(path) };
+        // The watcher's thread takes the subscriptions to match events, so they aren't held
+        // while it's asked to watch
+        {
+            let mut subscribed = self.subscribed.lock().unwrap();
+            if let Some(existing) = subscribed.get_mut(path) {
+                existing.sessions.insert(session);
+                return Ok(());
+            }
+            if subscribed.values().any(|other| other.watched == watched) {
+                subscribed.insert(path.to_path_buf(), Subscribed::new(uri, watched, session));
+                return Ok(());
+            }
+        }
+        watcher
+            .watch(watched, RecursiveMode::NonRecursive)
+            .with_context(|| format!("Failed to watch {}", watched.display()))?;
+        self.subscribed.lock().unwrap().insert(path.to_path_buf(), Subscribed::new(uri, watched, session));
+        Ok(())
+    }
+
+    /// Unsubscribe `session` from `path`
+    pub fn unsubscribe(&self, path: &Path, session: Option<&str>) {
+        self.forget(session, Some(path));
+    }
+
+    /// Unsubscribe `session` from everything
+    pub fn end_session(&self, session: Option<&str>) {
+        self.forget(session, None);
+    }
+
+    /// Drop `session`'s subscriptions (to `path`, or all), and the watches nobody needs anymore
+    fn forget(&self, session: Option<&str>, path: Option<&Path>) {
+        let mut watcher = self.watcher.lock().unwrap();
+        let Some(watcher) = watcher.as_mut() else {
+            return;
+        };
+        let session = session.map(str::to_string);
+        let unwatched: Vec<PathBuf> = {
+            let mut subscribed = self.subscribed.lock().unwrap();
+            let mut dropped = vec![];
+            subscribed.retain(|subscribed_path, subscription| {
+                if path.is_some_and(|path| path != subscribed_path) || !subscription.sessions.remove(&session) {
+                    return true;
+                }
+                if !subscription.sessions.is_empty() {
+                    return true;
+                }
+                dropped.push(subscription.watched.clone());
+                false
+            });
+            dropped.sort();
+            dropped.dedup();
+            dropped.retain(|watched| !subscribed.values().any(|other| other.watched == *watched));
+            dropped
+        };
+        for watched in unwatched {
+            // Fails if the directory is gone, taking its watch with it
+            let _ = watcher.unwatch(&watched);
+        }
+    }
+}
+
+impl Subscribed {
+    fn new(uri: &str, watched: &Path, session: Option<String>) -> Self {
+        Self {
+            uri: uri.to_string(),
+            watched: watched.to_path_buf(),
+            sessions: HashSet::from([session]),
+        }
+    }
+}
+
+/// Tell the sessions subscribed to the paths of `event`, or to their directories, that
+/// they changed
+fn changed(subscribed: &Mutex<HashMap<PathBuf, Subscribed>>, notifier: &Notifier, event: &Event) {
+    if matches!(event.kind, EventKind::Access(_)) {
+        return;
+    }
+    let subscribed = subscribed.lock().unwrap();
+    let mut updated: Vec<&Subscribed> = event
+        .paths
+        .iter()
+        .flat_map(|path| [Some(path.as_path()), path.parent()])
+        .flatten()
+        .filter_map(|path| subscribed.get(path))
+        .collect();
+    updated.sort_by(|a, b| a.uri.cmp(&b.uri));
+    updated.dedup_by(|a, b| a.uri == b.uri);
+    for subscription in updated {
+        for session in &subscription.sessions {
+            notifier.resource_updated(session.as_deref(), &subscription.uri);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::servers::common::Outgoing;
+    use std::time::{Duration, Instant};
+    use tokio::sync::broadcast::Receiver;
+
+    /// The URIs updated within a second, once no more come for 200ms
+    fn updates(outgoing: &mut Receiver<Outgoing>) -> Vec<(Option<String>, String)> {
+        let deadline = Instant::now() + Duration::from_secs(1);
+        let mut updates = vec![];
+        let mut quiet_since = Instant::now();
+        while Instant::now() < deadline && (updates.is_empty() || quiet_since.elapsed() < Duration::from_millis(200)) {
+            match outgoing.try_recv() {
+                Ok(outgoing) => {
+                    assert_eq!(outgoing.message["method"], "notifications/resources/updated");
+                    updates.push((outgoing.to, outgoing.message["params"]["uri"].as_str().unwrap().to_string()));
+                    quiet_since = Instant::now();
+                }
+                Err(_) => std::thread::sleep(Duration::from_millis(10)),
+            }
+        }
+        updates.sort();
+        updates.dedup();
+        updates
+    }
+
+    #[test]
+    fn test_file_subscriptions() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir_path = dir.path().canonicalize().unwrap();
+        let file = dir_path.join("notes.txt");
+        std::fs::write(&file, "one").unwrap();
+        let notifier = Arc::new(Notifier::default());
+        let mut outgoing = notifier.subscribe();
+        let subscriptions = FileSubscriptions::new(notifier);
+
+        subscriptions.subscribe(&file, "file:///notes", Some("a")).unwrap();
+        subscriptions.subscribe(&dir_path, "file:///dir", None).unwrap();
+        std::fs::write(&file, "two").unwrap();
+        let expected = [(None, "file:///dir"), (Some("a"), "file:///notes")];
+        let expected: Vec<_> = expected.iter().map(|(to, uri)| (to.map(str::to_string), uri.to_string())).collect();
+        assert_eq!(updates(&mut outgoing), expected);
+
+        // Other files only change the directory
+        std::fs::write(dir_path.join("other.txt"), "").unwrap();
+        assert_eq!(updates(&mut outgoing), vec![(None, "file:///dir".to_string())]);
+
+        subscriptions.unsubscribe(&dir_path, None);
+        subscriptions.end_session(Some("a"));
+        std::fs::write(&file, "three").unwrap();
+        assert!(updates(&mut outgoing).is_empty());
+        assert!(subscriptions.subscribed.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,408 @@
+use anyhow::{anyhow, Context, Result};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::common::{LogLevel, Notifier};
+
+/// Most changes kept for a session between polls of one watch; later ones are dropped and
+/// the poll says so
+const MAX_PENDING: usize = 1000;
+
+/// How a path changed since the last poll
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Created,
+    Modified,
+    Deleted,
+}
+
+impl Change {
+    /// The net change of `self` followed by `later`; None if they cancel out
+    fn then(self, later: Change) -> Option<Change> {
+        match (self, later) {
+            (Change::Created, Change::Deleted) => None,
+            (Change::Created, _) => Some(Change::Created),
+            (Change::Deleted, Change::Created | Change::Modified) | (Change::Modified, Change::Created) => {
+                Some(Change::Modified)
+            }
+            (_, later) => Some(later),
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Change::Created => "created",
+            Change::Modified => "modified",
+            Change::Deleted => "deleted",
+        })
+    }
+}
+
+/// Changes a session hasn't polled yet
+#[derive(Debug, Default)]
+struct Pending {
+    changes: BTreeMap<PathBuf, Change>,
+    /// Whether changes were dropped past `MAX_PENDING`
+    overflowed: bool,
+}
+
+/// A path `watch_path` was called for
+struct Watch {
+    /// The directory watched for it: a directory itself (recursively), or a file's parent,
+    /// so that a file replaced by renaming another over it is still followed
+    watched: PathBuf,
+    recursive: bool,
+    /// Sessions watching it (None: stdio), and their unpolled changes
+    sessions: HashMap<Option<String>, Pending>,
+}
+
+/// Watched paths
+type Watched = Arc<Mutex<HashMap<PathBuf, Watch>>>;
+
+/// What `poll` found for a session: its changes since the last poll, by path
+#[derive(Debug, Default, Serialize)]
+pub struct Changes {
+    pub changes: Vec<ChangedPath>,
+    /// Whether some changes were dropped, so the watched paths should be listed again
+    pub overflowed: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChangedPath {
+    pub path: String,
+    pub change: Change,
+}
+
+impl fmt::Display for Changes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.changes.is_empty() && !self.overflowed {
+            return write!(f, "No changes");
+        }
+        let lines: Vec<String> = self
+            .changes
+            .iter()
+            .map(|changed| format!("{} {}", changed.change, changed.path))
+            .collect();
+        write!(f, "{}", lines.join("\n"))?;
+        if self.overflowed {
+            write!(f, "\n[more changes were dropped; list the watched paths again]")?;
+        }
+        Ok(())
+    }
+}
+
+/// The filesystem server's `watch_path`/`poll_changes`: changes under watched files and
+/// directories are collected per session until it polls for them. The first change after a
+/// poll is also announced to the session as a `notifications/message`.
+pub struct PathWatches {
+    watched: Watched,
+    notifier: Arc<Notifier>,
+    /// Held while watches change, so the watcher follows them
+    watcher: Mutex<Option<OsWatches>>,
+}
+
+/// The watcher, and the directories it watches (recursively or not)
+struct OsWatches {
+    watcher: RecommendedWatcher,
+    dirs: HashMap<PathBuf, bool>,
+}
+
+impl PathWatches {
+    pub fn new(notifier: Arc<Notifier>) -> Self {
+        Self {
+            watched: Arc::default(),
+            notifier,
+            watcher: Mutex::new(None),
+        }
+    }
+
+    /// Start collecting changes under `path` for `session`
+    pub fn watch(&self, path: &Path, session: Option<&str>) -> Result<()> {
+        let mut watcher = self.watcher.lock().unwrap();
+        if watcher.is_none() {
+            let (watched, notifier) = (self.watched.clone(), self.notifier.clone());
+            let handler = move |event: notify::Result<Event>| {
+                if let Ok(event) = event {
+                    changed(&watched, &notifier, &event);
+                }
+            };
+            let os_watcher = notify::recommended_watcher(handler).context("Failed to start watching files")?;
+            *watcher = Some(OsWatches { watcher: os_watcher, dirs: HashMap::new() });
+        }
+        let os_watches = watcher.as_mut().expect("watcher started above");
+
+        let session = session.map(str::to_string);
+        let recursive = path.is_dir();
+        let watched = if recursive { path } else { path.parent().unwrap_or(path) };
+        // The watcher's thread takes the watches to match events, so they aren't held while
+        // it's asked to watch
+        let needed = {
+            let mut watches = self.watched.lock().unwrap();
+            if let Some(existing) = watches.get_mut(path) {
+                existing.sessions.entry(session).or_default();
+                return Ok(());
+            }
+            watches.insert(path.to_path_buf(), Watch::new(watched, recursive, session));
+            needed(&watches)
+        };
+        if let Err(e) = os_watches.follow(needed) {
+            self.watched.lock().unwrap().remove(path);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Stop watching `path` for `session`; false if it wasn't
+    pub fn unwatch(&self, path: &Path, session: Option<&str>) -> bool {
+        self.forget(session, Some(path))
+    }
+
+    /// Stop every watch of `session`
+    pub fn end_session(&self, session: Option<&str>) {
+        self.forget(session, None);
+    }
+
+    /// Take `session`'s changes under `path` (or under every path it watches) since its last
+    /// poll, waiting up to `wait` for some if there are none yet
+    pub fn poll(&self, path: Option<&Path>, session: Option<&str>, wait: Duration) -> Result<Changes> {
+        let session = session.map(str::to_string);
+        let deadline = Instant::now() + wait;
+        loop {
+            let mut found = Changes::default();
+            {
+                let mut watches = self.watched.lock().unwrap();
+                let mut polled = 0;
+                for (watched_path, watch) in watches.iter_mut() {
+                    if path.is_some_and(|path| path != watched_path) {
+                        continue;
+                    }
+                    let Some(pending) = watch.sessions.get_mut(&session) else {
+                        continue;
+                    };
+                    polled += 1;
+                    let pending = std::mem::take(pending);
+                    found.overflowed |= pending.overflowed;
+                    found.changes.extend(pending.changes.into_iter().map(|(path, change)| ChangedPath {
+                        path: path.to_string_lossy().to_string(),
+                        change,
+                    }));
+                }
+                if polled == 0 {
+                    return Err(match path {
+                        Some(path) => anyhow!("Not watching {}", path.display()),
+                        None => anyhow!("Not watching anything; call watch_path first"),
+                    });
+                }
+            }
+            if !found.changes.is_empty() || found.overflowed || Instant::now() >= deadline {
+                found.changes.sort_by(|a, b| a.path.cmp(&b.path));
+                found.changes.dedup_by(|a, b| a.path == b.path);
+                return Ok(found);
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    /// Drop `session`'s watches (of `path`, or all), and the OS watches nobody needs anymore.
+    /// Returns whether any were dropped.
+    fn forget(&self, session: Option<&str>, path: Option<&Path>) -> bool {
+        let mut watcher = self.watcher.lock().unwrap();
+        let Some(os_watches) = watcher.as_mut() else {
+            return false;
+        };
+        let session = session.map(str::to_string);
+        let mut forgot = false;
+        let needed = {
+            let mut watches = self.watched.lock().unwrap();
+            watches.retain(|watched_path, watch| {
+                if path.is_some_and(|path| path != watched_path) || watch.sessions.remove(&session).is_none() {
+                    return true;
+                }
+                forgot = true;
+                !watch.sessions.is_empty()
+            });
+            needed(&watches)
+        };
+        // Directories still needed were being watched, so watching them again can't fail
+        // unless they're gone
+        let _ = os_watches.follow(needed);
+        forgot
+    }
+}
+
+impl OsWatches {
+    /// Watch the `needed` directories, and only those
+    fn follow(&mut self, needed: HashMap<PathBuf, bool>) -> Result<()> {
+        let stale: Vec<PathBuf> = self
+            .dirs
+            .iter()
+            .filter(|(dir, recursive)| needed.get(*dir) != Some(recursive))
+            .map(|(dir, _)| dir.clone())
+            .collect();
+        for dir in stale {
+            // Fails if the directory is gone, taking its watch with it
+            let _ = self.watcher.unwatch(&dir);
+            self.dirs.remove(&dir);
+        }
+        for (dir, recursive) in needed {
+            if self.dirs.contains_key(&dir) {
+                continue;
+            }
+            let mode = if recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+            self.watcher.watch(&dir, mode).with_context(|| format!("Failed to watch {}", dir.display()))?;
+            self.dirs.insert(dir, recursive);
+        }
+        Ok(())
+    }
+}
+
+/// The directories the watcher must watch for `watches`, and whether recursively: those of
+/// the watches, less those a recursive watch of a directory above them already covers
+fn needed(watches: &HashMap<PathBuf, Watch>) -> HashMap<PathBuf, bool> {
+    let mut needed: HashMap<PathBuf, bool> = HashMap::new();
+    for watch in watches.values() {
+        *needed.entry(watch.watched.clone()).or_default() |= watch.recursive;
+    }
+    let recursive: Vec<PathBuf> =
+        needed.iter().filter(|(_, recursive)| **recursive).map(|(dir, _)| dir.clone()).collect();
+    needed.retain(|dir, _| !recursive.iter().any(|above| above != dir && dir.starts_with(above)));
+    needed
+}
+
+impl Watch {
+    fn new(watched: &Path, recursive: bool, session: Option<String>) -> Self {
+        Self {
+            watched: watched.to_path_buf(),
+            recursive,
+            sessions: HashMap::from([(session, Pending::default())]),
+        }
+    }
+}
+
+/// The changes `event` reports, by path
+fn event_changes(event: &Event) -> Vec<(&Path, Change)> {
+    let all = |change: Change| event.paths.iter().map(|path| (path.as_path(), change)).collect();
+    match event.kind {
+        EventKind::Access(_) => vec![],
+        EventKind::Create(_) => all(Change::Created),
+        EventKind::Remove(_) => all(Change::Deleted),
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) => all(Change::Deleted),
+        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => all(Change::Created),
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+            let mut changes: Vec<(&Path, Change)> = all(Change::Created);
+            if let Some(from) = changes.first_mut() {
+                from.1 = Change::Deleted;
+            }
+            changes
+        }
+        // Renames the platform doesn't pair up
+        EventKind::Modify(ModifyKind::Name(_)) => event
+            .paths
+            .iter()
+            .map(|path| (path.as_path(), if path.exists() { Change::Created } else { Change::Deleted }))
+            .collect(),
+        _ => all(Change::Modified),
+    }
+}
+
+/// Add the changes of `event` to the sessions watching their paths, telling each session
+/// whose changes were all polled that there are new ones
+fn changed(watched: &Mutex<HashMap<PathBuf, Watch>>, notifier: &Notifier, event: &Event) {
+    let changes = event_changes(event);
+    if changes.is_empty() {
+        return;
+    }
+    let mut watches = watched.lock().unwrap();
+    for (watched_path, watch) in watches.iter_mut() {
+        let changes: Vec<&(&Path, Change)> = changes
+            .iter()
+            .filter(|(path, _)| if watch.recursive { path.starts_with(watched_path) } else { path == watched_path })
+            .collect();
+        if changes.is_empty() {
+            continue;
+        }
+        for (session, pending) in watch.sessions.iter_mut() {
+            let was_empty = pending.changes.is_empty() && !pending.overflowed;
+            for (path, change) in &changes {
+                let merged = match pending.changes.get(*path) {
+                    Some(earlier) => earlier.then(*change),
+                    None if pending.changes.len() >= MAX_PENDING => {
+                        pending.overflowed = true;
+                        continue;
+                    }
+                    None => Some(*change),
+                };
+                match merged {
+                    Some(change) => pending.changes.insert(path.to_path_buf(), change),
+                    None => pending.changes.remove(*path),
+                };
+            }
+            if was_empty && (!pending.changes.is_empty() || pending.overflowed) {
+                let message = format!("{} changed; call poll_changes for what changed", watched_path.display());
+                notifier.message_to(session.as_deref(), LogLevel::Info, "watch_path", &message);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes_merge() {
+        assert_eq!(Change::Created.then(Change::Modified), Some(Change::Created));
+        assert_eq!(Change::Created.then(Change::Deleted), None);
+        assert_eq!(Change::Deleted.then(Change::Created), Some(Change::Modified));
+        assert_eq!(Change::Modified.then(Change::Deleted), Some(Change::Deleted));
+    }
+
+    #[test]
+    fn test_path_watches() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir_path = dir.path().canonicalize().unwrap();
+        std::fs::create_dir(dir_path.join("out")).unwrap();
+        std::fs::write(dir_path.join("out/old.txt"), "old").unwrap();
+        let watches = PathWatches::new(Arc::new(Notifier::default()));
+        let poll = |session: Option<&str>| {
+            let found = watches.poll(None, session, Duration::from_secs(2)).unwrap();
+            found.changes.into_iter().map(|changed| (changed.path, changed.change)).collect::<Vec<_>>()
+        };
+        let path = |name: &str| dir_path.join(name).to_string_lossy().to_string();
+
+        watches.watch(&dir_path, Some("a")).unwrap();
+        watches.watch(&dir_path.join("out/old.txt"), Some("b")).unwrap();
+        std::fs::create_dir(dir_path.join("out/deep")).unwrap();
+        // New directories are watched once their creation is seen
+        std::thread::sleep(Duration::from_millis(200));
+        std::fs::write(dir_path.join("out/deep/new.txt"), "new").unwrap();
+        std::fs::remove_file(dir_path.join("out/old.txt")).unwrap();
+        // Let the last events arrive
+        std::thread::sleep(Duration::from_millis(300));
+        let changes = poll(Some("a"));
+        assert!(changes.contains(&(path("out/deep/new.txt"), Change::Created)));
+        assert!(changes.contains(&(path("out/old.txt"), Change::Deleted)));
+        assert_eq!(poll(Some("b")), [(path("out/old.txt"), Change::Deleted)]);
+
+        // Polled changes are gone
+        let found = watches.poll(None, Some("a"), Duration::ZERO).unwrap();
+        assert!(found.changes.is_empty());
+        assert!(watches.poll(Some(&dir_path.join("out")), Some("a"), Duration::ZERO).is_err());
+        assert!(watches.poll(None, Some("c"), Duration::ZERO).is_err());
+
+        assert!(watches.unwatch(&dir_path, Some("a")));
+        assert!(!watches.unwatch(&dir_path, Some("a")));
+        watches.end_session(Some("b"));
+        assert!(watches.watched.lock().unwrap().is_empty());
+        assert!(watches.watcher.lock().unwrap().as_ref().unwrap().dirs.is_empty());
+    }
+}
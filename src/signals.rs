@@ -0,0 +1,216 @@
+use std::process::{Child, ExitStatus};
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// How long a child gets to exit after a forwarded signal before it is killed
+pub const STOP_GRACE: Duration = Duration::from_secs(10);
+
+/// How often `wait` checks on the child and for new signals
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Last stop signal mcpz received (0 if none) and how many have arrived
+static STOP_SIGNAL: AtomicI32 = AtomicI32::new(0);
+static STOP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A package process that exited unsuccessfully; `mcpz run` exits with the same code
+#[derive(Debug, thiserror::Error)]
+#[error("Process exited with status: {status}")]
+pub struct ChildExit {
+    pub status: ExitStatus,
+}
+
+impl ChildExit {
+    /// The child's exit code, or 128 + N for a child killed by signal N (as shells report it)
+    pub fn code(&self) -> i32 {
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            if let Some(signal) = self.status.signal() {
+                return 128 + signal;
+            }
+        }
+        self.status.code().unwrap_or(1)
+    }
+}
+
+/// Catch SIGINT, SIGTERM and SIGHUP from now on, so `wait` can pass them on to the
+/// child instead of mcpz dying and orphaning it. Safe to call more than once.
+pub fn install() {
+    #[cfg(unix)]
+    {
+        use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+        static INSTALLED: std::sync::Once = std::sync::Once::new();
+        INSTALLED.call_once(|| match signal_hook::iterator::Signals::new([SIGINT, SIGTERM, SIGHUP]) {
+            Ok(mut signals) => {
+                std::thread::spawn(move || {
+                    for signal in signals.forever() {
+                        STOP_SIGNAL.store(signal, Ordering::SeqCst);
+                        STOP_COUNT.fetch_add(1, Ordering::SeqCst);
+                    }
+                });
+            }
+            Err(e) => eprintln!("[mcpz] Could not install signal handlers: {}", e),
+        });
+    }
+}
+
+/// Start the child in its own process group when stdin isn't a terminal, so forwarded
+/// signals also reach the processes it spawns (npx -> node, uvx -> python). In a
+/// terminal the child stays in the foreground group, where Ctrl-C already reaches it
+/// and it can still read the tty.
+pub fn isolate(cmd: &mut std::process::Command) {
+    #[cfg(unix)]
+    {
+        use std::io::IsTerminal;
+        use std::os::unix::process::CommandExt;
+        if !std::io::stdin().is_terminal() {
+            cmd.process_group(0);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = cmd;
+}
+
+/// The signal that asked mcpz to stop, if any
+pub fn stop_requested() -> Option<i32> {
+    match STOP_SIGNAL.load(Ordering::SeqCst) {
+        0 => None,
+        signal => Some(signal),
+    }
+}
+
+/// Sleep for `duration`, returning early (false) if mcpz is asked to stop
+pub fn sleep_unless_stopped(duration: Duration) -> bool {
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        if stop_requested().is_some() {
+            return false;
+        }
+        std::thread::sleep(POLL_INTERVAL.min(deadline - Instant::now()));
+    }
+    stop_requested().is_none()
+}
+
+/// Wait for the child to exit. A stop signal received meanwhile is forwarded to it;
+/// if it is still running `grace` later, or a second signal arrives, it is killed.
+pub fn wait(child: &mut Child, name: &str, grace: Duration) -> std::io::Result<ExitStatus> {
+    let mut forwarded = 0;
+    let mut kill_at: Option<Instant> = None;
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+
+        let count = STOP_COUNT.load(Ordering::SeqCst);
+        if count > forwarded {
+            if forwarded == 0 && count == 1 {
+                if let Some(signal) = stop_requested() {
+                    eprintln!("[mcpz] Stopping {}...", name);
+                    forward(child, signal);
+                }
+                kill_at = Some(Instant::now() + grace);
+            } else {
+                kill_at = Some(Instant::now());
+            }
+            forwarded = count;
+        }
+
+        if kill_at.is_some_and(|at| Instant::now() >= at) {
+            eprintln!("[mcpz] {} didn't exit in time; killing it", name);
+            kill(child);
+            return child.wait();
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Send `signal` to the child, or to its whole process group if `isolate` gave it one
+#[cfg(unix)]
+fn forward(child: &mut Child, signal: i32) {
+    let Ok(pid) = libc::pid_t::try_from(child.id()) else {
+        return;
+    };
+    // SAFETY: getpgid and kill have no memory-safety preconditions; the pid is our own
+    // unreaped child, so it can't have been reused
+    unsafe {
+        let target = if libc::getpgid(pid) == pid { -pid } else { pid };
+        libc::kill(target, signal);
+    }
+}
+
+#[cfg(not(unix))]
+fn forward(child: &mut Child, _signal: i32) {
+    let _ = child.kill();
+}
+
+/// Ask the child (or its process group) to stop with SIGTERM, giving it the chance to stop
+/// its own children; follow with `kill` if it doesn't
+#[cfg(unix)]
+pub fn terminate(child: &mut Child) {
+    forward(child, libc::SIGTERM);
+}
+
+#[cfg(not(unix))]
+pub fn terminate(child: &mut Child) {
+    let _ = child.kill();
+}
+
+#[cfg(unix)]
+pub fn kill(child: &mut Child) {
+    forward(child, libc::SIGKILL);
+}
+
+#[cfg(not(unix))]
+pub fn kill(child: &mut Child) {
+    let _ = child.kill();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn test_child_exit_code() {
+        let status = Command::new("sh").args(["-c", "exit 7"]).status().unwrap();
+        let exit = ChildExit { status };
+        assert_eq!(exit.code(), 7);
+        assert!(exit.to_string().contains("exit status: 7"));
+
+        let status = Command::new("sh").args(["-c", "kill -TERM $$"]).status().unwrap();
+        assert_eq!(ChildExit { status }.code(), 128 + 15);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_forward_reaches_process_group() {
+        use std::io::BufRead;
+        use std::os::unix::process::CommandExt;
+        let mut cmd = Command::new("sh");
+        // The grandchild outlives sh unless the signal reaches the whole group
+        cmd.args(["-c", "sleep 30 & echo $!; wait"]);
+        cmd.stdin(std::process::Stdio::null());
+        cmd.stdout(std::process::Stdio::piped());
+        cmd.process_group(0);
+        let mut child = cmd.spawn().unwrap();
+        let mut line = String::new();
+        std::io::BufReader::new(child.stdout.take().unwrap()).read_line(&mut line).unwrap();
+        let grandchild = line.trim().to_string();
+
+        forward(&mut child, libc::SIGTERM);
+        let status = child.wait().unwrap();
+        assert_eq!(ChildExit { status }.code(), 128 + libc::SIGTERM);
+        std::thread::sleep(Duration::from_millis(200));
+        // Gone, or a zombie waiting to be reaped by init
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", grandchild)).unwrap_or_default();
+        assert!(stat.is_empty() || stat.contains(") Z "), "grandchild still running: {}", stat);
+    }
+
+    #[test]
+    fn test_wait_returns_child_status() {
+        let mut child = Command::new("sh").args(["-c", "exit 4"]).spawn().unwrap();
+        let status = wait(&mut child, "test", STOP_GRACE).unwrap();
+        assert_eq!(status.code(), Some(4));
+    }
+}
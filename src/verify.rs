@@ -0,0 +1,243 @@
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use sha2::{Digest, Sha256, Sha512};
+use std::time::Duration;
+
+use crate::credentials::credentials;
+use crate::registry::{self, endpoints};
+use crate::PackageType;
+
+/// Outcome of checking a package against its registry metadata
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verification {
+    /// The artifact matched the registry checksum (details for display)
+    Verified(String),
+    /// No checksum could be checked (reason for display)
+    Unverifiable(String),
+}
+
+/// Checksum published by a registry for an artifact
+#[derive(Debug, Clone, PartialEq)]
+struct Expected {
+    url: String,
+    algorithm: Algorithm,
+    /// Digest bytes decoded from the registry's hex/base64 form
+    digest: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Algorithm {
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Sha512 => "sha512",
+        }
+    }
+
+    fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Algorithm::Sha256 => Sha256::digest(data).to_vec(),
+            Algorithm::Sha512 => Sha512::digest(data).to_vec(),
+        }
+    }
+}
+
+/// Download a package's artifact and compare it with the checksum in the registry metadata.
+/// A mismatch is an error; packages without checksums are reported as unverifiable.
+pub async fn verify(package: &str, pkg_type: PackageType, version: Option<&str>) -> Result<Verification> {
+    if pkg_type == PackageType::Docker {
+        return Ok(match version {
+            Some(v) if v.starts_with("sha256:") => Verification::Verified("pinned by image digest".to_string()),
+            _ => Verification::Unverifiable(
+                "image tags are mutable; pin a digest with docker:image@sha256:...".to_string(),
+            ),
+        });
+    }
+
+    let Some(version) = version else {
+        return Ok(Verification::Unverifiable("version could not be resolved".to_string()));
+    };
+    let client = registry::client(Duration::from_secs(60))
+        .ok_or_else(|| anyhow!("Failed to create HTTP client"))?;
+
+    let expected = match pkg_type {
+        PackageType::Npm => npm_expected(&client, package, version).await,
+        PackageType::Python => pypi_expected(&client, package, version).await,
+        PackageType::Cargo => cargo_expected(&client, package, version).await,
+        PackageType::Deno => match package.strip_prefix("npm:") {
+            Some(npm) => npm_expected(&client, npm, version).await,
+            None => {
+                return Ok(Verification::Unverifiable(
+                    "JSR packages are not checksum-verified yet".to_string(),
+                ))
+            }
+        },
+        PackageType::Docker => unreachable!("handled above"),
+    };
+
+    let Some(expected) = expected else {
+        return Ok(Verification::Unverifiable(
+            "registry metadata has no checksum for this version".to_string(),
+        ));
+    };
+
+    let artifact = registry::get_bytes(&client, &expected.url).await?;
+    check_digest(&expected, &artifact)?;
+    Ok(Verification::Verified(format!(
+        "{} of {} matches registry metadata",
+        expected.algorithm.name(),
+        expected.url.rsplit('/').next().unwrap_or(&expected.url)
+    )))
+}
+
+fn check_digest(expected: &Expected, artifact: &[u8]) -> Result<()> {
+    let actual = expected.algorithm.digest(artifact);
+    if actual != expected.digest {
+        return Err(anyhow!(
+            "Checksum mismatch for {}: registry says {} {}, downloaded artifact is {}",
+            expected.url,
+            expected.algorithm.name(),
+            hex::encode(&expected.digest),
+            hex::encode(&actual)
+        ));
+    }
+    Ok(())
+}
+
+async fn npm_expected(client: &reqwest::Client, package: &str, version: &str) -> Option<Expected> {
+    let registry_url = endpoints().npm_registry_for(package, credentials().npmrc());
+    let url = format!("{}/{}/{}", registry_url, package, version);
+    parse_npm_dist(&registry::get_json(client, &url).await?)
+}
+
+/// Expected tarball digest from an npm version manifest (`dist.integrity`, SRI format)
+fn parse_npm_dist(manifest: &serde_json::Value) -> Option<Expected> {
+    let tarball = manifest.pointer("/dist/tarball")?.as_str()?;
+    let integrity = manifest.pointer("/dist/integrity")?.as_str()?;
+
+    // SRI strings may list several hashes; prefer the strongest one we support
+    let (algorithm, encoded) = integrity
+        .split_whitespace()
+        .filter_map(|entry| match entry.split_once('-')? {
+            ("sha512", digest) => Some((Algorithm::Sha512, digest)),
+            ("sha256", digest) => Some((Algorithm::Sha256, digest)),
+            _ => None,
+        })
+        .max_by_key(|(algorithm, _)| *algorithm == Algorithm::Sha512)?;
+
+    Some(Expected {
+        url: tarball.to_string(),
+        algorithm,
+        digest: base64::engine::general_purpose::STANDARD.decode(encoded).ok()?,
+    })
+}
+
+async fn pypi_expected(client: &reqwest::Client, package: &str, version: &str) -> Option<Expected> {
+    let url = format!("{}/pypi/{}/{}/json", endpoints().pypi_json_base(), package, version);
+    parse_pypi_release(&registry::get_json(client, &url).await?)
+}
+
+/// Expected digest for a release's universal wheel, falling back to the first file
+fn parse_pypi_release(release: &serde_json::Value) -> Option<Expected> {
+    let files = release.get("urls")?.as_array()?;
+    let file = files
+        .iter()
+        .find(|f| {
+            f.get("filename")
+                .and_then(|n| n.as_str())
+                .is_some_and(|n| n.ends_with("-none-any.whl"))
+        })
+        .or_else(|| files.first())?;
+
+    Some(Expected {
+        url: file.get("url")?.as_str()?.to_string(),
+        algorithm: Algorithm::Sha256,
+        digest: hex::decode(file.pointer("/digests/sha256")?.as_str()?).ok()?,
+    })
+}
+
+async fn cargo_expected(client: &reqwest::Client, package: &str, version: &str) -> Option<Expected> {
+    let crate_url = endpoints().crate_url(package);
+    let metadata = registry::get_json(client, &format!("{}/{}", crate_url, version)).await?;
+    let checksum = metadata.pointer("/version/checksum")?.as_str()?;
+
+    Some(Expected {
+        url: format!("{}/{}/download", crate_url, version),
+        algorithm: Algorithm::Sha256,
+        digest: hex::decode(checksum).ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_npm_dist_prefers_sha512() {
+        let sha512 = base64::engine::general_purpose::STANDARD.encode(Sha512::digest(b"tarball"));
+        let manifest = serde_json::json!({
+            "dist": {
+                "tarball": "https://registry.npmjs.org/pkg/-/pkg-1.0.0.tgz",
+                "integrity": format!("sha1-abc sha512-{}", sha512),
+                "shasum": "abc"
+            }
+        });
+
+        let expected = parse_npm_dist(&manifest).unwrap();
+        assert_eq!(expected.algorithm, Algorithm::Sha512);
+        assert!(check_digest(&expected, b"tarball").is_ok());
+        assert!(check_digest(&expected, b"tampered").is_err());
+    }
+
+    #[test]
+    fn test_parse_npm_dist_without_integrity() {
+        let manifest = serde_json::json!({"dist": {"tarball": "https://x/pkg.tgz", "shasum": "abc"}});
+        assert!(parse_npm_dist(&manifest).is_none());
+    }
+
+    #[test]
+    fn test_parse_pypi_release_prefers_universal_wheel() {
+        let release = serde_json::json!({
+            "urls": [
+                {"filename": "pkg-1.0.tar.gz", "url": "https://files/pkg-1.0.tar.gz", "digests": {"sha256": "00"}},
+                {"filename": "pkg-1.0-py3-none-any.whl", "url": "https://files/pkg.whl", "digests": {"sha256": hex::encode(Sha256::digest(b"wheel"))}}
+            ]
+        });
+
+        let expected = parse_pypi_release(&release).unwrap();
+        assert_eq!(expected.url, "https://files/pkg.whl");
+        assert!(check_digest(&expected, b"wheel").is_ok());
+    }
+
+    #[test]
+    fn test_checksum_mismatch_message() {
+        let expected = Expected {
+            url: "https://crates.io/api/v1/crates/foo/1.0.0/download".to_string(),
+            algorithm: Algorithm::Sha256,
+            digest: Sha256::digest(b"original").to_vec(),
+        };
+        let err = check_digest(&expected, b"tampered").unwrap_err().to_string();
+        assert!(err.contains("Checksum mismatch"));
+        assert!(err.contains("sha256"));
+    }
+
+    #[test]
+    fn test_docker_verification_requires_digest() {
+        let pinned = registry::block_on(verify("foo", PackageType::Docker, Some("sha256:abc"))).unwrap();
+        assert!(matches!(pinned.unwrap(), Verification::Verified(_)));
+
+        let tagged = registry::block_on(verify("foo", PackageType::Docker, Some("latest"))).unwrap();
+        assert!(matches!(tagged.unwrap(), Verification::Unverifiable(_)));
+    }
+
+    #[test]
+    fn test_unresolved_version_is_unverifiable() {
+        let result = registry::block_on(verify("foo", PackageType::Npm, None)).unwrap().unwrap();
+        assert!(matches!(result, Verification::Unverifiable(_)));
+    }
+}